@@ -0,0 +1,514 @@
+//! Procedural macros for `sainnhe-common`.
+//!
+//! Use the re-exports in `sainnhe-common` instead of depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{
+    Error, Ident, LitStr, Path, Result, Token, bracketed,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+};
+
+const PLACEHOLDER: &str = "?";
+
+#[derive(Clone, Copy)]
+enum Type {
+    MySQL,
+    PostgreSQL,
+    SQLite,
+}
+
+impl Parse for Type {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // Both `MySQL` and `Type::MySQL` are accepted.
+        let path: Path = input.parse()?;
+        let ident = &path.segments.last().expect("path is not empty").ident;
+        match ident.to_string().as_str() {
+            // MariaDB and TiDB share the syntax of MySQL.
+            "MySQL" | "MariaDB" | "TiDB" => Ok(Type::MySQL),
+            // CockroachDB shares the syntax of PostgreSQL.
+            "PostgreSQL" | "CockroachDB" => Ok(Type::PostgreSQL),
+            // DuckDB quotes identifiers and binds parameters like SQLite.
+            "SQLite" | "DuckDB" => Ok(Type::SQLite),
+            _ => Err(Error::new(
+                ident.span(),
+                "expected one of `MySQL`, `MariaDB`, `TiDB`, `PostgreSQL`, `CockroachDB`, `SQLite` and `DuckDB`",
+            )),
+        }
+    }
+}
+
+/// `"col"` or `"col" = "val"`, where the value defaults to a placeholder.
+struct Pair {
+    key: String,
+    val: String,
+}
+
+impl Parse for Pair {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let key: LitStr = input.parse()?;
+        let val = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            input.parse::<LitStr>()?.value()
+        } else {
+            String::from(PLACEHOLDER)
+        };
+        Ok(Pair {
+            key: key.value(),
+            val,
+        })
+    }
+}
+
+enum Op {
+    Insert(Vec<Pair>),
+    Query(Vec<String>),
+    Update(Vec<Pair>),
+    Delete,
+}
+
+struct Stmt {
+    typ: Type,
+    op: Op,
+    tbl: String,
+    conds: Vec<Pair>,
+}
+
+fn parse_list<T: Parse>(input: ParseStream) -> Result<Vec<T>> {
+    let content;
+    bracketed!(content in input);
+    Ok(Punctuated::<T, Token![,]>::parse_terminated(&content)?
+        .into_iter()
+        .collect())
+}
+
+fn parse_keyword(input: ParseStream, keyword: &str) -> Result<()> {
+    let ident: Ident = input.parse()?;
+    if ident == keyword {
+        Ok(())
+    } else {
+        Err(Error::new(ident.span(), format!("expected `{}`", keyword)))
+    }
+}
+
+fn parse_conds(input: ParseStream) -> Result<Vec<Pair>> {
+    if input.is_empty() {
+        return Ok(Vec::new());
+    }
+    input.parse::<Token![where]>()?;
+    parse_list(input)
+}
+
+impl Parse for Stmt {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let typ: Type = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let op: Ident = input.parse()?;
+        let stmt = match op.to_string().as_str() {
+            "insert" => {
+                parse_keyword(input, "into")?;
+                let tbl: LitStr = input.parse()?;
+                let cols = parse_list(input)?;
+                Stmt {
+                    typ,
+                    op: Op::Insert(cols),
+                    tbl: tbl.value(),
+                    conds: Vec::new(),
+                }
+            }
+            "select" => {
+                let cols: Vec<LitStr> = parse_list(input)?;
+                parse_keyword(input, "from")?;
+                let tbl: LitStr = input.parse()?;
+                Stmt {
+                    typ,
+                    op: Op::Query(cols.iter().map(LitStr::value).collect()),
+                    tbl: tbl.value(),
+                    conds: parse_conds(input)?,
+                }
+            }
+            "update" => {
+                let tbl: LitStr = input.parse()?;
+                parse_keyword(input, "set")?;
+                let cols = parse_list(input)?;
+                Stmt {
+                    typ,
+                    op: Op::Update(cols),
+                    tbl: tbl.value(),
+                    conds: parse_conds(input)?,
+                }
+            }
+            "delete" => {
+                parse_keyword(input, "from")?;
+                let tbl: LitStr = input.parse()?;
+                Stmt {
+                    typ,
+                    op: Op::Delete,
+                    tbl: tbl.value(),
+                    conds: parse_conds(input)?,
+                }
+            }
+            _ => {
+                return Err(Error::new(
+                    op.span(),
+                    "expected one of `insert`, `select`, `update` and `delete`",
+                ));
+            }
+        };
+        if let Op::Insert(cols) | Op::Update(cols) = &stmt.op
+            && cols.is_empty()
+        {
+            return Err(Error::new(Span::call_site(), "columns must not be empty"));
+        }
+        Ok(stmt)
+    }
+}
+
+/// Renders the statement exactly like `StmtBuilder` in `sainnhe-common` does.
+struct Renderer {
+    typ: Type,
+    idx: i32,
+}
+
+impl Renderer {
+    fn col(&self, col: &str) -> String {
+        if col == "*" {
+            return col.to_string();
+        }
+        match self.typ {
+            Type::MySQL => format!("`{}`", col),
+            Type::PostgreSQL | Type::SQLite => format!("\"{}\"", col),
+        }
+    }
+
+    fn val(&mut self, val: &str) -> String {
+        match self.typ {
+            Type::PostgreSQL if val == PLACEHOLDER => {
+                let s = format!("${}", self.idx);
+                self.idx += 1;
+                s
+            }
+            _ => val.to_string(),
+        }
+    }
+
+    fn conds(&mut self, conds: &[Pair]) -> String {
+        if conds.is_empty() {
+            return String::new();
+        }
+        let conds: Vec<String> = conds
+            .iter()
+            .map(|p| format!("{} = {}", p.key, self.val(&p.val)))
+            .collect();
+        format!(" WHERE {}", conds.join(" AND "))
+    }
+
+    fn render(&mut self, stmt: &Stmt) -> String {
+        match &stmt.op {
+            Op::Insert(cols) => {
+                let keys: Vec<String> = cols.iter().map(|p| self.col(&p.key)).collect();
+                let vals: Vec<String> = cols.iter().map(|p| self.val(&p.val)).collect();
+                format!(
+                    "INSERT INTO {} ({}) VALUES ({})",
+                    stmt.tbl,
+                    keys.join(", "),
+                    vals.join(", ")
+                )
+            }
+            Op::Query(cols) => {
+                let cols = if cols.is_empty() {
+                    String::from("*")
+                } else {
+                    cols.iter()
+                        .map(|col| self.col(col))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                format!(
+                    "SELECT {} FROM {}{}",
+                    cols,
+                    stmt.tbl,
+                    self.conds(&stmt.conds)
+                )
+            }
+            Op::Update(cols) => {
+                let sets: Vec<String> = cols
+                    .iter()
+                    .map(|p| format!("{} = {}", self.col(&p.key), self.val(&p.val)))
+                    .collect();
+                format!(
+                    "UPDATE {} SET {}{}",
+                    stmt.tbl,
+                    sets.join(", "),
+                    self.conds(&stmt.conds)
+                )
+            }
+            Op::Delete => format!("DELETE FROM {}{}", stmt.tbl, self.conds(&stmt.conds)),
+        }
+    }
+}
+
+/// Builds a SQL statement at compile time and expands to a `&'static str`.
+///
+/// The statement is identical to the one built by `StmtBuilder` at runtime,
+/// so hot paths pay no formatting cost.
+/// The first argument is the database type, followed by one of:
+///
+///   - `insert into "tbl" [cols]`
+///   - `select [cols] from "tbl" where [conds]`
+///   - `update "tbl" set [cols] where [conds]`
+///   - `delete from "tbl" where [conds]`
+///
+/// The `where` clauses are optional.
+/// Columns and conditions are given as `"key"` or `"key" = "val"`,
+/// where the value is a SQL fragment that defaults to a placeholder.
+/// Like `StmtBuilder`, placeholders are converted to `$N` for PostgreSQL.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::stmt;
+///
+/// const INSERT: &str = stmt!(MySQL, insert into "users" ["name", "created_at" = "NOW()"]);
+/// const QUERY: &str = stmt!(Type::PostgreSQL, select ["name"] from "users" where ["id", "deleted" = "FALSE"]);
+/// const UPDATE: &str = stmt!(PostgreSQL, update "users" set ["name"] where ["id"]);
+/// const DELETE: &str = stmt!(SQLite, delete from "users" where ["id"]);
+///
+/// assert_eq!(INSERT, "INSERT INTO users (`name`, `created_at`) VALUES (?, NOW())");
+/// assert_eq!(QUERY, "SELECT \"name\" FROM users WHERE id = $1 AND deleted = FALSE");
+/// assert_eq!(UPDATE, "UPDATE users SET \"name\" = $1 WHERE id = $2");
+/// assert_eq!(DELETE, "DELETE FROM users WHERE id = ?");
+/// ```
+#[proc_macro]
+pub fn stmt(input: TokenStream) -> TokenStream {
+    let stmt = parse_macro_input!(input as Stmt);
+    let sql = Renderer {
+        typ: stmt.typ,
+        idx: 1,
+    }
+    .render(&stmt);
+    let lit = LitStr::new(&sql, Span::call_site());
+    quote::quote!(#lit).into()
+}
+
+/// `"path"` or `Dialect, "path"`.
+struct IncludeSql {
+    typ: Option<Type>,
+    path: LitStr,
+}
+
+impl Parse for IncludeSql {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let typ = if input.peek(LitStr) {
+            None
+        } else {
+            let typ = input.parse()?;
+            input.parse::<Token![,]>()?;
+            Some(typ)
+        };
+        let path = input.parse()?;
+        input.parse::<Option<Token![,]>>()?;
+        Ok(IncludeSql { typ, path })
+    }
+}
+
+/// Finds the first placeholder that isn't supported by `typ`,
+/// like `registry::check_placeholders` in `sainnhe-common` does.
+fn check_placeholders(sql: &str, typ: Type) -> Option<String> {
+    let bytes = sql.as_bytes();
+    let mut first_is_question = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let placeholder = match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => {
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == quote && bytes.get(i + 1) != Some(&quote) {
+                        break;
+                    }
+                    i += if bytes[i] == quote { 2 } else { 1 };
+                }
+                None
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i += bytes[i..].iter().take_while(|&&b| b != b'\n').count();
+                None
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = sql[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 1);
+                None
+            }
+            b'?' => Some(&sql[i..i + 1]),
+            b'$' if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let len = bytes[i + 1..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_digit())
+                    .count();
+                let placeholder = &sql[i..i + 1 + len];
+                i += len;
+                Some(placeholder)
+            }
+            _ => None,
+        };
+        i += 1;
+        let Some(placeholder) = placeholder else {
+            continue;
+        };
+        let is_question = placeholder == PLACEHOLDER;
+        let first_is_question = *first_is_question.get_or_insert(is_question);
+        let supported = match typ {
+            Type::MySQL => is_question,
+            Type::PostgreSQL => !is_question,
+            Type::SQLite => is_question == first_is_question,
+        };
+        if !supported {
+            return Some(placeholder.to_string());
+        }
+    }
+    None
+}
+
+/// Embeds a SQL file at compile time and expands to a `&'static str`.
+///
+/// The path is relative to the directory containing the manifest of the calling crate,
+/// and leading and trailing whitespaces are trimmed.
+/// If a database type is given as the first argument,
+/// the placeholders are checked at compile time:
+/// MySQL only supports `?`, PostgreSQL only supports `$N`,
+/// and SQLite supports both but they can't be mixed.
+///
+/// Use `registry::QueryRegistry` in `sainnhe-common` to load a directory of queries at runtime.
+///
+/// # Examples
+///
+/// ```ignore
+/// use sainnhe_common::db::include_sql;
+///
+/// const GET_USER: &str = include_sql!("queries/get_user.sql");
+/// const LIST_USERS: &str = include_sql!(PostgreSQL, "queries/list_users.sql");
+/// ```
+#[proc_macro]
+pub fn include_sql(input: TokenStream) -> TokenStream {
+    let IncludeSql { typ, path } = parse_macro_input!(input as IncludeSql);
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let full_path = std::path::Path::new(&dir).join(path.value());
+    let sql = match std::fs::read_to_string(&full_path) {
+        Ok(sql) => sql,
+        Err(e) => {
+            let msg = format!("failed to read {}: {}", full_path.display(), e);
+            return Error::new(path.span(), msg).to_compile_error().into();
+        }
+    };
+    let sql = sql.trim();
+    if let Some(typ) = typ
+        && let Some(placeholder) = check_placeholders(sql, typ)
+    {
+        let msg = format!("unsupported placeholder {}", placeholder);
+        return Error::new(path.span(), msg).to_compile_error().into();
+    }
+    let full_path = LitStr::new(&full_path.to_string_lossy(), path.span());
+    let lit = LitStr::new(sql, path.span());
+    // `include_str!` makes the compiler rebuild the crate when the file changes.
+    quote::quote!({
+        const _: &str = include_str!(#full_path);
+        #lit
+    })
+    .into()
+}
+
+/// How a field is redacted, given by its `#[redact(...)]` attribute.
+enum Redaction {
+    Clone,
+    Mask(Ident),
+    Nested,
+}
+
+fn redaction(field: &syn::Field) -> Result<Redaction> {
+    let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("redact")) else {
+        return Ok(Redaction::Clone);
+    };
+    if let syn::Meta::Path(_) = attr.meta {
+        return Ok(Redaction::Mask(Ident::new("full", Span::call_site())));
+    }
+    let ident: Ident = attr.parse_args()?;
+    match ident.to_string().as_str() {
+        "nested" => Ok(Redaction::Nested),
+        "email" | "phone" | "card" | "hash" => Ok(Redaction::Mask(ident)),
+        _ => Err(Error::new(
+            ident.span(),
+            "expected one of `email`, `phone`, `card`, `hash` and `nested`",
+        )),
+    }
+}
+
+/// Derives `redact::Redact` for structs, see the `redact` module of `sainnhe-common`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::redact::Redact;
+///
+/// #[derive(Redact)]
+/// struct User {
+///     id: i64,
+///     #[redact(email)]
+///     email: String,
+///     #[redact]
+///     password: Option<String>,
+/// }
+///
+/// let user = User {
+///     id: 1,
+///     email: String::from("alice@example.com"),
+///     password: Some(String::from("hunter2")),
+/// }
+/// .redact();
+///
+/// assert_eq!(user.id, 1);
+/// assert_eq!(user.email, "a***@example.com");
+/// assert_eq!(user.password.as_deref(), Some("***"));
+/// ```
+#[proc_macro_derive(Redact, attributes(redact))]
+pub fn derive_redact(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    let syn::Data::Struct(data) = &input.data else {
+        return Error::new(
+            input.ident.span(),
+            "`Redact` can only be derived for structs",
+        )
+        .to_compile_error()
+        .into();
+    };
+    let mut fields = Vec::with_capacity(data.fields.len());
+    for (i, field) in data.fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => syn::Member::Named(ident.clone()),
+            None => syn::Member::Unnamed(syn::Index::from(i)),
+        };
+        let expr = match redaction(field) {
+            Ok(Redaction::Clone) => quote::quote!(::std::clone::Clone::clone(&self.#member)),
+            Ok(Redaction::Mask(f)) => quote::quote!(
+                ::sainnhe_common::redact::Mask::mask(&self.#member, ::sainnhe_common::redact::#f)
+            ),
+            Ok(Redaction::Nested) => {
+                quote::quote!(::sainnhe_common::redact::Redact::redact(&self.#member))
+            }
+            Err(e) => return e.to_compile_error().into(),
+        };
+        fields.push(quote::quote!(#member: #expr));
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote::quote!(
+        impl #impl_generics ::sainnhe_common::redact::Redact for #name #ty_generics #where_clause {
+            fn redact(&self) -> Self {
+                #name { #(#fields),* }
+            }
+        }
+    )
+    .into()
+}
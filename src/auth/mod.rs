@@ -0,0 +1,9 @@
+//! Authentication.
+//!
+//! - [`jwt`] issues and verifies JSON Web Tokens, with the `jwt` feature enabled.
+//! - [`password`] hashes and verifies passwords, with the `password` feature enabled.
+
+#[cfg(feature = "jwt")]
+pub mod jwt;
+#[cfg(feature = "password")]
+pub mod password;
@@ -0,0 +1,240 @@
+//! Password hashing.
+//!
+//! [`Hasher`] hashes passwords via argon2id into PHC strings like `$argon2id$v=19$m=19456,t=2,p=1$...`,
+//! which embed the parameters, so hashes stay verifiable after the parameters are upgraded,
+//! and [`Hasher::needs_rehash`] tells which hashes should be replaced on the next successful login.
+
+use std::fmt;
+
+use argon2::{
+    ARGON2ID_IDENT, Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier,
+    Version, password_hash,
+};
+
+/// Errors returned by this module.
+#[derive(Debug)]
+pub enum Error {
+    /// The parameters are out of range, or the hash is malformed.
+    Hash(password_hash::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Hash(e) => write!(f, "password hash error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Hash(e) => Some(e),
+        }
+    }
+}
+
+impl From<password_hash::Error> for Error {
+    fn from(e: password_hash::Error) -> Self {
+        Error::Hash(e)
+    }
+}
+
+impl From<argon2::Error> for Error {
+    fn from(e: argon2::Error) -> Self {
+        Error::Hash(e.into())
+    }
+}
+
+/// Hashes and verifies passwords via argon2id.
+///
+/// The default parameters are 19 MiB of memory, 2 iterations and 1 degree of parallelism,
+/// as recommended by OWASP.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::auth::password::Hasher;
+///
+/// let hasher = Hasher::new();
+/// let hash = hasher.hash("hunter2").unwrap();
+///
+/// assert!(hasher.verify("hunter2", &hash).unwrap());
+/// assert!(!hasher.verify("hunter3", &hash).unwrap());
+///
+/// // Upgrading the parameters makes existing hashes stale
+/// let hasher = Hasher::with_params(32 * 1024, 3, 1).unwrap();
+/// assert!(hasher.needs_rehash(&hash).unwrap());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Hasher {
+    params: Params,
+}
+
+impl Hasher {
+    /// Creates a new [`Hasher`] with the default parameters.
+    pub fn new() -> Hasher {
+        Hasher {
+            params: Params::DEFAULT,
+        }
+    }
+
+    /// Creates a new [`Hasher`] with custom parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `m_cost` - The memory size in KiB.
+    /// * `t_cost` - The number of iterations.
+    /// * `p_cost` - The degree of parallelism.
+    pub fn with_params(m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Hasher, Error> {
+        Ok(Hasher {
+            params: Params::new(m_cost, t_cost, p_cost, None)?,
+        })
+    }
+
+    /// Gets memory size in KiB.
+    pub fn get_m_cost(&self) -> u32 {
+        self.params.m_cost()
+    }
+
+    /// Gets number of iterations.
+    pub fn get_t_cost(&self) -> u32 {
+        self.params.t_cost()
+    }
+
+    /// Gets degree of parallelism.
+    pub fn get_p_cost(&self) -> u32 {
+        self.params.p_cost()
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Hashes `password` with a random salt into a PHC string.
+    pub fn hash(&self, password: &str) -> Result<String, Error> {
+        Ok(self
+            .argon2()
+            .hash_password(password.as_bytes())?
+            .to_string())
+    }
+
+    /// Verifies `password` against `hash`, using the parameters embedded in `hash`.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the password matches, or an error if `hash` is malformed.
+    pub fn verify(&self, password: &str, hash: &str) -> Result<bool, Error> {
+        let hash = parse(hash)?;
+        match self.argon2().verify_password(password.as_bytes(), &hash) {
+            Ok(()) => Ok(true),
+            Err(password_hash::Error::PasswordInvalid) => Ok(false),
+            Err(e) => Err(Error::Hash(e)),
+        }
+    }
+
+    /// Returns whether `hash` was created by another algorithm, version or parameters than this hasher's,
+    /// so it should be replaced by a new hash once the password is verified.
+    pub fn needs_rehash(&self, hash: &str) -> Result<bool, Error> {
+        let hash = parse(hash)?;
+        if hash.algorithm != ARGON2ID_IDENT || hash.version != Some(Version::V0x13.into()) {
+            return Ok(true);
+        }
+        let params = Params::try_from(&hash)?;
+        Ok(params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+            || params.output_len().unwrap_or(Params::DEFAULT_OUTPUT_LEN)
+                != Params::DEFAULT_OUTPUT_LEN)
+    }
+}
+
+fn parse(hash: &str) -> Result<PasswordHash, Error> {
+    PasswordHash::new(hash).map_err(|e| Error::Hash(e.into()))
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher::new()
+    }
+}
+
+/// Compares `a` and `b` in time depending only on their lengths, not their contents,
+/// so that comparing secrets like API keys or tokens doesn't leak how many leading bytes match.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::auth::password::constant_time_eq;
+///
+/// assert!(constant_time_eq(b"token", b"token"));
+/// assert!(!constant_time_eq(b"token", b"tokem"));
+/// assert!(!constant_time_eq(b"token", b"tok"));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    // Keep the compiler from short-circuiting the fold.
+    std::hint::black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Hasher};
+
+    #[test]
+    fn test_needs_rehash() {
+        struct TC {
+            hasher: Hasher,
+            want: bool,
+        }
+
+        // Small parameters keep the test fast.
+        let hash = Hasher::with_params(1024, 1, 1)
+            .unwrap()
+            .hash("hunter2")
+            .unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+
+        let test_cases = vec![
+            TC {
+                hasher: Hasher::with_params(1024, 1, 1).unwrap(),
+                want: false,
+            },
+            TC {
+                hasher: Hasher::with_params(2048, 1, 1).unwrap(),
+                want: true,
+            },
+            TC {
+                hasher: Hasher::with_params(1024, 2, 1).unwrap(),
+                want: true,
+            },
+            TC {
+                hasher: Hasher::with_params(1024, 1, 2).unwrap(),
+                want: true,
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(tc.hasher.needs_rehash(&hash).unwrap(), tc.want);
+            // Hashes stay verifiable after the parameters change
+            assert!(tc.hasher.verify("hunter2", &hash).unwrap());
+            assert!(!tc.hasher.verify("hunter3", &hash).unwrap());
+        }
+
+        // argon2i hashes are upgraded to argon2id
+        let hasher = Hasher::new();
+        assert!(
+            hasher
+                .needs_rehash("$argon2i$v=19$m=1024,t=1,p=1$c29tZXNhbHQ$iWh06vD8Fy27wf9npn6FXWiCX4K6pW6Ue1Bnzz07Z8A")
+                .unwrap()
+        );
+        assert!(matches!(
+            hasher.verify("hunter2", "not a hash"),
+            Err(Error::Hash(_))
+        ));
+        assert!(Hasher::with_params(0, 1, 1).is_err());
+    }
+}
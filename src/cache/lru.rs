@@ -0,0 +1,373 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+struct Entry<V> {
+    val: V,
+    expires_at: Option<Instant>,
+    /// The position in the recency order.
+    tick: u64,
+}
+
+impl<V> Entry<V> {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+}
+
+#[derive(Debug)]
+struct Inner<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Keys ordered from the least recently used to the most recently used.
+    order: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> Inner<K, V> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove<Q>(&mut self, key: &Q) -> Option<Entry<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        Some(entry)
+    }
+}
+
+/// A concurrent LRU cache, whose entries may expire after a TTL.
+///
+/// When the cache is full, inserting a new entry evicts the least recently used one.
+/// Expired entries are removed lazily when they are read or evicted, see [`LruCache::purge_expired`].
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::cache::LruCache;
+///
+/// let cache = LruCache::new(2).ttl(Some(Duration::from_secs(60)));
+/// cache.insert("a", 1);
+/// cache.insert("b", 2);
+/// assert_eq!(cache.get("a"), Some(1));
+///
+/// // "b" is the least recently used entry
+/// cache.insert("c", 3);
+/// assert_eq!(cache.get("b"), None);
+/// assert_eq!(cache.len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    inner: Mutex<Inner<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
+    /// Creates a new [`LruCache`] that holds at most `capacity` entries, which never expire by default.
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            capacity,
+            ttl: None,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Sets the default TTL of entries. [`None`] means entries never expire.
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Gets capacity.
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Gets the default TTL.
+    pub fn get_ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Returns the number of entries, including expired ones that haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns `true` if the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner<K, V>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns a clone of the value of `key` and marks it as the most recently used,
+    /// or [`None`] if it's missing or expired.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at<Q>(&self, key: &Q, now: Instant) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut inner = self.lock();
+        let tick = inner.next_tick();
+        let inner = &mut *inner;
+        let entry = inner.entries.get_mut(key)?;
+        if entry.is_expired(now) {
+            let tick = entry.tick;
+            inner.entries.remove(key);
+            inner.order.remove(&tick);
+            return None;
+        }
+        if let Some(k) = inner.order.remove(&entry.tick) {
+            inner.order.insert(tick, k);
+        }
+        entry.tick = tick;
+        Some(entry.val.clone())
+    }
+
+    /// Inserts an entry with the default TTL, see [`LruCache::insert_with_ttl`].
+    pub fn insert(&self, key: K, val: V) -> Option<V> {
+        self.insert_with_ttl(key, val, self.ttl)
+    }
+
+    /// Inserts an entry that expires after `ttl`, or never expires if `ttl` is [`None`].
+    ///
+    /// # Returns
+    ///
+    /// * The previous value of `key`, if any.
+    pub fn insert_with_ttl(&self, key: K, val: V, ttl: Option<Duration>) -> Option<V> {
+        self.insert_at(key, val, ttl, Instant::now())
+    }
+
+    fn insert_at(&self, key: K, val: V, ttl: Option<Duration>, now: Instant) -> Option<V> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut inner = self.lock();
+        let prev = inner.remove(&key).map(|e| e.val);
+        while inner.entries.len() >= self.capacity {
+            let Some((_, k)) = inner.order.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&k);
+        }
+        let tick = inner.next_tick();
+        inner.order.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                val,
+                expires_at: ttl.and_then(|ttl| now.checked_add(ttl)),
+                tick,
+            },
+        );
+        prev
+    }
+
+    /// Removes the entry of `key`, returning its value if it's not expired.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = Instant::now();
+        self.lock()
+            .remove(key)
+            .filter(|e| !e.is_expired(now))
+            .map(|e| e.val)
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+        inner.entries.clear();
+        inner.order.clear();
+    }
+
+    /// Removes the entries for which `f` returns `false`.
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&self, mut f: F) {
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let order = &mut inner.order;
+        inner.entries.retain(|k, e| {
+            let keep = f(k, &e.val);
+            if !keep {
+                order.remove(&e.tick);
+            }
+            keep
+        });
+    }
+
+    /// Removes all expired entries.
+    pub fn purge_expired(&self) {
+        let now = Instant::now();
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let order = &mut inner.order;
+        inner.entries.retain(|_, e| {
+            let keep = !e.is_expired(now);
+            if !keep {
+                order.remove(&e.tick);
+            }
+            keep
+        });
+    }
+
+    /// Returns the value of `key`, computing and inserting it with `f` if it's missing or expired.
+    ///
+    /// The lock is not held while `f` runs, so concurrent callers missing the same key may all run `f`,
+    /// and the last one wins.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::cache::LruCache;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let cache = LruCache::new(10);
+    ///
+    /// assert_eq!(cache.get_or_insert_with(1, async || "computed").await, "computed");
+    /// assert_eq!(cache.get_or_insert_with(1, async || "ignored").await, "computed");
+    /// # });
+    /// ```
+    pub async fn get_or_insert_with<F: AsyncFnOnce() -> V>(&self, key: K, f: F) -> V {
+        if let Some(val) = self.get(&key) {
+            return val;
+        }
+        let val = f().await;
+        self.insert(key, val.clone());
+        val
+    }
+
+    /// Like [`LruCache::get_or_insert_with`], but `f` may fail, in which case nothing is inserted.
+    pub async fn try_get_or_insert_with<F, E>(&self, key: K, f: F) -> Result<V, E>
+    where
+        F: AsyncFnOnce() -> Result<V, E>,
+    {
+        if let Some(val) = self.get(&key) {
+            return Ok(val);
+        }
+        let val = f().await?;
+        self.insert(key, val.clone());
+        Ok(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::LruCache;
+
+    #[test]
+    fn test_lru() {
+        let cache = LruCache::new(3);
+        for i in 0..3 {
+            assert_eq!(cache.insert(i, i * 10), None);
+        }
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.insert(1, 11), Some(10));
+
+        // 2 is the least recently used
+        cache.insert(3, 30);
+        assert_eq!(cache.get(&2), None);
+        // then 0
+        cache.insert(4, 40);
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.get(&1), Some(11));
+        assert_eq!(cache.len(), 3);
+
+        assert_eq!(cache.remove(&1), Some(11));
+        cache.retain(|k, _| *k != 3);
+        assert_eq!(cache.len(), 1);
+        cache.insert(5, 50);
+        cache.insert(6, 60);
+        assert_eq!(cache.get(&4), Some(40));
+        cache.clear();
+        assert!(cache.is_empty());
+
+        let cache = LruCache::new(0);
+        cache.insert(1, 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let cache = LruCache::new(10).ttl(Some(Duration::from_secs(10)));
+        let t0 = Instant::now();
+        let at = |s| t0 + Duration::from_secs(s);
+
+        struct TC {
+            key: &'static str,
+            ttl: Option<Duration>,
+        }
+
+        let test_cases = vec![
+            TC {
+                key: "default",
+                ttl: cache.get_ttl(),
+            },
+            TC {
+                key: "short",
+                ttl: Some(Duration::from_secs(1)),
+            },
+            TC {
+                key: "forever",
+                ttl: None,
+            },
+        ];
+        for tc in test_cases {
+            cache.insert_at(tc.key, tc.key, tc.ttl, t0);
+        }
+
+        assert_eq!(cache.get_at("short", at(0)), Some("short"));
+        assert_eq!(cache.get_at("short", at(1)), None);
+        assert_eq!(cache.get_at("default", at(9)), Some("default"));
+        assert_eq!(cache.get_at("default", at(10)), None);
+        assert_eq!(cache.get_at("forever", at(1_000_000)), Some("forever"));
+        assert_eq!(cache.len(), 1);
+
+        cache.insert_with_ttl("expired", "expired", Some(Duration::ZERO));
+        assert_eq!(cache.len(), 2);
+        cache.purge_expired();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_try_get_or_insert_with() {
+        let cache = LruCache::new(10);
+        let res: Result<i32, &str> = cache
+            .try_get_or_insert_with("a", async || Err("failed"))
+            .await;
+        assert_eq!(res, Err("failed"));
+        assert!(cache.is_empty());
+        let res: Result<i32, &str> = cache.try_get_or_insert_with("a", async || Ok(1)).await;
+        assert_eq!(res, Ok(1));
+        assert_eq!(cache.get("a"), Some(1));
+    }
+}
@@ -0,0 +1,62 @@
+//! Caching.
+//!
+//! [`LruCache`] is an in-memory cache bounded by the number of entries, whose entries may expire after a TTL.
+//! Query results can be cached by [`QueryKey`], see [`fetch_all_cached`] with the `sqlx` feature enabled.
+//!
+//! The [`Cache`] trait abstracts over caches keyed by strings, so the same code can use [`LruCache`] locally
+//! and a cache shared by all instances in production, for example [`redis::RedisCache`] with the `redis` feature enabled.
+//! To prevent cache stampedes, load missing values through [`SingleFlight`].
+
+mod lru;
+mod query;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "redis")]
+mod single_flight;
+
+use std::{convert::Infallible, time::Duration};
+
+pub use lru::LruCache;
+pub use query::QueryKey;
+#[cfg(feature = "sqlx")]
+pub use query::fetch_all_cached;
+#[cfg(feature = "redis")]
+pub use single_flight::SingleFlight;
+
+/// A cache of values keyed by strings.
+pub trait Cache<V> {
+    /// The error returned by the backend.
+    type Error;
+
+    /// Returns the value of `key`, or [`None`] if it's missing or expired.
+    fn get(&self, key: &str) -> impl Future<Output = Result<Option<V>, Self::Error>> + Send;
+
+    /// Sets the value of `key`, which expires after `ttl`, or never expires if `ttl` is [`None`].
+    fn set(
+        &self,
+        key: &str,
+        val: &V,
+        ttl: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Removes the value of `key`.
+    fn remove(&self, key: &str) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+impl<V: Clone + Send + Sync> Cache<V> for LruCache<String, V> {
+    type Error = Infallible;
+
+    async fn get(&self, key: &str) -> Result<Option<V>, Infallible> {
+        Ok(LruCache::get(self, key))
+    }
+
+    async fn set(&self, key: &str, val: &V, ttl: Option<Duration>) -> Result<(), Infallible> {
+        self.insert_with_ttl(key.to_string(), val.clone(), ttl);
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Infallible> {
+        LruCache::remove(self, key);
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "sqlx")]
+use std::sync::Arc;
+
+#[cfg(feature = "sqlx")]
+use sqlx::{Any, Executor, any::AnyRow};
+
+use crate::db::{
+    Value,
+    fingerprint::{fingerprint_with_literals, placeholders},
+};
+#[cfg(feature = "sqlx")]
+use crate::{cache::LruCache, db::exec};
+
+/// The cache key of a query, which is the fingerprint of the statement
+/// together with its literals and bound values.
+///
+/// Statements that only differ in whitespaces or placeholder styles share the same key,
+/// while statements with different values don't.
+/// The bound values are keyed in the order of their placeholders,
+/// so `a = $2 AND b = $1` and `a = $1 AND b = $2` only share a key if they bind the same values to `a` and `b`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{cache::QueryKey, db::Value};
+///
+/// let a = QueryKey::new("SELECT * FROM t WHERE a = ?", &[Value::from(1)]);
+/// let b = QueryKey::new("SELECT *  FROM t WHERE a = $1", &[Value::from(1)]);
+/// let c = QueryKey::new("SELECT * FROM t WHERE a = ?", &[Value::from(2)]);
+///
+/// assert_eq!(a, b);
+/// assert_ne!(a, c);
+/// assert_eq!(a.get_fingerprint(), "SELECT * FROM t WHERE a = ?");
+/// ```
+#[derive(Clone, Debug)]
+pub struct QueryKey {
+    fingerprint: String,
+    literals: Vec<String>,
+    args: Vec<Value>,
+}
+
+impl QueryKey {
+    /// Creates a new [`QueryKey`] from a statement and the values bound to its placeholders.
+    pub fn new(stmt: &str, args: &[Value]) -> QueryKey {
+        let (fingerprint, literals) = fingerprint_with_literals(stmt);
+        // The fingerprint drops the indexes of `$N`, so the values are reordered as the placeholders are written.
+        let found = placeholders(stmt);
+        let args = if found.is_empty() {
+            args.to_vec()
+        } else {
+            found
+                .iter()
+                .filter_map(|(_, idx)| args.get(*idx).cloned())
+                .collect()
+        };
+        QueryKey {
+            fingerprint,
+            literals,
+            args,
+        }
+    }
+
+    /// Gets fingerprint.
+    pub fn get_fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Gets bound values, in the order of their placeholders.
+    pub fn get_args(&self) -> &[Value] {
+        &self.args
+    }
+}
+
+/// Compares values by their bits, so that keys are reflexive even with NaNs.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+        (a, b) => a == b,
+    }
+}
+
+impl PartialEq for QueryKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.fingerprint == other.fingerprint
+            && self.literals == other.literals
+            && self.args.len() == other.args.len()
+            && self
+                .args
+                .iter()
+                .zip(&other.args)
+                .all(|(a, b)| same_value(a, b))
+    }
+}
+
+impl Eq for QueryKey {}
+
+impl Hash for QueryKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.fingerprint.hash(state);
+        self.literals.hash(state);
+        for arg in &self.args {
+            std::mem::discriminant(arg).hash(state);
+            match arg {
+                Value::Null => {}
+                Value::Bool(v) => v.hash(state),
+                Value::Int(v) => v.hash(state),
+                Value::Float(v) => v.to_bits().hash(state),
+                Value::Text(v) => v.hash(state),
+                Value::Bytes(v) => v.hash(state),
+            }
+        }
+    }
+}
+
+/// Executes a statement and returns all rows like [`exec::fetch_all`](crate::db::exec::fetch_all),
+/// serving the rows from `cache` if the same query has been executed before.
+///
+/// Entries are not invalidated when the data changes, so pick a TTL that matches how stale the rows may be,
+/// or use [`QueryCache`](crate::db::cache::QueryCache), which invalidates results on writes.
+///
+/// # Arguments
+///
+/// * `cache` - The cache of rows.
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The rows, shared with the cache.
+///
+/// # Examples
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+///
+/// use sainnhe_common::cache::{LruCache, fetch_all_cached};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let cache = LruCache::new(100).ttl(Some(Duration::from_secs(5)));
+///
+/// let rows = fetch_all_cached(&cache, &mut conn, "SELECT 1", &[]).await.unwrap();
+/// let cached = fetch_all_cached(&cache, &mut conn, "SELECT 1", &[]).await.unwrap();
+///
+/// assert!(Arc::ptr_eq(&rows, &cached));
+/// # });
+/// ```
+#[cfg(feature = "sqlx")]
+pub async fn fetch_all_cached<'e, E>(
+    cache: &LruCache<QueryKey, Arc<Vec<AnyRow>>>,
+    executor: E,
+    stmt: &str,
+    args: &[Value],
+) -> Result<Arc<Vec<AnyRow>>, sqlx::Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    cache
+        .try_get_or_insert_with(QueryKey::new(stmt, args), async || {
+            Ok(Arc::new(exec::fetch_all(executor, stmt, args).await?))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::db::Value;
+
+    use super::QueryKey;
+
+    #[test]
+    fn test_query_key() {
+        let keys: HashSet<QueryKey> = [
+            QueryKey::new("SELECT * FROM t WHERE a = 1", &[]),
+            QueryKey::new("SELECT * FROM t WHERE a = 2", &[]),
+            QueryKey::new("SELECT * FROM t WHERE a = ?", &[Value::from(1)]),
+            QueryKey::new("SELECT * FROM t WHERE a = ?", &[Value::from("1")]),
+            QueryKey::new("SELECT * FROM t WHERE a = ?", &[Value::Float(f64::NAN)]),
+            QueryKey::new("SELECT * FROM t WHERE a = ?", &[Value::Float(f64::NAN)]),
+            QueryKey::new("SELECT *\nFROM t WHERE a = 1", &[]),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(keys.len(), 5);
+
+        let args = [Value::from(1), Value::from(2)];
+        let a = QueryKey::new("SELECT * FROM t WHERE a = $2 AND b = $1", &args);
+        let b = QueryKey::new("SELECT * FROM t WHERE a = $1 AND b = $2", &args);
+        let c = QueryKey::new(
+            "SELECT * FROM t WHERE a = ? AND b = ?",
+            &[Value::from(2), Value::from(1)],
+        );
+        assert_ne!(a, b);
+        assert_eq!(a, c);
+        assert_eq!(a.get_args(), [Value::from(2), Value::from(1)]);
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_fetch_all_cached_placeholder_order() {
+        use sqlx::{AnyConnection, Connection, Row};
+
+        use crate::cache::LruCache;
+
+        use super::fetch_all_cached;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let cache = LruCache::new(10);
+        let args = [Value::from(1), Value::from(2)];
+
+        let mut got = Vec::new();
+        for stmt in ["SELECT $2 - $1", "SELECT $1 - $2"] {
+            let rows = fetch_all_cached(&cache, &mut conn, stmt, &args)
+                .await
+                .unwrap();
+            got.push(rows[0].get::<i64, _>(0));
+        }
+        assert_eq!(got, [1, -1]);
+    }
+}
@@ -0,0 +1,185 @@
+//! A [`Cache`] shared by all instances through Redis.
+//!
+//! Values are encoded as JSON, and keys are prefixed with a namespace so that several caches,
+//! or several applications, can share the same Redis database.
+
+use std::{fmt::Display, marker::PhantomData, time::Duration};
+
+use ::redis::{RedisError, aio::ConnectionManager, cmd};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::cache::Cache;
+
+/// The error of [`RedisCache`].
+#[derive(Debug)]
+pub enum Error {
+    /// Redis error.
+    Redis(RedisError),
+    /// The value could not be encoded or decoded.
+    Serde(serde_json::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Redis(e) => write!(f, "redis error: {}", e),
+            Error::Serde(e) => write!(f, "serde error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Redis(e) => Some(e),
+            Error::Serde(e) => Some(e),
+        }
+    }
+}
+
+impl From<RedisError> for Error {
+    fn from(e: RedisError) -> Self {
+        Error::Redis(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serde(e)
+    }
+}
+
+/// A cache of `V` stored in Redis under `<namespace>:<key>`.
+///
+/// The connection manager reconnects automatically and is cheap to clone, so a [`RedisCache`] can be
+/// shared by all tasks.
+pub struct RedisCache<V> {
+    conn: ConnectionManager,
+    namespace: String,
+    _marker: PhantomData<fn() -> V>,
+}
+
+impl<V> Clone for RedisCache<V> {
+    fn clone(&self) -> Self {
+        RedisCache {
+            conn: self.conn.clone(),
+            namespace: self.namespace.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<V> RedisCache<V> {
+    /// Creates a new [`RedisCache`].
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The connection manager, see [`redis::Client::get_connection_manager`](::redis::Client::get_connection_manager).
+    /// * `namespace` - The prefix of keys.
+    pub fn new(conn: ConnectionManager, namespace: String) -> RedisCache<V> {
+        RedisCache {
+            conn,
+            namespace,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Gets namespace.
+    pub fn get_namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Returns the Redis key of `key`.
+    pub fn key(&self, key: &str) -> String {
+        namespaced(&self.namespace, key)
+    }
+}
+
+fn namespaced(namespace: &str, key: &str) -> String {
+    format!("{}:{}", namespace, key)
+}
+
+/// Returns the TTL in milliseconds, rounded up so that short TTLs don't become 0, which Redis rejects.
+fn ttl_ms(ttl: Duration) -> u64 {
+    u64::try_from(ttl.as_nanos().div_ceil(1_000_000))
+        .unwrap_or(u64::MAX)
+        .max(1)
+}
+
+impl<V> Cache<V> for RedisCache<V>
+where
+    V: Serialize + DeserializeOwned + Sync,
+{
+    type Error = Error;
+
+    async fn get(&self, key: &str) -> Result<Option<V>, Error> {
+        let val: Option<String> = cmd("GET")
+            .arg(self.key(key))
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(val.map(|v| serde_json::from_str(&v)).transpose()?)
+    }
+
+    async fn set(&self, key: &str, val: &V, ttl: Option<Duration>) -> Result<(), Error> {
+        let val = serde_json::to_string(val)?;
+        let mut cmd = cmd("SET");
+        cmd.arg(self.key(key)).arg(val);
+        if let Some(ttl) = ttl {
+            cmd.arg("PX").arg(ttl_ms(ttl));
+        }
+        cmd.query_async::<()>(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        cmd("DEL")
+            .arg(self.key(key))
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{namespaced, ttl_ms};
+
+    #[test]
+    fn test_namespaced() {
+        assert_eq!(namespaced("users", "1"), "users:1");
+        assert_eq!(namespaced("app:users", "a:b"), "app:users:a:b");
+    }
+
+    #[test]
+    fn test_ttl_ms() {
+        struct TC {
+            ttl: Duration,
+            want: u64,
+        }
+
+        let test_cases = vec![
+            TC {
+                ttl: Duration::from_secs(1),
+                want: 1000,
+            },
+            TC {
+                ttl: Duration::from_micros(1500),
+                want: 2,
+            },
+            TC {
+                ttl: Duration::ZERO,
+                want: 1,
+            },
+            TC {
+                ttl: Duration::MAX,
+                want: u64::MAX,
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(ttl_ms(tc.ttl), tc.want);
+        }
+    }
+}
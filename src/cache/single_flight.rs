@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::cache::Cache;
+
+/// Coalesces concurrent loads of the same missing key, so that only one caller runs the loader
+/// while the others wait for its result, which prevents cache stampedes on hot keys.
+///
+/// Loads are only coalesced within the process. With a shared cache, each instance loads a missing key
+/// at most once at a time.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::cache::{LruCache, SingleFlight};
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let cache = LruCache::<String, u64>::new(100);
+/// let flight = SingleFlight::new();
+///
+/// let res: Result<u64, std::convert::Infallible> = flight
+///     .get_or_insert_with(&cache, "answer", None, async || Ok(42))
+///     .await;
+///
+/// assert_eq!(res, Ok(42));
+/// assert_eq!(cache.get("answer"), Some(42));
+/// # });
+/// ```
+#[derive(Debug, Default)]
+pub struct SingleFlight {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl SingleFlight {
+    /// Creates a new [`SingleFlight`].
+    pub fn new() -> SingleFlight {
+        SingleFlight::default()
+    }
+
+    /// Returns the value of `key` from `cache`, loading it with `f` and storing it with `ttl` if it's missing.
+    ///
+    /// Concurrent callers missing the same key wait for the first one and then read the value it stored.
+    /// If the loader fails, the error is returned to its caller and the next waiter runs its own loader.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache.
+    /// * `key` - The key.
+    /// * `ttl` - The TTL of the loaded value.
+    /// * `f` - The loader, for example a database query.
+    ///
+    /// # Returns
+    ///
+    /// * The cached or loaded value.
+    pub async fn get_or_insert_with<C, V, F, E>(
+        &self,
+        cache: &C,
+        key: &str,
+        ttl: Option<Duration>,
+        f: F,
+    ) -> Result<V, E>
+    where
+        C: Cache<V>,
+        F: AsyncFnOnce() -> Result<V, E>,
+        E: From<C::Error>,
+    {
+        if let Some(val) = cache.get(key).await? {
+            return Ok(val);
+        }
+        let lock = self.lock().entry(key.to_string()).or_default().clone();
+        let res = async {
+            let _guard = lock.lock().await;
+            // The value may have been loaded while waiting.
+            if let Some(val) = cache.get(key).await? {
+                return Ok(val);
+            }
+            let val = f().await?;
+            cache.set(key, &val, ttl).await?;
+            Ok(val)
+        }
+        .await;
+        let mut locks = self.lock();
+        // Only the map and this caller hold the lock, so no one is waiting.
+        if Arc::strong_count(&lock) == 2 {
+            locks.remove(key);
+        }
+        res
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Arc<tokio::sync::Mutex<()>>>> {
+        self.locks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::cache::LruCache;
+
+    use super::SingleFlight;
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let cache = LruCache::<String, usize>::new(10);
+        let flight = SingleFlight::new();
+        let loads = AtomicUsize::new(0);
+        let load = async || -> Result<usize, Infallible> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(loads.fetch_add(1, Ordering::SeqCst) + 1)
+        };
+
+        let (a, b, c) = tokio::join!(
+            flight.get_or_insert_with(&cache, "k", None, load),
+            flight.get_or_insert_with(&cache, "k", None, load),
+            flight.get_or_insert_with(&cache, "k", None, load),
+        );
+        assert_eq!((a, b, c), (Ok(1), Ok(1), Ok(1)));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(flight.lock().is_empty());
+
+        // Failed loads are not cached
+        #[derive(PartialEq, Debug)]
+        struct Failed;
+
+        impl From<Infallible> for Failed {
+            fn from(e: Infallible) -> Self {
+                match e {}
+            }
+        }
+
+        let res = flight
+            .get_or_insert_with(&cache, "e", None, async || Err(Failed))
+            .await;
+        assert_eq!(res, Err(Failed));
+        assert_eq!(cache.get("e"), None);
+    }
+}
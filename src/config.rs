@@ -0,0 +1,509 @@
+//! Layered configuration loading.
+//!
+//! A [`Loader`] merges several layers into a serde struct, where later layers override earlier ones.
+//! The usual order is defaults, then a file in TOML, YAML or JSON, then environment variables with a prefix,
+//! then overrides from the command line.
+//!
+//! Objects are merged key by key, while other values, including arrays, are replaced as a whole.
+//! Environment variables and overrides are strings, which are parsed when the target field is a number or
+//! a boolean, and split by commas when it's a sequence.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{
+    Deserializer, Serialize,
+    de::{
+        self, DeserializeOwned, IntoDeserializer, Unexpected, Visitor,
+        value::{MapDeserializer, SeqDeserializer},
+    },
+    forward_to_deserialize_any,
+};
+use serde_json::{Map, Value};
+
+/// Errors returned by [`Loader::load`].
+#[derive(Debug)]
+pub enum Error {
+    /// The defaults could not be serialized.
+    Defaults(serde_json::Error),
+    /// The file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// The file could not be parsed.
+    Parse { path: PathBuf, message: String },
+    /// The extension of the file is not one of `toml`, `yaml`, `yml` and `json`.
+    Format(PathBuf),
+    /// The override is not in the form of `key=value`.
+    Override(String),
+    /// The value of a key doesn't match the target type.
+    Deserialize { key: String, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Defaults(e) => write!(f, "invalid defaults: {}", e),
+            Error::Io { path, source } => {
+                write!(f, "failed to read {}: {}", path.display(), source)
+            }
+            Error::Parse { path, message } => {
+                write!(f, "failed to parse {}: {}", path.display(), message)
+            }
+            Error::Format(path) => write!(f, "unsupported config format: {}", path.display()),
+            Error::Override(s) => write!(f, "invalid override {:?}, expected key=value", s),
+            Error::Deserialize { key, message } => write!(f, "invalid config {}: {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Defaults(e) => Some(e),
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Layer {
+    Defaults(Result<Value, serde_json::Error>),
+    File {
+        path: PathBuf,
+        required: bool,
+    },
+    /// Key paths and their values.
+    Vars(Vec<(Vec<String>, String)>),
+    Overrides(Vec<String>),
+}
+
+/// Loads layered configuration into serde structs.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::config::Loader;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Config {
+///     db: Db,
+///     debug: bool,
+/// }
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Db {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// let defaults = Config {
+///     db: Db { host: String::from("localhost"), port: 5432 },
+///     debug: false,
+/// };
+///
+/// let config: Config = Loader::new()
+///     .defaults(&defaults)
+///     .optional_file("config.toml")
+///     .env("APP")
+///     .overrides(["db.port=6543", "debug=true"])
+///     .load()
+///     .unwrap();
+///
+/// assert_eq!(config.db.host, "localhost");
+/// assert_eq!(config.db.port, 6543);
+/// assert!(config.debug);
+/// ```
+#[derive(Debug, Default)]
+pub struct Loader {
+    layers: Vec<Layer>,
+}
+
+impl Loader {
+    /// Creates a new [`Loader`] without layers.
+    pub fn new() -> Loader {
+        Loader::default()
+    }
+
+    /// Adds a layer of default values.
+    pub fn defaults<D: Serialize>(mut self, defaults: &D) -> Self {
+        self.layers
+            .push(Layer::Defaults(serde_json::to_value(defaults)));
+        self
+    }
+
+    /// Adds a layer read from a file, whose format is detected by its extension.
+    /// Loading fails if the file doesn't exist.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.layers.push(Layer::File {
+            path: path.as_ref().to_path_buf(),
+            required: true,
+        });
+        self
+    }
+
+    /// Like [`Loader::file`], but the layer is skipped if the file doesn't exist.
+    pub fn optional_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.layers.push(Layer::File {
+            path: path.as_ref().to_path_buf(),
+            required: false,
+        });
+        self
+    }
+
+    /// Adds a layer from the environment variables starting with `<prefix>_`, read when this method is called.
+    ///
+    /// The rest of the name is split by `__` into nested keys, which are lowercased,
+    /// so `APP_DB__MAX_CONNS` sets `db.max_conns` with the prefix `APP`.
+    pub fn env(self, prefix: &str) -> Self {
+        self.vars(prefix, std::env::vars())
+    }
+
+    fn vars<I: IntoIterator<Item = (String, String)>>(mut self, prefix: &str, vars: I) -> Self {
+        let prefix = format!("{}_", prefix);
+        let vars = vars
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let path: Vec<String> = k
+                    .strip_prefix(&prefix)?
+                    .split("__")
+                    .map(str::to_lowercase)
+                    .collect();
+                (!path.iter().any(String::is_empty)).then_some((path, v))
+            })
+            .collect();
+        self.layers.push(Layer::Vars(vars));
+        self
+    }
+
+    /// Adds a layer of overrides in the form of `key=value`, where nested keys are separated by `.`,
+    /// for example the values of a repeatable `--set db.port=5432` flag.
+    pub fn overrides<I, S>(mut self, overrides: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.layers.push(Layer::Overrides(
+            overrides
+                .into_iter()
+                .map(|s| s.as_ref().to_string())
+                .collect(),
+        ));
+        self
+    }
+
+    /// Merges the layers and deserializes the result.
+    ///
+    /// # Returns
+    ///
+    /// * The configuration, or an error pointing at the offending file or key.
+    pub fn load<T: DeserializeOwned>(self) -> Result<T, Error> {
+        let mut merged = Value::Object(Map::new());
+        for layer in self.layers {
+            match layer {
+                Layer::Defaults(defaults) => merge(&mut merged, defaults.map_err(Error::Defaults)?),
+                Layer::File { path, required } => {
+                    if let Some(val) = read(&path, required)? {
+                        merge(&mut merged, val);
+                    }
+                }
+                Layer::Vars(vars) => {
+                    for (path, val) in vars {
+                        set(&mut merged, &path, val);
+                    }
+                }
+                Layer::Overrides(overrides) => {
+                    for s in overrides {
+                        let Some((key, val)) = s.split_once('=') else {
+                            return Err(Error::Override(s));
+                        };
+                        let path: Vec<String> = key.split('.').map(String::from).collect();
+                        if path.iter().any(String::is_empty) {
+                            return Err(Error::Override(s));
+                        }
+                        set(&mut merged, &path, val.to_string());
+                    }
+                }
+            }
+        }
+        serde_path_to_error::deserialize(De(merged)).map_err(|e| Error::Deserialize {
+            key: e.path().to_string(),
+            message: e.into_inner().to_string(),
+        })
+    }
+}
+
+/// Reads and parses the file, returning [`None`] if it's optional and doesn't exist.
+fn read(path: &Path, required: bool) -> Result<Option<Value>, Error> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let parse = match ext.as_deref() {
+        Some("toml") => |s: &str| toml::from_str(s).map_err(|e| e.to_string()),
+        Some("yaml" | "yml") => |s: &str| serde_yaml_ng::from_str(s).map_err(|e| e.to_string()),
+        Some("json") => |s: &str| serde_json::from_str(s).map_err(|e| e.to_string()),
+        _ => return Err(Error::Format(path.to_path_buf())),
+    };
+    let s = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if !required && e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(Error::Io {
+                path: path.to_path_buf(),
+                source,
+            });
+        }
+    };
+    parse(&s).map(Some).map_err(|message| Error::Parse {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+/// Merges `src` into `dst`. An empty file parses as null, which leaves `dst` unchanged.
+fn merge(dst: &mut Value, src: Value) {
+    match (dst, src) {
+        (_, Value::Null) => {}
+        (Value::Object(dst), Value::Object(src)) => {
+            for (k, v) in src {
+                match dst.get_mut(&k) {
+                    Some(d) if v.is_object() => merge(d, v),
+                    _ => {
+                        dst.insert(k, v);
+                    }
+                }
+            }
+        }
+        (dst, src) => *dst = src,
+    }
+}
+
+/// Sets the value at `path`, replacing non-object values on the way with objects.
+fn set(dst: &mut Value, path: &[String], val: String) {
+    let mut cur = dst;
+    for key in path {
+        if !cur.is_object() {
+            *cur = Value::Object(Map::new());
+        }
+        let Value::Object(map) = cur else {
+            unreachable!()
+        };
+        cur = map.entry(key.as_str()).or_insert(Value::Null);
+    }
+    *cur = Value::String(val);
+}
+
+/// A deserializer of merged values, which parses strings into numbers, booleans and sequences on demand.
+struct De(Value);
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for De {
+    type Deserializer = De;
+
+    fn into_deserializer(self) -> De {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty),* $(,)?) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, serde_json::Error> {
+            match self.0 {
+                Value::String(s) => match s.trim().parse::<$ty>() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+                },
+                v => De(v).deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for De {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, serde_json::Error> {
+        match self.0 {
+            Value::Array(arr) => {
+                let mut seq = SeqDeserializer::new(arr.into_iter().map(De));
+                let v = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(v)
+            }
+            Value::Object(map) => {
+                let mut map = MapDeserializer::new(map.into_iter().map(|(k, v)| (k, De(v))));
+                let v = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(v)
+            }
+            v => v.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, serde_json::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            v => visitor.visit_some(De(v)),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, serde_json::Error> {
+        match self.0 {
+            Value::String(s) => {
+                let items = s
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| Value::String(s.to_string()))
+                    .collect();
+                De(Value::Array(items)).deserialize_any(visitor)
+            }
+            v => De(v).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, serde_json::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, serde_json::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use serde::Deserialize;
+
+    use super::{Error, Loader};
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    struct Config {
+        name: String,
+        db: Db,
+        #[serde(default)]
+        tags: Vec<String>,
+        timeout: Option<f64>,
+    }
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    struct Db {
+        host: String,
+        port: u16,
+        tls: bool,
+    }
+
+    fn write_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sainnhe_common_config_{}", name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load() {
+        let toml = write_file(
+            "test_load.toml",
+            "name = \"app\"\n[db]\nhost = \"db\"\nport = 5432\ntls = false\n",
+        );
+        let yaml = write_file("test_load.yaml", "db:\n  tls: true\ntags: [a]\n");
+        let json = write_file("test_load.json", "{\"timeout\": 1.5}");
+        let empty = write_file("test_load.yml", "");
+
+        let config: Config = Loader::new()
+            .file(&toml)
+            .file(&yaml)
+            .file(&json)
+            .file(&empty)
+            .optional_file("no_such_file.toml")
+            .vars(
+                "APP",
+                [
+                    (String::from("APP_DB__PORT"), String::from("6543")),
+                    (String::from("APP_TAGS"), String::from("b, c")),
+                    (String::from("OTHER_NAME"), String::from("other")),
+                    (String::from("APP_"), String::from("ignored")),
+                ],
+            )
+            .overrides(["name=cli", "db.host=localhost"])
+            .load()
+            .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                name: String::from("cli"),
+                db: Db {
+                    host: String::from("localhost"),
+                    port: 6543,
+                    tls: true,
+                },
+                tags: vec![String::from("b"), String::from("c")],
+                timeout: Some(1.5),
+            }
+        );
+    }
+
+    #[test]
+    fn test_error() {
+        let toml = write_file(
+            "test_error.toml",
+            "name = \"app\"\n[db]\nhost = \"db\"\nport = 5432\ntls = false\n",
+        );
+        let invalid = write_file("test_error.json", "{");
+
+        let res = Loader::new()
+            .file(&toml)
+            .overrides(["db.port=high"])
+            .load::<Config>();
+        match res {
+            Err(Error::Deserialize { key, message }) => {
+                assert_eq!(key, "db.port");
+                assert!(message.contains("high"), "{}", message);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let res = Loader::new().file(&toml).overrides(["db"]).load::<Config>();
+        assert!(matches!(res, Err(Error::Override(s)) if s == "db"));
+        let res = Loader::new().file("no_such_file.toml").load::<Config>();
+        assert!(matches!(res, Err(Error::Io { .. })));
+        let res = Loader::new().file(&invalid).load::<Config>();
+        assert!(matches!(res, Err(Error::Parse { .. })));
+        let res = Loader::new().file("config.ini").load::<Config>();
+        assert!(matches!(res, Err(Error::Format(_))));
+    }
+}
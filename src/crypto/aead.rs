@@ -0,0 +1,311 @@
+//! Authenticated encryption of column values.
+//!
+//! A [`Keyring`] encrypts values with its current key into [`EncryptedValue`]s, which record the version of the key,
+//! so that values encrypted with older keys stay decryptable after the current key is rotated,
+//! and [`Keyring::needs_reencrypt`] tells which values should be encrypted again.
+//!
+//! [`EncryptedValue`]s convert into [`Value::Bytes`],
+//! so they can be bound to `BYTEA` / `BLOB` columns like any other value.
+
+use std::{collections::HashMap, fmt};
+
+use aes_gcm::{
+    Aes256Gcm,
+    aead::{self, Aead, AeadCore, Generate, KeyInit, Nonce, Payload},
+};
+use chacha20poly1305::ChaCha20Poly1305;
+
+use crate::db::Value;
+
+const VERSION_LEN: usize = 4;
+
+/// Both ciphers use 96-bit nonces.
+const NONCE_LEN: usize = 12;
+
+/// Both ciphers use 128-bit tags.
+const TAG_LEN: usize = 16;
+
+/// Errors returned by this module.
+#[derive(Debug)]
+pub enum Error {
+    /// The value is encrypted with a key that is not in the keyring.
+    UnknownKey(u32),
+    /// The value is too short to be an encrypted value.
+    Malformed,
+    /// The value is tampered with, or the associated data doesn't match.
+    Aead(aead::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownKey(version) => write!(f, "unknown key version {}", version),
+            Error::Malformed => write!(f, "malformed encrypted value"),
+            Error::Aead(e) => write!(f, "aead error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Aead(e) => Some(e),
+            Error::UnknownKey(_) | Error::Malformed => None,
+        }
+    }
+}
+
+impl From<aead::Error> for Error {
+    fn from(e: aead::Error) -> Self {
+        Error::Aead(e)
+    }
+}
+
+/// A 256-bit key of a cipher.
+///
+/// AES-256-GCM is the fastest on CPUs with AES instructions, and ChaCha20-Poly1305 is the fastest on the others.
+#[derive(Clone)]
+pub struct Key(Cipher);
+
+#[derive(Clone)]
+enum Cipher {
+    // The expanded AES key schedule is large.
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(ChaCha20Poly1305),
+}
+
+impl Key {
+    /// Creates a new AES-256-GCM key.
+    pub fn aes256_gcm(key: &[u8; 32]) -> Key {
+        Key(Cipher::Aes256Gcm(Box::new(
+            Aes256Gcm::new_from_slice(key).unwrap_or_else(|_| unreachable!("keys are 32 bytes")),
+        )))
+    }
+
+    /// Creates a new ChaCha20-Poly1305 key.
+    pub fn chacha20_poly1305(key: &[u8; 32]) -> Key {
+        Key(Cipher::ChaCha20Poly1305(
+            ChaCha20Poly1305::new_from_slice(key)
+                .unwrap_or_else(|_| unreachable!("keys are 32 bytes")),
+        ))
+    }
+
+    fn encrypt(&self, plaintext: &[u8], aad: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+        match &self.0 {
+            Cipher::Aes256Gcm(c) => seal(c.as_ref(), plaintext, aad, out),
+            Cipher::ChaCha20Poly1305(c) => seal(c, plaintext, aad, out),
+        }
+    }
+
+    fn decrypt(&self, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        match &self.0 {
+            Cipher::Aes256Gcm(c) => open(c.as_ref(), nonce, ciphertext, aad),
+            Cipher::ChaCha20Poly1305(c) => open(c, nonce, ciphertext, aad),
+        }
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Cipher::Aes256Gcm(_) => f.write_str("Aes256Gcm(***)"),
+            Cipher::ChaCha20Poly1305(_) => f.write_str("ChaCha20Poly1305(***)"),
+        }
+    }
+}
+
+/// Appends a random nonce and the ciphertext to `out`.
+fn seal<A: Aead>(cipher: &A, plaintext: &[u8], aad: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+    let nonce = Nonce::<A>::generate();
+    let ciphertext = cipher.encrypt(
+        &nonce,
+        Payload {
+            msg: plaintext,
+            aad,
+        },
+    )?;
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(())
+}
+
+fn open<A: Aead + AeadCore>(
+    cipher: &A,
+    nonce: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let nonce = Nonce::<A>::try_from(nonce).map_err(|_| Error::Malformed)?;
+    Ok(cipher.decrypt(
+        &nonce,
+        Payload {
+            msg: ciphertext,
+            aad,
+        },
+    )?)
+}
+
+/// An encrypted value, laid out as the big-endian key version, the nonce, and the ciphertext with the tag.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct EncryptedValue(Vec<u8>);
+
+impl EncryptedValue {
+    /// Creates a new [`EncryptedValue`] from the bytes read from a column.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<EncryptedValue, Error> {
+        if bytes.len() < VERSION_LEN + NONCE_LEN + TAG_LEN {
+            return Err(Error::Malformed);
+        }
+        Ok(EncryptedValue(bytes))
+    }
+
+    /// Gets the version of the key encrypting the value.
+    pub fn get_version(&self) -> u32 {
+        let mut version = [0; VERSION_LEN];
+        version.copy_from_slice(&self.0[..VERSION_LEN]);
+        u32::from_be_bytes(version)
+    }
+
+    /// Returns the bytes to write to a column.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Converts into the bytes to write to a column.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<EncryptedValue> for Value {
+    fn from(v: EncryptedValue) -> Self {
+        Value::Bytes(v.0)
+    }
+}
+
+/// Versioned keys, where the current key encrypts and every key decrypts.
+///
+/// The associated data passed to [`Keyring::encrypt`] must be passed to [`Keyring::decrypt`] again.
+/// Passing the table, column and primary key of the value binds the ciphertext to its row,
+/// so it can't be copied into another row.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{
+///     crypto::aead::{Key, Keyring},
+///     db::Value,
+/// };
+///
+/// let old = Keyring::new(1, Key::aes256_gcm(&[1; 32]));
+/// let encrypted = old.encrypt(b"123-45-6789", b"users.ssn:42").unwrap();
+/// assert!(matches!(Value::from(encrypted.clone()), Value::Bytes(_)));
+///
+/// // Rotate the key, keeping the old one for decryption
+/// let keyring = Keyring::new(2, Key::chacha20_poly1305(&[2; 32])).key(1, Key::aes256_gcm(&[1; 32]));
+/// assert_eq!(keyring.decrypt(&encrypted, b"users.ssn:42").unwrap(), b"123-45-6789");
+/// assert!(keyring.decrypt(&encrypted, b"users.ssn:43").is_err());
+/// assert!(keyring.needs_reencrypt(&encrypted));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Keyring {
+    version: u32,
+    keys: HashMap<u32, Key>,
+}
+
+impl Keyring {
+    /// Creates a new [`Keyring`] whose current key is `key` of `version`.
+    pub fn new(version: u32, key: Key) -> Keyring {
+        Keyring {
+            version,
+            keys: HashMap::from([(version, key)]),
+        }
+    }
+
+    /// Adds `key` of `version`, typically a retired key, for decryption only.
+    /// The current key is never replaced.
+    pub fn key(mut self, version: u32, key: Key) -> Self {
+        self.keys.entry(version).or_insert(key);
+        self
+    }
+
+    /// Gets the version of the current key.
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    /// Encrypts `plaintext` with the current key, authenticating `aad` along with it.
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<EncryptedValue, Error> {
+        let mut out = Vec::with_capacity(VERSION_LEN + NONCE_LEN + plaintext.len() + TAG_LEN);
+        out.extend_from_slice(&self.version.to_be_bytes());
+        self.keys[&self.version].encrypt(plaintext, aad, &mut out)?;
+        Ok(EncryptedValue(out))
+    }
+
+    /// Decrypts `value` with the key of its version.
+    pub fn decrypt(&self, value: &EncryptedValue, aad: &[u8]) -> Result<Vec<u8>, Error> {
+        let version = value.get_version();
+        let key = self.keys.get(&version).ok_or(Error::UnknownKey(version))?;
+        let (nonce, ciphertext) = value.0[VERSION_LEN..].split_at(NONCE_LEN);
+        key.decrypt(nonce, ciphertext, aad)
+    }
+
+    /// Returns whether `value` is encrypted with a key other than the current one.
+    pub fn needs_reencrypt(&self, value: &EncryptedValue) -> bool {
+        value.get_version() != self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncryptedValue, Error, Key, Keyring};
+
+    #[test]
+    fn test_keyring() {
+        struct TC {
+            key: Key,
+        }
+
+        let test_cases = vec![
+            TC {
+                key: Key::aes256_gcm(&[7; 32]),
+            },
+            TC {
+                key: Key::chacha20_poly1305(&[7; 32]),
+            },
+        ];
+
+        for tc in test_cases {
+            let keyring = Keyring::new(3, tc.key);
+            let a = keyring.encrypt(b"secret", b"aad").unwrap();
+            let b = keyring.encrypt(b"secret", b"aad").unwrap();
+            // Nonces are random
+            assert_ne!(a, b);
+            assert_eq!(a.get_version(), 3);
+            assert_eq!(a.as_bytes().len(), 4 + 12 + 6 + 16);
+            assert_eq!(keyring.decrypt(&a, b"aad").unwrap(), b"secret");
+            assert!(!keyring.needs_reencrypt(&a));
+            assert!(matches!(keyring.decrypt(&a, b""), Err(Error::Aead(_))));
+
+            let mut bytes = a.into_bytes();
+            let last = bytes.len() - 1;
+            bytes[last] ^= 1;
+            let tampered = EncryptedValue::from_bytes(bytes.clone()).unwrap();
+            assert!(matches!(
+                keyring.decrypt(&tampered, b"aad"),
+                Err(Error::Aead(_))
+            ));
+
+            bytes[3] = 4;
+            let unknown = EncryptedValue::from_bytes(bytes).unwrap();
+            assert!(matches!(
+                keyring.decrypt(&unknown, b"aad"),
+                Err(Error::UnknownKey(4))
+            ));
+        }
+
+        assert!(matches!(
+            EncryptedValue::from_bytes(vec![0; 31]),
+            Err(Error::Malformed)
+        ));
+    }
+}
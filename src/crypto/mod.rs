@@ -0,0 +1,6 @@
+//! Cryptography.
+//!
+//! - [`aead`] encrypts values like sensitive columns, with the `aead` feature enabled.
+
+#[cfg(feature = "aead")]
+pub mod aead;
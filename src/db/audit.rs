@@ -0,0 +1,478 @@
+//! Heuristics that detect SQL injection attempts in the inputs of [`StmtBuilder`](crate::db::StmtBuilder).
+//!
+//! Values are embedded into statements as is, so client-provided fragments must be checked
+//! before building. [`scan`] walks the column names and values, skipping [`PLACEHOLDER`]s,
+//! and reports every suspicious fragment. Binding values to placeholders is still the only
+//! reliable defense; these heuristics are a safety net for code paths that can't do so.
+
+use crate::db::{AsCond, Cond, CondRef, PLACEHOLDER, Type, keywords, table::is_ident_too_long};
+
+/// Keywords that are flagged in [`Mode::Strict`], since they rarely appear in legitimate values.
+const KEYWORDS: &[&str] = &[
+    "ALTER", "AND", "DELETE", "DROP", "EXEC", "INSERT", "OR", "SELECT", "UNION", "UPDATE",
+];
+
+/// How strictly fragments are scanned.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Default)]
+pub enum Mode {
+    /// Flags fragments that can break out of the statement structure.
+    #[default]
+    Lenient,
+    /// Additionally flags SQL keywords in values and column names that are not plain identifiers.
+    /// Use it for every value not coming from [`PLACEHOLDER`].
+    Strict,
+}
+
+/// A suspicious pattern found in a fragment.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Issue {
+    /// A quote is not closed.
+    UnbalancedQuote,
+    /// A `;` that terminates the statement.
+    Semicolon,
+    /// A `;` followed by another statement.
+    StackedStatement,
+    /// A comment token, either `--`, `#` or `/*`.
+    Comment,
+    /// A SQL keyword outside of quoted literals. Only reported in [`Mode::Strict`].
+    Keyword,
+    /// A column name that is not a plain identifier. Only reported in [`Mode::Strict`].
+    Identifier,
+    /// A column name longer than the database type allows, see [`TableRef::max_ident_len`](crate::db::TableRef::max_ident_len).
+    /// Only reported by [`scan_for`] in [`Mode::Strict`].
+    IdentifierTooLong,
+    /// A column name that is a reserved word of the database type, see [`keywords`],
+    /// which breaks the statement unless it's quoted. Only reported by [`scan_for`] in [`Mode::Strict`].
+    ReservedWord,
+}
+
+/// An issue found in a fragment.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Finding {
+    /// The column the fragment belongs to, or an empty string for raw conditions.
+    pub col: String,
+    /// The suspicious fragment.
+    pub fragment: String,
+    /// The issue.
+    pub issue: Issue,
+}
+
+/// The result of [`scan`].
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Default)]
+pub struct Report {
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Gets all findings.
+    pub fn get_findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Returns `true` if nothing suspicious is found.
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    fn add(&mut self, col: &str, fragment: &str, issues: Vec<Issue>) {
+        self.findings
+            .extend(issues.into_iter().map(|issue| Finding {
+                col: col.to_string(),
+                fragment: fragment.to_string(),
+                issue,
+            }));
+    }
+}
+
+/// Scans the inputs of a build call.
+///
+/// Column names and values of key-value pairs and conditions are scanned,
+/// except for values that are exactly [`PLACEHOLDER`].
+/// [`Cond::Raw`] fragments are written by developers rather than clients,
+/// so only issues that break the statement structure are reported for them.
+///
+/// # Arguments
+///
+/// * `items` - The key-value pairs or conditions passed to [`StmtBuilder`](crate::db::StmtBuilder).
+/// * `mode` - The scan mode.
+///
+/// # Returns
+///
+/// * The report.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{
+///     KV, PLACEHOLDER,
+///     audit::{Issue, Mode, scan},
+/// };
+///
+/// let cols = vec![
+///     KV {
+///         key: "name",
+///         val: PLACEHOLDER,
+///     },
+///     KV {
+///         key: "nickname",
+///         val: "'x'; DROP TABLE users; --",
+///     },
+/// ];
+///
+/// let report = scan(&cols, Mode::Lenient);
+/// let issues: Vec<Issue> = report.get_findings().iter().map(|f| f.issue).collect();
+///
+/// assert_eq!(issues, [Issue::StackedStatement, Issue::Comment]);
+/// ```
+pub fn scan<P: AsCond>(items: &[P], mode: Mode) -> Report {
+    scan_items(items, mode, None)
+}
+
+/// Same as [`scan`], but additionally flags the column names that are too long for the database type
+/// or reserved words of it in [`Mode::Strict`], checking each dot-separated segment.
+///
+/// Oracle, which allows 30 bytes before 12.2 and 128 bytes since, is not a supported database type.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{
+///     Cond, PLACEHOLDER, Type,
+///     audit::{Issue, Mode, scan_for},
+/// };
+///
+/// let conds = [Cond::eq(&"x".repeat(64), PLACEHOLDER)];
+///
+/// assert!(scan_for(&conds, Mode::Strict, Type::MySQL).is_clean());
+/// assert_eq!(
+///     scan_for(&conds, Mode::Strict, Type::PostgreSQL).get_findings()[0].issue,
+///     Issue::IdentifierTooLong
+/// );
+/// ```
+pub fn scan_for<P: AsCond>(items: &[P], mode: Mode, typ: Type) -> Report {
+    scan_items(items, mode, Some(typ))
+}
+
+fn scan_items<P: AsCond>(items: &[P], mode: Mode, typ: Option<Type>) -> Report {
+    let mut report = Report::default();
+    let mode = Scan { mode, typ };
+    for item in items {
+        match item.as_cond() {
+            CondRef::Eq(kv) => {
+                scan_col(&mut report, kv.key, mode);
+                scan_val(&mut report, kv.key, kv.val, mode.mode);
+            }
+            CondRef::Tree(cond) => scan_cond(&mut report, cond, mode),
+        }
+    }
+    report
+}
+
+/// The options of a scan.
+#[derive(Clone, Copy)]
+struct Scan {
+    mode: Mode,
+    /// The database type whose identifier length is checked, if any.
+    typ: Option<Type>,
+}
+
+fn scan_cond(report: &mut Report, cond: &Cond, mode: Scan) {
+    match cond {
+        Cond::Cmp { col, val, .. } | Cond::CmpCi { col, val, .. } => {
+            scan_col(report, col, mode);
+            scan_val(report, col, val, mode.mode);
+        }
+        Cond::In { col, vals, .. } => {
+            scan_col(report, col, mode);
+            for val in vals {
+                scan_val(report, col, val, mode.mode);
+            }
+        }
+        Cond::Tuple { cols, rows, .. } => {
+            for col in cols {
+                scan_col(report, col, mode);
+            }
+            for row in rows {
+                for (col, val) in cols.iter().zip(row) {
+                    scan_val(report, col, val, mode.mode);
+                }
+            }
+        }
+        Cond::IsNull { col, .. } => scan_col(report, col, mode),
+        Cond::And(conds) | Cond::Or(conds) => {
+            for cond in conds {
+                scan_cond(report, cond, mode);
+            }
+        }
+        Cond::Not(cond) => scan_cond(report, cond, mode),
+        Cond::Raw(sql) => report.add("", sql, scan_fragment(sql, Mode::Lenient)),
+    }
+}
+
+fn scan_col(report: &mut Report, col: &str, mode: Scan) {
+    if mode.mode != Mode::Strict {
+        return;
+    }
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let valid = col == "*"
+        || (!col.is_empty()
+            && col
+                .split('.')
+                .all(|s| s.chars().all(is_ident) && !s.is_empty()));
+    if !valid {
+        report.add(col, col, vec![Issue::Identifier]);
+    }
+    let Some(typ) = mode.typ else {
+        return;
+    };
+    if col.split('.').any(|s| is_ident_too_long(typ, s)) {
+        report.add(col, col, vec![Issue::IdentifierTooLong]);
+    }
+    if col.split('.').any(|s| keywords::is_reserved(typ, s)) {
+        report.add(col, col, vec![Issue::ReservedWord]);
+    }
+}
+
+fn scan_val(report: &mut Report, col: &str, val: &str, mode: Mode) {
+    if val != PLACEHOLDER {
+        report.add(col, val, scan_fragment(val, mode));
+    }
+}
+
+/// Scans a single SQL fragment.
+///
+/// # Arguments
+///
+/// * `fragment` - The fragment.
+/// * `mode` - The scan mode.
+///
+/// # Returns
+///
+/// * The issues found, each reported once in the order of first appearance.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::audit::{Issue, Mode, scan_fragment};
+///
+/// assert!(scan_fragment("'it''s; -- fine'", Mode::Strict).is_empty());
+/// assert_eq!(scan_fragment("'x' OR 1 = 1", Mode::Strict), [Issue::Keyword]);
+/// assert_eq!(scan_fragment("'x", Mode::Lenient), [Issue::UnbalancedQuote]);
+/// ```
+pub fn scan_fragment(fragment: &str, mode: Mode) -> Vec<Issue> {
+    let bytes = fragment.as_bytes();
+    let mut issues = Vec::new();
+    let mut add = |issue| {
+        if !issues.contains(&issue) {
+            issues.push(issue);
+        }
+    };
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"' | b'`') => {
+                i += 1;
+                loop {
+                    match bytes.get(i) {
+                        None => {
+                            add(Issue::UnbalancedQuote);
+                            break;
+                        }
+                        // Doubled quotes are escaped quotes.
+                        Some(&b) if b == quote && bytes.get(i + 1) == Some(&quote) => i += 2,
+                        Some(&b) if b == quote => break,
+                        // MySQL treats backslashes as escape characters by default.
+                        Some(b'\\') => i += 2,
+                        Some(_) => i += 1,
+                    }
+                }
+            }
+            b';' => {
+                let rest = &fragment[i + 1..];
+                if rest.trim().is_empty() {
+                    add(Issue::Semicolon);
+                } else {
+                    add(Issue::StackedStatement);
+                }
+            }
+            b'#' => add(Issue::Comment),
+            b'-' if bytes.get(i + 1) == Some(&b'-') => add(Issue::Comment),
+            b'/' if bytes.get(i + 1) == Some(&b'*') => add(Issue::Comment),
+            b if mode == Mode::Strict && b.is_ascii_alphabetic() => {
+                let len = bytes[i..]
+                    .iter()
+                    .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+                    .count();
+                let word = &fragment[i..i + len];
+                if KEYWORDS.iter().any(|kw| kw.eq_ignore_ascii_case(word)) {
+                    add(Issue::Keyword);
+                }
+                i += len;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Cond, KV, PLACEHOLDER, Type};
+
+    use super::{Finding, Issue, Mode, scan, scan_for, scan_fragment};
+
+    #[test]
+    fn test_scan_fragment() {
+        struct TC<'a> {
+            fragment: &'a str,
+            want_lenient: Vec<Issue>,
+            want_strict: Vec<Issue>,
+        }
+
+        let test_cases = vec![
+            // Literals and functions
+            TC {
+                fragment: "'foo', 20, NOW(), 'a''b;--'",
+                want_lenient: vec![],
+                want_strict: vec![],
+            },
+            // Unbalanced quotes
+            TC {
+                fragment: "'foo",
+                want_lenient: vec![Issue::UnbalancedQuote],
+                want_strict: vec![Issue::UnbalancedQuote],
+            },
+            TC {
+                fragment: "'foo\\'",
+                want_lenient: vec![Issue::UnbalancedQuote],
+                want_strict: vec![Issue::UnbalancedQuote],
+            },
+            // Statement terminators
+            TC {
+                fragment: "1; ",
+                want_lenient: vec![Issue::Semicolon],
+                want_strict: vec![Issue::Semicolon],
+            },
+            TC {
+                fragment: "1; DROP TABLE users",
+                want_lenient: vec![Issue::StackedStatement],
+                want_strict: vec![Issue::StackedStatement, Issue::Keyword],
+            },
+            // Comments
+            TC {
+                fragment: "1 -- x",
+                want_lenient: vec![Issue::Comment],
+                want_strict: vec![Issue::Comment],
+            },
+            TC {
+                fragment: "1 # x /* y",
+                want_lenient: vec![Issue::Comment],
+                want_strict: vec![Issue::Comment],
+            },
+            // Keywords
+            TC {
+                fragment: "1 or 1 = 1",
+                want_lenient: vec![],
+                want_strict: vec![Issue::Keyword],
+            },
+            TC {
+                fragment: "'union' || order_id",
+                want_lenient: vec![],
+                want_strict: vec![],
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(
+                scan_fragment(tc.fragment, Mode::Lenient),
+                tc.want_lenient,
+                "{}",
+                tc.fragment
+            );
+            assert_eq!(
+                scan_fragment(tc.fragment, Mode::Strict),
+                tc.want_strict,
+                "{}",
+                tc.fragment
+            );
+        }
+    }
+
+    #[test]
+    fn test_scan() {
+        let kvs = vec![
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "age",
+                val: "20",
+            },
+        ];
+        assert!(scan(&kvs, Mode::Strict).is_clean());
+
+        let conds = vec![
+            Cond::eq("t.id", PLACEHOLDER),
+            Cond::Or(vec![
+                Cond::in_list("a", &["1", "2 OR 1 = 1"]),
+                Cond::not(Cond::is_null("b) OR (1")),
+            ]),
+            Cond::raw("a = 1 OR b = 2; --"),
+        ];
+        assert_eq!(
+            scan(&conds, Mode::Lenient).get_findings(),
+            [
+                Finding {
+                    col: String::new(),
+                    fragment: String::from("a = 1 OR b = 2; --"),
+                    issue: Issue::StackedStatement,
+                },
+                Finding {
+                    col: String::new(),
+                    fragment: String::from("a = 1 OR b = 2; --"),
+                    issue: Issue::Comment,
+                },
+            ]
+        );
+        let issues: Vec<_> = scan(&conds, Mode::Strict)
+            .get_findings()
+            .iter()
+            .map(|f| (f.col.clone(), f.issue))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                (String::from("a"), Issue::Keyword),
+                (String::from("b) OR (1"), Issue::Identifier),
+                (String::new(), Issue::StackedStatement),
+                (String::new(), Issue::Comment),
+            ]
+        );
+
+        let long = format!("t.{}", "x".repeat(64));
+        let conds = vec![Cond::is_null(&long), Cond::eq("group", PLACEHOLDER)];
+        assert!(scan_for(&conds, Mode::Lenient, Type::PostgreSQL).is_clean());
+        assert_eq!(
+            scan_for(&conds, Mode::Strict, Type::PostgreSQL).get_findings(),
+            [
+                Finding {
+                    col: long.clone(),
+                    fragment: long,
+                    issue: Issue::IdentifierTooLong,
+                },
+                Finding {
+                    col: String::from("group"),
+                    fragment: String::from("group"),
+                    issue: Issue::ReservedWord,
+                },
+            ]
+        );
+        assert_eq!(
+            scan_for(&conds, Mode::Strict, Type::TiDB).get_findings()[0].issue,
+            Issue::ReservedWord
+        );
+    }
+}
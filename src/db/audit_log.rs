@@ -0,0 +1,394 @@
+//! Audit log of data changes.
+//!
+//! An [`Entry`] records who changed which row of which table, along with the changed columns
+//! computed from the snapshots before and after the change.
+//! [`AuditLog`] writes entries into an audit table, storing the diff in a JSON column where supported.
+
+use std::fmt::{self, Write};
+
+use sqlx::AnyConnection;
+
+use crate::db::{AsKV, KV, PLACEHOLDER, StmtBuilder, Type, Value, exec, unsupported_dialect};
+
+/// The kind of change.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Action {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Action {
+    /// Returns the name stored in the audit table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Insert => "INSERT",
+            Action::Update => "UPDATE",
+            Action::Delete => "DELETE",
+        }
+    }
+}
+
+/// Who made the change.
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Default)]
+pub struct Actor {
+    /// The user or service id.
+    pub id: String,
+    /// The client IP address, if known.
+    pub ip: Option<String>,
+}
+
+/// A changed column.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Change {
+    pub col: String,
+    /// The value before the change, or [`None`] if the column is added.
+    pub before: Option<String>,
+    /// The value after the change, or [`None`] if the column is removed.
+    pub after: Option<String>,
+}
+
+/// Computes the changed columns between two snapshots.
+///
+/// Columns are compared by name, so the snapshots don't have to list them in the same order.
+///
+/// # Arguments
+///
+/// * `before` - The snapshot before the change, which is empty for inserts.
+/// * `after` - The snapshot after the change, which is empty for deletes.
+///
+/// # Returns
+///
+/// * The changed columns, in the order of `before` followed by the columns only in `after`.
+pub fn diff<P: AsKV, Q: AsKV>(before: &[P], after: &[Q]) -> Vec<Change> {
+    let find = |kvs: &[Q], col: &str| {
+        kvs.iter()
+            .map(AsKV::as_kv)
+            .find(|kv| kv.key == col)
+            .map(|kv| kv.val.to_string())
+    };
+    let mut changes: Vec<Change> = before
+        .iter()
+        .map(AsKV::as_kv)
+        .map(|kv| Change {
+            col: kv.key.to_string(),
+            before: Some(kv.val.to_string()),
+            after: find(after, kv.key),
+        })
+        .filter(|c| c.before != c.after)
+        .collect();
+    changes.extend(
+        after
+            .iter()
+            .map(AsKV::as_kv)
+            .filter(|kv| !before.iter().any(|p| p.as_kv().key == kv.key))
+            .map(|kv| Change {
+                col: kv.key.to_string(),
+                before: None,
+                after: Some(kv.val.to_string()),
+            }),
+    );
+    changes
+}
+
+pub(crate) fn write_json_str<W: Write>(w: &mut W, s: &str) -> fmt::Result {
+    w.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?,
+        }
+    }
+    w.write_char('"')
+}
+
+fn write_json_opt<W: Write>(w: &mut W, s: Option<&str>) -> fmt::Result {
+    match s {
+        Some(s) => write_json_str(w, s),
+        None => w.write_str("null"),
+    }
+}
+
+/// A change of a row.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Entry {
+    pub tbl: String,
+    pub action: Action,
+    /// The primary key of the changed row, rendered as a string.
+    pub row_id: String,
+    pub actor: Actor,
+    pub changes: Vec<Change>,
+}
+
+impl Entry {
+    /// Creates a new [`Entry`], computing the changes via [`diff`].
+    pub fn new<P: AsKV, Q: AsKV>(
+        tbl: String,
+        action: Action,
+        row_id: String,
+        actor: Actor,
+        before: &[P],
+        after: &[Q],
+    ) -> Entry {
+        Entry {
+            tbl,
+            action,
+            row_id,
+            actor,
+            changes: diff(before, after),
+        }
+    }
+
+    /// Renders the changes as a JSON object, for example `{"name":{"before":"'foo'","after":"'bar'"}}`.
+    pub fn diff_json(&self) -> String {
+        let mut json = String::from("{");
+        for (i, c) in self.changes.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            // Writing to a String never fails.
+            let _ = write_json_str(&mut json, &c.col);
+            json.push_str(":{\"before\":");
+            let _ = write_json_opt(&mut json, c.before.as_deref());
+            json.push_str(",\"after\":");
+            let _ = write_json_opt(&mut json, c.after.as_deref());
+            json.push('}');
+        }
+        json.push('}');
+        json
+    }
+}
+
+/// An audit table.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{
+///     KV, Type,
+///     audit_log::{Action, Actor, AuditLog, Entry},
+///     exec::execute,
+/// };
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let log = AuditLog::new(String::from("audit_log"), Type::SQLite);
+/// execute(&mut conn, &log.ddl().unwrap(), &[]).await.unwrap();
+///
+/// let before = [KV { key: "name", val: "'foo'" }, KV { key: "age", val: "20" }];
+/// let after = [KV { key: "name", val: "'bar'" }, KV { key: "age", val: "20" }];
+/// let actor = Actor {
+///     id: String::from("admin"),
+///     ip: None,
+/// };
+/// let entry = Entry::new(String::from("users"), Action::Update, String::from("1"), actor, &before, &after);
+///
+/// assert_eq!(entry.diff_json(), r#"{"name":{"before":"'foo'","after":"'bar'"}}"#);
+/// log.record(&mut conn, &entry).await.unwrap();
+/// # });
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct AuditLog {
+    sb: StmtBuilder,
+}
+
+impl AuditLog {
+    /// Creates a new [`AuditLog`], where `tbl` is the table name and `typ` is the database type.
+    pub fn new(tbl: String, typ: Type) -> AuditLog {
+        AuditLog {
+            sb: StmtBuilder::new(tbl, typ),
+        }
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        self.sb.get_tbl()
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        self.sb.get_typ()
+    }
+
+    /// Generates the statement that creates the audit table if it doesn't exist.
+    ///
+    /// The diff is stored as `JSONB` on PostgreSQL, `JSON` on MySQL and `TEXT` on SQLite.
+    /// On TiDB, ids are `AUTO_RANDOM` to avoid write hotspots, so they don't follow the insertion order.
+    /// Returns an error on DuckDB, which sqlx has no driver for.
+    pub fn ddl(&self) -> Result<String, sqlx::Error> {
+        let (id, text, json) = match self.get_typ() {
+            Type::MySQL | Type::MariaDB => (
+                "id BIGINT AUTO_INCREMENT PRIMARY KEY",
+                "VARCHAR(255)",
+                "JSON",
+            ),
+            Type::TiDB => ("id BIGINT AUTO_RANDOM PRIMARY KEY", "VARCHAR(255)", "JSON"),
+            Type::PostgreSQL | Type::CockroachDB => ("id BIGSERIAL PRIMARY KEY", "TEXT", "JSONB"),
+            Type::SQLite => ("id INTEGER PRIMARY KEY AUTOINCREMENT", "TEXT", "TEXT"),
+            Type::DuckDB => return Err(unsupported_dialect(Type::DuckDB)),
+        };
+        Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}, tbl {text} NOT NULL, action VARCHAR(16) NOT NULL, row_id {text} NOT NULL, actor_id {text} NOT NULL, actor_ip {text}, diff {} NOT NULL, created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+            self.get_tbl(),
+            id,
+            json,
+            text = text
+        ))
+    }
+
+    /// Generates the statement that inserts an entry, along with its arguments.
+    pub fn insert_stmt(&self, entry: &Entry) -> (String, Vec<Value>) {
+        let kv = |key| KV {
+            key,
+            val: PLACEHOLDER,
+        };
+        // PostgreSQL doesn't cast text parameters to JSONB implicitly.
+        // The diff is the 6th column, so its placeholder is `$6`.
+        let diff = match self.get_typ() {
+            Type::PostgreSQL | Type::CockroachDB => "CAST($6 AS JSONB)",
+            Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite | Type::DuckDB => PLACEHOLDER,
+        };
+        let cols = [
+            kv("tbl"),
+            kv("action"),
+            kv("row_id"),
+            kv("actor_id"),
+            kv("actor_ip"),
+            KV {
+                key: "diff",
+                val: diff,
+            },
+        ];
+        let args = vec![
+            Value::from(entry.tbl.as_str()),
+            Value::from(entry.action.as_str()),
+            Value::from(entry.row_id.as_str()),
+            Value::from(entry.actor.id.as_str()),
+            entry.actor.ip.as_deref().map_or(Value::Null, Value::from),
+            Value::from(entry.diff_json()),
+        ];
+        (self.sb.build_insert_stmt(&cols), args)
+    }
+
+    /// Writes an entry, usually within the transaction that makes the change.
+    pub async fn record(&self, conn: &mut AnyConnection, entry: &Entry) -> Result<(), sqlx::Error> {
+        let (stmt, args) = self.insert_stmt(entry);
+        exec::execute(conn, &stmt, &args).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{AnyConnection, Connection, Row};
+
+    use crate::db::{KV, KVBuf, Type, exec};
+
+    use super::{Action, Actor, AuditLog, Change, Entry, diff};
+
+    #[test]
+    fn test_diff() {
+        let kv = |key, val| KV { key, val };
+        let before = [kv("a", "1"), kv("b", "2"), kv("c", "3")];
+        let after = [kv("d", "4"), kv("c", "3"), kv("a", "5")];
+        let change = |col: &str, before: Option<&str>, after: Option<&str>| Change {
+            col: col.to_string(),
+            before: before.map(String::from),
+            after: after.map(String::from),
+        };
+        assert_eq!(
+            diff(&before, &after),
+            [
+                change("a", Some("1"), Some("5")),
+                change("b", Some("2"), None),
+                change("d", None, Some("4")),
+            ]
+        );
+        assert!(diff(&before, &before).is_empty());
+
+        let entry = Entry::new(
+            String::from("t"),
+            Action::Insert,
+            String::from("1"),
+            Actor::default(),
+            &[] as &[KVBuf],
+            &[kv("note", "'say \"hi\"\n'")],
+        );
+        assert_eq!(
+            entry.diff_json(),
+            r#"{"note":{"before":null,"after":"'say \"hi\"\n'"}}"#
+        );
+    }
+
+    #[test]
+    fn test_insert_stmt() {
+        let entry = Entry::new::<KV, KV>(
+            String::from("t"),
+            Action::Delete,
+            String::from("1"),
+            Actor::default(),
+            &[],
+            &[],
+        );
+        let (stmt, args) =
+            AuditLog::new(String::from("audit_log"), Type::PostgreSQL).insert_stmt(&entry);
+        assert_eq!(
+            stmt,
+            "INSERT INTO audit_log (\"tbl\", \"action\", \"row_id\", \"actor_id\", \"actor_ip\", \"diff\") VALUES ($1, $2, $3, $4, $5, CAST($6 AS JSONB))"
+        );
+        assert_eq!(args.len(), 6);
+
+        let log = AuditLog::new(String::from("audit_log"), Type::DuckDB);
+        assert!(matches!(log.ddl(), Err(sqlx::Error::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_record() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let log = AuditLog::new(String::from("audit_log"), Type::SQLite);
+        exec::execute(&mut conn, &log.ddl().unwrap(), &[])
+            .await
+            .unwrap();
+
+        let actor = Actor {
+            id: String::from("u1"),
+            ip: Some(String::from("127.0.0.1")),
+        };
+        let before = [KV {
+            key: "name",
+            val: "'foo'",
+        }];
+        let entry = Entry::new::<KV, KV>(
+            String::from("users"),
+            Action::Delete,
+            String::from("7"),
+            actor,
+            &before,
+            &[],
+        );
+        log.record(&mut conn, &entry).await.unwrap();
+
+        let rows = exec::fetch_all(
+            &mut conn,
+            "SELECT action, actor_ip, diff FROM audit_log",
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get::<String, _>(0), "DELETE");
+        assert_eq!(rows[0].get::<String, _>(1), "127.0.0.1");
+        assert_eq!(
+            rows[0].get::<String, _>(2),
+            r#"{"name":{"before":"'foo'","after":null}}"#
+        );
+    }
+}
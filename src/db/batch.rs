@@ -0,0 +1,192 @@
+use crate::db::Type;
+
+/// Multiple statements run as one script, for example by migrations and setup routines.
+///
+/// Statements are pushed without their trailing semicolons and rendered with the delimiters of the database type.
+/// They are run as plain SQL without binding, so render values as literals,
+/// for example via [`Value::to_literal`](crate::db::Value::to_literal), instead of using placeholders.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Batch, KV, StmtBuilder, Type};
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+/// let mut batch = Batch::new(Type::MySQL);
+/// batch.push(String::from("CREATE TABLE users (id INT PRIMARY KEY, name TEXT)"));
+/// batch.push(sb.build_insert_stmt(&[KV { key: "id", val: "1" }, KV { key: "name", val: "'alice'" }]));
+/// batch.push(String::from(
+///     "CREATE TRIGGER trim_name BEFORE INSERT ON users FOR EACH ROW BEGIN SET NEW.name = TRIM(NEW.name); END",
+/// ));
+///
+/// assert_eq!(
+///     batch.render(),
+///     "CREATE TABLE users (id INT PRIMARY KEY, name TEXT);\n\
+///     INSERT INTO users (`id`, `name`) VALUES (1, 'alice');\n\
+///     DELIMITER $$\n\
+///     CREATE TRIGGER trim_name BEFORE INSERT ON users FOR EACH ROW BEGIN SET NEW.name = TRIM(NEW.name); END$$\n\
+///     DELIMITER ;\n"
+/// );
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Batch {
+    typ: Type,
+    stmts: Vec<String>,
+}
+
+/// The delimiter of MySQL scripts while a statement contains semicolons.
+const MYSQL_DELIMITER: &str = "$$";
+
+impl Batch {
+    /// Creates an empty [`Batch`].
+    pub fn new(typ: Type) -> Batch {
+        Batch {
+            typ,
+            stmts: Vec::new(),
+        }
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        &self.typ
+    }
+
+    /// Gets the statements.
+    pub fn get_stmts(&self) -> &Vec<String> {
+        &self.stmts
+    }
+
+    /// Returns the number of statements.
+    pub fn len(&self) -> usize {
+        self.stmts.len()
+    }
+
+    /// Returns `true` if there are no statements.
+    pub fn is_empty(&self) -> bool {
+        self.stmts.is_empty()
+    }
+
+    /// Appends a statement. Trailing semicolons are removed, and blank statements are ignored.
+    pub fn push(&mut self, mut stmt: String) {
+        let len = stmt.trim_end().trim_end_matches(';').trim_end().len();
+        if stmt[..len].trim_start().is_empty() {
+            return;
+        }
+        stmt.truncate(len);
+        self.stmts.push(stmt);
+    }
+
+    /// Renders the statements as a script read by the command-line client of the database type.
+    ///
+    /// Each statement is terminated by a semicolon and a newline.
+    /// MySQL clients split statements at every semicolon, so statements containing semicolons,
+    /// like triggers and procedures, are wrapped in `DELIMITER` commands there.
+    pub fn render(&self) -> String {
+        let len: usize = self.stmts.iter().map(|stmt| stmt.len() + 2).sum();
+        let mut script = String::with_capacity(len);
+        for stmt in &self.stmts {
+            if self.typ.is_mysql_family() && stmt.contains(';') {
+                script.push_str("DELIMITER ");
+                script.push_str(MYSQL_DELIMITER);
+                script.push('\n');
+                script.push_str(stmt);
+                script.push_str(MYSQL_DELIMITER);
+                script.push_str("\nDELIMITER ;\n");
+            } else {
+                script.push_str(stmt);
+                script.push_str(";\n");
+            }
+        }
+        script
+    }
+
+    /// Runs the statements in one round trip.
+    ///
+    /// `DELIMITER` is a command of the MySQL clients rather than SQL,
+    /// so the statements are joined by plain semicolons, which the servers split correctly.
+    /// The statements are not run in a transaction by themselves,
+    /// so pass a transaction to roll back the earlier statements if a later one fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The pool, connection or transaction.
+    ///
+    /// # Returns
+    ///
+    /// * The total number of rows affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Batch, Type};
+    /// use sqlx::{AnyConnection, Connection};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// sqlx::any::install_default_drivers();
+    /// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+    ///
+    /// let mut batch = Batch::new(Type::SQLite);
+    /// batch.push(String::from("CREATE TABLE t (a INTEGER)"));
+    /// batch.push(String::from("INSERT INTO t (a) VALUES (1), (2);"));
+    ///
+    /// assert_eq!(batch.execute(&mut conn).await.unwrap(), 2);
+    /// # });
+    /// ```
+    #[cfg(feature = "sqlx")]
+    pub async fn execute<'c, C>(&self, conn: C) -> Result<u64, sqlx::Error>
+    where
+        C: sqlx::Acquire<'c, Database = sqlx::Any>,
+    {
+        if self.stmts.is_empty() {
+            return Ok(0);
+        }
+        let sql = self.stmts.join(";\n");
+        let mut conn = conn.acquire().await?;
+        let result = sqlx::raw_sql(&sql).execute(&mut *conn).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::Batch;
+
+    #[test]
+    fn test_push() {
+        let mut batch = Batch::new(Type::PostgreSQL);
+        batch.push(String::from("SELECT 1"));
+        batch.push(String::from("SELECT 2 ; \n"));
+        batch.push(String::from(" ;; "));
+        batch.push(String::new());
+        assert_eq!(batch.get_stmts(), &vec!["SELECT 1", "SELECT 2"]);
+        assert_eq!(batch.render(), "SELECT 1;\nSELECT 2;\n");
+    }
+
+    #[test]
+    fn test_render() {
+        struct TC {
+            typ: Type,
+            want: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                typ: Type::MariaDB,
+                want: "DELIMITER $$\nBEGIN NOT ATOMIC SELECT 1; END$$\nDELIMITER ;\nSELECT 2;\n",
+            },
+            TC {
+                typ: Type::PostgreSQL,
+                want: "BEGIN NOT ATOMIC SELECT 1; END;\nSELECT 2;\n",
+            },
+        ];
+
+        for tc in test_cases {
+            let mut batch = Batch::new(tc.typ);
+            batch.push(String::from("BEGIN NOT ATOMIC SELECT 1; END"));
+            batch.push(String::from("SELECT 2"));
+            assert_eq!(batch.render(), tc.want);
+        }
+    }
+}
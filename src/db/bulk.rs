@@ -0,0 +1,306 @@
+//! Bulk loading with PostgreSQL `COPY FROM STDIN` and MySQL `LOAD DATA LOCAL INFILE`.
+//!
+//! Both statements read rows in a tab-separated text format,
+//! which is much faster than multi-row `INSERT` statements for large imports.
+//! SQLite has no such statement, use [`StmtBuilder::build_batch_insert_stmt`](crate::db::StmtBuilder::build_batch_insert_stmt)
+//! inside a transaction instead. DuckDB reads files directly,
+//! see [`StmtBuilder::build_copy_from_stmt`](crate::db::StmtBuilder::build_copy_from_stmt).
+//!
+//! With the `sqlx` feature enabled, [`copy_in`] streams rows to PostgreSQL.
+//! sqlx doesn't support `LOAD DATA LOCAL INFILE`, so on MySQL pass the statement and the encoded rows
+//! to a driver that supports local infile handlers.
+
+use std::fmt::Write;
+
+use crate::db::{Type, Value};
+
+/// The size of the chunks sent by [`copy_in`].
+#[cfg(feature = "sqlx")]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Builder of bulk-load statements and the data they read.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, Value, bulk::BulkLoad};
+///
+/// let load = BulkLoad::new(
+///     String::from("users"),
+///     Type::PostgreSQL,
+///     vec![String::from("id"), String::from("name")],
+/// );
+///
+/// assert_eq!(
+///     load.build_stmt("users.tsv").unwrap(),
+///     "COPY users (id, name) FROM STDIN"
+/// );
+/// assert_eq!(
+///     load.encode([[Value::from(1), Value::from("a\tb")], [Value::from(2), Value::Null]]),
+///     b"1\ta\\tb\n2\t\\N\n"
+/// );
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct BulkLoad {
+    tbl: String,
+    typ: Type,
+    cols: Vec<String>,
+}
+
+impl BulkLoad {
+    /// Creates a new [`BulkLoad`] that loads the columns `cols` of the table `tbl`.
+    pub fn new(tbl: String, typ: Type, cols: Vec<String>) -> BulkLoad {
+        BulkLoad { tbl, typ, cols }
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        &self.tbl
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        &self.typ
+    }
+
+    /// Gets column names.
+    pub fn get_cols(&self) -> &Vec<String> {
+        &self.cols
+    }
+
+    /// Builds the bulk-load statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file name passed to the local infile handler on MySQL. It's ignored on PostgreSQL,
+    ///   which reads from STDIN.
+    ///
+    /// # Returns
+    ///
+    /// * The statement, or [`None`] on SQLite and DuckDB.
+    pub fn build_stmt(&self, file: &str) -> Option<String> {
+        let cols = self.cols.join(", ");
+        match self.typ {
+            Type::PostgreSQL | Type::CockroachDB => {
+                Some(format!("COPY {} ({}) FROM STDIN", self.tbl, cols))
+            }
+            Type::MySQL | Type::MariaDB | Type::TiDB => Some(format!(
+                "LOAD DATA LOCAL INFILE {} INTO TABLE {} CHARACTER SET utf8mb4 \
+                FIELDS TERMINATED BY '\\t' ESCAPED BY '\\\\' LINES TERMINATED BY '\\n' ({})",
+                Value::from(file).to_literal(Type::MySQL),
+                self.tbl,
+                cols
+            )),
+            Type::SQLite | Type::DuckDB => None,
+        }
+    }
+
+    /// Appends `row` to `buf` as a line read by the statement of [`BulkLoad::build_stmt`].
+    ///
+    /// The values must be in the order of the columns.
+    pub fn encode_row<R: AsRef<[Value]>>(&self, row: R, buf: &mut Vec<u8>) {
+        for (i, val) in row.as_ref().iter().enumerate() {
+            if i > 0 {
+                buf.push(b'\t');
+            }
+            self.encode_value(val, buf);
+        }
+        buf.push(b'\n');
+    }
+
+    /// Encodes all `rows`, see [`BulkLoad::encode_row`].
+    pub fn encode<I: IntoIterator<Item = R>, R: AsRef<[Value]>>(&self, rows: I) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for row in rows {
+            self.encode_row(row, &mut buf);
+        }
+        buf
+    }
+
+    fn encode_value(&self, val: &Value, buf: &mut Vec<u8>) {
+        match val {
+            Value::Null => buf.extend_from_slice(b"\\N"),
+            Value::Bool(v) => buf.extend_from_slice(match (self.typ, v) {
+                (Type::PostgreSQL, true) => b"t",
+                (Type::PostgreSQL, false) => b"f",
+                (_, true) => b"1",
+                (_, false) => b"0",
+            }),
+            Value::Int(v) => buf.extend_from_slice(v.to_string().as_bytes()),
+            Value::Float(v) if v.is_finite() => buf.extend_from_slice(v.to_string().as_bytes()),
+            Value::Float(v) => buf.extend_from_slice(match self.typ {
+                Type::PostgreSQL | Type::CockroachDB if v.is_nan() => b"NaN",
+                Type::PostgreSQL | Type::CockroachDB if *v > 0.0 => b"Infinity",
+                Type::PostgreSQL | Type::CockroachDB => b"-Infinity",
+                Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite | Type::DuckDB => b"\\N",
+            }),
+            Value::Text(v) => escape(v.as_bytes(), buf),
+            Value::Bytes(v) => match self.typ {
+                // bytea accepts the hex format, whose leading backslash must be escaped.
+                Type::PostgreSQL | Type::CockroachDB => {
+                    let mut hex = String::with_capacity(v.len() * 2 + 3);
+                    hex.push_str("\\\\x");
+                    for b in v {
+                        // Writing to a String never fails.
+                        let _ = write!(hex, "{:02x}", b);
+                    }
+                    buf.extend_from_slice(hex.as_bytes());
+                }
+                Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite | Type::DuckDB => {
+                    escape(v, buf)
+                }
+            },
+        }
+    }
+}
+
+/// Escapes the bytes that have special meanings in the text format.
+fn escape(v: &[u8], buf: &mut Vec<u8>) {
+    for &b in v {
+        match b {
+            b'\\' => buf.extend_from_slice(b"\\\\"),
+            b'\t' => buf.extend_from_slice(b"\\t"),
+            b'\n' => buf.extend_from_slice(b"\\n"),
+            b'\r' => buf.extend_from_slice(b"\\r"),
+            // MySQL reads `\0` as NUL, while PostgreSQL rejects NUL in text anyway.
+            0 => buf.extend_from_slice(b"\\0"),
+            b => buf.push(b),
+        }
+    }
+}
+
+/// Streams `rows` to PostgreSQL with `COPY FROM STDIN`.
+///
+/// Rows are encoded lazily and sent in chunks, so the iterator can produce more rows than fit in memory.
+/// If sending fails, the copy is aborted and nothing is loaded.
+///
+/// # Arguments
+///
+/// * `conn` - The PostgreSQL connection.
+/// * `load` - The bulk load, whose database type must be PostgreSQL.
+/// * `rows` - The rows, whose values are in the order of the columns.
+///
+/// # Returns
+///
+/// * The number of rows loaded.
+#[cfg(feature = "sqlx")]
+pub async fn copy_in<I, R>(
+    conn: &mut sqlx::PgConnection,
+    load: &BulkLoad,
+    rows: I,
+) -> Result<u64, sqlx::Error>
+where
+    I: IntoIterator<Item = R>,
+    R: AsRef<[Value]>,
+{
+    let stmt = load
+        .build_stmt("")
+        .filter(|_| load.typ == Type::PostgreSQL)
+        .ok_or_else(|| sqlx::Error::Configuration("COPY requires PostgreSQL".into()))?;
+    let mut copy = conn.copy_in_raw(&stmt).await?;
+    let mut buf = Vec::with_capacity(CHUNK_SIZE);
+    for row in rows {
+        load.encode_row(row, &mut buf);
+        if buf.len() >= CHUNK_SIZE {
+            let chunk = std::mem::replace(&mut buf, Vec::with_capacity(CHUNK_SIZE));
+            copy.send(chunk).await?;
+        }
+    }
+    if !buf.is_empty() {
+        copy.send(buf).await?;
+    }
+    copy.finish().await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Type, Value};
+
+    use super::BulkLoad;
+
+    #[test]
+    fn test_build_stmt() {
+        let load = |typ| {
+            BulkLoad::new(
+                String::from("t"),
+                typ,
+                vec![String::from("a"), String::from("b")],
+            )
+        };
+        assert_eq!(
+            load(Type::PostgreSQL).build_stmt("f").unwrap(),
+            "COPY t (a, b) FROM STDIN"
+        );
+        assert_eq!(
+            load(Type::MySQL).build_stmt("it's.tsv").unwrap(),
+            "LOAD DATA LOCAL INFILE 'it''s.tsv' INTO TABLE t CHARACTER SET utf8mb4 \
+            FIELDS TERMINATED BY '\\t' ESCAPED BY '\\\\' LINES TERMINATED BY '\\n' (a, b)"
+        );
+        assert_eq!(load(Type::SQLite).build_stmt("f"), None);
+    }
+
+    #[test]
+    fn test_encode() {
+        struct TC {
+            val: Value,
+            want_mysql: &'static [u8],
+            want_postgresql: &'static [u8],
+        }
+
+        let test_cases = vec![
+            TC {
+                val: Value::Null,
+                want_mysql: b"\\N",
+                want_postgresql: b"\\N",
+            },
+            TC {
+                val: Value::Bool(true),
+                want_mysql: b"1",
+                want_postgresql: b"t",
+            },
+            TC {
+                val: Value::Int(-42),
+                want_mysql: b"-42",
+                want_postgresql: b"-42",
+            },
+            TC {
+                val: Value::Float(1.5),
+                want_mysql: b"1.5",
+                want_postgresql: b"1.5",
+            },
+            TC {
+                val: Value::Float(f64::NEG_INFINITY),
+                want_mysql: b"\\N",
+                want_postgresql: b"-Infinity",
+            },
+            TC {
+                val: Value::from("a\\b\tc\r\nd"),
+                want_mysql: b"a\\\\b\\tc\\r\\nd",
+                want_postgresql: b"a\\\\b\\tc\\r\\nd",
+            },
+            TC {
+                val: Value::Bytes(vec![0x00, b'\t', 0xff]),
+                want_mysql: b"\\0\\t\xff",
+                want_postgresql: b"\\\\x0009ff",
+            },
+        ];
+
+        for tc in test_cases {
+            for (typ, want) in [
+                (Type::MySQL, tc.want_mysql),
+                (Type::PostgreSQL, tc.want_postgresql),
+            ] {
+                let load = BulkLoad::new(String::from("t"), typ, vec![String::from("a")]);
+                let mut want = want.to_vec();
+                want.push(b'\n');
+                assert_eq!(load.encode([[tc.val.clone()]]), want);
+            }
+        }
+
+        let load = BulkLoad::new(String::from("t"), Type::MySQL, Vec::new());
+        assert_eq!(
+            load.encode(vec![vec![Value::from(1), Value::from("x")], vec![]]),
+            b"1\tx\n\n"
+        );
+    }
+}
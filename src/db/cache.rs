@@ -0,0 +1,706 @@
+//! Caching query results with table-based invalidation.
+//!
+//! [`QueryCache`] wraps the helpers in [`exec`]. Reads are served from an in-memory cache,
+//! and writes invalidate the cached reads of the tables they touch.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use sqlx::{Any, Executor, any::AnyRow};
+
+use crate::{
+    cache::{LruCache, QueryKey},
+    db::{Value, exec},
+};
+
+/// Words that make a statement starting with `SELECT` or `WITH` a write, or a locking read.
+const WRITE_WORDS: [&str; 7] = [
+    "INSERT", "UPDATE", "DELETE", "MERGE", "INTO", "LOCK", "SHARE",
+];
+
+/// Words followed by a table name.
+const TABLE_WORDS: [&str; 8] = [
+    "FROM", "JOIN", "INTO", "UPDATE", "TABLE", "USING", "TRUNCATE", "COPY",
+];
+
+/// Words that may appear between a word in [`TABLE_WORDS`] and the table name.
+const SKIPPED_WORDS: [&str; 5] = ["IF", "NOT", "EXISTS", "ONLY", "TABLE"];
+
+/// The first words of statements that write the tables they reference.
+const WRITE_STMT_WORDS: [&str; 14] = [
+    "SELECT", "WITH", "VALUES", "INSERT", "UPDATE", "DELETE", "MERGE", "REPLACE", "UPSERT",
+    "CREATE", "DROP", "ALTER", "TRUNCATE", "COPY",
+];
+
+/// The first words of statements that neither read nor write tables.
+const NO_OP_WORDS: [&str; 9] = [
+    "BEGIN",
+    "START",
+    "COMMIT",
+    "END",
+    "ROLLBACK",
+    "SAVEPOINT",
+    "RELEASE",
+    "SET",
+    "SHOW",
+];
+
+/// How a statement accesses the tables.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Access {
+    /// Reads the referenced tables.
+    Read,
+    /// Writes the referenced tables.
+    Write,
+    /// May write any table, since the statement can't be classified, like `CALL proc()`.
+    WriteAll,
+    /// Accesses no table, like `BEGIN`.
+    None,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Punct(char),
+}
+
+impl Token {
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self, Token::Word(w) if w.eq_ignore_ascii_case(word))
+    }
+
+    /// Returns the identifier, if the token may be one.
+    fn ident(&self) -> Option<&str> {
+        match self {
+            Token::Word(w) if !w.starts_with(|c: char| c.is_ascii_digit()) => Some(w),
+            Token::Quoted(w) => Some(w),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a statement into words, quoted identifiers and punctuations, skipping literals and comments.
+fn tokenize(stmt: &str) -> Vec<Token> {
+    let chars: Vec<char> = stmt.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                let mut s = String::new();
+                i += 1;
+                while i < chars.len() {
+                    if chars[i] == close {
+                        // A doubled quote is an escaped quote.
+                        if chars.get(i + 1) == Some(&close) && close != ']' {
+                            s.push(close);
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    s.push(chars[i]);
+                    i += 1;
+                }
+                i += 1;
+                if c != '\'' {
+                    tokens.push(Token::Quoted(s));
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let begin = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '$'))
+                {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[begin..i].iter().collect()));
+            }
+            c if c.is_whitespace() => i += 1,
+            c => {
+                tokens.push(Token::Punct(c));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+/// Parses the possibly qualified table name at `i`, returning its last part in lower case
+/// and the position after it.
+fn table_at(tokens: &[Token], mut i: usize) -> Option<(String, usize)> {
+    let mut name = tokens.get(i)?.ident()?;
+    i += 1;
+    while tokens.get(i) == Some(&Token::Punct('.')) {
+        let Some(part) = tokens.get(i + 1).and_then(Token::ident) else {
+            break;
+        };
+        name = part;
+        i += 2;
+    }
+    Some((name.to_lowercase(), i))
+}
+
+/// Returns how the statement accesses the tables, and the tables it references in lower case,
+/// without schema qualifiers.
+///
+/// The scan is lexical, so it may report extra names, such as the column in `EXTRACT(YEAR FROM col)`,
+/// which only causes extra invalidations.
+fn analyze(stmt: &str) -> (Access, Vec<String>) {
+    let tokens = tokenize(stmt);
+    let first_is = |words: &[&str]| {
+        tokens
+            .first()
+            .is_some_and(|t| words.iter().any(|w| t.is_word(w)))
+    };
+    if first_is(&NO_OP_WORDS) {
+        return (Access::None, Vec::new());
+    }
+    let read = first_is(&["SELECT", "WITH", "VALUES"])
+        && !tokens
+            .iter()
+            .any(|t| WRITE_WORDS.iter().any(|w| t.is_word(w)));
+    let mut tables = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if !TABLE_WORDS.iter().any(|w| tokens[i].is_word(w)) {
+            i += 1;
+            continue;
+        }
+        i += 1;
+        while tokens
+            .get(i)
+            .is_some_and(|t| SKIPPED_WORDS.iter().any(|w| t.is_word(w)))
+        {
+            i += 1;
+        }
+        // A comma separated list of tables, each of which may have an alias.
+        while let Some((tbl, next)) = table_at(&tokens, i) {
+            tables.push(tbl);
+            i = next;
+            if tokens.get(i).is_some_and(|t| t.is_word("AS")) {
+                i += 1;
+            }
+            let alias = tokens.get(i).and_then(Token::ident).is_some()
+                && tokens.get(i + 1) == Some(&Token::Punct(','));
+            if alias {
+                i += 1;
+            }
+            if tokens.get(i) != Some(&Token::Punct(',')) {
+                break;
+            }
+            i += 1;
+        }
+    }
+    tables.sort();
+    tables.dedup();
+    let access = if read {
+        Access::Read
+    } else if first_is(&WRITE_STMT_WORDS) && !tables.is_empty() {
+        Access::Write
+    } else {
+        Access::WriteAll
+    };
+    (access, tables)
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Fetch {
+    All,
+    Optional,
+}
+
+#[derive(Clone)]
+struct Entry {
+    rows: Arc<Vec<AnyRow>>,
+    tables: Arc<[String]>,
+}
+
+/// Wraps the helpers in [`exec`] and caches the results of reads in memory.
+///
+/// Statements starting with `SELECT`, `WITH` or `VALUES` are reads, unless they contain words like
+/// `INSERT`, `UPDATE`, `INTO` or `LOCK`, which includes locking reads such as `SELECT ... FOR UPDATE`.
+/// Reads are cached by [`QueryKey`] together with the tables they reference.
+/// Reads that reference no table, like `SELECT NOW()` or `SELECT nextval('s')`, are never cached,
+/// since no write would ever invalidate them.
+/// Every other statement executed through the cache is a write, which invalidates the cached reads of
+/// the tables it references once it finishes, whether it succeeds or not.
+/// Transaction control statements like `BEGIN` don't invalidate anything,
+/// while writes whose tables can't be found, like `CALL proc()`, invalidate every cached read.
+///
+/// Tables are matched case-insensitively by their unqualified names, so `app.users` and `"Users"` are
+/// both `users`.
+///
+/// Writes that don't go through the cache, including those of other processes, are invisible to it,
+/// so either pick TTLs that match how stale the rows may be, or call [`QueryCache::invalidate`].
+/// Likewise, reads made by other connections while a write transaction is still open may cache
+/// rows that are stale after it commits, until they expire.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::db::{cache::QueryCache, exec};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// exec::execute(&mut conn, "CREATE TABLE t (a INTEGER)", &[]).await.unwrap();
+///
+/// let cache = QueryCache::new(100)
+///     .ttl(Some(Duration::from_secs(60)))
+///     .table_ttl("t", Some(Duration::from_secs(5)));
+///
+/// assert!(cache.fetch_all(&mut conn, "SELECT a FROM t", &[]).await.unwrap().is_empty());
+/// assert_eq!(cache.len(), 1);
+///
+/// cache.execute(&mut conn, "INSERT INTO t VALUES (1)", &[]).await.unwrap();
+/// assert!(cache.is_empty());
+/// assert_eq!(cache.fetch_all(&mut conn, "SELECT a FROM t", &[]).await.unwrap().len(), 1);
+/// # });
+/// ```
+pub struct QueryCache {
+    cache: LruCache<(QueryKey, Fetch), Entry>,
+    ttl: Option<Duration>,
+    table_ttls: HashMap<String, Option<Duration>>,
+    excluded: HashSet<String>,
+    /// Bumped on every invalidation, so that reads racing with a write don't cache stale rows.
+    generation: AtomicU64,
+}
+
+impl fmt::Debug for QueryCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryCache")
+            .field("capacity", &self.cache.get_capacity())
+            .field("len", &self.cache.len())
+            .field("ttl", &self.ttl)
+            .field("table_ttls", &self.table_ttls)
+            .field("excluded", &self.excluded)
+            .finish()
+    }
+}
+
+impl QueryCache {
+    /// Creates a new [`QueryCache`] that holds at most `capacity` results, which never expire by default.
+    pub fn new(capacity: usize) -> QueryCache {
+        QueryCache {
+            cache: LruCache::new(capacity),
+            ttl: None,
+            table_ttls: HashMap::new(),
+            excluded: HashSet::new(),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the default TTL of results. [`None`] means results never expire.
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the TTL of results that reference `tbl`, overriding the default TTL.
+    ///
+    /// A result referencing several tables expires after the shortest of their TTLs.
+    pub fn table_ttl(mut self, tbl: &str, ttl: Option<Duration>) -> Self {
+        self.table_ttls.insert(tbl.to_lowercase(), ttl);
+        self
+    }
+
+    /// Disables caching of results that reference `tbl`. Writes to it still invalidate other results.
+    pub fn exclude(mut self, tbl: &str) -> Self {
+        self.excluded.insert(tbl.to_lowercase());
+        self
+    }
+
+    /// Gets the default TTL.
+    pub fn get_ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Gets the TTL of results that reference `tbl`.
+    pub fn get_table_ttl(&self, tbl: &str) -> Option<Duration> {
+        self.table_ttls
+            .get(&tbl.to_lowercase())
+            .copied()
+            .unwrap_or(self.ttl)
+    }
+
+    /// Returns the number of cached results, including expired ones that haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns `true` if no result is cached.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Removes the cached results that reference `tbl`.
+    pub fn invalidate(&self, tbl: &str) {
+        self.invalidate_all(&[tbl.to_lowercase()]);
+    }
+
+    /// Removes all cached results.
+    pub fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.cache.clear();
+    }
+
+    /// Invalidates the tables written by a statement.
+    fn invalidate_written(&self, access: Access, tables: &[String]) {
+        match access {
+            Access::Write => self.invalidate_all(tables),
+            Access::WriteAll => self.clear(),
+            Access::Read | Access::None => {}
+        }
+    }
+
+    fn invalidate_all(&self, tables: &[String]) {
+        if tables.is_empty() {
+            return;
+        }
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.cache
+            .retain(|_, e| !e.tables.iter().any(|t| tables.contains(t)));
+    }
+
+    /// Returns the TTL of a result referencing `tables`, where [`None`] means it never expires.
+    fn ttl_of(&self, tables: &[String]) -> Option<Duration> {
+        if tables.is_empty() {
+            return self.ttl;
+        }
+        tables
+            .iter()
+            .map(|t| self.table_ttls.get(t).copied().unwrap_or(self.ttl))
+            .min_by_key(|ttl| ttl.unwrap_or(Duration::MAX))
+            .flatten()
+    }
+
+    /// Executes a statement, see [`exec::execute`], and invalidates the tables it references if it's a write.
+    pub async fn execute<'e, E>(
+        &self,
+        executor: E,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<u64, sqlx::Error>
+    where
+        E: Executor<'e, Database = Any>,
+    {
+        let (access, tables) = analyze(stmt);
+        let res = exec::execute(executor, stmt, args).await;
+        self.invalidate_written(access, &tables);
+        res
+    }
+
+    /// Executes a statement and returns all rows, see [`exec::fetch_all`],
+    /// serving them from the cache if it's a read.
+    ///
+    /// # Returns
+    ///
+    /// * The rows, shared with the cache.
+    pub async fn fetch_all<'e, E>(
+        &self,
+        executor: E,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<Arc<Vec<AnyRow>>, sqlx::Error>
+    where
+        E: Executor<'e, Database = Any>,
+    {
+        self.fetch(Fetch::All, stmt, args, async || {
+            exec::fetch_all(executor, stmt, args).await
+        })
+        .await
+    }
+
+    /// Executes a statement and returns at most one row, see [`exec::fetch_optional`],
+    /// serving it from the cache if it's a read.
+    pub async fn fetch_optional<'e, E>(
+        &self,
+        executor: E,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<Option<AnyRow>, sqlx::Error>
+    where
+        E: Executor<'e, Database = Any>,
+    {
+        let rows = self
+            .fetch(Fetch::Optional, stmt, args, async || {
+                Ok(exec::fetch_optional(executor, stmt, args)
+                    .await?
+                    .into_iter()
+                    .collect())
+            })
+            .await?;
+        Ok(rows.first().cloned())
+    }
+
+    async fn fetch<F>(
+        &self,
+        fetch: Fetch,
+        stmt: &str,
+        args: &[Value],
+        f: F,
+    ) -> Result<Arc<Vec<AnyRow>>, sqlx::Error>
+    where
+        F: AsyncFnOnce() -> Result<Vec<AnyRow>, sqlx::Error>,
+    {
+        let (access, tables) = analyze(stmt);
+        if access != Access::Read {
+            let res = f().await;
+            self.invalidate_written(access, &tables);
+            return res.map(Arc::new);
+        }
+        if tables.is_empty() || tables.iter().any(|t| self.excluded.contains(t)) {
+            return f().await.map(Arc::new);
+        }
+        let key = (QueryKey::new(stmt, args), fetch);
+        if let Some(entry) = self.cache.get(&key) {
+            return Ok(entry.rows);
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let rows = Arc::new(f().await?);
+        // A write finished while reading, so the rows may be stale.
+        if self.generation.load(Ordering::SeqCst) == generation {
+            let ttl = self.ttl_of(&tables);
+            let entry = Entry {
+                rows: rows.clone(),
+                tables: tables.into(),
+            };
+            self.cache.insert_with_ttl(key, entry, ttl);
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use sqlx::{AnyConnection, Connection, Row};
+
+    use crate::db::{Value, exec};
+
+    use super::{Access, QueryCache, analyze};
+
+    #[test]
+    fn test_analyze() {
+        struct TC {
+            stmt: &'static str,
+            access: Access,
+            tables: Vec<&'static str>,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "SELECT 1",
+                access: Access::Read,
+                tables: vec![],
+            },
+            TC {
+                stmt: "SELECT * FROM users u JOIN app.\"Orders\" o ON u.id = o.user_id WHERE u.name = 'from x'",
+                access: Access::Read,
+                tables: vec!["orders", "users"],
+            },
+            TC {
+                stmt: "select * from a, b AS y, `c` z, (SELECT 1 FROM d) e -- FROM f",
+                access: Access::Read,
+                tables: vec!["a", "b", "c", "d"],
+            },
+            TC {
+                stmt: "WITH x AS (SELECT * FROM t) SELECT * FROM x ORDER BY a, b LIMIT 1, 2",
+                access: Access::Read,
+                tables: vec!["t", "x"],
+            },
+            TC {
+                stmt: "SELECT * FROM t WHERE id = ? FOR UPDATE",
+                access: Access::Write,
+                tables: vec!["t"],
+            },
+            TC {
+                stmt: "INSERT INTO t (a, b) SELECT a, b FROM s",
+                access: Access::Write,
+                tables: vec!["s", "t"],
+            },
+            TC {
+                stmt: "UPDATE ONLY t SET a = 1, b = 2 WHERE id = 3",
+                access: Access::Write,
+                tables: vec!["t"],
+            },
+            TC {
+                stmt: "DELETE FROM t USING s WHERE t.id = s.id",
+                access: Access::Write,
+                tables: vec!["s", "t"],
+            },
+            TC {
+                stmt: "DROP TABLE IF EXISTS [t]",
+                access: Access::Write,
+                tables: vec!["t"],
+            },
+            TC {
+                stmt: "BEGIN",
+                access: Access::None,
+                tables: vec![],
+            },
+            TC {
+                stmt: "TRUNCATE t",
+                access: Access::Write,
+                tables: vec!["t"],
+            },
+            TC {
+                stmt: "TRUNCATE TABLE app.t, s",
+                access: Access::Write,
+                tables: vec!["s", "t"],
+            },
+            TC {
+                // The extra name only causes extra invalidations.
+                stmt: "COPY t (a, b) FROM STDIN",
+                access: Access::Write,
+                tables: vec!["stdin", "t"],
+            },
+            TC {
+                stmt: "CALL refresh_totals()",
+                access: Access::WriteAll,
+                tables: vec![],
+            },
+            TC {
+                stmt: "VACUUM",
+                access: Access::WriteAll,
+                tables: vec![],
+            },
+        ];
+
+        for tc in test_cases {
+            let (access, tables) = analyze(tc.stmt);
+            assert_eq!(access, tc.access, "{}", tc.stmt);
+            assert_eq!(tables, tc.tables, "{}", tc.stmt);
+        }
+    }
+
+    #[test]
+    fn test_ttl() {
+        let cache = QueryCache::new(10)
+            .ttl(Some(Duration::from_secs(60)))
+            .table_ttl("A", Some(Duration::from_secs(5)))
+            .table_ttl("b", None);
+        assert_eq!(cache.get_ttl(), Some(Duration::from_secs(60)));
+        assert_eq!(cache.get_table_ttl("a"), Some(Duration::from_secs(5)));
+        assert_eq!(cache.get_table_ttl("c"), Some(Duration::from_secs(60)));
+
+        let tables = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(cache.ttl_of(&[]), Some(Duration::from_secs(60)));
+        assert_eq!(
+            cache.ttl_of(&tables(&["a", "c"])),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            cache.ttl_of(&tables(&["b", "c"])),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(cache.ttl_of(&tables(&["b"])), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        for stmt in [
+            "CREATE TABLE a (id INTEGER)",
+            "CREATE TABLE b (id INTEGER)",
+            "INSERT INTO a VALUES (1)",
+            "INSERT INTO b VALUES (1)",
+        ] {
+            exec::execute(&mut conn, stmt, &[]).await.unwrap();
+        }
+        let cache = QueryCache::new(10).exclude("B");
+
+        let rows = cache
+            .fetch_all(&mut conn, "SELECT id FROM a", &[])
+            .await
+            .unwrap();
+        let cached = cache
+            .fetch_all(&mut conn, "SELECT  id FROM a", &[])
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&rows, &cached));
+        let row = cache
+            .fetch_optional(
+                &mut conn,
+                "SELECT id FROM a WHERE id = ?",
+                &[Value::from(1)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(row.unwrap().get::<i64, _>(0), 1);
+        cache
+            .fetch_all(&mut conn, "SELECT id FROM b", &[])
+            .await
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        // Reads without tables may depend on the time or have side effects
+        let rows = cache
+            .fetch_all(&mut conn, "SELECT random()", &[])
+            .await
+            .unwrap();
+        let uncached = cache
+            .fetch_all(&mut conn, "SELECT random()", &[])
+            .await
+            .unwrap();
+        assert!(!Arc::ptr_eq(&rows, &uncached));
+        assert_eq!(cache.len(), 2);
+
+        // Writes to other tables keep the results
+        cache
+            .execute(&mut conn, "INSERT INTO b VALUES (2)", &[])
+            .await
+            .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        // Writes returning rows invalidate as well
+        let rows = cache
+            .fetch_all(&mut conn, "INSERT INTO a VALUES (2) RETURNING id", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert!(cache.is_empty());
+        let rows = cache
+            .fetch_all(&mut conn, "SELECT id FROM a", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+
+        cache.invalidate("A");
+        assert!(cache.is_empty());
+        cache
+            .fetch_all(&mut conn, "SELECT id FROM a", &[])
+            .await
+            .unwrap();
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
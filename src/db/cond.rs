@@ -0,0 +1,672 @@
+use crate::db::{AsKV, KV, PLACEHOLDER, Value};
+
+/// Comparison operator used in [`Cond::Cmp`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    NotLike,
+}
+
+impl CmpOp {
+    /// Gets the SQL representation of the operator.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => "=",
+            CmpOp::Ne => "<>",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+            CmpOp::Like => "LIKE",
+            CmpOp::NotLike => "NOT LIKE",
+        }
+    }
+}
+
+/// A condition tree that can be used in [`StmtBuilder`](crate::db::StmtBuilder).
+///
+/// Like [`KV`], column names and values are used as is,
+/// so values should be SQL fragments, for example placeholders or literals.
+/// [`PLACEHOLDER`](crate::db::PLACEHOLDER)s in values are converted for PostgreSQL,
+/// while placeholders in [`Cond::Raw`] are not.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Cond {
+    /// `col op val`
+    Cmp { col: String, op: CmpOp, val: String },
+    /// `col op val` ignoring case.
+    ///
+    /// It's written as `col COLLATE utf8mb4_general_ci op val` on MySQL, MariaDB and TiDB,
+    /// whose columns must use the `utf8mb4` character set,
+    /// as `col ILIKE val` for [`CmpOp::Like`] on PostgreSQL, CockroachDB and DuckDB,
+    /// and as `LOWER(col) op LOWER(val)` otherwise, which can use expression indexes on `LOWER(col)` only.
+    /// SQLite lowers ASCII letters only.
+    CmpCi { col: String, op: CmpOp, val: String },
+    /// `col IN (vals)`, or `col NOT IN (vals)` if `negated` is `true`.
+    In {
+        col: String,
+        vals: Vec<String>,
+        negated: bool,
+    },
+    /// `(cols) IN (rows)`, or `(cols) NOT IN (rows)` if `negated` is `true`, where each row has a value per column.
+    ///
+    /// A single row is written as `(cols) = (row)`. Databases without [`Feature::RowValues`](crate::db::Feature::RowValues)
+    /// get the equivalent `AND` and `OR` chains instead.
+    Tuple {
+        cols: Vec<String>,
+        rows: Vec<Vec<String>>,
+        negated: bool,
+    },
+    /// `col IS NULL`, or `col IS NOT NULL` if `negated` is `true`.
+    IsNull { col: String, negated: bool },
+    /// All the conditions are met. An empty list is always true.
+    And(Vec<Cond>),
+    /// Any of the conditions is met. An empty list is always false.
+    Or(Vec<Cond>),
+    /// The condition is not met.
+    Not(Box<Cond>),
+    /// A raw SQL fragment.
+    Raw(String),
+}
+
+impl Cond {
+    fn cmp(col: &str, op: CmpOp, val: &str) -> Cond {
+        Cond::Cmp {
+            col: col.to_string(),
+            op,
+            val: val.to_string(),
+        }
+    }
+
+    /// Creates `col = val`.
+    pub fn eq(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Eq, val)
+    }
+
+    /// Creates `col <> val`.
+    pub fn ne(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Ne, val)
+    }
+
+    /// Creates `col < val`.
+    pub fn lt(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Lt, val)
+    }
+
+    /// Creates `col <= val`.
+    pub fn le(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Le, val)
+    }
+
+    /// Creates `col > val`.
+    pub fn gt(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Gt, val)
+    }
+
+    /// Creates `col >= val`.
+    pub fn ge(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Ge, val)
+    }
+
+    /// Creates `col LIKE val`.
+    pub fn like(col: &str, val: &str) -> Cond {
+        Cond::cmp(col, CmpOp::Like, val)
+    }
+
+    fn cmp_ci(col: &str, op: CmpOp, val: &str) -> Cond {
+        Cond::CmpCi {
+            col: col.to_string(),
+            op,
+            val: val.to_string(),
+        }
+    }
+
+    /// Creates `col = val` ignoring case, for example to look up users by email, see [`Cond::CmpCi`].
+    pub fn eq_ci(col: &str, val: &str) -> Cond {
+        Cond::cmp_ci(col, CmpOp::Eq, val)
+    }
+
+    /// Creates `col <> val` ignoring case, see [`Cond::CmpCi`].
+    pub fn ne_ci(col: &str, val: &str) -> Cond {
+        Cond::cmp_ci(col, CmpOp::Ne, val)
+    }
+
+    /// Creates `col LIKE val` ignoring case, see [`Cond::CmpCi`].
+    pub fn like_ci(col: &str, val: &str) -> Cond {
+        Cond::cmp_ci(col, CmpOp::Like, val)
+    }
+
+    /// Creates `col IN (vals)`. An empty list is always false.
+    pub fn in_list<S: AsRef<str>>(col: &str, vals: &[S]) -> Cond {
+        Cond::In {
+            col: col.to_string(),
+            vals: vals.iter().map(|v| v.as_ref().to_string()).collect(),
+            negated: false,
+        }
+    }
+
+    /// Creates `col NOT IN (vals)`. An empty list is always true.
+    pub fn not_in_list<S: AsRef<str>>(col: &str, vals: &[S]) -> Cond {
+        Cond::In {
+            col: col.to_string(),
+            vals: vals.iter().map(|v| v.as_ref().to_string()).collect(),
+            negated: true,
+        }
+    }
+
+    fn tuple<S: AsRef<str>, R: AsRef<[S]>>(cols: &[S], rows: &[R], negated: bool) -> Cond {
+        Cond::Tuple {
+            cols: cols.iter().map(|col| col.as_ref().to_string()).collect(),
+            rows: rows
+                .iter()
+                .map(|row| {
+                    row.as_ref()
+                        .iter()
+                        .map(|v| v.as_ref().to_string())
+                        .collect()
+                })
+                .collect(),
+            negated,
+        }
+    }
+
+    /// Creates `(cols) = (vals)`, for example to look up a row by a composite key.
+    pub fn tuple_eq<S: AsRef<str>>(cols: &[S], vals: &[S]) -> Cond {
+        Cond::tuple(cols, &[vals], false)
+    }
+
+    /// Creates `(cols) IN (rows)`. An empty list is always false.
+    pub fn tuple_in<S: AsRef<str>, R: AsRef<[S]>>(cols: &[S], rows: &[R]) -> Cond {
+        Cond::tuple(cols, rows, false)
+    }
+
+    /// Creates `(cols) NOT IN (rows)`. An empty list is always true.
+    pub fn tuple_not_in<S: AsRef<str>, R: AsRef<[S]>>(cols: &[S], rows: &[R]) -> Cond {
+        Cond::tuple(cols, rows, true)
+    }
+
+    /// Creates `col IS NULL`.
+    pub fn is_null(col: &str) -> Cond {
+        Cond::IsNull {
+            col: col.to_string(),
+            negated: false,
+        }
+    }
+
+    /// Creates `col IS NOT NULL`.
+    pub fn is_not_null(col: &str) -> Cond {
+        Cond::IsNull {
+            col: col.to_string(),
+            negated: true,
+        }
+    }
+
+    /// Creates `NOT (cond)`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(cond: Cond) -> Cond {
+        Cond::Not(Box::new(cond))
+    }
+
+    /// Creates a raw SQL fragment.
+    pub fn raw(sql: &str) -> Cond {
+        Cond::Raw(sql.to_string())
+    }
+
+    /// Lets `visitor` visit this condition.
+    pub fn accept<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        visitor.visit(self);
+    }
+
+    /// Lets `visitor` visit the direct children of this condition.
+    pub fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        match self {
+            Cond::And(conds) | Cond::Or(conds) => conds.iter().for_each(|c| visitor.visit(c)),
+            Cond::Not(cond) => visitor.visit(cond),
+            Cond::Cmp { .. }
+            | Cond::CmpCi { .. }
+            | Cond::In { .. }
+            | Cond::Tuple { .. }
+            | Cond::IsNull { .. }
+            | Cond::Raw(_) => {}
+        }
+    }
+
+    /// Expands [`Cond::Tuple`] into the equivalent `AND` and `OR` chains of comparisons,
+    /// like `(a = ? AND b = ?) OR (a = ? AND b = ?)`, keeping the order of the values.
+    /// Other conditions are returned as is.
+    pub fn expand_tuple(&self) -> Cond {
+        let Cond::Tuple {
+            cols,
+            rows,
+            negated,
+        } = self
+        else {
+            return self.clone();
+        };
+        let cond = Cond::Or(
+            rows.iter()
+                .map(|row| {
+                    Cond::And(
+                        cols.iter()
+                            .zip(row)
+                            .map(|(col, val)| Cond::eq(col, val))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        );
+        if *negated { Cond::not(cond) } else { cond }
+    }
+
+    /// Rewrites the condition tree bottom-up via `rewriter`.
+    ///
+    /// Removed conditions are dropped from `AND` and `OR` lists, and `NOT` of a removed condition is removed too.
+    ///
+    /// # Returns
+    ///
+    /// * The rewritten condition, or [`None`] if it's removed.
+    pub fn rewrite<R: Rewriter + ?Sized>(self, rewriter: &mut R) -> Option<Cond> {
+        let cond = match self {
+            Cond::And(conds) => Cond::And(
+                conds
+                    .into_iter()
+                    .filter_map(|c| c.rewrite(rewriter))
+                    .collect(),
+            ),
+            Cond::Or(conds) => Cond::Or(
+                conds
+                    .into_iter()
+                    .filter_map(|c| c.rewrite(rewriter))
+                    .collect(),
+            ),
+            Cond::Not(cond) => Cond::not(cond.rewrite(rewriter)?),
+            cond => cond,
+        };
+        rewriter.rewrite(cond)
+    }
+
+    /// Counts the [`PLACEHOLDER`](crate::db::PLACEHOLDER)s in the condition tree, excluding [`Cond::Raw`] fragments.
+    pub fn count_placeholders(&self) -> usize {
+        struct Counter(usize);
+
+        impl Visitor for Counter {
+            fn visit(&mut self, cond: &Cond) {
+                match cond {
+                    Cond::Cmp { val, .. } | Cond::CmpCi { val, .. } => {
+                        self.0 += usize::from(val == PLACEHOLDER)
+                    }
+                    Cond::In { vals, .. } => {
+                        self.0 += vals.iter().filter(|v| *v == PLACEHOLDER).count()
+                    }
+                    Cond::Tuple { rows, .. } => {
+                        self.0 += rows.iter().flatten().filter(|v| *v == PLACEHOLDER).count()
+                    }
+                    cond => cond.walk(self),
+                }
+            }
+        }
+
+        let mut counter = Counter(0);
+        self.accept(&mut counter);
+        counter.0
+    }
+}
+
+/// Visits condition trees, for example to collect the referenced columns.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, PLACEHOLDER, Visitor};
+///
+/// struct Cols(Vec<String>);
+///
+/// impl Visitor for Cols {
+///     fn visit(&mut self, cond: &Cond) {
+///         match cond {
+///             Cond::Cmp { col, .. } | Cond::In { col, .. } | Cond::IsNull { col, .. } => {
+///                 self.0.push(col.clone())
+///             }
+///             cond => cond.walk(self),
+///         }
+///     }
+/// }
+///
+/// let cond = Cond::Or(vec![Cond::eq("a", PLACEHOLDER), Cond::not(Cond::is_null("b"))]);
+/// let mut cols = Cols(Vec::new());
+/// cond.accept(&mut cols);
+///
+/// assert_eq!(cols.0, vec!["a", "b"]);
+/// ```
+pub trait Visitor {
+    /// Visits a condition. By default, it visits the children via [`Cond::walk`].
+    fn visit(&mut self, cond: &Cond) {
+        cond.walk(self);
+    }
+}
+
+/// Rewrites condition trees before they are built into statements,
+/// for example to add tenant conditions, strip disallowed columns or rewrite soft-delete filters.
+///
+/// Closures taking and returning conditions are rewriters too.
+///
+/// Values are bound to placeholders in order, so when a condition with placeholders is removed or reordered,
+/// its values should be removed or reordered as well, see [`Cond::count_placeholders`].
+/// Conditions added by rewriters should use literals rather than placeholders.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+///
+/// let cond = Cond::And(vec![
+///     Cond::eq("name", PLACEHOLDER),
+///     Cond::is_null("deleted_at"),
+///     Cond::eq("password", "'x'"),
+/// ]);
+///
+/// let cond = cond
+///     .rewrite(&mut |cond: Cond| match &cond {
+///         // Strip disallowed columns.
+///         Cond::Cmp { col, .. } if col == "password" => None,
+///         // Rewrite soft-delete filters.
+///         Cond::IsNull { col, negated: false } if col == "deleted_at" => {
+///             Some(Cond::ne("status", "'deleted'"))
+///         }
+///         _ => Some(cond),
+///     })
+///     .unwrap();
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// assert_eq!(
+///     sb.build_count_stmt(&[cond]),
+///     "SELECT COUNT(*) FROM users WHERE name = $1 AND status <> 'deleted'"
+/// );
+/// ```
+pub trait Rewriter {
+    /// Rewrites a condition whose children have been rewritten, returning [`None`] to remove it.
+    fn rewrite(&mut self, cond: Cond) -> Option<Cond>;
+}
+
+impl<F: FnMut(Cond) -> Option<Cond>> Rewriter for F {
+    fn rewrite(&mut self, cond: Cond) -> Option<Cond> {
+        self(cond)
+    }
+}
+
+impl From<KV<'_>> for Cond {
+    fn from(kv: KV<'_>) -> Self {
+        Cond::eq(kv.key, kv.val)
+    }
+}
+
+/// A borrowed view of a condition, see [`AsCond`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum CondRef<'a> {
+    /// `key = val`
+    Eq(KV<'a>),
+    /// A condition tree.
+    Tree(&'a Cond),
+}
+
+impl CondRef<'_> {
+    /// Converts to an owned condition tree.
+    pub fn to_cond(&self) -> Cond {
+        match self {
+            CondRef::Eq(kv) => Cond::from(*kv),
+            CondRef::Tree(cond) => (*cond).clone(),
+        }
+    }
+}
+
+/// Types that can be used as conditions in [`StmtBuilder`](crate::db::StmtBuilder).
+///
+/// Key-value pairs are treated as equal conditions.
+pub trait AsCond {
+    /// Views `self` as a condition.
+    fn as_cond(&self) -> CondRef<'_>;
+}
+
+impl<T: AsKV> AsCond for T {
+    fn as_cond(&self) -> CondRef<'_> {
+        CondRef::Eq(self.as_kv())
+    }
+}
+
+impl AsCond for Cond {
+    fn as_cond(&self) -> CondRef<'_> {
+        CondRef::Tree(self)
+    }
+}
+
+/// Collects conditions and their bound values, for example optional filters from HTTP query parameters.
+///
+/// All the conditions are combined with `AND`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, Filter, PLACEHOLDER, StmtBuilder, Type, Value};
+///
+/// let status: Option<&str> = Some("active");
+/// let min_age: Option<i64> = None;
+/// let only_verified = true;
+///
+/// let filter = Filter::new()
+///     .filter_some(status, || Cond::eq("status", PLACEHOLDER))
+///     .filter_some(min_age, || Cond::ge("age", PLACEHOLDER))
+///     .filter_if(only_verified, || Cond::is_not_null("verified_at"));
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// let stmt = sb.build_query_stmt(&["id"], filter.get_conds());
+///
+/// assert_eq!(
+///     stmt,
+///     "SELECT \"id\" FROM users WHERE status = $1 AND verified_at IS NOT NULL"
+/// );
+/// assert_eq!(filter.get_args(), &[Value::from("active")]);
+/// ```
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Filter {
+    conds: Vec<Cond>,
+    args: Vec<Value>,
+}
+
+impl Filter {
+    /// Creates an empty [`Filter`].
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    /// Gets the conditions.
+    pub fn get_conds(&self) -> &[Cond] {
+        &self.conds
+    }
+
+    /// Gets the values bound to the placeholders in the conditions, in order.
+    pub fn get_args(&self) -> &[Value] {
+        &self.args
+    }
+
+    /// Returns `true` if there are no conditions.
+    pub fn is_empty(&self) -> bool {
+        self.conds.is_empty()
+    }
+
+    /// Consumes the filter and returns the conditions and the bound values.
+    pub fn into_parts(self) -> (Vec<Cond>, Vec<Value>) {
+        (self.conds, self.args)
+    }
+
+    /// Adds a condition.
+    pub fn filter(mut self, cond: Cond) -> Filter {
+        self.conds.push(cond);
+        self
+    }
+
+    /// Adds a condition and the values bound to its placeholders.
+    pub fn filter_with<I>(mut self, cond: Cond, args: I) -> Filter
+    where
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        self.conds.push(cond);
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Adds the condition created by `f` if `pred` is `true`.
+    pub fn filter_if<F: FnOnce() -> Cond>(self, pred: bool, f: F) -> Filter {
+        if pred { self.filter(f()) } else { self }
+    }
+
+    /// Adds the condition created by `f` and binds the value in `opt` if `opt` is [`Some`].
+    ///
+    /// The condition should contain exactly one placeholder.
+    pub fn filter_some<T, F>(self, opt: Option<T>, f: F) -> Filter
+    where
+        T: Into<Value>,
+        F: FnOnce() -> Cond,
+    {
+        match opt {
+            Some(v) => self.filter_with(f(), [v]),
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{AsCond, KV, KVBuf, PLACEHOLDER, Value};
+
+    use super::{CmpOp, Cond, CondRef, Filter};
+
+    #[test]
+    fn test_cmp_op() {
+        let ops = [
+            (CmpOp::Eq, "="),
+            (CmpOp::Ne, "<>"),
+            (CmpOp::Lt, "<"),
+            (CmpOp::Le, "<="),
+            (CmpOp::Gt, ">"),
+            (CmpOp::Ge, ">="),
+            (CmpOp::Like, "LIKE"),
+            (CmpOp::NotLike, "NOT LIKE"),
+        ];
+        for (op, want) in ops {
+            assert_eq!(op.as_str(), want);
+        }
+    }
+
+    #[test]
+    fn test_as_cond() {
+        let kv = KV {
+            key: "id",
+            val: PLACEHOLDER,
+        };
+        assert_eq!(kv.as_cond(), CondRef::Eq(kv));
+        assert_eq!(KVBuf::from(kv).as_cond(), CondRef::Eq(kv));
+        assert_eq!(kv.as_cond().to_cond(), Cond::eq("id", PLACEHOLDER));
+
+        let cond = Cond::is_null("id");
+        assert_eq!(cond.as_cond(), CondRef::Tree(&cond));
+        assert_eq!(cond.as_cond().to_cond(), cond);
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::new();
+        assert!(filter.is_empty());
+
+        let filter = filter
+            .filter(Cond::eq("a", "1"))
+            .filter_if(false, || Cond::eq("b", "2"))
+            .filter_if(true, || Cond::eq("c", "3"))
+            .filter_some(None::<i64>, || Cond::eq("d", PLACEHOLDER))
+            .filter_some(Some("foo"), || Cond::eq("e", PLACEHOLDER))
+            .filter_with(Cond::in_list("f", &[PLACEHOLDER, PLACEHOLDER]), [1, 2]);
+        assert!(!filter.is_empty());
+        assert_eq!(
+            filter.get_conds(),
+            &[
+                Cond::eq("a", "1"),
+                Cond::eq("c", "3"),
+                Cond::eq("e", PLACEHOLDER),
+                Cond::in_list("f", &[PLACEHOLDER, PLACEHOLDER]),
+            ]
+        );
+        assert_eq!(
+            filter.get_args(),
+            &[Value::from("foo"), Value::from(1), Value::from(2)]
+        );
+
+        let (conds, args) = filter.into_parts();
+        assert_eq!(conds.len(), 4);
+        assert_eq!(args.len(), 3);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        struct TC {
+            cond: Cond,
+            want: Option<Cond>,
+        }
+
+        // Removes conditions on `secret`, and adds the tenant condition to every `AND`.
+        let mut rewriter = |cond: Cond| match cond {
+            Cond::Cmp { ref col, .. } if col == "secret" => None,
+            Cond::And(mut conds) => {
+                conds.push(Cond::eq("tenant_id", "1"));
+                Some(Cond::And(conds))
+            }
+            cond => Some(cond),
+        };
+        let test_cases = vec![
+            TC {
+                cond: Cond::eq("secret", PLACEHOLDER),
+                want: None,
+            },
+            TC {
+                cond: Cond::not(Cond::eq("secret", PLACEHOLDER)),
+                want: None,
+            },
+            TC {
+                cond: Cond::Or(vec![
+                    Cond::eq("secret", PLACEHOLDER),
+                    Cond::And(vec![Cond::eq("a", PLACEHOLDER)]),
+                ]),
+                want: Some(Cond::Or(vec![Cond::And(vec![
+                    Cond::eq("a", PLACEHOLDER),
+                    Cond::eq("tenant_id", "1"),
+                ])])),
+            },
+            TC {
+                cond: Cond::raw("1 = 1"),
+                want: Some(Cond::raw("1 = 1")),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(tc.cond.rewrite(&mut rewriter), tc.want);
+        }
+    }
+
+    #[test]
+    fn test_count_placeholders() {
+        let cond = Cond::And(vec![
+            Cond::eq("a", PLACEHOLDER),
+            Cond::eq("b", "1"),
+            Cond::not(Cond::in_list("c", &[PLACEHOLDER, "2", PLACEHOLDER])),
+            Cond::raw("d = ?"),
+            Cond::is_null("e"),
+        ]);
+        assert_eq!(cond.count_placeholders(), 3);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use crate::db::KVBuf;
+#[cfg(feature = "serde")]
+use crate::db::{PLACEHOLDER, Type, Value};
+
+/// How values are rendered into key-value pairs.
+#[cfg(feature = "serde")]
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Render {
+    /// Render values as SQL literals of the given database type, see [`Value::to_literal`].
+    Literal(Type),
+    /// Render values as [`PLACEHOLDER`]s, and return the values for binding.
+    Placeholder,
+}
+
+/// Converts a map to key-value pairs that can be used in [`StmtBuilder`](crate::db::StmtBuilder).
+///
+/// Like [`KV`](crate::db::KV), the values are used as is, so they should be SQL fragments,
+/// for example placeholders or literals.
+/// The pairs are sorted by key, so the same map always produces the same statement.
+///
+/// # Arguments
+///
+/// * `map` - The map from column names to values.
+///
+/// # Returns
+///
+/// * The key-value pairs.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use sainnhe_common::db::{PLACEHOLDER, StmtBuilder, Type, kv_from_map};
+///
+/// let sb = StmtBuilder::new(String::from("my_tbl"), Type::MySQL);
+/// let map = HashMap::from([
+///     (String::from("username"), String::from(PLACEHOLDER)),
+///     (String::from("age"), String::from("20")),
+/// ]);
+///
+/// let stmt = sb.build_insert_stmt(&kv_from_map(&map));
+///
+/// assert_eq!(stmt, "INSERT INTO my_tbl (`age`, `username`) VALUES (20, ?)");
+/// ```
+pub fn kv_from_map(map: &HashMap<String, String>) -> Vec<KVBuf> {
+    let mut kvs: Vec<KVBuf> = map
+        .iter()
+        .map(|(key, val)| KVBuf {
+            key: key.clone(),
+            val: val.clone(),
+        })
+        .collect();
+    kvs.sort_by(|a, b| a.key.cmp(&b.key));
+    kvs
+}
+
+/// Converts a JSON object to key-value pairs that can be used in [`StmtBuilder`](crate::db::StmtBuilder).
+///
+/// The values are rendered as SQL literals of the given database type, see [`Value::to_literal`].
+/// Arrays and nested objects are rendered as their JSON text.
+/// The pairs are sorted by key, so the same object always produces the same statement.
+///
+/// # Arguments
+///
+/// * `json` - The JSON object, for example the body of a PATCH request.
+/// * `typ` - The database type.
+/// * `skip_nulls` - Whether to skip the fields whose values are `null`.
+///
+/// # Returns
+///
+/// * The key-value pairs, or [`None`] if `json` is not an object.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type, kv_from_json};
+/// use serde_json::json;
+///
+/// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+/// let body = json!({
+///     "nickname": "it's me",
+///     "age": 20,
+///     "avatar": null,
+/// });
+///
+/// let cols = kv_from_json(&body, Type::PostgreSQL, true).unwrap();
+/// let conds = vec![KV {
+///     key: "id",
+///     val: PLACEHOLDER,
+/// }];
+/// let stmt = sb.build_update_stmt(&cols, &conds);
+///
+/// assert_eq!(
+///     stmt,
+///     "UPDATE my_tbl SET \"age\" = 20, \"nickname\" = 'it''s me' WHERE id = $1"
+/// );
+/// ```
+#[cfg(feature = "serde")]
+pub fn kv_from_json(json: &serde_json::Value, typ: Type, skip_nulls: bool) -> Option<Vec<KVBuf>> {
+    let obj = json.as_object()?;
+    Some(render_object(obj, Render::Literal(typ), skip_nulls).0)
+}
+
+/// Serializes a struct into key-value pairs that can be used in [`StmtBuilder`](crate::db::StmtBuilder).
+///
+/// The struct is serialized via serde, so attributes like `#[serde(rename = "...")]`
+/// and `#[serde(skip_serializing_if = "...")]` can be used to control the columns.
+/// Nested structs and sequences are rendered as their JSON text.
+/// The pairs are sorted by key.
+///
+/// # Arguments
+///
+/// * `v` - The value to serialize. It must be serialized as a map, for example a struct.
+/// * `render` - How values are rendered.
+///
+/// # Returns
+///
+/// * The key-value pairs, and the values to bind if `render` is [`Render::Placeholder`].
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Render, StmtBuilder, Type, Value, to_kv_pairs};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct User {
+///     name: String,
+///     age: i32,
+/// }
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// let user = User {
+///     name: String::from("foo"),
+///     age: 20,
+/// };
+///
+/// let (cols, args) = to_kv_pairs(&user, Render::Placeholder).unwrap();
+///
+/// assert_eq!(
+///     sb.build_insert_stmt(&cols),
+///     "INSERT INTO users (\"age\", \"name\") VALUES ($1, $2)"
+/// );
+/// assert_eq!(args, vec![Value::from(20), Value::from("foo")]);
+///
+/// let (cols, args) = to_kv_pairs(&user, Render::Literal(Type::PostgreSQL)).unwrap();
+///
+/// assert_eq!(
+///     sb.build_insert_stmt(&cols),
+///     "INSERT INTO users (\"age\", \"name\") VALUES (20, 'foo')"
+/// );
+/// assert!(args.is_empty());
+/// ```
+#[cfg(feature = "serde")]
+pub fn to_kv_pairs<T: serde::Serialize + ?Sized>(
+    v: &T,
+    render: Render,
+) -> Result<(Vec<KVBuf>, Vec<Value>), serde_json::Error> {
+    match serde_json::to_value(v)? {
+        serde_json::Value::Object(obj) => Ok(render_object(&obj, render, false)),
+        _ => Err(serde::ser::Error::custom(
+            "value must be serialized as a map",
+        )),
+    }
+}
+
+#[cfg(feature = "serde")]
+fn render_object(
+    obj: &serde_json::Map<String, serde_json::Value>,
+    render: Render,
+    skip_nulls: bool,
+) -> (Vec<KVBuf>, Vec<Value>) {
+    let mut fields: Vec<(&String, &serde_json::Value)> = obj
+        .iter()
+        .filter(|(_, val)| !(skip_nulls && val.is_null()))
+        .collect();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+    let mut args = Vec::new();
+    let kvs = fields
+        .into_iter()
+        .map(|(key, val)| KVBuf {
+            key: key.clone(),
+            val: match render {
+                Render::Literal(typ) => Value::from(val).to_literal(typ),
+                Render::Placeholder => {
+                    args.push(Value::from(val));
+                    String::from(PLACEHOLDER)
+                }
+            },
+        })
+        .collect();
+    (kvs, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::db::KVBuf;
+
+    use super::kv_from_map;
+
+    fn kv(key: &str, val: &str) -> KVBuf {
+        KVBuf {
+            key: key.to_string(),
+            val: val.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_kv_from_map() {
+        let map = HashMap::from([
+            (String::from("b"), String::from("?")),
+            (String::from("a"), String::from("'foo'")),
+            (String::from("c"), String::from("NOW()")),
+        ]);
+        assert_eq!(
+            kv_from_map(&map),
+            vec![kv("a", "'foo'"), kv("b", "?"), kv("c", "NOW()")]
+        );
+        assert!(kv_from_map(&HashMap::new()).is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_kv_from_json() {
+        use serde_json::json;
+
+        use crate::db::Type;
+
+        use super::kv_from_json;
+
+        let body = json!({
+            "name": "a\\b",
+            "tags": ["x", "y"],
+            "active": true,
+            "deleted_at": null,
+        });
+        assert_eq!(
+            kv_from_json(&body, Type::MySQL, false).unwrap(),
+            vec![
+                kv("active", "TRUE"),
+                kv("deleted_at", "NULL"),
+                kv("name", "'a\\\\b'"),
+                kv("tags", "'[\"x\",\"y\"]'"),
+            ]
+        );
+        assert_eq!(
+            kv_from_json(&body, Type::SQLite, true).unwrap(),
+            vec![
+                kv("active", "TRUE"),
+                kv("name", "'a\\b'"),
+                kv("tags", "'[\"x\",\"y\"]'"),
+            ]
+        );
+        assert!(kv_from_json(&json!([1, 2]), Type::SQLite, true).is_none());
+        assert!(kv_from_json(&json!(null), Type::SQLite, true).is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_kv_pairs() {
+        use std::collections::BTreeMap;
+
+        use serde::Serialize;
+
+        use crate::db::{PLACEHOLDER, Type, Value};
+
+        use super::{Render, to_kv_pairs};
+
+        #[derive(Serialize)]
+        struct Profile {
+            bio: String,
+        }
+
+        #[derive(Serialize)]
+        struct User {
+            #[serde(rename = "user_name")]
+            name: String,
+            nickname: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            email: Option<String>,
+            profile: Profile,
+        }
+
+        let user = User {
+            name: String::from("it's"),
+            nickname: None,
+            email: None,
+            profile: Profile {
+                bio: String::from("hi"),
+            },
+        };
+
+        let (kvs, args) = to_kv_pairs(&user, Render::Literal(Type::SQLite)).unwrap();
+        assert_eq!(
+            kvs,
+            vec![
+                kv("nickname", "NULL"),
+                kv("profile", "'{\"bio\":\"hi\"}'"),
+                kv("user_name", "'it''s'"),
+            ]
+        );
+        assert!(args.is_empty());
+
+        let (kvs, args) = to_kv_pairs(&user, Render::Placeholder).unwrap();
+        assert_eq!(
+            kvs,
+            vec![
+                kv("nickname", PLACEHOLDER),
+                kv("profile", PLACEHOLDER),
+                kv("user_name", PLACEHOLDER),
+            ]
+        );
+        assert_eq!(
+            args,
+            vec![
+                Value::Null,
+                Value::from("{\"bio\":\"hi\"}"),
+                Value::from("it's"),
+            ]
+        );
+
+        // Maps are supported
+        let map = BTreeMap::from([("a", 1)]);
+        let (kvs, args) = to_kv_pairs(&map, Render::Placeholder).unwrap();
+        assert_eq!(kvs, vec![kv("a", PLACEHOLDER)]);
+        assert_eq!(args, vec![Value::from(1)]);
+
+        // Non-map values are rejected
+        assert!(to_kv_pairs(&[1, 2], Render::Placeholder).is_err());
+        assert!(to_kv_pairs("foo", Render::Placeholder).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Recursive common table expressions, for example to walk trees like org charts and category hierarchies.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{Cond, Dialect, StmtBuilder, Type, cte::RecursiveCte};
+//!
+//! // The category 1 and all its descendants.
+//! let tree = RecursiveCte::new(
+//!     "tree",
+//!     "SELECT id, parent_id FROM categories WHERE id = 1",
+//!     "SELECT c.id, c.parent_id FROM categories c JOIN tree t ON c.parent_id = t.id",
+//! )
+//! .cols(&["id", "parent_id"]);
+//! let query = StmtBuilder::new(String::from("tree"), Type::MySQL).build_query_stmt(&["id"], &[] as &[Cond]);
+//!
+//! assert_eq!(
+//!     tree.try_build(Dialect::new(Type::MySQL), &query).unwrap(),
+//!     "WITH RECURSIVE tree (`id`, `parent_id`) AS (\
+//!      SELECT id, parent_id FROM categories WHERE id = 1 \
+//!      UNION ALL \
+//!      SELECT c.id, c.parent_id FROM categories c JOIN tree t ON c.parent_id = t.id\
+//!      ) SELECT `id` FROM tree"
+//! );
+//! ```
+
+use crate::db::{Dialect, Feature, UnsupportedFeature, table::write_quoted};
+
+/// How the rows of the anchor and the recursive queries are combined.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Default)]
+pub enum Union {
+    /// `UNION ALL`, which keeps duplicates and is cheaper.
+    #[default]
+    All,
+    /// `UNION`, which removes duplicates and thus stops at cycles of identical rows.
+    Distinct,
+}
+
+/// The column marking the rows that close a cycle, see [`RecursiveCte::cycle`].
+pub const CYCLE_MARK_COL: &str = "is_cycle";
+
+/// The column holding the visited rows, see [`RecursiveCte::cycle`].
+pub const CYCLE_PATH_COL: &str = "cycle_path";
+
+/// Builder of `WITH RECURSIVE` statements.
+///
+/// The queries are written as is, so write their values as literals on PostgreSQL,
+/// where the placeholders of each query are numbered separately.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct RecursiveCte {
+    name: String,
+    cols: Vec<String>,
+    anchor: String,
+    recursive: String,
+    union: Union,
+    cycle: Vec<String>,
+}
+
+impl RecursiveCte {
+    /// Creates a new [`RecursiveCte`] named `name`,
+    /// where `anchor` selects the first rows and `recursive` selects the next rows by joining `name`.
+    pub fn new(name: &str, anchor: &str, recursive: &str) -> RecursiveCte {
+        RecursiveCte {
+            name: name.to_string(),
+            cols: Vec::new(),
+            anchor: anchor.to_string(),
+            recursive: recursive.to_string(),
+            union: Union::default(),
+            cycle: Vec::new(),
+        }
+    }
+
+    /// Names the columns of the expression. Without names, the columns of the anchor query are used.
+    pub fn cols<S: AsRef<str>>(mut self, cols: &[S]) -> Self {
+        self.cols = cols.iter().map(|col| col.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Sets how the rows are combined.
+    pub fn union(mut self, union: Union) -> Self {
+        self.union = union;
+        self
+    }
+
+    /// Stops the recursion at rows whose columns `cols` have been visited, which requires [`Feature::CteCycle`].
+    ///
+    /// Such rows are still returned, with [`CYCLE_MARK_COL`] set to true,
+    /// so filter them out of the outer query, like `WHERE NOT is_cycle`.
+    /// Where the feature is unsupported, use [`Union::Distinct`] or limit the depth in the recursive query instead.
+    pub fn cycle<S: AsRef<str>>(mut self, cols: &[S]) -> Self {
+        self.cycle = cols.iter().map(|col| col.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Builds the statement that runs `query` on the expression.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement, or an error if the database doesn't support [`Feature::Cte`],
+    ///   or [`Feature::CteCycle`] if cycle detection is enabled.
+    pub fn try_build(&self, dialect: Dialect, query: &str) -> Result<String, UnsupportedFeature> {
+        dialect.check(Feature::Cte)?;
+        if !self.cycle.is_empty() {
+            dialect.check(Feature::CteCycle)?;
+        }
+        let typ = dialect.get_typ();
+        let mut stmt = String::with_capacity(
+            64 + self.name.len() + self.anchor.len() + self.recursive.len() + query.len(),
+        );
+        stmt.push_str("WITH RECURSIVE ");
+        stmt.push_str(&self.name);
+        if !self.cols.is_empty() {
+            stmt.push_str(" (");
+            for (i, col) in self.cols.iter().enumerate() {
+                if i > 0 {
+                    stmt.push_str(", ");
+                }
+                write_quoted(&mut stmt, typ, col);
+            }
+            stmt.push(')');
+        }
+        stmt.push_str(" AS (");
+        stmt.push_str(&self.anchor);
+        stmt.push_str(match self.union {
+            Union::All => " UNION ALL ",
+            Union::Distinct => " UNION ",
+        });
+        stmt.push_str(&self.recursive);
+        stmt.push(')');
+        if !self.cycle.is_empty() {
+            stmt.push_str(" CYCLE ");
+            for (i, col) in self.cycle.iter().enumerate() {
+                if i > 0 {
+                    stmt.push_str(", ");
+                }
+                write_quoted(&mut stmt, typ, col);
+            }
+            stmt.push_str(" SET ");
+            stmt.push_str(CYCLE_MARK_COL);
+            stmt.push_str(" USING ");
+            stmt.push_str(CYCLE_PATH_COL);
+        }
+        stmt.push(' ');
+        stmt.push_str(query);
+        Ok(stmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Dialect, Feature, Type, UnsupportedFeature, Version};
+
+    use super::{RecursiveCte, Union};
+
+    #[test]
+    fn test_try_build() {
+        struct TC {
+            dialect: Dialect,
+            cte: RecursiveCte,
+            want: Result<&'static str, UnsupportedFeature>,
+        }
+
+        let cte = RecursiveCte::new("r", "SELECT 1 AS n", "SELECT n + 1 FROM r WHERE n < 3");
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::SQLite),
+                cte: cte.clone().union(Union::Distinct),
+                want: Ok(
+                    "WITH RECURSIVE r AS (SELECT 1 AS n UNION SELECT n + 1 FROM r WHERE n < 3) SELECT * FROM r",
+                ),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL),
+                cte: cte.clone().cols(&["n"]).cycle(&["n"]),
+                want: Ok(
+                    "WITH RECURSIVE r (\"n\") AS (SELECT 1 AS n UNION ALL SELECT n + 1 FROM r WHERE n < 3) CYCLE \"n\" SET is_cycle USING cycle_path SELECT * FROM r",
+                ),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL).version(Version::new(13, 0, 0)),
+                cte: cte.clone().cycle(&["n"]),
+                want: Err(UnsupportedFeature {
+                    feature: Feature::CteCycle,
+                    typ: Type::PostgreSQL,
+                    required: Some(Version::new(14, 0, 0)),
+                }),
+            },
+            TC {
+                dialect: Dialect::new(Type::MySQL).version(Version::new(5, 7, 44)),
+                cte,
+                want: Err(UnsupportedFeature {
+                    feature: Feature::Cte,
+                    typ: Type::MySQL,
+                    required: Some(Version::new(8, 0, 1)),
+                }),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(
+                tc.cte.try_build(tc.dialect, "SELECT * FROM r"),
+                tc.want.map(str::to_string)
+            );
+        }
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_try_build_sqlite() {
+        use sqlx::{AnyConnection, Connection, Row};
+
+        use crate::db::exec;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        for stmt in [
+            "CREATE TABLE categories (id INTEGER PRIMARY KEY, parent_id INTEGER)",
+            "INSERT INTO categories VALUES (1, NULL), (2, 1), (3, 2), (4, NULL)",
+        ] {
+            exec::execute(&mut conn, stmt, &[]).await.unwrap();
+        }
+        let stmt = RecursiveCte::new(
+            "tree",
+            "SELECT id FROM categories WHERE id = 1",
+            "SELECT c.id FROM categories c JOIN tree t ON c.parent_id = t.id",
+        )
+        .try_build(
+            Dialect::new(Type::SQLite),
+            "SELECT id FROM tree ORDER BY id",
+        )
+        .unwrap();
+        let ids: Vec<i64> = exec::fetch_all(&mut conn, &stmt, &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,848 @@
+//! Builders of data definition statements, for example to create the tables of a service on every supported database.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{Dialect, Type, ddl::{Partitions, TableDef}};
+//!
+//! let events = TableDef::new("events")
+//!     .col("id", "BIGINT NOT NULL")
+//!     .col("created_at", "DATE NOT NULL")
+//!     .primary_key(&["id", "created_at"])
+//!     .partitions(
+//!         Partitions::range(&["created_at"])
+//!             .part("p2024", &["'2025-01-01'"])
+//!             .part("pmax", &["MAXVALUE"]),
+//!     );
+//!
+//! assert_eq!(
+//!     events.try_build(Dialect::new(Type::MySQL)).unwrap(),
+//!     vec![
+//!         "CREATE TABLE events (`id` BIGINT NOT NULL, `created_at` DATE NOT NULL, PRIMARY KEY (`id`, `created_at`)) \
+//!          PARTITION BY RANGE COLUMNS(`created_at`) \
+//!          (PARTITION p2024 VALUES LESS THAN ('2025-01-01'), PARTITION pmax VALUES LESS THAN (MAXVALUE))"
+//!     ]
+//! );
+//! assert_eq!(
+//!     events.try_build(Dialect::new(Type::PostgreSQL)).unwrap(),
+//!     vec![
+//!         "CREATE TABLE events (\"id\" BIGINT NOT NULL, \"created_at\" DATE NOT NULL, PRIMARY KEY (\"id\", \"created_at\")) \
+//!          PARTITION BY RANGE (\"created_at\")",
+//!         "CREATE TABLE p2024 PARTITION OF events FOR VALUES FROM (MINVALUE) TO ('2025-01-01')",
+//!         "CREATE TABLE pmax PARTITION OF events FOR VALUES FROM ('2025-01-01') TO (MAXVALUE)",
+//!     ]
+//! );
+//! ```
+
+use crate::db::{
+    Dialect, Feature, Type, UnsupportedFeature,
+    table::{shorten_ident, write_quoted},
+};
+
+/// How the rows are assigned to partitions.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+enum Method {
+    Range,
+    List,
+    Hash(u32),
+}
+
+/// A named partition and the values that bound it.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+struct Part {
+    name: String,
+    vals: Vec<String>,
+}
+
+/// The partitions of a table, see [`TableDef::partitions`].
+///
+/// The values bounding the partitions are written as is, so they should be literals.
+/// The partitioning columns must be part of every unique key, including the primary key.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Partitions {
+    method: Method,
+    cols: Vec<String>,
+    parts: Vec<Part>,
+}
+
+impl Partitions {
+    /// Partitions the table by ranges of the columns `cols`, see [`Partitions::part`].
+    pub fn range<S: AsRef<str>>(cols: &[S]) -> Partitions {
+        Partitions::new(Method::Range, cols)
+    }
+
+    /// Partitions the table by the values of the column `col`, see [`Partitions::part`].
+    pub fn list(col: &str) -> Partitions {
+        Partitions::new(Method::List, &[col])
+    }
+
+    /// Partitions the table into `count` partitions by the hash of the columns `cols`.
+    ///
+    /// MySQL, MariaDB and TiDB get `KEY` partitioning, which hashes columns of any type, and names the partitions `p0`, `p1` and so on.
+    /// PostgreSQL gets a partition table per partition named after the table, like `events_p0`.
+    pub fn hash<S: AsRef<str>>(cols: &[S], count: u32) -> Partitions {
+        Partitions::new(Method::Hash(count), cols)
+    }
+
+    fn new<S: AsRef<str>>(method: Method, cols: &[S]) -> Partitions {
+        Partitions {
+            method,
+            cols: cols.iter().map(|col| col.as_ref().to_string()).collect(),
+            parts: Vec::new(),
+        }
+    }
+
+    /// Adds the partition `name`.
+    ///
+    /// Range partitions hold the rows below `vals`, a value per column, and not below the previous partition.
+    /// The last partition may be bounded by `MAXVALUE`.
+    /// List partitions hold the rows whose column is one of `vals`.
+    /// Hash partitions are generated, so they ignore this.
+    pub fn part<S: AsRef<str>>(mut self, name: &str, vals: &[S]) -> Self {
+        self.parts.push(Part {
+            name: name.to_string(),
+            vals: vals.iter().map(|v| v.as_ref().to_string()).collect(),
+        });
+        self
+    }
+}
+
+/// How the values of generated columns are kept, see [`TableDef::generated_col`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Storage {
+    /// Computed when rows are written and stored like other columns, see [`Feature::StoredGeneratedColumns`].
+    Stored,
+    /// Computed when rows are read, see [`Feature::VirtualGeneratedColumns`].
+    Virtual,
+}
+
+/// A column of a table.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+struct Col {
+    name: String,
+    def: String,
+    generated: Option<(String, Storage)>,
+}
+
+/// What happens to the referencing rows when the referenced row is deleted or updated, see [`ForeignKey`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Action {
+    /// Fail at the end of the statement, which is the default.
+    NoAction,
+    /// Fail immediately.
+    Restrict,
+    /// Delete or update the referencing rows too.
+    Cascade,
+    /// Set the referencing columns to `NULL`.
+    SetNull,
+    /// Set the referencing columns to their defaults, which MySQL rejects.
+    SetDefault,
+}
+
+impl Action {
+    /// Gets the SQL representation of the action.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::NoAction => "NO ACTION",
+            Action::Restrict => "RESTRICT",
+            Action::Cascade => "CASCADE",
+            Action::SetNull => "SET NULL",
+            Action::SetDefault => "SET DEFAULT",
+        }
+    }
+}
+
+/// A foreign key constraint, see [`TableDef::foreign_key`].
+///
+/// SQLite enforces foreign keys only after `PRAGMA foreign_keys = ON`, which is per connection.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Dialect, Type, ddl::{Action, ForeignKey, TableDef}};
+///
+/// let orders = TableDef::new("orders")
+///     .col("id", "BIGINT NOT NULL")
+///     .col("user_id", "BIGINT NOT NULL")
+///     .primary_key(&["id"])
+///     .foreign_key(ForeignKey::new(&["user_id"], "users", &["id"]).on_delete(Action::Cascade));
+///
+/// assert_eq!(
+///     orders.try_build(Dialect::new(Type::PostgreSQL)).unwrap(),
+///     vec![
+///         "CREATE TABLE orders (\"id\" BIGINT NOT NULL, \"user_id\" BIGINT NOT NULL, PRIMARY KEY (\"id\"), \
+///          CONSTRAINT fk_orders_user_id FOREIGN KEY (\"user_id\") REFERENCES users (\"id\") ON DELETE CASCADE)"
+///     ]
+/// );
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct ForeignKey {
+    name: Option<String>,
+    cols: Vec<String>,
+    ref_tbl: String,
+    ref_cols: Vec<String>,
+    on_delete: Option<Action>,
+    on_update: Option<Action>,
+}
+
+impl ForeignKey {
+    /// Creates a new [`ForeignKey`] where the columns `cols` reference the columns `ref_cols` of the table `ref_tbl`.
+    pub fn new<S: AsRef<str>>(cols: &[S], ref_tbl: &str, ref_cols: &[S]) -> ForeignKey {
+        ForeignKey {
+            name: None,
+            cols: cols.iter().map(|col| col.as_ref().to_string()).collect(),
+            ref_tbl: ref_tbl.to_string(),
+            ref_cols: ref_cols
+                .iter()
+                .map(|col| col.as_ref().to_string())
+                .collect(),
+            on_delete: None,
+            on_update: None,
+        }
+    }
+
+    /// Names the constraint. Without a name, it's named like `fk_orders_user_id`, after the table and the columns.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the action taken when the referenced row is deleted.
+    pub fn on_delete(mut self, action: Action) -> Self {
+        self.on_delete = Some(action);
+        self
+    }
+
+    /// Sets the action taken when the referenced columns are updated.
+    pub fn on_update(mut self, action: Action) -> Self {
+        self.on_update = Some(action);
+        self
+    }
+
+    /// Returns `true` if any action requires [`Feature::ForeignKeyActions`].
+    fn has_actions(&self) -> bool {
+        [self.on_delete, self.on_update]
+            .iter()
+            .flatten()
+            .any(|action| !matches!(action, Action::NoAction | Action::Restrict))
+    }
+}
+
+/// A check constraint, see [`TableDef::check`].
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Check {
+    name: Option<String>,
+    expr: String,
+}
+
+impl Check {
+    /// Creates a new [`Check`] that rejects the rows for which the expression `expr` is false, like `price >= 0`.
+    pub fn new(expr: &str) -> Check {
+        Check {
+            name: None,
+            expr: expr.to_string(),
+        }
+    }
+
+    /// Names the constraint. Without a name, the database names it.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+}
+
+/// Builder of `CREATE TABLE` statements.
+///
+/// The table name and the column definitions are written as is, while the column names are quoted.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct TableDef {
+    name: String,
+    cols: Vec<Col>,
+    primary_key: Vec<String>,
+    foreign_keys: Vec<ForeignKey>,
+    checks: Vec<Check>,
+    partitions: Option<Partitions>,
+    shorten_names: bool,
+}
+
+impl TableDef {
+    /// Creates a new [`TableDef`] of the table `name` without columns.
+    pub fn new(name: &str) -> TableDef {
+        TableDef {
+            name: name.to_string(),
+            cols: Vec::new(),
+            primary_key: Vec::new(),
+            foreign_keys: Vec::new(),
+            checks: Vec::new(),
+            partitions: None,
+            shorten_names: false,
+        }
+    }
+
+    /// Gets the table name.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Adds the column `name` with the definition `def`, like `BIGINT NOT NULL`.
+    pub fn col(mut self, name: &str, def: &str) -> Self {
+        self.cols.push(Col {
+            name: name.to_string(),
+            def: def.to_string(),
+            generated: None,
+        });
+        self
+    }
+
+    /// Adds the column `name` of the type `typ`, whose value is computed from the expression `expr`,
+    /// like `price * quantity`, which references the other columns of the row.
+    ///
+    /// Generated columns can't be written, so leave them out of inserts and updates.
+    /// Their support depends on the storage, see [`Storage`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Dialect, Type, ddl::{Storage, TableDef}};
+    ///
+    /// let items = TableDef::new("items")
+    ///     .col("price", "INTEGER NOT NULL")
+    ///     .col("quantity", "INTEGER NOT NULL")
+    ///     .generated_col("total", "INTEGER", "price * quantity", Storage::Stored);
+    ///
+    /// assert_eq!(
+    ///     items.try_build(Dialect::new(Type::SQLite)).unwrap(),
+    ///     vec![
+    ///         "CREATE TABLE items (\"price\" INTEGER NOT NULL, \"quantity\" INTEGER NOT NULL, \
+    ///          \"total\" INTEGER GENERATED ALWAYS AS (price * quantity) STORED)"
+    ///     ]
+    /// );
+    /// // DuckDB supports virtual generated columns only.
+    /// assert!(items.try_build(Dialect::new(Type::DuckDB)).is_err());
+    /// ```
+    pub fn generated_col(mut self, name: &str, typ: &str, expr: &str, storage: Storage) -> Self {
+        self.cols.push(Col {
+            name: name.to_string(),
+            def: typ.to_string(),
+            generated: Some((expr.to_string(), storage)),
+        });
+        self
+    }
+
+    /// Sets the columns of the primary key.
+    pub fn primary_key<S: AsRef<str>>(mut self, cols: &[S]) -> Self {
+        self.primary_key = cols.iter().map(|col| col.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Adds a foreign key, which requires [`Feature::ForeignKeys`],
+    /// and [`Feature::ForeignKeyActions`] for actions other than [`Action::NoAction`] and [`Action::Restrict`].
+    pub fn foreign_key(mut self, fk: ForeignKey) -> Self {
+        self.foreign_keys.push(fk);
+        self
+    }
+
+    /// Adds a check constraint, which requires [`Feature::CheckConstraints`].
+    pub fn check(mut self, check: Check) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Shortens the generated constraint names that exceed [`TableRef::max_ident_len`](crate::db::TableRef::max_ident_len)
+    /// by truncating them and appending a hash of the whole name, so the names are stable across builds.
+    ///
+    /// Otherwise, MySQL, MariaDB and TiDB reject such names, and PostgreSQL truncates them,
+    /// so the foreign keys of long tables and columns sharing a prefix collide.
+    /// Names given explicitly are written as is.
+    pub fn shorten_names(mut self, shorten: bool) -> Self {
+        self.shorten_names = shorten;
+        self
+    }
+
+    /// Partitions the table, which requires [`Feature::Partitioning`].
+    pub fn partitions(mut self, partitions: Partitions) -> Self {
+        self.partitions = Some(partitions);
+        self
+    }
+
+    /// Builds the statements creating the table, in order.
+    ///
+    /// PostgreSQL creates each partition by a separate statement, while other databases need a single statement.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statements, or an error if the database doesn't support the generated columns,
+    ///   the constraints, or [`Feature::Partitioning`] if the table is partitioned.
+    pub fn try_build(&self, dialect: Dialect) -> Result<Vec<String>, UnsupportedFeature> {
+        for col in &self.cols {
+            match col.generated {
+                Some((_, Storage::Stored)) => dialect.check(Feature::StoredGeneratedColumns)?,
+                Some((_, Storage::Virtual)) => dialect.check(Feature::VirtualGeneratedColumns)?,
+                None => {}
+            }
+        }
+        for fk in &self.foreign_keys {
+            dialect.check(Feature::ForeignKeys)?;
+            if fk.has_actions() {
+                dialect.check(Feature::ForeignKeyActions)?;
+            }
+        }
+        if !self.checks.is_empty() {
+            dialect.check(Feature::CheckConstraints)?;
+        }
+        if self.partitions.is_some() {
+            dialect.check(Feature::Partitioning)?;
+        }
+        let typ = dialect.get_typ();
+        let mut stmt = String::with_capacity(64 + self.name.len() + 32 * self.cols.len());
+        stmt.push_str("CREATE TABLE ");
+        stmt.push_str(&self.name);
+        stmt.push_str(" (");
+        for (i, col) in self.cols.iter().enumerate() {
+            if i > 0 {
+                stmt.push_str(", ");
+            }
+            write_quoted(&mut stmt, typ, &col.name);
+            stmt.push(' ');
+            stmt.push_str(&col.def);
+            if let Some((expr, storage)) = &col.generated {
+                stmt.push_str(" GENERATED ALWAYS AS (");
+                stmt.push_str(expr);
+                stmt.push_str(match storage {
+                    Storage::Stored => ") STORED",
+                    Storage::Virtual => ") VIRTUAL",
+                });
+            }
+        }
+        if !self.primary_key.is_empty() {
+            stmt.push_str(", PRIMARY KEY (");
+            push_quoted(&mut stmt, typ, &self.primary_key);
+            stmt.push(')');
+        }
+        for fk in &self.foreign_keys {
+            self.push_foreign_key(&mut stmt, typ, fk);
+        }
+        for check in &self.checks {
+            stmt.push_str(", ");
+            if let Some(name) = &check.name {
+                stmt.push_str("CONSTRAINT ");
+                stmt.push_str(name);
+                stmt.push(' ');
+            }
+            stmt.push_str("CHECK (");
+            stmt.push_str(&check.expr);
+            stmt.push(')');
+        }
+        stmt.push(')');
+        let mut stmts = vec![stmt];
+        if let Some(partitions) = &self.partitions {
+            if typ.is_mysql_family() {
+                self.push_mysql_partitions(&mut stmts[0], typ, partitions);
+            } else {
+                self.push_postgres_partitions(&mut stmts, typ, partitions);
+            }
+        }
+        Ok(stmts)
+    }
+
+    /// Appends the foreign key to the definitions of the table.
+    fn push_foreign_key(&self, stmt: &mut String, typ: Type, fk: &ForeignKey) {
+        stmt.push_str(", CONSTRAINT ");
+        match &fk.name {
+            Some(name) => stmt.push_str(name),
+            None => {
+                // Constraint names are unique per schema, so the schema is left out.
+                let tbl = self.name.rsplit('.').next().unwrap_or_default();
+                let name = format!("fk_{}_{}", tbl, fk.cols.join("_"));
+                if self.shorten_names {
+                    stmt.push_str(&shorten_ident(typ, &name));
+                } else {
+                    stmt.push_str(&name);
+                }
+            }
+        }
+        stmt.push_str(" FOREIGN KEY (");
+        push_quoted(stmt, typ, &fk.cols);
+        stmt.push_str(") REFERENCES ");
+        stmt.push_str(&fk.ref_tbl);
+        stmt.push_str(" (");
+        push_quoted(stmt, typ, &fk.ref_cols);
+        stmt.push(')');
+        if let Some(action) = fk.on_delete {
+            stmt.push_str(" ON DELETE ");
+            stmt.push_str(action.as_str());
+        }
+        if let Some(action) = fk.on_update {
+            stmt.push_str(" ON UPDATE ");
+            stmt.push_str(action.as_str());
+        }
+    }
+
+    /// Appends the partitions of MySQL, MariaDB and TiDB to the statement creating the table.
+    fn push_mysql_partitions(&self, stmt: &mut String, typ: Type, partitions: &Partitions) {
+        stmt.push_str(match partitions.method {
+            Method::Range => " PARTITION BY RANGE COLUMNS(",
+            Method::List => " PARTITION BY LIST COLUMNS(",
+            Method::Hash(_) => " PARTITION BY KEY(",
+        });
+        push_quoted(stmt, typ, &partitions.cols);
+        stmt.push(')');
+        if let Method::Hash(count) = partitions.method {
+            stmt.push_str(" PARTITIONS ");
+            stmt.push_str(&count.to_string());
+            return;
+        }
+        stmt.push_str(" (");
+        for (i, part) in partitions.parts.iter().enumerate() {
+            if i > 0 {
+                stmt.push_str(", ");
+            }
+            stmt.push_str("PARTITION ");
+            stmt.push_str(&part.name);
+            stmt.push_str(if partitions.method == Method::Range {
+                " VALUES LESS THAN ("
+            } else {
+                " VALUES IN ("
+            });
+            stmt.push_str(&part.vals.join(", "));
+            stmt.push(')');
+        }
+        stmt.push(')');
+    }
+
+    /// Appends the partitioning of PostgreSQL to the statement creating the table,
+    /// followed by the statements creating the partitions.
+    fn push_postgres_partitions(
+        &self,
+        stmts: &mut Vec<String>,
+        typ: Type,
+        partitions: &Partitions,
+    ) {
+        let stmt = &mut stmts[0];
+        stmt.push_str(match partitions.method {
+            Method::Range => " PARTITION BY RANGE (",
+            Method::List => " PARTITION BY LIST (",
+            Method::Hash(_) => " PARTITION BY HASH (",
+        });
+        push_quoted(stmt, typ, &partitions.cols);
+        stmt.push(')');
+        match partitions.method {
+            Method::Range => {
+                let mut from = vec!["MINVALUE"; partitions.cols.len()].join(", ");
+                for part in &partitions.parts {
+                    let to = part.vals.join(", ");
+                    stmts.push(format!(
+                        "CREATE TABLE {} PARTITION OF {} FOR VALUES FROM ({}) TO ({})",
+                        part.name, self.name, from, to
+                    ));
+                    // The next partition starts where this one ends.
+                    from = to;
+                }
+            }
+            Method::List => {
+                for part in &partitions.parts {
+                    stmts.push(format!(
+                        "CREATE TABLE {} PARTITION OF {} FOR VALUES IN ({})",
+                        part.name,
+                        self.name,
+                        part.vals.join(", ")
+                    ));
+                }
+            }
+            Method::Hash(count) => {
+                for i in 0..count {
+                    stmts.push(format!(
+                        "CREATE TABLE {}_p{} PARTITION OF {} FOR VALUES WITH (MODULUS {}, REMAINDER {})",
+                        self.name, i, self.name, count, i
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Pushes the quoted identifiers separated by commas.
+fn push_quoted(s: &mut String, typ: Type, idents: &[String]) {
+    for (i, ident) in idents.iter().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        write_quoted(s, typ, ident);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Dialect, Feature, Type, UnsupportedFeature, Version};
+
+    use super::{Action, Check, ForeignKey, Partitions, Storage, TableDef};
+
+    #[test]
+    fn test_try_build() {
+        struct TC {
+            dialect: Dialect,
+            partitions: Partitions,
+            want: Result<Vec<&'static str>, UnsupportedFeature>,
+        }
+
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::TiDB),
+                partitions: Partitions::list("region").part("p_eu", &["'de'", "'fr'"]),
+                want: Ok(vec![
+                    "CREATE TABLE t (`id` BIGINT, `region` TEXT) PARTITION BY LIST COLUMNS(`region`) (PARTITION p_eu VALUES IN ('de', 'fr'))",
+                ]),
+            },
+            TC {
+                dialect: Dialect::new(Type::MariaDB),
+                partitions: Partitions::hash(&["id"], 2),
+                want: Ok(vec![
+                    "CREATE TABLE t (`id` BIGINT, `region` TEXT) PARTITION BY KEY(`id`) PARTITIONS 2",
+                ]),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL),
+                partitions: Partitions::hash(&["id"], 2),
+                want: Ok(vec![
+                    "CREATE TABLE t (\"id\" BIGINT, \"region\" TEXT) PARTITION BY HASH (\"id\")",
+                    "CREATE TABLE t_p0 PARTITION OF t FOR VALUES WITH (MODULUS 2, REMAINDER 0)",
+                    "CREATE TABLE t_p1 PARTITION OF t FOR VALUES WITH (MODULUS 2, REMAINDER 1)",
+                ]),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL),
+                partitions: Partitions::range(&["id", "region"])
+                    .part("p0", &["10", "'m'"])
+                    .part("p1", &["20", "'m'"]),
+                want: Ok(vec![
+                    "CREATE TABLE t (\"id\" BIGINT, \"region\" TEXT) PARTITION BY RANGE (\"id\", \"region\")",
+                    "CREATE TABLE p0 PARTITION OF t FOR VALUES FROM (MINVALUE, MINVALUE) TO (10, 'm')",
+                    "CREATE TABLE p1 PARTITION OF t FOR VALUES FROM (10, 'm') TO (20, 'm')",
+                ]),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL).version(Version::new(10, 23, 0)),
+                partitions: Partitions::hash(&["id"], 2),
+                want: Err(UnsupportedFeature {
+                    feature: Feature::Partitioning,
+                    typ: Type::PostgreSQL,
+                    required: Some(Version::new(11, 0, 0)),
+                }),
+            },
+        ];
+
+        let tbl = TableDef::new("t").col("id", "BIGINT").col("region", "TEXT");
+        for tc in test_cases {
+            let got = tbl.clone().partitions(tc.partitions).try_build(tc.dialect);
+            let want = tc
+                .want
+                .map(|stmts| stmts.into_iter().map(str::to_string).collect());
+            assert_eq!(got, want);
+        }
+        assert_eq!(
+            tbl.primary_key(&["id"])
+                .try_build(Dialect::new(Type::SQLite))
+                .unwrap(),
+            vec!["CREATE TABLE t (\"id\" BIGINT, \"region\" TEXT, PRIMARY KEY (\"id\"))"]
+        );
+    }
+
+    #[test]
+    fn test_generated_col() {
+        struct TC {
+            dialect: Dialect,
+            storage: Storage,
+            want: Result<&'static str, UnsupportedFeature>,
+        }
+
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::MariaDB),
+                storage: Storage::Virtual,
+                want: Ok(
+                    "CREATE TABLE t (`email` TEXT, `domain` TEXT GENERATED ALWAYS AS (SUBSTR(email, 1)) VIRTUAL)",
+                ),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL).version(Version::new(17, 0, 0)),
+                storage: Storage::Stored,
+                want: Ok(
+                    "CREATE TABLE t (\"email\" TEXT, \"domain\" TEXT GENERATED ALWAYS AS (SUBSTR(email, 1)) STORED)",
+                ),
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL).version(Version::new(17, 0, 0)),
+                storage: Storage::Virtual,
+                want: Err(UnsupportedFeature {
+                    feature: Feature::VirtualGeneratedColumns,
+                    typ: Type::PostgreSQL,
+                    required: Some(Version::new(18, 0, 0)),
+                }),
+            },
+            TC {
+                dialect: Dialect::new(Type::DuckDB),
+                storage: Storage::Stored,
+                want: Err(UnsupportedFeature {
+                    feature: Feature::StoredGeneratedColumns,
+                    typ: Type::DuckDB,
+                    required: None,
+                }),
+            },
+        ];
+
+        for tc in test_cases {
+            let tbl = TableDef::new("t").col("email", "TEXT").generated_col(
+                "domain",
+                "TEXT",
+                "SUBSTR(email, 1)",
+                tc.storage,
+            );
+            assert_eq!(
+                tbl.try_build(tc.dialect),
+                tc.want.map(|stmt| vec![stmt.to_string()])
+            );
+        }
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_generated_col_sqlite() {
+        use sqlx::{AnyConnection, Connection, Row};
+
+        use crate::db::exec;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let stmts = TableDef::new("items")
+            .col("price", "INTEGER")
+            .col("quantity", "INTEGER")
+            .generated_col("total", "INTEGER", "price * quantity", Storage::Virtual)
+            .try_build(Dialect::new(Type::SQLite))
+            .unwrap();
+        for stmt in &stmts {
+            exec::execute(&mut conn, stmt, &[]).await.unwrap();
+        }
+        exec::execute(
+            &mut conn,
+            "INSERT INTO items (price, quantity) VALUES (3, 4)",
+            &[],
+        )
+        .await
+        .unwrap();
+        let rows = exec::fetch_all(&mut conn, "SELECT total FROM items", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get::<i64, _>(0), 12);
+    }
+
+    #[test]
+    fn test_constraints() {
+        struct TC {
+            dialect: Dialect,
+            want: Result<&'static str, UnsupportedFeature>,
+        }
+
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::MySQL),
+                want: Ok(
+                    "CREATE TABLE shop.items (`id` BIGINT, `order_id` BIGINT, `price` INTEGER, \
+                     CONSTRAINT fk_items_order_id FOREIGN KEY (`order_id`) REFERENCES orders (`id`) ON DELETE CASCADE ON UPDATE RESTRICT, \
+                     CONSTRAINT price_positive CHECK (price > 0), CHECK (id > 0))",
+                ),
+            },
+            TC {
+                dialect: Dialect::new(Type::MySQL).version(Version::new(8, 0, 15)),
+                want: Err(UnsupportedFeature {
+                    feature: Feature::CheckConstraints,
+                    typ: Type::MySQL,
+                    required: Some(Version::new(8, 0, 16)),
+                }),
+            },
+            TC {
+                dialect: Dialect::new(Type::DuckDB),
+                want: Err(UnsupportedFeature {
+                    feature: Feature::ForeignKeyActions,
+                    typ: Type::DuckDB,
+                    required: None,
+                }),
+            },
+            TC {
+                dialect: Dialect::new(Type::TiDB).version(Version::new(6, 5, 0)),
+                want: Err(UnsupportedFeature {
+                    feature: Feature::ForeignKeys,
+                    typ: Type::TiDB,
+                    required: Some(Version::new(6, 6, 0)),
+                }),
+            },
+        ];
+
+        let tbl = TableDef::new("shop.items")
+            .col("id", "BIGINT")
+            .col("order_id", "BIGINT")
+            .col("price", "INTEGER")
+            .foreign_key(
+                ForeignKey::new(&["order_id"], "orders", &["id"])
+                    .on_delete(Action::Cascade)
+                    .on_update(Action::Restrict),
+            )
+            .check(Check::new("price > 0").name("price_positive"))
+            .check(Check::new("id > 0"));
+        for tc in test_cases {
+            assert_eq!(
+                tbl.try_build(tc.dialect),
+                tc.want.map(|stmt| vec![stmt.to_string()])
+            );
+        }
+        // Restricting actions are supported everywhere.
+        let tbl = TableDef::new("items")
+            .col("order_id", "BIGINT")
+            .foreign_key(
+                ForeignKey::new(&["order_id"], "orders", &["id"])
+                    .name("fk_order")
+                    .on_delete(Action::Restrict),
+            );
+        assert_eq!(
+            tbl.try_build(Dialect::new(Type::DuckDB)).unwrap(),
+            vec![
+                "CREATE TABLE items (\"order_id\" BIGINT, CONSTRAINT fk_order FOREIGN KEY (\"order_id\") REFERENCES orders (\"id\") ON DELETE RESTRICT)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shorten_names() {
+        let tbl = TableDef::new("customer_subscription_billing_events")
+            .col("subscription_plan_version_id", "BIGINT")
+            .foreign_key(ForeignKey::new(
+                &["subscription_plan_version_id"],
+                "plans",
+                &["id"],
+            ));
+        let name = "fk_customer_subscription_billing_events_subscription_plan_version_id";
+        assert!(tbl.try_build(Dialect::new(Type::PostgreSQL)).unwrap()[0].contains(name));
+
+        let stmt = tbl
+            .clone()
+            .shorten_names(true)
+            .try_build(Dialect::new(Type::PostgreSQL))
+            .unwrap()
+            .remove(0);
+        assert!(stmt.contains(&format!("CONSTRAINT {}_", &name[..54])));
+        assert!(!stmt.contains(name));
+        assert_eq!(
+            stmt,
+            tbl.clone()
+                .shorten_names(true)
+                .try_build(Dialect::new(Type::PostgreSQL))
+                .unwrap()[0]
+        );
+        // Unlimited identifiers are kept.
+        assert!(
+            tbl.shorten_names(true)
+                .try_build(Dialect::new(Type::SQLite))
+                .unwrap()[0]
+                .contains(name)
+        );
+    }
+}
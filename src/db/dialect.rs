@@ -0,0 +1,607 @@
+use std::{fmt, str::FromStr};
+
+use crate::db::{PolicyError, Type};
+
+/// A server version like `8.0.36`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    /// Creates a new [`Version`].
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Error returned when a version can't be parsed.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ParseVersionError(String);
+
+impl fmt::Display for ParseVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid version {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseVersionError {}
+
+impl FromStr for Version {
+    type Err = ParseVersionError;
+
+    /// Parses the first dotted number in `s`, so version strings reported by servers
+    /// like `8.0.36-0ubuntu0.22.04.1` and `PostgreSQL 15.4 on x86_64-pc-linux-gnu` are accepted.
+    /// Missing minor and patch numbers are 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseVersionError(s.to_string());
+        let start = s.find(|c: char| c.is_ascii_digit()).ok_or_else(err)?;
+        let rest = &s[start..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let mut nums = rest[..end]
+            .split('.')
+            .filter(|n| !n.is_empty())
+            .map(|n| n.parse::<u32>());
+        let mut next = || nums.next().transpose().map_err(|_| err());
+        let major = next()?.ok_or_else(err)?;
+        Ok(Version::new(
+            major,
+            next()?.unwrap_or(0),
+            next()?.unwrap_or(0),
+        ))
+    }
+}
+
+/// A feature whose support depends on the database type and version.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Feature {
+    /// `INSERT ... ON CONFLICT` or `INSERT ... ON DUPLICATE KEY UPDATE`.
+    Upsert,
+    /// Referencing inserted values via a row alias, `INSERT ... AS new ON DUPLICATE KEY UPDATE col = new.col`.
+    InsertRowAlias,
+    /// `RETURNING` clauses of `INSERT`, `UPDATE` and `DELETE`.
+    Returning,
+    /// `FOR UPDATE SKIP LOCKED`.
+    SkipLocked,
+    /// Common table expressions, `WITH ... AS (...)`.
+    Cte,
+    /// Window functions, `... OVER (...)`.
+    WindowFunctions,
+    /// `MERGE INTO`.
+    Merge,
+    /// Historical reads via `AS OF SYSTEM TIME`.
+    AsOfSystemTime,
+    /// `UPSERT INTO`, which replaces the row with the same primary key.
+    UpsertStatement,
+    /// `LATERAL` subqueries, which reference the columns of the preceding tables.
+    Lateral,
+    /// Cycle detection of recursive common table expressions, `WITH RECURSIVE ... CYCLE ...`.
+    CteCycle,
+    /// Row value comparisons, `(a, b) = (?, ?)` and `(a, b) IN ((?, ?), (?, ?))`, that can use composite indexes.
+    RowValues,
+    /// `ORDER BY` inside aggregates, like `STRING_AGG(col, ',' ORDER BY col)`.
+    AggregateOrderBy,
+    /// Spatial functions on points, like `ST_DWithin` or `ST_Distance_Sphere`, see the `geo` module.
+    Spatial,
+    /// Partitioned tables, `CREATE TABLE ... PARTITION BY RANGE/LIST/HASH`, see the `ddl` module.
+    Partitioning,
+    /// Generated columns computed when rows are written, `GENERATED ALWAYS AS (...) STORED`.
+    StoredGeneratedColumns,
+    /// Generated columns computed when rows are read, `GENERATED ALWAYS AS (...) VIRTUAL`.
+    VirtualGeneratedColumns,
+    /// Enforced foreign keys, `FOREIGN KEY (...) REFERENCES ...`.
+    ForeignKeys,
+    /// Foreign key actions other than `NO ACTION` and `RESTRICT`, like `ON DELETE CASCADE`.
+    ForeignKeyActions,
+    /// Enforced check constraints, `CHECK (...)`.
+    CheckConstraints,
+    /// Creating tables from queries, `CREATE TABLE ... AS SELECT ...`.
+    CreateTableAs,
+    /// Copying rows between tables and files, `COPY ... TO/FROM 'path' (FORMAT ...)`, see the `file` module.
+    CopyFile,
+}
+
+impl Feature {
+    /// All features, for example to print a feature matrix.
+    pub const ALL: [Feature; 22] = [
+        Feature::Upsert,
+        Feature::InsertRowAlias,
+        Feature::Returning,
+        Feature::SkipLocked,
+        Feature::Cte,
+        Feature::WindowFunctions,
+        Feature::Merge,
+        Feature::AsOfSystemTime,
+        Feature::UpsertStatement,
+        Feature::Lateral,
+        Feature::CteCycle,
+        Feature::RowValues,
+        Feature::AggregateOrderBy,
+        Feature::Spatial,
+        Feature::Partitioning,
+        Feature::StoredGeneratedColumns,
+        Feature::VirtualGeneratedColumns,
+        Feature::ForeignKeys,
+        Feature::ForeignKeyActions,
+        Feature::CheckConstraints,
+        Feature::CreateTableAs,
+        Feature::CopyFile,
+    ];
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Feature::Upsert => "upsert",
+            Feature::InsertRowAlias => "insert row alias",
+            Feature::Returning => "RETURNING",
+            Feature::SkipLocked => "SKIP LOCKED",
+            Feature::Cte => "common table expressions",
+            Feature::WindowFunctions => "window functions",
+            Feature::Merge => "MERGE",
+            Feature::AsOfSystemTime => "AS OF SYSTEM TIME",
+            Feature::UpsertStatement => "UPSERT",
+            Feature::Lateral => "LATERAL",
+            Feature::CteCycle => "CYCLE",
+            Feature::RowValues => "row values",
+            Feature::AggregateOrderBy => "ORDER BY in aggregates",
+            Feature::Spatial => "spatial functions",
+            Feature::Partitioning => "partitioning",
+            Feature::StoredGeneratedColumns => "stored generated columns",
+            Feature::VirtualGeneratedColumns => "virtual generated columns",
+            Feature::ForeignKeys => "foreign keys",
+            Feature::ForeignKeyActions => "foreign key actions",
+            Feature::CheckConstraints => "check constraints",
+            Feature::CreateTableAs => "CREATE TABLE AS",
+            Feature::CopyFile => "COPY to and from files",
+        })
+    }
+}
+
+/// Error returned when a feature is not supported by the server version.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct UnsupportedFeature {
+    pub feature: Feature,
+    pub typ: Type,
+    /// The minimum version supporting the feature, or [`None`] if no version supports it.
+    pub required: Option<Version>,
+}
+
+impl fmt::Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.required {
+            Some(v) => write!(f, "{} requires {:?} {} or later", self.feature, self.typ, v),
+            None => write!(f, "{} is not supported by {:?}", self.feature, self.typ),
+        }
+    }
+}
+
+impl std::error::Error for UnsupportedFeature {}
+
+/// A database type with an optional server version.
+///
+/// Without a version, the latest version is assumed.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Dialect, Feature, Type, Version};
+///
+/// let dialect = Dialect::new(Type::SQLite).version("3.22.0".parse().unwrap());
+///
+/// assert_eq!(dialect.min_version(Feature::Upsert), Some(Version::new(3, 24, 0)));
+/// assert!(dialect.check(Feature::Upsert).is_err());
+/// assert!(Dialect::new(Type::SQLite).check(Feature::Upsert).is_ok());
+///
+/// // Branch on capabilities rather than database types.
+/// let stmt = if dialect.supports(Feature::Returning) {
+///     "DELETE FROM jobs WHERE id = ? RETURNING payload"
+/// } else {
+///     "DELETE FROM jobs WHERE id = ?"
+/// };
+/// assert_eq!(stmt, "DELETE FROM jobs WHERE id = ?");
+/// ```
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub struct Dialect {
+    typ: Type,
+    version: Option<Version>,
+}
+
+impl Dialect {
+    /// Creates a new [`Dialect`] of the latest version.
+    pub fn new(typ: Type) -> Dialect {
+        Dialect { typ, version: None }
+    }
+
+    /// Sets the server version.
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> Type {
+        self.typ
+    }
+
+    /// Gets server version.
+    pub fn get_version(&self) -> Option<Version> {
+        self.version
+    }
+
+    /// Gets the minimum version of this database type supporting `feature`,
+    /// which is [`Version::new(0, 0, 0)`](Version::new) if every version supports it, or [`None`] if no version does.
+    pub fn min_version(&self, feature: Feature) -> Option<Version> {
+        let v = Version::new;
+        match (feature, self.typ) {
+            (Feature::Upsert, Type::MySQL | Type::MariaDB | Type::TiDB) => Some(v(0, 0, 0)),
+            (Feature::Upsert, Type::PostgreSQL) => Some(v(9, 5, 0)),
+            (Feature::Upsert, Type::CockroachDB) => Some(v(0, 0, 0)),
+            (Feature::Upsert, Type::DuckDB) => Some(v(0, 7, 0)),
+            (Feature::Upsert, Type::SQLite) => Some(v(3, 24, 0)),
+            (Feature::InsertRowAlias, Type::MySQL) => Some(v(8, 0, 19)),
+            (
+                Feature::InsertRowAlias,
+                Type::PostgreSQL
+                | Type::SQLite
+                | Type::MariaDB
+                | Type::TiDB
+                | Type::CockroachDB
+                | Type::DuckDB,
+            ) => None,
+            (Feature::Returning, Type::MySQL | Type::TiDB) => None,
+            (Feature::Returning, Type::PostgreSQL) => Some(v(8, 2, 0)),
+            (Feature::Returning, Type::SQLite) => Some(v(3, 35, 0)),
+            (Feature::Returning, Type::MariaDB) => Some(v(10, 5, 0)),
+            (Feature::Returning, Type::CockroachDB) => Some(v(0, 0, 0)),
+            (Feature::Returning, Type::DuckDB) => Some(v(0, 7, 0)),
+            (Feature::SkipLocked, Type::MySQL) => Some(v(8, 0, 1)),
+            (Feature::SkipLocked, Type::PostgreSQL) => Some(v(9, 5, 0)),
+            (Feature::SkipLocked, Type::MariaDB) => Some(v(10, 6, 0)),
+            (Feature::SkipLocked, Type::CockroachDB) => Some(v(23, 1, 0)),
+            // SQLite and DuckDB lock the whole database, so there are no row locks to skip,
+            // and TiDB parses `SKIP LOCKED` but doesn't skip anything in pessimistic transactions.
+            (Feature::SkipLocked, Type::SQLite | Type::TiDB | Type::DuckDB) => None,
+            (Feature::Cte, Type::MySQL) => Some(v(8, 0, 1)),
+            (Feature::Cte, Type::PostgreSQL) => Some(v(8, 4, 0)),
+            (Feature::Cte, Type::SQLite) => Some(v(3, 8, 3)),
+            (Feature::Cte, Type::MariaDB) => Some(v(10, 2, 1)),
+            (Feature::Cte, Type::TiDB) => Some(v(5, 1, 0)),
+            (Feature::Cte, Type::CockroachDB) => Some(v(2, 0, 0)),
+            (Feature::Cte, Type::DuckDB) => Some(v(0, 0, 0)),
+            (Feature::WindowFunctions, Type::MySQL) => Some(v(8, 0, 2)),
+            (Feature::WindowFunctions, Type::PostgreSQL) => Some(v(8, 4, 0)),
+            (Feature::WindowFunctions, Type::SQLite) => Some(v(3, 25, 0)),
+            (Feature::WindowFunctions, Type::MariaDB) => Some(v(10, 2, 0)),
+            (Feature::WindowFunctions, Type::TiDB) => Some(v(3, 0, 0)),
+            (Feature::WindowFunctions, Type::CockroachDB) => Some(v(2, 0, 0)),
+            (Feature::WindowFunctions, Type::DuckDB) => Some(v(0, 0, 0)),
+            (Feature::Merge, Type::PostgreSQL) => Some(v(15, 0, 0)),
+            (Feature::Merge, Type::DuckDB) => Some(v(1, 4, 0)),
+            (
+                Feature::Merge,
+                Type::MySQL | Type::SQLite | Type::MariaDB | Type::TiDB | Type::CockroachDB,
+            ) => None,
+            (Feature::AsOfSystemTime | Feature::UpsertStatement, Type::CockroachDB) => {
+                Some(v(0, 0, 0))
+            }
+            (Feature::AsOfSystemTime | Feature::UpsertStatement, _) => None,
+            (Feature::Lateral, Type::MySQL) => Some(v(8, 0, 14)),
+            (Feature::Lateral, Type::PostgreSQL) => Some(v(9, 3, 0)),
+            (Feature::Lateral, Type::CockroachDB) => Some(v(20, 1, 0)),
+            (Feature::Lateral, Type::DuckDB) => Some(v(0, 7, 0)),
+            (Feature::Lateral, Type::SQLite | Type::MariaDB | Type::TiDB) => None,
+            (Feature::CteCycle, Type::PostgreSQL) => Some(v(14, 0, 0)),
+            (Feature::CteCycle, _) => None,
+            // Older versions of MySQL parse row values, but scan the whole table.
+            (Feature::RowValues, Type::MySQL) => Some(v(5, 7, 3)),
+            (Feature::RowValues, Type::PostgreSQL) => Some(v(8, 2, 0)),
+            (Feature::RowValues, Type::SQLite) => Some(v(3, 15, 0)),
+            (Feature::RowValues, Type::MariaDB | Type::TiDB | Type::CockroachDB | Type::DuckDB) => {
+                Some(v(0, 0, 0))
+            }
+            (Feature::AggregateOrderBy, Type::PostgreSQL) => Some(v(9, 0, 0)),
+            (Feature::AggregateOrderBy, Type::SQLite) => Some(v(3, 44, 0)),
+            (Feature::AggregateOrderBy, _) => Some(v(0, 0, 0)),
+            // PostgreSQL requires the PostGIS extension, and MariaDB has backported
+            // `ST_Distance_Sphere` to the latest patch releases of 10.2, 10.3 and 10.4 too.
+            (Feature::Spatial, Type::MySQL) => Some(v(5, 7, 6)),
+            (Feature::Spatial, Type::PostgreSQL) => Some(v(0, 0, 0)),
+            (Feature::Spatial, Type::MariaDB) => Some(v(10, 5, 10)),
+            (Feature::Spatial, Type::CockroachDB) => Some(v(20, 2, 0)),
+            (Feature::Spatial, Type::SQLite | Type::TiDB | Type::DuckDB) => None,
+            // Hash partitions use KEY partitioning on MySQL and TiDB, and HASH partitioning on PostgreSQL.
+            // CockroachDB partitions only to place the data in regions.
+            (Feature::Partitioning, Type::MySQL | Type::MariaDB) => Some(v(0, 0, 0)),
+            (Feature::Partitioning, Type::PostgreSQL) => Some(v(11, 0, 0)),
+            (Feature::Partitioning, Type::TiDB) => Some(v(7, 0, 0)),
+            (Feature::Partitioning, Type::SQLite | Type::CockroachDB | Type::DuckDB) => None,
+            (Feature::StoredGeneratedColumns | Feature::VirtualGeneratedColumns, Type::MySQL) => {
+                Some(v(5, 7, 6))
+            }
+            (Feature::StoredGeneratedColumns, Type::PostgreSQL) => Some(v(12, 0, 0)),
+            (Feature::VirtualGeneratedColumns, Type::PostgreSQL) => Some(v(18, 0, 0)),
+            (Feature::StoredGeneratedColumns | Feature::VirtualGeneratedColumns, Type::SQLite) => {
+                Some(v(3, 31, 0))
+            }
+            // MariaDB calls stored columns persistent before 10.2.1.
+            (Feature::StoredGeneratedColumns | Feature::VirtualGeneratedColumns, Type::MariaDB) => {
+                Some(v(10, 2, 1))
+            }
+            (Feature::StoredGeneratedColumns | Feature::VirtualGeneratedColumns, Type::TiDB) => {
+                Some(v(2, 1, 0))
+            }
+            (
+                Feature::StoredGeneratedColumns | Feature::VirtualGeneratedColumns,
+                Type::CockroachDB,
+            ) => Some(v(21, 1, 0)),
+            (Feature::StoredGeneratedColumns, Type::DuckDB) => None,
+            (Feature::VirtualGeneratedColumns, Type::DuckDB) => Some(v(0, 3, 3)),
+            // Older versions of TiDB, and of MySQL for checks, parse the constraints but don't enforce them.
+            (Feature::ForeignKeys | Feature::ForeignKeyActions, Type::TiDB) => Some(v(6, 6, 0)),
+            (Feature::ForeignKeyActions, Type::DuckDB) => None,
+            (Feature::ForeignKeys | Feature::ForeignKeyActions, _) => Some(v(0, 0, 0)),
+            (Feature::CheckConstraints, Type::MySQL) => Some(v(8, 0, 16)),
+            (Feature::CheckConstraints, Type::MariaDB) => Some(v(10, 2, 1)),
+            (Feature::CheckConstraints, Type::TiDB) => Some(v(7, 2, 0)),
+            (Feature::CheckConstraints, _) => Some(v(0, 0, 0)),
+            (Feature::CreateTableAs, Type::TiDB) => None,
+            (Feature::CreateTableAs, _) => Some(v(0, 0, 0)),
+            (Feature::CopyFile, Type::DuckDB) => Some(v(0, 0, 0)),
+            (Feature::CopyFile, _) => None,
+        }
+    }
+
+    /// Returns `true` if the server version supports `feature`, see [`Dialect::check`].
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.check(feature).is_ok()
+    }
+
+    /// Checks that the server version supports `feature`.
+    pub fn check(&self, feature: Feature) -> Result<(), UnsupportedFeature> {
+        match self.min_version(feature) {
+            Some(min) if self.version.is_none_or(|v| v >= min) => Ok(()),
+            required => Err(UnsupportedFeature {
+                feature,
+                typ: self.typ,
+                required,
+            }),
+        }
+    }
+}
+
+impl From<Type> for Dialect {
+    fn from(typ: Type) -> Self {
+        Dialect::new(typ)
+    }
+}
+
+/// Error returned by [`StmtBuilder`](crate::db::StmtBuilder) methods that check both the policy and the dialect.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum BuildError {
+    Policy(PolicyError),
+    Unsupported(UnsupportedFeature),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Policy(e) => write!(f, "{}", e),
+            BuildError::Unsupported(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<PolicyError> for BuildError {
+    fn from(e: PolicyError) -> Self {
+        BuildError::Policy(e)
+    }
+}
+
+impl From<UnsupportedFeature> for BuildError {
+    fn from(e: UnsupportedFeature) -> Self {
+        BuildError::Unsupported(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::{Dialect, Feature, ParseVersionError, UnsupportedFeature, Version};
+
+    #[test]
+    fn test_parse_version() {
+        struct TC {
+            s: &'static str,
+            want: Result<Version, ParseVersionError>,
+        }
+
+        let test_cases = vec![
+            TC {
+                s: "8.0.36-0ubuntu0.22.04.1",
+                want: Ok(Version::new(8, 0, 36)),
+            },
+            TC {
+                s: "PostgreSQL 15.4 on x86_64-pc-linux-gnu",
+                want: Ok(Version::new(15, 4, 0)),
+            },
+            TC {
+                s: "16",
+                want: Ok(Version::new(16, 0, 0)),
+            },
+            TC {
+                s: "unknown",
+                want: Err(ParseVersionError(String::from("unknown"))),
+            },
+            TC {
+                s: "99999999999.1",
+                want: Err(ParseVersionError(String::from("99999999999.1"))),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(tc.s.parse::<Version>(), tc.want);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let dialect = Dialect::new(Type::PostgreSQL).version(Version::new(9, 4, 26));
+        assert_eq!(
+            dialect.check(Feature::Upsert),
+            Err(UnsupportedFeature {
+                feature: Feature::Upsert,
+                typ: Type::PostgreSQL,
+                required: Some(Version::new(9, 5, 0)),
+            })
+        );
+        assert_eq!(
+            dialect.check(Feature::Upsert).unwrap_err().to_string(),
+            "upsert requires PostgreSQL 9.5.0 or later"
+        );
+        assert!(
+            dialect
+                .version(Version::new(9, 5, 0))
+                .check(Feature::Upsert)
+                .is_ok()
+        );
+        assert_eq!(
+            Dialect::new(Type::SQLite)
+                .check(Feature::InsertRowAlias)
+                .unwrap_err()
+                .to_string(),
+            "insert row alias is not supported by SQLite"
+        );
+    }
+
+    #[test]
+    fn test_supports() {
+        struct TC {
+            dialect: Dialect,
+            want: [bool; 22],
+        }
+
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::MySQL),
+                want: [
+                    true, true, false, true, true, true, false, false, false, true, false, true,
+                    true, true, true, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::MySQL).version(Version::new(5, 7, 44)),
+                want: [
+                    true, false, false, false, false, false, false, false, false, false, false,
+                    true, true, true, true, true, true, true, true, false, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL),
+                want: [
+                    true, false, true, true, true, true, true, false, false, true, true, true,
+                    true, true, true, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::PostgreSQL).version(Version::new(14, 10, 0)),
+                want: [
+                    true, false, true, true, true, true, false, false, false, true, true, true,
+                    true, true, true, true, false, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::SQLite),
+                want: [
+                    true, false, true, false, true, true, false, false, false, false, false, true,
+                    true, false, false, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::SQLite).version(Version::new(3, 31, 1)),
+                want: [
+                    true, false, false, false, true, true, false, false, false, false, false, true,
+                    false, false, false, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::MariaDB),
+                want: [
+                    true, false, true, true, true, true, false, false, false, false, false, true,
+                    true, true, true, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::MariaDB).version(Version::new(10, 4, 32)),
+                want: [
+                    true, false, false, false, true, true, false, false, false, false, false, true,
+                    true, false, true, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::TiDB),
+                want: [
+                    true, false, false, false, true, true, false, false, false, false, false, true,
+                    true, false, true, true, true, true, true, true, false, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::TiDB).version(Version::new(4, 0, 16)),
+                want: [
+                    true, false, false, false, false, true, false, false, false, false, false,
+                    true, true, false, false, true, true, false, false, false, false, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::DuckDB),
+                want: [
+                    true, false, true, false, true, true, true, false, false, true, false, true,
+                    true, false, false, false, true, true, false, true, true, true,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::DuckDB).version(Version::new(0, 6, 1)),
+                want: [
+                    false, false, false, false, true, true, false, false, false, false, false,
+                    true, true, false, false, false, true, true, false, true, true, true,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::CockroachDB),
+                want: [
+                    true, false, true, true, true, true, false, true, true, true, false, true,
+                    true, true, false, true, true, true, true, true, true, false,
+                ],
+            },
+            TC {
+                dialect: Dialect::new(Type::CockroachDB).version(Version::new(22, 2, 0)),
+                want: [
+                    true, false, true, false, true, true, false, true, true, true, false, true,
+                    true, true, false, true, true, true, true, true, true, false,
+                ],
+            },
+        ];
+
+        for tc in test_cases {
+            let got = Feature::ALL.map(|feature| tc.dialect.supports(feature));
+            assert_eq!(got, tc.want, "{:?}", tc.dialect);
+        }
+    }
+}
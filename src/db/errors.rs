@@ -0,0 +1,249 @@
+//! Classification of database errors.
+//!
+//! [`classify`] maps the error codes of MySQL, PostgreSQL and SQLite to a [`DbErrorKind`],
+//! so that callers don't need to match the messages of each driver.
+
+use sqlx::{error::DatabaseError, mysql::MySqlDatabaseError, sqlite::SqliteError};
+
+/// The kind of a database error.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum DbErrorKind {
+    /// A unique or primary key constraint is violated.
+    ///
+    /// The constraint is the constraint name on PostgreSQL, the key name on MySQL,
+    /// and the columns like `users.email` on SQLite, if known.
+    UniqueViolation { constraint: Option<String> },
+    /// A foreign key constraint is violated. The constraint name is unknown on SQLite.
+    ForeignKeyViolation { constraint: Option<String> },
+    /// A null value is written to a non-null column.
+    NotNullViolation,
+    /// The transaction conflicts with a concurrent one, including deadlocks, and may succeed if retried.
+    SerializationFailure,
+    /// The statement timed out, or waited too long for a lock or a pooled connection.
+    Timeout,
+    /// The connection is lost or closed.
+    ConnectionLost,
+    /// Any other error.
+    Other,
+}
+
+/// Classifies a sqlx error.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{
+///     errors::{DbErrorKind, classify},
+///     exec,
+/// };
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// exec::execute(&mut conn, "CREATE TABLE users (email TEXT UNIQUE)", &[]).await.unwrap();
+/// exec::execute(&mut conn, "INSERT INTO users VALUES ('a@b.c')", &[]).await.unwrap();
+///
+/// let e = exec::execute(&mut conn, "INSERT INTO users VALUES ('a@b.c')", &[]).await.unwrap_err();
+///
+/// assert_eq!(
+///     classify(&e),
+///     DbErrorKind::UniqueViolation { constraint: Some(String::from("users.email")) },
+/// );
+/// # });
+/// ```
+pub fn classify(e: &sqlx::Error) -> DbErrorKind {
+    match e {
+        sqlx::Error::Database(db) => classify_database(db.as_ref()),
+        sqlx::Error::PoolTimedOut => DbErrorKind::Timeout,
+        sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => DbErrorKind::ConnectionLost,
+        sqlx::Error::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => DbErrorKind::Timeout,
+        sqlx::Error::Io(_) => DbErrorKind::ConnectionLost,
+        _ => DbErrorKind::Other,
+    }
+}
+
+fn classify_database(db: &dyn DatabaseError) -> DbErrorKind {
+    if let Some(e) = db.try_downcast_ref::<MySqlDatabaseError>() {
+        return classify_mysql(e.number(), e.message());
+    }
+    if let Some(e) = db.try_downcast_ref::<SqliteError>() {
+        let code = e.code().and_then(|c| c.parse().ok()).unwrap_or_default();
+        return classify_sqlite(code, e.message());
+    }
+    classify_sqlstate(db.code().as_deref().unwrap_or_default(), db.constraint())
+}
+
+/// Classifies a PostgreSQL error, or any error with a SQLSTATE code.
+fn classify_sqlstate(code: &str, constraint: Option<&str>) -> DbErrorKind {
+    let constraint = constraint.map(str::to_string);
+    match code {
+        "23505" => DbErrorKind::UniqueViolation { constraint },
+        "23503" => DbErrorKind::ForeignKeyViolation { constraint },
+        "23502" => DbErrorKind::NotNullViolation,
+        "40001" | "40P01" => DbErrorKind::SerializationFailure,
+        // query_canceled, which is raised by statement_timeout, and lock_not_available
+        "57014" | "55P03" => DbErrorKind::Timeout,
+        // admin_shutdown, crash_shutdown and cannot_connect_now
+        "57P01" | "57P02" | "57P03" => DbErrorKind::ConnectionLost,
+        c if c.starts_with("08") => DbErrorKind::ConnectionLost,
+        _ => DbErrorKind::Other,
+    }
+}
+
+/// Returns the text between `begin` and the next `end` in `s`.
+fn between<'a>(s: &'a str, begin: &str, end: char) -> Option<&'a str> {
+    let rest = &s[s.find(begin)? + begin.len()..];
+    Some(&rest[..rest.find(end)?])
+}
+
+/// Classifies a MySQL error by its error number.
+fn classify_mysql(number: u16, message: &str) -> DbErrorKind {
+    match number {
+        // ER_DUP_ENTRY: Duplicate entry '1' for key 'users.PRIMARY'
+        1062 | 1586 => DbErrorKind::UniqueViolation {
+            constraint: between(message, "for key '", '\'').map(str::to_string),
+        },
+        // ER_NO_REFERENCED_ROW_2 and ER_ROW_IS_REFERENCED_2: ... CONSTRAINT `fk` FOREIGN KEY ...
+        1451 | 1452 | 1216 | 1217 => DbErrorKind::ForeignKeyViolation {
+            constraint: between(message, "CONSTRAINT `", '`').map(str::to_string),
+        },
+        // ER_BAD_NULL_ERROR
+        1048 | 1364 => DbErrorKind::NotNullViolation,
+        // ER_LOCK_DEADLOCK
+        1213 => DbErrorKind::SerializationFailure,
+        // ER_LOCK_WAIT_TIMEOUT and ER_QUERY_TIMEOUT
+        1205 | 3024 => DbErrorKind::Timeout,
+        // Server shutdown, connection killed and lost connection
+        1053 | 1927 | 2006 | 2013 => DbErrorKind::ConnectionLost,
+        _ => DbErrorKind::Other,
+    }
+}
+
+/// Classifies a SQLite error by its extended result code.
+fn classify_sqlite(code: i32, message: &str) -> DbErrorKind {
+    // UNIQUE constraint failed: users.email
+    let columns = || message.split_once(": ").map(|(_, c)| c.to_string());
+    match code {
+        // SQLITE_CONSTRAINT_UNIQUE and SQLITE_CONSTRAINT_PRIMARYKEY
+        2067 | 1555 => DbErrorKind::UniqueViolation {
+            constraint: columns(),
+        },
+        // SQLITE_CONSTRAINT_FOREIGNKEY
+        787 => DbErrorKind::ForeignKeyViolation { constraint: None },
+        // SQLITE_CONSTRAINT_NOTNULL
+        1299 => DbErrorKind::NotNullViolation,
+        // SQLITE_BUSY_SNAPSHOT
+        517 => DbErrorKind::SerializationFailure,
+        // SQLITE_BUSY and SQLITE_LOCKED, after the busy timeout
+        c if matches!(c & 0xff, 5 | 6) => DbErrorKind::Timeout,
+        _ => DbErrorKind::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{AnyConnection, Connection};
+
+    use crate::db::exec;
+
+    use super::{DbErrorKind, classify, classify_mysql, classify_sqlstate};
+
+    #[test]
+    fn test_classify_sqlstate() {
+        assert_eq!(
+            classify_sqlstate("23505", Some("users_email_key")),
+            DbErrorKind::UniqueViolation {
+                constraint: Some(String::from("users_email_key"))
+            }
+        );
+        assert_eq!(
+            classify_sqlstate("23503", None),
+            DbErrorKind::ForeignKeyViolation { constraint: None }
+        );
+        assert_eq!(
+            classify_sqlstate("40P01", None),
+            DbErrorKind::SerializationFailure
+        );
+        assert_eq!(classify_sqlstate("57014", None), DbErrorKind::Timeout);
+        assert_eq!(
+            classify_sqlstate("08006", None),
+            DbErrorKind::ConnectionLost
+        );
+        assert_eq!(classify_sqlstate("42P01", None), DbErrorKind::Other);
+    }
+
+    #[test]
+    fn test_classify_mysql() {
+        assert_eq!(
+            classify_mysql(1062, "Duplicate entry 'a' for key 'users.email'"),
+            DbErrorKind::UniqueViolation {
+                constraint: Some(String::from("users.email"))
+            }
+        );
+        assert_eq!(
+            classify_mysql(
+                1452,
+                "Cannot add or update a child row: a foreign key constraint fails \
+                 (`db`.`c`, CONSTRAINT `c_ibfk_1` FOREIGN KEY (`p`) REFERENCES `p` (`id`))"
+            ),
+            DbErrorKind::ForeignKeyViolation {
+                constraint: Some(String::from("c_ibfk_1"))
+            }
+        );
+        assert_eq!(classify_mysql(1048, ""), DbErrorKind::NotNullViolation);
+        assert_eq!(classify_mysql(1213, ""), DbErrorKind::SerializationFailure);
+        assert_eq!(classify_mysql(1205, ""), DbErrorKind::Timeout);
+        assert_eq!(classify_mysql(1146, ""), DbErrorKind::Other);
+    }
+
+    #[tokio::test]
+    async fn test_classify_sqlite() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        for stmt in [
+            "PRAGMA foreign_keys = ON",
+            "CREATE TABLE p (id INTEGER PRIMARY KEY)",
+            "CREATE TABLE c (id INTEGER, p INTEGER NOT NULL REFERENCES p (id))",
+            "INSERT INTO p VALUES (1)",
+        ] {
+            exec::execute(&mut conn, stmt, &[]).await.unwrap();
+        }
+
+        struct TC {
+            stmt: &'static str,
+            want: DbErrorKind,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "INSERT INTO p VALUES (1)",
+                want: DbErrorKind::UniqueViolation {
+                    constraint: Some(String::from("p.id")),
+                },
+            },
+            TC {
+                stmt: "INSERT INTO c VALUES (1, 2)",
+                want: DbErrorKind::ForeignKeyViolation { constraint: None },
+            },
+            TC {
+                stmt: "INSERT INTO c VALUES (1, NULL)",
+                want: DbErrorKind::NotNullViolation,
+            },
+            TC {
+                stmt: "SELECT * FROM no_such_tbl",
+                want: DbErrorKind::Other,
+            },
+        ];
+
+        for tc in test_cases {
+            let e = exec::execute(&mut conn, tc.stmt, &[]).await.unwrap_err();
+            assert_eq!(classify(&e), tc.want, "{}", tc.stmt);
+        }
+        assert_eq!(classify(&sqlx::Error::PoolTimedOut), DbErrorKind::Timeout);
+        assert_eq!(
+            classify(&sqlx::Error::PoolClosed),
+            DbErrorKind::ConnectionLost
+        );
+    }
+}
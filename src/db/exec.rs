@@ -0,0 +1,877 @@
+//! Helpers that execute statements built by [`StmtBuilder`](crate::db::StmtBuilder) via sqlx.
+//!
+//! All helpers work with [`sqlx::Any`], so the same code can run against MySQL, PostgreSQL and SQLite.
+//! Remember to call [`sqlx::any::install_default_drivers`] before connecting.
+
+use std::fmt;
+
+use sqlx::{
+    Acquire, Any, Database, Encode, Executor, QueryBuilder, Row, Transaction, ValueRef,
+    any::{AnyArguments, AnyRow, AnyTypeInfoKind},
+    query::Query,
+};
+
+#[cfg(feature = "retry")]
+use sqlx::AnyConnection;
+
+use crate::db::{
+    ParseVersionError, Type, Value, Version, fingerprint::placeholders, unsupported_dialect,
+};
+#[cfg(feature = "retry")]
+use crate::retry::{self, Policy};
+
+/// Errors returned by the execution helpers.
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by sqlx.
+    Sqlx(sqlx::Error),
+    /// A versioned update affected zero rows,
+    /// which means the row has been modified or deleted since it was read.
+    StaleVersion,
+    /// The number of placeholders in a statement doesn't match the number of arguments.
+    ArgCount { placeholders: usize, args: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sqlx(e) => write!(f, "sqlx error: {}", e),
+            Error::StaleVersion => write!(f, "stale version: no rows affected"),
+            Error::ArgCount { placeholders, args } => write!(
+                f,
+                "argument count mismatch: {} placeholders, {} arguments",
+                placeholders, args
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sqlx(e) => Some(e),
+            Error::StaleVersion | Error::ArgCount { .. } => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+/// Creates a sqlx query from `stmt` and binds `args` to its placeholders in order.
+pub fn query<'q>(stmt: &'q str, args: &'q [Value]) -> Query<'q, Any, AnyArguments<'q>> {
+    args.iter().fold(sqlx::query(stmt), |q, arg| match arg {
+        Value::Null => q.bind(None::<String>),
+        Value::Bool(v) => q.bind(*v),
+        Value::Int(v) => q.bind(*v),
+        Value::Float(v) => q.bind(*v),
+        Value::Text(v) => q.bind(v.as_str()),
+        Value::Bytes(v) => q.bind(v.as_slice()),
+    })
+}
+
+/// Converts a statement and its arguments into a [`QueryBuilder`],
+/// so that more SQL and bound values can be pushed with [`QueryBuilder::push`] and [`QueryBuilder::push_bind`].
+///
+/// Each placeholder in `stmt`, either `?` or `$N`, is replaced by [`QueryBuilder::push_bind`],
+/// so the placeholders are rendered in the format of `DB`.
+/// `$N` is bound to the N-th argument, while `?` is bound to the next argument in order.
+/// Placeholders in quoted literals and identifiers are ignored.
+///
+/// # Arguments
+///
+/// * `stmt` - The SQL statement, for example one built by [`StmtBuilder`](crate::db::StmtBuilder).
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The query builder, or [`Error::ArgCount`] if the placeholders don't match the arguments.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type, Value, exec::query_builder};
+/// use sqlx::Postgres;
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// let conds = vec![KV {
+///     key: "status",
+///     val: PLACEHOLDER,
+/// }];
+/// let stmt = sb.build_query_stmt(&["id"], &conds);
+/// let args = [Value::from("active")];
+///
+/// let mut qb = query_builder::<Postgres>(&stmt, &args).unwrap();
+/// qb.push(" AND age > ").push_bind(18_i64);
+///
+/// assert_eq!(
+///     qb.sql(),
+///     "SELECT \"id\" FROM users WHERE status = $1 AND age > $2"
+/// );
+/// ```
+pub fn query_builder<'args, DB>(
+    stmt: &str,
+    args: &'args [Value],
+) -> Result<QueryBuilder<'args, DB>, Error>
+where
+    DB: Database,
+    bool: Encode<'args, DB> + sqlx::Type<DB>,
+    i64: Encode<'args, DB> + sqlx::Type<DB>,
+    f64: Encode<'args, DB> + sqlx::Type<DB>,
+    &'args str: Encode<'args, DB> + sqlx::Type<DB>,
+    &'args [u8]: Encode<'args, DB> + sqlx::Type<DB>,
+    Option<&'args str>: Encode<'args, DB> + sqlx::Type<DB>,
+{
+    let mut qb = QueryBuilder::new("");
+    let (mut begin, mut count) = (0, 0);
+    for (range, arg_idx) in placeholders(stmt) {
+        let Some(arg) = args.get(arg_idx) else {
+            return Err(Error::ArgCount {
+                placeholders: arg_idx.saturating_add(1),
+                args: args.len(),
+            });
+        };
+        qb.push(&stmt[begin..range.start]);
+        match arg {
+            Value::Null => qb.push_bind(None::<&str>),
+            Value::Bool(v) => qb.push_bind(*v),
+            Value::Int(v) => qb.push_bind(*v),
+            Value::Float(v) => qb.push_bind(*v),
+            Value::Text(v) => qb.push_bind(v.as_str()),
+            Value::Bytes(v) => qb.push_bind(v.as_slice()),
+        };
+        count = count.max(arg_idx + 1);
+        begin = range.end;
+    }
+    if count != args.len() {
+        return Err(Error::ArgCount {
+            placeholders: count,
+            args: args.len(),
+        });
+    }
+    qb.push(&stmt[begin..]);
+    Ok(qb)
+}
+
+/// Executes a statement.
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The number of rows affected.
+pub async fn execute<'e, E>(executor: E, stmt: &str, args: &[Value]) -> Result<u64, sqlx::Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    Ok(executor.execute(query(stmt, args)).await?.rows_affected())
+}
+
+/// Executes a statement and returns all rows.
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The rows.
+pub async fn fetch_all<'e, E>(
+    executor: E,
+    stmt: &str,
+    args: &[Value],
+) -> Result<Vec<AnyRow>, sqlx::Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    executor.fetch_all(query(stmt, args)).await
+}
+
+/// Executes a statement and returns at most one row.
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The first row, if any.
+pub async fn fetch_optional<'e, E>(
+    executor: E,
+    stmt: &str,
+    args: &[Value],
+) -> Result<Option<AnyRow>, sqlx::Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    executor.fetch_optional(query(stmt, args)).await
+}
+
+/// Queries the server version, which can be set via [`StmtBuilder::set_server_version`](crate::db::StmtBuilder::set_server_version).
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, exec::server_version};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+///
+/// assert!(server_version(&mut conn, Type::SQLite).await.unwrap().major >= 3);
+/// # });
+/// ```
+pub async fn server_version<'e, E>(executor: E, typ: Type) -> Result<Version, sqlx::Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    let stmt = match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => "SELECT VERSION()",
+        Type::PostgreSQL => "SHOW server_version",
+        // `server_version` is the PostgreSQL version CockroachDB is compatible with, not its own version.
+        Type::CockroachDB => "SELECT version()",
+        Type::SQLite => "SELECT sqlite_version()",
+        Type::DuckDB => return Err(unsupported_dialect(typ)),
+    };
+    let row = executor.fetch_one(query(stmt, &[])).await?;
+    let version: String = row.try_get(0)?;
+    parse_server_version(&version, typ).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// Parses the version reported by the server, like `8.0.11-TiDB-v7.5.0` on TiDB
+/// or `5.5.5-10.11.6-MariaDB` on MariaDB behind old replication proxies.
+fn parse_server_version(version: &str, typ: Type) -> Result<Version, ParseVersionError> {
+    let version = match typ {
+        // TiDB reports the MySQL version it's compatible with, followed by its own version.
+        Type::TiDB => version.split_once("-TiDB-v").map_or(version, |(_, v)| v),
+        Type::MariaDB => version.strip_prefix("5.5.5-").unwrap_or(version),
+        Type::MySQL | Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => version,
+    };
+    version.parse()
+}
+
+/// Decodes the `i`-th column of `row` into a [`Value`] according to the column type.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Value, exec::{decode_value, fetch_optional}};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let row = fetch_optional(&mut conn, "SELECT 1, 'a', NULL", &[]).await.unwrap().unwrap();
+///
+/// assert_eq!(decode_value(&row, 0).unwrap(), Value::from(1));
+/// assert_eq!(decode_value(&row, 1).unwrap(), Value::from("a"));
+/// assert_eq!(decode_value(&row, 2).unwrap(), Value::Null);
+/// # });
+/// ```
+pub fn decode_value(row: &AnyRow, i: usize) -> Result<Value, sqlx::Error> {
+    let raw = row.try_get_raw(i)?;
+    if raw.is_null() {
+        return Ok(Value::Null);
+    }
+    Ok(match raw.type_info().kind() {
+        AnyTypeInfoKind::Null => Value::Null,
+        AnyTypeInfoKind::Bool => Value::Bool(row.try_get(i)?),
+        AnyTypeInfoKind::SmallInt | AnyTypeInfoKind::Integer | AnyTypeInfoKind::BigInt => {
+            Value::Int(row.try_get(i)?)
+        }
+        AnyTypeInfoKind::Real | AnyTypeInfoKind::Double => Value::Float(row.try_get(i)?),
+        AnyTypeInfoKind::Text => Value::Text(row.try_get(i)?),
+        AnyTypeInfoKind::Blob => Value::Bytes(row.try_get(i)?),
+    })
+}
+
+/// Executes an update statement built with a version column,
+/// see [`StmtBuilder::set_version_col`](crate::db::StmtBuilder::set_version_col).
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders. The last one should be the expected version.
+///
+/// # Returns
+///
+/// * The number of rows affected, or [`Error::StaleVersion`] if no rows are affected.
+pub async fn execute_versioned<'e, E>(executor: E, stmt: &str, args: &[Value]) -> Result<u64, Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    match execute(executor, stmt, args).await? {
+        0 => Err(Error::StaleVersion),
+        n => Ok(n),
+    }
+}
+
+/// Runs `f` inside a transaction.
+///
+/// The transaction is committed if `f` returns [`Ok`], and rolled back otherwise.
+///
+/// CockroachDB runs every transaction at `SERIALIZABLE` isolation and aborts conflicting ones
+/// with SQLSTATE `40001`, even at low contention, so wrap its transactions with `transaction_with_retry`
+/// or retry on [`is_transient`] errors. Since the whole transaction is rerun,
+/// `f` must not have side effects outside the database.
+///
+/// # Arguments
+///
+/// * `conn` - The connection or pool.
+/// * `f` - The function that executes statements via the transaction.
+///
+/// # Returns
+///
+/// * The result of `f`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::exec::{execute, transaction};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+///
+/// let rows_affected = transaction(&mut conn, async |tx| {
+///     execute(&mut **tx, "CREATE TABLE t (a INTEGER)", &[]).await?;
+///     execute(&mut **tx, "INSERT INTO t (a) VALUES (1)", &[]).await
+/// })
+/// .await
+/// .unwrap();
+///
+/// assert_eq!(rows_affected, 1);
+/// # });
+/// ```
+pub async fn transaction<'c, C, F, T, E>(conn: C, f: F) -> Result<T, E>
+where
+    C: Acquire<'c, Database = Any>,
+    F: AsyncFnOnce(&mut Transaction<'c, Any>) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    let mut tx = conn.begin().await?;
+    match f(&mut tx).await {
+        Ok(v) => {
+            tx.commit().await?;
+            Ok(v)
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+/// Returns whether `e` is a transient conflict, after which retrying the whole transaction may succeed.
+///
+/// Deadlocks and serialization failures (SQLSTATE `40001` and `40P01`) on MySQL, PostgreSQL and their relatives,
+/// including the retry errors of CockroachDB, and busy or locked databases on SQLite are transient.
+pub fn is_transient(e: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(e) = e else {
+        return false;
+    };
+    match e.code().as_deref() {
+        Some("40001" | "40P01") => true,
+        // SQLite reports extended result codes, whose lower 8 bits are the primary code.
+        Some(code) => code
+            .parse::<i32>()
+            .is_ok_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)),
+        None => false,
+    }
+}
+
+const SQLITE_BUSY: i32 = 5;
+
+const SQLITE_LOCKED: i32 = 6;
+
+/// Runs `f` inside a transaction like [`transaction`],
+/// and reruns the whole transaction while it fails with a transient error, see [`is_transient`].
+///
+/// # Arguments
+///
+/// * `conn` - The connection.
+/// * `policy` - The retry policy.
+/// * `f` - The function that executes statements via the transaction, which is called once per attempt.
+///
+/// # Returns
+///
+/// * The result of the last attempt.
+#[cfg(feature = "retry")]
+pub async fn transaction_with_retry<F, T>(
+    conn: &mut AnyConnection,
+    policy: &Policy,
+    mut f: F,
+) -> Result<T, sqlx::Error>
+where
+    F: AsyncFnMut(&mut Transaction<'_, Any>) -> Result<T, sqlx::Error>,
+{
+    retry::spawn_if(policy, is_transient, async || {
+        transaction(&mut *conn, async |tx| f(tx).await).await
+    })
+    .await
+}
+
+/// Explains the execution plan of a statement.
+///
+/// `EXPLAIN QUERY PLAN` is used for SQLite, and `EXPLAIN` is used for the others.
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `typ` - The database type.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The plan, where each row is rendered as a line and columns are separated by ` | `.
+pub async fn explain<'e, E>(
+    executor: E,
+    typ: Type,
+    stmt: &str,
+    args: &[Value],
+) -> Result<String, sqlx::Error>
+where
+    E: Executor<'e, Database = Any>,
+{
+    let stmt = match typ {
+        Type::MySQL
+        | Type::MariaDB
+        | Type::TiDB
+        | Type::PostgreSQL
+        | Type::CockroachDB
+        | Type::DuckDB => format!("EXPLAIN {}", stmt),
+        Type::SQLite => format!("EXPLAIN QUERY PLAN {}", stmt),
+    };
+    let rows = fetch_all(executor, &stmt, args).await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (0..row.len())
+                .map(|i| {
+                    row.try_get::<String, _>(i)
+                        .or_else(|_| row.try_get::<i64, _>(i).map(|v| v.to_string()))
+                        .or_else(|_| row.try_get::<f64, _>(i).map(|v| v.to_string()))
+                        .unwrap_or_else(|_| String::from("NULL"))
+                })
+                .collect::<Vec<String>>()
+                .join(" | ")
+        })
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{AnyConnection, Connection, Row};
+
+    use crate::db::{KV, PLACEHOLDER, StmtBuilder, Type, Value, Version};
+
+    use super::{
+        Error, execute, execute_versioned, explain, fetch_all, fetch_optional,
+        parse_server_version, query_builder, server_version, transaction,
+    };
+
+    async fn connect() -> AnyConnection {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        execute(
+            &mut conn,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, avatar BLOB, score REAL, active BOOLEAN, version INTEGER NOT NULL)",
+            &[],
+        )
+        .await
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_parse_server_version() {
+        struct TC {
+            version: &'static str,
+            typ: Type,
+            want: Version,
+        }
+
+        let test_cases = vec![
+            TC {
+                version: "8.0.36",
+                typ: Type::MySQL,
+                want: Version::new(8, 0, 36),
+            },
+            TC {
+                version: "5.5.5-10.11.6-MariaDB-1:10.11.6+maria~ubu2204",
+                typ: Type::MariaDB,
+                want: Version::new(10, 11, 6),
+            },
+            TC {
+                version: "8.0.11-TiDB-v7.5.0",
+                typ: Type::TiDB,
+                want: Version::new(7, 5, 0),
+            },
+            TC {
+                version: "16.2 (Debian 16.2-1.pgdg120+2)",
+                typ: Type::PostgreSQL,
+                want: Version::new(16, 2, 0),
+            },
+            TC {
+                version: "CockroachDB CCL v23.1.11 (x86_64-pc-linux-gnu, built 2023/09/27 01:53:43, go1.19.10)",
+                typ: Type::CockroachDB,
+                want: Version::new(23, 1, 11),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(parse_server_version(tc.version, tc.typ).unwrap(), tc.want);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_version() {
+        let mut conn = connect().await;
+        assert!(server_version(&mut conn, Type::SQLite).await.unwrap().major >= 3);
+        // sqlx has no DuckDB driver
+        let res = server_version(&mut conn, Type::DuckDB).await;
+        assert!(matches!(res, Err(sqlx::Error::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_execute_and_fetch() {
+        let mut conn = connect().await;
+        let sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+        let cols = vec![
+            KV {
+                key: "id",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "avatar",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "score",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "active",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "version",
+                val: "0",
+            },
+        ];
+        let rows_affected = execute(
+            &mut conn,
+            &sb.build_insert_stmt(&cols),
+            &[
+                Value::from(1),
+                Value::from("foo"),
+                Value::from(vec![1_u8, 2]),
+                Value::from(1.5),
+                Value::from(true),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(rows_affected, 1);
+        execute(
+            &mut conn,
+            &sb.build_insert_stmt(&cols),
+            &[
+                Value::from(2),
+                Value::Null,
+                Value::Null,
+                Value::Null,
+                Value::Null,
+            ],
+        )
+        .await
+        .unwrap();
+
+        let rows = fetch_all(
+            &mut conn,
+            &sb.build_query_stmt(&[String::from("name")], &Vec::<KV>::new()),
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let conds = vec![KV {
+            key: "id",
+            val: PLACEHOLDER,
+        }];
+        let stmt = sb.build_query_stmt(&[String::from("name")], &conds);
+        let row = fetch_optional(&mut conn, &stmt, &[Value::from(1)])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.get::<String, _>(0), "foo");
+        let row = fetch_optional(&mut conn, &stmt, &[Value::from(3)])
+            .await
+            .unwrap();
+        assert!(row.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_execute_versioned() {
+        let mut conn = connect().await;
+        execute(
+            &mut conn,
+            "INSERT INTO users (id, name, version) VALUES (1, 'foo', 0)",
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let mut sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+        sb.set_version_col(Some(String::from("version")));
+        let stmt = sb.build_update_stmt(
+            &[KV {
+                key: "name",
+                val: PLACEHOLDER,
+            }],
+            &[KV {
+                key: "id",
+                val: PLACEHOLDER,
+            }],
+        );
+
+        let args = [Value::from("bar"), Value::from(1), Value::from(0)];
+        assert_eq!(execute_versioned(&mut conn, &stmt, &args).await.unwrap(), 1);
+        let err = execute_versioned(&mut conn, &stmt, &args)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::StaleVersion));
+        assert_eq!(err.to_string(), "stale version: no rows affected");
+
+        let err = execute_versioned(&mut conn, "UPDATE no_such_tbl SET a = 1", &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Sqlx(_)));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let mut conn = connect().await;
+        let insert = "INSERT INTO users (id, version) VALUES (?, 0)";
+
+        let res: Result<u64, sqlx::Error> = transaction(&mut conn, async |tx| {
+            execute(&mut **tx, insert, &[Value::from(1)]).await
+        })
+        .await;
+        assert_eq!(res.unwrap(), 1);
+
+        // Rolled back on error
+        let res: Result<u64, Error> = transaction(&mut conn, async |tx| {
+            execute(&mut **tx, insert, &[Value::from(2)]).await?;
+            Ok(execute(&mut **tx, insert, &[Value::from(1)]).await?)
+        })
+        .await;
+        assert!(matches!(res, Err(Error::Sqlx(_))));
+
+        let rows = fetch_all(&mut conn, "SELECT id FROM users", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[cfg(feature = "retry")]
+    #[tokio::test]
+    async fn test_transaction_with_retry() {
+        use std::time::Duration;
+
+        use crate::retry::Policy;
+
+        use super::{is_transient, transaction_with_retry};
+
+        sqlx::any::install_default_drivers();
+        let path = std::env::temp_dir().join("sainnhe_common_test_transaction_with_retry.db");
+        let _ = std::fs::remove_file(&path);
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        let mut a = AnyConnection::connect(&url).await.unwrap();
+        let mut b = AnyConnection::connect(&url).await.unwrap();
+        execute(&mut b, "PRAGMA busy_timeout = 0", &[])
+            .await
+            .unwrap();
+        execute(&mut a, "CREATE TABLE t (a INTEGER)", &[])
+            .await
+            .unwrap();
+
+        // The write lock held by `a` makes `b` busy
+        execute(&mut a, "BEGIN IMMEDIATE", &[]).await.unwrap();
+        let e = execute(&mut b, "INSERT INTO t (a) VALUES (1)", &[])
+            .await
+            .unwrap_err();
+        assert!(is_transient(&e));
+        assert!(!is_transient(&sqlx::Error::RowNotFound));
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            execute(&mut a, "COMMIT", &[]).await.unwrap();
+        });
+        let policy = Policy::constant(50, Duration::from_millis(5));
+        let mut attempts = 0;
+        let res = transaction_with_retry(&mut b, &policy, async |tx| {
+            attempts += 1;
+            execute(&mut **tx, "INSERT INTO t (a) VALUES (1)", &[]).await
+        })
+        .await;
+        assert_eq!(res.unwrap(), 1);
+        assert!(attempts > 1);
+        release.await.unwrap();
+
+        // Other errors are not retried
+        let mut attempts = 0;
+        let res = transaction_with_retry(&mut b, &policy, async |tx| {
+            attempts += 1;
+            execute(&mut **tx, "INSERT INTO no_such_tbl (a) VALUES (1)", &[]).await
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(attempts, 1);
+
+        b.close().await.unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_explain() {
+        let mut conn = connect().await;
+        let plan = explain(
+            &mut conn,
+            Type::SQLite,
+            "SELECT name FROM users WHERE id = ?",
+            &[Value::from(1)],
+        )
+        .await
+        .unwrap();
+        assert!(plan.contains("SEARCH users USING INTEGER PRIMARY KEY"));
+        assert!(plan.contains(" | "));
+
+        assert!(
+            explain(&mut conn, Type::SQLite, "SELECT * FROM no_such_tbl", &[])
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_query_builder() {
+        use sqlx::{MySql, Postgres, Sqlite};
+
+        struct TC<'a> {
+            stmt: &'a str,
+            args: Vec<Value>,
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "SELECT * FROM t WHERE a = ? AND b = '?' AND \"c?\" = ?",
+                args: vec![Value::from(1), Value::Null],
+                want_mysql: "SELECT * FROM t WHERE a = ? AND b = '?' AND \"c?\" = ?",
+                want_postgresql: "SELECT * FROM t WHERE a = $1 AND b = '?' AND \"c?\" = $2",
+            },
+            TC {
+                stmt: "UPDATE t SET a = $1, b = '$2' WHERE c = $2",
+                args: vec![Value::from("foo"), Value::from(vec![1_u8])],
+                want_mysql: "UPDATE t SET a = ?, b = '$2' WHERE c = ?",
+                want_postgresql: "UPDATE t SET a = $1, b = '$2' WHERE c = $2",
+            },
+            TC {
+                stmt: "DELETE FROM t",
+                args: vec![],
+                want_mysql: "DELETE FROM t",
+                want_postgresql: "DELETE FROM t",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(
+                query_builder::<MySql>(tc.stmt, &tc.args).unwrap().sql(),
+                tc.want_mysql
+            );
+            assert_eq!(
+                query_builder::<Postgres>(tc.stmt, &tc.args).unwrap().sql(),
+                tc.want_postgresql
+            );
+        }
+
+        // Mismatched arguments
+        let args = [Value::from(1)];
+        let Err(err) = query_builder::<Sqlite>("SELECT ?, ?", &args) else {
+            panic!("expected an error");
+        };
+        assert!(matches!(
+            err,
+            Error::ArgCount {
+                placeholders: 2,
+                args: 1
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "argument count mismatch: 2 placeholders, 1 arguments"
+        );
+        assert!(query_builder::<Sqlite>("SELECT $0", &args).is_err());
+        assert!(query_builder::<Sqlite>("SELECT 1", &args).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_execute() {
+        use sqlx::{Connection, SqliteConnection};
+
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)")
+            .execute(&mut conn)
+            .await
+            .unwrap();
+
+        let sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+        let stmt = sb.build_insert_stmt(&[
+            KV {
+                key: "id",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+        ]);
+        let args = [Value::from(1), Value::from("foo")];
+        for _ in 0..2 {
+            let mut qb = query_builder::<sqlx::Sqlite>(&stmt, &args).unwrap();
+            qb.push(" ON CONFLICT (id) DO UPDATE SET name = ")
+                .push_bind("bar");
+            qb.build().execute(&mut conn).await.unwrap();
+        }
+
+        let name: String = sqlx::query_scalar("SELECT name FROM users WHERE id = 1")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(name, "bar");
+    }
+}
@@ -0,0 +1,291 @@
+//! Streaming export of query results for reports and data dumps.
+//!
+//! Rows are written as soon as they are fetched, so results larger than memory can be exported.
+//! The writer is synchronous, wrap files and sockets in a [`std::io::BufWriter`].
+
+use std::{
+    fmt::{self, Write as _},
+    io,
+};
+
+use futures_util::TryStreamExt;
+use sqlx::{Any, Column, Executor, Row, any::AnyRow};
+
+use crate::db::{Value, audit_log::write_json_str, exec};
+
+/// Errors returned by the export helpers.
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by sqlx.
+    Sqlx(sqlx::Error),
+    /// Error returned by the writer.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sqlx(e) => write!(f, "sqlx error: {}", e),
+            Error::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sqlx(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Format {
+    Csv,
+    Ndjson,
+}
+
+/// Executes a query and writes the rows to `w` as CSV, see RFC 4180.
+///
+/// The first line is the header with the column names, which is omitted if there are no rows.
+/// Fields are quoted when they contain commas, quotes or line breaks,
+/// `NULL` is written as an empty field and binary values are written in hex.
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+/// * `w` - The writer.
+///
+/// # Returns
+///
+/// * The number of rows written.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::export::to_csv;
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let mut out = Vec::new();
+/// let n = to_csv(&mut conn, "SELECT 1 AS id, 'a, b' AS name", &[], &mut out)
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(n, 1);
+/// assert_eq!(out, b"id,name\r\n1,\"a, b\"\r\n");
+/// # });
+/// ```
+pub async fn to_csv<'e, E, W>(executor: E, stmt: &str, args: &[Value], w: W) -> Result<u64, Error>
+where
+    E: Executor<'e, Database = Any>,
+    W: io::Write,
+{
+    export(executor, stmt, args, w, Format::Csv).await
+}
+
+/// Executes a query and writes the rows to `w` as newline-delimited JSON.
+///
+/// Each row is written as an object keyed by the column names.
+/// Non-finite floats are written as `null` and binary values are written as hex strings.
+///
+/// # Arguments
+///
+/// * `executor` - The executor, for example a pool, a connection or a transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+/// * `w` - The writer.
+///
+/// # Returns
+///
+/// * The number of rows written.
+pub async fn to_ndjson<'e, E, W>(
+    executor: E,
+    stmt: &str,
+    args: &[Value],
+    w: W,
+) -> Result<u64, Error>
+where
+    E: Executor<'e, Database = Any>,
+    W: io::Write,
+{
+    export(executor, stmt, args, w, Format::Ndjson).await
+}
+
+async fn export<'e, E, W>(
+    executor: E,
+    stmt: &str,
+    args: &[Value],
+    mut w: W,
+    format: Format,
+) -> Result<u64, Error>
+where
+    E: Executor<'e, Database = Any>,
+    W: io::Write,
+{
+    let mut rows = executor.fetch(exec::query(stmt, args));
+    let mut line = String::new();
+    let mut n = 0;
+    while let Some(row) = rows.try_next().await? {
+        line.clear();
+        if format == Format::Csv && n == 0 {
+            let names = row.columns().iter().map(|c| Some(c.name().to_string()));
+            // Writing to a String never fails.
+            let _ = write_csv_line(&mut line, names);
+        }
+        let vals = (0..row.len())
+            .map(|i| exec::decode_value(&row, i))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Writing to a String never fails.
+        let _ = match format {
+            Format::Csv => write_csv_line(&mut line, vals.iter().map(render)),
+            Format::Ndjson => write_json_line(&mut line, &row, &vals),
+        };
+        w.write_all(line.as_bytes())?;
+        n += 1;
+    }
+    w.flush()?;
+    Ok(n)
+}
+
+fn hex(v: &[u8]) -> String {
+    let mut hex = String::with_capacity(v.len() * 2);
+    for b in v {
+        // Writing to a String never fails.
+        let _ = write!(hex, "{:02x}", b);
+    }
+    hex
+}
+
+/// Renders a value as a CSV field before quoting.
+fn render(val: &Value) -> Option<String> {
+    match val {
+        Value::Null => None,
+        Value::Bool(v) => Some(v.to_string()),
+        Value::Int(v) => Some(v.to_string()),
+        Value::Float(v) => Some(v.to_string()),
+        Value::Text(v) => Some(v.clone()),
+        Value::Bytes(v) => Some(hex(v)),
+    }
+}
+
+fn write_csv_line<W: fmt::Write, I: Iterator<Item = Option<String>>>(
+    w: &mut W,
+    fields: I,
+) -> fmt::Result {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        let Some(field) = field else {
+            continue;
+        };
+        if field.contains([',', '"', '\r', '\n']) {
+            write!(w, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            w.write_str(&field)?;
+        }
+    }
+    w.write_str("\r\n")
+}
+
+fn write_json_line<W: fmt::Write>(w: &mut W, row: &AnyRow, vals: &[Value]) -> fmt::Result {
+    w.write_char('{')?;
+    for (i, (col, val)) in row.columns().iter().zip(vals).enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        write_json_str(w, col.name())?;
+        w.write_char(':')?;
+        match val {
+            Value::Null => w.write_str("null")?,
+            Value::Bool(v) => write!(w, "{}", v)?,
+            Value::Int(v) => write!(w, "{}", v)?,
+            Value::Float(v) if v.is_finite() => write!(w, "{}", v)?,
+            Value::Float(_) => w.write_str("null")?,
+            Value::Text(v) => write_json_str(w, v)?,
+            Value::Bytes(v) => write_json_str(w, &hex(v))?,
+        }
+    }
+    w.write_str("}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{AnyConnection, Connection};
+
+    use crate::db::{Value, exec};
+
+    use super::{to_csv, to_ndjson};
+
+    #[tokio::test]
+    async fn test_export() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        exec::execute(
+            &mut conn,
+            "CREATE TABLE t (id INTEGER, name TEXT, score REAL, data BLOB)",
+            &[],
+        )
+        .await
+        .unwrap();
+        exec::execute(
+            &mut conn,
+            "INSERT INTO t VALUES (1, 'say \"hi\"', 1.5, X'00ff'), (2, NULL, NULL, NULL), (3, 'a\nb', -2.0, X'')",
+            &[],
+        )
+        .await
+        .unwrap();
+
+        let mut out = Vec::new();
+        let n = to_csv(&mut conn, "SELECT * FROM t ORDER BY id", &[], &mut out)
+            .await
+            .unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id,name,score,data\r\n1,\"say \"\"hi\"\"\",1.5,00ff\r\n2,,,\r\n3,\"a\nb\",-2,\r\n"
+        );
+
+        let mut out = Vec::new();
+        let n = to_ndjson(
+            &mut conn,
+            "SELECT * FROM t WHERE id < ? ORDER BY id",
+            &[Value::from(3)],
+            &mut out,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\":1,\"name\":\"say \\\"hi\\\"\",\"score\":1.5,\"data\":\"00ff\"}\n\
+            {\"id\":2,\"name\":null,\"score\":null,\"data\":null}\n"
+        );
+
+        // No header without rows
+        let mut out = Vec::new();
+        let n = to_csv(&mut conn, "SELECT * FROM t WHERE id > 3", &[], &mut out)
+            .await
+            .unwrap();
+        assert_eq!(n, 0);
+        assert!(out.is_empty());
+    }
+}
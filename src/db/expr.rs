@@ -0,0 +1,265 @@
+//! SQL expressions whose syntax differs between database types.
+//!
+//! The expressions are SQL fragments, which can be used as the values of [`Cond`](crate::db::Cond)
+//! and [`KV`](crate::db::KV), in raw conditions and in hand-written statements,
+//! but not as selected columns, since the builders quote column names.
+
+use crate::{
+    db::{Dialect, Feature, Type, UnsupportedFeature, Value, table::write_quoted},
+    page::SortField,
+};
+
+/// Returns the aggregate that concatenates the values of `expr` separated by `sep`,
+/// `GROUP_CONCAT` on MySQL, MariaDB, TiDB and SQLite, and `STRING_AGG` elsewhere.
+///
+/// PostgreSQL and CockroachDB concatenate text only, so cast other types, like `id::text`.
+///
+/// # Arguments
+///
+/// * `dialect` - The database type and version.
+/// * `expr` - The concatenated expression, which is written as is.
+/// * `sep` - The separator, which is quoted as a string literal.
+/// * `order` - The order of the values. If it's empty, the order is unspecified.
+///
+/// # Returns
+///
+/// * The expression, or an error if `order` is not empty and the database doesn't support [`Feature::AggregateOrderBy`].
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{
+///     db::{Dialect, Type, expr::try_string_agg},
+///     page::SortField,
+/// };
+///
+/// let order = [SortField::asc("name")];
+///
+/// assert_eq!(
+///     try_string_agg(Dialect::new(Type::MySQL), "name", ", ", &order).unwrap(),
+///     "GROUP_CONCAT(name ORDER BY `name` ASC SEPARATOR ', ')"
+/// );
+/// assert_eq!(
+///     try_string_agg(Dialect::new(Type::PostgreSQL), "name", ", ", &order).unwrap(),
+///     "STRING_AGG(name, ', ' ORDER BY \"name\" ASC)"
+/// );
+/// assert_eq!(
+///     try_string_agg(Dialect::new(Type::SQLite), "name", ", ", &[]).unwrap(),
+///     "GROUP_CONCAT(name, ', ')"
+/// );
+/// ```
+pub fn try_string_agg(
+    dialect: Dialect,
+    expr: &str,
+    sep: &str,
+    order: &[SortField],
+) -> Result<String, UnsupportedFeature> {
+    if !order.is_empty() {
+        dialect.check(Feature::AggregateOrderBy)?;
+    }
+    let typ = dialect.get_typ();
+    let sep = Value::from(sep).to_literal(typ);
+    let mut s = String::with_capacity(32 + expr.len() + sep.len());
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            s.push_str("GROUP_CONCAT(");
+            s.push_str(expr);
+            push_order_by(&mut s, typ, order);
+            s.push_str(" SEPARATOR ");
+        }
+        Type::SQLite => {
+            s.push_str("GROUP_CONCAT(");
+            s.push_str(expr);
+            s.push_str(", ");
+        }
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => {
+            s.push_str("STRING_AGG(");
+            s.push_str(expr);
+            s.push_str(", ");
+        }
+    }
+    s.push_str(&sep);
+    if !typ.is_mysql_family() {
+        push_order_by(&mut s, typ, order);
+    }
+    s.push(')');
+    Ok(s)
+}
+
+/// A unit of [`now_plus`] and [`now_minus`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Unit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Month,
+    Year,
+}
+
+impl Unit {
+    /// Gets the plural name of the unit, as used in PostgreSQL intervals and SQLite modifiers.
+    fn plural(&self) -> &'static str {
+        match self {
+            Unit::Second => "seconds",
+            Unit::Minute => "minutes",
+            Unit::Hour => "hours",
+            Unit::Day => "days",
+            Unit::Month => "months",
+            Unit::Year => "years",
+        }
+    }
+
+    /// Gets the keyword of the unit in MySQL intervals.
+    fn keyword(&self) -> &'static str {
+        match self {
+            Unit::Second => "SECOND",
+            Unit::Minute => "MINUTE",
+            Unit::Hour => "HOUR",
+            Unit::Day => "DAY",
+            Unit::Month => "MONTH",
+            Unit::Year => "YEAR",
+        }
+    }
+}
+
+/// Returns the current time plus `amount` units, which is negative for the past.
+///
+/// SQLite stores times as text, and the expression returns the text of `CURRENT_TIMESTAMP`,
+/// so it compares correctly with columns defaulting to `CURRENT_TIMESTAMP`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, expr::{Unit, now_plus}};
+///
+/// assert_eq!(now_plus(Type::MySQL, 30, Unit::Minute), "NOW() + INTERVAL 30 MINUTE");
+/// assert_eq!(now_plus(Type::PostgreSQL, 30, Unit::Minute), "NOW() + INTERVAL '30 minutes'");
+/// assert_eq!(now_plus(Type::SQLite, 30, Unit::Minute), "datetime('now', '+30 minutes')");
+/// ```
+pub fn now_plus(typ: Type, amount: i64, unit: Unit) -> String {
+    let sign = if amount < 0 { '-' } else { '+' };
+    let amount = amount.unsigned_abs();
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            format!("NOW() {} INTERVAL {} {}", sign, amount, unit.keyword())
+        }
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => {
+            format!("NOW() {} INTERVAL '{} {}'", sign, amount, unit.plural())
+        }
+        Type::SQLite => format!("datetime('now', '{}{} {}')", sign, amount, unit.plural()),
+    }
+}
+
+/// Returns the current time minus `amount` units, for example the cutoff of a retention policy.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, StmtBuilder, Type, expr::{Unit, now_minus}};
+///
+/// let sb = StmtBuilder::new(String::from("sessions"), Type::PostgreSQL);
+/// let expired = Cond::lt("created_at", &now_minus(Type::PostgreSQL, 7, Unit::Day));
+///
+/// assert_eq!(
+///     sb.build_delete_stmt(&[expired]),
+///     "DELETE FROM sessions WHERE created_at < NOW() - INTERVAL '7 days'"
+/// );
+/// ```
+pub fn now_minus(typ: Type, amount: i64, unit: Unit) -> String {
+    now_plus(typ, amount.saturating_neg(), unit)
+}
+
+/// Pushes ` ORDER BY` with the sort keys, or nothing if there are no keys.
+fn push_order_by(s: &mut String, typ: Type, order: &[SortField]) {
+    for (i, key) in order.iter().enumerate() {
+        s.push_str(if i == 0 { " ORDER BY " } else { ", " });
+        write_quoted(s, typ, &key.col);
+        s.push_str(if key.desc { " DESC" } else { " ASC" });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        db::{Dialect, Feature, Type, UnsupportedFeature, Version},
+        page::SortField,
+    };
+
+    use super::{Unit, now_minus, now_plus, try_string_agg};
+
+    #[test]
+    fn test_try_string_agg() {
+        struct TC {
+            dialect: Dialect,
+            want: Result<&'static str, UnsupportedFeature>,
+        }
+
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::TiDB),
+                want: Ok("GROUP_CONCAT(tag ORDER BY `tag` DESC, `id` ASC SEPARATOR '\\\\')"),
+            },
+            TC {
+                dialect: Dialect::new(Type::SQLite),
+                want: Ok("GROUP_CONCAT(tag, '\\' ORDER BY \"tag\" DESC, \"id\" ASC)"),
+            },
+            TC {
+                dialect: Dialect::new(Type::SQLite).version(Version::new(3, 43, 2)),
+                want: Err(UnsupportedFeature {
+                    feature: Feature::AggregateOrderBy,
+                    typ: Type::SQLite,
+                    required: Some(Version::new(3, 44, 0)),
+                }),
+            },
+            TC {
+                dialect: Dialect::new(Type::DuckDB),
+                want: Ok("STRING_AGG(tag, '\\' ORDER BY \"tag\" DESC, \"id\" ASC)"),
+            },
+        ];
+
+        let order = [SortField::desc("tag"), SortField::asc("id")];
+        for tc in test_cases {
+            assert_eq!(
+                try_string_agg(tc.dialect, "tag", "\\", &order),
+                tc.want.map(str::to_string)
+            );
+        }
+    }
+
+    #[test]
+    fn test_now_plus() {
+        struct TC {
+            typ: Type,
+            want_plus: &'static str,
+            want_minus: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                typ: Type::MariaDB,
+                want_plus: "NOW() - INTERVAL 1 YEAR",
+                want_minus: "NOW() + INTERVAL 1 YEAR",
+            },
+            TC {
+                typ: Type::DuckDB,
+                want_plus: "NOW() - INTERVAL '1 years'",
+                want_minus: "NOW() + INTERVAL '1 years'",
+            },
+            TC {
+                typ: Type::SQLite,
+                want_plus: "datetime('now', '-1 years')",
+                want_minus: "datetime('now', '+1 years')",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(now_plus(tc.typ, -1, Unit::Year), tc.want_plus);
+            assert_eq!(now_minus(tc.typ, -1, Unit::Year), tc.want_minus);
+        }
+        assert_eq!(
+            now_minus(Type::MySQL, i64::MIN, Unit::Second),
+            "NOW() + INTERVAL 9223372036854775807 SECOND"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Data files read and written by DuckDB.
+//!
+//! DuckDB queries files as if they were tables, so [`read`] can be used as the table name of a
+//! [`StmtBuilder`](crate::db::StmtBuilder), and tables or query results are copied from and to files via
+//! [`StmtBuilder::build_copy_from_stmt`](crate::db::StmtBuilder::build_copy_from_stmt) and
+//! [`StmtBuilder::build_copy_to_stmt`](crate::db::StmtBuilder::build_copy_to_stmt). Their `try_`
+//! variants fail for other databases, which have no such `COPY`.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{
+//!     Cond, PLACEHOLDER, StmtBuilder, Type,
+//!     file::{Format, read},
+//! };
+//!
+//! let sb = StmtBuilder::new(read("events/*.parquet", Format::Parquet), Type::DuckDB);
+//! let conds = [Cond::eq("kind", PLACEHOLDER)];
+//!
+//! assert_eq!(
+//!     sb.build_count_stmt(&conds),
+//!     "SELECT COUNT(*) FROM read_parquet('events/*.parquet') WHERE kind = ?"
+//! );
+//! assert_eq!(
+//!     sb.build_copy_to_stmt(&["id", "kind"], &conds, "clicks.csv", Format::Csv),
+//!     "COPY (SELECT \"id\", \"kind\" FROM read_parquet('events/*.parquet') WHERE kind = ?) TO 'clicks.csv' (FORMAT csv)"
+//! );
+//! ```
+
+use std::fmt;
+
+use crate::db::{Type, Value};
+
+/// The format of a data file.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Format {
+    /// Apache Parquet.
+    Parquet,
+    /// Comma-separated values with a header.
+    Csv,
+    /// Newline-delimited JSON.
+    Json,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Parquet => "parquet",
+            Format::Csv => "csv",
+            Format::Json => "json",
+        })
+    }
+}
+
+/// Returns the table function that reads the files matching `path`, like `read_parquet('events/*.parquet')`.
+///
+/// `path` may contain globs and is quoted as a string literal, so it's safe to take from input,
+/// though the database can then read any file it has access to.
+pub fn read(path: &str, format: Format) -> String {
+    format!(
+        "read_{}({})",
+        format,
+        Value::from(path).to_literal(Type::DuckDB)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{BuildError, Cond, Feature, StmtBuilder, Type, UnsupportedFeature};
+
+    use super::{Format, read};
+
+    #[test]
+    fn test_copy_stmt() {
+        struct TC {
+            format: Format,
+            want_to: &'static str,
+            want_from: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                format: Format::Parquet,
+                want_to: "COPY (SELECT * FROM t) TO 'o''brien.parquet' (FORMAT parquet)",
+                want_from: "COPY t (\"a\", \"b\") FROM 'o''brien.parquet' (FORMAT parquet)",
+            },
+            TC {
+                format: Format::Json,
+                want_to: "COPY (SELECT * FROM t) TO 'o''brien.json' (FORMAT json)",
+                want_from: "COPY t (\"a\", \"b\") FROM 'o''brien.json' (FORMAT json)",
+            },
+        ];
+
+        let sb = StmtBuilder::new(String::from("t"), Type::DuckDB);
+        for tc in test_cases {
+            let path = format!("o'brien.{}", tc.format);
+            assert_eq!(
+                sb.build_copy_to_stmt(&[] as &[&str], &[] as &[Cond], &path, tc.format),
+                tc.want_to
+            );
+            assert_eq!(
+                sb.build_copy_from_stmt(&["a", "b"], &path, tc.format),
+                tc.want_from
+            );
+        }
+        assert_eq!(
+            sb.build_copy_from_stmt(&[] as &[&str], "a.csv", Format::Csv),
+            "COPY t FROM 'a.csv' (FORMAT csv)"
+        );
+    }
+
+    #[test]
+    fn test_try_copy_stmt() {
+        let sb = StmtBuilder::new(String::from("t"), Type::DuckDB);
+        assert_eq!(
+            sb.try_build_copy_to_stmt(&["a"], &[] as &[Cond], "a.csv", Format::Csv),
+            Ok(String::from(
+                "COPY (SELECT \"a\" FROM t) TO 'a.csv' (FORMAT csv)"
+            ))
+        );
+        assert_eq!(
+            sb.try_build_copy_from_stmt(&["a"], "a.csv", Format::Csv),
+            Ok(String::from("COPY t (\"a\") FROM 'a.csv' (FORMAT csv)"))
+        );
+
+        for typ in [Type::PostgreSQL, Type::MySQL, Type::SQLite] {
+            let sb = StmtBuilder::new(String::from("t"), typ);
+            let want = Err(BuildError::Unsupported(UnsupportedFeature {
+                feature: Feature::CopyFile,
+                typ,
+                required: None,
+            }));
+            assert_eq!(
+                sb.try_build_copy_to_stmt(&["a"], &[] as &[Cond], "a.csv", Format::Csv),
+                want
+            );
+            assert_eq!(
+                sb.try_build_copy_from_stmt(&["a"], "a.csv", Format::Csv),
+                want
+            );
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        assert_eq!(
+            read("s3://bucket/*.json", Format::Json),
+            "read_json('s3://bucket/*.json')"
+        );
+    }
+}
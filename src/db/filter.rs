@@ -0,0 +1,615 @@
+//! Filter expressions for APIs.
+//!
+//! A [`Schema`] lists the fields that can be filtered, with their value kinds and operators,
+//! and compiles expressions like `age>=18 AND status IN (active, trial)` into a [`Filter`],
+//! whose conditions only contain allowed columns and placeholders bound to typed values.
+//!
+//! The grammar is:
+//!
+//! ```text
+//! expr  := and ("OR" and)*
+//! and   := unary ("AND" unary)*
+//! unary := "NOT" unary | "(" expr ")" | pred
+//! pred  := field ("=" | "!=" | "<>" | "<" | "<=" | ">" | ">=" | "LIKE" | "NOT LIKE") value
+//!        | field ["NOT"] "IN" "(" value ("," value)* ")"
+//!        | field "IS" ["NOT"] "NULL"
+//! value := word | 'quoted' | "quoted"
+//! ```
+//!
+//! Keywords are case-insensitive. Words consist of alphanumerics and `_-.:@+`,
+//! and quotes are escaped by doubling them.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{
+//!     StmtBuilder, Type, Value,
+//!     filter::{Kind, Op, Schema},
+//! };
+//!
+//! let schema = Schema::new()
+//!     .field("age", Kind::Int, &[Op::Eq, Op::Ge, Op::Le])
+//!     .field("status", Kind::Text, &[Op::Eq, Op::In]);
+//!
+//! let filter = schema.parse("age>=18 AND status IN (active, 'trial')").unwrap();
+//! let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+//!
+//! assert_eq!(
+//!     sb.build_query_stmt(&["id"], filter.get_conds()),
+//!     "SELECT \"id\" FROM users WHERE age >= $1 AND status IN ($2, $3)"
+//! );
+//! assert_eq!(
+//!     filter.get_args(),
+//!     &[Value::from(18), Value::from("active"), Value::from("trial")]
+//! );
+//! assert!(schema.parse("password = x").is_err());
+//! assert!(schema.parse("age LIKE '1%'").is_err());
+//! assert!(schema.parse("age = abc").is_err());
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{
+    db::{CmpOp, Cond, Filter, PLACEHOLDER, Value},
+    error::{AppError, Code},
+};
+
+/// The maximum nesting depth of parentheses and `NOT`s.
+const MAX_DEPTH: usize = 16;
+
+/// Errors returned by [`Schema::parse`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The expression is malformed at the byte offset.
+    Syntax(usize),
+    /// The expression is nested too deeply.
+    TooDeep,
+    /// The field is not in the schema.
+    Field(String),
+    /// The operator is not allowed for the field.
+    Operator { field: String, op: Op },
+    /// The value can't be converted to the kind of the field.
+    Value { field: String, val: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax(pos) => write!(f, "syntax error at {}", pos),
+            Error::TooDeep => f.write_str("filter is nested too deeply"),
+            Error::Field(field) => write!(f, "field {} is not filterable", field),
+            Error::Operator { field, op } => {
+                write!(f, "operator {} is not allowed for field {}", op, field)
+            }
+            Error::Value { field, val } => write!(f, "invalid value {} for field {}", val, field),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for AppError {
+    fn from(e: Error) -> Self {
+        AppError::new(Code::InvalidArgument, e.to_string())
+    }
+}
+
+/// Filter operator.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    NotLike,
+    In,
+    NotIn,
+    IsNull,
+    IsNotNull,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Like => "LIKE",
+            Op::NotLike => "NOT LIKE",
+            Op::In => "IN",
+            Op::NotIn => "NOT IN",
+            Op::IsNull => "IS NULL",
+            Op::IsNotNull => "IS NOT NULL",
+        })
+    }
+}
+
+/// The kind of values a field is compared with.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Kind {
+    Text,
+    Int,
+    Float,
+    /// `true` or `false`.
+    Bool,
+}
+
+impl Kind {
+    fn convert(&self, val: &str) -> Option<Value> {
+        match self {
+            Kind::Text => Some(Value::from(val)),
+            Kind::Int => val.parse::<i64>().ok().map(Value::from),
+            Kind::Float => val
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .map(Value::from),
+            Kind::Bool => match val.to_ascii_lowercase().as_str() {
+                "true" => Some(Value::from(true)),
+                "false" => Some(Value::from(false)),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Field {
+    kind: Kind,
+    ops: BTreeSet<Op>,
+}
+
+/// The fields that can be filtered.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Schema {
+    fields: BTreeMap<String, Field>,
+}
+
+impl Schema {
+    /// Creates an empty [`Schema`], which allows nothing.
+    pub fn new() -> Schema {
+        Schema::default()
+    }
+
+    /// Allows filtering the column `name` of `kind` with `ops`.
+    pub fn field(mut self, name: &str, kind: Kind, ops: &[Op]) -> Schema {
+        self.fields.insert(
+            name.to_string(),
+            Field {
+                kind,
+                ops: ops.iter().copied().collect(),
+            },
+        );
+        self
+    }
+
+    /// Returns `true` if the operator `op` is allowed for the field `name`.
+    pub fn is_allowed(&self, name: &str, op: Op) -> bool {
+        self.fields
+            .get(name)
+            .is_some_and(|field| field.ops.contains(&op))
+    }
+
+    /// Parses and compiles a filter expression.
+    ///
+    /// Conditions combined with `AND` at the top level become separate conditions of the [`Filter`].
+    /// A blank expression results in an empty filter.
+    pub fn parse(&self, s: &str) -> Result<Filter, Error> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            schema: self,
+            tokens,
+            pos: 0,
+            end: s.len(),
+            args: Vec::new(),
+        };
+        if parser.tokens.is_empty() {
+            return Ok(Filter::new());
+        }
+        let cond = parser.parse_expr(0)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(Error::Syntax(token.pos));
+        }
+        let mut conds = match cond {
+            Cond::And(conds) => conds,
+            cond => vec![cond],
+        }
+        .into_iter();
+        // The values are bound in the order of the conditions, so they can be added with the first one.
+        Ok(match conds.next() {
+            Some(first) => conds.fold(
+                Filter::new().filter_with(first, parser.args),
+                Filter::filter,
+            ),
+            None => Filter::new(),
+        })
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum Tok {
+    Word(String),
+    Quoted(String),
+    Sym(&'static str),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+struct Token {
+    tok: Tok,
+    pos: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '@' | '+')
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let tok = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Tok::Sym("("),
+            ')' => Tok::Sym(")"),
+            ',' => Tok::Sym(","),
+            '=' => Tok::Sym("="),
+            '!' => match chars.next_if(|(_, c)| *c == '=') {
+                Some(_) => Tok::Sym("!="),
+                None => return Err(Error::Syntax(pos)),
+            },
+            '<' => match chars.next_if(|(_, c)| *c == '=' || *c == '>') {
+                Some((_, '=')) => Tok::Sym("<="),
+                Some(_) => Tok::Sym("!="),
+                None => Tok::Sym("<"),
+            },
+            '>' => match chars.next_if(|(_, c)| *c == '=') {
+                Some(_) => Tok::Sym(">="),
+                None => Tok::Sym(">"),
+            },
+            '\'' | '"' => {
+                let mut val = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, q)) if q == c => match chars.next_if(|(_, q)| *q == c) {
+                            Some(_) => val.push(c),
+                            None => break,
+                        },
+                        Some((_, ch)) => val.push(ch),
+                        None => return Err(Error::Syntax(pos)),
+                    }
+                }
+                Tok::Quoted(val)
+            }
+            c if is_word_char(c) => {
+                let mut word = String::from(c);
+                while let Some((_, ch)) = chars.next_if(|(_, ch)| is_word_char(*ch)) {
+                    word.push(ch);
+                }
+                Tok::Word(word)
+            }
+            _ => return Err(Error::Syntax(pos)),
+        };
+        tokens.push(Token { tok, pos });
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    schema: &'a Schema,
+    tokens: Vec<Token>,
+    pos: usize,
+    end: usize,
+    args: Vec<Value>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|token| &token.tok)
+    }
+
+    /// Gets the byte offset of the current token, which is used in syntax errors.
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |token| token.pos)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let ok = self.is_keyword(keyword);
+        if ok {
+            self.pos += 1;
+        }
+        ok
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(Error::Syntax(self.offset()))
+        }
+    }
+
+    fn eat_sym(&mut self, sym: &str) -> bool {
+        let ok = matches!(self.peek(), Some(Tok::Sym(s)) if *s == sym);
+        if ok {
+            self.pos += 1;
+        }
+        ok
+    }
+
+    fn expect_sym(&mut self, sym: &str) -> Result<(), Error> {
+        if self.eat_sym(sym) {
+            Ok(())
+        } else {
+            Err(Error::Syntax(self.offset()))
+        }
+    }
+
+    fn parse_expr(&mut self, depth: usize) -> Result<Cond, Error> {
+        let mut conds = vec![self.parse_and(depth)?];
+        while self.eat_keyword("OR") {
+            conds.push(self.parse_and(depth)?);
+        }
+        Ok(if conds.len() == 1 {
+            conds.remove(0)
+        } else {
+            Cond::Or(conds)
+        })
+    }
+
+    fn parse_and(&mut self, depth: usize) -> Result<Cond, Error> {
+        let mut conds = vec![self.parse_unary(depth)?];
+        while self.eat_keyword("AND") {
+            conds.push(self.parse_unary(depth)?);
+        }
+        Ok(if conds.len() == 1 {
+            conds.remove(0)
+        } else {
+            Cond::And(conds)
+        })
+    }
+
+    fn parse_unary(&mut self, depth: usize) -> Result<Cond, Error> {
+        if depth >= MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        if self.eat_keyword("NOT") {
+            return Ok(Cond::not(self.parse_unary(depth + 1)?));
+        }
+        if self.eat_sym("(") {
+            let cond = self.parse_expr(depth + 1)?;
+            self.expect_sym(")")?;
+            return Ok(cond);
+        }
+        self.parse_pred()
+    }
+
+    fn parse_pred(&mut self) -> Result<Cond, Error> {
+        let offset = self.offset();
+        let name = match self.peek() {
+            Some(Tok::Word(w)) if is_ident(w) => w.clone(),
+            _ => return Err(Error::Syntax(offset)),
+        };
+        self.pos += 1;
+        let op = self.parse_op()?;
+        let field = self
+            .schema
+            .fields
+            .get(&name)
+            .ok_or_else(|| Error::Field(name.clone()))?;
+        if !field.ops.contains(&op) {
+            return Err(Error::Operator { field: name, op });
+        }
+        let kind = field.kind;
+        let cmp = |op| Cond::Cmp {
+            col: name.clone(),
+            op,
+            val: PLACEHOLDER.to_string(),
+        };
+        let cond = match op {
+            Op::IsNull => Cond::is_null(&name),
+            Op::IsNotNull => Cond::is_not_null(&name),
+            Op::In | Op::NotIn => {
+                self.expect_sym("(")?;
+                let mut n = 0;
+                loop {
+                    self.parse_value(&name, kind)?;
+                    n += 1;
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                self.expect_sym(")")?;
+                let vals = vec![PLACEHOLDER; n];
+                if op == Op::In {
+                    Cond::in_list(&name, &vals)
+                } else {
+                    Cond::not_in_list(&name, &vals)
+                }
+            }
+            _ => {
+                self.parse_value(&name, kind)?;
+                cmp(match op {
+                    Op::Eq => CmpOp::Eq,
+                    Op::Ne => CmpOp::Ne,
+                    Op::Lt => CmpOp::Lt,
+                    Op::Le => CmpOp::Le,
+                    Op::Gt => CmpOp::Gt,
+                    Op::Ge => CmpOp::Ge,
+                    Op::Like => CmpOp::Like,
+                    _ => CmpOp::NotLike,
+                })
+            }
+        };
+        Ok(cond)
+    }
+
+    fn parse_op(&mut self) -> Result<Op, Error> {
+        let offset = self.offset();
+        let op = match self.peek() {
+            Some(Tok::Sym(sym)) => match *sym {
+                "=" => Op::Eq,
+                "!=" => Op::Ne,
+                "<" => Op::Lt,
+                "<=" => Op::Le,
+                ">" => Op::Gt,
+                ">=" => Op::Ge,
+                _ => return Err(Error::Syntax(offset)),
+            },
+            Some(Tok::Word(_)) if self.is_keyword("LIKE") => Op::Like,
+            Some(Tok::Word(_)) if self.is_keyword("IN") => Op::In,
+            Some(Tok::Word(_)) if self.is_keyword("NOT") => {
+                self.pos += 1;
+                if self.is_keyword("LIKE") {
+                    Op::NotLike
+                } else if self.is_keyword("IN") {
+                    Op::NotIn
+                } else {
+                    return Err(Error::Syntax(self.offset()));
+                }
+            }
+            Some(Tok::Word(_)) if self.is_keyword("IS") => {
+                self.pos += 1;
+                let negated = self.eat_keyword("NOT");
+                self.expect_keyword("NULL")?;
+                return Ok(if negated { Op::IsNotNull } else { Op::IsNull });
+            }
+            _ => return Err(Error::Syntax(offset)),
+        };
+        self.pos += 1;
+        Ok(op)
+    }
+
+    fn parse_value(&mut self, name: &str, kind: Kind) -> Result<(), Error> {
+        let val = match self.peek() {
+            Some(Tok::Word(w) | Tok::Quoted(w)) => w.clone(),
+            _ => return Err(Error::Syntax(self.offset())),
+        };
+        self.pos += 1;
+        let val = kind.convert(&val).ok_or_else(|| Error::Value {
+            field: name.to_string(),
+            val,
+        })?;
+        self.args.push(val);
+        Ok(())
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Cond, PLACEHOLDER, Value};
+
+    use super::{Error, Kind, Op, Schema};
+
+    #[test]
+    fn test_parse() {
+        struct TC {
+            s: &'static str,
+            want: Result<(Vec<Cond>, Vec<Value>), Error>,
+        }
+
+        let schema = Schema::new()
+            .field("age", Kind::Int, &[Op::Eq, Op::Gt, Op::NotIn])
+            .field("name", Kind::Text, &[Op::Eq, Op::Like, Op::IsNull])
+            .field("score", Kind::Float, &[Op::Ne])
+            .field("verified", Kind::Bool, &[Op::Eq]);
+        let test_cases = vec![
+            TC {
+                s: "  ",
+                want: Ok((vec![], vec![])),
+            },
+            TC {
+                s: "name = 'O''Brien' and verified = TRUE",
+                want: Ok((
+                    vec![
+                        Cond::eq("name", PLACEHOLDER),
+                        Cond::eq("verified", PLACEHOLDER),
+                    ],
+                    vec![Value::from("O'Brien"), Value::from(true)],
+                )),
+            },
+            TC {
+                s: "age > -1 OR NOT (name LIKE \"a%\" AND name IS NULL) OR score <> 1.5",
+                want: Ok((
+                    vec![Cond::Or(vec![
+                        Cond::gt("age", PLACEHOLDER),
+                        Cond::not(Cond::And(vec![
+                            Cond::like("name", PLACEHOLDER),
+                            Cond::is_null("name"),
+                        ])),
+                        Cond::ne("score", PLACEHOLDER),
+                    ])],
+                    vec![Value::from(-1), Value::from("a%"), Value::from(1.5)],
+                )),
+            },
+            TC {
+                s: "age NOT IN (1,2)",
+                want: Ok((
+                    vec![Cond::not_in_list("age", &[PLACEHOLDER, PLACEHOLDER])],
+                    vec![Value::from(1), Value::from(2)],
+                )),
+            },
+            TC {
+                s: "age = 1 AND",
+                want: Err(Error::Syntax(11)),
+            },
+            TC {
+                s: "age = 1; DROP TABLE users",
+                want: Err(Error::Syntax(7)),
+            },
+            TC {
+                s: "name = 'abc",
+                want: Err(Error::Syntax(7)),
+            },
+            TC {
+                s: "password = x",
+                want: Err(Error::Field(String::from("password"))),
+            },
+            TC {
+                s: "age IN (1)",
+                want: Err(Error::Operator {
+                    field: String::from("age"),
+                    op: Op::In,
+                }),
+            },
+            TC {
+                s: "verified = yes",
+                want: Err(Error::Value {
+                    field: String::from("verified"),
+                    val: String::from("yes"),
+                }),
+            },
+            TC {
+                s: "((((((((((((((((age = 1))))))))))))))))",
+                want: Err(Error::TooDeep),
+            },
+        ];
+
+        for tc in test_cases {
+            let got = schema.parse(tc.s).map(|filter| filter.into_parts());
+            assert_eq!(got, tc.want, "{}", tc.s);
+        }
+    }
+}
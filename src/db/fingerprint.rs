@@ -0,0 +1,242 @@
+use std::ops::Range;
+
+/// Computes the fingerprint of a SQL statement.
+///
+/// The fingerprint is the statement with all literal values replaced by `?`,
+/// so statements that only differ in their values share the same fingerprint.
+/// Specifically:
+///
+///   - String literals like `'foo'` are replaced by `?`.
+///   - Numeric literals like `42` and `1.5` are replaced by `?`.
+///   - PostgreSQL placeholders like `$1` are replaced by `?`.
+///   - Consecutive whitespaces are collapsed into a single space.
+///
+/// Quoted identifiers like `` `foo` `` and `"foo"` are kept as is.
+///
+/// # Arguments
+///
+/// * `stmt` - The SQL statement.
+///
+/// # Returns
+///
+/// * The fingerprint.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::fingerprint;
+///
+/// let fp = fingerprint("SELECT \"name\" FROM users  WHERE age = 20 AND name = 'foo' AND id = $1");
+///
+/// assert_eq!(fp, "SELECT \"name\" FROM users WHERE age = ? AND name = ? AND id = ?");
+/// ```
+pub fn fingerprint(stmt: &str) -> String {
+    fingerprint_with_literals(stmt).0
+}
+
+/// Computes the fingerprint of a SQL statement like [`fingerprint`],
+/// and returns the replaced string and numeric literals in order as well.
+pub(crate) fn fingerprint_with_literals(stmt: &str) -> (String, Vec<String>) {
+    let chars: Vec<char> = stmt.trim().chars().collect();
+    let mut fp = String::with_capacity(stmt.len());
+    let mut literals = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' => {
+                let end = skip_quoted(&chars, i, '\'');
+                literals.push(chars[i..end].iter().collect());
+                i = end;
+                fp.push('?');
+                continue;
+            }
+            '"' | '`' => {
+                let end = skip_quoted(&chars, i, c);
+                fp.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '$' if chars.get(i + 1).is_some_and(char::is_ascii_digit) => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                fp.push('?');
+                continue;
+            }
+            c if c.is_ascii_digit() && !fp.ends_with(is_ident_char) => {
+                let begin = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                literals.push(chars[begin..i].iter().collect());
+                fp.push('?');
+                continue;
+            }
+            c if c.is_whitespace() => {
+                if !fp.ends_with(' ') {
+                    fp.push(' ');
+                }
+            }
+            c => fp.push(c),
+        }
+        i += 1;
+    }
+    (fp, literals)
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Finds the placeholders in a SQL statement, either `?` or `$N`.
+///
+/// Placeholders in quoted literals, quoted identifiers and comments are ignored.
+/// Returns the byte range of each placeholder and the zero-based index of the argument bound to it,
+/// where `$N` is bound to the N-th argument and `?` is bound to the next argument in order.
+pub(crate) fn placeholders(stmt: &str) -> Vec<(Range<usize>, usize)> {
+    let bytes = stmt.as_bytes();
+    let mut found = Vec::new();
+    let (mut i, mut next) = (0, 0);
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' | b'`' => {
+                i = skip_quoted(bytes, i, bytes[i]);
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i += bytes[i..].iter().take_while(|&&b| b != b'\n').count();
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = stmt[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            b'?' => {
+                found.push((i..i + 1, next));
+                next += 1;
+            }
+            b'$' if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                let end = i
+                    + 1
+                    + bytes[i + 1..]
+                        .iter()
+                        .take_while(|b| b.is_ascii_digit())
+                        .count();
+                // `$0` is not a valid placeholder, so it is mapped to an out-of-range index.
+                let n: usize = stmt[i + 1..end].parse().unwrap_or(0);
+                found.push((i..end, n.wrapping_sub(1)));
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    found
+}
+
+/// Returns the index right after the closing quote. Doubled quotes are treated as escaped quotes.
+fn skip_quoted<T: PartialEq>(chars: &[T], begin: usize, quote: T) -> usize {
+    let mut i = begin + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fingerprint;
+
+    #[test]
+    fn test_placeholders() {
+        use super::placeholders;
+
+        assert_eq!(
+            placeholders("SELECT ? FROM t WHERE a = '?' AND \"b?\" = ? AND c = `?`"),
+            vec![(7..8, 0), (41..42, 1)]
+        );
+        assert_eq!(
+            placeholders("UPDATE t SET a = $2, b = '$1' WHERE c = $10 AND d = $0"),
+            vec![(17..19, 1), (40..43, 9), (52..54, usize::MAX)]
+        );
+        assert!(placeholders("SELECT 'it''s ?'").is_empty());
+        assert_eq!(
+            placeholders("-- id?\nSELECT /* ? */ a FROM t WHERE b = ? /* ?"),
+            vec![(41..42, 0)]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        struct TC<'a> {
+            stmt: &'a str,
+            want: &'a str,
+        }
+
+        let test_cases = vec![
+            // String literals
+            TC {
+                stmt: "SELECT * FROM t WHERE a = 'foo' AND b = 'it''s'",
+                want: "SELECT * FROM t WHERE a = ? AND b = ?",
+            },
+            // Numeric literals
+            TC {
+                stmt: "UPDATE t SET a = 20, b = 1.5 WHERE c1 = 3",
+                want: "UPDATE t SET a = ?, b = ? WHERE c1 = ?",
+            },
+            // Placeholders
+            TC {
+                stmt: "INSERT INTO t (a, b) VALUES ($1, $12)",
+                want: "INSERT INTO t (a, b) VALUES (?, ?)",
+            },
+            TC {
+                stmt: "DELETE FROM t WHERE a = ?",
+                want: "DELETE FROM t WHERE a = ?",
+            },
+            // Quoted identifiers
+            TC {
+                stmt: "SELECT `a1`, \"b'2\" FROM t",
+                want: "SELECT `a1`, \"b'2\" FROM t",
+            },
+            // Whitespaces
+            TC {
+                stmt: "  SELECT *\n\tFROM   t  ",
+                want: "SELECT * FROM t",
+            },
+            // Unterminated literal
+            TC {
+                stmt: "SELECT 'foo",
+                want: "SELECT ?",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(fingerprint(tc.stmt), tc.want);
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_with_literals() {
+        use super::fingerprint_with_literals;
+
+        assert_eq!(
+            fingerprint_with_literals("SELECT a1 FROM t WHERE b = 'it''s' AND c > 1.5 AND d = $1"),
+            (
+                String::from("SELECT a1 FROM t WHERE b = ? AND c > ? AND d = ?"),
+                vec![String::from("'it''s'"), String::from("1.5")]
+            )
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Conditions on locations, for example to find the stores near a user.
+//!
+//! PostgreSQL and CockroachDB use PostGIS functions on `geometry` columns with SRID 4326,
+//! and MySQL and MariaDB use spatial functions on `POINT` columns with SRID 0,
+//! whose X is the longitude and Y is the latitude.
+//! SQLite has no spatial functions, so it uses an [R*Tree](https://www.sqlite.org/rtree.html) index of the rows instead,
+//! like `CREATE VIRTUAL TABLE stores_rtree USING rtree(id, min_lng, max_lng, min_lat, max_lat)`.
+//!
+//! The coordinates are written as literals, so that the placeholders of the rest of the statement keep their numbers.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{Dialect, StmtBuilder, Type, geo::{Geo, Point}};
+//!
+//! let near = Geo::new(Dialect::new(Type::PostgreSQL), "location")
+//!     .unwrap()
+//!     .within_radius(Point::new(13.4, 52.52), 500.0);
+//! let sb = StmtBuilder::new(String::from("stores"), Type::PostgreSQL);
+//!
+//! assert_eq!(
+//!     sb.build_query_stmt(&["name"], &[near]),
+//!     "SELECT \"name\" FROM stores WHERE \
+//!      (ST_DWithin(location::geography, ST_SetSRID(ST_MakePoint(13.4, 52.52), 4326)::geography, 500))"
+//! );
+//! ```
+
+use crate::db::{Cond, Dialect, Feature, Type, UnsupportedFeature};
+
+/// The mean radius of the earth in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// A location in degrees.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Point {
+    pub lng: f64,
+    pub lat: f64,
+}
+
+impl Point {
+    /// Creates a new [`Point`] at the longitude `lng` and the latitude `lat`.
+    pub fn new(lng: f64, lat: f64) -> Point {
+        Point { lng, lat }
+    }
+}
+
+/// Where the locations are stored.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+enum Index {
+    /// A spatial column.
+    Col(String),
+    /// An R*Tree table whose `id` column is matched against `id_col`.
+    RTree { tbl: String, id_col: String },
+}
+
+/// Builder of conditions on the locations of a table.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Geo {
+    typ: Type,
+    index: Index,
+}
+
+impl Geo {
+    /// Creates a new [`Geo`] on the spatial column `col`.
+    ///
+    /// # Returns
+    ///
+    /// * The builder, or an error if the database doesn't support [`Feature::Spatial`].
+    ///   Use [`Geo::rtree`] on SQLite.
+    pub fn new(dialect: Dialect, col: &str) -> Result<Geo, UnsupportedFeature> {
+        dialect.check(Feature::Spatial)?;
+        Ok(Geo {
+            typ: dialect.get_typ(),
+            index: Index::Col(col.to_string()),
+        })
+    }
+
+    /// Creates a new [`Geo`] on the SQLite R*Tree table `tbl`,
+    /// whose `id` is the value of the column `id_col` of the row it indexes.
+    ///
+    /// The bounds of the table are the columns `min_lng`, `max_lng`, `min_lat` and `max_lat`,
+    /// which are equal for points.
+    pub fn rtree(tbl: &str, id_col: &str) -> Geo {
+        Geo {
+            typ: Type::SQLite,
+            index: Index::RTree {
+                tbl: tbl.to_string(),
+                id_col: id_col.to_string(),
+            },
+        }
+    }
+
+    /// Returns the condition matching the locations within `meters` of `center`.
+    ///
+    /// The distance is measured on a sphere, except on SQLite, where the condition matches the bounding box
+    /// of the circle and thus a few more locations near its corners, so filter the rows by distance afterwards.
+    pub fn within_radius(&self, center: Point, meters: f64) -> Cond {
+        match (&self.index, self.typ) {
+            (Index::Col(col), Type::PostgreSQL | Type::CockroachDB) => Cond::Raw(format!(
+                "ST_DWithin({}::geography, ST_SetSRID(ST_MakePoint({}, {}), 4326)::geography, {})",
+                col, center.lng, center.lat, meters
+            )),
+            (Index::Col(col), _) => Cond::Raw(format!(
+                "ST_Distance_Sphere({}, POINT({}, {})) <= {}",
+                col, center.lng, center.lat, meters
+            )),
+            (Index::RTree { .. }, _) => {
+                let (sw, ne) = bounding_box(center, meters);
+                self.within_box(sw, ne)
+            }
+        }
+    }
+
+    /// Returns the condition matching the locations within the box from the south-west corner `sw`
+    /// to the north-east corner `ne`, including its edges.
+    pub fn within_box(&self, sw: Point, ne: Point) -> Cond {
+        match &self.index {
+            Index::Col(col) if matches!(self.typ, Type::PostgreSQL | Type::CockroachDB) => {
+                Cond::Raw(format!(
+                    "{} && ST_MakeEnvelope({}, {}, {}, {}, 4326)",
+                    col, sw.lng, sw.lat, ne.lng, ne.lat
+                ))
+            }
+            Index::Col(col) => Cond::Raw(format!(
+                "MBRIntersects(ST_GeomFromText('POLYGON(({0} {1}, {2} {1}, {2} {3}, {0} {3}, {0} {1}))'), {4})",
+                sw.lng, sw.lat, ne.lng, ne.lat, col
+            )),
+            Index::RTree { tbl, id_col } => Cond::Raw(format!(
+                "{} IN (SELECT id FROM {} WHERE min_lng <= {} AND max_lng >= {} AND min_lat <= {} AND max_lat >= {})",
+                id_col, tbl, ne.lng, sw.lng, ne.lat, sw.lat
+            )),
+        }
+    }
+}
+
+/// Returns the south-west and the north-east corners of the box around the circle of `meters` around `center`.
+///
+/// The box spans every longitude if it reaches a pole or crosses the antimeridian.
+fn bounding_box(center: Point, meters: f64) -> (Point, Point) {
+    let d_lat = (meters / EARTH_RADIUS).to_degrees();
+    let min_lat = (center.lat - d_lat).max(-90.0);
+    let max_lat = (center.lat + d_lat).min(90.0);
+    let (mut min_lng, mut max_lng) = (-180.0, 180.0);
+    if min_lat > -90.0 && max_lat < 90.0 {
+        // The circle is widest at the latitude farthest from the equator.
+        let lat = min_lat.abs().max(max_lat.abs()).to_radians();
+        let d_lng = d_lat / lat.cos();
+        if center.lng - d_lng >= -180.0 && center.lng + d_lng <= 180.0 {
+            (min_lng, max_lng) = (center.lng - d_lng, center.lng + d_lng);
+        }
+    }
+    (Point::new(min_lng, min_lat), Point::new(max_lng, max_lat))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Cond, Dialect, Feature, Type, UnsupportedFeature, Version};
+
+    use super::{Geo, Point, bounding_box};
+
+    #[test]
+    fn test_geo() {
+        struct TC {
+            geo: Result<Geo, UnsupportedFeature>,
+            want_radius: &'static str,
+            want_box: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                geo: Geo::new(Dialect::new(Type::CockroachDB), "loc"),
+                want_radius: "ST_DWithin(loc::geography, ST_SetSRID(ST_MakePoint(1.5, -2), 4326)::geography, 100)",
+                want_box: "loc && ST_MakeEnvelope(0, 1, 2, 3, 4326)",
+            },
+            TC {
+                geo: Geo::new(Dialect::new(Type::MariaDB), "loc"),
+                want_radius: "ST_Distance_Sphere(loc, POINT(1.5, -2)) <= 100",
+                want_box: "MBRIntersects(ST_GeomFromText('POLYGON((0 1, 2 1, 2 3, 0 3, 0 1))'), loc)",
+            },
+        ];
+
+        for tc in test_cases {
+            let geo = tc.geo.unwrap();
+            assert_eq!(
+                geo.within_radius(Point::new(1.5, -2.0), 100.0),
+                Cond::Raw(tc.want_radius.to_string())
+            );
+            assert_eq!(
+                geo.within_box(Point::new(0.0, 1.0), Point::new(2.0, 3.0)),
+                Cond::Raw(tc.want_box.to_string())
+            );
+        }
+        assert_eq!(
+            Geo::new(
+                Dialect::new(Type::MySQL).version(Version::new(5, 7, 5)),
+                "loc"
+            ),
+            Err(UnsupportedFeature {
+                feature: Feature::Spatial,
+                typ: Type::MySQL,
+                required: Some(Version::new(5, 7, 6)),
+            })
+        );
+        assert_eq!(
+            Geo::new(Dialect::new(Type::SQLite), "loc")
+                .unwrap_err()
+                .required,
+            None
+        );
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        // 100 meters are about 0.0009 degrees of latitude.
+        let (sw, ne) = bounding_box(Point::new(1.5, -2.0), 100.0);
+        assert!((ne.lat - sw.lat - 0.0018).abs() < 1e-5);
+        assert!(ne.lng - sw.lng > ne.lat - sw.lat);
+        // Near the antimeridian.
+        let (sw, ne) = bounding_box(Point::new(179.99, 0.0), 10_000.0);
+        assert_eq!((sw.lng, ne.lng), (-180.0, 180.0));
+        assert!(sw.lat < 0.0 && ne.lat > 0.0);
+        // Near the pole.
+        let (sw, ne) = bounding_box(Point::new(0.0, 89.99), 10_000.0);
+        assert_eq!((sw.lng, ne.lng, ne.lat), (-180.0, 180.0, 90.0));
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_rtree_sqlite() {
+        use sqlx::{AnyConnection, Connection, Row};
+
+        use crate::db::{StmtBuilder, exec};
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        for stmt in [
+            "CREATE TABLE stores (id INTEGER PRIMARY KEY, name TEXT)",
+            "CREATE VIRTUAL TABLE stores_rtree USING rtree(id, min_lng, max_lng, min_lat, max_lat)",
+            "INSERT INTO stores VALUES (1, 'mitte'), (2, 'potsdam')",
+            "INSERT INTO stores_rtree VALUES (1, 13.40, 13.40, 52.52, 52.52), (2, 13.06, 13.06, 52.39, 52.39)",
+        ] {
+            exec::execute(&mut conn, stmt, &[]).await.unwrap();
+        }
+        let near = Geo::rtree("stores_rtree", "stores.id")
+            .within_radius(Point::new(13.41, 52.52), 1_000.0);
+        let stmt = StmtBuilder::new(String::from("stores"), Type::SQLite)
+            .build_query_stmt(&["name"], &[near]);
+        let names: Vec<String> = exec::fetch_all(&mut conn, &stmt, &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(names, vec![String::from("mitte")]);
+    }
+}
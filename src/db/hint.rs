@@ -0,0 +1,165 @@
+use std::{fmt, time::Duration};
+
+use crate::db::Type;
+
+/// An optimizer hint attached to the statements built by [`StmtBuilder`](crate::db::StmtBuilder),
+/// see [`StmtBuilder::set_hints`](crate::db::StmtBuilder::set_hints).
+///
+/// Hints are written in the syntax of the database type:
+///
+///   - MySQL, MariaDB and TiDB: an optimizer hint comment after the leading keyword, like `SELECT /*+ INDEX(t idx) */ ...`.
+///   - PostgreSQL: a [`pg_hint_plan`](https://github.com/ossc-db/pg_hint_plan) comment before the statement,
+///     which is an ordinary comment if the extension is not loaded.
+///   - SQLite: `INDEXED BY` after the table name.
+///
+/// Hints unsupported by the database type are skipped, just like the optimizers ignore hints they can't apply.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub enum Hint {
+    /// Use the index, on every database type but CockroachDB and DuckDB.
+    Index(String),
+    /// Don't use the index, on MySQL, MariaDB and TiDB.
+    NoIndex(String),
+    /// Join the tables in the order they are listed, on MySQL, MariaDB and TiDB.
+    StraightJoin,
+    /// Abort the statement after the duration, on MySQL, TiDB and PostgreSQL.
+    MaxExecutionTime(Duration),
+    /// A hint written into the hint comment as is, on MySQL, MariaDB, TiDB and PostgreSQL.
+    Raw(String),
+}
+
+/// Writes the hints placed before the statement, that is the `pg_hint_plan` comment.
+pub(crate) fn write_prefix<W: fmt::Write>(
+    w: &mut W,
+    typ: Type,
+    tbl: &str,
+    hints: &[Hint],
+) -> fmt::Result {
+    if typ != Type::PostgreSQL {
+        return Ok(());
+    }
+    let items = hints.iter().filter_map(|hint| match hint {
+        Hint::Index(idx) => Some(format!("IndexScan({} {})", tbl, idx)),
+        Hint::MaxExecutionTime(d) => {
+            Some(format!("Set(statement_timeout \"{}ms\")", d.as_millis()))
+        }
+        Hint::Raw(raw) => Some(raw.clone()),
+        Hint::NoIndex(_) | Hint::StraightJoin => None,
+    });
+    write_comment(w, items)
+}
+
+/// Writes the hints placed right after the leading keyword, that is the optimizer hint comment
+/// and the `STRAIGHT_JOIN` modifier of `SELECT`.
+pub(crate) fn write_after_keyword<W: fmt::Write>(
+    w: &mut W,
+    typ: Type,
+    tbl: &str,
+    hints: &[Hint],
+    select: bool,
+) -> fmt::Result {
+    if !typ.is_mysql_family() {
+        return Ok(());
+    }
+    let items = hints.iter().filter_map(|hint| match (hint, typ) {
+        (Hint::Index(idx), Type::TiDB) => Some(format!("USE_INDEX({}, {})", tbl, idx)),
+        (Hint::Index(idx), _) => Some(format!("INDEX({} {})", tbl, idx)),
+        (Hint::NoIndex(idx), Type::TiDB) => Some(format!("IGNORE_INDEX({}, {})", tbl, idx)),
+        (Hint::NoIndex(idx), _) => Some(format!("NO_INDEX({} {})", tbl, idx)),
+        // MariaDB uses the max_statement_time variable instead.
+        (Hint::MaxExecutionTime(_), Type::MariaDB) => None,
+        (Hint::MaxExecutionTime(d), _) => Some(format!("MAX_EXECUTION_TIME({})", d.as_millis())),
+        (Hint::Raw(raw), _) => Some(raw.clone()),
+        (Hint::StraightJoin, _) => None,
+    });
+    write_comment(w, items)?;
+    if select && hints.contains(&Hint::StraightJoin) {
+        w.write_str("STRAIGHT_JOIN ")?;
+    }
+    Ok(())
+}
+
+/// Writes the hints placed after the table name, that is `INDEXED BY`.
+pub(crate) fn write_after_tbl<W: fmt::Write>(w: &mut W, typ: Type, hints: &[Hint]) -> fmt::Result {
+    if typ != Type::SQLite {
+        return Ok(());
+    }
+    // SQLite accepts a single index.
+    match hints.iter().find_map(|hint| match hint {
+        Hint::Index(idx) => Some(idx),
+        _ => None,
+    }) {
+        Some(idx) => write!(w, " INDEXED BY {}", idx),
+        None => Ok(()),
+    }
+}
+
+/// Writes `/*+ items */ `, or nothing if there are no items.
+fn write_comment<W: fmt::Write, I: Iterator<Item = String>>(w: &mut W, items: I) -> fmt::Result {
+    let mut empty = true;
+    for item in items {
+        w.write_str(if empty { "/*+ " } else { " " })?;
+        // Closing the comment early would let the rest of a raw hint run as SQL.
+        w.write_str(item.replace("*/", "").trim())?;
+        empty = false;
+    }
+    if empty { Ok(()) } else { w.write_str(" */ ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Cond, PLACEHOLDER, StmtBuilder, TenantScope, Type};
+
+    use super::Hint;
+
+    #[test]
+    fn test_hints() {
+        struct TC {
+            typ: Type,
+            want_query: &'static str,
+            want_delete: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                typ: Type::MySQL,
+                want_query: "SELECT /*+ INDEX(t idx_a) NO_INDEX(t idx_b) BKA(t) */ STRAIGHT_JOIN * FROM s.t WHERE a = ?",
+                want_delete: "DELETE /*+ INDEX(t idx_a) NO_INDEX(t idx_b) BKA(t) */ FROM s.t WHERE a = ?",
+            },
+            TC {
+                typ: Type::TiDB,
+                want_query: "SELECT /*+ USE_INDEX(t, idx_a) IGNORE_INDEX(t, idx_b) BKA(t) */ STRAIGHT_JOIN * FROM s.t WHERE a = ?",
+                want_delete: "DELETE /*+ USE_INDEX(t, idx_a) IGNORE_INDEX(t, idx_b) BKA(t) */ FROM s.t WHERE a = ?",
+            },
+            TC {
+                typ: Type::PostgreSQL,
+                want_query: "/*+ IndexScan(t idx_a) BKA(t) */ SELECT * FROM s.t WHERE a = $1",
+                want_delete: "/*+ IndexScan(t idx_a) BKA(t) */ DELETE FROM s.t WHERE a = $1",
+            },
+            TC {
+                typ: Type::SQLite,
+                want_query: "SELECT * FROM s.t INDEXED BY idx_a WHERE a = ?",
+                want_delete: "DELETE FROM s.t INDEXED BY idx_a WHERE a = ?",
+            },
+            TC {
+                typ: Type::DuckDB,
+                want_query: "SELECT * FROM s.t WHERE a = ?",
+                want_delete: "DELETE FROM s.t WHERE a = ?",
+            },
+        ];
+
+        let conds = [Cond::eq("a", PLACEHOLDER)];
+        for tc in test_cases {
+            let mut sb = StmtBuilder::new(String::from("t"), tc.typ);
+            sb.set_tenant(Some(TenantScope::Schema(String::from("s"))));
+            sb.set_hints(vec![
+                Hint::Index(String::from("idx_a")),
+                Hint::NoIndex(String::from("idx_b")),
+                Hint::StraightJoin,
+                // The comment can't be closed early.
+                Hint::Raw(String::from("BKA(t) */")),
+            ]);
+            assert_eq!(sb.build_query_stmt(&[] as &[&str], &conds), tc.want_query);
+            assert_eq!(sb.build_delete_stmt(&conds), tc.want_delete);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use crate::db::Cond;
+
+/// The kind of statement built by [`StmtBuilder`](crate::db::StmtBuilder).
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum StmtKind {
+    /// `INSERT`, including batch inserts.
+    Insert,
+    /// `INSERT` that updates the row on conflict.
+    Upsert,
+    /// `SELECT`.
+    Query,
+    /// `SELECT COUNT(*)`.
+    Count,
+    /// `UPDATE`.
+    Update,
+    /// `DELETE`.
+    Delete,
+}
+
+/// A statement passed to the hooks registered via [`StmtBuilder::on_build`](crate::db::StmtBuilder::on_build).
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Statement {
+    /// The kind of statement.
+    pub kind: StmtKind,
+    /// The table name.
+    pub tbl: String,
+    /// The SQL statement, which hooks may modify.
+    pub sql: String,
+}
+
+type PreHook = Arc<dyn Fn(StmtKind, &mut Vec<Cond>) + Send + Sync>;
+type PostHook = Arc<dyn Fn(&mut Statement) + Send + Sync>;
+
+/// Hooks of a [`StmtBuilder`](crate::db::StmtBuilder), which are compared and hashed by identity.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) pre: Vec<PreHook>,
+    pub(crate) post: Vec<PostHook>,
+}
+
+impl Hooks {
+    /// Collects the conditions added by the pre-build hooks.
+    pub(crate) fn conds(&self, kind: StmtKind) -> Vec<Cond> {
+        let mut conds = Vec::new();
+        for hook in &self.pre {
+            hook(kind, &mut conds);
+        }
+        conds
+    }
+
+    /// Runs the post-build hooks on the statement.
+    pub(crate) fn finish(&self, kind: StmtKind, tbl: &str, sql: String) -> String {
+        if self.post.is_empty() {
+            return sql;
+        }
+        let mut stmt = Statement {
+            kind,
+            tbl: tbl.to_string(),
+            sql,
+        };
+        for hook in &self.post {
+            hook(&mut stmt);
+        }
+        stmt.sql
+    }
+
+    fn ptrs(&self) -> impl Iterator<Item = *const ()> + '_ {
+        let pre = self.pre.iter().map(|hook| Arc::as_ptr(hook) as *const ());
+        let post = self.post.iter().map(|hook| Arc::as_ptr(hook) as *const ());
+        pre.chain(post)
+    }
+}
+
+impl PartialEq for Hooks {
+    fn eq(&self, other: &Self) -> bool {
+        self.pre.len() == other.pre.len()
+            && self.post.len() == other.post.len()
+            && self.ptrs().eq(other.ptrs())
+    }
+}
+
+impl Eq for Hooks {}
+
+impl Hash for Hooks {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pre.len().hash(state);
+        for ptr in self.ptrs() {
+            ptr.hash(state);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("pre", &self.pre.len())
+            .field("post", &self.post.len())
+            .finish()
+    }
+}
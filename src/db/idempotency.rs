@@ -0,0 +1,234 @@
+//! Deduplication of retried requests by idempotency keys, stored in the primary database.
+//!
+//! A handler first calls [`Store::check_and_record`] with the key sent by the client.
+//! If the key is new, the handler processes the request and saves the response via [`Store::store_response`],
+//! so that retries get the same response instead of processing the request again.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sqlx::{AnyConnection, Row};
+
+use crate::db::{Cond, KV, PLACEHOLDER, StmtBuilder, Type, Value, exec, unsupported_dialect};
+
+/// The state of an idempotency key, returned by [`Store::check_and_record`].
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub enum Status {
+    /// The key is recorded for the first time, so the request should be processed.
+    New,
+    /// The key is recorded but no response is stored yet,
+    /// which means the first request is still being processed or has failed.
+    InProgress,
+    /// The response of the first request.
+    Completed(String),
+}
+
+/// A table of idempotency keys.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::db::{
+///     Type,
+///     exec::execute,
+///     idempotency::{Status, Store},
+/// };
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let store = Store::new(String::from("idempotency_keys"), Type::SQLite);
+/// execute(&mut conn, &store.ddl().unwrap(), &[]).await.unwrap();
+///
+/// let ttl = Duration::from_secs(86400);
+/// let status = store.check_and_record(&mut conn, "req-1", ttl).await.unwrap();
+/// assert_eq!(status, Status::New);
+///
+/// store.store_response(&mut conn, "req-1", "201 Created").await.unwrap();
+/// let status = store.check_and_record(&mut conn, "req-1", ttl).await.unwrap();
+/// assert_eq!(status, Status::Completed(String::from("201 Created")));
+/// # });
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Store {
+    sb: StmtBuilder,
+}
+
+impl Store {
+    /// Creates a new [`Store`], where `tbl` is the table name and `typ` is the database type.
+    pub fn new(tbl: String, typ: Type) -> Store {
+        Store {
+            sb: StmtBuilder::new(tbl, typ),
+        }
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        self.sb.get_tbl()
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        self.sb.get_typ()
+    }
+
+    /// Generates the statement that creates the table if it doesn't exist.
+    ///
+    /// Expiration times are Unix timestamps in seconds, so that no date functions of the database are needed.
+    /// Returns an error on DuckDB, which sqlx has no driver for.
+    pub fn ddl(&self) -> Result<String, sqlx::Error> {
+        let key = match self.get_typ() {
+            Type::MySQL | Type::MariaDB | Type::TiDB => "VARCHAR(255)",
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite => "TEXT",
+            Type::DuckDB => return Err(unsupported_dialect(Type::DuckDB)),
+        };
+        Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} (idempotency_key {} PRIMARY KEY, response TEXT, expires_at BIGINT NOT NULL)",
+            self.get_tbl(),
+            key
+        ))
+    }
+
+    /// Records `key` if it's not recorded yet or has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The connection.
+    /// * `key` - The idempotency key sent by the client.
+    /// * `ttl` - How long the key is kept, after which the same key is treated as a new request.
+    ///
+    /// # Returns
+    ///
+    /// * The state of the key.
+    pub async fn check_and_record(
+        &self,
+        conn: &mut AnyConnection,
+        key: &str,
+        ttl: Duration,
+    ) -> Result<Status, sqlx::Error> {
+        let now = unix_now();
+        let key_cond = Cond::eq("idempotency_key", PLACEHOLDER);
+
+        let stmt = self
+            .sb
+            .build_delete_stmt(&[key_cond.clone(), Cond::le("expires_at", PLACEHOLDER)]);
+        exec::execute(&mut *conn, &stmt, &[Value::from(key), Value::from(now)]).await?;
+
+        let cols = [
+            KV {
+                key: "idempotency_key",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "expires_at",
+                val: PLACEHOLDER,
+            },
+        ];
+        // The primary key is the only constraint, so any conflict means the key is recorded.
+        let stmt = self.sb.build_upsert_stmt::<_, &str>(&cols, &[]);
+        let expires_at = now.saturating_add(i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX));
+        let args = [Value::from(key), Value::from(expires_at)];
+        if exec::execute(&mut *conn, &stmt, &args).await? == 1 {
+            return Ok(Status::New);
+        }
+
+        let stmt = self.sb.build_query_stmt(&["response"], &[key_cond]);
+        let row = exec::fetch_optional(&mut *conn, &stmt, &[Value::from(key)]).await?;
+        match row
+            .map(|row| row.try_get::<Option<String>, _>(0))
+            .transpose()?
+        {
+            Some(Some(response)) => Ok(Status::Completed(response)),
+            // The row may have been deleted concurrently, which is also a request in flight.
+            Some(None) | None => Ok(Status::InProgress),
+        }
+    }
+
+    /// Stores the response of the request identified by `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The connection.
+    /// * `key` - The idempotency key.
+    /// * `payload` - The response, usually serialized as JSON.
+    pub async fn store_response(
+        &self,
+        conn: &mut AnyConnection,
+        key: &str,
+        payload: &str,
+    ) -> Result<(), sqlx::Error> {
+        let stmt = self.sb.build_update_stmt(
+            &[KV {
+                key: "response",
+                val: PLACEHOLDER,
+            }],
+            &[Cond::eq("idempotency_key", PLACEHOLDER)],
+        );
+        exec::execute(conn, &stmt, &[Value::from(payload), Value::from(key)]).await?;
+        Ok(())
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| i64::try_from(d.as_secs()).unwrap_or(i64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::{AnyConnection, Connection};
+
+    use crate::db::{Type, exec};
+
+    use super::{Status, Store};
+
+    #[tokio::test]
+    async fn test_check_and_record() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let store = Store::new(String::from("idempotency_keys"), Type::SQLite);
+        exec::execute(&mut conn, &store.ddl().unwrap(), &[])
+            .await
+            .unwrap();
+        let ttl = Duration::from_secs(60);
+
+        let check = async |conn: &mut AnyConnection, key, ttl| {
+            store.check_and_record(conn, key, ttl).await.unwrap()
+        };
+        assert_eq!(check(&mut conn, "a", ttl).await, Status::New);
+        assert_eq!(check(&mut conn, "a", ttl).await, Status::InProgress);
+        assert_eq!(check(&mut conn, "b", ttl).await, Status::New);
+
+        store.store_response(&mut conn, "a", "ok").await.unwrap();
+        assert_eq!(
+            check(&mut conn, "a", ttl).await,
+            Status::Completed(String::from("ok"))
+        );
+
+        // Expired keys are recorded again
+        assert_eq!(check(&mut conn, "c", Duration::ZERO).await, Status::New);
+        assert_eq!(check(&mut conn, "c", ttl).await, Status::New);
+        assert_eq!(check(&mut conn, "c", ttl).await, Status::InProgress);
+    }
+
+    #[test]
+    fn test_ddl() {
+        let store = Store::new(String::from("keys"), Type::MySQL);
+        assert_eq!(store.get_tbl(), "keys");
+        assert_eq!(store.get_typ(), &Type::MySQL);
+        assert!(
+            store
+                .ddl()
+                .unwrap()
+                .contains("idempotency_key VARCHAR(255) PRIMARY KEY")
+        );
+
+        let store = Store::new(String::from("keys"), Type::DuckDB);
+        assert!(matches!(store.ddl(), Err(sqlx::Error::Configuration(_))));
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    ptr,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{
+    db::{
+        CmpOp, Cond, CondRef, Type,
+        table::{shorten_ident, write_quoted},
+    },
+    page::SortField,
+};
+
+/// The columns a statement filters and sorts a table by.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+struct Shape {
+    tbl: String,
+    /// The columns compared for equality, sorted.
+    eq_cols: Vec<String>,
+    /// The columns compared by range, sorted.
+    range_cols: Vec<String>,
+    /// The sort columns, in order.
+    order_cols: Vec<String>,
+}
+
+impl Shape {
+    /// Returns the columns of the index serving the shape, following the equality, sort, range rule.
+    fn index_cols(&self) -> Vec<String> {
+        let mut cols = self.eq_cols.clone();
+        for col in self.order_cols.iter().chain(self.range_cols.first()) {
+            if !cols.contains(col) {
+                cols.push(col.clone());
+            }
+        }
+        cols
+    }
+}
+
+/// Collects the columns of the conditions that an index can serve, that is the conditions combined with `AND`.
+fn collect_cols(cond: &Cond, eq_cols: &mut Vec<String>, range_cols: &mut Vec<String>) {
+    match cond {
+        Cond::Cmp { col, op, .. } => match op {
+            CmpOp::Eq => eq_cols.push(col.clone()),
+            CmpOp::Lt | CmpOp::Le | CmpOp::Gt | CmpOp::Ge | CmpOp::Like => {
+                range_cols.push(col.clone())
+            }
+            CmpOp::Ne | CmpOp::NotLike => {}
+        },
+        Cond::In {
+            col,
+            negated: false,
+            ..
+        }
+        | Cond::IsNull {
+            col,
+            negated: false,
+        } => eq_cols.push(col.clone()),
+        Cond::IsNull { col, negated: true } => range_cols.push(col.clone()),
+        Cond::Tuple {
+            cols,
+            negated: false,
+            ..
+        } => eq_cols.extend(cols.iter().cloned()),
+        Cond::And(conds) => {
+            for cond in conds {
+                collect_cols(cond, eq_cols, range_cols);
+            }
+        }
+        // Expressions on the columns, alternatives and raw fragments can't use plain indexes.
+        _ => {}
+    }
+}
+
+/// A suggested index, see [`IndexAdvisor::suggest`].
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct IndexSuggestion {
+    /// The table name.
+    pub tbl: String,
+    /// The indexed columns, in order.
+    pub cols: Vec<String>,
+    /// The number of built statements the index serves.
+    pub builds: u64,
+    /// The `CREATE INDEX` statement.
+    pub stmt: String,
+}
+
+/// Opt-in recorder of the columns that statements filter and sort tables by,
+/// which suggests the indexes serving them, for example to find missing indexes from real query shapes.
+///
+/// Attach the advisor to builders via [`StmtBuilder::set_index_advisor`](crate::db::StmtBuilder::set_index_advisor),
+/// run the workload, and compare the suggestions with the existing indexes.
+/// Queries, counts, updates and deletes are recorded by the `build_*_stmt` methods,
+/// including the tenant condition but not the conditions added by hooks,
+/// and by [`StmtCache`](crate::db::StmtCache) on cache hits too.
+///
+/// The index of a statement starts with the columns compared for equality, followed by the sort columns,
+/// and by the first column compared by range, and indexes that prefix others are merged into them.
+/// The suggestions are a starting point, since the advisor knows neither the data nor the existing indexes.
+///
+/// Advisors are compared and hashed by identity.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use sainnhe_common::db::{Cond, IndexAdvisor, OrderBy, PLACEHOLDER, Policy, StmtBuilder, Type};
+///
+/// let advisor = Arc::new(IndexAdvisor::new());
+/// let mut sb = StmtBuilder::new(String::from("orders"), Type::PostgreSQL);
+/// sb.set_index_advisor(Some(advisor.clone()));
+///
+/// let policy = Policy::new().allow("orders", &["created_at"]);
+/// let order = OrderBy::parse("-created_at", "orders", &policy).unwrap();
+/// let conds = [Cond::eq("user_id", PLACEHOLDER), Cond::gt("total", PLACEHOLDER)];
+/// sb.build_ordered_query_stmt(&["id"], &conds, &order);
+/// sb.build_count_stmt(&[Cond::eq("user_id", PLACEHOLDER)]);
+///
+/// let suggestions = advisor.suggest(Type::PostgreSQL);
+/// assert_eq!(suggestions.len(), 1);
+/// assert_eq!(suggestions[0].builds, 2);
+/// assert_eq!(
+///     suggestions[0].stmt,
+///     "CREATE INDEX idx_orders_user_id_created_at_total ON orders (\"user_id\", \"created_at\", \"total\")"
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct IndexAdvisor {
+    shapes: Mutex<HashMap<Shape, u64>>,
+}
+
+impl IndexAdvisor {
+    /// Creates a new [`IndexAdvisor`] without records.
+    pub fn new() -> IndexAdvisor {
+        IndexAdvisor::default()
+    }
+
+    /// Gets the number of recorded shapes.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if nothing is recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all records.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Records a statement on the table `tbl` with the conditions `conds` and the sort keys `order`.
+    pub(crate) fn record<'a, I>(&self, tbl: &str, conds: I, order: &[SortField])
+    where
+        I: IntoIterator<Item = CondRef<'a>>,
+    {
+        let (mut eq_cols, mut range_cols) = (Vec::new(), Vec::new());
+        for cond in conds {
+            match cond {
+                CondRef::Eq(kv) => eq_cols.push(kv.key.to_string()),
+                CondRef::Tree(cond) => collect_cols(cond, &mut eq_cols, &mut range_cols),
+            }
+        }
+        eq_cols.sort();
+        eq_cols.dedup();
+        range_cols.sort();
+        range_cols.dedup();
+        range_cols.retain(|col| !eq_cols.contains(col));
+        let shape = Shape {
+            tbl: tbl.to_string(),
+            eq_cols,
+            range_cols,
+            order_cols: order.iter().map(|key| key.col.clone()).collect(),
+        };
+        *self.lock().entry(shape).or_default() += 1;
+    }
+
+    /// Suggests the indexes serving the recorded statements, most used first.
+    ///
+    /// # Arguments
+    ///
+    /// * `typ` - The database type, which determines how the columns are quoted.
+    ///
+    /// # Returns
+    ///
+    /// * The suggestions, named like `idx_orders_user_id`, after the table without its schema and the columns,
+    ///   and shortened with a hash if they're too long, see [`TableRef::max_ident_len`](crate::db::TableRef::max_ident_len).
+    pub fn suggest(&self, typ: Type) -> Vec<IndexSuggestion> {
+        let mut indexes: HashMap<(String, Vec<String>), u64> = HashMap::new();
+        for (shape, builds) in self.lock().iter() {
+            let cols = shape.index_cols();
+            if !cols.is_empty() {
+                *indexes.entry((shape.tbl.clone(), cols)).or_default() += builds;
+            }
+        }
+        let mut indexes: Vec<_> = indexes.into_iter().collect();
+        // Longer indexes first, so that each index is merged into the first index it prefixes.
+        indexes.sort_by(|((a_tbl, a_cols), _), ((b_tbl, b_cols), _)| {
+            (a_tbl, b_cols.len(), a_cols).cmp(&(b_tbl, a_cols.len(), b_cols))
+        });
+        let mut merged: Vec<((String, Vec<String>), u64)> = Vec::with_capacity(indexes.len());
+        for ((tbl, cols), builds) in indexes {
+            let covering = merged
+                .iter_mut()
+                .find(|((t, c), _)| *t == tbl && c.starts_with(&cols));
+            match covering {
+                Some((_, total)) => *total += builds,
+                None => merged.push(((tbl, cols), builds)),
+            }
+        }
+        merged.sort_by(|((a_tbl, a_cols), a), ((b_tbl, b_cols), b)| {
+            (b, a_tbl, a_cols).cmp(&(a, b_tbl, b_cols))
+        });
+        merged
+            .into_iter()
+            .map(|((tbl, cols), builds)| IndexSuggestion {
+                stmt: create_index_stmt(typ, &tbl, &cols),
+                tbl,
+                cols,
+                builds,
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Shape, u64>> {
+        self.shapes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PartialEq for IndexAdvisor {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for IndexAdvisor {}
+
+impl Hash for IndexAdvisor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        ptr::hash(self, state);
+    }
+}
+
+/// Returns the `CREATE INDEX` statement of the columns `cols` of the table `tbl`.
+fn create_index_stmt(typ: Type, tbl: &str, cols: &[String]) -> String {
+    let name = tbl.rsplit('.').next().unwrap_or_default();
+    let name = shorten_ident(typ, &format!("idx_{}_{}", name, cols.join("_")));
+    let mut stmt = format!("CREATE INDEX {} ON {} (", name, tbl);
+    for (i, col) in cols.iter().enumerate() {
+        if i > 0 {
+            stmt.push_str(", ");
+        }
+        write_quoted(&mut stmt, typ, col);
+    }
+    stmt.push(')');
+    stmt
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        db::{AsCond, Cond, KV, PLACEHOLDER, Type},
+        page::SortField,
+    };
+
+    use super::IndexAdvisor;
+
+    #[test]
+    fn test_suggest() {
+        struct TC {
+            tbl: &'static str,
+            conds: Vec<Cond>,
+            order: Vec<SortField>,
+        }
+
+        let test_cases = vec![
+            TC {
+                tbl: "shop.orders",
+                conds: vec![
+                    Cond::eq("user_id", PLACEHOLDER),
+                    Cond::ge("created_at", PLACEHOLDER),
+                ],
+                order: vec![],
+            },
+            TC {
+                tbl: "shop.orders",
+                conds: vec![Cond::And(vec![
+                    Cond::is_null("deleted_at"),
+                    Cond::eq("user_id", PLACEHOLDER),
+                ])],
+                order: vec![SortField::desc("id")],
+            },
+            // Served by the index of the former case.
+            TC {
+                tbl: "shop.orders",
+                conds: vec![
+                    Cond::eq("user_id", PLACEHOLDER),
+                    Cond::is_null("deleted_at"),
+                ],
+                order: vec![],
+            },
+            TC {
+                tbl: "users",
+                conds: vec![
+                    Cond::like("name", PLACEHOLDER),
+                    Cond::gt("age", PLACEHOLDER),
+                ],
+                order: vec![],
+            },
+            // Nothing to index.
+            TC {
+                tbl: "users",
+                conds: vec![
+                    Cond::ne("name", PLACEHOLDER),
+                    Cond::Or(vec![
+                        Cond::eq("id", PLACEHOLDER),
+                        Cond::eq("age", PLACEHOLDER),
+                    ]),
+                    Cond::raw("LENGTH(name) > 3"),
+                ],
+                order: vec![],
+            },
+        ];
+
+        let advisor = IndexAdvisor::new();
+        for tc in &test_cases {
+            advisor.record(tc.tbl, tc.conds.iter().map(AsCond::as_cond), &tc.order);
+        }
+        advisor.record(
+            "shop.orders",
+            [KV {
+                key: "user_id",
+                val: PLACEHOLDER,
+            }]
+            .iter()
+            .map(AsCond::as_cond),
+            &[],
+        );
+        assert_eq!(advisor.len(), 6);
+        let suggestions: Vec<_> = advisor
+            .suggest(Type::MySQL)
+            .into_iter()
+            .map(|s| (s.stmt, s.builds))
+            .collect();
+        assert_eq!(
+            suggestions,
+            vec![
+                (
+                    String::from(
+                        "CREATE INDEX idx_orders_deleted_at_user_id_id ON shop.orders (`deleted_at`, `user_id`, `id`)"
+                    ),
+                    2
+                ),
+                (
+                    String::from(
+                        "CREATE INDEX idx_orders_user_id_created_at ON shop.orders (`user_id`, `created_at`)"
+                    ),
+                    2
+                ),
+                (
+                    String::from("CREATE INDEX idx_users_age ON users (`age`)"),
+                    1
+                ),
+            ]
+        );
+        advisor.clear();
+        assert!(advisor.is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+//! Joins with `LATERAL` subqueries, for example to fetch the top N rows per group.
+//!
+//! [`lateral`] returns a table expression that can be used as the table name of a
+//! [`StmtBuilder`](crate::db::StmtBuilder), like [`values::derived`](crate::db::values::derived).
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::{
+//!     db::{Cond, Dialect, QueryPlan, StmtBuilder, Type, join::{JoinKind, lateral}},
+//!     page::SortField,
+//! };
+//!
+//! // The three largest orders of each user.
+//! let orders = StmtBuilder::new(String::from("orders"), Type::PostgreSQL);
+//! let top = orders.build_plan_stmt(&QueryPlan {
+//!     cols: vec![String::from("total")],
+//!     conds: vec![Cond::eq("user_id", "users.id")],
+//!     order: vec![SortField::desc("total")],
+//!     limit: Some(3),
+//!     ..QueryPlan::default()
+//! });
+//! let tbl = lateral(Dialect::new(Type::PostgreSQL), "users", &top, "top", JoinKind::Inner).unwrap();
+//! let sb = StmtBuilder::new(tbl, Type::PostgreSQL);
+//!
+//! assert_eq!(
+//!     sb.build_query_stmt(&["name", "total"], &[] as &[Cond]),
+//!     "SELECT \"name\", \"total\" FROM users JOIN LATERAL \
+//!      (SELECT \"total\" FROM orders WHERE user_id = users.id ORDER BY \"total\" DESC LIMIT 3) AS top ON TRUE"
+//! );
+//! ```
+
+use crate::db::{Dialect, Feature, UnsupportedFeature};
+
+/// The kind of a join.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum JoinKind {
+    /// Keeps the rows that have matches only.
+    Inner,
+    /// Keeps every row of the left table, with `NULL`s where the subquery returns no rows.
+    Left,
+}
+
+/// Returns the table `tbl` joined with the `LATERAL` subquery `subquery` named `alias`.
+///
+/// The subquery references the columns of `tbl` by their qualified names, like `users.id`.
+/// It's written as is, so write its values as literals on PostgreSQL,
+/// where the placeholders of the subquery and the outer statement are numbered separately.
+///
+/// # Returns
+///
+/// * The table expression, or an error if the database doesn't support [`Feature::Lateral`].
+///   MariaDB, TiDB and SQLite don't support it, and MySQL supports it since 8.0.14.
+pub fn lateral(
+    dialect: Dialect,
+    tbl: &str,
+    subquery: &str,
+    alias: &str,
+    kind: JoinKind,
+) -> Result<String, UnsupportedFeature> {
+    dialect.check(Feature::Lateral)?;
+    let join = match kind {
+        JoinKind::Inner => "JOIN",
+        JoinKind::Left => "LEFT JOIN",
+    };
+    Ok(format!(
+        "{} {} LATERAL ({}) AS {} ON TRUE",
+        tbl, join, subquery, alias
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Dialect, Feature, Type, UnsupportedFeature, Version};
+
+    use super::{JoinKind, lateral};
+
+    #[test]
+    fn test_lateral() {
+        struct TC {
+            dialect: Dialect,
+            kind: JoinKind,
+            want: Result<&'static str, UnsupportedFeature>,
+        }
+
+        let test_cases = vec![
+            TC {
+                dialect: Dialect::new(Type::MySQL),
+                kind: JoinKind::Left,
+                want: Ok("t LEFT JOIN LATERAL (SELECT 1) AS s ON TRUE"),
+            },
+            TC {
+                dialect: Dialect::new(Type::MySQL).version(Version::new(8, 0, 13)),
+                kind: JoinKind::Inner,
+                want: Err(UnsupportedFeature {
+                    feature: Feature::Lateral,
+                    typ: Type::MySQL,
+                    required: Some(Version::new(8, 0, 14)),
+                }),
+            },
+            TC {
+                dialect: Dialect::new(Type::DuckDB),
+                kind: JoinKind::Inner,
+                want: Ok("t JOIN LATERAL (SELECT 1) AS s ON TRUE"),
+            },
+            TC {
+                dialect: Dialect::new(Type::SQLite),
+                kind: JoinKind::Inner,
+                want: Err(UnsupportedFeature {
+                    feature: Feature::Lateral,
+                    typ: Type::SQLite,
+                    required: None,
+                }),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(
+                lateral(tc.dialect, "t", "SELECT 1", "s", tc.kind),
+                tc.want.map(str::to_string)
+            );
+        }
+    }
+}
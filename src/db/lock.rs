@@ -0,0 +1,308 @@
+//! Distributed locks backed by the database, for example to run a singleton job in one replica only.
+//!
+//! PostgreSQL uses session-level advisory locks (`pg_advisory_lock`), MySQL uses named locks (`GET_LOCK`),
+//! and SQLite and CockroachDB, which have no advisory locks, emulate them with rows in the [`SQLITE_LOCK_TBL`] table.
+//! A lock is held by a dedicated connection of the pool until the returned [`Guard`] is released.
+//! Unlike the server-side locks, emulated rows survive crashes and must then be deleted manually.
+
+use std::time::Duration;
+
+use sqlx::{Any, AnyPool, Row, pool::PoolConnection};
+
+use crate::db::{Type, Value, exec, unsupported_dialect};
+
+/// The table that emulates locks on SQLite and CockroachDB.
+pub const SQLITE_LOCK_TBL: &str = "_advisory_locks";
+
+/// How long [`advisory`] waits before retrying emulated locks, which can't block until a lock is released.
+const SQLITE_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A held lock, which is released when dropped.
+///
+/// Prefer [`Guard::release`], since dropping can only release the lock in the background.
+/// Outside of a tokio runtime, dropping closes the connection instead, which releases the lock
+/// on MySQL and PostgreSQL but leaves the row of the lock behind where locks are emulated.
+#[derive(Debug)]
+pub struct Guard {
+    conn: Option<PoolConnection<Any>>,
+    typ: Type,
+    key: i64,
+}
+
+impl Guard {
+    /// Gets the lock key.
+    pub fn get_key(&self) -> i64 {
+        self.key
+    }
+
+    /// Releases the lock.
+    pub async fn release(mut self) -> Result<(), sqlx::Error> {
+        match self.conn.take() {
+            Some(mut conn) => unlock(&mut conn, self.typ, self.key).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let Some(mut conn) = self.conn.take() else {
+            return;
+        };
+        // Dropping is synchronous, so the lock can only be released in the background.
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let (typ, key) = (self.typ, self.key);
+            handle.spawn(async move {
+                if unlock(&mut conn, typ, key).await.is_err() {
+                    conn.close_on_drop();
+                }
+            });
+        } else {
+            conn.close_on_drop();
+        }
+    }
+}
+
+/// The placeholder of the lock key in emulated locks.
+fn placeholder(typ: Type) -> &'static str {
+    if typ == Type::SQLite { "?" } else { "$1" }
+}
+
+async fn ensure_lock_tbl(conn: &mut PoolConnection<Any>) -> Result<(), sqlx::Error> {
+    let stmt = format!(
+        "CREATE TABLE IF NOT EXISTS {} (lock_key INTEGER PRIMARY KEY)",
+        SQLITE_LOCK_TBL
+    );
+    exec::execute(&mut **conn, &stmt, &[]).await?;
+    Ok(())
+}
+
+/// Tries to take the lock once, without waiting.
+async fn try_lock(
+    conn: &mut PoolConnection<Any>,
+    typ: Type,
+    key: i64,
+) -> Result<bool, sqlx::Error> {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            let row = exec::fetch_optional(
+                &mut **conn,
+                "SELECT GET_LOCK(?, 0)",
+                &[Value::from(key.to_string())],
+            )
+            .await?;
+            // GET_LOCK returns 1 if the lock is obtained.
+            Ok(row.is_some_and(|row| row.try_get::<i64, _>(0).ok() == Some(1)))
+        }
+        Type::PostgreSQL => {
+            let row = exec::fetch_optional(
+                &mut **conn,
+                "SELECT pg_try_advisory_lock($1)",
+                &[Value::from(key)],
+            )
+            .await?;
+            Ok(row.is_some_and(|row| row.try_get::<bool, _>(0).unwrap_or(false)))
+        }
+        Type::DuckDB => Err(unsupported_dialect(typ)),
+        Type::SQLite | Type::CockroachDB => {
+            let stmt = format!(
+                "INSERT INTO {} (lock_key) VALUES ({}) ON CONFLICT DO NOTHING",
+                SQLITE_LOCK_TBL,
+                placeholder(typ)
+            );
+            Ok(exec::execute(&mut **conn, &stmt, &[Value::from(key)]).await? == 1)
+        }
+    }
+}
+
+async fn unlock(conn: &mut PoolConnection<Any>, typ: Type, key: i64) -> Result<(), sqlx::Error> {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            exec::execute(
+                &mut **conn,
+                "SELECT RELEASE_LOCK(?)",
+                &[Value::from(key.to_string())],
+            )
+            .await?
+        }
+        Type::PostgreSQL => {
+            exec::execute(
+                &mut **conn,
+                "SELECT pg_advisory_unlock($1)",
+                &[Value::from(key)],
+            )
+            .await?
+        }
+        Type::DuckDB => return Err(unsupported_dialect(typ)),
+        Type::SQLite | Type::CockroachDB => {
+            let stmt = format!(
+                "DELETE FROM {} WHERE lock_key = {}",
+                SQLITE_LOCK_TBL,
+                placeholder(typ)
+            );
+            exec::execute(&mut **conn, &stmt, &[Value::from(key)]).await?
+        }
+    };
+    Ok(())
+}
+
+/// Takes the lock `key`, waiting until it's available.
+///
+/// # Arguments
+///
+/// * `pool` - The pool, from which a connection is held until the lock is released.
+/// * `typ` - The database type.
+/// * `key` - The lock key, shared by all processes that coordinate on the same resource.
+///
+/// # Returns
+///
+/// * The guard of the lock.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, lock::{advisory, try_advisory}};
+/// use sqlx::AnyPool;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let path = std::env::temp_dir().join("sainnhe_common_lock_example.db");
+/// let pool = AnyPool::connect(&format!("sqlite://{}?mode=rwc", path.display()))
+///     .await
+///     .unwrap();
+///
+/// let guard = advisory(&pool, Type::SQLite, 42).await.unwrap();
+/// assert!(try_advisory(&pool, Type::SQLite, 42).await.unwrap().is_none());
+///
+/// guard.release().await.unwrap();
+/// let guard = try_advisory(&pool, Type::SQLite, 42).await.unwrap().unwrap();
+/// guard.release().await.unwrap();
+/// # pool.close().await;
+/// # let _ = std::fs::remove_file(path);
+/// # });
+/// ```
+pub async fn advisory(pool: &AnyPool, typ: Type, key: i64) -> Result<Guard, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            // A negative timeout waits forever.
+            let row = exec::fetch_optional(
+                &mut *conn,
+                "SELECT GET_LOCK(?, -1)",
+                &[Value::from(key.to_string())],
+            )
+            .await?;
+            // GET_LOCK returns NULL on errors, for example if the thread is killed while waiting.
+            if row.is_none_or(|row| row.try_get::<i64, _>(0).ok() != Some(1)) {
+                return Err(sqlx::Error::Protocol(format!(
+                    "GET_LOCK failed to take the lock {}",
+                    key
+                )));
+            }
+        }
+        Type::PostgreSQL => {
+            exec::execute(
+                &mut *conn,
+                "SELECT pg_advisory_lock($1)",
+                &[Value::from(key)],
+            )
+            .await?;
+        }
+        Type::DuckDB => return Err(unsupported_dialect(typ)),
+        Type::SQLite | Type::CockroachDB => {
+            ensure_lock_tbl(&mut conn).await?;
+            while !try_lock(&mut conn, typ, key).await? {
+                tokio::time::sleep(SQLITE_RETRY_INTERVAL).await;
+            }
+        }
+    }
+    Ok(Guard {
+        conn: Some(conn),
+        typ,
+        key,
+    })
+}
+
+/// Same as [`advisory`], but returns [`None`] immediately if the lock is held by someone else.
+pub async fn try_advisory(
+    pool: &AnyPool,
+    typ: Type,
+    key: i64,
+) -> Result<Option<Guard>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    if matches!(typ, Type::SQLite | Type::CockroachDB) {
+        ensure_lock_tbl(&mut conn).await?;
+    }
+    if !try_lock(&mut conn, typ, key).await? {
+        return Ok(None);
+    }
+    Ok(Some(Guard {
+        conn: Some(conn),
+        typ,
+        key,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::AnyPool;
+
+    use crate::db::Type;
+
+    use super::{advisory, try_advisory};
+
+    #[tokio::test]
+    async fn test_advisory() {
+        sqlx::any::install_default_drivers();
+        let path =
+            std::env::temp_dir().join(format!("sainnhe_common_lock_{}.db", std::process::id()));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        let pool = AnyPool::connect(&url).await.unwrap();
+
+        let guard = advisory(&pool, Type::SQLite, 1).await.unwrap();
+        assert_eq!(guard.get_key(), 1);
+        assert!(
+            try_advisory(&pool, Type::SQLite, 1)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Other keys are independent
+        let other = try_advisory(&pool, Type::SQLite, 2).await.unwrap().unwrap();
+        other.release().await.unwrap();
+
+        // Waiters get the lock once it's released
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                advisory(&pool, Type::SQLite, 1)
+                    .await
+                    .unwrap()
+                    .release()
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiter.is_finished());
+        guard.release().await.unwrap();
+        waiter.await.unwrap().unwrap();
+
+        // Dropping releases the lock in the background
+        let guard = advisory(&pool, Type::SQLite, 1).await.unwrap();
+        drop(guard);
+        let guard = advisory(&pool, Type::SQLite, 1).await.unwrap();
+        guard.release().await.unwrap();
+
+        // sqlx has no DuckDB driver
+        let res = advisory(&pool, Type::DuckDB, 1).await;
+        assert!(matches!(res, Err(sqlx::Error::Configuration(_))));
+        let res = try_advisory(&pool, Type::DuckDB, 1).await;
+        assert!(matches!(res, Err(sqlx::Error::Configuration(_))));
+
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
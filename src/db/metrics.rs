@@ -0,0 +1,223 @@
+//! Query metrics collection.
+
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+/// Default upper bounds of latency histogram buckets, in seconds.
+pub const DEFAULT_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// Statistics of statements sharing the same fingerprint.
+#[derive(PartialEq, Clone, Debug)]
+pub struct QueryStats {
+    /// Number of executions.
+    pub count: u64,
+    /// Number of failed executions.
+    pub errors: u64,
+    /// Total duration of all executions.
+    pub sum: Duration,
+    /// Cumulative histogram counts, one for each bucket of [`Registry::get_buckets`].
+    pub buckets: Vec<u64>,
+}
+
+/// Registry that records per-fingerprint query counts, error counts and latency histograms.
+///
+/// The registry is thread-safe and is usually shared via [`Arc`](std::sync::Arc).
+/// Use [`Registry::render`] to expose the metrics in Prometheus text format.
+#[derive(Debug)]
+pub struct Registry {
+    buckets: Vec<f64>,
+    stats: Mutex<BTreeMap<String, QueryStats>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+impl Registry {
+    /// Creates a new [`Registry`], where `buckets` are the upper bounds of latency histogram buckets in seconds.
+    pub fn new(mut buckets: Vec<f64>) -> Registry {
+        buckets.sort_by(f64::total_cmp);
+        Registry {
+            buckets,
+            stats: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Gets upper bounds of latency histogram buckets.
+    pub fn get_buckets(&self) -> &[f64] {
+        &self.buckets
+    }
+
+    /// Records an execution.
+    ///
+    /// # Arguments
+    ///
+    /// * `fingerprint` - The fingerprint of the statement, see [`fingerprint`](crate::db::fingerprint).
+    /// * `duration` - The execution duration.
+    /// * `ok` - Whether the execution succeeded.
+    pub fn record(&self, fingerprint: &str, duration: Duration, ok: bool) {
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let s = stats
+            .entry(fingerprint.to_string())
+            .or_insert_with(|| QueryStats {
+                count: 0,
+                errors: 0,
+                sum: Duration::ZERO,
+                buckets: vec![0; self.buckets.len()],
+            });
+        s.count += 1;
+        if !ok {
+            s.errors += 1;
+        }
+        s.sum += duration;
+        let secs = duration.as_secs_f64();
+        for (cnt, le) in s.buckets.iter_mut().zip(&self.buckets) {
+            if secs <= *le {
+                *cnt += 1;
+            }
+        }
+    }
+
+    /// Gets statistics of the given fingerprint.
+    pub fn get_stats(&self, fingerprint: &str) -> Option<QueryStats> {
+        self.stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(fingerprint)
+            .cloned()
+    }
+
+    /// Renders all metrics in Prometheus text format.
+    ///
+    /// The following metrics are rendered, each labeled with `fingerprint`:
+    ///
+    ///   - `db_queries_total`: Counter of executions.
+    ///   - `db_query_errors_total`: Counter of failed executions.
+    ///   - `db_query_duration_seconds`: Histogram of execution durations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use sainnhe_common::db::metrics::Registry;
+    ///
+    /// let registry = Registry::new(vec![0.1]);
+    /// registry.record("SELECT ?", Duration::from_millis(50), true);
+    ///
+    /// let out = registry.render();
+    ///
+    /// assert!(out.contains("db_queries_total{fingerprint=\"SELECT ?\"} 1\n"));
+    /// assert!(out.contains("db_query_errors_total{fingerprint=\"SELECT ?\"} 0\n"));
+    /// assert!(out.contains("db_query_duration_seconds_bucket{fingerprint=\"SELECT ?\",le=\"0.1\"} 1\n"));
+    /// assert!(out.contains("db_query_duration_seconds_sum{fingerprint=\"SELECT ?\"} 0.05\n"));
+    /// ```
+    pub fn render(&self) -> String {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        // Writing to a String never fails.
+        let _ = writeln!(
+            out,
+            "# HELP db_queries_total Total number of executed statements.\n# TYPE db_queries_total counter"
+        );
+        for (fp, s) in stats.iter() {
+            let _ = writeln!(
+                out,
+                "db_queries_total{{fingerprint=\"{}\"}} {}",
+                escape_label(fp),
+                s.count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP db_query_errors_total Total number of failed statements.\n# TYPE db_query_errors_total counter"
+        );
+        for (fp, s) in stats.iter() {
+            let _ = writeln!(
+                out,
+                "db_query_errors_total{{fingerprint=\"{}\"}} {}",
+                escape_label(fp),
+                s.errors
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP db_query_duration_seconds Statement execution duration in seconds.\n# TYPE db_query_duration_seconds histogram"
+        );
+        for (fp, s) in stats.iter() {
+            let fp = escape_label(fp);
+            for (cnt, le) in s.buckets.iter().zip(&self.buckets) {
+                let _ = writeln!(
+                    out,
+                    "db_query_duration_seconds_bucket{{fingerprint=\"{}\",le=\"{}\"}} {}",
+                    fp, le, cnt
+                );
+            }
+            let _ = writeln!(
+                out,
+                "db_query_duration_seconds_bucket{{fingerprint=\"{}\",le=\"+Inf\"}} {}\ndb_query_duration_seconds_sum{{fingerprint=\"{}\"}} {}\ndb_query_duration_seconds_count{{fingerprint=\"{}\"}} {}",
+                fp,
+                s.count,
+                fp,
+                s.sum.as_secs_f64(),
+                fp,
+                s.count
+            );
+        }
+        out
+    }
+}
+
+fn escape_label(val: &str) -> String {
+    val.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{DEFAULT_BUCKETS, QueryStats, Registry};
+
+    #[test]
+    fn test_record() {
+        let registry = Registry::new(vec![0.5, 0.1]);
+        assert_eq!(registry.get_buckets(), &[0.1, 0.5]);
+        assert!(registry.get_stats("SELECT ?").is_none());
+
+        registry.record("SELECT ?", Duration::from_millis(50), true);
+        registry.record("SELECT ?", Duration::from_millis(200), false);
+        registry.record("SELECT ?", Duration::from_secs(1), true);
+
+        assert_eq!(
+            registry.get_stats("SELECT ?").unwrap(),
+            QueryStats {
+                count: 3,
+                errors: 1,
+                sum: Duration::from_millis(1250),
+                buckets: vec![1, 2],
+            }
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let registry = Registry::default();
+        assert_eq!(registry.get_buckets(), &DEFAULT_BUCKETS);
+
+        registry.record("SELECT \"a\\b\"\n", Duration::from_millis(2), false);
+        let out = registry.render();
+        assert!(out.contains("db_queries_total{fingerprint=\"SELECT \\\"a\\\\b\\\"\\n\"} 1\n"));
+        assert!(
+            out.contains("db_query_errors_total{fingerprint=\"SELECT \\\"a\\\\b\\\"\\n\"} 1\n")
+        );
+        assert!(out.contains(
+            "db_query_duration_seconds_bucket{fingerprint=\"SELECT \\\"a\\\\b\\\"\\n\",le=\"0.001\"} 0\n"
+        ));
+        assert!(out.contains(
+            "db_query_duration_seconds_bucket{fingerprint=\"SELECT \\\"a\\\\b\\\"\\n\",le=\"0.005\"} 1\n"
+        ));
+    }
+}
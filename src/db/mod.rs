@@ -1,11 +1,138 @@
 //! Database utilities.
 
+pub mod audit;
+#[cfg(feature = "sqlx")]
+pub mod audit_log;
+mod batch;
+pub mod bulk;
+#[cfg(feature = "sqlx")]
+pub mod cache;
+mod cond;
+#[cfg(feature = "testcontainers")]
+pub mod containers;
+mod convert;
+pub mod cte;
+pub mod ddl;
+mod dialect;
+#[cfg(feature = "sqlx")]
+pub mod errors;
+#[cfg(feature = "sqlx")]
+pub mod exec;
+#[cfg(feature = "sqlx")]
+pub mod export;
+pub mod expr;
+pub mod file;
+pub mod filter;
+pub(crate) mod fingerprint;
+#[cfg(feature = "geo")]
+pub mod geo;
+mod hint;
+mod hook;
+#[cfg(feature = "sqlx")]
+pub mod idempotency;
+mod index_advisor;
+pub mod join;
+pub mod keywords;
+#[cfg(feature = "lock")]
+pub mod lock;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "sqlx")]
+pub mod observe;
+mod order;
+#[cfg(feature = "sqlx")]
+pub mod outbox;
+pub mod page;
+mod plan;
+mod policy;
+#[cfg(feature = "queue")]
+pub mod queue;
+pub mod registry;
+#[cfg(feature = "sqlx")]
+pub mod rls;
+#[cfg(feature = "rusqlite")]
+pub mod rusqlite;
+mod sample;
+#[cfg(feature = "sea-query")]
+pub mod sea_query;
+pub mod seed;
+pub mod select;
+#[cfg(feature = "sqlx")]
+pub mod sequence;
+pub mod shard;
 mod stmt_builder;
-pub use stmt_builder::{KV, PLACEHOLDER, StmtBuilder};
+mod stmt_cache;
+mod stmt_template;
+#[cfg(feature = "sqlx")]
+pub mod stream;
+pub(crate) mod table;
+pub mod temp;
+mod tenant;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tokio-postgres")]
+pub mod tokio_postgres;
+#[cfg(feature = "validate")]
+mod validate;
+mod value;
+pub mod values;
+pub use batch::Batch;
+pub use cond::{AsCond, CmpOp, Cond, CondRef, Filter, Rewriter, Visitor};
+pub use convert::kv_from_map;
+#[cfg(feature = "serde")]
+pub use convert::{Render, kv_from_json, to_kv_pairs};
+pub use dialect::{BuildError, Dialect, Feature, ParseVersionError, UnsupportedFeature, Version};
+pub use fingerprint::fingerprint;
+pub use hint::Hint;
+pub use hook::{Statement, StmtKind};
+pub use index_advisor::{IndexAdvisor, IndexSuggestion};
+pub use order::{OrderBy, OrderByError};
+pub use plan::QueryPlan;
+pub use policy::{Policy, PolicyError};
+#[cfg(feature = "macros")]
+pub use sainnhe_common_macros::{include_sql, stmt};
+pub use sample::{InvalidSample, Sample};
+pub use stmt_builder::{AsKV, KV, KVBuf, PLACEHOLDER, StmtBuilder};
+pub use stmt_cache::StmtCache;
+pub use stmt_template::StmtTemplate;
+pub use table::{TableRef, TableRefError};
+pub use tenant::TenantScope;
+#[cfg(feature = "validate")]
+pub use validate::{ValidationError, validate};
+pub use value::Value;
 
 /// The type of database.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub enum Type {
     MySQL,
     PostgreSQL,
     SQLite,
+    /// MariaDB, which shares the syntax of MySQL.
+    MariaDB,
+    /// TiDB, which shares the syntax of MySQL.
+    TiDB,
+    /// CockroachDB, which shares the syntax of PostgreSQL.
+    CockroachDB,
+    /// DuckDB, the embedded analytical database.
+    DuckDB,
+}
+
+impl Type {
+    /// Returns `true` if the database shares the syntax of MySQL, that is MySQL, MariaDB and TiDB.
+    pub fn is_mysql_family(&self) -> bool {
+        matches!(self, Type::MySQL | Type::MariaDB | Type::TiDB)
+    }
+
+    /// Returns `true` if the database shares the syntax of PostgreSQL, that is PostgreSQL and CockroachDB.
+    pub fn is_postgres_family(&self) -> bool {
+        matches!(self, Type::PostgreSQL | Type::CockroachDB)
+    }
+}
+
+/// Returns the error for a database that sqlx has no driver for, which is DuckDB.
+#[cfg(feature = "sqlx")]
+pub(crate) fn unsupported_dialect(typ: Type) -> sqlx::Error {
+    sqlx::Error::Configuration(
+        format!("unsupported dialect: sqlx has no driver for {:?}", typ).into(),
+    )
 }
@@ -0,0 +1,427 @@
+//! Observability layer for statement execution.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use sqlx::{Any, AnyPool, Executor, any::AnyRow};
+use tracing::{Instrument, field};
+
+#[cfg(feature = "metrics")]
+use crate::db::metrics::Registry;
+use crate::{
+    db::{Type, Value, exec, fingerprint},
+    redact,
+};
+
+/// A statement whose execution exceeded the slow query threshold.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SlowQuery {
+    /// The statement, whose string literals are masked via [`redact::statement`].
+    /// If redaction is enabled, it is the fingerprint of the statement.
+    pub statement: String,
+    /// The execution duration.
+    pub duration: Duration,
+    /// The execution plan, if EXPLAIN capture is enabled and succeeded.
+    pub plan: Option<String>,
+}
+
+/// Callback invoked with every slow query.
+pub type SlowQueryCallback = Arc<dyn Fn(&SlowQuery) + Send + Sync>;
+
+/// Wraps the helpers in [`exec`] and emits a `tracing` span for each execution.
+///
+/// The span is named `db.execute` and has the following fields:
+///
+///   - `db.fingerprint`: The fingerprint of the statement, see [`fingerprint`].
+///   - `db.statement`: The statement, whose string literals are masked via [`redact::statement`].
+///     If redaction is enabled, it is the same as the fingerprint.
+///   - `db.bind_count`: The number of bound values.
+///   - `db.binds`: The bound values, where text is masked via [`redact::values`].
+///     If redaction is enabled, it's omitted.
+///   - `db.duration_ms`: The execution duration in milliseconds.
+///   - `db.rows_affected`: The number of rows affected or returned.
+///   - `db.error`: The error message if execution fails.
+///   - `db.request_id`: The ID of the request being served, with the `web` feature enabled,
+///     see [`request_id`](crate::web::request_id).
+///
+/// Executions that exceed a threshold can be reported as slow queries,
+/// see [`Observer::set_slow_query_threshold`].
+///
+/// With the `metrics` feature enabled, executions can also be recorded into a [`Registry`],
+/// see [`Observer::set_metrics`].
+#[derive(Clone, Default)]
+pub struct Observer {
+    redact: bool,
+    slow_query_threshold: Option<Duration>,
+    slow_query_callback: Option<SlowQueryCallback>,
+    explain: Option<(AnyPool, Type)>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<Registry>>,
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Observer");
+        d.field("redact", &self.redact)
+            .field("slow_query_threshold", &self.slow_query_threshold)
+            .field(
+                "slow_query_callback",
+                &self.slow_query_callback.as_ref().map(|_| "Fn(&SlowQuery)"),
+            )
+            .field("explain", &self.explain.as_ref().map(|(_, typ)| typ));
+        #[cfg(feature = "metrics")]
+        d.field("metrics", &self.metrics);
+        d.finish()
+    }
+}
+
+impl Observer {
+    /// Creates a new [`Observer`] with redaction disabled.
+    pub fn new() -> Observer {
+        Observer::default()
+    }
+
+    /// Gets whether literal values in statements are redacted.
+    pub fn get_redact(&self) -> bool {
+        self.redact
+    }
+
+    /// Sets whether literal values in statements are redacted.
+    pub fn set_redact(&mut self, redact: bool) {
+        self.redact = redact;
+    }
+
+    /// Gets slow query threshold.
+    pub fn get_slow_query_threshold(&self) -> Option<Duration> {
+        self.slow_query_threshold
+    }
+
+    /// Sets slow query threshold. Executions that take longer than it are reported as slow queries.
+    ///
+    /// Slow queries are passed to the callback set by [`Observer::set_slow_query_callback`],
+    /// or logged via `tracing::warn!` if no callback is set.
+    pub fn set_slow_query_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_query_threshold = threshold;
+    }
+
+    /// Sets callback invoked with every slow query.
+    pub fn set_slow_query_callback(&mut self, callback: Option<SlowQueryCallback>) {
+        self.slow_query_callback = callback;
+    }
+
+    /// Sets the pool used to capture execution plans of slow queries, see [`exec::explain`].
+    ///
+    /// The plan is captured on a separate connection after the slow query finishes,
+    /// so uncommitted changes made in the same transaction are invisible to it.
+    pub fn set_explain(&mut self, explain: Option<(AnyPool, Type)>) {
+        self.explain = explain;
+    }
+
+    /// Gets metrics registry.
+    #[cfg(feature = "metrics")]
+    pub fn get_metrics(&self) -> Option<&Arc<Registry>> {
+        self.metrics.as_ref()
+    }
+
+    /// Sets metrics registry that records every execution.
+    #[cfg(feature = "metrics")]
+    pub fn set_metrics(&mut self, metrics: Option<Arc<Registry>>) {
+        self.metrics = metrics;
+    }
+
+    /// Executes a statement, see [`exec::execute`].
+    pub async fn execute<'e, E>(
+        &self,
+        executor: E,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<u64, sqlx::Error>
+    where
+        E: Executor<'e, Database = Any>,
+    {
+        self.observe(stmt, args, exec::execute(executor, stmt, args), |n| *n)
+            .await
+    }
+
+    /// Executes a statement and returns all rows, see [`exec::fetch_all`].
+    pub async fn fetch_all<'e, E>(
+        &self,
+        executor: E,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<Vec<AnyRow>, sqlx::Error>
+    where
+        E: Executor<'e, Database = Any>,
+    {
+        self.observe(stmt, args, exec::fetch_all(executor, stmt, args), |rows| {
+            rows.len() as u64
+        })
+        .await
+    }
+
+    /// Executes a statement and returns at most one row, see [`exec::fetch_optional`].
+    pub async fn fetch_optional<'e, E>(
+        &self,
+        executor: E,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<Option<AnyRow>, sqlx::Error>
+    where
+        E: Executor<'e, Database = Any>,
+    {
+        self.observe(
+            stmt,
+            args,
+            exec::fetch_optional(executor, stmt, args),
+            |row| u64::from(row.is_some()),
+        )
+        .await
+    }
+
+    async fn observe<T, F, C>(
+        &self,
+        stmt: &str,
+        args: &[Value],
+        fut: F,
+        count: C,
+    ) -> Result<T, sqlx::Error>
+    where
+        F: Future<Output = Result<T, sqlx::Error>>,
+        C: FnOnce(&T) -> u64,
+    {
+        let fp = fingerprint(stmt);
+        let logged = if self.redact {
+            fp.clone()
+        } else {
+            redact::statement(stmt)
+        };
+        let span = tracing::info_span!(
+            "db.execute",
+            db.fingerprint = %fp,
+            db.statement = logged.as_str(),
+            db.bind_count = args.len(),
+            db.binds = field::Empty,
+            db.duration_ms = field::Empty,
+            db.rows_affected = field::Empty,
+            db.error = field::Empty,
+            db.request_id = field::Empty,
+        );
+        if !self.redact && !args.is_empty() {
+            span.record("db.binds", redact::values(args));
+        }
+        #[cfg(feature = "web")]
+        if let Some(id) = crate::web::request_id() {
+            span.record("db.request_id", id);
+        }
+        let start = Instant::now();
+        let res = fut.instrument(span.clone()).await;
+        let elapsed = start.elapsed();
+        span.record("db.duration_ms", elapsed.as_secs_f64() * 1000.0);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&fp, elapsed, res.is_ok());
+        }
+        match &res {
+            Ok(v) => span.record("db.rows_affected", count(v)),
+            Err(e) => span.record("db.error", field::display(e)),
+        };
+        if self
+            .slow_query_threshold
+            .is_some_and(|threshold| elapsed > threshold)
+        {
+            let plan = match &self.explain {
+                Some((pool, typ)) => exec::explain(pool, *typ, stmt, args).await.ok(),
+                None => None,
+            };
+            self.report_slow_query(SlowQuery {
+                statement: logged,
+                duration: elapsed,
+                plan,
+            });
+        }
+        res
+    }
+
+    fn report_slow_query(&self, slow_query: SlowQuery) {
+        match &self.slow_query_callback {
+            Some(callback) => callback(&slow_query),
+            None => tracing::warn!(
+                db.statement = %slow_query.statement,
+                db.duration_ms = slow_query.duration.as_secs_f64() * 1000.0,
+                db.plan = slow_query.plan,
+                "slow query"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use sqlx::{AnyConnection, AnyPool, Connection};
+
+    use crate::db::{Type, Value};
+
+    use super::{Observer, SlowQuery};
+
+    #[test]
+    fn test_getter_setter() {
+        let mut observer = Observer::new();
+        assert!(!observer.get_redact());
+        observer.set_redact(true);
+        assert!(observer.get_redact());
+
+        assert!(observer.get_slow_query_threshold().is_none());
+        observer.set_slow_query_threshold(Some(Duration::from_secs(1)));
+        assert_eq!(
+            observer.get_slow_query_threshold(),
+            Some(Duration::from_secs(1))
+        );
+        observer.set_slow_query_callback(Some(Arc::new(|_| {})));
+        assert!(
+            format!("{:?}", observer).contains("slow_query_callback: Some(\"Fn(&SlowQuery)\")")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_query() {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPool::connect("sqlite:file:test_slow_query?mode=memory&cache=shared")
+            .await
+            .unwrap();
+        let slow_queries = Arc::new(Mutex::new(Vec::<SlowQuery>::new()));
+        let mut observer = Observer::new();
+        observer
+            .execute(&pool, "CREATE TABLE t (a INTEGER PRIMARY KEY)", &[])
+            .await
+            .unwrap();
+
+        let sink = slow_queries.clone();
+        observer.set_slow_query_threshold(Some(Duration::ZERO));
+        observer.set_slow_query_callback(Some(Arc::new(move |q| {
+            sink.lock().unwrap().push(q.clone())
+        })));
+        observer
+            .fetch_all(&pool, "SELECT a FROM t WHERE a = 1", &[])
+            .await
+            .unwrap();
+        observer.set_redact(true);
+        observer.set_explain(Some((pool.clone(), Type::SQLite)));
+        observer
+            .fetch_all(&pool, "SELECT a FROM t WHERE a = ?", &[Value::from(1)])
+            .await
+            .unwrap();
+
+        let got = slow_queries.lock().unwrap().clone();
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].statement, "SELECT a FROM t WHERE a = 1");
+        assert!(got[0].plan.is_none());
+        assert_eq!(got[1].statement, "SELECT a FROM t WHERE a = ?");
+        assert!(
+            got[1]
+                .plan
+                .as_ref()
+                .unwrap()
+                .contains("USING INTEGER PRIMARY KEY")
+        );
+
+        // Without callback, slow queries are logged
+        observer.set_slow_query_callback(None);
+        observer
+            .fetch_all(&pool, "SELECT a FROM t", &[])
+            .await
+            .unwrap();
+        observer.set_slow_query_threshold(Some(Duration::from_secs(60)));
+        observer
+            .fetch_all(&pool, "SELECT a FROM t", &[])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_execute_and_fetch() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let mut observer = Observer::new();
+        observer.set_redact(true);
+
+        observer
+            .execute(&mut conn, "CREATE TABLE t (a INTEGER)", &[])
+            .await
+            .unwrap();
+        let rows_affected = observer
+            .execute(
+                &mut conn,
+                "INSERT INTO t (a) VALUES (?), (2)",
+                &[Value::from(1)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows_affected, 2);
+
+        let rows = observer
+            .fetch_all(&mut conn, "SELECT a FROM t", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let row = observer
+            .fetch_optional(&mut conn, "SELECT a FROM t WHERE a = ?", &[Value::from(3)])
+            .await
+            .unwrap();
+        assert!(row.is_none());
+
+        assert!(
+            observer
+                .execute(&mut conn, "SELECT * FROM no_such_tbl", &[])
+                .await
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_metrics() {
+        use std::sync::Arc;
+
+        use crate::db::metrics::Registry;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let mut observer = Observer::new();
+        assert!(observer.get_metrics().is_none());
+        let registry = Arc::new(Registry::default());
+        observer.set_metrics(Some(registry.clone()));
+        assert!(observer.get_metrics().is_some());
+
+        observer
+            .fetch_all(&mut conn, "SELECT 1", &[])
+            .await
+            .unwrap();
+        observer
+            .fetch_all(&mut conn, "SELECT 2", &[])
+            .await
+            .unwrap();
+        assert!(
+            observer
+                .fetch_all(&mut conn, "SELECT * FROM no_such_tbl", &[])
+                .await
+                .is_err()
+        );
+
+        let stats = registry.get_stats("SELECT ?").unwrap();
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.errors, 0);
+        let stats = registry.get_stats("SELECT * FROM no_such_tbl").unwrap();
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.errors, 1);
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+
+use crate::{
+    db::{Policy, PolicyError},
+    error::{AppError, Code},
+    page::{SortField, parse_sort},
+};
+
+/// Error returned by [`OrderBy::parse`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum OrderByError {
+    /// The sort field is empty or duplicated.
+    Invalid(String),
+    /// The sort field is not allowed by the policy.
+    Policy(PolicyError),
+}
+
+impl fmt::Display for OrderByError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderByError::Invalid(s) => write!(f, "invalid sort field {}", s),
+            OrderByError::Policy(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for OrderByError {}
+
+impl From<PolicyError> for OrderByError {
+    fn from(e: PolicyError) -> Self {
+        OrderByError::Policy(e)
+    }
+}
+
+impl From<OrderByError> for AppError {
+    fn from(e: OrderByError) -> Self {
+        AppError::new(Code::InvalidArgument, e.to_string())
+    }
+}
+
+/// Sort keys whose columns are checked against a [`Policy`], so they can be written into `ORDER BY` safely.
+///
+/// It can only be created by parsing, and is passed to [`StmtBuilder::build_ordered_query_stmt`](crate::db::StmtBuilder::build_ordered_query_stmt).
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, OrderBy, PLACEHOLDER, Policy, StmtBuilder, Type};
+///
+/// let policy = Policy::new().allow("users", &["created_at", "name"]);
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+///
+/// // For example, the sort parameter of an API.
+/// let order = OrderBy::parse("-created_at,+name", "users", &policy).unwrap();
+///
+/// assert_eq!(
+///     sb.build_ordered_query_stmt(&["name"], &[Cond::eq("status", PLACEHOLDER)], &order),
+///     "SELECT \"name\" FROM users WHERE status = $1 ORDER BY \"created_at\" DESC, \"name\" ASC"
+/// );
+/// assert!(OrderBy::parse("password", "users", &policy).is_err());
+/// assert!(OrderBy::parse("name\" DESC; DROP TABLE users; --", "users", &policy).is_err());
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Default)]
+pub struct OrderBy {
+    keys: Vec<SortField>,
+}
+
+impl OrderBy {
+    /// Parses the sort parameter of the table `tbl`.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The comma-separated sort fields, each of which is prefixed by `-` to sort in descending order,
+    ///   or optionally by `+` to sort in ascending order. A blank string means no sorting.
+    /// * `tbl` - The table name registered in the policy.
+    /// * `policy` - The allow-list of columns.
+    pub fn parse(s: &str, tbl: &str, policy: &Policy) -> Result<OrderBy, OrderByError> {
+        if s.trim().is_empty() {
+            return Ok(OrderBy::default());
+        }
+        let keys = parse_sort(
+            s,
+            |_, name| policy.check_col(tbl, name).map_err(OrderByError::from),
+            |item| OrderByError::Invalid(item.to_string()),
+        )?;
+        Ok(OrderBy { keys })
+    }
+
+    /// Gets the sort keys.
+    pub fn get_keys(&self) -> &[SortField] {
+        &self.keys
+    }
+
+    /// Returns `true` if there are no sort keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl From<OrderBy> for Vec<SortField> {
+    fn from(order: OrderBy) -> Self {
+        order.keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        db::{Policy, PolicyError},
+        page::SortField,
+    };
+
+    use super::{OrderBy, OrderByError};
+
+    #[test]
+    fn test_parse() {
+        struct TC {
+            s: &'static str,
+            tbl: &'static str,
+            want: Result<Vec<SortField>, OrderByError>,
+        }
+
+        let policy = Policy::new().allow("users", &["id", "name"]);
+        let test_cases = vec![
+            TC {
+                s: " ",
+                tbl: "users",
+                want: Ok(vec![]),
+            },
+            TC {
+                s: "-id, +name",
+                tbl: "users",
+                want: Ok(vec![SortField::desc("id"), SortField::asc("name")]),
+            },
+            TC {
+                s: "name,",
+                tbl: "users",
+                want: Err(OrderByError::Invalid(String::from(""))),
+            },
+            TC {
+                s: "name,-name",
+                tbl: "users",
+                want: Err(OrderByError::Invalid(String::from("-name"))),
+            },
+            TC {
+                s: "-password",
+                tbl: "users",
+                want: Err(OrderByError::Policy(PolicyError::Column {
+                    tbl: String::from("users"),
+                    col: String::from("password"),
+                })),
+            },
+            TC {
+                s: "id",
+                tbl: "orders",
+                want: Err(OrderByError::Policy(PolicyError::Table(String::from(
+                    "orders",
+                )))),
+            },
+        ];
+
+        for tc in test_cases {
+            let got = OrderBy::parse(tc.s, tc.tbl, &policy).map(Vec::from);
+            assert_eq!(got, tc.want);
+        }
+    }
+}
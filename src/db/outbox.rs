@@ -0,0 +1,335 @@
+//! Transactional outbox for reliable event publishing.
+//!
+//! Events are inserted into an outbox table within the transaction that changes the business data,
+//! so they are recorded if and only if the change is committed.
+//! A poller then fetches the events, dispatches them to a message broker and deletes the acknowledged ones.
+//! Events are delivered at least once, so consumers should be idempotent.
+
+use std::fmt;
+
+use sqlx::{Acquire, Any, AnyConnection, Row};
+
+use crate::db::{
+    Cond, Feature, KV, PLACEHOLDER, StmtBuilder, Type, Value, exec, unsupported_dialect,
+};
+
+/// Errors returned by [`Outbox::poll`].
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by sqlx.
+    Sqlx(sqlx::Error),
+    /// The dispatcher failed. Events acknowledged before the failure are still deleted.
+    Dispatch(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sqlx(e) => write!(f, "sqlx error: {}", e),
+            Error::Dispatch(e) => write!(f, "dispatch error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sqlx(e) => Some(e),
+            Error::Dispatch(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+/// An event stored in the outbox.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Event {
+    /// The id assigned by the database, which increases in insertion order. It's 0 before enqueuing.
+    pub id: i64,
+    /// The topic, for example the name of the queue or the event type.
+    pub topic: String,
+    /// The payload, usually serialized as JSON.
+    pub payload: String,
+}
+
+impl Event {
+    /// Creates a new [`Event`] to be enqueued.
+    pub fn new(topic: String, payload: String) -> Event {
+        Event {
+            id: 0,
+            topic,
+            payload,
+        }
+    }
+}
+
+/// An outbox table.
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::Infallible;
+///
+/// use sainnhe_common::db::{
+///     Type,
+///     exec::{execute, transaction},
+///     outbox::{Event, Outbox},
+/// };
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let outbox = Outbox::new(String::from("outbox"), Type::SQLite);
+/// execute(&mut conn, &outbox.ddl().unwrap(), &[]).await.unwrap();
+///
+/// transaction(&mut conn, async |tx| {
+///     // Change the business data here, then record the event in the same transaction.
+///     let event = Event::new(String::from("user.created"), String::from(r#"{"id":1}"#));
+///     outbox.enqueue(tx, &event).await
+/// })
+/// .await
+/// .unwrap();
+///
+/// let mut published = Vec::new();
+/// let n = outbox
+///     .poll(&mut conn, 100, async |event| {
+///         published.push(event.topic.clone());
+///         Ok::<(), Infallible>(())
+///     })
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(n, 1);
+/// assert_eq!(published, ["user.created"]);
+/// # });
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Outbox {
+    sb: StmtBuilder,
+}
+
+impl Outbox {
+    /// Creates a new [`Outbox`], where `tbl` is the table name and `typ` is the database type.
+    pub fn new(tbl: String, typ: Type) -> Outbox {
+        Outbox {
+            sb: StmtBuilder::new(tbl, typ),
+        }
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        self.sb.get_tbl()
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        self.sb.get_typ()
+    }
+
+    /// Generates the statement that creates the outbox table if it doesn't exist.
+    ///
+    /// Returns an error on DuckDB, which sqlx has no driver for.
+    pub fn ddl(&self) -> Result<String, sqlx::Error> {
+        let id = match self.get_typ() {
+            // Messages are relayed in the order of ids, so `AUTO_RANDOM` doesn't fit TiDB.
+            Type::MySQL | Type::MariaDB | Type::TiDB => "id BIGINT AUTO_INCREMENT PRIMARY KEY",
+            Type::PostgreSQL | Type::CockroachDB => "id BIGSERIAL PRIMARY KEY",
+            Type::SQLite => "id INTEGER PRIMARY KEY AUTOINCREMENT",
+            Type::DuckDB => return Err(unsupported_dialect(Type::DuckDB)),
+        };
+        let topic = match self.get_typ() {
+            Type::MySQL | Type::MariaDB | Type::TiDB => "VARCHAR(255)",
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => "TEXT",
+        };
+        Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}, topic {} NOT NULL, payload TEXT NOT NULL, created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+            self.get_tbl(),
+            id,
+            topic
+        ))
+    }
+
+    /// Inserts an event within the caller's transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` - The transaction, or any connection in general.
+    /// * `event` - The event. Its id is ignored.
+    pub async fn enqueue(&self, tx: &mut AnyConnection, event: &Event) -> Result<(), sqlx::Error> {
+        let stmt = self.sb.build_insert_stmt(&[
+            KV {
+                key: "topic",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "payload",
+                val: PLACEHOLDER,
+            },
+        ]);
+        let args = [
+            Value::from(event.topic.as_str()),
+            Value::from(event.payload.as_str()),
+        ];
+        exec::execute(tx, &stmt, &args).await?;
+        Ok(())
+    }
+
+    /// Generates the statement that fetches the oldest `limit` events.
+    ///
+    /// On MySQL and PostgreSQL, the rows are locked with `FOR UPDATE SKIP LOCKED`,
+    /// so concurrent pollers process disjoint batches.
+    /// SQLite serializes writers, so no row lock is needed.
+    pub fn fetch_stmt(&self, limit: usize) -> String {
+        let mut stmt = self
+            .sb
+            .build_query_stmt(&["id", "topic", "payload"], &[] as &[Cond]);
+        stmt.push_str(" ORDER BY id LIMIT ");
+        stmt.push_str(&limit.to_string());
+        if self.sb.get_dialect().supports(Feature::SkipLocked) {
+            stmt.push_str(" FOR UPDATE SKIP LOCKED");
+        }
+        stmt
+    }
+
+    /// Fetches at most `limit` events, dispatches them in order and deletes the acknowledged ones,
+    /// all within one transaction.
+    ///
+    /// Dispatching stops at the first failure, so that the events of a topic are not reordered.
+    /// The failed event and the rest of the batch are kept for the next poll.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The connection or pool.
+    /// * `limit` - The maximum number of events to dispatch.
+    /// * `dispatch` - The function that publishes an event, which acknowledges it by returning [`Ok`].
+    ///
+    /// # Returns
+    ///
+    /// * The number of dispatched events.
+    pub async fn poll<'c, C, F, E>(
+        &self,
+        conn: C,
+        limit: usize,
+        mut dispatch: F,
+    ) -> Result<usize, Error>
+    where
+        C: Acquire<'c, Database = Any>,
+        F: AsyncFnMut(&Event) -> Result<(), E>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut tx = conn.begin().await?;
+        let rows = exec::fetch_all(&mut *tx, &self.fetch_stmt(limit), &[]).await?;
+        let events = rows
+            .iter()
+            .map(|row| {
+                Ok(Event {
+                    id: row.try_get("id")?,
+                    topic: row.try_get("topic")?,
+                    payload: row.try_get("payload")?,
+                })
+            })
+            .collect::<Result<Vec<Event>, sqlx::Error>>()?;
+
+        let mut acked = Vec::with_capacity(events.len());
+        let mut res = Ok(());
+        for event in &events {
+            if let Err(e) = dispatch(event).await {
+                res = Err(Error::Dispatch(e.into()));
+                break;
+            }
+            acked.push(event.id.to_string());
+        }
+        if !acked.is_empty() {
+            let stmt = self.sb.build_delete_stmt(&[Cond::in_list("id", &acked)]);
+            exec::execute(&mut *tx, &stmt, &[]).await?;
+        }
+        tx.commit().await?;
+        res.map(|_| acked.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{AnyConnection, Connection};
+
+    use crate::db::{Type, exec};
+
+    use super::{Error, Event, Outbox};
+
+    #[test]
+    fn test_stmts() {
+        let outbox = Outbox::new(String::from("outbox"), Type::PostgreSQL);
+        assert_eq!(
+            outbox.fetch_stmt(10),
+            "SELECT \"id\", \"topic\", \"payload\" FROM outbox ORDER BY id LIMIT 10 FOR UPDATE SKIP LOCKED"
+        );
+        assert!(outbox.ddl().unwrap().contains("id BIGSERIAL PRIMARY KEY"));
+
+        let outbox = Outbox::new(String::from("outbox"), Type::SQLite);
+        assert_eq!(
+            outbox.fetch_stmt(10),
+            "SELECT \"id\", \"topic\", \"payload\" FROM outbox ORDER BY id LIMIT 10"
+        );
+
+        let outbox = Outbox::new(String::from("outbox"), Type::DuckDB);
+        assert!(matches!(outbox.ddl(), Err(sqlx::Error::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_poll() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let outbox = Outbox::new(String::from("outbox"), Type::SQLite);
+        exec::execute(&mut conn, &outbox.ddl().unwrap(), &[])
+            .await
+            .unwrap();
+        for i in 0..3 {
+            let event = Event::new(String::from("t"), i.to_string());
+            outbox.enqueue(&mut conn, &event).await.unwrap();
+        }
+
+        // Dispatching stops at the first failure and keeps the rest
+        let mut seen = Vec::new();
+        let err = outbox
+            .poll(&mut conn, 10, async |event| {
+                if event.payload == "1" {
+                    return Err("broker unavailable");
+                }
+                seen.push(event.payload.clone());
+                Ok(())
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Dispatch(_)));
+        assert_eq!(err.to_string(), "dispatch error: broker unavailable");
+        assert_eq!(seen, ["0"]);
+
+        let mut seen = Vec::new();
+        let n = outbox
+            .poll(&mut conn, 1, async |event| {
+                seen.push(event.payload.clone());
+                Ok::<(), Error>(())
+            })
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(seen, ["1"]);
+
+        let n = outbox
+            .poll(&mut conn, 10, async |_| Ok::<(), Error>(()))
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+        let n = outbox
+            .poll(&mut conn, 10, async |_| Ok::<(), Error>(()))
+            .await
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    db::Value,
+    encoding::{self, decode_base64url, encode_base64url},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Errors returned when decoding a [`Cursor`].
+#[derive(Debug)]
+pub enum CursorError {
+    /// The token is not valid URL-safe base64.
+    Base64(encoding::Error),
+    /// The payload is not a valid cursor.
+    Json(serde_json::Error),
+    /// The signature is missing or doesn't match the payload.
+    Signature,
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::Base64(e) => write!(f, "invalid cursor encoding: {}", e),
+            CursorError::Json(e) => write!(f, "invalid cursor payload: {}", e),
+            CursorError::Signature => write!(f, "invalid cursor signature"),
+        }
+    }
+}
+
+impl std::error::Error for CursorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CursorError::Base64(e) => Some(e),
+            CursorError::Json(e) => Some(e),
+            CursorError::Signature => None,
+        }
+    }
+}
+
+/// An opaque cursor for keyset pagination, which holds the sort values of the last seen row.
+///
+/// The cursor is encoded as a URL-safe base64 token, so it can be passed in query parameters.
+/// Use [`Cursor::encode_signed`] and [`Cursor::decode_signed`] to prevent clients from tampering with it.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Value, page::Cursor};
+///
+/// let key = b"secret";
+/// let cursor = Cursor::new(vec![Value::from("2024-01-01"), Value::from(42)]);
+///
+/// let token = cursor.encode_signed(key);
+/// let decoded = Cursor::decode_signed(&token, key).unwrap();
+///
+/// assert_eq!(decoded, cursor);
+/// assert!(Cursor::decode_signed(&token, b"other").is_err());
+/// ```
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Cursor {
+    values: Vec<Value>,
+}
+
+impl Cursor {
+    /// Creates a new [`Cursor`] from the sort values of the last seen row, in the order of the sort keys.
+    pub fn new(values: Vec<Value>) -> Cursor {
+        Cursor { values }
+    }
+
+    /// Gets the sort values.
+    pub fn get_values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// Consumes the cursor and returns the sort values, for example to bind them to a keyset query.
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    /// Encodes the cursor into an unsigned token.
+    pub fn encode(&self) -> String {
+        // Serializing values into JSON never fails.
+        let json = serde_json::to_vec(&self.values).unwrap_or_default();
+        encode_base64url(&json)
+    }
+
+    /// Decodes a token created by [`Cursor::encode`].
+    pub fn decode(token: &str) -> Result<Cursor, CursorError> {
+        let json = decode_base64url(token).map_err(CursorError::Base64)?;
+        let values = serde_json::from_slice(&json).map_err(CursorError::Json)?;
+        Ok(Cursor { values })
+    }
+
+    /// Encodes the cursor into a token signed with HMAC-SHA256.
+    pub fn encode_signed(&self, key: &[u8]) -> String {
+        let payload = self.encode();
+        let sig = Self::mac(key, &payload).finalize().into_bytes();
+        format!("{}.{}", payload, encode_base64url(&sig))
+    }
+
+    /// Decodes a token created by [`Cursor::encode_signed`], verifying its signature in constant time.
+    pub fn decode_signed(token: &str, key: &[u8]) -> Result<Cursor, CursorError> {
+        let (payload, sig) = token.split_once('.').ok_or(CursorError::Signature)?;
+        let sig = decode_base64url(sig).map_err(CursorError::Base64)?;
+        Self::mac(key, payload)
+            .verify_slice(&sig)
+            .map_err(|_| CursorError::Signature)?;
+        Self::decode(payload)
+    }
+
+    fn mac(key: &[u8], payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(key)
+            .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Value;
+
+    use super::{Cursor, CursorError};
+
+    #[test]
+    fn test_cursor() {
+        let cursor = Cursor::new(vec![
+            Value::Null,
+            Value::from(true),
+            Value::from(1.5),
+            Value::from("a/b+c"),
+            Value::from(vec![0xff_u8]),
+        ]);
+        let token = cursor.encode();
+        assert!(
+            token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_eq!(Cursor::decode(&token).unwrap(), cursor);
+        assert_eq!(
+            Cursor::decode(&token).unwrap().into_values(),
+            cursor.get_values()
+        );
+
+        assert!(matches!(Cursor::decode("!!!"), Err(CursorError::Base64(_))));
+        assert!(matches!(Cursor::decode("e30"), Err(CursorError::Json(_))));
+
+        let key = b"key";
+        let token = cursor.encode_signed(key);
+        assert_eq!(Cursor::decode_signed(&token, key).unwrap(), cursor);
+        assert!(matches!(
+            Cursor::decode_signed(&cursor.encode(), key),
+            Err(CursorError::Signature)
+        ));
+        // Tampered payload
+        let (_, sig) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", Cursor::new(vec![Value::from(1)]).encode(), sig);
+        let err = Cursor::decode_signed(&forged, key).unwrap_err();
+        assert!(matches!(err, CursorError::Signature));
+        assert_eq!(err.to_string(), "invalid cursor signature");
+    }
+}
@@ -0,0 +1,232 @@
+//! Pagination.
+//!
+//! The request and response types are defined in [`crate::page`] and re-exported here.
+
+#[cfg(feature = "cursor")]
+mod cursor;
+
+use std::fmt::Write;
+
+#[cfg(feature = "cursor")]
+pub use cursor::{Cursor, CursorError};
+
+use crate::db::{AsCond, StmtBuilder, StmtKind};
+/// The former name of [`SortField`].
+pub use crate::page::SortField as Sort;
+pub use crate::page::{CursorPage, Page, PageRequest, SortField};
+
+/// Builds a SQL statement that queries the requested page.
+///
+/// # Arguments
+///
+/// * `sb` - The statement builder.
+/// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
+/// * `conds` - The conditions, combined with `AND`.
+/// * `req` - The page request.
+///
+/// # Returns
+///
+/// * The SQL statement.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{
+///     Cond, PLACEHOLDER, StmtBuilder, Type,
+///     page::{PageRequest, Sort, build_page_stmt},
+/// };
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// let req = PageRequest {
+///     page: 3,
+///     size: 20,
+///     sort: vec![Sort::desc("created_at"), Sort::asc("id")],
+/// };
+///
+/// let stmt = build_page_stmt(&sb, &["id", "name"], &[Cond::eq("status", PLACEHOLDER)], &req);
+///
+/// assert_eq!(
+///     stmt,
+///     "SELECT \"id\", \"name\" FROM users WHERE status = $1 ORDER BY \"created_at\" DESC, \"id\" ASC LIMIT 20 OFFSET 40"
+/// );
+/// ```
+pub fn build_page_stmt<S: AsRef<str>, P: AsCond>(
+    sb: &StmtBuilder,
+    cols: &[S],
+    conds: &[P],
+    req: &PageRequest,
+) -> String {
+    let mut stmt = String::new();
+    // Writing to a String never fails.
+    let _ = sb.build_query_stmt_into(&mut stmt, cols, conds);
+    let _ = sb.write_order_by(&mut stmt, &req.sort);
+    let _ = write!(stmt, " LIMIT {} OFFSET {}", req.size, req.get_offset());
+    sb.finish(StmtKind::Query, stmt)
+}
+
+/// Fetches the requested page.
+///
+/// Both the limited query and the count query are executed on the same connection,
+/// and rows are mapped via [`sqlx::FromRow`].
+///
+/// # Arguments
+///
+/// * `conn` - The connection source, for example a pool, a connection or a transaction.
+/// * `sb` - The statement builder.
+/// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
+/// * `conds` - The conditions, combined with `AND`.
+/// * `args` - The values bound to the placeholders in the conditions.
+/// * `req` - The page request.
+///
+/// # Returns
+///
+/// * The page.
+#[cfg(feature = "sqlx")]
+pub async fn fetch_page<'c, C, T, S, P>(
+    conn: C,
+    sb: &StmtBuilder,
+    cols: &[S],
+    conds: &[P],
+    args: &[crate::db::Value],
+    req: &PageRequest,
+) -> Result<Page<T>, sqlx::Error>
+where
+    C: sqlx::Acquire<'c, Database = sqlx::Any>,
+    T: for<'r> sqlx::FromRow<'r, sqlx::any::AnyRow>,
+    S: AsRef<str>,
+    P: AsCond,
+{
+    use sqlx::Row;
+
+    use crate::db::exec::query;
+
+    let mut conn = conn.acquire().await?;
+    let rows = query(&build_page_stmt(sb, cols, conds, req), args)
+        .fetch_all(&mut *conn)
+        .await?;
+    let items = rows
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<Vec<T>, sqlx::Error>>()?;
+    let total: i64 = query(&sb.build_count_stmt(conds), args)
+        .fetch_one(&mut *conn)
+        .await?
+        .try_get(0)?;
+    Ok(Page::new(items, u64::try_from(total).unwrap_or(0), req))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{KV, StmtBuilder, Type};
+
+    use super::{Page, PageRequest, Sort, build_page_stmt};
+
+    #[test]
+    fn test_page() {
+        let req = PageRequest {
+            page: 2,
+            size: 10,
+            sort: vec![],
+        };
+        assert_eq!(req.get_offset(), 10);
+
+        let page = Page::new(vec![1, 2], 25, &req);
+        assert!(page.has_next);
+        assert_eq!(page.map(|i| i * 2).items, vec![2, 4]);
+        assert!(!Page::new(vec![1], 20, &req).has_next);
+
+        let req = PageRequest {
+            page: 0,
+            size: 10,
+            sort: vec![],
+        };
+        assert_eq!(req.get_offset(), 0);
+    }
+
+    #[test]
+    fn test_build_page_stmt() {
+        let req = PageRequest {
+            page: 1,
+            size: 5,
+            sort: vec![Sort::asc("a")],
+        };
+        let sb = StmtBuilder::new(String::from("t"), Type::MySQL);
+        assert_eq!(
+            build_page_stmt(&sb, &Vec::<&str>::new(), &Vec::<KV>::new(), &req),
+            "SELECT * FROM t ORDER BY `a` ASC LIMIT 5 OFFSET 0"
+        );
+
+        let req = PageRequest {
+            page: 2,
+            size: 5,
+            sort: vec![],
+        };
+        assert_eq!(
+            build_page_stmt(&sb, &["a"], &Vec::<KV>::new(), &req),
+            "SELECT `a` FROM t LIMIT 5 OFFSET 5"
+        );
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_fetch_page() {
+        use sqlx::{AnyConnection, Connection};
+
+        use crate::db::{Cond, PLACEHOLDER, Value, exec::execute};
+
+        use super::fetch_page;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        execute(
+            &mut conn,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, active BOOLEAN)",
+            &[],
+        )
+        .await
+        .unwrap();
+        for i in 1..=5 {
+            execute(
+                &mut conn,
+                "INSERT INTO users (id, name, active) VALUES (?, ?, ?)",
+                &[
+                    Value::from(i),
+                    Value::from(format!("user{}", i)),
+                    Value::from(i != 3),
+                ],
+            )
+            .await
+            .unwrap();
+        }
+
+        let sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+        let conds = [Cond::eq("active", PLACEHOLDER)];
+        let args = [Value::from(true)];
+        let req = PageRequest {
+            page: 1,
+            size: 3,
+            sort: vec![Sort::desc("id")],
+        };
+        let page: Page<(i64, String)> =
+            fetch_page(&mut conn, &sb, &["id", "name"], &conds, &args, &req)
+                .await
+                .unwrap();
+        assert_eq!(
+            page.items,
+            vec![
+                (5, String::from("user5")),
+                (4, String::from("user4")),
+                (2, String::from("user2")),
+            ]
+        );
+        assert_eq!((page.total, page.page, page.size), (4, 1, 3));
+        assert!(page.has_next);
+
+        let req = PageRequest { page: 2, ..req };
+        let page: Page<(i64,)> = fetch_page(&mut conn, &sb, &["id"], &conds, &args, &req)
+            .await
+            .unwrap();
+        assert_eq!(page.items, vec![(1,)]);
+        assert!(!page.has_next);
+    }
+}
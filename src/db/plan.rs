@@ -0,0 +1,158 @@
+use crate::{
+    db::{Cond, Value},
+    page::SortField,
+};
+
+/// A query definition that can be stored and built later, for example by another service.
+///
+/// With the `serde` feature enabled, it can be serialized into JSON or any other format supported by serde.
+/// The table, database type, tenant scope and policy are not part of the plan,
+/// but taken from the [`StmtBuilder`](crate::db::StmtBuilder) that builds it.
+///
+/// Like [`Cond`], column names and values are used as is,
+/// so only load plans from trusted sources like configuration,
+/// and build them via [`StmtBuilder::try_build_plan_stmt`](crate::db::StmtBuilder::try_build_plan_stmt)
+/// to check the columns against the policy.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{
+///     db::{Cond, PLACEHOLDER, QueryPlan, StmtBuilder, Type, Value},
+///     page::SortField,
+/// };
+///
+/// let plan = QueryPlan {
+///     cols: vec![String::from("id"), String::from("name")],
+///     conds: vec![Cond::eq("status", PLACEHOLDER)],
+///     args: vec![Value::from("active")],
+///     order: vec![SortField::desc("created_at")],
+///     limit: Some(10),
+///     offset: Some(20),
+/// };
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+///
+/// assert_eq!(
+///     sb.build_plan_stmt(&plan),
+///     "SELECT \"id\", \"name\" FROM users WHERE status = $1 ORDER BY \"created_at\" DESC LIMIT 10 OFFSET 20"
+/// );
+/// ```
+#[derive(PartialEq, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct QueryPlan {
+    /// The selected columns. If it's empty, `["*"]` will be used.
+    pub cols: Vec<String>,
+    /// The conditions, combined with `AND`.
+    pub conds: Vec<Cond>,
+    /// The values bound to the placeholders in the conditions, in order.
+    pub args: Vec<Value>,
+    /// The sort keys.
+    pub order: Vec<SortField>,
+    /// The maximum number of rows.
+    pub limit: Option<u64>,
+    /// The number of rows skipped.
+    pub offset: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        db::{Cond, PLACEHOLDER, Policy, StmtBuilder, Type, Value},
+        page::SortField,
+    };
+
+    use super::QueryPlan;
+
+    #[test]
+    fn test_build_plan_stmt() {
+        struct TC {
+            typ: Type,
+            want: &'static str,
+        }
+
+        let plan = QueryPlan {
+            conds: vec![Cond::in_list("id", &[PLACEHOLDER, PLACEHOLDER])],
+            args: vec![Value::from(1), Value::from(2)],
+            offset: Some(5),
+            ..QueryPlan::default()
+        };
+        let test_cases = vec![
+            TC {
+                typ: Type::MySQL,
+                want: "SELECT * FROM t WHERE id IN (?, ?) LIMIT 18446744073709551615 OFFSET 5",
+            },
+            TC {
+                typ: Type::PostgreSQL,
+                want: "SELECT * FROM t WHERE id IN ($1, $2) OFFSET 5",
+            },
+            TC {
+                typ: Type::SQLite,
+                want: "SELECT * FROM t WHERE id IN (?, ?) LIMIT -1 OFFSET 5",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb = StmtBuilder::new(String::from("t"), tc.typ);
+            assert_eq!(sb.build_plan_stmt(&plan), tc.want);
+        }
+    }
+
+    #[test]
+    fn test_try_build_plan_stmt() {
+        let mut sb = StmtBuilder::new(String::from("t"), Type::SQLite);
+        sb.set_policy(Some(Arc::new(Policy::new().allow("t", &["id"]))));
+        let mut plan = QueryPlan {
+            cols: vec![String::from("id")],
+            order: vec![SortField::asc("id")],
+            limit: Some(1),
+            ..QueryPlan::default()
+        };
+        assert_eq!(
+            sb.try_build_plan_stmt(&plan).unwrap(),
+            "SELECT \"id\" FROM t ORDER BY \"id\" ASC LIMIT 1"
+        );
+        plan.order = vec![SortField::asc("secret")];
+        assert!(sb.try_build_plan_stmt(&plan).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let plan = QueryPlan {
+            cols: vec![String::from("id")],
+            conds: vec![Cond::Or(vec![
+                Cond::eq("a", PLACEHOLDER),
+                Cond::is_null("b"),
+            ])],
+            args: vec![Value::from("x")],
+            ..QueryPlan::default()
+        };
+        let json = serde_json::to_value(&plan).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "cols": ["id"],
+                "conds": [{"Or": [
+                    {"Cmp": {"col": "a", "op": "Eq", "val": "?"}},
+                    {"IsNull": {"col": "b", "negated": false}},
+                ]}],
+                "args": [{"Text": "x"}],
+                "order": [],
+                "limit": null,
+                "offset": null,
+            })
+        );
+        assert_eq!(serde_json::from_value::<QueryPlan>(json).unwrap(), plan);
+        // Missing fields are defaulted.
+        assert_eq!(
+            serde_json::from_str::<QueryPlan>(r#"{"limit": 1}"#).unwrap(),
+            QueryPlan {
+                limit: Some(1),
+                ..QueryPlan::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::db::{AsCond, Cond, CondRef};
+
+/// Error returned when a statement references an identifier that is not allowed by a [`Policy`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum PolicyError {
+    /// The table is not registered.
+    Table(String),
+    /// The column is not allowed for the table.
+    Column { tbl: String, col: String },
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::Table(tbl) => write!(f, "table {} is not allowed", tbl),
+            PolicyError::Column { tbl, col } => {
+                write!(f, "column {} of table {} is not allowed", col, tbl)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// An allow-list of columns per table.
+///
+/// Attach it to a [`StmtBuilder`](crate::db::StmtBuilder) via
+/// [`StmtBuilder::set_policy`](crate::db::StmtBuilder::set_policy),
+/// so that the `try_build_*` methods reject identifiers that are not listed,
+/// for example column names taken from the sort and filter parameters of an API.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Policy, PolicyError};
+///
+/// let policy = Policy::new().allow("users", &["id", "name"]);
+///
+/// assert!(policy.check_col("users", "name").is_ok());
+/// assert_eq!(
+///     policy.check_col("users", "password"),
+///     Err(PolicyError::Column {
+///         tbl: String::from("users"),
+///         col: String::from("password"),
+///     })
+/// );
+/// assert_eq!(
+///     policy.check_col("orders", "id"),
+///     Err(PolicyError::Table(String::from("orders")))
+/// );
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Default)]
+pub struct Policy {
+    tbls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Policy {
+    /// Creates an empty [`Policy`], which allows nothing.
+    pub fn new() -> Policy {
+        Policy::default()
+    }
+
+    /// Allows the columns `cols` of the table `tbl`, in addition to the columns allowed before.
+    pub fn allow<S: AsRef<str>>(mut self, tbl: &str, cols: &[S]) -> Policy {
+        self.tbls
+            .entry(tbl.to_string())
+            .or_default()
+            .extend(cols.iter().map(|col| col.as_ref().to_string()));
+        self
+    }
+
+    /// Returns `true` if the column `col` of the table `tbl` is allowed.
+    pub fn is_allowed(&self, tbl: &str, col: &str) -> bool {
+        self.tbls.get(tbl).is_some_and(|cols| cols.contains(col))
+    }
+
+    /// Checks that the table `tbl` is registered.
+    pub fn check_tbl(&self, tbl: &str) -> Result<(), PolicyError> {
+        if self.tbls.contains_key(tbl) {
+            Ok(())
+        } else {
+            Err(PolicyError::Table(tbl.to_string()))
+        }
+    }
+
+    /// Checks that the column `col` of the table `tbl` is allowed.
+    pub fn check_col(&self, tbl: &str, col: &str) -> Result<(), PolicyError> {
+        match self.tbls.get(tbl) {
+            None => Err(PolicyError::Table(tbl.to_string())),
+            Some(cols) if cols.contains(col) => Ok(()),
+            Some(_) => Err(PolicyError::Column {
+                tbl: tbl.to_string(),
+                col: col.to_string(),
+            }),
+        }
+    }
+
+    /// Checks the columns referenced by `conds`. [`Cond::Raw`] fragments are not checked.
+    pub(crate) fn check_conds<P: AsCond>(&self, tbl: &str, conds: &[P]) -> Result<(), PolicyError> {
+        conds.iter().try_for_each(|p| match p.as_cond() {
+            CondRef::Eq(kv) => self.check_col(tbl, kv.key),
+            CondRef::Tree(cond) => self.check_cond(tbl, cond),
+        })
+    }
+
+    fn check_cond(&self, tbl: &str, cond: &Cond) -> Result<(), PolicyError> {
+        match cond {
+            Cond::Cmp { col, .. }
+            | Cond::CmpCi { col, .. }
+            | Cond::In { col, .. }
+            | Cond::IsNull { col, .. } => self.check_col(tbl, col),
+            Cond::Tuple { cols, .. } => cols.iter().try_for_each(|col| self.check_col(tbl, col)),
+            Cond::And(conds) | Cond::Or(conds) => {
+                conds.iter().try_for_each(|c| self.check_cond(tbl, c))
+            }
+            Cond::Not(cond) => self.check_cond(tbl, cond),
+            Cond::Raw(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Cond, KV, PLACEHOLDER};
+
+    use super::{Policy, PolicyError};
+
+    #[test]
+    fn test_check_conds() {
+        let policy = Policy::new()
+            .allow("users", &["id"])
+            .allow("users", &["name"]);
+        assert!(policy.is_allowed("users", "id"));
+        assert!(policy.is_allowed("users", "name"));
+
+        let kvs = [KV {
+            key: "id",
+            val: PLACEHOLDER,
+        }];
+        assert!(policy.check_conds("users", &kvs).is_ok());
+        assert_eq!(
+            policy.check_conds("orders", &kvs),
+            Err(PolicyError::Table(String::from("orders")))
+        );
+
+        let conds = [Cond::Or(vec![
+            Cond::eq("id", PLACEHOLDER),
+            Cond::not(Cond::is_null("name")),
+            Cond::raw("deleted_at IS NULL"),
+        ])];
+        assert!(policy.check_conds("users", &conds).is_ok());
+
+        let conds = [Cond::Or(vec![
+            Cond::eq("id", PLACEHOLDER),
+            Cond::in_list("1 = 1 OR id", &["1"]),
+        ])];
+        assert_eq!(
+            policy.check_conds("users", &conds),
+            Err(PolicyError::Column {
+                tbl: String::from("users"),
+                col: String::from("1 = 1 OR id"),
+            })
+        );
+    }
+}
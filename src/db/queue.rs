@@ -0,0 +1,631 @@
+//! Durable job queue on a database table.
+//!
+//! Jobs are claimed with `FOR UPDATE SKIP LOCKED` on MySQL and PostgreSQL, so concurrent workers
+//! process disjoint jobs. A claimed job is leased for the visibility timeout. If the worker neither completes
+//! nor fails it in time, for example because it crashed, the job becomes claimable again.
+//! Failed jobs are retried with the backoff of a [`Policy`], and move to the dead letters once the attempts
+//! are exhausted. Jobs are processed at least once, so handlers should be idempotent.
+
+use std::{
+    fmt,
+    marker::PhantomData,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use sqlx::{AnyConnection, Connection, Row};
+
+use crate::{
+    db::{Cond, Feature, KV, PLACEHOLDER, StmtBuilder, Type, Value, exec, unsupported_dialect},
+    retry::Policy,
+};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+const DEFAULT_VISIBILITY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Errors returned by [`Queue`].
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by sqlx.
+    Sqlx(sqlx::Error),
+    /// The payload could not be serialized.
+    Serde(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sqlx(e) => write!(f, "sqlx error: {}", e),
+            Error::Serde(e) => write!(f, "serde error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sqlx(e) => Some(e),
+            Error::Serde(e) => Some(e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serde(e)
+    }
+}
+
+/// A claimed job.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct Job<T> {
+    /// The id assigned by the database.
+    pub id: i64,
+    /// The payload.
+    pub payload: T,
+    /// The number of attempts, including the current one.
+    pub attempts: u32,
+}
+
+/// A job whose attempts are exhausted, or whose payload could not be deserialized.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct DeadLetter {
+    /// The id assigned by the database.
+    pub id: i64,
+    /// The payload as JSON.
+    pub payload: String,
+    /// The number of attempts.
+    pub attempts: u32,
+    /// The error of the last attempt.
+    pub last_error: Option<String>,
+}
+
+fn now_ms() -> i64 {
+    to_ms(SystemTime::now())
+}
+
+fn to_ms(t: SystemTime) -> i64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+}
+
+fn add_ms(t: i64, d: Duration) -> i64 {
+    t.saturating_add(i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+}
+
+/// A named queue of jobs with payloads of type `T`, stored as JSON.
+///
+/// Several queues may share a table.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, exec, queue::Queue};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// let queue = Queue::<String>::new(String::from("jobs"), Type::SQLite, String::from("emails"));
+/// exec::execute(&mut conn, &queue.ddl().unwrap(), &[]).await.unwrap();
+///
+/// queue.enqueue(&mut conn, &String::from("a@b.c")).await.unwrap();
+///
+/// let mut sent = Vec::new();
+/// let n = queue
+///     .run_once(&mut conn, 10, async |job| {
+///         sent.push(job.payload.clone());
+///         Ok::<(), std::convert::Infallible>(())
+///     })
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(n, 1);
+/// assert_eq!(sent, ["a@b.c"]);
+/// # });
+/// ```
+#[derive(Clone, Debug)]
+pub struct Queue<T> {
+    sb: StmtBuilder,
+    name: String,
+    policy: Policy,
+    visibility_timeout: Duration,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> {
+    /// Creates a new [`Queue`] named `name` in the table `tbl`.
+    ///
+    /// Jobs are attempted at most 5 times with the default backoff of [`Policy`],
+    /// and are leased for 5 minutes when claimed.
+    pub fn new(tbl: String, typ: Type, name: String) -> Queue<T> {
+        Queue {
+            sb: StmtBuilder::new(tbl, typ),
+            name,
+            policy: Policy::new(DEFAULT_MAX_ATTEMPTS),
+            visibility_timeout: DEFAULT_VISIBILITY_TIMEOUT,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the retry policy, which decides the max attempts and the backoff between them.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets how long a claimed job is leased before it can be claimed again.
+    pub fn visibility_timeout(mut self, visibility_timeout: Duration) -> Self {
+        self.visibility_timeout = visibility_timeout;
+        self
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        self.sb.get_tbl()
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        self.sb.get_typ()
+    }
+
+    /// Gets queue name.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets retry policy.
+    pub fn get_policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Gets visibility timeout.
+    pub fn get_visibility_timeout(&self) -> Duration {
+        self.visibility_timeout
+    }
+
+    /// Generates the statement that creates the queue table if it doesn't exist.
+    ///
+    /// Times are stored as milliseconds since the Unix epoch.
+    /// On TiDB, ids are `AUTO_RANDOM` to spread inserts across regions, since jobs are ordered by `run_at` anyway.
+    /// Returns an error on DuckDB, which sqlx has no driver for.
+    pub fn ddl(&self) -> Result<String, sqlx::Error> {
+        let id = match self.get_typ() {
+            Type::MySQL | Type::MariaDB => "id BIGINT AUTO_INCREMENT PRIMARY KEY",
+            // Sequential ids would make every insert hit the same TiKV region.
+            Type::TiDB => "id BIGINT AUTO_RANDOM PRIMARY KEY",
+            Type::PostgreSQL | Type::CockroachDB => "id BIGSERIAL PRIMARY KEY",
+            Type::SQLite => "id INTEGER PRIMARY KEY AUTOINCREMENT",
+            Type::DuckDB => return Err(unsupported_dialect(Type::DuckDB)),
+        };
+        let queue = match self.get_typ() {
+            Type::MySQL | Type::MariaDB | Type::TiDB => "VARCHAR(255)",
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => "TEXT",
+        };
+        Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}, queue {} NOT NULL, payload TEXT NOT NULL, attempts INTEGER NOT NULL DEFAULT 0, run_at BIGINT NOT NULL, locked_until BIGINT, last_error TEXT, failed_at BIGINT)",
+            self.get_tbl(),
+            id,
+            queue
+        ))
+    }
+
+    /// Enqueues a job, which can be claimed immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `conn` - The connection, which may be in the transaction that produces the job.
+    /// * `payload` - The payload.
+    pub async fn enqueue(&self, conn: &mut AnyConnection, payload: &T) -> Result<(), Error> {
+        self.enqueue_delayed(conn, payload, Duration::ZERO).await
+    }
+
+    /// Enqueues a job, which can be claimed after `delay`.
+    pub async fn enqueue_delayed(
+        &self,
+        conn: &mut AnyConnection,
+        payload: &T,
+        delay: Duration,
+    ) -> Result<(), Error> {
+        let stmt = self.sb.build_insert_stmt(&[
+            KV {
+                key: "queue",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "payload",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "run_at",
+                val: PLACEHOLDER,
+            },
+        ]);
+        let args = [
+            Value::from(self.name.as_str()),
+            Value::from(serde_json::to_string(payload)?),
+            Value::from(add_ms(now_ms(), delay)),
+        ];
+        exec::execute(conn, &stmt, &args).await?;
+        Ok(())
+    }
+
+    /// Generates the statement that fetches at most `limit` due jobs, with 2 placeholders for the queue name
+    /// and the current time, and 1 more for the current time on PostgreSQL.
+    ///
+    /// On MySQL and PostgreSQL, the rows are locked with `FOR UPDATE SKIP LOCKED`.
+    /// SQLite serializes writers, so no row lock is needed.
+    pub fn claim_stmt(&self, limit: usize) -> String {
+        let conds = [
+            Cond::eq("queue", PLACEHOLDER),
+            Cond::is_null("failed_at"),
+            Cond::le("run_at", PLACEHOLDER),
+            Cond::Or(vec![
+                Cond::is_null("locked_until"),
+                Cond::le("locked_until", PLACEHOLDER),
+            ]),
+        ];
+        let mut stmt = self
+            .sb
+            .build_query_stmt(&["id", "payload", "attempts"], &conds);
+        stmt.push_str(" ORDER BY run_at, id LIMIT ");
+        stmt.push_str(&limit.to_string());
+        if self.sb.get_dialect().supports(Feature::SkipLocked) {
+            stmt.push_str(" FOR UPDATE SKIP LOCKED");
+        }
+        stmt
+    }
+
+    /// Claims at most `limit` due jobs, leasing them for the visibility timeout and counting an attempt.
+    ///
+    /// Jobs whose payloads can't be deserialized move to the dead letters immediately.
+    ///
+    /// # Returns
+    ///
+    /// * The claimed jobs, which must be passed to [`Queue::complete`] or [`Queue::fail`].
+    pub async fn claim(
+        &self,
+        conn: &mut AnyConnection,
+        limit: usize,
+    ) -> Result<Vec<Job<T>>, Error> {
+        let now = now_ms();
+        let mut tx = conn.begin().await?;
+        let rows = exec::fetch_all(
+            &mut *tx,
+            &self.claim_stmt(limit),
+            &[
+                Value::from(self.name.as_str()),
+                Value::from(now),
+                Value::from(now),
+            ],
+        )
+        .await?;
+        let mut jobs = Vec::with_capacity(rows.len());
+        let mut ids = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: i64 = row.try_get("id")?;
+            let attempts: i64 = row.try_get("attempts")?;
+            let attempts = u32::try_from(attempts + 1).unwrap_or(u32::MAX);
+            match serde_json::from_str(&row.try_get::<String, _>("payload")?) {
+                Ok(payload) => {
+                    ids.push(id.to_string());
+                    jobs.push(Job {
+                        id,
+                        payload,
+                        attempts,
+                    });
+                }
+                Err(e) => self.bury(&mut tx, id, &e.to_string(), now).await?,
+            }
+        }
+        if !ids.is_empty() {
+            let stmt = self.sb.build_update_stmt(
+                &[
+                    KV {
+                        key: "attempts",
+                        val: "attempts + 1",
+                    },
+                    KV {
+                        key: "locked_until",
+                        val: PLACEHOLDER,
+                    },
+                ],
+                &[Cond::in_list("id", &ids)],
+            );
+            let args = [Value::from(add_ms(now, self.visibility_timeout))];
+            exec::execute(&mut *tx, &stmt, &args).await?;
+        }
+        tx.commit().await?;
+        Ok(jobs)
+    }
+
+    /// Moves a job to the dead letters.
+    async fn bury(
+        &self,
+        conn: &mut AnyConnection,
+        id: i64,
+        error: &str,
+        now: i64,
+    ) -> Result<(), sqlx::Error> {
+        let stmt = self.sb.build_update_stmt(
+            &[
+                KV {
+                    key: "locked_until",
+                    val: "NULL",
+                },
+                KV {
+                    key: "last_error",
+                    val: PLACEHOLDER,
+                },
+                KV {
+                    key: "failed_at",
+                    val: PLACEHOLDER,
+                },
+            ],
+            &[Cond::eq("id", PLACEHOLDER)],
+        );
+        let args = [Value::from(error), Value::from(now), Value::from(id)];
+        exec::execute(conn, &stmt, &args).await?;
+        Ok(())
+    }
+
+    /// Deletes a job that has been processed.
+    pub async fn complete(&self, conn: &mut AnyConnection, job: &Job<T>) -> Result<(), Error> {
+        let stmt = self.sb.build_delete_stmt(&[Cond::eq("id", PLACEHOLDER)]);
+        exec::execute(conn, &stmt, &[Value::from(job.id)]).await?;
+        Ok(())
+    }
+
+    /// Records a failed attempt of a job, which is retried after the backoff of the policy,
+    /// or moves to the dead letters if the attempts are exhausted.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the job moved to the dead letters.
+    pub async fn fail(
+        &self,
+        conn: &mut AnyConnection,
+        job: &Job<T>,
+        error: &str,
+    ) -> Result<bool, Error> {
+        let now = now_ms();
+        if job.attempts >= self.policy.get_max_attempts() {
+            self.bury(conn, job.id, error, now).await?;
+            return Ok(true);
+        }
+        let stmt = self.sb.build_update_stmt(
+            &[
+                KV {
+                    key: "run_at",
+                    val: PLACEHOLDER,
+                },
+                KV {
+                    key: "locked_until",
+                    val: "NULL",
+                },
+                KV {
+                    key: "last_error",
+                    val: PLACEHOLDER,
+                },
+            ],
+            &[Cond::eq("id", PLACEHOLDER)],
+        );
+        let args = [
+            Value::from(add_ms(now, self.policy.backoff(job.attempts))),
+            Value::from(error),
+            Value::from(job.id),
+        ];
+        exec::execute(conn, &stmt, &args).await?;
+        Ok(false)
+    }
+
+    /// Claims at most `limit` jobs and processes them one by one,
+    /// completing the jobs `handler` succeeds on and failing the others with the error message.
+    ///
+    /// # Returns
+    ///
+    /// * The number of claimed jobs, so that workers can sleep when it's 0.
+    pub async fn run_once<F, E>(
+        &self,
+        conn: &mut AnyConnection,
+        limit: usize,
+        mut handler: F,
+    ) -> Result<usize, Error>
+    where
+        F: AsyncFnMut(&Job<T>) -> Result<(), E>,
+        E: fmt::Display,
+    {
+        let jobs = self.claim(conn, limit).await?;
+        for job in &jobs {
+            match handler(job).await {
+                Ok(()) => self.complete(conn, job).await?,
+                Err(e) => {
+                    self.fail(conn, job, &e.to_string()).await?;
+                }
+            }
+        }
+        Ok(jobs.len())
+    }
+
+    /// Fetches at most `limit` dead letters, oldest first.
+    pub async fn dead_letters(
+        &self,
+        conn: &mut AnyConnection,
+        limit: usize,
+    ) -> Result<Vec<DeadLetter>, Error> {
+        let mut stmt = self.sb.build_query_stmt(
+            &["id", "payload", "attempts", "last_error"],
+            &[
+                Cond::eq("queue", PLACEHOLDER),
+                Cond::is_not_null("failed_at"),
+            ],
+        );
+        stmt.push_str(" ORDER BY failed_at, id LIMIT ");
+        stmt.push_str(&limit.to_string());
+        let rows = exec::fetch_all(conn, &stmt, &[Value::from(self.name.as_str())]).await?;
+        let dead = rows
+            .iter()
+            .map(|row| {
+                Ok(DeadLetter {
+                    id: row.try_get("id")?,
+                    payload: row.try_get("payload")?,
+                    attempts: u32::try_from(row.try_get::<i64, _>("attempts")?).unwrap_or(u32::MAX),
+                    last_error: row.try_get("last_error")?,
+                })
+            })
+            .collect::<Result<Vec<DeadLetter>, sqlx::Error>>()?;
+        Ok(dead)
+    }
+
+    /// Moves a dead letter back to the queue with its attempts reset.
+    ///
+    /// # Returns
+    ///
+    /// * `false` if there's no such dead letter.
+    pub async fn requeue(&self, conn: &mut AnyConnection, id: i64) -> Result<bool, Error> {
+        let stmt = self.sb.build_update_stmt(
+            &[
+                KV {
+                    key: "attempts",
+                    val: "0",
+                },
+                KV {
+                    key: "run_at",
+                    val: PLACEHOLDER,
+                },
+                KV {
+                    key: "failed_at",
+                    val: "NULL",
+                },
+            ],
+            &[
+                Cond::eq("id", PLACEHOLDER),
+                Cond::eq("queue", PLACEHOLDER),
+                Cond::is_not_null("failed_at"),
+            ],
+        );
+        let args = [
+            Value::from(now_ms()),
+            Value::from(id),
+            Value::from(self.name.as_str()),
+        ];
+        Ok(exec::execute(conn, &stmt, &args).await? > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Serialize};
+    use sqlx::{AnyConnection, Connection};
+
+    use crate::{
+        db::{Type, exec},
+        retry::Policy,
+    };
+
+    use super::Queue;
+
+    #[derive(PartialEq, Serialize, Deserialize, Debug)]
+    struct Email {
+        to: String,
+    }
+
+    #[test]
+    fn test_claim_stmt() {
+        let queue = Queue::<Email>::new(String::from("jobs"), Type::PostgreSQL, String::from("q"));
+        assert_eq!(
+            queue.claim_stmt(10),
+            "SELECT \"id\", \"payload\", \"attempts\" FROM jobs WHERE queue = $1 AND failed_at IS NULL AND run_at <= $2 AND (locked_until IS NULL OR locked_until <= $3) ORDER BY run_at, id LIMIT 10 FOR UPDATE SKIP LOCKED"
+        );
+        assert!(queue.ddl().unwrap().contains("id BIGSERIAL PRIMARY KEY"));
+
+        let queue = Queue::<Email>::new(String::from("jobs"), Type::DuckDB, String::from("q"));
+        assert!(matches!(queue.ddl(), Err(sqlx::Error::Configuration(_))));
+    }
+
+    #[tokio::test]
+    async fn test_queue() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let queue = Queue::<Email>::new(String::from("jobs"), Type::SQLite, String::from("emails"))
+            .policy(Policy::constant(2, Duration::ZERO))
+            .visibility_timeout(Duration::from_secs(60));
+        let other = Queue::<Email>::new(String::from("jobs"), Type::SQLite, String::from("other"));
+        exec::execute(&mut conn, &queue.ddl().unwrap(), &[])
+            .await
+            .unwrap();
+        for to in ["a", "b"] {
+            let email = Email { to: to.to_string() };
+            queue.enqueue(&mut conn, &email).await.unwrap();
+        }
+        let email = Email {
+            to: String::from("later"),
+        };
+        queue
+            .enqueue_delayed(&mut conn, &email, Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert!(other.claim(&mut conn, 10).await.unwrap().is_empty());
+
+        // Claimed jobs are leased
+        let jobs = queue.claim(&mut conn, 1).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].payload.to, "a");
+        assert_eq!(jobs[0].attempts, 1);
+        let n = queue
+            .run_once(&mut conn, 10, async |job| {
+                if job.payload.to == "b" {
+                    Err("smtp unavailable")
+                } else {
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+
+        // "a" is completed, while "b" is retried immediately and exhausts its attempts
+        queue.complete(&mut conn, &jobs[0]).await.unwrap();
+        let jobs = queue.claim(&mut conn, 10).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].attempts, 2);
+        assert!(
+            queue
+                .fail(&mut conn, &jobs[0], "still unavailable")
+                .await
+                .unwrap()
+        );
+        assert!(queue.claim(&mut conn, 10).await.unwrap().is_empty());
+
+        // Undecodable payloads are dead letters as well
+        exec::execute(
+            &mut conn,
+            "INSERT INTO jobs (queue, payload, run_at) VALUES ('emails', 'oops', 0)",
+            &[],
+        )
+        .await
+        .unwrap();
+        assert!(queue.claim(&mut conn, 10).await.unwrap().is_empty());
+
+        let dead = queue.dead_letters(&mut conn, 10).await.unwrap();
+        assert_eq!(dead.len(), 2);
+        assert_eq!(dead[0].payload, r#"{"to":"b"}"#);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].last_error.as_deref(), Some("still unavailable"));
+        assert!(queue.requeue(&mut conn, dead[0].id).await.unwrap());
+        assert!(!queue.requeue(&mut conn, dead[0].id).await.unwrap());
+        let jobs = queue.claim(&mut conn, 10).await.unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].attempts, 1);
+    }
+}
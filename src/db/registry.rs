@@ -0,0 +1,297 @@
+//! Named SQL queries kept outside Rust code.
+//!
+//! [`QueryRegistry`] loads `.sql` files from a directory at runtime,
+//! while the `include_sql!` macro embeds a single file at compile time.
+
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::db::{Type, fingerprint::placeholders};
+
+/// Errors returned when loading queries.
+#[derive(Debug)]
+pub enum Error {
+    /// The file or directory can't be read.
+    Io { path: PathBuf, err: io::Error },
+    /// The query contains a placeholder that isn't supported by the database type.
+    Placeholder { name: String, placeholder: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, err } => write!(f, "failed to read {}: {}", path.display(), err),
+            Error::Placeholder { name, placeholder } => {
+                write!(f, "query {}: unsupported placeholder {}", name, placeholder)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { err, .. } => Some(err),
+            Error::Placeholder { .. } => None,
+        }
+    }
+}
+
+/// Checks that the placeholders in `stmt` follow the style of `typ`.
+///
+/// MySQL only supports `?`, PostgreSQL only supports `$N`,
+/// and SQLite supports both but they can't be mixed in one statement.
+/// Placeholders in quoted literals, quoted identifiers and comments are ignored.
+///
+/// # Arguments
+///
+/// * `stmt` - The SQL statement.
+/// * `typ` - The database type.
+///
+/// # Returns
+///
+/// * The first unsupported placeholder, or [`None`] if all placeholders are supported.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, registry::check_placeholders};
+///
+/// assert_eq!(check_placeholders("SELECT * FROM t WHERE a = $1", Type::PostgreSQL), None);
+/// assert_eq!(check_placeholders("SELECT * FROM t WHERE a = ?", Type::PostgreSQL), Some("?"));
+/// assert_eq!(check_placeholders("SELECT * FROM t WHERE a = ? AND b = $2", Type::SQLite), Some("$2"));
+/// ```
+pub fn check_placeholders(stmt: &str, typ: Type) -> Option<&str> {
+    let found = placeholders(stmt);
+    let first_is_question = found
+        .first()
+        .is_some_and(|(range, _)| &stmt[range.clone()] == "?");
+    found.into_iter().map(|(range, _)| &stmt[range]).find(|p| {
+        let is_question = *p == "?";
+        match typ {
+            Type::MySQL | Type::MariaDB | Type::TiDB => !is_question,
+            Type::PostgreSQL | Type::CockroachDB => is_question,
+            // SQLite and DuckDB accept both, but not mixed in one statement.
+            Type::SQLite | Type::DuckDB => is_question != first_is_question,
+        }
+    })
+}
+
+/// A set of named queries for a database type.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, registry::QueryRegistry};
+///
+/// let mut registry = QueryRegistry::new(Type::PostgreSQL);
+/// registry
+///     .insert(String::from("get_user"), String::from("SELECT * FROM users WHERE id = $1"))
+///     .unwrap();
+///
+/// assert_eq!(registry.get("get_user"), Some("SELECT * FROM users WHERE id = $1"));
+/// assert!(
+///     registry
+///         .insert(String::from("bad"), String::from("SELECT * FROM users WHERE id = ?"))
+///         .is_err()
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct QueryRegistry {
+    typ: Type,
+    queries: HashMap<String, String>,
+}
+
+impl QueryRegistry {
+    /// Creates an empty [`QueryRegistry`] for the database type `typ`.
+    pub fn new(typ: Type) -> QueryRegistry {
+        QueryRegistry {
+            typ,
+            queries: HashMap::new(),
+        }
+    }
+
+    /// Loads every `.sql` file in `dir` and its subdirectories.
+    ///
+    /// A query is named after its path relative to `dir` without the extension,
+    /// for example `users/get_user` for `dir/users/get_user.sql`.
+    /// Leading and trailing whitespaces are trimmed like `include_sql!` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `typ` - The database type, whose placeholder style every query must follow.
+    /// * `dir` - The directory.
+    ///
+    /// # Returns
+    ///
+    /// * The registry.
+    pub fn load_dir<P: AsRef<Path>>(typ: Type, dir: P) -> Result<QueryRegistry, Error> {
+        let mut registry = QueryRegistry::new(typ);
+        registry.load(dir.as_ref(), "")?;
+        Ok(registry)
+    }
+
+    fn load(&mut self, dir: &Path, prefix: &str) -> Result<(), Error> {
+        let io_err = |path: &Path| {
+            let path = path.to_path_buf();
+            move |err| Error::Io { path, err }
+        };
+        for entry in fs::read_dir(dir).map_err(io_err(dir))? {
+            let path = entry.map_err(io_err(dir))?.path();
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let name = format!("{}{}", prefix, stem);
+            if path.is_dir() {
+                self.load(&path, &format!("{}/", name))?;
+            } else if path.extension().is_some_and(|ext| ext == "sql") {
+                let sql = fs::read_to_string(&path).map_err(io_err(&path))?;
+                self.insert(name, sql.trim().to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a query, replacing the query with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The query name.
+    /// * `sql` - The SQL statement, whose placeholders are checked via [`check_placeholders`].
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok`] if the query is added.
+    pub fn insert(&mut self, name: String, sql: String) -> Result<(), Error> {
+        if let Some(placeholder) = check_placeholders(&sql, self.typ) {
+            return Err(Error::Placeholder {
+                placeholder: placeholder.to_string(),
+                name,
+            });
+        }
+        self.queries.insert(name, sql);
+        Ok(())
+    }
+
+    /// Gets a query by name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.queries.get(name).map(String::as_str)
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        &self.typ
+    }
+
+    /// Returns the names of all queries in arbitrary order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.queries.keys().map(String::as_str)
+    }
+
+    /// Returns the number of queries.
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Returns `true` if there are no queries.
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::{Error, QueryRegistry, check_placeholders};
+
+    const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/queries");
+
+    #[test]
+    fn test_check_placeholders() {
+        struct TC<'a> {
+            stmt: &'a str,
+            typ: Type,
+            want: Option<&'a str>,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "SELECT * FROM t WHERE a = ? AND b = '$1'",
+                typ: Type::MySQL,
+                want: None,
+            },
+            TC {
+                stmt: "SELECT * FROM t WHERE a = ? AND b = $1",
+                typ: Type::MySQL,
+                want: Some("$1"),
+            },
+            TC {
+                stmt: "-- Finds a row?\nSELECT * FROM t WHERE a = $1",
+                typ: Type::PostgreSQL,
+                want: None,
+            },
+            TC {
+                stmt: "SELECT * FROM t WHERE a = $1 AND b = ?",
+                typ: Type::PostgreSQL,
+                want: Some("?"),
+            },
+            TC {
+                stmt: "SELECT * FROM t WHERE a = $1 AND b = $2",
+                typ: Type::SQLite,
+                want: None,
+            },
+            TC {
+                stmt: "SELECT * FROM t WHERE a = $1 AND b = ?",
+                typ: Type::SQLite,
+                want: Some("?"),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(check_placeholders(tc.stmt, tc.typ), tc.want, "{}", tc.stmt);
+        }
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let registry = QueryRegistry::load_dir(Type::PostgreSQL, DIR).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            registry.get("get_user"),
+            Some("-- Gets a user by id.\nSELECT id, name FROM users WHERE id = $1")
+        );
+        assert!(registry.get("users/list_active").is_some());
+        assert!(registry.get("missing").is_none());
+        let mut names: Vec<_> = registry.names().collect();
+        names.sort();
+        assert_eq!(names, ["get_user", "users/list_active"]);
+
+        assert!(matches!(
+            QueryRegistry::load_dir(Type::MySQL, DIR),
+            Err(Error::Placeholder { placeholder, .. }) if placeholder == "$1"
+        ));
+        assert!(matches!(
+            QueryRegistry::load_dir(Type::MySQL, "/nonexistent"),
+            Err(Error::Io { .. })
+        ));
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_include_sql() {
+        use crate::db::include_sql;
+
+        const GET_USER: &str = include_sql!(PostgreSQL, "testdata/queries/get_user.sql");
+
+        let registry = QueryRegistry::load_dir(Type::PostgreSQL, DIR).unwrap();
+        assert_eq!(registry.get("get_user"), Some(GET_USER));
+        assert_eq!(
+            include_sql!("testdata/queries/users/list_active.sql"),
+            "SELECT id, name FROM users WHERE active = TRUE ORDER BY id"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Session helpers for multi-tenancy based on PostgreSQL row-level security.
+//!
+//! RLS policies usually read the tenant from a setting, for example
+//! `USING (tenant_id = current_setting('app.tenant_id')::bigint)`,
+//! and are enforced for a role without the `BYPASSRLS` attribute.
+//! [`Session`] describes the setting and the role, and [`transaction`] applies them
+//! at the beginning of a transaction so that they are reset when it ends.
+
+use std::fmt::Write;
+
+use sqlx::{Acquire, Any, AnyConnection, Transaction};
+
+use crate::db::{
+    Value,
+    exec::{self, execute},
+};
+
+/// The setting read by RLS policies for the current tenant, see [`Session::tenant`].
+pub const TENANT_SETTING: &str = "app.tenant_id";
+
+/// The role and settings applied to a transaction.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Value, rls::Session};
+///
+/// let session = Session::new()
+///     .role(String::from("app_user"))
+///     .tenant(String::from("123"));
+///
+/// assert_eq!(
+///     session.stmts(),
+///     [
+///         (String::from("SET LOCAL ROLE \"app_user\""), vec![]),
+///         (
+///             String::from("SELECT set_config($1, $2, true)"),
+///             vec![Value::from("app.tenant_id"), Value::from("123")]
+///         ),
+///     ]
+/// );
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Default)]
+pub struct Session {
+    role: Option<String>,
+    settings: Vec<(String, String)>,
+}
+
+impl Session {
+    /// Creates an empty [`Session`], which changes nothing.
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Switches to the role `role` via `SET LOCAL ROLE`.
+    pub fn role(mut self, role: String) -> Self {
+        self.role = Some(role);
+        self
+    }
+
+    /// Sets the setting `name` to `val` for the transaction, which is what `SET LOCAL name = val` does.
+    pub fn set(mut self, name: String, val: String) -> Self {
+        self.settings.push((name, val));
+        self
+    }
+
+    /// Sets [`TENANT_SETTING`] to `tenant`.
+    pub fn tenant(self, tenant: String) -> Self {
+        self.set(String::from(TENANT_SETTING), tenant)
+    }
+
+    /// Gets the role.
+    pub fn get_role(&self) -> Option<&String> {
+        self.role.as_ref()
+    }
+
+    /// Gets the settings in the order they are applied.
+    pub fn get_settings(&self) -> &[(String, String)] {
+        &self.settings
+    }
+
+    /// Generates the statements that apply the session, along with their arguments.
+    ///
+    /// PostgreSQL doesn't accept placeholders in `SET` statements, so settings are applied via
+    /// `set_config(name, val, true)`, the function form of `SET LOCAL`, to keep values bound as parameters.
+    /// The role is an identifier, which is quoted instead.
+    ///
+    /// # Returns
+    ///
+    /// * The statements and their arguments, in execution order.
+    pub fn stmts(&self) -> Vec<(String, Vec<Value>)> {
+        let role = self.role.iter().map(|role| {
+            let mut stmt = String::from("SET LOCAL ROLE \"");
+            // Writing to a String never fails.
+            let _ = write!(stmt, "{}\"", role.replace('"', "\"\""));
+            (stmt, vec![])
+        });
+        let settings = self.settings.iter().map(|(name, val)| {
+            (
+                String::from("SELECT set_config($1, $2, true)"),
+                vec![Value::from(name.as_str()), Value::from(val.as_str())],
+            )
+        });
+        role.chain(settings).collect()
+    }
+
+    /// Applies the session to the current transaction of `conn`.
+    ///
+    /// Outside of a transaction, PostgreSQL discards local settings immediately, so prefer [`transaction`].
+    pub async fn apply(&self, conn: &mut AnyConnection) -> Result<(), sqlx::Error> {
+        for (stmt, args) in self.stmts() {
+            execute(&mut *conn, &stmt, &args).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` inside a transaction with `session` applied,
+/// see [`exec::transaction`].
+///
+/// # Arguments
+///
+/// * `conn` - The connection or pool of a PostgreSQL database.
+/// * `session` - The session.
+/// * `f` - The function that executes statements via the transaction.
+///
+/// # Returns
+///
+/// * The result of `f`.
+pub async fn transaction<'c, C, F, T, E>(conn: C, session: &Session, f: F) -> Result<T, E>
+where
+    C: Acquire<'c, Database = Any>,
+    F: AsyncFnOnce(&mut Transaction<'c, Any>) -> Result<T, E>,
+    E: From<sqlx::Error>,
+{
+    exec::transaction(conn, async |tx| {
+        session.apply(tx).await?;
+        f(tx).await
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Value;
+
+    use super::Session;
+
+    #[test]
+    fn test_stmts() {
+        assert!(Session::new().stmts().is_empty());
+
+        let session = Session::new()
+            .set(String::from("app.user_id"), String::from("7"))
+            .role(String::from("a\"b"));
+        assert_eq!(session.get_role().unwrap(), "a\"b");
+        assert_eq!(session.get_settings().len(), 1);
+        assert_eq!(
+            session.stmts(),
+            [
+                (String::from("SET LOCAL ROLE \"a\"\"b\""), vec![]),
+                (
+                    String::from("SELECT set_config($1, $2, true)"),
+                    vec![Value::from("app.user_id"), Value::from("7")]
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Helpers that execute statements built by [`StmtBuilder`](crate::db::StmtBuilder) via rusqlite.
+//!
+//! Statements are prepared with [`Connection::prepare_cached`],
+//! so executing the same statement repeatedly doesn't parse it again.
+//! Build statements with [`Type::SQLite`](crate::db::Type::SQLite).
+
+use ::rusqlite::{
+    Connection, OptionalExtension, Result, Row, params_from_iter,
+    types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef},
+};
+
+use crate::db::Value;
+
+impl ToSql for Value {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            Value::Null => ToSqlOutput::Borrowed(ValueRef::Null),
+            Value::Bool(v) => ToSqlOutput::from(*v),
+            Value::Int(v) => ToSqlOutput::from(*v),
+            Value::Float(v) => ToSqlOutput::from(*v),
+            Value::Text(v) => ToSqlOutput::Borrowed(ValueRef::Text(v.as_bytes())),
+            Value::Bytes(v) => ToSqlOutput::Borrowed(ValueRef::Blob(v)),
+        })
+    }
+}
+
+impl FromSql for Value {
+    /// Converts a column value. Text that is not valid UTF-8 is converted to [`Value::Bytes`].
+    fn column_result(v: ValueRef<'_>) -> FromSqlResult<Self> {
+        Ok(match v {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(v) => Value::Int(v),
+            ValueRef::Real(v) => Value::Float(v),
+            ValueRef::Text(v) => match std::str::from_utf8(v) {
+                Ok(v) => Value::from(v),
+                Err(_) => Value::Bytes(v.to_vec()),
+            },
+            ValueRef::Blob(v) => Value::Bytes(v.to_vec()),
+        })
+    }
+}
+
+/// Executes a statement.
+///
+/// # Arguments
+///
+/// * `conn` - The connection.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The number of rows affected.
+pub fn execute(conn: &Connection, stmt: &str, args: &[Value]) -> Result<usize> {
+    conn.prepare_cached(stmt)?.execute(params_from_iter(args))
+}
+
+/// Executes a statement and maps all rows via `f`.
+///
+/// # Arguments
+///
+/// * `conn` - The connection.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+/// * `f` - The function that maps a row.
+///
+/// # Returns
+///
+/// * The mapped rows.
+///
+/// # Examples
+///
+/// ```
+/// use rusqlite::Connection;
+/// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type, Value, rusqlite::{execute, fetch_all}};
+///
+/// let conn = Connection::open_in_memory().unwrap();
+/// execute(&conn, "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)", &[]).unwrap();
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+/// let cols = vec![KV {
+///     key: "name",
+///     val: PLACEHOLDER,
+/// }];
+/// let stmt = sb.build_insert_stmt(&cols);
+/// execute(&conn, &stmt, &[Value::from("foo")]).unwrap();
+/// execute(&conn, &stmt, &[Value::from("bar")]).unwrap();
+///
+/// let stmt = sb.build_query_stmt(&["name"], &Vec::<KV>::new());
+/// let names = fetch_all(&conn, &stmt, &[], |row| row.get::<_, String>(0)).unwrap();
+///
+/// assert_eq!(names, vec!["foo", "bar"]);
+/// ```
+pub fn fetch_all<T, F>(conn: &Connection, stmt: &str, args: &[Value], f: F) -> Result<Vec<T>>
+where
+    F: FnMut(&Row<'_>) -> Result<T>,
+{
+    conn.prepare_cached(stmt)?
+        .query_map(params_from_iter(args), f)?
+        .collect()
+}
+
+/// Executes a statement and maps the first row via `f`.
+///
+/// # Arguments
+///
+/// * `conn` - The connection.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+/// * `f` - The function that maps a row.
+///
+/// # Returns
+///
+/// * The mapped row, or [`None`] if no rows are returned.
+pub fn fetch_optional<T, F>(
+    conn: &Connection,
+    stmt: &str,
+    args: &[Value],
+    f: F,
+) -> Result<Option<T>>
+where
+    F: FnOnce(&Row<'_>) -> Result<T>,
+{
+    conn.prepare_cached(stmt)?
+        .query_row(params_from_iter(args), f)
+        .optional()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::rusqlite::Connection;
+
+    use crate::db::{KV, PLACEHOLDER, StmtBuilder, Type, Value};
+
+    use super::{execute, fetch_all, fetch_optional};
+
+    #[test]
+    fn test_execute_and_fetch() {
+        let conn = Connection::open_in_memory().unwrap();
+        execute(
+            &conn,
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, a, b, c, d, e)",
+            &[],
+        )
+        .unwrap();
+
+        let sb = StmtBuilder::new(String::from("t"), Type::SQLite);
+        let cols: Vec<KV> = ["id", "a", "b", "c", "d", "e"]
+            .into_iter()
+            .map(|key| KV {
+                key,
+                val: PLACEHOLDER,
+            })
+            .collect();
+        let args = vec![
+            Value::from(1),
+            Value::Null,
+            Value::from(true),
+            Value::from(1.5),
+            Value::from("foo"),
+            Value::from(vec![1_u8, 2]),
+        ];
+        assert_eq!(
+            execute(&conn, &sb.build_insert_stmt(&cols), &args).unwrap(),
+            1
+        );
+
+        let row = fetch_all(
+            &conn,
+            &sb.build_query_stmt(&Vec::<&str>::new(), &Vec::<KV>::new()),
+            &[],
+            |row| {
+                (0..6)
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<Result<Vec<_>, _>>()
+            },
+        )
+        .unwrap();
+        // Booleans are stored as integers.
+        let mut want = args;
+        want[2] = Value::from(1);
+        assert_eq!(row, vec![want]);
+
+        let stmt = sb.build_query_stmt(&["d"], &cols[..1]);
+        let get = |row: &::rusqlite::Row<'_>| row.get::<_, String>(0);
+        assert_eq!(
+            fetch_optional(&conn, &stmt, &[Value::from(1)], get).unwrap(),
+            Some(String::from("foo"))
+        );
+        assert_eq!(
+            fetch_optional(&conn, &stmt, &[Value::from(2)], get).unwrap(),
+            None
+        );
+
+        assert!(execute(&conn, "DELETE FROM no_such_tbl", &[]).is_err());
+    }
+}
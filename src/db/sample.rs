@@ -0,0 +1,90 @@
+use std::fmt;
+
+use crate::db::{Cond, Type};
+
+/// How the rows of a query are sampled, see [`StmtBuilder::build_sample_stmt`](crate::db::StmtBuilder::build_sample_stmt).
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Sample {
+    /// About `percent` percent of the rows, from 0 to 100.
+    ///
+    /// PostgreSQL and DuckDB sample the pages of the table via `TABLESAMPLE`, which is fast,
+    /// but returns the rows of a page together, so the sample is clustered.
+    /// Other databases read the whole table and keep each row by chance, like `RAND() < 0.01`.
+    Percent(f64),
+    /// `n` rows picked at random, via `ORDER BY RANDOM() LIMIT n`, or `ORDER BY RAND()` on MySQL, MariaDB and TiDB,
+    /// which sorts the whole table.
+    Rows(u64),
+}
+
+impl Sample {
+    /// Checks that the percentage of [`Sample::Percent`] is a finite number from 0 to 100.
+    pub(crate) fn check(self) -> Result<Sample, InvalidSample> {
+        match self {
+            Sample::Percent(percent) if !(0.0..=100.0).contains(&percent) => {
+                Err(InvalidSample(percent))
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Error returned when the percentage of [`Sample::Percent`] isn't a finite number from 0 to 100.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct InvalidSample(pub f64);
+
+impl fmt::Display for InvalidSample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid sample percentage {}, which must be from 0 to 100",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidSample {}
+
+/// Resolution of the percentages sampled by chance on SQLite, whose `RANDOM()` returns integers.
+const SQLITE_RESOLUTION: f64 = 1_000_000.0;
+
+/// Writes the `TABLESAMPLE` clause placed after the table name, if the database samples pages.
+pub(crate) fn write_after_tbl<W: fmt::Write>(w: &mut W, typ: Type, sample: Sample) -> fmt::Result {
+    match (sample, typ) {
+        (Sample::Percent(percent), Type::PostgreSQL) => {
+            write!(w, " TABLESAMPLE SYSTEM ({})", percent)
+        }
+        (Sample::Percent(percent), Type::DuckDB) => write!(w, " TABLESAMPLE {} PERCENT", percent),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the condition keeping each row by chance, if the database doesn't sample pages.
+pub(crate) fn cond(typ: Type, sample: Sample) -> Option<Cond> {
+    let Sample::Percent(percent) = sample else {
+        return None;
+    };
+    let ratio = percent / 100.0;
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => Some(Cond::Raw(format!("RAND() < {}", ratio))),
+        Type::CockroachDB => Some(Cond::Raw(format!("RANDOM() < {}", ratio))),
+        Type::SQLite => Some(Cond::Raw(format!(
+            "ABS(RANDOM() % {}) < {}",
+            SQLITE_RESOLUTION,
+            (ratio * SQLITE_RESOLUTION).round()
+        ))),
+        Type::PostgreSQL | Type::DuckDB => None,
+    }
+}
+
+/// Writes the clauses placed after the conditions, that is the random order and the limit.
+pub(crate) fn write_suffix<W: fmt::Write>(w: &mut W, typ: Type, sample: Sample) -> fmt::Result {
+    let Sample::Rows(n) = sample else {
+        return Ok(());
+    };
+    let random = if typ.is_mysql_family() {
+        "RAND()"
+    } else {
+        "RANDOM()"
+    };
+    write!(w, " ORDER BY {} LIMIT {}", random, n)
+}
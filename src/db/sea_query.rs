@@ -0,0 +1,345 @@
+//! Conversions from [`StmtBuilder`] inputs into [`sea_query`] statements.
+//!
+//! These helpers let code that is migrating to or from sea-query reuse the same column lists and conditions.
+//! Values in key-value pairs and conditions are SQL fragments, see [`KV`](crate::db::KV).
+//! [`PLACEHOLDER`]s are bound to the given arguments in order,
+//! while other fragments are embedded as custom expressions.
+
+use ::sea_query::{
+    Asterisk, Condition, Expr, ExprTrait, Func, InsertStatement, Query, SelectStatement,
+};
+
+use crate::db::{AsCond, AsKV, CmpOp, Cond, CondRef, PLACEHOLDER, StmtBuilder, Value};
+
+impl From<Value> for ::sea_query::Value {
+    fn from(v: Value) -> Self {
+        match v {
+            Value::Null => ::sea_query::Value::String(None),
+            Value::Bool(v) => v.into(),
+            Value::Int(v) => v.into(),
+            Value::Float(v) => v.into(),
+            Value::Text(v) => v.into(),
+            Value::Bytes(v) => v.into(),
+        }
+    }
+}
+
+/// Compares `col` with `val` via `op`.
+fn cmp(col: Expr, op: CmpOp, val: Expr) -> Expr {
+    match op {
+        CmpOp::Eq => col.eq(val),
+        CmpOp::Ne => col.ne(val),
+        CmpOp::Lt => col.lt(val),
+        CmpOp::Le => col.lte(val),
+        CmpOp::Gt => col.gt(val),
+        CmpOp::Ge => col.gte(val),
+        CmpOp::Like => col.binary(::sea_query::BinOper::Like, val),
+        CmpOp::NotLike => col.binary(::sea_query::BinOper::NotLike, val),
+    }
+}
+
+/// Binds arguments to placeholders in order.
+struct Binder<I> {
+    args: I,
+}
+
+impl<I: Iterator<Item = Value>> Binder<I> {
+    fn val(&mut self, val: &str) -> Option<Expr> {
+        if val == PLACEHOLDER {
+            self.args.next().map(Expr::val)
+        } else {
+            Some(Expr::cust(val.to_string()))
+        }
+    }
+
+    fn cond(&mut self, cond: &Cond) -> Option<Condition> {
+        let expr = match cond {
+            Cond::Cmp { col, op, val } => cmp(Expr::col(col.clone()), *op, self.val(val)?),
+            // The backend of the statement is unknown here, so only LOWER is portable.
+            Cond::CmpCi { col, op, val } => cmp(
+                Func::lower(Expr::col(col.clone())).into(),
+                *op,
+                Func::lower(self.val(val)?).into(),
+            ),
+            Cond::In { col, vals, negated } => {
+                let vals = vals
+                    .iter()
+                    .map(|val| self.val(val))
+                    .collect::<Option<Vec<Expr>>>()?;
+                let col = Expr::col(col.clone());
+                if *negated {
+                    col.is_not_in(vals)
+                } else {
+                    col.is_in(vals)
+                }
+            }
+            Cond::Tuple { .. } => return self.cond(&cond.expand_tuple()),
+            Cond::IsNull { col, negated } => {
+                let col = Expr::col(col.clone());
+                if *negated {
+                    col.is_not_null()
+                } else {
+                    col.is_null()
+                }
+            }
+            Cond::And(conds) | Cond::Or(conds) => {
+                let init = if matches!(cond, Cond::And(_)) {
+                    Condition::all()
+                } else {
+                    Condition::any()
+                };
+                return conds
+                    .iter()
+                    .try_fold(init, |acc, c| Some(acc.add(self.cond(c)?)));
+            }
+            Cond::Not(cond) => return Some(self.cond(cond)?.not()),
+            Cond::Raw(sql) => Expr::cust(sql.clone()),
+        };
+        Some(Condition::all().add(expr))
+    }
+
+    fn conds<P: AsCond>(&mut self, conds: &[P]) -> Option<Condition> {
+        conds.iter().try_fold(Condition::all(), |acc, p| {
+            let cond = match p.as_cond() {
+                CondRef::Eq(kv) => {
+                    Condition::all().add(Expr::col(kv.key.to_string()).eq(self.val(kv.val)?))
+                }
+                CondRef::Tree(cond) => self.cond(cond)?,
+            };
+            Some(acc.add(cond))
+        })
+    }
+
+    /// Returns `Some(())` if all the arguments are consumed.
+    fn finish(mut self) -> Option<()> {
+        self.args.next().is_none().then_some(())
+    }
+}
+
+/// Converts the inputs of [`StmtBuilder::build_query_stmt`] into a [`SelectStatement`].
+///
+/// # Arguments
+///
+/// * `sb` - The statement builder, which provides the table name.
+/// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
+/// * `conds` - The conditions, combined with `AND`.
+/// * `args` - The values bound to the placeholders in the conditions.
+///
+/// # Returns
+///
+/// * The statement, or [`None`] if the number of placeholders doesn't match the number of arguments.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type, Value, sea_query::to_select_stmt};
+/// use sea_query::PostgresQueryBuilder;
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// let conds = [Cond::eq("status", PLACEHOLDER), Cond::is_null("deleted_at")];
+///
+/// let mut select = to_select_stmt(&sb, &["id"], &conds, vec![Value::from("active")]).unwrap();
+/// select.limit(10);
+/// let (stmt, values) = select.build(PostgresQueryBuilder);
+///
+/// assert_eq!(
+///     stmt,
+///     "SELECT \"id\" FROM \"users\" WHERE \"status\" = $1 AND \"deleted_at\" IS NULL LIMIT $2"
+/// );
+/// assert_eq!(values.0.len(), 2);
+/// ```
+pub fn to_select_stmt<S: AsRef<str>, P: AsCond>(
+    sb: &StmtBuilder,
+    cols: &[S],
+    conds: &[P],
+    args: Vec<Value>,
+) -> Option<SelectStatement> {
+    let mut binder = Binder {
+        args: args.into_iter(),
+    };
+    let mut select = Query::select();
+    select.from(sb.get_tbl().clone());
+    for col in cols {
+        match col.as_ref() {
+            "*" => select.column(Asterisk),
+            col => select.column(col.to_string()),
+        };
+    }
+    if cols.is_empty() {
+        select.column(Asterisk);
+    }
+    if !conds.is_empty() {
+        select.cond_where(binder.conds(conds)?);
+    }
+    binder.finish()?;
+    Some(select)
+}
+
+/// Converts the inputs of [`StmtBuilder::build_insert_stmt`] into an [`InsertStatement`].
+///
+/// # Arguments
+///
+/// * `sb` - The statement builder, which provides the table name.
+/// * `cols` - The column names and values.
+/// * `args` - The values bound to the placeholders in the values.
+///
+/// # Returns
+///
+/// * The statement, or [`None`] if the number of placeholders doesn't match the number of arguments.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type, Value, sea_query::to_insert_stmt};
+/// use sea_query::MysqlQueryBuilder;
+///
+/// let sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+/// let cols = [
+///     KV {
+///         key: "name",
+///         val: PLACEHOLDER,
+///     },
+///     KV {
+///         key: "created_at",
+///         val: "NOW()",
+///     },
+/// ];
+///
+/// let insert = to_insert_stmt(&sb, &cols, vec![Value::from("foo")]).unwrap();
+///
+/// assert_eq!(
+///     insert.to_string(MysqlQueryBuilder),
+///     "INSERT INTO `users` (`name`, `created_at`) VALUES ('foo', NOW())"
+/// );
+/// ```
+pub fn to_insert_stmt<P: AsKV>(
+    sb: &StmtBuilder,
+    cols: &[P],
+    args: Vec<Value>,
+) -> Option<InsertStatement> {
+    let mut binder = Binder {
+        args: args.into_iter(),
+    };
+    let vals = cols
+        .iter()
+        .map(|p| binder.val(p.as_kv().val))
+        .collect::<Option<Vec<Expr>>>()?;
+    binder.finish()?;
+    let mut insert = Query::insert();
+    insert
+        .into_table(sb.get_tbl().clone())
+        .columns(cols.iter().map(|p| p.as_kv().key.to_string()));
+    if !vals.is_empty() {
+        insert.values(vals).ok()?;
+    }
+    Some(insert)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::sea_query::{MysqlQueryBuilder, PostgresQueryBuilder, SqliteQueryBuilder};
+
+    use crate::db::{Cond, KV, PLACEHOLDER, StmtBuilder, Type, Value};
+
+    use super::{to_insert_stmt, to_select_stmt};
+
+    #[test]
+    fn test_to_select_stmt() {
+        struct TC<'a> {
+            cols: Vec<&'a str>,
+            conds: Vec<Cond>,
+            args: Vec<Value>,
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+        }
+
+        let test_cases = vec![
+            TC {
+                cols: vec![],
+                conds: vec![],
+                args: vec![],
+                want_mysql: "SELECT * FROM `t`",
+                want_postgresql: "SELECT * FROM \"t\"",
+            },
+            TC {
+                cols: vec!["a", "*"],
+                conds: vec![
+                    Cond::ne("a", PLACEHOLDER),
+                    Cond::lt("b", "1"),
+                    Cond::le("c", "2"),
+                    Cond::gt("d", "3"),
+                    Cond::ge("e", "4"),
+                    Cond::like("f", PLACEHOLDER),
+                ],
+                args: vec![Value::from(1), Value::from("x%")],
+                want_mysql: "SELECT `a`, * FROM `t` WHERE `a` <> 1 AND `b` < (1) AND `c` <= (2) AND `d` > (3) AND `e` >= (4) AND `f` LIKE 'x%'",
+                want_postgresql: "SELECT \"a\", * FROM \"t\" WHERE \"a\" <> 1 AND \"b\" < (1) AND \"c\" <= (2) AND \"d\" > (3) AND \"e\" >= (4) AND \"f\" LIKE 'x%'",
+            },
+            TC {
+                cols: vec!["a"],
+                conds: vec![
+                    Cond::in_list("a", &[PLACEHOLDER, "2"]),
+                    Cond::not_in_list("b", &["3"]),
+                    Cond::Or(vec![Cond::is_null("c"), Cond::is_not_null("d")]),
+                    Cond::not(Cond::raw("e = 1")),
+                    Cond::eq_ci("f", PLACEHOLDER),
+                ],
+                args: vec![Value::Null, Value::from("X")],
+                want_mysql: "SELECT `a` FROM `t` WHERE `a` IN (NULL, 2) AND `b` NOT IN (3) AND (`c` IS NULL OR `d` IS NOT NULL) AND (NOT (e = 1)) AND LOWER(`f`) = LOWER('X')",
+                want_postgresql: "SELECT \"a\" FROM \"t\" WHERE \"a\" IN (NULL, 2) AND \"b\" NOT IN (3) AND (\"c\" IS NULL OR \"d\" IS NOT NULL) AND (NOT (e = 1)) AND LOWER(\"f\") = LOWER('X')",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb = StmtBuilder::new(String::from("t"), Type::MySQL);
+            let select = to_select_stmt(&sb, &tc.cols, &tc.conds, tc.args).unwrap();
+            assert_eq!(select.to_string(MysqlQueryBuilder), tc.want_mysql);
+            assert_eq!(select.to_string(PostgresQueryBuilder), tc.want_postgresql);
+        }
+
+        // Mismatched arguments
+        let sb = StmtBuilder::new(String::from("t"), Type::SQLite);
+        let conds = [KV {
+            key: "a",
+            val: PLACEHOLDER,
+        }];
+        assert!(to_select_stmt(&sb, &["a"], &conds, vec![]).is_none());
+        assert!(
+            to_select_stmt(&sb, &["a"], &conds, vec![Value::from(1), Value::from(2)]).is_none()
+        );
+        assert_eq!(
+            to_select_stmt(&sb, &["a"], &conds, vec![Value::from(vec![1_u8])])
+                .unwrap()
+                .to_string(SqliteQueryBuilder),
+            "SELECT \"a\" FROM \"t\" WHERE \"a\" = x'01'"
+        );
+    }
+
+    #[test]
+    fn test_to_insert_stmt() {
+        let sb = StmtBuilder::new(String::from("t"), Type::PostgreSQL);
+        let cols = [
+            KV {
+                key: "a",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "b",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "c",
+                val: "DEFAULT",
+            },
+        ];
+        let insert = to_insert_stmt(&sb, &cols, vec![Value::from(true), Value::from(1.5)]).unwrap();
+        let (stmt, values) = insert.build(PostgresQueryBuilder);
+        assert_eq!(
+            stmt,
+            "INSERT INTO \"t\" (\"a\", \"b\", \"c\") VALUES ($1, $2, DEFAULT)"
+        );
+        assert_eq!(values.0.len(), 2);
+
+        assert!(to_insert_stmt(&sb, &cols, vec![Value::from(1)]).is_none());
+    }
+}
@@ -0,0 +1,266 @@
+//! Fake data generators for seeds.
+//!
+//! [`Faker`] is seeded explicitly, so the same seed always produces the same dataset.
+
+use std::{fmt::Write, ops::Range};
+
+use rand::{RngExt, SeedableRng, rngs::StdRng};
+
+use crate::{
+    db::{KVBuf, Type, Value},
+    time::civil_from_days,
+};
+
+const FIRST_NAMES: &[&str] = &[
+    "Alice", "Bob", "Carol", "David", "Emma", "Frank", "Grace", "Henry", "Isla", "Jack", "Kate",
+    "Liam", "Mia", "Noah", "Olivia", "Peter", "Quinn", "Ruby", "Sam", "Tina", "Umar", "Vera",
+    "Will", "Xena", "Yuki", "Zoe",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Anderson", "Brown", "Chen", "Davis", "Evans", "Garcia", "Hughes", "Ito", "Johnson", "Kim",
+    "Lopez", "Miller", "Nguyen", "Patel", "Rossi", "Smith", "Taylor", "Walker", "Wilson", "Young",
+];
+
+/// Domains reserved for documentation, so generated addresses never reach real mailboxes.
+const DOMAINS: &[&str] = &["example.com", "example.net", "example.org"];
+
+type Generator<'a> = Box<dyn FnMut(&mut Faker, usize) -> Value + 'a>;
+
+/// A column of generated rows, see [`Faker::rows`].
+pub struct Column<'a> {
+    name: &'a str,
+    generator: Generator<'a>,
+}
+
+impl<'a> Column<'a> {
+    /// Creates a new [`Column`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The column name.
+    /// * `generator` - The function that generates the value from the faker and the 0-based row index.
+    pub fn new<F>(name: &'a str, generator: F) -> Column<'a>
+    where
+        F: FnMut(&mut Faker, usize) -> Value + 'a,
+    {
+        Column {
+            name,
+            generator: Box::new(generator),
+        }
+    }
+
+    /// Gets column name.
+    pub fn get_name(&self) -> &str {
+        self.name
+    }
+}
+
+/// Deterministic fake data generator.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{
+///     Type, Value,
+///     seed::{
+///         Seed,
+///         faker::{Column, Faker},
+///     },
+/// };
+///
+/// let mut faker = Faker::new(42);
+/// let rows = faker.rows(
+///     100,
+///     Type::PostgreSQL,
+///     &mut [
+///         Column::new("id", |_, i| Value::from(i as i64 + 1)),
+///         Column::new("name", |f, _| Value::from(f.name())),
+///         Column::new("email", |f, i| Value::from(f.email(i))),
+///         Column::new("status", |f, _| Value::from(*f.pick(&["active", "banned"]))),
+///     ],
+/// );
+/// let seed = Seed::new(String::from("users")).rows(&rows);
+///
+/// assert_eq!(seed.get_rows().len(), 100);
+/// assert_eq!(Faker::new(42).name(), Faker::new(42).name());
+/// ```
+#[derive(Debug)]
+pub struct Faker {
+    rng: StdRng,
+}
+
+impl Faker {
+    /// Creates a new [`Faker`] from `seed`.
+    pub fn new(seed: u64) -> Faker {
+        Faker {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Generates an integer in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty.
+    pub fn int(&mut self, range: Range<i64>) -> i64 {
+        self.rng.random_range(range)
+    }
+
+    /// Generates `true` with probability `p`.
+    pub fn bool(&mut self, p: f64) -> bool {
+        self.rng.random_bool(p)
+    }
+
+    /// Picks an item of `items`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `items` is empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.rng.random_range(0..items.len())]
+    }
+
+    /// Generates a first name.
+    pub fn first_name(&mut self) -> &'static str {
+        FIRST_NAMES[self.rng.random_range(0..FIRST_NAMES.len())]
+    }
+
+    /// Generates a last name.
+    pub fn last_name(&mut self) -> &'static str {
+        LAST_NAMES[self.rng.random_range(0..LAST_NAMES.len())]
+    }
+
+    /// Generates a full name, for example `Alice Smith`.
+    pub fn name(&mut self) -> String {
+        format!("{} {}", self.first_name(), self.last_name())
+    }
+
+    /// Generates an email address under a domain reserved for documentation.
+    ///
+    /// # Arguments
+    ///
+    /// * `idx` - A number embedded in the local part, for example the row index to make addresses unique.
+    ///
+    /// # Returns
+    ///
+    /// * The email address, for example `alice.smith3@example.com`.
+    pub fn email(&mut self, idx: usize) -> String {
+        let first = self.first_name().to_lowercase();
+        let last = self.last_name().to_lowercase();
+        format!("{}.{}{}@{}", first, last, idx, self.pick(DOMAINS))
+    }
+
+    /// Generates a UTC timestamp formatted as `YYYY-MM-DD HH:MM:SS`,
+    /// which is accepted as a literal by MySQL, PostgreSQL and SQLite.
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - The range of the Unix timestamp in seconds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty.
+    pub fn timestamp(&mut self, range: Range<i64>) -> String {
+        let secs = self.int(range);
+        let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60
+        )
+    }
+
+    /// Generates a random (version 4) UUID in the hyphenated format.
+    pub fn uuid(&mut self) -> String {
+        let mut bytes: [u8; 16] = self.rng.random();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let mut uuid = String::with_capacity(36);
+        for (i, b) in bytes.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                uuid.push('-');
+            }
+            // Writing to a String never fails.
+            let _ = write!(uuid, "{:02x}", b);
+        }
+        uuid
+    }
+
+    /// Generates rows that can be added to a [`Seed`](crate::db::seed::Seed).
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of rows.
+    /// * `typ` - The database type, which determines how values are rendered as literals.
+    /// * `cols` - The columns of each row.
+    ///
+    /// # Returns
+    ///
+    /// * The rows.
+    pub fn rows(&mut self, n: usize, typ: Type, cols: &mut [Column<'_>]) -> Vec<Vec<KVBuf>> {
+        (0..n)
+            .map(|i| {
+                cols.iter_mut()
+                    .map(|col| KVBuf {
+                        key: col.name.to_string(),
+                        val: (col.generator)(self, i).to_literal(typ),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Type, Value};
+
+    use super::{Column, Faker};
+
+    #[test]
+    fn test_faker() {
+        // Same seed, same data
+        assert_eq!(Faker::new(1).uuid(), Faker::new(1).uuid());
+        assert_ne!(Faker::new(1).uuid(), Faker::new(2).uuid());
+
+        let mut faker = Faker::new(1);
+
+        let email = faker.email(7);
+        assert!(email.contains("7@example."), "{}", email);
+
+        let ts = faker.timestamp(0..1);
+        assert_eq!(ts, "1970-01-01 00:00:00");
+        let ts = faker.timestamp(1_700_000_000..1_800_000_000);
+        assert_eq!(ts.len(), 19);
+
+        let uuid = faker.uuid();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+
+        assert!((10..20).contains(&faker.int(10..20)));
+        assert_eq!(*faker.pick(&["a"]), "a");
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut faker = Faker::new(1);
+        let mut cols = [
+            Column::new("id", |_, i| Value::from(i as i64)),
+            Column::new("name", |_, _| Value::from("it's")),
+        ];
+        assert_eq!(cols[0].get_name(), "id");
+
+        let rows = faker.rows(3, Type::PostgreSQL, &mut cols);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[2][0].key, "id");
+        assert_eq!(rows[2][0].val, "2");
+        assert_eq!(rows[2][1].val, "'it''s'");
+    }
+}
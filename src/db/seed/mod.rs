@@ -0,0 +1,432 @@
+//! Declarative seed data for test and staging databases.
+//!
+//! A [`Seed`] lists the rows inserted into a table and the tables it depends on.
+//! [`plan`] orders the seeds by their dependencies and renders the statements,
+//! while `run` executes them inside a transaction via sqlx when the `sqlx` feature is enabled.
+//! With the `faker` feature enabled, rows can be generated via `faker::Faker`.
+
+#[cfg(feature = "faker")]
+pub mod faker;
+
+use std::fmt;
+
+use crate::db::{AsKV, KVBuf, StmtBuilder, Type};
+
+/// The maximum number of rows inserted by a single statement.
+const BATCH_SIZE: usize = 500;
+
+/// Errors returned when seeding a database.
+#[derive(Debug)]
+pub enum Error {
+    /// Error returned by sqlx.
+    #[cfg(feature = "sqlx")]
+    Sqlx(sqlx::Error),
+    /// The dependencies of the given tables form a cycle.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "sqlx")]
+            Error::Sqlx(e) => write!(f, "sqlx error: {}", e),
+            Error::Cycle(tbls) => write!(f, "cyclic seed dependencies: {}", tbls.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "sqlx")]
+            Error::Sqlx(e) => Some(e),
+            Error::Cycle(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+/// Rows to be inserted into a table.
+///
+/// Row values are SQL fragments like the values of [`KV`](crate::db::KV),
+/// see [`Value::to_literal`](crate::db::Value::to_literal) to render values safely.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, seed::Seed};
+///
+/// let seed = Seed::new(String::from("posts"))
+///     .truncate(true)
+///     .depends_on(String::from("users"))
+///     .row(&[KV { key: "id", val: "1" }, KV { key: "user_id", val: "1" }]);
+///
+/// assert_eq!(seed.get_rows().len(), 1);
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Seed {
+    tbl: String,
+    rows: Vec<Vec<KVBuf>>,
+    truncate: bool,
+    deps: Vec<String>,
+}
+
+impl Seed {
+    /// Creates an empty [`Seed`] for the table `tbl`.
+    pub fn new(tbl: String) -> Seed {
+        Seed {
+            tbl,
+            rows: Vec::new(),
+            truncate: false,
+            deps: Vec::new(),
+        }
+    }
+
+    /// Appends a row.
+    pub fn row<P: AsKV>(mut self, row: &[P]) -> Seed {
+        self.rows
+            .push(row.iter().map(|p| KVBuf::from(p.as_kv())).collect());
+        self
+    }
+
+    /// Appends multiple rows, for example the rows generated by `faker::Faker::rows`.
+    pub fn rows<R: AsRef<[P]>, P: AsKV>(self, rows: &[R]) -> Seed {
+        rows.iter().fold(self, |seed, row| seed.row(row.as_ref()))
+    }
+
+    /// Sets whether to delete all existing rows of the table before inserting.
+    pub fn truncate(mut self, truncate: bool) -> Seed {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Adds a table that must be seeded before this one, for example the target of a foreign key.
+    pub fn depends_on(mut self, tbl: String) -> Seed {
+        self.deps.push(tbl);
+        self
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        &self.tbl
+    }
+
+    /// Gets rows.
+    pub fn get_rows(&self) -> &[Vec<KVBuf>] {
+        &self.rows
+    }
+
+    /// Gets whether existing rows are deleted before inserting.
+    pub fn get_truncate(&self) -> bool {
+        self.truncate
+    }
+
+    /// Gets the tables this seed depends on.
+    pub fn get_deps(&self) -> &[String] {
+        &self.deps
+    }
+}
+
+/// Sorts seeds so that every seed comes after the seeds of the tables it depends on.
+///
+/// Seeds without dependencies between each other keep their relative order.
+/// Dependencies on tables without seeds are ignored.
+///
+/// # Arguments
+///
+/// * `seeds` - The seeds.
+///
+/// # Returns
+///
+/// * The sorted seeds, or [`Error::Cycle`] if the dependencies form a cycle.
+pub fn sort(seeds: &[Seed]) -> Result<Vec<&Seed>, Error> {
+    let mut done = vec![false; seeds.len()];
+    let mut sorted = Vec::with_capacity(seeds.len());
+    while sorted.len() < seeds.len() {
+        // Picks the first pending seed whose dependencies are all seeded.
+        let next = (0..seeds.len()).find(|&i| {
+            !done[i]
+                && seeds[i].deps.iter().all(|dep| {
+                    seeds
+                        .iter()
+                        .zip(&done)
+                        .all(|(seed, &done)| done || &seed.tbl != dep || seed.tbl == seeds[i].tbl)
+                })
+        });
+        let Some(i) = next else {
+            let pending = seeds
+                .iter()
+                .zip(&done)
+                .filter(|(_, done)| !**done)
+                .map(|(seed, _)| seed.tbl.clone())
+                .collect();
+            return Err(Error::Cycle(pending));
+        };
+        done[i] = true;
+        sorted.push(&seeds[i]);
+    }
+    Ok(sorted)
+}
+
+/// Renders the statements that apply `seeds`.
+///
+/// Tables are first emptied via `DELETE` in reverse dependency order,
+/// then rows are inserted in dependency order.
+/// Consecutive rows with the same columns are inserted by a single statement,
+/// see [`StmtBuilder::build_batch_insert_stmt`].
+///
+/// # Arguments
+///
+/// * `seeds` - The seeds.
+/// * `typ` - The database type.
+///
+/// # Returns
+///
+/// * The statements, or [`Error::Cycle`] if the dependencies form a cycle.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, Type, seed::{Seed, plan}};
+///
+/// let seeds = [
+///     Seed::new(String::from("posts"))
+///         .depends_on(String::from("users"))
+///         .row(&[KV { key: "user_id", val: "1" }]),
+///     Seed::new(String::from("users"))
+///         .truncate(true)
+///         .row(&[KV { key: "id", val: "1" }])
+///         .row(&[KV { key: "id", val: "2" }]),
+/// ];
+///
+/// assert_eq!(
+///     plan(&seeds, Type::SQLite).unwrap(),
+///     [
+///         "DELETE FROM users",
+///         "INSERT INTO users (\"id\") VALUES (1), (2)",
+///         "INSERT INTO posts (\"user_id\") VALUES (1)",
+///     ]
+/// );
+/// ```
+pub fn plan(seeds: &[Seed], typ: Type) -> Result<Vec<String>, Error> {
+    let sorted = sort(seeds)?;
+    let mut stmts: Vec<String> = sorted
+        .iter()
+        .rev()
+        .filter(|seed| seed.truncate)
+        .map(|seed| StmtBuilder::new(seed.tbl.clone(), typ).build_delete_stmt::<KVBuf>(&[]))
+        .collect();
+    for seed in sorted {
+        let sb = StmtBuilder::new(seed.tbl.clone(), typ);
+        let mut begin = 0;
+        while begin < seed.rows.len() {
+            let same_cols = |row: &Vec<KVBuf>| {
+                row.iter()
+                    .map(|kv| &kv.key)
+                    .eq(seed.rows[begin].iter().map(|kv| &kv.key))
+            };
+            let len = seed.rows[begin..]
+                .iter()
+                .take(BATCH_SIZE)
+                .take_while(|row| same_cols(row))
+                .count();
+            let stmt = sb.build_batch_insert_stmt(&seed.rows[begin..begin + len]);
+            if !stmt.is_empty() {
+                stmts.push(stmt);
+            }
+            begin += len;
+        }
+    }
+    Ok(stmts)
+}
+
+/// Applies `seeds` inside a transaction, which is rolled back if any statement fails.
+///
+/// See [`plan`] for the executed statements.
+///
+/// # Arguments
+///
+/// * `conn` - The connection or pool.
+/// * `typ` - The database type.
+/// * `seeds` - The seeds.
+///
+/// # Returns
+///
+/// * [`Ok`] if all seeds are applied.
+#[cfg(feature = "sqlx")]
+pub async fn run<'c, C>(conn: C, typ: Type, seeds: &[Seed]) -> Result<(), Error>
+where
+    C: sqlx::Acquire<'c, Database = sqlx::Any>,
+{
+    use crate::db::exec::execute;
+
+    let stmts = plan(seeds, typ)?;
+    let mut tx = conn.begin().await?;
+    for stmt in stmts {
+        execute(&mut *tx, &stmt, &[]).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{KV, Type};
+
+    use super::{BATCH_SIZE, Error, Seed, plan, sort};
+
+    fn seed(tbl: &str, deps: &[&str]) -> Seed {
+        deps.iter().fold(Seed::new(tbl.to_string()), |seed, dep| {
+            seed.depends_on(dep.to_string())
+        })
+    }
+
+    #[test]
+    fn test_sort() {
+        struct TC<'a> {
+            seeds: Vec<Seed>,
+            want: Option<Vec<&'a str>>,
+        }
+
+        let test_cases = vec![
+            // Independent seeds keep their order
+            TC {
+                seeds: vec![seed("a", &[]), seed("b", &[])],
+                want: Some(vec!["a", "b"]),
+            },
+            // Dependencies come first
+            TC {
+                seeds: vec![seed("c", &["b"]), seed("b", &["a"]), seed("a", &[])],
+                want: Some(vec!["a", "b", "c"]),
+            },
+            // Unknown and self dependencies are ignored
+            TC {
+                seeds: vec![seed("a", &["x", "a"]), seed("b", &[])],
+                want: Some(vec!["a", "b"]),
+            },
+            // Cycle
+            TC {
+                seeds: vec![seed("a", &["b"]), seed("b", &["a"]), seed("c", &[])],
+                want: None,
+            },
+        ];
+
+        for tc in test_cases {
+            match (sort(&tc.seeds), tc.want) {
+                (Ok(sorted), Some(want)) => {
+                    assert_eq!(sorted.iter().map(|s| s.get_tbl()).collect::<Vec<_>>(), want)
+                }
+                (Err(Error::Cycle(tbls)), None) => assert_eq!(tbls, ["a", "b"]),
+                (got, want) => panic!("got {:?}, want {:?}", got, want),
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let id = |val| KV { key: "id", val };
+        let seeds = [
+            seed("b", &["a"])
+                .truncate(true)
+                .row(&[id("1")])
+                .row(&[id("2"), KV { key: "x", val: "3" }])
+                .row(&[id("4"), KV { key: "x", val: "5" }]),
+            seed("a", &[]).truncate(true).row(&[id("1")]),
+        ];
+
+        assert_eq!(
+            plan(&seeds, Type::MySQL).unwrap(),
+            [
+                "DELETE FROM b",
+                "DELETE FROM a",
+                "INSERT INTO a (`id`) VALUES (1)",
+                "INSERT INTO b (`id`) VALUES (1)",
+                "INSERT INTO b (`id`, `x`) VALUES (2, 3), (4, 5)",
+            ]
+        );
+
+        // Large seeds are split into batches
+        let vals: Vec<String> = (0..BATCH_SIZE + 1).map(|i| i.to_string()).collect();
+        let seeds = [vals
+            .iter()
+            .fold(seed("a", &[]), |seed, val| seed.row(&[id(val)]))];
+        let stmts = plan(&seeds, Type::MySQL).unwrap();
+        assert_eq!(stmts.len(), 2);
+        assert!(stmts[1].ends_with(&format!("VALUES ({})", BATCH_SIZE)));
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_run() {
+        use sqlx::{AnyConnection, Connection, Row};
+
+        use crate::db::exec::{execute, fetch_all};
+
+        use super::run;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        execute(
+            &mut conn,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY)",
+            &[],
+        )
+        .await
+        .unwrap();
+        execute(&mut conn, "INSERT INTO users (id) VALUES (9)", &[])
+            .await
+            .unwrap();
+
+        let users = || {
+            seed("users", &[])
+                .truncate(true)
+                .row(&[KV {
+                    key: "id",
+                    val: "1",
+                }])
+                .row(&[KV {
+                    key: "id",
+                    val: "2",
+                }])
+        };
+        let ids = async |conn: &mut AnyConnection| {
+            fetch_all(conn, "SELECT id FROM users ORDER BY id", &[])
+                .await
+                .unwrap()
+                .iter()
+                .map(|row| row.get::<i64, _>(0))
+                .collect::<Vec<_>>()
+        };
+
+        run(&mut conn, Type::SQLite, &[users()]).await.unwrap();
+        assert_eq!(ids(&mut conn).await, [1, 2]);
+
+        // Failed seeds are rolled back
+        let broken = seed("missing", &["users"]).row(&[KV {
+            key: "id",
+            val: "1",
+        }]);
+        assert!(matches!(
+            run(
+                &mut conn,
+                Type::SQLite,
+                &[
+                    broken,
+                    users().row(&[KV {
+                        key: "id",
+                        val: "3"
+                    }])
+                ]
+            )
+            .await,
+            Err(Error::Sqlx(_))
+        ));
+        assert_eq!(ids(&mut conn).await, [1, 2]);
+    }
+}
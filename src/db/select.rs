@@ -0,0 +1,194 @@
+//! Field selection.
+//!
+//! A [`FieldMap`] maps the fields exposed by an API to columns,
+//! and resolves the fields requested by GraphQL queries or sparse fieldsets like `fields=id,createdAt`
+//! into a [`Selection`], so that only the requested columns are fetched and no other columns can be.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type, select::FieldMap};
+//!
+//! let fields = FieldMap::new()
+//!     .field("id", "id")
+//!     .field("name", "name")
+//!     .field("createdAt", "created_at");
+//! let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+//! let conds = [Cond::eq("id", PLACEHOLDER)];
+//!
+//! let selection = fields.parse("createdAt,id").unwrap();
+//! assert_eq!(selection.get_cols(), vec!["created_at", "id"]);
+//! assert_eq!(
+//!     sb.build_aliased_query_stmt(&selection.get_aliases(), &conds),
+//!     "SELECT \"created_at\" AS \"createdAt\", \"id\" FROM users WHERE id = $1"
+//! );
+//!
+//! // All fields are selected if none is requested.
+//! assert_eq!(fields.parse("").unwrap().get_fields(), vec!["id", "name", "createdAt"]);
+//! assert!(fields.parse("password").is_err());
+//! ```
+
+use std::fmt;
+
+use crate::error::{AppError, Code};
+
+/// Errors returned by [`FieldMap::select`] and [`FieldMap::parse`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The field is not in the map.
+    Field(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Field(field) => write!(f, "field {} is not selectable", field),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for AppError {
+    fn from(e: Error) -> Self {
+        AppError::new(Code::InvalidArgument, e.to_string())
+    }
+}
+
+/// Maps API fields to columns.
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct FieldMap {
+    fields: Vec<(String, String)>,
+}
+
+impl FieldMap {
+    /// Creates an empty [`FieldMap`], which allows nothing.
+    pub fn new() -> FieldMap {
+        FieldMap::default()
+    }
+
+    /// Maps the field `field` to the column `col`, replacing the former mapping of the field.
+    pub fn field(mut self, field: &str, col: &str) -> FieldMap {
+        match self.fields.iter_mut().find(|(f, _)| f == field) {
+            Some((_, c)) => *c = col.to_string(),
+            None => self.fields.push((field.to_string(), col.to_string())),
+        }
+        self
+    }
+
+    /// Gets the column of the field `field`.
+    pub fn get_col(&self, field: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(f, _)| f == field)
+            .map(|(_, col)| col.as_str())
+    }
+
+    /// Resolves the requested fields in order, ignoring duplicates.
+    ///
+    /// All fields are selected in the order they are mapped if `fields` is empty.
+    pub fn select<S: AsRef<str>>(&self, fields: &[S]) -> Result<Selection, Error> {
+        if fields.is_empty() {
+            return Ok(Selection {
+                fields: self.fields.clone(),
+            });
+        }
+        let mut selected: Vec<(String, String)> = Vec::with_capacity(fields.len());
+        for field in fields.iter().map(AsRef::as_ref) {
+            if selected.iter().any(|(f, _)| f == field) {
+                continue;
+            }
+            let col = self
+                .get_col(field)
+                .ok_or_else(|| Error::Field(field.to_string()))?;
+            selected.push((field.to_string(), col.to_string()));
+        }
+        Ok(Selection { fields: selected })
+    }
+
+    /// Resolves comma-separated fields, like the `fields` parameter of sparse fieldsets.
+    pub fn parse(&self, s: &str) -> Result<Selection, Error> {
+        let fields: Vec<&str> = s
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect();
+        self.select(&fields)
+    }
+}
+
+/// Fields resolved by a [`FieldMap`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Selection {
+    fields: Vec<(String, String)>,
+}
+
+impl Selection {
+    /// Gets the selected fields.
+    pub fn get_fields(&self) -> Vec<&str> {
+        self.fields
+            .iter()
+            .map(|(field, _)| field.as_str())
+            .collect()
+    }
+
+    /// Gets the selected columns, which can be passed to [`StmtBuilder::build_query_stmt`](crate::db::StmtBuilder::build_query_stmt).
+    pub fn get_cols(&self) -> Vec<&str> {
+        self.fields.iter().map(|(_, col)| col.as_str()).collect()
+    }
+
+    /// Gets the selected columns and their fields,
+    /// which can be passed to [`StmtBuilder::build_aliased_query_stmt`](crate::db::StmtBuilder::build_aliased_query_stmt)
+    /// so that the columns of rows are named after the fields.
+    pub fn get_aliases(&self) -> Vec<(&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(field, col)| (col.as_str(), field.as_str()))
+            .collect()
+    }
+
+    /// Returns `true` if the field `field` is selected.
+    pub fn contains(&self, field: &str) -> bool {
+        self.fields.iter().any(|(f, _)| f == field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, FieldMap, Selection};
+
+    #[test]
+    fn test_select() {
+        struct TC {
+            fields: &'static [&'static str],
+            want: Result<Vec<(&'static str, &'static str)>, Error>,
+        }
+
+        let map = FieldMap::new()
+            .field("id", "id")
+            .field("name", "username")
+            .field("name", "display_name");
+        let test_cases = vec![
+            TC {
+                fields: &[],
+                want: Ok(vec![("id", "id"), ("display_name", "name")]),
+            },
+            TC {
+                fields: &["name", "id", "name"],
+                want: Ok(vec![("display_name", "name"), ("id", "id")]),
+            },
+            TC {
+                fields: &["id", "display_name"],
+                want: Err(Error::Field(String::from("display_name"))),
+            },
+        ];
+
+        for tc in test_cases {
+            let got = map.select(tc.fields);
+            assert_eq!(
+                got.as_ref().map(Selection::get_aliases),
+                tc.want.as_ref().cloned()
+            );
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Portable ID allocation for services that can't use auto-increment columns.
+//!
+//! IDs are reserved from the database in blocks, and [`HiLo`] hands them out from memory,
+//! so only one round trip is needed per block.
+//! PostgreSQL uses native sequences, while MySQL and SQLite use rows of the [`SEQUENCE_TBL`] table.
+
+use std::{collections::HashMap, ops::Range, sync::Mutex};
+
+use sqlx::{Acquire, Any, AnyPool, Row};
+
+use crate::db::{Cond, KV, PLACEHOLDER, StmtBuilder, Type, Value, exec};
+
+/// The table that stores the next value of each sequence on MySQL and SQLite.
+pub const SEQUENCE_TBL: &str = "_sequences";
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Reserves a block of `block_size` IDs from the sequence `name`, creating the sequence if it doesn't exist.
+///
+/// IDs start from 1. Different blocks never overlap, even across processes,
+/// but IDs are only sortable within a block.
+///
+/// On PostgreSQL, the sequence is created with `block_size` as its increment,
+/// so keep using the same block size for the same name.
+///
+/// # Arguments
+///
+/// * `conn` - The connection or pool.
+/// * `typ` - The database type.
+/// * `name` - The sequence name.
+/// * `block_size` - The number of IDs to reserve, which must be positive.
+///
+/// # Returns
+///
+/// * The reserved IDs.
+pub async fn reserve<'c, C>(
+    conn: C,
+    typ: Type,
+    name: &str,
+    block_size: i64,
+) -> Result<Range<i64>, sqlx::Error>
+where
+    C: Acquire<'c, Database = Any>,
+{
+    let mut conn = conn.acquire().await?;
+    if typ.is_postgres_family() {
+        let seq = quote_ident(name);
+        let stmt = format!(
+            "CREATE SEQUENCE IF NOT EXISTS {} INCREMENT BY {} START WITH 1",
+            seq, block_size
+        );
+        exec::execute(&mut *conn, &stmt, &[]).await?;
+        let row = exec::fetch_optional(
+            &mut *conn,
+            "SELECT nextval(CAST($1 AS regclass))",
+            &[Value::from(seq)],
+        )
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+        let start: i64 = row.try_get(0)?;
+        return Ok(start..start + block_size);
+    }
+
+    let sb = StmtBuilder::new(String::from(SEQUENCE_TBL), typ);
+    let key = [Cond::eq("name", PLACEHOLDER)];
+    let mut tx = conn.begin().await?;
+    let stmt = format!(
+        "CREATE TABLE IF NOT EXISTS {} (name VARCHAR(255) PRIMARY KEY, next_val BIGINT NOT NULL)",
+        SEQUENCE_TBL
+    );
+    exec::execute(&mut *tx, &stmt, &[]).await?;
+    let cols = [
+        KV {
+            key: "name",
+            val: PLACEHOLDER,
+        },
+        KV {
+            key: "next_val",
+            val: "1",
+        },
+    ];
+    let stmt = sb.build_upsert_stmt::<_, &str>(&cols, &[]);
+    exec::execute(&mut *tx, &stmt, &[Value::from(name)]).await?;
+    // The update locks the row until the transaction ends, so concurrent reservations are serialized.
+    let incr = format!("next_val + {}", block_size);
+    let stmt = sb.build_update_stmt(
+        &[KV {
+            key: "next_val",
+            val: &incr,
+        }],
+        &key,
+    );
+    exec::execute(&mut *tx, &stmt, &[Value::from(name)]).await?;
+    let stmt = sb.build_query_stmt(&["next_val"], &key);
+    let row = exec::fetch_optional(&mut *tx, &stmt, &[Value::from(name)])
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+    let end: i64 = row.try_get(0)?;
+    tx.commit().await?;
+    Ok(end - block_size..end)
+}
+
+/// A hi-lo allocator, which caches a block of IDs per sequence.
+///
+/// IDs left in the cached blocks are lost when the allocator is dropped, leaving gaps in the sequences.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, sequence::HiLo};
+/// use sqlx::any::AnyPoolOptions;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let pool = AnyPoolOptions::new()
+///     .max_connections(1)
+///     .connect("sqlite::memory:")
+///     .await
+///     .unwrap();
+/// let hilo = HiLo::new(pool, Type::SQLite, 100);
+///
+/// assert_eq!(hilo.next("orders").await.unwrap(), 1);
+/// assert_eq!(hilo.next("orders").await.unwrap(), 2);
+/// assert_eq!(hilo.next("users").await.unwrap(), 1);
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct HiLo {
+    pool: AnyPool,
+    typ: Type,
+    block_size: i64,
+    blocks: Mutex<HashMap<String, Range<i64>>>,
+}
+
+impl HiLo {
+    /// Creates a new [`HiLo`], which reserves `block_size` IDs at a time.
+    pub fn new(pool: AnyPool, typ: Type, block_size: i64) -> HiLo {
+        HiLo {
+            pool,
+            typ,
+            block_size,
+            blocks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        &self.typ
+    }
+
+    /// Gets block size.
+    pub fn get_block_size(&self) -> i64 {
+        self.block_size
+    }
+
+    fn take(&self, name: &str) -> Option<i64> {
+        let mut blocks = self.blocks.lock().unwrap_or_else(|e| e.into_inner());
+        blocks.get_mut(name).and_then(Iterator::next)
+    }
+
+    /// Allocates the next ID of the sequence `name`.
+    pub async fn next(&self, name: &str) -> Result<i64, sqlx::Error> {
+        if let Some(id) = self.take(name) {
+            return Ok(id);
+        }
+        let mut block = reserve(&self.pool, self.typ, name, self.block_size).await?;
+        let id = block.next().ok_or(sqlx::Error::RowNotFound)?;
+        // A concurrent call may have reserved a block as well, in which case the older one is dropped.
+        let mut blocks = self.blocks.lock().unwrap_or_else(|e| e.into_inner());
+        blocks.insert(name.to_string(), block);
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{AnyConnection, Connection, any::AnyPoolOptions};
+
+    use crate::db::Type;
+
+    use super::{HiLo, reserve};
+
+    #[tokio::test]
+    async fn test_reserve() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        assert_eq!(
+            reserve(&mut conn, Type::SQLite, "a", 10).await.unwrap(),
+            1..11
+        );
+        assert_eq!(
+            reserve(&mut conn, Type::SQLite, "a", 10).await.unwrap(),
+            11..21
+        );
+        assert_eq!(
+            reserve(&mut conn, Type::SQLite, "b", 5).await.unwrap(),
+            1..6
+        );
+        // Changing the block size keeps blocks disjoint
+        assert_eq!(
+            reserve(&mut conn, Type::SQLite, "a", 5).await.unwrap(),
+            21..26
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hilo() {
+        sqlx::any::install_default_drivers();
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let hilo = HiLo::new(pool, Type::SQLite, 2);
+        assert_eq!(hilo.get_block_size(), 2);
+
+        let mut ids = Vec::new();
+        for _ in 0..5 {
+            ids.push(hilo.next("t").await.unwrap());
+        }
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+
+        // Another allocator gets the next block
+        let other = HiLo::new(hilo.pool.clone(), Type::SQLite, 2);
+        assert_eq!(other.next("t").await.unwrap(), 7);
+    }
+}
@@ -0,0 +1,311 @@
+//! Sharding utilities.
+
+use std::{hash::Hasher, ops::Range};
+
+use crate::{
+    db::{StmtBuilder, Type},
+    hash::Fnv1a,
+};
+
+/// Sharding keys that can be routed to hash buckets.
+///
+/// The key is hashed by an explicit byte encoding rather than [`Hash`](std::hash::Hash),
+/// whose output isn't guaranteed to stay the same across Rust versions.
+pub trait ShardKey {
+    /// Writes the bytes that encode the key, where integers are little-endian
+    /// and `usize` and `isize` are widened to 64 bits so the encoding doesn't depend on the platform.
+    fn write_key<H: Hasher>(&self, hasher: &mut H);
+}
+
+macro_rules! impl_shard_key {
+    ($($ty:ty => $as:ty),*) => {$(
+        impl ShardKey for $ty {
+            fn write_key<H: Hasher>(&self, hasher: &mut H) {
+                hasher.write(&(*self as $as).to_le_bytes());
+            }
+        }
+    )*};
+}
+
+impl_shard_key!(
+    i8 => i8, i16 => i16, i32 => i32, i64 => i64, i128 => i128, isize => i64,
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => u64
+);
+
+impl ShardKey for str {
+    fn write_key<H: Hasher>(&self, hasher: &mut H) {
+        hasher.write(self.as_bytes());
+    }
+}
+
+impl ShardKey for String {
+    fn write_key<H: Hasher>(&self, hasher: &mut H) {
+        self.as_str().write_key(hasher);
+    }
+}
+
+impl ShardKey for [u8] {
+    fn write_key<H: Hasher>(&self, hasher: &mut H) {
+        hasher.write(self);
+    }
+}
+
+impl ShardKey for Vec<u8> {
+    fn write_key<H: Hasher>(&self, hasher: &mut H) {
+        self.as_slice().write_key(hasher);
+    }
+}
+
+impl<T: ShardKey + ?Sized> ShardKey for &T {
+    fn write_key<H: Hasher>(&self, hasher: &mut H) {
+        (**self).write_key(hasher);
+    }
+}
+
+/// A shard, which is a table suffix together with the pool that serves it.
+pub struct Shard<P> {
+    suffix: String,
+    pool: P,
+}
+
+impl<P> Shard<P> {
+    /// Gets table suffix.
+    pub fn get_suffix(&self) -> &String {
+        &self.suffix
+    }
+
+    /// Gets pool.
+    pub fn get_pool(&self) -> &P {
+        &self.pool
+    }
+}
+
+/// Routes sharding keys to shards.
+///
+/// Shards can be registered in two ways:
+///
+///   - Key ranges via [`ShardRouter::add_range`]. A key is routed to the shard whose range contains it.
+///   - Hash buckets via [`ShardRouter::add_bucket`]. A key is hashed and routed to bucket `hash % buckets`.
+///
+/// Ranges take precedence over buckets, so buckets can serve as a fallback for keys outside all ranges.
+///
+/// Keys are hashed with FNV-1a over their [`ShardKey`] encoding, which is stable across processes and Rust versions,
+/// so the same key is always routed to the same bucket as long as the buckets stay unchanged.
+pub struct ShardRouter<K, P> {
+    tbl: String,
+    typ: Type,
+    ranges: Vec<(Range<K>, Shard<P>)>,
+    buckets: Vec<Shard<P>>,
+}
+
+impl<K: Ord + ShardKey, P> ShardRouter<K, P> {
+    /// Creates a new [`ShardRouter`], where `tbl` is the base table name and `typ` is the database type.
+    pub fn new(tbl: String, typ: Type) -> ShardRouter<K, P> {
+        ShardRouter {
+            tbl,
+            typ,
+            ranges: Vec::new(),
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Gets base table name.
+    pub fn get_tbl(&self) -> &String {
+        &self.tbl
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        &self.typ
+    }
+
+    /// Registers a shard that serves keys in `range`.
+    ///
+    /// If ranges overlap, the shard registered first wins.
+    pub fn add_range(&mut self, range: Range<K>, suffix: String, pool: P) {
+        self.ranges.push((range, Shard { suffix, pool }));
+    }
+
+    /// Registers a shard as the next hash bucket.
+    pub fn add_bucket(&mut self, suffix: String, pool: P) {
+        self.buckets.push(Shard { suffix, pool });
+    }
+
+    /// Finds the shard that serves `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The sharding key.
+    ///
+    /// # Returns
+    ///
+    /// * The shard, or [`None`] if no range contains the key and no bucket is registered.
+    pub fn shard_for(&self, key: &K) -> Option<&Shard<P>> {
+        if let Some((_, shard)) = self.ranges.iter().find(|(range, _)| range.contains(key)) {
+            return Some(shard);
+        }
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let mut hasher = Fnv1a::default();
+        key.write_key(&mut hasher);
+        let idx = hasher.finish() % self.buckets.len() as u64;
+        self.buckets.get(idx as usize)
+    }
+
+    /// Creates a [`StmtBuilder`] bound to the table of the shard that serves `key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The sharding key.
+    ///
+    /// # Returns
+    ///
+    /// * The statement builder and the pool of the shard,
+    ///   or [`None`] if no range contains the key and no bucket is registered.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Type, shard::ShardRouter};
+    ///
+    /// let mut router = ShardRouter::new(String::from("orders"), Type::MySQL);
+    /// router.add_range(0..1000, String::from("_0"), "pool_0");
+    /// router.add_range(1000..2000, String::from("_1"), "pool_1");
+    ///
+    /// let (sb, pool) = router.builder_for(&1500).unwrap();
+    ///
+    /// assert_eq!(sb.get_tbl(), "orders_1");
+    /// assert_eq!(*pool, "pool_1");
+    /// assert!(router.builder_for(&2000).is_none());
+    /// ```
+    pub fn builder_for(&self, key: &K) -> Option<(StmtBuilder, &P)> {
+        self.shard_for(key).map(|shard| {
+            (
+                StmtBuilder::new(format!("{}{}", self.tbl, shard.suffix), self.typ),
+                &shard.pool,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::ShardRouter;
+
+    static TABLE: &str = "my_tbl";
+
+    #[test]
+    fn test_getter() {
+        let mut router = ShardRouter::new(String::from(TABLE), Type::PostgreSQL);
+        router.add_bucket(String::from("_0"), 0);
+        assert_eq!(TABLE, router.get_tbl());
+        assert_eq!(&Type::PostgreSQL, router.get_typ());
+
+        let shard = router.shard_for(&42).unwrap();
+        assert_eq!("_0", shard.get_suffix());
+        assert_eq!(&0, shard.get_pool());
+    }
+
+    #[test]
+    fn test_builder_for_range() {
+        struct TC {
+            key: i64,
+            want: Option<(&'static str, i32)>,
+        }
+
+        let mut router = ShardRouter::new(String::from(TABLE), Type::MySQL);
+        router.add_range(0..100, String::from("_0"), 0);
+        router.add_range(100..200, String::from("_1"), 1);
+        router.add_range(150..300, String::from("_2"), 2);
+
+        let test_cases = vec![
+            // Lower bound
+            TC {
+                key: 0,
+                want: Some(("my_tbl_0", 0)),
+            },
+            // Upper bound is exclusive
+            TC {
+                key: 100,
+                want: Some(("my_tbl_1", 1)),
+            },
+            // Overlapping ranges
+            TC {
+                key: 150,
+                want: Some(("my_tbl_1", 1)),
+            },
+            TC {
+                key: 299,
+                want: Some(("my_tbl_2", 2)),
+            },
+            // Out of range
+            TC {
+                key: 300,
+                want: None,
+            },
+            TC {
+                key: -1,
+                want: None,
+            },
+        ];
+
+        for tc in test_cases {
+            let got = router
+                .builder_for(&tc.key)
+                .map(|(sb, pool)| (sb.get_tbl().clone(), *pool));
+            assert_eq!(
+                got,
+                tc.want.map(|(tbl, pool)| (String::from(tbl), pool)),
+                "key = {}",
+                tc.key
+            );
+        }
+    }
+
+    #[test]
+    fn test_builder_for_hash() {
+        let mut router = ShardRouter::new(String::from(TABLE), Type::SQLite);
+        assert!(router.builder_for(&"user_1").is_none());
+
+        for i in 0..4 {
+            router.add_bucket(format!("_{}", i), i);
+        }
+
+        let mut hit = [false; 4];
+        for i in 0..64 {
+            let key = format!("user_{}", i);
+            let (sb, pool) = router.builder_for(&key.as_str()).unwrap();
+            assert_eq!(sb.get_tbl(), &format!("{}_{}", TABLE, pool));
+            assert_eq!(sb.get_typ(), &Type::SQLite);
+            // Routing must be deterministic
+            assert_eq!(router.builder_for(&key.as_str()).unwrap().1, pool);
+            hit[*pool] = true;
+        }
+        assert!(hit.iter().all(|h| *h));
+
+        // The buckets are pinned, since they must not change between releases
+        for (key, want) in [("user_1", 2), ("user_2", 3), ("user_3", 0)] {
+            assert_eq!(*router.builder_for(&key).unwrap().1, want, "key = {}", key);
+        }
+        let mut router = ShardRouter::new(String::from(TABLE), Type::SQLite);
+        for i in 0..4 {
+            router.add_bucket(format!("_{}", i), i);
+        }
+        for (key, want) in [(1_i64, 0), (2, 3), (3, 2)] {
+            assert_eq!(*router.builder_for(&key).unwrap().1, want, "key = {}", key);
+        }
+    }
+
+    #[test]
+    fn test_builder_for_range_with_bucket_fallback() {
+        let mut router = ShardRouter::new(String::from(TABLE), Type::MySQL);
+        router.add_range(0..10, String::from("_hot"), "hot");
+        router.add_bucket(String::from("_cold"), "cold");
+
+        assert_eq!(router.builder_for(&5).unwrap().0.get_tbl(), "my_tbl_hot");
+        assert_eq!(router.builder_for(&50).unwrap().0.get_tbl(), "my_tbl_cold");
+    }
+}
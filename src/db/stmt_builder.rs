@@ -1,4 +1,16 @@
-use crate::db::Type;
+use std::{
+    fmt::{self, Write},
+    sync::Arc,
+};
+
+use crate::{
+    db::{
+        AsCond, BuildError, CmpOp, Cond, CondRef, Dialect, Feature, Hint, IndexAdvisor,
+        InvalidSample, OrderBy, Policy, PolicyError, QueryPlan, Sample, Statement, StmtKind,
+        StmtTemplate, TenantScope, Type, Value, Version, file, hint, hook::Hooks, keywords, sample,
+    },
+    page::SortField,
+};
 
 /// Key-value pair that can be used in [`StmtBuilder`].
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
@@ -7,6 +19,49 @@ pub struct KV<'a> {
     pub val: &'a str,
 }
 
+/// Owned version of [`KV`], for keys and values computed at runtime.
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Default)]
+pub struct KVBuf {
+    pub key: String,
+    pub val: String,
+}
+
+impl From<KV<'_>> for KVBuf {
+    fn from(kv: KV<'_>) -> Self {
+        KVBuf {
+            key: kv.key.to_string(),
+            val: kv.val.to_string(),
+        }
+    }
+}
+
+impl<'a> From<&'a KVBuf> for KV<'a> {
+    fn from(kv: &'a KVBuf) -> Self {
+        KV {
+            key: &kv.key,
+            val: &kv.val,
+        }
+    }
+}
+
+/// Types that can be viewed as a [`KV`], so that they can be used in [`StmtBuilder`].
+pub trait AsKV {
+    /// Views `self` as a [`KV`].
+    fn as_kv(&self) -> KV<'_>;
+}
+
+impl AsKV for KV<'_> {
+    fn as_kv(&self) -> KV<'_> {
+        *self
+    }
+}
+
+impl AsKV for KVBuf {
+    fn as_kv(&self) -> KV<'_> {
+        KV::from(self)
+    }
+}
+
 /// Placeholder for binding a parameter.
 pub const PLACEHOLDER: &str = "?";
 
@@ -24,23 +79,67 @@ const PG_PLACEHOLDER_BEGIN_IDX: i32 = 1;
 ///
 /// The placeholders used in different databases are listed as follows:
 ///
-///   - MySQL: `?`
-///   - PostgreSQL: `$N`, where N is the 1-based positional argument index.
-///   - SQLite: `?`
+///   - MySQL, MariaDB and TiDB: `?`
+///   - PostgreSQL and CockroachDB: `$N`, where N is the 1-based positional argument index.
+///   - SQLite and DuckDB: `?`
 ///
-/// If the given database type is PostgreSQL, and the given value is `?`,
+/// If the given database type is PostgreSQL or CockroachDB, and the given value is `?`,
 /// this builder will automatically converts `?` to `$N` based placeholders.
 ///
 /// Consider using [`PLACEHOLDER`] to represent a placeholder.
+///
+/// Columns can be given as [`KV`]s or as owned [`KVBuf`]s, see [`AsKV`].
+/// Conditions can additionally be given as [`Cond`]s, see [`AsCond`].
+///
+/// For optimistic locking, see [`StmtBuilder::set_version_col`].
+///
+/// To accept column names from untrusted input, see [`StmtBuilder::set_policy`].
+///
+/// For multi-tenancy, see [`StmtBuilder::set_tenant`].
+///
+/// For cross-cutting concerns like query tagging, see [`StmtBuilder::before_build`] and [`StmtBuilder::on_build`].
+///
+/// To find missing indexes, see [`StmtBuilder::set_index_advisor`].
+///
+/// A configured builder can be frozen into a [`StmtTemplate`](crate::db::StmtTemplate)
+/// and shared across threads, see [`StmtBuilder::freeze`].
+///
+/// With the `validate` feature enabled, debug builds parse every statement returned by the `build_*_stmt` methods
+/// and panic if it's malformed, for example because of a broken raw fragment.
+/// Enable the feature for tests only, since parsing is not free.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
 pub struct StmtBuilder {
     tbl: String,
     typ: Type,
+    version_col: Option<String>,
+    policy: Option<Arc<Policy>>,
+    tenant: Option<TenantScope>,
+    version: Option<Version>,
+    as_of: Option<String>,
+    hints: Vec<Hint>,
+    partitions: Vec<String>,
+    hooks: Hooks,
+    advisor: Option<Arc<IndexAdvisor>>,
+    quote_reserved: bool,
 }
 
 impl StmtBuilder {
     /// Creates a new [`StmtBuilder`], where `tbl` is the table name and `typ` is the database type.
     pub fn new(tbl: String, typ: Type) -> StmtBuilder {
-        StmtBuilder { tbl, typ }
+        StmtBuilder {
+            tbl,
+            typ,
+            version_col: None,
+            policy: None,
+            tenant: None,
+            version: None,
+            as_of: None,
+            hints: Vec::new(),
+            partitions: Vec::new(),
+            hooks: Hooks::default(),
+            advisor: None,
+            quote_reserved: false,
+        }
     }
 
     /// Gets table name.
@@ -53,47 +152,596 @@ impl StmtBuilder {
         &self.typ
     }
 
-    fn escape_col(&self, col: &str) -> String {
+    /// Gets version column.
+    pub fn get_version_col(&self) -> Option<&String> {
+        self.version_col.as_ref()
+    }
+
+    /// Sets version column for optimistic locking.
+    ///
+    /// When set, [`StmtBuilder::build_update_stmt`] appends `version = version + 1` to the assignments
+    /// and `version = ?` to the conditions, where the placeholder should be bound to the version read before.
+    /// With the `sqlx` feature enabled, use `exec::execute_versioned` to execute the statement,
+    /// which reports a stale version if no rows are affected.
+    pub fn set_version_col(&mut self, col: Option<String>) {
+        self.version_col = col;
+    }
+
+    /// Gets server version.
+    pub fn get_server_version(&self) -> Option<Version> {
+        self.version
+    }
+
+    /// Sets server version, so that statements use the syntax supported by the version.
+    ///
+    /// Without the version, statements use the syntax supported by most versions.
+    /// With the `sqlx` feature enabled, the version can be queried via `exec::server_version`.
+    pub fn set_server_version(&mut self, version: Option<Version>) {
+        self.version = version;
+    }
+
+    /// Gets the dialect of the database type and server version.
+    pub fn get_dialect(&self) -> Dialect {
+        let dialect = Dialect::new(self.typ);
+        match self.version {
+            Some(version) => dialect.version(version),
+            None => dialect,
+        }
+    }
+
+    /// Gets the `AS OF SYSTEM TIME` expression.
+    pub fn get_as_of_system_time(&self) -> Option<&String> {
+        self.as_of.as_ref()
+    }
+
+    /// Sets the `AS OF SYSTEM TIME` expression of queries and counts, like `'-10s'` or `follower_read_timestamp()`,
+    /// so that they read historical data without conflicting with concurrent writes on CockroachDB.
+    ///
+    /// The expression is written as is. Other databases don't support historical reads,
+    /// see [`Feature::AsOfSystemTime`], so the clause is omitted there and the latest data is read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("orders"), Type::CockroachDB);
+    /// sb.set_as_of_system_time(Some(String::from("follower_read_timestamp()")));
+    ///
+    /// assert_eq!(
+    ///     sb.build_query_stmt(&["id"], &[Cond::eq("status", PLACEHOLDER)]),
+    ///     "SELECT \"id\" FROM orders AS OF SYSTEM TIME follower_read_timestamp() WHERE status = $1"
+    /// );
+    /// ```
+    pub fn set_as_of_system_time(&mut self, expr: Option<String>) {
+        self.as_of = expr;
+    }
+
+    /// Gets the optimizer hints.
+    pub fn get_hints(&self) -> &[Hint] {
+        &self.hints
+    }
+
+    /// Sets the optimizer hints of queries, counts, updates and deletes, see [`Hint`].
+    ///
+    /// Index hints refer to the table of the builder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use sainnhe_common::db::{Cond, Hint, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let hints = vec![
+    ///     Hint::Index(String::from("idx_email")),
+    ///     Hint::MaxExecutionTime(Duration::from_secs(1)),
+    /// ];
+    /// let conds = [Cond::eq("email", PLACEHOLDER)];
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+    /// sb.set_hints(hints.clone());
+    /// assert_eq!(
+    ///     sb.build_query_stmt(&["id"], &conds),
+    ///     "SELECT /*+ INDEX(users idx_email) MAX_EXECUTION_TIME(1000) */ `id` FROM users WHERE email = ?"
+    /// );
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+    /// sb.set_hints(hints.clone());
+    /// assert_eq!(
+    ///     sb.build_query_stmt(&["id"], &conds),
+    ///     "/*+ IndexScan(users idx_email) Set(statement_timeout \"1000ms\") */ SELECT \"id\" FROM users WHERE email = $1"
+    /// );
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+    /// sb.set_hints(hints);
+    /// assert_eq!(
+    ///     sb.build_query_stmt(&["id"], &conds),
+    ///     "SELECT \"id\" FROM users INDEXED BY idx_email WHERE email = ?"
+    /// );
+    /// ```
+    pub fn set_hints(&mut self, hints: Vec<Hint>) {
+        self.hints = hints;
+    }
+
+    /// Gets the selected partitions.
+    pub fn get_partitions(&self) -> &[String] {
+        &self.partitions
+    }
+
+    /// Sets the partitions that the statements read and write, like `PARTITION (p0, p1)` on MySQL, MariaDB and TiDB,
+    /// so that the other partitions are neither scanned nor locked.
+    ///
+    /// Other databases don't select partitions by name, so the partitions are skipped there.
+    /// On PostgreSQL, build the statements on the partition table itself instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("events"), Type::MySQL);
+    /// sb.set_partitions(vec![String::from("p2024")]);
+    ///
+    /// assert_eq!(
+    ///     sb.build_query_stmt(&["id"], &[Cond::eq("kind", PLACEHOLDER)]),
+    ///     "SELECT `id` FROM events PARTITION (p2024) WHERE kind = ?"
+    /// );
+    /// ```
+    pub fn set_partitions(&mut self, partitions: Vec<String>) {
+        self.partitions = partitions;
+    }
+
+    fn supports_upsert(&self) -> bool {
+        self.get_dialect().check(Feature::Upsert).is_ok()
+    }
+
+    /// Gets the policy.
+    pub fn get_policy(&self) -> Option<&Policy> {
+        self.policy.as_deref()
+    }
+
+    /// Sets the policy that restricts the table and column names.
+    ///
+    /// The policy is enforced by the `try_build_*` methods, which fail if the table
+    /// or any column of the columns and conditions is not allowed.
+    /// The `build_*` methods don't check the policy.
+    /// [`Cond::Raw`] fragments and the version column are not checked either,
+    /// since they are written by developers rather than taken from input.
+    pub fn set_policy(&mut self, policy: Option<Arc<Policy>>) {
+        self.policy = policy;
+    }
+
+    /// Gets the tenant scope.
+    pub fn get_tenant(&self) -> Option<&TenantScope> {
+        self.tenant.as_ref()
+    }
+
+    /// Sets the tenant scope applied to every statement, see [`TenantScope`].
+    ///
+    /// The policy still checks the table name without the tenant prefix or schema,
+    /// and the tenant column is not checked.
+    pub fn set_tenant(&mut self, tenant: Option<TenantScope>) {
+        self.tenant = tenant;
+    }
+
+    /// Returns `true` if reserved words are quoted, see [`StmtBuilder::set_quote_reserved`].
+    pub fn is_quote_reserved(&self) -> bool {
+        self.quote_reserved
+    }
+
+    /// Sets whether the table name and the columns of conditions are quoted if they're reserved words
+    /// of the database type, like `order` and `group`, see [`keywords`](crate::db::keywords).
+    ///
+    /// Each dot-separated segment is checked separately. Other names are written as is,
+    /// and the table name is not checked with a tenant scope.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("order"), Type::PostgreSQL);
+    /// sb.set_quote_reserved(true);
+    ///
+    /// assert_eq!(
+    ///     sb.build_count_stmt(&[Cond::eq("user", PLACEHOLDER), Cond::is_null("o.group")]),
+    ///     "SELECT COUNT(*) FROM \"order\" WHERE \"user\" = $1 AND o.\"group\" IS NULL"
+    /// );
+    /// ```
+    pub fn set_quote_reserved(&mut self, quote: bool) {
+        self.quote_reserved = quote;
+    }
+
+    /// Gets the index advisor.
+    pub fn get_index_advisor(&self) -> Option<&IndexAdvisor> {
+        self.advisor.as_deref()
+    }
+
+    /// Sets the index advisor that records the columns of the statements, see [`IndexAdvisor`].
+    ///
+    /// Recording takes a lock per statement, so enable it while analyzing a workload rather than permanently.
+    pub fn set_index_advisor(&mut self, advisor: Option<Arc<IndexAdvisor>>) {
+        self.advisor = advisor;
+    }
+
+    /// Records the conditions and the sort keys of a statement if an index advisor is set.
+    pub(crate) fn record<P: AsCond>(&self, conds: &[P], order: &[SortField]) {
+        if let Some(advisor) = &self.advisor {
+            let tenant = self.tenant.as_ref().and_then(TenantScope::as_cond);
+            advisor.record(
+                &self.tbl,
+                conds.iter().map(AsCond::as_cond).chain(tenant),
+                order,
+            );
+        }
+    }
+
+    /// Registers a pre-build hook, which adds conditions to the statements with conditions,
+    /// that is queries, counts, updates and deletes.
+    ///
+    /// The hook is called with the kind of statement and the conditions added by the former hooks,
+    /// which are combined with the given conditions via `AND`.
+    /// Like [`Rewriter`](crate::db::Rewriter)s, hooks should add literals rather than placeholders.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, StmtKind, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+    /// sb.before_build(|kind, conds| {
+    ///     if kind != StmtKind::Delete {
+    ///         conds.push(Cond::is_null("deleted_at"));
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(
+    ///     sb.build_count_stmt(&[Cond::eq("name", PLACEHOLDER)]),
+    ///     "SELECT COUNT(*) FROM users WHERE name = $1 AND deleted_at IS NULL"
+    /// );
+    /// ```
+    pub fn before_build<F>(&mut self, hook: F)
+    where
+        F: Fn(StmtKind, &mut Vec<Cond>) + Send + Sync + 'static,
+    {
+        self.hooks.pre.push(Arc::new(hook));
+    }
+
+    /// Registers a post-build hook, which is called with every statement returned by the `build_*_stmt` methods,
+    /// for example to tag statements with comments or to capture them for auditing.
+    ///
+    /// The `*_into` methods write statements into the given writers, so they don't call post-build hooks.
+    /// Hooks are kept when the builder is cloned, scoped or frozen, and compared by identity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+    /// sb.on_build(|stmt| stmt.sql.push_str(" /* app='billing' */"));
+    ///
+    /// assert_eq!(
+    ///     sb.build_delete_stmt(&[Cond::eq("id", PLACEHOLDER)]),
+    ///     "DELETE FROM users WHERE id = ? /* app='billing' */"
+    /// );
+    /// ```
+    pub fn on_build<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Statement) + Send + Sync + 'static,
+    {
+        self.hooks.post.push(Arc::new(hook));
+    }
+
+    /// Returns a copy of the builder scoped to `tenant`, which is handy when the tenant changes per call.
+    pub fn scoped(&self, tenant: TenantScope) -> StmtBuilder {
+        StmtBuilder {
+            tenant: Some(tenant),
+            ..self.clone()
+        }
+    }
+
+    /// Freezes the builder into a [`StmtTemplate`], which can be cloned cheaply and shared across threads.
+    pub fn freeze(self) -> StmtTemplate {
+        StmtTemplate::from(self)
+    }
+
+    fn write_tbl<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        match &self.tenant {
+            Some(tenant) => tenant.write_tbl(w, &self.tbl),
+            None => self.write_ident(w, &self.tbl),
+        }
+    }
+
+    /// Writes an unquoted name, quoting its reserved segments if [`StmtBuilder::set_quote_reserved`] is enabled.
+    fn write_ident<W: fmt::Write>(&self, w: &mut W, ident: &str) -> fmt::Result {
+        if !self.quote_reserved {
+            return w.write_str(ident);
+        }
+        Self::write_joined(w, ".", ident.split('.'), |w, segment| {
+            if keywords::is_reserved(self.typ, segment) {
+                self.write_col(w, segment)
+            } else {
+                w.write_str(segment)
+            }
+        })
+    }
+
+    /// Writes the table name followed by the selected partitions if any.
+    fn write_target<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        self.write_tbl(w)?;
+        if self.partitions.is_empty() || !self.typ.is_mysql_family() {
+            return Ok(());
+        }
+        w.write_str(" PARTITION (")?;
+        Self::write_joined(w, ", ", &self.partitions, |w, p| w.write_str(p))?;
+        w.write_char(')')
+    }
+
+    /// Writes the leading keyword of a statement along with the hints around it.
+    fn write_keyword<W: fmt::Write>(&self, w: &mut W, keyword: &str, select: bool) -> fmt::Result {
+        if self.hints.is_empty() {
+            return w.write_str(keyword);
+        }
+        let tbl = self.hint_tbl()?;
+        hint::write_prefix(w, self.typ, &tbl, &self.hints)?;
+        w.write_str(keyword)?;
+        hint::write_after_keyword(w, self.typ, &tbl, &self.hints, select)
+    }
+
+    /// Returns the table name referred to by hints, which is without its schema.
+    fn hint_tbl(&self) -> Result<String, fmt::Error> {
+        let mut tbl = String::new();
+        self.write_tbl(&mut tbl)?;
+        Ok(tbl.rsplit('.').next().unwrap_or_default().to_string())
+    }
+
+    /// Writes the `FROM` clause of queries, including `AS OF SYSTEM TIME` if supported.
+    fn write_from<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        w.write_str(" FROM ")?;
+        self.write_target(w)?;
+        hint::write_after_tbl(w, self.typ, &self.hints)?;
+        match &self.as_of {
+            Some(expr) if self.get_dialect().supports(Feature::AsOfSystemTime) => {
+                w.write_str(" AS OF SYSTEM TIME ")?;
+                w.write_str(expr)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn write_col<W: fmt::Write>(&self, w: &mut W, col: &str) -> fmt::Result {
         if col == "*" {
-            return col.to_string();
+            return w.write_str(col);
         }
+        let quote = match self.typ {
+            Type::MySQL | Type::MariaDB | Type::TiDB => '`',
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => '"',
+        };
+        w.write_char(quote)?;
+        w.write_str(col)?;
+        w.write_char(quote)
+    }
+
+    fn write_val<W: fmt::Write>(&self, w: &mut W, idx: &mut i32, val: &str) -> fmt::Result {
         match self.typ {
-            Type::MySQL => format!("`{}`", col),
-            Type::PostgreSQL | Type::SQLite => format!("\"{}\"", col),
+            Type::PostgreSQL | Type::CockroachDB if val == PLACEHOLDER => {
+                write!(w, "${}", *idx)?;
+                *idx += 1;
+                Ok(())
+            }
+            _ => w.write_str(val),
         }
     }
 
-    fn convert_placeholder(&self, begin_idx: &mut i32, val: &str) -> String {
-        match self.typ {
-            Type::MySQL | Type::SQLite => val.to_string(),
-            Type::PostgreSQL => {
-                if val == PLACEHOLDER {
-                    *begin_idx += 1;
-                    format!("${}", *begin_idx - 1)
-                } else {
-                    val.to_string()
+    fn write_conds<'a, W, I>(&self, w: &mut W, idx: &mut i32, conds: I) -> fmt::Result
+    where
+        W: fmt::Write,
+        I: IntoIterator<Item = CondRef<'a>>,
+    {
+        for (i, cond) in conds.into_iter().enumerate() {
+            w.write_str(if i == 0 { " WHERE " } else { " AND " })?;
+            match cond {
+                CondRef::Eq(kv) => {
+                    self.write_ident(w, kv.key)?;
+                    w.write_str(" = ")?;
+                    self.write_val(w, idx, kv.val)?;
                 }
+                // The top level conditions are already combined with AND.
+                CondRef::Tree(Cond::And(conds)) if !conds.is_empty() => {
+                    Self::write_joined(w, " AND ", conds, |w, c| self.write_cond(w, idx, c))?;
+                }
+                CondRef::Tree(cond) => self.write_cond(w, idx, cond)?,
             }
         }
+        Ok(())
     }
 
-    fn build_conds(&self, begin_idx: &mut i32, conds: &[KV]) -> String {
-        if conds.is_empty() {
-            String::new()
-        } else {
-            format!(
-                " WHERE {}",
-                conds
-                    .iter()
-                    .map(|kv| format!(
-                        "{} = {}",
-                        kv.key,
-                        self.convert_placeholder(begin_idx, kv.val)
-                    ))
-                    .collect::<Vec<String>>()
-                    .join(" AND ")
-            )
+    fn write_cond<W: fmt::Write>(&self, w: &mut W, idx: &mut i32, cond: &Cond) -> fmt::Result {
+        match cond {
+            Cond::Cmp { col, op, val } => {
+                self.write_ident(w, col)?;
+                write!(w, " {} ", op.as_str())?;
+                self.write_val(w, idx, val)
+            }
+            Cond::CmpCi { col, op, val } => match self.typ {
+                Type::MySQL | Type::MariaDB | Type::TiDB => {
+                    self.write_ident(w, col)?;
+                    write!(w, " COLLATE utf8mb4_general_ci {} ", op.as_str())?;
+                    self.write_val(w, idx, val)
+                }
+                Type::PostgreSQL | Type::CockroachDB | Type::DuckDB
+                    if matches!(op, CmpOp::Like | CmpOp::NotLike) =>
+                {
+                    let not = if *op == CmpOp::NotLike { "NOT " } else { "" };
+                    self.write_ident(w, col)?;
+                    write!(w, " {}ILIKE ", not)?;
+                    self.write_val(w, idx, val)
+                }
+                _ => {
+                    w.write_str("LOWER(")?;
+                    self.write_ident(w, col)?;
+                    write!(w, ") {} LOWER(", op.as_str())?;
+                    self.write_val(w, idx, val)?;
+                    w.write_char(')')
+                }
+            },
+            Cond::In { vals, negated, .. } if vals.is_empty() => {
+                w.write_str(if *negated { "1 = 1" } else { "1 = 0" })
+            }
+            Cond::In { col, vals, negated } => {
+                self.write_ident(w, col)?;
+                write!(w, " {}IN (", if *negated { "NOT " } else { "" })?;
+                Self::write_joined(w, ", ", vals, |w, val| self.write_val(w, idx, val))?;
+                w.write_char(')')
+            }
+            Cond::Tuple { rows, negated, .. } if rows.is_empty() => {
+                w.write_str(if *negated { "1 = 1" } else { "1 = 0" })
+            }
+            Cond::Tuple { .. } if !self.get_dialect().supports(Feature::RowValues) => {
+                self.write_cond(w, idx, &cond.expand_tuple())
+            }
+            Cond::Tuple {
+                cols,
+                rows,
+                negated,
+            } => {
+                w.write_char('(')?;
+                Self::write_joined(w, ", ", cols, |w, col| self.write_ident(w, col))?;
+                w.write_str(match (rows.len(), negated) {
+                    (1, false) => ") = ",
+                    (1, true) => ") <> ",
+                    (_, false) => ") IN (",
+                    (_, true) => ") NOT IN (",
+                })?;
+                // SQLite accepts lists of row values as VALUES only.
+                if rows.len() > 1 && self.typ == Type::SQLite {
+                    w.write_str("VALUES ")?;
+                }
+                Self::write_joined(w, ", ", rows, |w, row| {
+                    w.write_char('(')?;
+                    Self::write_joined(w, ", ", row, |w, val| self.write_val(w, idx, val))?;
+                    w.write_char(')')
+                })?;
+                if rows.len() > 1 {
+                    w.write_char(')')?;
+                }
+                Ok(())
+            }
+            Cond::IsNull { col, negated } => {
+                self.write_ident(w, col)?;
+                write!(w, " IS {}NULL", if *negated { "NOT " } else { "" })
+            }
+            Cond::And(conds) if conds.is_empty() => w.write_str("1 = 1"),
+            Cond::Or(conds) if conds.is_empty() => w.write_str("1 = 0"),
+            Cond::And(conds) | Cond::Or(conds) if conds.len() == 1 => {
+                self.write_cond(w, idx, &conds[0])
+            }
+            Cond::And(conds) | Cond::Or(conds) => {
+                let sep = if matches!(cond, Cond::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                w.write_char('(')?;
+                Self::write_joined(w, sep, conds, |w, c| self.write_cond(w, idx, c))?;
+                w.write_char(')')
+            }
+            Cond::Not(cond) => {
+                w.write_str("NOT (")?;
+                self.write_cond(w, idx, cond)?;
+                w.write_char(')')
+            }
+            Cond::Raw(sql) => write!(w, "({})", sql),
+        }
+    }
+
+    /// Returns `true` if any post-build hook is registered.
+    pub(crate) fn has_post_hooks(&self) -> bool {
+        !self.hooks.post.is_empty()
+    }
+
+    /// Returns a copy of the builder without the post-build hooks,
+    /// whose statements can be cached and finished by [`StmtBuilder::finish`] on every use.
+    pub(crate) fn without_post_hooks(&self) -> StmtBuilder {
+        let mut sb = self.clone();
+        sb.hooks.post.clear();
+        sb
+    }
+
+    /// Runs the post-build hooks on the statement and validates it.
+    pub(crate) fn finish(&self, kind: StmtKind, stmt: String) -> String {
+        let stmt = self.hooks.finish(kind, &self.tbl, stmt);
+        self.debug_validate(&stmt);
+        stmt
+    }
+
+    /// Panics if the statement can't be parsed, see [`validate`](crate::db::validate).
+    #[cfg(all(feature = "validate", debug_assertions))]
+    fn debug_validate(&self, stmt: &str) {
+        if stmt.is_empty() {
+            return;
         }
+        // The parser doesn't know the extensions of CockroachDB, `INDEXED BY` of SQLite
+        // and the partitions of MySQL inserts.
+        let unparsable = match self.typ {
+            Type::CockroachDB => stmt.starts_with("UPSERT ") || self.as_of.is_some(),
+            Type::SQLite => stmt.contains(" INDEXED BY "),
+            Type::MySQL | Type::MariaDB | Type::TiDB => {
+                !self.partitions.is_empty() && stmt.starts_with("INSERT ")
+            }
+            _ => false,
+        };
+        if unparsable {
+            return;
+        }
+        if let Err(e) = crate::db::validate(stmt, self.typ) {
+            panic!("invalid statement {:?}: {}", stmt, e);
+        }
+    }
+
+    #[cfg(not(all(feature = "validate", debug_assertions)))]
+    fn debug_validate(&self, _: &str) {}
+
+    /// Estimates the length of a statement, so that the buffer can be allocated only once.
+    fn estimate_len(&self, parts_len: usize) -> usize {
+        32 + self.tbl.len() + parts_len
+    }
+
+    fn kvs_len<P: AsKV>(kvs: &[P]) -> usize {
+        kvs.iter()
+            .map(|p| {
+                let kv = p.as_kv();
+                kv.key.len() + kv.val.len() + 8
+            })
+            .sum()
+    }
+
+    fn conds_len<P: AsCond>(conds: &[P]) -> usize {
+        conds
+            .iter()
+            .map(|p| match p.as_cond() {
+                CondRef::Eq(kv) => kv.key.len() + kv.val.len() + 8,
+                CondRef::Tree(_) => 32,
+            })
+            .sum()
+    }
+
+    /// Writes each item into `w` via `f`, separated by `sep`.
+    fn write_joined<W, I, F>(w: &mut W, sep: &str, items: I, mut f: F) -> fmt::Result
+    where
+        W: fmt::Write,
+        I: IntoIterator,
+        F: FnMut(&mut W, I::Item) -> fmt::Result,
+    {
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                w.write_str(sep)?;
+            }
+            f(w, item)?;
+        }
+        Ok(())
     }
 
     /// Builds a SQL statement that performs insert operation.
@@ -133,38 +781,14 @@ impl StmtBuilder {
     ///
     /// assert_eq!(stmt, expected_stmt);
     /// ```
-    pub fn build_insert_stmt(&self, cols: &[KV]) -> String {
-        if cols.is_empty() {
-            return String::new();
-        }
-        let mut begin_idx = PG_PLACEHOLDER_BEGIN_IDX;
-        let (keys, vals): (Vec<String>, Vec<String>) = cols
-            .iter()
-            .map(|kv| {
-                (
-                    self.escape_col(kv.key),
-                    self.convert_placeholder(&mut begin_idx, kv.val),
-                )
-            })
-            .unzip();
-        format!(
-            "INSERT INTO {} ({}) VALUES ({})",
-            self.tbl,
-            keys.join(", "),
-            vals.join(", ")
-        )
+    pub fn build_insert_stmt<P: AsKV>(&self, cols: &[P]) -> String {
+        let mut stmt = String::with_capacity(self.estimate_len(Self::kvs_len(cols)));
+        // Writing to a String never fails.
+        let _ = self.build_insert_stmt_into(&mut stmt, cols);
+        self.finish(StmtKind::Insert, stmt)
     }
 
-    /// Builds a SQL statement that performs query operation.
-    ///
-    /// # Arguments
-    ///
-    /// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
-    /// * `conds` - The equal conditions.
-    ///
-    /// # Returns
-    ///
-    /// * The SQL statement.
+    /// Same as [`StmtBuilder::build_insert_stmt`], but writes the statement into `w` without intermediate allocations.
     ///
     /// # Examples
     ///
@@ -172,47 +796,88 @@ impl StmtBuilder {
     /// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
     ///
     /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
-    /// let cols = vec![String::from("username"), String::from("nickname")];
-    /// let conds = vec![
-    ///     KV {
-    ///         key: "age",
-    ///         val: PLACEHOLDER,
-    ///     },
-    ///     KV {
-    ///         key: "gender",
-    ///         val: PLACEHOLDER,
-    ///     },
-    /// ];
+    /// let cols = vec![KV {
+    ///     key: "username",
+    ///     val: PLACEHOLDER,
+    /// }];
     ///
-    /// let stmt = sb.build_query_stmt(&cols, &conds);
-    /// let expected_stmt = "SELECT \"username\", \"nickname\" FROM my_tbl WHERE age = $1 AND gender = $2";
+    /// let mut stmt = String::with_capacity(64);
+    /// sb.build_insert_stmt_into(&mut stmt, &cols).unwrap();
     ///
-    /// assert_eq!(stmt, expected_stmt);
+    /// assert_eq!(stmt, "INSERT INTO my_tbl (\"username\") VALUES ($1)");
     /// ```
-    pub fn build_query_stmt(&self, cols: &[String], conds: &[KV]) -> String {
-        let cols_str = if cols.is_empty() {
-            String::from("*")
-        } else {
-            cols.iter()
-                .map(|col| self.escape_col(col))
-                .collect::<Vec<String>>()
-                .join(", ")
+    pub fn build_insert_stmt_into<W: fmt::Write, P: AsKV>(
+        &self,
+        w: &mut W,
+        cols: &[P],
+    ) -> fmt::Result {
+        self.write_insert(w, cols, false)
+    }
+
+    /// Writes an insert statement, where `ignore` turns it into `INSERT IGNORE` on MySQL,
+    /// and `INSERT OR IGNORE` on SQLite versions without upsert support.
+    fn write_insert<W: fmt::Write, P: AsKV>(
+        &self,
+        w: &mut W,
+        cols: &[P],
+        ignore: bool,
+    ) -> fmt::Result {
+        let verb = match self.typ {
+            Type::MySQL | Type::MariaDB | Type::TiDB if ignore => "INSERT IGNORE INTO ",
+            Type::SQLite if ignore && !self.supports_upsert() => "INSERT OR IGNORE INTO ",
+            _ => "INSERT INTO ",
         };
-        let mut begin_idx = PG_PLACEHOLDER_BEGIN_IDX;
-        format!(
-            "SELECT {} FROM {}{}",
-            cols_str,
-            self.tbl,
-            self.build_conds(&mut begin_idx, conds)
-        )
+        self.write_insert_as(w, verb, cols)
     }
 
-    /// Builds a SQL statement that performs update operation.
+    /// Writes `verb` followed by the table, the columns and the values.
+    fn write_insert_as<W: fmt::Write, P: AsKV>(
+        &self,
+        w: &mut W,
+        verb: &str,
+        cols: &[P],
+    ) -> fmt::Result {
+        if cols.is_empty() {
+            return Ok(());
+        }
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_kv);
+        w.write_str(verb)?;
+        self.write_target(w)?;
+        w.write_str(" (")?;
+        Self::write_joined(
+            w,
+            ", ",
+            cols.iter().map(AsKV::as_kv).chain(tenant),
+            |w, kv| self.write_col(w, kv.key),
+        )?;
+        w.write_str(") VALUES (")?;
+        Self::write_joined(
+            w,
+            ", ",
+            cols.iter().map(AsKV::as_kv).chain(tenant),
+            |w, kv| self.write_val(w, &mut idx, kv.val),
+        )?;
+        w.write_char(')')
+    }
+
+    /// Builds a SQL statement that inserts a row, or updates it if it conflicts with an existing row.
+    ///
+    /// On conflict, the columns not listed in `keys` are updated with the inserted values.
+    /// If every column is a key, the existing row is kept as is,
+    /// which is useful to insert a row only if it doesn't exist.
+    ///
+    /// MySQL detects conflicts with any unique index, so `keys` only determines the updated columns there.
+    ///
+    /// With the server version set, the statement uses row aliases instead of the deprecated `VALUES()` on MySQL 8.0.19 or later,
+    /// and `INSERT OR IGNORE` when nothing is updated on SQLite before 3.24.
+    /// Other statements unsupported by the version are built as is, see [`StmtBuilder::try_build_upsert_stmt`].
     ///
     /// # Arguments
     ///
     /// * `cols` - The column names and values. If it's empty, an empty string will be returned.
-    /// * `conds` - The equal conditions.
+    /// * `keys` - The columns of the primary key or unique constraint that may conflict.
+    ///   If it's empty, conflicts with any constraint keep the existing row.
     ///
     /// # Returns
     ///
@@ -223,18 +888,724 @@ impl StmtBuilder {
     /// ```
     /// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
     ///
-    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
     /// let cols = vec![
     ///     KV {
-    ///         key: "username",
+    ///         key: "id",
     ///         val: PLACEHOLDER,
     ///     },
     ///     KV {
-    ///         key: "nickname",
+    ///         key: "name",
     ///         val: PLACEHOLDER,
     ///     },
-    ///     KV {
-    ///         key: "update_at",
+    /// ];
+    ///
+    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+    /// assert_eq!(
+    ///     sb.build_upsert_stmt(&cols, &["id"]),
+    ///     "INSERT INTO my_tbl (\"id\", \"name\") VALUES ($1, $2) ON CONFLICT (\"id\") DO UPDATE SET \"name\" = EXCLUDED.\"name\""
+    /// );
+    ///
+    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::MySQL);
+    /// assert_eq!(
+    ///     sb.build_upsert_stmt(&cols, &["id"]),
+    ///     "INSERT INTO my_tbl (`id`, `name`) VALUES (?, ?) ON DUPLICATE KEY UPDATE `name` = VALUES(`name`)"
+    /// );
+    /// ```
+    pub fn build_upsert_stmt<P: AsKV, S: AsRef<str>>(&self, cols: &[P], keys: &[S]) -> String {
+        let keys_len: usize = keys.iter().map(|key| key.as_ref().len() + 4).sum();
+        let mut stmt = String::with_capacity(self.estimate_len(2 * Self::kvs_len(cols) + keys_len));
+        // Writing to a String never fails.
+        let _ = self.build_upsert_stmt_into(&mut stmt, cols, keys);
+        self.finish(StmtKind::Upsert, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_upsert_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_upsert_stmt_into<W: fmt::Write, P: AsKV, S: AsRef<str>>(
+        &self,
+        w: &mut W,
+        cols: &[P],
+        keys: &[S],
+    ) -> fmt::Result {
+        if cols.is_empty() {
+            return Ok(());
+        }
+        let updated: Vec<&str> = cols
+            .iter()
+            .map(|p| p.as_kv().key)
+            .filter(|col| keys.is_empty() || !keys.iter().any(|key| key.as_ref() == *col))
+            .collect();
+        let do_nothing = keys.is_empty() || updated.is_empty();
+        self.write_insert(w, cols, do_nothing)?;
+        match self.typ {
+            Type::MySQL | Type::MariaDB | Type::TiDB if do_nothing => Ok(()),
+            // VALUES() is deprecated since MySQL 8.0.20, but row aliases are only available since 8.0.19.
+            Type::MySQL | Type::MariaDB | Type::TiDB
+                if self.version.is_some()
+                    && self.get_dialect().check(Feature::InsertRowAlias).is_ok() =>
+            {
+                w.write_str(" AS new ON DUPLICATE KEY UPDATE ")?;
+                Self::write_joined(w, ", ", updated, |w, col| {
+                    self.write_col(w, col)?;
+                    w.write_str(" = new.")?;
+                    self.write_col(w, col)
+                })
+            }
+            Type::MySQL | Type::MariaDB | Type::TiDB => {
+                w.write_str(" ON DUPLICATE KEY UPDATE ")?;
+                Self::write_joined(w, ", ", updated, |w, col| {
+                    self.write_col(w, col)?;
+                    w.write_str(" = VALUES(")?;
+                    self.write_col(w, col)?;
+                    w.write_char(')')
+                })
+            }
+            Type::SQLite if do_nothing && !self.supports_upsert() => Ok(()),
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => {
+                w.write_str(" ON CONFLICT")?;
+                if !keys.is_empty() {
+                    w.write_str(" (")?;
+                    Self::write_joined(w, ", ", keys, |w, key| self.write_col(w, key.as_ref()))?;
+                    w.write_char(')')?;
+                }
+                if do_nothing {
+                    return w.write_str(" DO NOTHING");
+                }
+                w.write_str(" DO UPDATE SET ")?;
+                Self::write_joined(w, ", ", updated, |w, col| {
+                    self.write_col(w, col)?;
+                    w.write_str(" = EXCLUDED.")?;
+                    self.write_col(w, col)
+                })
+            }
+        }
+    }
+
+    /// Builds a SQL statement that inserts multiple rows at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The inserted rows. The columns are taken from the first row,
+    ///   so every row must list the same columns in the same order.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement, or an empty string if there are no rows or columns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+    /// let rows = vec![
+    ///     vec![
+    ///         KV {
+    ///             key: "username",
+    ///             val: PLACEHOLDER,
+    ///         },
+    ///         KV {
+    ///             key: "age",
+    ///             val: "20",
+    ///         },
+    ///     ],
+    ///     vec![
+    ///         KV {
+    ///             key: "username",
+    ///             val: PLACEHOLDER,
+    ///         },
+    ///         KV {
+    ///             key: "age",
+    ///             val: "30",
+    ///         },
+    ///     ],
+    /// ];
+    ///
+    /// let stmt = sb.build_batch_insert_stmt(&rows);
+    /// let expected_stmt =
+    ///     "INSERT INTO my_tbl (\"username\", \"age\") VALUES ($1, 20), ($2, 30)";
+    ///
+    /// assert_eq!(stmt, expected_stmt);
+    /// ```
+    pub fn build_batch_insert_stmt<R: AsRef<[P]>, P: AsKV>(&self, rows: &[R]) -> String {
+        let rows_len: usize = rows.iter().map(|row| Self::kvs_len(row.as_ref())).sum();
+        let mut stmt = String::with_capacity(self.estimate_len(rows_len));
+        // Writing to a String never fails.
+        let _ = self.build_batch_insert_stmt_into(&mut stmt, rows);
+        self.finish(StmtKind::Insert, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_batch_insert_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_batch_insert_stmt_into<W: fmt::Write, R: AsRef<[P]>, P: AsKV>(
+        &self,
+        w: &mut W,
+        rows: &[R],
+    ) -> fmt::Result {
+        let Some(first) = rows.first().map(AsRef::as_ref) else {
+            return Ok(());
+        };
+        if first.is_empty() {
+            return Ok(());
+        }
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_kv);
+        w.write_str("INSERT INTO ")?;
+        self.write_target(w)?;
+        w.write_str(" (")?;
+        Self::write_joined(
+            w,
+            ", ",
+            first.iter().map(AsKV::as_kv).chain(tenant),
+            |w, kv| self.write_col(w, kv.key),
+        )?;
+        w.write_str(") VALUES ")?;
+        Self::write_joined(w, ", ", rows, |w, row| {
+            w.write_char('(')?;
+            let row = row.as_ref().iter().map(AsKV::as_kv).chain(tenant);
+            Self::write_joined(w, ", ", row, |w, kv| self.write_val(w, &mut idx, kv.val))?;
+            w.write_char(')')
+        })
+    }
+
+    /// Builds a SQL statement that performs query operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
+    /// * `conds` - The conditions, combined with `AND`. Key-value pairs are treated as equal conditions.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+    /// let cols = vec![String::from("username"), String::from("nickname")];
+    /// let conds = vec![
+    ///     KV {
+    ///         key: "age",
+    ///         val: PLACEHOLDER,
+    ///     },
+    ///     KV {
+    ///         key: "gender",
+    ///         val: PLACEHOLDER,
+    ///     },
+    /// ];
+    ///
+    /// let stmt = sb.build_query_stmt(&cols, &conds);
+    /// let expected_stmt = "SELECT \"username\", \"nickname\" FROM my_tbl WHERE age = $1 AND gender = $2";
+    ///
+    /// assert_eq!(stmt, expected_stmt);
+    /// ```
+    pub fn build_query_stmt<S: AsRef<str>, P: AsCond>(&self, cols: &[S], conds: &[P]) -> String {
+        let cols_len: usize = cols.iter().map(|col| col.as_ref().len() + 4).sum();
+        let mut stmt = String::with_capacity(self.estimate_len(cols_len + Self::conds_len(conds)));
+        // Writing to a String never fails.
+        let _ = self.build_query_stmt_into(&mut stmt, cols, conds);
+        self.record(conds, &[]);
+        self.finish(StmtKind::Query, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_query_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_query_stmt_into<W: fmt::Write, S: AsRef<str>, P: AsCond>(
+        &self,
+        w: &mut W,
+        cols: &[S],
+        conds: &[P],
+    ) -> fmt::Result {
+        self.write_query(w, cols, conds, None)
+    }
+
+    /// Writes a query, sampling its rows if `sample` is [`Some`].
+    fn write_query<W: fmt::Write, S: AsRef<str>, P: AsCond>(
+        &self,
+        w: &mut W,
+        cols: &[S],
+        conds: &[P],
+        sample: Option<Sample>,
+    ) -> fmt::Result {
+        self.write_keyword(w, "SELECT ", true)?;
+        if cols.is_empty() {
+            w.write_char('*')?;
+        } else {
+            Self::write_joined(w, ", ", cols, |w, col| self.write_col(w, col.as_ref()))?;
+        }
+        self.write_from(w)?;
+        if let Some(sample) = sample {
+            sample::write_after_tbl(w, self.typ, sample)?;
+        }
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_cond);
+        let hooked = self.hooks.conds(StmtKind::Query);
+        let random = sample.and_then(|sample| sample::cond(self.typ, sample));
+        let conds = conds
+            .iter()
+            .map(AsCond::as_cond)
+            .chain(tenant)
+            .chain(random.iter().map(AsCond::as_cond));
+        self.write_conds(w, &mut idx, conds.chain(hooked.iter().map(AsCond::as_cond)))?;
+        match sample {
+            Some(sample) => sample::write_suffix(w, self.typ, sample),
+            None => Ok(()),
+        }
+    }
+
+    /// Same as [`StmtBuilder::build_query_stmt`], but renames the columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The selected columns and their aliases. A column is not renamed if its alias is the same.
+    ///   If it's empty, `["*"]` will be used.
+    /// * `conds` - The conditions, combined with `AND`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+    ///
+    /// assert_eq!(
+    ///     sb.build_aliased_query_stmt(&[("id", "id"), ("created_at", "createdAt")], &[Cond::eq("id", PLACEHOLDER)]),
+    ///     "SELECT `id`, `created_at` AS `createdAt` FROM users WHERE id = ?"
+    /// );
+    /// ```
+    pub fn build_aliased_query_stmt<S: AsRef<str>, A: AsRef<str>, P: AsCond>(
+        &self,
+        cols: &[(S, A)],
+        conds: &[P],
+    ) -> String {
+        let cols_len: usize = cols
+            .iter()
+            .map(|(col, alias)| col.as_ref().len() + alias.as_ref().len() + 10)
+            .sum();
+        let mut stmt = String::with_capacity(self.estimate_len(cols_len + Self::conds_len(conds)));
+        // Writing to a String never fails.
+        let _ = self.build_aliased_query_stmt_into(&mut stmt, cols, conds);
+        self.record(conds, &[]);
+        self.finish(StmtKind::Query, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_aliased_query_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_aliased_query_stmt_into<W: fmt::Write, S: AsRef<str>, A: AsRef<str>, P: AsCond>(
+        &self,
+        w: &mut W,
+        cols: &[(S, A)],
+        conds: &[P],
+    ) -> fmt::Result {
+        self.write_keyword(w, "SELECT ", true)?;
+        if cols.is_empty() {
+            w.write_char('*')?;
+        } else {
+            Self::write_joined(w, ", ", cols, |w, (col, alias)| {
+                let (col, alias) = (col.as_ref(), alias.as_ref());
+                self.write_col(w, col)?;
+                if col != alias {
+                    w.write_str(" AS ")?;
+                    self.write_col(w, alias)?;
+                }
+                Ok(())
+            })?;
+        }
+        self.write_from(w)?;
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_cond);
+        let hooked = self.hooks.conds(StmtKind::Query);
+        let conds = conds.iter().map(AsCond::as_cond).chain(tenant);
+        self.write_conds(w, &mut idx, conds.chain(hooked.iter().map(AsCond::as_cond)))
+    }
+
+    /// Same as [`StmtBuilder::build_query_stmt`], but sorts the rows by `order`.
+    ///
+    /// See [`OrderBy`] for examples.
+    pub fn build_ordered_query_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        cols: &[S],
+        conds: &[P],
+        order: &OrderBy,
+    ) -> String {
+        let cols_len: usize = cols.iter().map(|col| col.as_ref().len() + 4).sum();
+        let order_len: usize = order.get_keys().iter().map(|key| key.col.len() + 8).sum();
+        let mut stmt =
+            String::with_capacity(self.estimate_len(cols_len + Self::conds_len(conds) + order_len));
+        // Writing to a String never fails.
+        let _ = self.build_query_stmt_into(&mut stmt, cols, conds);
+        let _ = self.write_order_by(&mut stmt, order.get_keys());
+        self.record(conds, order.get_keys());
+        self.finish(StmtKind::Query, stmt)
+    }
+
+    /// Builds a SQL statement that queries rows as defined by `plan`.
+    ///
+    /// See [`QueryPlan`] for examples.
+    pub fn build_plan_stmt(&self, plan: &QueryPlan) -> String {
+        let cols_len: usize = plan.cols.iter().map(|col| col.len() + 4).sum();
+        let order_len: usize = plan.order.iter().map(|key| key.col.len() + 8).sum();
+        let mut stmt = String::with_capacity(
+            self.estimate_len(cols_len + Self::conds_len(&plan.conds) + order_len + 48),
+        );
+        // Writing to a String never fails.
+        let _ = self.build_query_stmt_into(&mut stmt, &plan.cols, &plan.conds);
+        let _ = self.write_order_by(&mut stmt, &plan.order);
+        let _ = match (plan.limit, plan.offset) {
+            (Some(limit), Some(offset)) => write!(stmt, " LIMIT {} OFFSET {}", limit, offset),
+            (Some(limit), None) => write!(stmt, " LIMIT {}", limit),
+            // MySQL and SQLite don't support OFFSET without LIMIT.
+            (None, Some(offset)) => match self.typ {
+                Type::MySQL | Type::MariaDB | Type::TiDB => {
+                    write!(stmt, " LIMIT 18446744073709551615 OFFSET {}", offset)
+                }
+                Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => {
+                    write!(stmt, " OFFSET {}", offset)
+                }
+                Type::SQLite => write!(stmt, " LIMIT -1 OFFSET {}", offset),
+            },
+            (None, None) => Ok(()),
+        };
+        self.record(&plan.conds, &plan.order);
+        self.finish(StmtKind::Query, stmt)
+    }
+
+    /// Builds a SQL statement that queries a random sample of the rows matching the conditions,
+    /// for example to spot-check the data of a large table.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
+    /// * `conds` - The conditions, combined with `AND`. Key-value pairs are treated as equal conditions.
+    /// * `sample` - How the rows are sampled.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement, or an error if the percentage of [`Sample::Percent`] isn't from 0 to 100.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, Sample, StmtBuilder, Type};
+    ///
+    /// let conds = [Cond::is_null("email")];
+    ///
+    /// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+    /// assert_eq!(
+    ///     sb.build_sample_stmt(&["id"], &conds, Sample::Percent(1.0)).unwrap(),
+    ///     "SELECT \"id\" FROM users TABLESAMPLE SYSTEM (1) WHERE email IS NULL"
+    /// );
+    ///
+    /// let sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+    /// assert_eq!(
+    ///     sb.build_sample_stmt(&["id"], &conds, Sample::Percent(1.0)).unwrap(),
+    ///     "SELECT `id` FROM users WHERE email IS NULL AND (RAND() < 0.01)"
+    /// );
+    /// assert_eq!(
+    ///     sb.build_sample_stmt(&["id"], &conds, Sample::Rows(100)).unwrap(),
+    ///     "SELECT `id` FROM users WHERE email IS NULL ORDER BY RAND() LIMIT 100"
+    /// );
+    /// assert!(sb.build_sample_stmt(&["id"], &conds, Sample::Percent(120.0)).is_err());
+    /// ```
+    pub fn build_sample_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        cols: &[S],
+        conds: &[P],
+        sample: Sample,
+    ) -> Result<String, InvalidSample> {
+        let sample = sample.check()?;
+        let cols_len: usize = cols.iter().map(|col| col.as_ref().len() + 4).sum();
+        let mut stmt =
+            String::with_capacity(self.estimate_len(cols_len + Self::conds_len(conds) + 48));
+        // Writing to a String never fails.
+        let _ = self.write_query(&mut stmt, cols, conds, Some(sample));
+        self.record(conds, &[]);
+        Ok(self.finish(StmtKind::Query, stmt))
+    }
+
+    /// Builds a DuckDB statement that writes the rows matching the conditions into a file.
+    ///
+    /// The statement is built for any type; use [`StmtBuilder::try_build_copy_to_stmt`] to reject
+    /// other databases.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The written columns. If it's empty, `["*"]` will be used.
+    /// * `conds` - The conditions, combined with `AND`.
+    /// * `path` - The path of the file, which is quoted as a string literal.
+    /// * `format` - The format of the file.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement.
+    ///
+    /// See [`file`](crate::db::file) for examples.
+    pub fn build_copy_to_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        cols: &[S],
+        conds: &[P],
+        path: &str,
+        format: file::Format,
+    ) -> String {
+        let cols_len: usize = cols.iter().map(|col| col.as_ref().len() + 4).sum();
+        let mut stmt = String::with_capacity(
+            self.estimate_len(cols_len + Self::conds_len(conds) + path.len() + 32),
+        );
+        stmt.push_str("COPY (");
+        // Writing to a String never fails.
+        let _ = self.build_query_stmt_into(&mut stmt, cols, conds);
+        let _ = write!(
+            stmt,
+            ") TO {} (FORMAT {})",
+            Value::from(path).to_literal(self.typ),
+            format
+        );
+        self.finish(StmtKind::Query, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_copy_to_stmt`], but fails if the policy doesn't allow the columns,
+    /// or the database doesn't support [`Feature::CopyFile`].
+    pub fn try_build_copy_to_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        cols: &[S],
+        conds: &[P],
+        path: &str,
+        format: file::Format,
+    ) -> Result<String, BuildError> {
+        let names = cols.iter().map(AsRef::as_ref).filter(|col| *col != "*");
+        self.check_policy(names, conds)?;
+        self.get_dialect().check(Feature::CopyFile)?;
+        Ok(self.build_copy_to_stmt(cols, conds, path, format))
+    }
+
+    /// Builds a DuckDB statement that inserts the rows of a file.
+    ///
+    /// The tenant column is not filled, so load files into tables with a tenant prefix or schema only.
+    /// The statement is built for any type; use [`StmtBuilder::try_build_copy_from_stmt`] to reject
+    /// other databases.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The columns of the table that the columns of the file are inserted into, in order.
+    ///   If it's empty, all columns will be used.
+    /// * `path` - The path of the file, which is quoted as a string literal.
+    /// * `format` - The format of the file.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement.
+    pub fn build_copy_from_stmt<S: AsRef<str>>(
+        &self,
+        cols: &[S],
+        path: &str,
+        format: file::Format,
+    ) -> String {
+        let cols_len: usize = cols.iter().map(|col| col.as_ref().len() + 4).sum();
+        let mut stmt = String::with_capacity(self.estimate_len(cols_len + path.len() + 32));
+        stmt.push_str("COPY ");
+        // Writing to a String never fails.
+        let _ = self.write_tbl(&mut stmt);
+        if !cols.is_empty() {
+            stmt.push_str(" (");
+            let _ = Self::write_joined(&mut stmt, ", ", cols, |w, col| {
+                self.write_col(w, col.as_ref())
+            });
+            stmt.push(')');
+        }
+        let _ = write!(
+            stmt,
+            " FROM {} (FORMAT {})",
+            Value::from(path).to_literal(self.typ),
+            format
+        );
+        self.finish(StmtKind::Insert, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_copy_from_stmt`], but fails if the policy doesn't allow the columns,
+    /// or the database doesn't support [`Feature::CopyFile`].
+    pub fn try_build_copy_from_stmt<S: AsRef<str>>(
+        &self,
+        cols: &[S],
+        path: &str,
+        format: file::Format,
+    ) -> Result<String, BuildError> {
+        let names = cols.iter().map(AsRef::as_ref);
+        self.check_policy(names, &[] as &[Cond])?;
+        self.get_dialect().check(Feature::CopyFile)?;
+        Ok(self.build_copy_from_stmt(cols, path, format))
+    }
+
+    /// Builds a statement that creates the table `new_tbl` from the rows matching the conditions,
+    /// like `CREATE TABLE x AS SELECT ...`, for example to snapshot a table before a migration.
+    ///
+    /// The new table has the column types of the selected columns, but none of the keys, indexes or defaults.
+    /// The hint comment of PostgreSQL leads the statement, before `CREATE TABLE`.
+    /// TiDB doesn't support creating tables from queries, see [`StmtBuilder::try_build_ctas_stmt`].
+    ///
+    /// # Arguments
+    ///
+    /// * `new_tbl` - The name of the created table, which is used as is.
+    /// * `cols` - The copied columns. If it's empty, `["*"]` will be used.
+    /// * `conds` - The conditions, combined with `AND`.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let sb = StmtBuilder::new(String::from("orders"), Type::PostgreSQL);
+    ///
+    /// assert_eq!(
+    ///     sb.build_ctas_stmt("orders_2024", &["id", "total"], &[Cond::lt("created_at", PLACEHOLDER)]),
+    ///     "CREATE TABLE orders_2024 AS SELECT \"id\", \"total\" FROM orders WHERE created_at < $1"
+    /// );
+    /// ```
+    pub fn build_ctas_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        new_tbl: &str,
+        cols: &[S],
+        conds: &[P],
+    ) -> String {
+        let cols_len: usize = cols.iter().map(|col| col.as_ref().len() + 4).sum();
+        let mut stmt = String::with_capacity(
+            self.estimate_len(cols_len + Self::conds_len(conds) + new_tbl.len() + 16),
+        );
+        let mut query = String::with_capacity(stmt.capacity());
+        // Writing to a String never fails.
+        let _ = self.build_query_stmt_into(&mut query, cols, conds);
+        // The hints placed before the statement would end up in the middle of it, so they're moved to the front.
+        let mut prefix = String::new();
+        if !self.hints.is_empty() {
+            let _ = self
+                .hint_tbl()
+                .and_then(|tbl| hint::write_prefix(&mut prefix, self.typ, &tbl, &self.hints));
+        }
+        let query = query.strip_prefix(prefix.as_str()).unwrap_or(&query);
+        let _ = write!(stmt, "{}CREATE TABLE {} AS {}", prefix, new_tbl, query);
+        self.finish(StmtKind::Query, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_ctas_stmt`], but fails if the policy doesn't allow the columns,
+    /// or the database doesn't support [`Feature::CreateTableAs`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{BuildError, Cond, StmtBuilder, Type};
+    ///
+    /// let conds = [Cond::is_null("deleted_at")];
+    ///
+    /// let sb = StmtBuilder::new(String::from("orders"), Type::MySQL);
+    /// assert!(sb.try_build_ctas_stmt("orders_bak", &["id"], &conds).is_ok());
+    ///
+    /// let sb = StmtBuilder::new(String::from("orders"), Type::TiDB);
+    /// assert!(matches!(
+    ///     sb.try_build_ctas_stmt("orders_bak", &["id"], &conds),
+    ///     Err(BuildError::Unsupported(_))
+    /// ));
+    /// ```
+    pub fn try_build_ctas_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        new_tbl: &str,
+        cols: &[S],
+        conds: &[P],
+    ) -> Result<String, BuildError> {
+        let names = cols.iter().map(AsRef::as_ref).filter(|col| *col != "*");
+        self.check_policy(names, conds)?;
+        self.get_dialect().check(Feature::CreateTableAs)?;
+        Ok(self.build_ctas_stmt(new_tbl, cols, conds))
+    }
+
+    /// Writes ` ORDER BY` with the sort keys, or nothing if there are no keys.
+    pub(crate) fn write_order_by<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        keys: &[SortField],
+    ) -> fmt::Result {
+        for (i, key) in keys.iter().enumerate() {
+            w.write_str(if i == 0 { " ORDER BY " } else { ", " })?;
+            self.write_col(w, &key.col)?;
+            w.write_str(if key.desc { " DESC" } else { " ASC" })?;
+        }
+        Ok(())
+    }
+
+    /// Builds a SQL statement that counts the rows matching the conditions.
+    ///
+    /// # Arguments
+    ///
+    /// * `conds` - The conditions, combined with `AND`. Key-value pairs are treated as equal conditions.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+    ///
+    /// let stmt = sb.build_count_stmt(&[Cond::gt("age", PLACEHOLDER)]);
+    ///
+    /// assert_eq!(stmt, "SELECT COUNT(*) FROM my_tbl WHERE age > $1");
+    /// ```
+    pub fn build_count_stmt<P: AsCond>(&self, conds: &[P]) -> String {
+        let mut stmt = String::with_capacity(self.estimate_len(Self::conds_len(conds)));
+        // Writing to a String never fails.
+        let _ = self.build_count_stmt_into(&mut stmt, conds);
+        self.record(conds, &[]);
+        self.finish(StmtKind::Count, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_count_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_count_stmt_into<W: fmt::Write, P: AsCond>(
+        &self,
+        w: &mut W,
+        conds: &[P],
+    ) -> fmt::Result {
+        self.write_keyword(w, "SELECT ", true)?;
+        w.write_str("COUNT(*)")?;
+        self.write_from(w)?;
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_cond);
+        let hooked = self.hooks.conds(StmtKind::Count);
+        let conds = conds.iter().map(AsCond::as_cond).chain(tenant);
+        self.write_conds(w, &mut idx, conds.chain(hooked.iter().map(AsCond::as_cond)))
+    }
+
+    /// Builds a SQL statement that performs update operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The column names and values. If it's empty, an empty string will be returned.
+    /// * `conds` - The conditions, combined with `AND`. Key-value pairs are treated as equal conditions.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+    /// let cols = vec![
+    ///     KV {
+    ///         key: "username",
+    ///         val: PLACEHOLDER,
+    ///     },
+    ///     KV {
+    ///         key: "nickname",
+    ///         val: PLACEHOLDER,
+    ///     },
+    ///     KV {
+    ///         key: "update_at",
     ///         val: "NOW()",
     ///     },
     /// ];
@@ -254,23 +1625,60 @@ impl StmtBuilder {
     ///
     /// assert_eq!(stmt, expected_stmt);
     /// ```
-    pub fn build_update_stmt(&self, cols: &[KV], conds: &[KV]) -> String {
+    pub fn build_update_stmt<C: AsKV, P: AsCond>(&self, cols: &[C], conds: &[P]) -> String {
+        let mut stmt =
+            String::with_capacity(self.estimate_len(Self::kvs_len(cols) + Self::conds_len(conds)));
+        // Writing to a String never fails.
+        let _ = self.build_update_stmt_into(&mut stmt, cols, conds);
+        self.record(conds, &[]);
+        self.finish(StmtKind::Update, stmt)
+    }
+
+    /// Same as [`StmtBuilder::build_update_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_update_stmt_into<W: fmt::Write, C: AsKV, P: AsCond>(
+        &self,
+        w: &mut W,
+        cols: &[C],
+        conds: &[P],
+    ) -> fmt::Result {
         if cols.is_empty() {
-            return String::new();
-        }
-        let mut begin_idx = PG_PLACEHOLDER_BEGIN_IDX;
-        format!(
-            "UPDATE {} SET {}{}",
-            self.tbl,
-            cols.iter()
-                .map(|kv| format!(
-                    "{} = {}",
-                    self.escape_col(kv.key),
-                    self.convert_placeholder(&mut begin_idx, kv.val)
-                ))
-                .collect::<Vec<String>>()
-                .join(", "),
-            self.build_conds(&mut begin_idx, conds)
+            return Ok(());
+        }
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        self.write_keyword(w, "UPDATE ", false)?;
+        self.write_target(w)?;
+        hint::write_after_tbl(w, self.typ, &self.hints)?;
+        w.write_str(" SET ")?;
+        Self::write_joined(w, ", ", cols, |w, p| {
+            let kv = p.as_kv();
+            self.write_col(w, kv.key)?;
+            w.write_str(" = ")?;
+            self.write_val(w, &mut idx, kv.val)
+        })?;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_cond);
+        let hooked = self.hooks.conds(StmtKind::Update);
+        let version = self.version_col.as_deref().map(|col| {
+            CondRef::Eq(KV {
+                key: col,
+                val: PLACEHOLDER,
+            })
+        });
+        if let Some(col) = &self.version_col {
+            w.write_str(", ")?;
+            self.write_col(w, col)?;
+            w.write_str(" = ")?;
+            self.write_col(w, col)?;
+            w.write_str(" + 1")?;
+        }
+        self.write_conds(
+            w,
+            &mut idx,
+            conds
+                .iter()
+                .map(AsCond::as_cond)
+                .chain(tenant)
+                .chain(hooked.iter().map(AsCond::as_cond))
+                .chain(version),
         )
     }
 
@@ -278,7 +1686,7 @@ impl StmtBuilder {
     ///
     /// # Arguments
     ///
-    /// * `conds` - The equal conditions.
+    /// * `conds` - The conditions, combined with `AND`. Key-value pairs are treated as equal conditions.
     ///
     /// # Returns
     ///
@@ -306,25 +1714,253 @@ impl StmtBuilder {
     ///
     /// assert_eq!(stmt, expected_stmt);
     /// ```
-    pub fn build_delete_stmt(&self, conds: &[KV]) -> String {
-        let mut begin_idx = PG_PLACEHOLDER_BEGIN_IDX;
-        format!(
-            "DELETE FROM {}{}",
-            self.tbl,
-            self.build_conds(&mut begin_idx, conds)
-        )
+    pub fn build_delete_stmt<P: AsCond>(&self, conds: &[P]) -> String {
+        let mut stmt = String::with_capacity(self.estimate_len(Self::conds_len(conds)));
+        // Writing to a String never fails.
+        let _ = self.build_delete_stmt_into(&mut stmt, conds);
+        self.record(conds, &[]);
+        self.finish(StmtKind::Delete, stmt)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::db::{PLACEHOLDER, Type};
+    /// Same as [`StmtBuilder::build_delete_stmt`], but writes the statement into `w` without intermediate allocations.
+    pub fn build_delete_stmt_into<W: fmt::Write, P: AsCond>(
+        &self,
+        w: &mut W,
+        conds: &[P],
+    ) -> fmt::Result {
+        self.write_keyword(w, "DELETE ", false)?;
+        w.write_str("FROM ")?;
+        self.write_target(w)?;
+        hint::write_after_tbl(w, self.typ, &self.hints)?;
+        let mut idx = PG_PLACEHOLDER_BEGIN_IDX;
+        let tenant = self.tenant.as_ref().and_then(TenantScope::as_cond);
+        let hooked = self.hooks.conds(StmtKind::Delete);
+        let conds = conds.iter().map(AsCond::as_cond).chain(tenant);
+        self.write_conds(w, &mut idx, conds.chain(hooked.iter().map(AsCond::as_cond)))
+    }
 
-    use super::{KV, StmtBuilder};
+    /// Checks the columns and conditions against the policy, if any.
+    fn check_policy<'a, I, P>(&self, cols: I, conds: &[P]) -> Result<(), PolicyError>
+    where
+        I: IntoIterator<Item = &'a str>,
+        P: AsCond,
+    {
+        let Some(policy) = &self.policy else {
+            return Ok(());
+        };
+        policy.check_tbl(&self.tbl)?;
+        for col in cols {
+            policy.check_col(&self.tbl, col)?;
+        }
+        policy.check_conds(&self.tbl, conds)
+    }
 
-    static TABLE: &str = "my_tbl";
+    /// Same as [`StmtBuilder::build_insert_stmt`], but fails if the policy doesn't allow the columns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use sainnhe_common::db::{KV, PLACEHOLDER, Policy, StmtBuilder, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::MySQL);
+    /// sb.set_policy(Some(Arc::new(Policy::new().allow("users", &["name"]))));
+    ///
+    /// let kv = |key| KV {
+    ///     key,
+    ///     val: PLACEHOLDER,
+    /// };
+    ///
+    /// assert!(sb.try_build_insert_stmt(&[kv("name")]).is_ok());
+    /// assert!(sb.try_build_insert_stmt(&[kv("is_admin")]).is_err());
+    /// ```
+    pub fn try_build_insert_stmt<P: AsKV>(&self, cols: &[P]) -> Result<String, PolicyError> {
+        self.check_policy(cols.iter().map(|p| p.as_kv().key), &[] as &[Cond])?;
+        Ok(self.build_insert_stmt(cols))
+    }
+
+    /// Same as [`StmtBuilder::build_upsert_stmt`], but fails if the policy doesn't allow the columns,
+    /// or the server version doesn't support the statement.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{BuildError, KV, PLACEHOLDER, StmtBuilder, Type, Version};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::SQLite);
+    /// sb.set_server_version(Some(Version::new(3, 22, 0)));
+    ///
+    /// let kv = |key| KV {
+    ///     key,
+    ///     val: PLACEHOLDER,
+    /// };
+    ///
+    /// assert_eq!(
+    ///     sb.try_build_upsert_stmt(&[kv("id")], &[] as &[&str]).unwrap(),
+    ///     "INSERT OR IGNORE INTO users (\"id\") VALUES (?)"
+    /// );
+    /// assert!(matches!(
+    ///     sb.try_build_upsert_stmt(&[kv("id"), kv("name")], &["id"]),
+    ///     Err(BuildError::Unsupported(_))
+    /// ));
+    /// ```
+    pub fn try_build_upsert_stmt<P: AsKV, S: AsRef<str>>(
+        &self,
+        cols: &[P],
+        keys: &[S],
+    ) -> Result<String, BuildError> {
+        let names = cols.iter().map(|p| p.as_kv().key);
+        self.check_policy(names.chain(keys.iter().map(AsRef::as_ref)), &[] as &[Cond])?;
+        let do_nothing = keys.is_empty()
+            || cols
+                .iter()
+                .all(|p| keys.iter().any(|key| key.as_ref() == p.as_kv().key));
+        match self.typ {
+            Type::MySQL | Type::MariaDB | Type::TiDB => {}
+            // SQLite falls back to INSERT OR IGNORE.
+            Type::SQLite if do_nothing => {}
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => {
+                self.get_dialect().check(Feature::Upsert)?
+            }
+        }
+        Ok(self.build_upsert_stmt(cols, keys))
+    }
+
+    /// Builds an `UPSERT` statement of CockroachDB, which inserts a row or replaces the row with the same primary key.
+    ///
+    /// Unlike [`StmtBuilder::build_upsert_stmt`], only the primary key is checked for conflicts,
+    /// which makes it faster than `INSERT ... ON CONFLICT` when writing every column of a table without secondary indexes.
+    ///
+    /// # Arguments
+    ///
+    /// * `cols` - The column names and values. If it's empty, an empty string will be returned.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL statement, or an error if the policy doesn't allow the columns
+    ///   or the database doesn't support [`Feature::UpsertStatement`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
+    ///
+    /// let cols = [
+    ///     KV {
+    ///         key: "id",
+    ///         val: PLACEHOLDER,
+    ///     },
+    ///     KV {
+    ///         key: "name",
+    ///         val: PLACEHOLDER,
+    ///     },
+    /// ];
+    ///
+    /// let sb = StmtBuilder::new(String::from("users"), Type::CockroachDB);
+    /// assert_eq!(
+    ///     sb.try_build_upsert_into_stmt(&cols).unwrap(),
+    ///     "UPSERT INTO users (\"id\", \"name\") VALUES ($1, $2)"
+    /// );
+    ///
+    /// let sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+    /// assert!(sb.try_build_upsert_into_stmt(&cols).is_err());
+    /// ```
+    pub fn try_build_upsert_into_stmt<P: AsKV>(&self, cols: &[P]) -> Result<String, BuildError> {
+        self.check_policy(cols.iter().map(|p| p.as_kv().key), &[] as &[Cond])?;
+        self.get_dialect().check(Feature::UpsertStatement)?;
+        let mut stmt = String::with_capacity(self.estimate_len(2 * Self::kvs_len(cols)));
+        // Writing to a String never fails.
+        let _ = self.write_insert_as(&mut stmt, "UPSERT INTO ", cols);
+        Ok(self.finish(StmtKind::Upsert, stmt))
+    }
+
+    /// Same as [`StmtBuilder::build_batch_insert_stmt`], but fails if the policy doesn't allow the columns.
+    pub fn try_build_batch_insert_stmt<R: AsRef<[P]>, P: AsKV>(
+        &self,
+        rows: &[R],
+    ) -> Result<String, PolicyError> {
+        let cols = rows.iter().flat_map(|row| row.as_ref().iter());
+        self.check_policy(cols.map(|p| p.as_kv().key), &[] as &[Cond])?;
+        Ok(self.build_batch_insert_stmt(rows))
+    }
+
+    /// Same as [`StmtBuilder::build_query_stmt`], but fails if the policy doesn't allow the columns.
+    ///
+    /// Selecting all columns via an empty list or `*` is always allowed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, Policy, StmtBuilder, Type};
+    ///
+    /// let mut sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+    /// sb.set_policy(Some(Arc::new(Policy::new().allow("users", &["id", "name"]))));
+    ///
+    /// // For example, the column is taken from a filter parameter.
+    /// let filter = "password";
+    ///
+    /// assert!(sb.try_build_query_stmt(&["id"], &[Cond::eq("name", PLACEHOLDER)]).is_ok());
+    /// assert!(sb.try_build_query_stmt(&["id"], &[Cond::eq(filter, PLACEHOLDER)]).is_err());
+    /// ```
+    pub fn try_build_query_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        cols: &[S],
+        conds: &[P],
+    ) -> Result<String, PolicyError> {
+        let names = cols.iter().map(AsRef::as_ref).filter(|col| *col != "*");
+        self.check_policy(names, conds)?;
+        Ok(self.build_query_stmt(cols, conds))
+    }
+
+    /// Same as [`StmtBuilder::build_plan_stmt`], but fails if the policy doesn't allow the columns,
+    /// including the columns of the sort keys.
+    pub fn try_build_plan_stmt(&self, plan: &QueryPlan) -> Result<String, PolicyError> {
+        let cols = plan
+            .cols
+            .iter()
+            .map(String::as_str)
+            .filter(|col| *col != "*");
+        let order = plan.order.iter().map(|key| key.col.as_str());
+        self.check_policy(cols.chain(order), &plan.conds)?;
+        Ok(self.build_plan_stmt(plan))
+    }
+
+    /// Same as [`StmtBuilder::build_count_stmt`], but fails if the policy doesn't allow the columns.
+    pub fn try_build_count_stmt<P: AsCond>(&self, conds: &[P]) -> Result<String, PolicyError> {
+        self.check_policy([], conds)?;
+        Ok(self.build_count_stmt(conds))
+    }
+
+    /// Same as [`StmtBuilder::build_update_stmt`], but fails if the policy doesn't allow the columns.
+    pub fn try_build_update_stmt<C: AsKV, P: AsCond>(
+        &self,
+        cols: &[C],
+        conds: &[P],
+    ) -> Result<String, PolicyError> {
+        self.check_policy(cols.iter().map(|p| p.as_kv().key), conds)?;
+        Ok(self.build_update_stmt(cols, conds))
+    }
+
+    /// Same as [`StmtBuilder::build_delete_stmt`], but fails if the policy doesn't allow the columns.
+    pub fn try_build_delete_stmt<P: AsCond>(&self, conds: &[P]) -> Result<String, PolicyError> {
+        self.check_policy([], conds)?;
+        Ok(self.build_delete_stmt(conds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{CmpOp, Cond, PLACEHOLDER, Type};
+
+    use super::{AsKV, KV, KVBuf, StmtBuilder};
+
+    static TABLE: &str = "my_tbl";
 
     #[test]
+    #[allow(clippy::match_like_matches_macro)]
     fn test_getter() {
         let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
         assert_eq!(TABLE, sb.get_tbl());
@@ -334,6 +1970,12 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_getter_typ_eq() {
+        let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        assert_eq!(&Type::MySQL, sb.get_typ());
+    }
+
     #[test]
     fn test_build_insert_stmt() {
         struct TC<'a> {
@@ -364,289 +2006,1349 @@ mod tests {
         let cols3: Vec<KV<'_>> = Vec::new();
 
         let test_cases = vec![
-            // Single column
-            TC {
-                cols: &cols1,
-                want_mysql: "INSERT INTO my_tbl (`name`) VALUES ('product')",
-                want_postgresql: "INSERT INTO my_tbl (\"name\") VALUES ('product')",
-                want_sqlite: "INSERT INTO my_tbl (\"name\") VALUES ('product')",
-            },
-            // Multiple columns
+            // Single column
+            TC {
+                cols: &cols1,
+                want_mysql: "INSERT INTO my_tbl (`name`) VALUES ('product')",
+                want_postgresql: "INSERT INTO my_tbl (\"name\") VALUES ('product')",
+                want_sqlite: "INSERT INTO my_tbl (\"name\") VALUES ('product')",
+            },
+            // Multiple columns
+            TC {
+                cols: &cols2,
+                want_mysql: "INSERT INTO my_tbl (`email`, `age`, `username`) VALUES (?, 20, ?)",
+                want_postgresql: "INSERT INTO my_tbl (\"email\", \"age\", \"username\") VALUES ($1, 20, $2)",
+                want_sqlite: "INSERT INTO my_tbl (\"email\", \"age\", \"username\") VALUES (?, 20, ?)",
+            },
+            // Empty column
+            TC {
+                cols: &cols3,
+                want_mysql: "",
+                want_postgresql: "",
+                want_sqlite: "",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+            assert_eq!(sb_mysql.build_insert_stmt(tc.cols), tc.want_mysql);
+
+            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            assert_eq!(sb_postgresql.build_insert_stmt(tc.cols), tc.want_postgresql);
+
+            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
+            assert_eq!(sb_sqlite.build_insert_stmt(tc.cols), tc.want_sqlite);
+        }
+    }
+
+    #[test]
+    fn test_build_upsert_stmt() {
+        struct TC<'a> {
+            keys: Vec<&'a str>,
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+        }
+
+        let cols = vec![
+            KV {
+                key: "id",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "age",
+                val: "20",
+            },
+        ];
+        let test_cases = vec![
+            TC {
+                keys: vec!["id"],
+                want_mysql: "INSERT INTO my_tbl (`id`, `name`, `age`) VALUES (?, ?, 20) ON DUPLICATE KEY UPDATE `name` = VALUES(`name`), `age` = VALUES(`age`)",
+                want_postgresql: "INSERT INTO my_tbl (\"id\", \"name\", \"age\") VALUES ($1, $2, 20) ON CONFLICT (\"id\") DO UPDATE SET \"name\" = EXCLUDED.\"name\", \"age\" = EXCLUDED.\"age\"",
+            },
+            // Every column is a key
+            TC {
+                keys: vec!["id", "name", "age"],
+                want_mysql: "INSERT IGNORE INTO my_tbl (`id`, `name`, `age`) VALUES (?, ?, 20)",
+                want_postgresql: "INSERT INTO my_tbl (\"id\", \"name\", \"age\") VALUES ($1, $2, 20) ON CONFLICT (\"id\", \"name\", \"age\") DO NOTHING",
+            },
+            // Any conflict
+            TC {
+                keys: vec![],
+                want_mysql: "INSERT IGNORE INTO my_tbl (`id`, `name`, `age`) VALUES (?, ?, 20)",
+                want_postgresql: "INSERT INTO my_tbl (\"id\", \"name\", \"age\") VALUES ($1, $2, 20) ON CONFLICT DO NOTHING",
+            },
+        ];
+
+        let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        for tc in test_cases {
+            assert_eq!(sb_mysql.build_upsert_stmt(&cols, &tc.keys), tc.want_mysql);
+            assert_eq!(
+                sb_postgresql.build_upsert_stmt(&cols, &tc.keys),
+                tc.want_postgresql
+            );
+        }
+        assert_eq!(sb_mysql.build_upsert_stmt::<KV, &str>(&[], &["id"]), "");
+    }
+
+    #[test]
+    fn test_build_batch_insert_stmt() {
+        struct TC<'a> {
+            rows: Vec<Vec<KV<'a>>>,
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+        }
+
+        let row = |name, age| {
+            vec![
+                KV {
+                    key: "name",
+                    val: name,
+                },
+                KV {
+                    key: "age",
+                    val: age,
+                },
+            ]
+        };
+
+        let test_cases = vec![
+            // Single row
+            TC {
+                rows: vec![row(PLACEHOLDER, "20")],
+                want_mysql: "INSERT INTO my_tbl (`name`, `age`) VALUES (?, 20)",
+                want_postgresql: "INSERT INTO my_tbl (\"name\", \"age\") VALUES ($1, 20)",
+            },
+            // Multiple rows
+            TC {
+                rows: vec![
+                    row(PLACEHOLDER, PLACEHOLDER),
+                    row("'foo'", "30"),
+                    row(PLACEHOLDER, "40"),
+                ],
+                want_mysql: "INSERT INTO my_tbl (`name`, `age`) VALUES (?, ?), ('foo', 30), (?, 40)",
+                want_postgresql: "INSERT INTO my_tbl (\"name\", \"age\") VALUES ($1, $2), ('foo', 30), ($3, 40)",
+            },
+            // Empty rows
+            TC {
+                rows: Vec::new(),
+                want_mysql: "",
+                want_postgresql: "",
+            },
+            // Empty columns
+            TC {
+                rows: vec![Vec::new()],
+                want_mysql: "",
+                want_postgresql: "",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+            assert_eq!(sb_mysql.build_batch_insert_stmt(&tc.rows), tc.want_mysql);
+
+            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            assert_eq!(
+                sb_postgresql.build_batch_insert_stmt(&tc.rows),
+                tc.want_postgresql
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_query_stmt() {
+        struct TC<'a> {
+            cols: &'a [String],
+            conds: &'a [KV<'a>],
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+            want_sqlite: &'a str,
+        }
+
+        let cols1 = vec![String::from("username")];
+        let conds1 = vec![KV {
+            key: "id",
+            val: PLACEHOLDER,
+        }];
+
+        let cols2 = vec![String::from("username"), String::from("nickname")];
+        let conds2 = vec![
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "age",
+                val: "20",
+            },
+            KV {
+                key: "gender",
+                val: PLACEHOLDER,
+            },
+        ];
+
+        let cols3 = vec![String::from("*")];
+        let conds3 = vec![
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "age",
+                val: "20",
+            },
+            KV {
+                key: "gender",
+                val: PLACEHOLDER,
+            },
+        ];
+
+        let cols4: Vec<String> = Vec::new();
+        let conds4: Vec<KV<'_>> = Vec::new();
+
+        let test_cases = vec![
+            // Single column and condition
+            TC {
+                cols: &cols1,
+                conds: &conds1,
+                want_mysql: "SELECT `username` FROM my_tbl WHERE id = ?",
+                want_postgresql: "SELECT \"username\" FROM my_tbl WHERE id = $1",
+                want_sqlite: "SELECT \"username\" FROM my_tbl WHERE id = ?",
+            },
+            // Multiple columns and conditions
+            TC {
+                cols: &cols2,
+                conds: &conds2,
+                want_mysql: "SELECT `username`, `nickname` FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
+                want_postgresql: "SELECT \"username\", \"nickname\" FROM my_tbl WHERE name = $1 AND age = 20 AND gender = $2",
+                want_sqlite: "SELECT \"username\", \"nickname\" FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
+            },
+            // Select all columns
+            TC {
+                cols: &cols3,
+                conds: &conds3,
+                want_mysql: "SELECT * FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
+                want_postgresql: "SELECT * FROM my_tbl WHERE name = $1 AND age = 20 AND gender = $2",
+                want_sqlite: "SELECT * FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
+            },
+            // Empty columns and conditions
+            TC {
+                cols: &cols4,
+                conds: &conds4,
+                want_mysql: "SELECT * FROM my_tbl",
+                want_postgresql: "SELECT * FROM my_tbl",
+                want_sqlite: "SELECT * FROM my_tbl",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+            assert_eq!(sb_mysql.build_query_stmt(tc.cols, tc.conds), tc.want_mysql);
+
+            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            assert_eq!(
+                sb_postgresql.build_query_stmt(tc.cols, tc.conds),
+                tc.want_postgresql
+            );
+
+            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
+            assert_eq!(
+                sb_sqlite.build_query_stmt(tc.cols, tc.conds),
+                tc.want_sqlite
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_update_stmt() {
+        struct TC<'a> {
+            cols: &'a [KV<'a>],
+            conds: &'a [KV<'a>],
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+            want_sqlite: &'a str,
+        }
+
+        let cols1 = vec![
+            KV {
+                key: "age",
+                val: "20",
+            },
+            KV {
+                key: "username",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "nickname",
+                val: PLACEHOLDER,
+            },
+        ];
+        let conds1 = vec![
+            KV {
+                key: "id",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "status",
+                val: "'active'",
+            },
+        ];
+
+        let cols2: Vec<KV<'_>> = Vec::new();
+        let conds2: Vec<KV<'_>> = Vec::new();
+
+        let test_cases = vec![
+            // Multiple columns and conditions
+            TC {
+                cols: &cols1,
+                conds: &conds1,
+                want_mysql: "UPDATE my_tbl SET `age` = 20, `username` = ?, `nickname` = ? WHERE id = ? AND status = 'active'",
+                want_postgresql: "UPDATE my_tbl SET \"age\" = 20, \"username\" = $1, \"nickname\" = $2 WHERE id = $3 AND status = 'active'",
+                want_sqlite: "UPDATE my_tbl SET \"age\" = 20, \"username\" = ?, \"nickname\" = ? WHERE id = ? AND status = 'active'",
+            },
+            // Empty columns
+            TC {
+                cols: &cols2,
+                conds: &conds1,
+                want_mysql: "",
+                want_postgresql: "",
+                want_sqlite: "",
+            },
+            // Empty conditions
+            TC {
+                cols: &cols1,
+                conds: &conds2,
+                want_mysql: "UPDATE my_tbl SET `age` = 20, `username` = ?, `nickname` = ?",
+                want_postgresql: "UPDATE my_tbl SET \"age\" = 20, \"username\" = $1, \"nickname\" = $2",
+                want_sqlite: "UPDATE my_tbl SET \"age\" = 20, \"username\" = ?, \"nickname\" = ?",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+            assert_eq!(sb_mysql.build_update_stmt(tc.cols, tc.conds), tc.want_mysql);
+
+            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            assert_eq!(
+                sb_postgresql.build_update_stmt(tc.cols, tc.conds),
+                tc.want_postgresql
+            );
+
+            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
+            assert_eq!(
+                sb_sqlite.build_update_stmt(tc.cols, tc.conds),
+                tc.want_sqlite
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_update_stmt_with_version_col() {
+        let cols = vec![KV {
+            key: "username",
+            val: PLACEHOLDER,
+        }];
+        let conds = vec![KV {
+            key: "id",
+            val: PLACEHOLDER,
+        }];
+
+        let mut sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        sb_mysql.set_version_col(Some(String::from("version")));
+        assert_eq!(sb_mysql.get_version_col().unwrap(), "version");
+        assert_eq!(
+            sb_mysql.build_update_stmt(&cols, &conds),
+            "UPDATE my_tbl SET `username` = ?, `version` = `version` + 1 WHERE id = ? AND version = ?"
+        );
+        assert_eq!(
+            sb_mysql.build_update_stmt(&cols, &Vec::<KV>::new()),
+            "UPDATE my_tbl SET `username` = ?, `version` = `version` + 1 WHERE version = ?"
+        );
+
+        let mut sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb_postgresql.set_version_col(Some(String::from("version")));
+        assert_eq!(
+            sb_postgresql.build_update_stmt(&cols, &conds),
+            "UPDATE my_tbl SET \"username\" = $1, \"version\" = \"version\" + 1 WHERE id = $2 AND version = $3"
+        );
+
+        sb_postgresql.set_version_col(None);
+        assert!(sb_postgresql.get_version_col().is_none());
+        assert_eq!(
+            sb_postgresql.build_update_stmt(&cols, &conds),
+            "UPDATE my_tbl SET \"username\" = $1 WHERE id = $2"
+        );
+    }
+
+    #[test]
+    fn test_build_stmt_into() {
+        let sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        let cols = vec![KV {
+            key: "username",
+            val: PLACEHOLDER,
+        }];
+        let conds = vec![KV {
+            key: "id",
+            val: PLACEHOLDER,
+        }];
+
+        // Statements are appended to the existing content of the writer
+        let mut buf = String::from("-- ");
+        sb.build_insert_stmt_into(&mut buf, &cols).unwrap();
+        buf.push_str("; ");
+        sb.build_query_stmt_into(&mut buf, &Vec::<String>::new(), &conds)
+            .unwrap();
+        buf.push_str("; ");
+        sb.build_update_stmt_into(&mut buf, &cols, &conds).unwrap();
+        buf.push_str("; ");
+        sb.build_delete_stmt_into(&mut buf, &conds).unwrap();
+        assert_eq!(
+            buf,
+            "-- INSERT INTO my_tbl (\"username\") VALUES ($1); SELECT * FROM my_tbl WHERE id = $1; UPDATE my_tbl SET \"username\" = $1 WHERE id = $2; DELETE FROM my_tbl WHERE id = $1"
+        );
+
+        // Empty columns write nothing
+        let mut buf = String::new();
+        sb.build_insert_stmt_into(&mut buf, &Vec::<KV>::new())
+            .unwrap();
+        sb.build_update_stmt_into(&mut buf, &Vec::<KV>::new(), &conds)
+            .unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_kv_buf() {
+        let kv = KV {
+            key: "username",
+            val: PLACEHOLDER,
+        };
+        let buf = KVBuf::from(kv);
+        assert_eq!(
+            buf,
+            KVBuf {
+                key: String::from("username"),
+                val: String::from(PLACEHOLDER),
+            }
+        );
+        assert_eq!(KV::from(&buf), kv);
+        assert_eq!(buf.as_kv(), kv.as_kv());
+
+        // Owned and borrowed pairs produce the same statements
+        let sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        let cols = vec![
+            KVBuf {
+                key: String::from("username"),
+                val: String::from(PLACEHOLDER),
+            },
+            KVBuf {
+                key: format!("nick{}", "name"),
+                val: format!("'{}'", "foo"),
+            },
+        ];
+        let conds = vec![KVBuf {
+            key: String::from("id"),
+            val: String::from(PLACEHOLDER),
+        }];
+        let cols_ref: Vec<KV> = cols.iter().map(KV::from).collect();
+        let conds_ref: Vec<KV> = conds.iter().map(KV::from).collect();
+        assert_eq!(sb.build_insert_stmt(&cols), sb.build_insert_stmt(&cols_ref));
+        assert_eq!(
+            sb.build_query_stmt(&["username"], &conds),
+            sb.build_query_stmt(&[String::from("username")], &conds_ref)
+        );
+        assert_eq!(
+            sb.build_update_stmt(&cols, &conds),
+            sb.build_update_stmt(&cols_ref, &conds_ref)
+        );
+        assert_eq!(
+            sb.build_update_stmt(&cols, &conds_ref),
+            "UPDATE my_tbl SET \"username\" = $1, \"nickname\" = 'foo' WHERE id = $2"
+        );
+        assert_eq!(
+            sb.build_delete_stmt(&conds),
+            sb.build_delete_stmt(&conds_ref)
+        );
+    }
+
+    #[test]
+    fn test_build_stmt_with_cond() {
+        struct TC<'a> {
+            conds: Vec<Cond>,
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+        }
+
+        let test_cases = vec![
+            // Comparisons
+            TC {
+                conds: vec![
+                    Cond::ne("a", PLACEHOLDER),
+                    Cond::lt("b", "1"),
+                    Cond::le("c", PLACEHOLDER),
+                    Cond::gt("d", "2"),
+                    Cond::ge("e", PLACEHOLDER),
+                    Cond::like("f", "'%foo%'"),
+                ],
+                want_mysql: "DELETE FROM my_tbl WHERE a <> ? AND b < 1 AND c <= ? AND d > 2 AND e >= ? AND f LIKE '%foo%'",
+                want_postgresql: "DELETE FROM my_tbl WHERE a <> $1 AND b < 1 AND c <= $2 AND d > 2 AND e >= $3 AND f LIKE '%foo%'",
+            },
+            // IN lists
+            TC {
+                conds: vec![
+                    Cond::in_list("a", &[PLACEHOLDER, "1", PLACEHOLDER]),
+                    Cond::not_in_list("b", &[PLACEHOLDER]),
+                    Cond::in_list::<&str>("c", &[]),
+                    Cond::not_in_list::<&str>("d", &[]),
+                ],
+                want_mysql: "DELETE FROM my_tbl WHERE a IN (?, 1, ?) AND b NOT IN (?) AND 1 = 0 AND 1 = 1",
+                want_postgresql: "DELETE FROM my_tbl WHERE a IN ($1, 1, $2) AND b NOT IN ($3) AND 1 = 0 AND 1 = 1",
+            },
+            // NULL checks
+            TC {
+                conds: vec![Cond::is_null("a"), Cond::is_not_null("b")],
+                want_mysql: "DELETE FROM my_tbl WHERE a IS NULL AND b IS NOT NULL",
+                want_postgresql: "DELETE FROM my_tbl WHERE a IS NULL AND b IS NOT NULL",
+            },
+            // Nested conditions
+            TC {
+                conds: vec![
+                    Cond::And(vec![Cond::eq("a", PLACEHOLDER), Cond::eq("b", "1")]),
+                    Cond::Or(vec![
+                        Cond::eq("c", PLACEHOLDER),
+                        Cond::And(vec![Cond::eq("d", "2"), Cond::eq("e", PLACEHOLDER)]),
+                    ]),
+                    Cond::not(Cond::Or(vec![Cond::eq("f", "3")])),
+                ],
+                want_mysql: "DELETE FROM my_tbl WHERE a = ? AND b = 1 AND (c = ? OR (d = 2 AND e = ?)) AND NOT (f = 3)",
+                want_postgresql: "DELETE FROM my_tbl WHERE a = $1 AND b = 1 AND (c = $2 OR (d = 2 AND e = $3)) AND NOT (f = 3)",
+            },
+            // Empty nested conditions
+            TC {
+                conds: vec![Cond::And(vec![]), Cond::Or(vec![])],
+                want_mysql: "DELETE FROM my_tbl WHERE 1 = 1 AND 1 = 0",
+                want_postgresql: "DELETE FROM my_tbl WHERE 1 = 1 AND 1 = 0",
+            },
+            // Raw fragments
+            TC {
+                conds: vec![Cond::raw("a = 1 OR b = 2"), Cond::eq("c", PLACEHOLDER)],
+                want_mysql: "DELETE FROM my_tbl WHERE (a = 1 OR b = 2) AND c = ?",
+                want_postgresql: "DELETE FROM my_tbl WHERE (a = 1 OR b = 2) AND c = $1",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+            assert_eq!(sb_mysql.build_delete_stmt(&tc.conds), tc.want_mysql);
+
+            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            assert_eq!(
+                sb_postgresql.build_delete_stmt(&tc.conds),
+                tc.want_postgresql
+            );
+        }
+
+        // Placeholders in raw fragments are kept as is
+        let conds = [Cond::raw("a = ?"), Cond::eq("c", PLACEHOLDER)];
+        let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        assert_eq!(
+            sb.build_delete_stmt(&conds),
+            "DELETE FROM my_tbl WHERE (a = ?) AND c = ?"
+        );
+        #[cfg(not(all(feature = "validate", debug_assertions)))]
+        assert_eq!(
+            StmtBuilder::new(String::from(TABLE), Type::PostgreSQL).build_delete_stmt(&conds),
+            "DELETE FROM my_tbl WHERE (a = ?) AND c = $1"
+        );
+
+        // Placeholders in conditions are numbered after the assignments
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb.set_version_col(Some(String::from("version")));
+        assert_eq!(
+            sb.build_update_stmt(
+                &[KV {
+                    key: "a",
+                    val: PLACEHOLDER,
+                }],
+                &[Cond::in_list("id", &[PLACEHOLDER, PLACEHOLDER])],
+            ),
+            "UPDATE my_tbl SET \"a\" = $1, \"version\" = \"version\" + 1 WHERE id IN ($2, $3) AND version = $4"
+        );
+        assert_eq!(
+            sb.build_query_stmt(&["a"], &[Cond::gt("id", PLACEHOLDER)]),
+            "SELECT \"a\" FROM my_tbl WHERE id > $1"
+        );
+    }
+
+    #[test]
+    fn test_try_build_stmt() {
+        use std::sync::Arc;
+
+        use crate::db::{Policy, PolicyError};
+
+        let kv = |key| KV {
+            key,
+            val: PLACEHOLDER,
+        };
+        let col_err = |col: &str| {
+            Err(PolicyError::Column {
+                tbl: String::from(TABLE),
+                col: col.to_string(),
+            })
+        };
+
+        // Without a policy, everything is allowed
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        assert_eq!(
+            sb.try_build_update_stmt(&[kv("a")], &[kv("b")]),
+            Ok(sb.build_update_stmt(&[kv("a")], &[kv("b")]))
+        );
+
+        sb.set_policy(Some(Arc::new(Policy::new().allow(TABLE, &["id", "name"]))));
+        assert!(sb.get_policy().unwrap().is_allowed(TABLE, "id"));
+        assert!(sb.try_build_insert_stmt(&[kv("name")]).is_ok());
+        assert_eq!(sb.try_build_insert_stmt(&[kv("role")]), col_err("role"));
+        assert_eq!(
+            sb.try_build_batch_insert_stmt(&[vec![kv("name")], vec![kv("role")]]),
+            col_err("role")
+        );
+        assert!(sb.try_build_query_stmt(&["*"], &[kv("id")]).is_ok());
+        assert!(sb.try_build_query_stmt::<&str, KV>(&[], &[]).is_ok());
+        assert_eq!(
+            sb.try_build_query_stmt(&["name", "secret"], &[kv("id")]),
+            col_err("secret")
+        );
+        assert_eq!(
+            sb.try_build_count_stmt(&[Cond::Or(vec![Cond::eq("id", "1"), Cond::eq("x", "1")])]),
+            col_err("x")
+        );
+        assert_eq!(
+            sb.try_build_update_stmt(&[kv("name")], &[kv("id")]),
+            Ok(sb.build_update_stmt(&[kv("name")], &[kv("id")]))
+        );
+        assert_eq!(sb.try_build_delete_stmt(&[kv("x")]), col_err("x"));
+
+        // Tables without registered columns are denied
+        sb.set_policy(Some(Arc::new(Policy::new().allow("other", &["id"]))));
+        assert_eq!(
+            sb.try_build_delete_stmt::<KV>(&[]),
+            Err(PolicyError::Table(String::from(TABLE)))
+        );
+    }
+
+    #[test]
+    fn test_tenant_scope() {
+        use crate::db::TenantScope;
+
+        let kv = |key| KV {
+            key,
+            val: PLACEHOLDER,
+        };
+        let column = TenantScope::Column {
+            col: String::from("tenant_id"),
+            val: String::from(PLACEHOLDER),
+        };
+
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        assert!(sb.get_tenant().is_none());
+
+        // Per call
+        let prefixed = sb.scoped(TenantScope::Prefix(String::from("tenant_1")));
+        assert_eq!(
+            prefixed.build_query_stmt(&["id"], &[kv("name")]),
+            "SELECT \"id\" FROM tenant_1_my_tbl WHERE name = $1"
+        );
+        assert_eq!(
+            prefixed.build_batch_insert_stmt(&[[kv("name")], [kv("name")]]),
+            "INSERT INTO tenant_1_my_tbl (\"name\") VALUES ($1), ($2)"
+        );
+        let schema = sb.scoped(TenantScope::Schema(String::from("tenant_1")));
+        assert_eq!(
+            schema.build_count_stmt::<KV>(&[]),
+            "SELECT COUNT(*) FROM tenant_1.my_tbl"
+        );
+        assert!(sb.get_tenant().is_none());
+
+        // Per builder
+        sb.set_tenant(Some(column.clone()));
+        assert_eq!(sb.get_tenant(), Some(&column));
+        assert_eq!(
+            sb.build_insert_stmt(&[kv("name")]),
+            "INSERT INTO my_tbl (\"name\", \"tenant_id\") VALUES ($1, $2)"
+        );
+        assert_eq!(
+            sb.build_batch_insert_stmt(&[[kv("name")], [kv("name")]]),
+            "INSERT INTO my_tbl (\"name\", \"tenant_id\") VALUES ($1, $2), ($3, $4)"
+        );
+        assert_eq!(
+            sb.build_query_stmt::<&str, KV>(&[], &[]),
+            "SELECT * FROM my_tbl WHERE tenant_id = $1"
+        );
+        assert_eq!(
+            sb.build_delete_stmt(&[Cond::Or(vec![Cond::eq("a", "1"), Cond::eq("b", "2")])]),
+            "DELETE FROM my_tbl WHERE (a = 1 OR b = 2) AND tenant_id = $1"
+        );
+
+        // The tenant parameter comes before the version
+        sb.set_version_col(Some(String::from("version")));
+        assert_eq!(
+            sb.build_update_stmt(&[kv("name")], &[kv("id")]),
+            "UPDATE my_tbl SET \"name\" = $1, \"version\" = \"version\" + 1 WHERE id = $2 AND tenant_id = $3 AND version = $4"
+        );
+
+        // Literal tenant values
+        let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL).scoped(TenantScope::Column {
+            col: String::from("tenant_id"),
+            val: String::from("42"),
+        });
+        assert_eq!(
+            sb.build_count_stmt(&[kv("name")]),
+            "SELECT COUNT(*) FROM my_tbl WHERE name = ? AND tenant_id = 42"
+        );
+    }
+
+    #[cfg(all(feature = "validate", debug_assertions))]
+    #[test]
+    #[should_panic(expected = "invalid statement")]
+    fn test_debug_validate() {
+        let sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb.build_delete_stmt(&[Cond::raw("a = ?")]);
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn test_stmt_macro() {
+        use crate::db::stmt;
+
+        let kv = |key| KV {
+            key,
+            val: PLACEHOLDER,
+        };
+        let cols = [kv("a"), KV { key: "b", val: "1" }];
+        let conds = [kv("id"), KV { key: "c", val: "2" }];
+
+        let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        assert_eq!(
+            stmt!(MySQL, insert into "my_tbl" ["a", "b" = "1"]),
+            sb.build_insert_stmt(&cols)
+        );
+        assert_eq!(
+            stmt!(MySQL, select [] from "my_tbl"),
+            sb.build_query_stmt(&Vec::<&str>::new(), &Vec::<KV>::new())
+        );
+
+        let sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        assert_eq!(
+            stmt!(PostgreSQL, select ["a", "*"] from "my_tbl" where ["id", "c" = "2"]),
+            sb.build_query_stmt(&["a", "*"], &conds)
+        );
+        assert_eq!(
+            stmt!(Type::PostgreSQL, update "my_tbl" set ["a", "b" = "1"] where ["id", "c" = "2"]),
+            sb.build_update_stmt(&cols, &conds)
+        );
+
+        let sb = StmtBuilder::new(String::from(TABLE), Type::SQLite);
+        assert_eq!(
+            stmt!(SQLite, delete from "my_tbl" where ["id", "c" = "2"]),
+            sb.build_delete_stmt(&conds)
+        );
+        assert_eq!(
+            stmt!(SQLite, delete from "my_tbl"),
+            sb.build_delete_stmt(&Vec::<KV>::new())
+        );
+    }
+
+    #[test]
+    fn test_build_count_stmt() {
+        let conds = vec![
+            KV {
+                key: "a",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "b",
+                val: PLACEHOLDER,
+            },
+        ];
+
+        let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        assert_eq!(
+            sb.build_count_stmt(&Vec::<KV>::new()),
+            "SELECT COUNT(*) FROM my_tbl"
+        );
+        assert_eq!(
+            sb.build_count_stmt(&conds),
+            "SELECT COUNT(*) FROM my_tbl WHERE a = ? AND b = ?"
+        );
+
+        let sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        assert_eq!(
+            sb.build_count_stmt(&conds),
+            "SELECT COUNT(*) FROM my_tbl WHERE a = $1 AND b = $2"
+        );
+    }
+
+    #[test]
+    fn test_build_delete_stmt() {
+        struct TC<'a> {
+            conds: &'a [KV<'a>],
+            want_mysql: &'a str,
+            want_postgresql: &'a str,
+            want_sqlite: &'a str,
+        }
+
+        let conds1 = vec![KV {
+            key: "username",
+            val: PLACEHOLDER,
+        }];
+        let conds2 = vec![
+            KV {
+                key: "username",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "nickname",
+                val: "'foo'",
+            },
+        ];
+        let conds3: Vec<KV<'_>> = Vec::new();
+
+        let test_cases = vec![
+            // Single column
+            TC {
+                conds: &conds1,
+                want_mysql: "DELETE FROM my_tbl WHERE username = ?",
+                want_postgresql: "DELETE FROM my_tbl WHERE username = $1",
+                want_sqlite: "DELETE FROM my_tbl WHERE username = ?",
+            },
+            // Multiple columns
+            TC {
+                conds: &conds2,
+                want_mysql: "DELETE FROM my_tbl WHERE username = ? AND nickname = 'foo'",
+                want_postgresql: "DELETE FROM my_tbl WHERE username = $1 AND nickname = 'foo'",
+                want_sqlite: "DELETE FROM my_tbl WHERE username = ? AND nickname = 'foo'",
+            },
+            // Empty conditions
+            TC {
+                conds: &conds3,
+                want_mysql: "DELETE FROM my_tbl",
+                want_postgresql: "DELETE FROM my_tbl",
+                want_sqlite: "DELETE FROM my_tbl",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+            assert_eq!(sb_mysql.build_delete_stmt(tc.conds), tc.want_mysql);
+
+            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            assert_eq!(
+                sb_postgresql.build_delete_stmt(tc.conds),
+                tc.want_postgresql
+            );
+
+            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
+            assert_eq!(sb_sqlite.build_delete_stmt(tc.conds), tc.want_sqlite);
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        use crate::db::StmtKind;
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb.set_version_col(Some(String::from("version")));
+        sb.before_build(|kind, conds| {
+            if kind == StmtKind::Update {
+                conds.push(Cond::eq("locked", "FALSE"));
+            }
+        });
+        sb.before_build(|_, conds| conds.push(Cond::eq("tenant_id", "1")));
+        {
+            let captured = Arc::clone(&captured);
+            sb.on_build(move |stmt| {
+                captured
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push((stmt.kind, stmt.tbl.clone()));
+                stmt.sql.insert_str(0, "/* tagged */ ");
+            });
+        }
+
+        let kv = KV {
+            key: "name",
+            val: PLACEHOLDER,
+        };
+        assert_eq!(
+            sb.build_update_stmt(&[kv], &[Cond::eq("id", PLACEHOLDER)]),
+            "/* tagged */ UPDATE my_tbl SET \"name\" = $1, \"version\" = \"version\" + 1 WHERE id = $2 AND locked = FALSE AND tenant_id = 1 AND version = $3"
+        );
+        assert_eq!(
+            sb.build_insert_stmt(&[kv]),
+            "/* tagged */ INSERT INTO my_tbl (\"name\") VALUES ($1)"
+        );
+        let mut stmt = String::new();
+        sb.build_count_stmt_into(&mut stmt, &[] as &[Cond]).unwrap();
+        assert_eq!(stmt, "SELECT COUNT(*) FROM my_tbl WHERE tenant_id = 1");
+        assert_eq!(
+            *captured.lock().unwrap_or_else(|e| e.into_inner()),
+            vec![
+                (StmtKind::Update, String::from(TABLE)),
+                (StmtKind::Insert, String::from(TABLE)),
+            ]
+        );
+
+        // Hooks are compared by identity.
+        assert_eq!(sb.clone(), sb);
+        let mut other = sb.clone();
+        other.on_build(|_| {});
+        assert_ne!(other, sb);
+    }
+
+    #[test]
+    fn test_server_version() {
+        use crate::db::{BuildError, Feature, UnsupportedFeature, Version};
+
+        let cols = [
+            KV {
+                key: "id",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+        ];
+
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        sb.set_server_version(Some(Version::new(8, 0, 36)));
+        assert_eq!(
+            sb.build_upsert_stmt(&cols, &["id"]),
+            "INSERT INTO my_tbl (`id`, `name`) VALUES (?, ?) AS new ON DUPLICATE KEY UPDATE `name` = new.`name`"
+        );
+        sb.set_server_version(Some(Version::new(5, 7, 44)));
+        assert_eq!(
+            sb.build_upsert_stmt(&cols, &["id"]),
+            "INSERT INTO my_tbl (`id`, `name`) VALUES (?, ?) ON DUPLICATE KEY UPDATE `name` = VALUES(`name`)"
+        );
+
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb.set_server_version(Some(Version::new(9, 4, 0)));
+        assert_eq!(
+            sb.try_build_upsert_stmt(&cols, &[] as &[&str]),
+            Err(BuildError::Unsupported(UnsupportedFeature {
+                feature: Feature::Upsert,
+                typ: Type::PostgreSQL,
+                required: Some(Version::new(9, 5, 0)),
+            }))
+        );
+        sb.set_server_version(None);
+        assert!(sb.try_build_upsert_stmt(&cols, &["id"]).is_ok());
+    }
+
+    #[test]
+    fn test_as_of_system_time() {
+        let conds = [KV {
+            key: "a",
+            val: PLACEHOLDER,
+        }];
+
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::CockroachDB);
+        sb.set_as_of_system_time(Some(String::from("'-10s'")));
+        assert_eq!(
+            sb.build_count_stmt(&conds),
+            "SELECT COUNT(*) FROM my_tbl AS OF SYSTEM TIME '-10s' WHERE a = $1"
+        );
+        assert_eq!(
+            sb.build_aliased_query_stmt(&[("a", "b")], &[] as &[Cond]),
+            "SELECT \"a\" AS \"b\" FROM my_tbl AS OF SYSTEM TIME '-10s'"
+        );
+        // Writes always read the latest data.
+        assert_eq!(
+            sb.build_delete_stmt(&conds),
+            "DELETE FROM my_tbl WHERE a = $1"
+        );
+
+        // Other databases read the latest data.
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb.set_as_of_system_time(Some(String::from("'-10s'")));
+        assert_eq!(
+            sb.build_count_stmt(&conds),
+            "SELECT COUNT(*) FROM my_tbl WHERE a = $1"
+        );
+    }
+
+    #[test]
+    fn test_build_ctas_stmt() {
+        struct TC {
+            typ: Type,
+            want: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                typ: Type::MySQL,
+                want: "CREATE TABLE snapshot AS SELECT * FROM my_tbl WHERE a = ?",
+            },
+            TC {
+                typ: Type::SQLite,
+                want: "CREATE TABLE snapshot AS SELECT * FROM my_tbl WHERE a = ?",
+            },
+            TC {
+                typ: Type::CockroachDB,
+                want: "CREATE TABLE snapshot AS SELECT * FROM my_tbl WHERE a = $1",
+            },
+        ];
+
+        for tc in test_cases {
+            let sb = StmtBuilder::new(String::from(TABLE), tc.typ);
+            assert_eq!(
+                sb.build_ctas_stmt("snapshot", &[] as &[&str], &[Cond::eq("a", PLACEHOLDER)]),
+                tc.want
+            );
+        }
+    }
+
+    #[test]
+    fn test_build_ctas_stmt_hints() {
+        use crate::db::Hint;
+
+        struct TC {
+            typ: Type,
+            want: &'static str,
+        }
+
+        let test_cases = vec![
+            // The pg_hint_plan comment leads the statement
             TC {
-                cols: &cols2,
-                want_mysql: "INSERT INTO my_tbl (`email`, `age`, `username`) VALUES (?, 20, ?)",
-                want_postgresql: "INSERT INTO my_tbl (\"email\", \"age\", \"username\") VALUES ($1, 20, $2)",
-                want_sqlite: "INSERT INTO my_tbl (\"email\", \"age\", \"username\") VALUES (?, 20, ?)",
+                typ: Type::PostgreSQL,
+                want: "/*+ IndexScan(my_tbl idx_a) */ CREATE TABLE snapshot AS SELECT * FROM my_tbl WHERE a = $1",
             },
-            // Empty column
+            // Optimizer hints follow the keyword of the query
             TC {
-                cols: &cols3,
-                want_mysql: "",
-                want_postgresql: "",
-                want_sqlite: "",
+                typ: Type::MySQL,
+                want: "CREATE TABLE snapshot AS SELECT /*+ INDEX(my_tbl idx_a) */ * FROM my_tbl WHERE a = ?",
             },
         ];
 
         for tc in test_cases {
-            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
-            assert_eq!(sb_mysql.build_insert_stmt(tc.cols), tc.want_mysql);
+            let mut sb = StmtBuilder::new(String::from(TABLE), tc.typ);
+            sb.set_hints(vec![Hint::Index(String::from("idx_a"))]);
+            assert_eq!(
+                sb.build_ctas_stmt("snapshot", &[] as &[&str], &[Cond::eq("a", PLACEHOLDER)]),
+                tc.want
+            );
+        }
+    }
 
-            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
-            assert_eq!(sb_postgresql.build_insert_stmt(tc.cols), tc.want_postgresql);
+    #[test]
+    fn test_try_build_ctas_stmt() {
+        use crate::db::{BuildError, Feature, UnsupportedFeature};
 
-            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
-            assert_eq!(sb_sqlite.build_insert_stmt(tc.cols), tc.want_sqlite);
-        }
+        let conds = [Cond::eq("a", PLACEHOLDER)];
+        let sb = StmtBuilder::new(String::from(TABLE), Type::MySQL);
+        assert_eq!(
+            sb.try_build_ctas_stmt("snapshot", &["a"], &conds).unwrap(),
+            "CREATE TABLE snapshot AS SELECT `a` FROM my_tbl WHERE a = ?"
+        );
+
+        let sb = StmtBuilder::new(String::from(TABLE), Type::TiDB);
+        assert_eq!(
+            sb.try_build_ctas_stmt("snapshot", &["a"], &conds),
+            Err(BuildError::Unsupported(UnsupportedFeature {
+                feature: Feature::CreateTableAs,
+                typ: Type::TiDB,
+                required: None,
+            }))
+        );
     }
 
     #[test]
-    fn test_build_query_stmt() {
-        struct TC<'a> {
-            cols: &'a [String],
-            conds: &'a [KV<'a>],
-            want_mysql: &'a str,
-            want_postgresql: &'a str,
-            want_sqlite: &'a str,
-        }
+    fn test_tuple_cond() {
+        use crate::db::Version;
 
-        let cols1 = vec![String::from("username")];
-        let conds1 = vec![KV {
-            key: "id",
-            val: PLACEHOLDER,
-        }];
+        struct TC {
+            typ: Type,
+            version: Option<Version>,
+            want_eq: &'static str,
+            want_in: &'static str,
+        }
 
-        let cols2 = vec![String::from("username"), String::from("nickname")];
-        let conds2 = vec![
-            KV {
-                key: "name",
-                val: PLACEHOLDER,
+        let test_cases = vec![
+            TC {
+                typ: Type::MySQL,
+                version: None,
+                want_eq: "SELECT * FROM my_tbl WHERE (a, b) = (?, 1)",
+                want_in: "SELECT * FROM my_tbl WHERE (a, b) NOT IN ((?, ?), (?, ?))",
             },
-            KV {
-                key: "age",
-                val: "20",
+            TC {
+                typ: Type::MySQL,
+                version: Some(Version::new(5, 6, 51)),
+                want_eq: "SELECT * FROM my_tbl WHERE (a = ? AND b = 1)",
+                want_in: "SELECT * FROM my_tbl WHERE NOT (((a = ? AND b = ?) OR (a = ? AND b = ?)))",
             },
-            KV {
-                key: "gender",
-                val: PLACEHOLDER,
+            TC {
+                typ: Type::PostgreSQL,
+                version: None,
+                want_eq: "SELECT * FROM my_tbl WHERE (a, b) = ($1, 1)",
+                want_in: "SELECT * FROM my_tbl WHERE (a, b) NOT IN (($1, $2), ($3, $4))",
+            },
+            TC {
+                typ: Type::SQLite,
+                version: None,
+                want_eq: "SELECT * FROM my_tbl WHERE (a, b) = (?, 1)",
+                want_in: "SELECT * FROM my_tbl WHERE (a, b) NOT IN (VALUES (?, ?), (?, ?))",
             },
         ];
 
-        let cols3 = vec![String::from("*")];
-        let conds3 = vec![
-            KV {
-                key: "name",
-                val: PLACEHOLDER,
+        let cols = ["a", "b"];
+        let eq = [Cond::tuple_eq(&cols, &[PLACEHOLDER, "1"])];
+        let not_in = [Cond::tuple_not_in(
+            &cols,
+            &[[PLACEHOLDER; 2], [PLACEHOLDER; 2]],
+        )];
+        assert_eq!(not_in[0].count_placeholders(), 4);
+        for tc in test_cases {
+            let mut sb = StmtBuilder::new(String::from(TABLE), tc.typ);
+            sb.set_server_version(tc.version);
+            assert_eq!(sb.build_query_stmt(&[] as &[&str], &eq), tc.want_eq);
+            assert_eq!(sb.build_query_stmt(&[] as &[&str], &not_in), tc.want_in);
+        }
+        let sb = StmtBuilder::new(String::from(TABLE), Type::DuckDB);
+        assert_eq!(
+            sb.build_count_stmt(&[Cond::tuple_in(&cols, &[] as &[[&str; 2]])]),
+            "SELECT COUNT(*) FROM my_tbl WHERE 1 = 0"
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_cond() {
+        struct TC {
+            typ: Type,
+            want: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                typ: Type::TiDB,
+                want: "SELECT * FROM my_tbl WHERE email COLLATE utf8mb4_general_ci = ? AND name COLLATE utf8mb4_general_ci LIKE 'a%'",
             },
-            KV {
-                key: "age",
-                val: "20",
+            TC {
+                typ: Type::PostgreSQL,
+                want: "SELECT * FROM my_tbl WHERE LOWER(email) = LOWER($1) AND name ILIKE 'a%'",
             },
-            KV {
-                key: "gender",
-                val: PLACEHOLDER,
+            TC {
+                typ: Type::SQLite,
+                want: "SELECT * FROM my_tbl WHERE LOWER(email) = LOWER(?) AND LOWER(name) LIKE LOWER('a%')",
             },
         ];
 
-        let cols4: Vec<String> = Vec::new();
-        let conds4: Vec<KV<'_>> = Vec::new();
+        let conds = [
+            Cond::eq_ci("email", PLACEHOLDER),
+            Cond::like_ci("name", "'a%'"),
+        ];
+        assert_eq!(conds[0].count_placeholders(), 1);
+        for tc in test_cases {
+            let sb = StmtBuilder::new(String::from(TABLE), tc.typ);
+            assert_eq!(sb.build_query_stmt(&[] as &[&str], &conds), tc.want);
+        }
+        let sb = StmtBuilder::new(String::from(TABLE), Type::DuckDB);
+        assert_eq!(
+            sb.build_count_stmt(&[Cond::not(Cond::CmpCi {
+                col: String::from("name"),
+                op: CmpOp::NotLike,
+                val: String::from("'a%'"),
+            })]),
+            "SELECT COUNT(*) FROM my_tbl WHERE NOT (name NOT ILIKE 'a%')"
+        );
+    }
+
+    #[test]
+    fn test_build_sample_stmt() {
+        use crate::db::Sample;
+
+        struct TC {
+            typ: Type,
+            want_percent: &'static str,
+            want_rows: &'static str,
+        }
 
         let test_cases = vec![
-            // Single column and condition
             TC {
-                cols: &cols1,
-                conds: &conds1,
-                want_mysql: "SELECT `username` FROM my_tbl WHERE id = ?",
-                want_postgresql: "SELECT \"username\" FROM my_tbl WHERE id = $1",
-                want_sqlite: "SELECT \"username\" FROM my_tbl WHERE id = ?",
+                typ: Type::TiDB,
+                want_percent: "SELECT * FROM my_tbl WHERE a = ? AND (RAND() < 0.005)",
+                want_rows: "SELECT * FROM my_tbl WHERE a = ? ORDER BY RAND() LIMIT 10",
             },
-            // Multiple columns and conditions
             TC {
-                cols: &cols2,
-                conds: &conds2,
-                want_mysql: "SELECT `username`, `nickname` FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
-                want_postgresql: "SELECT \"username\", \"nickname\" FROM my_tbl WHERE name = $1 AND age = 20 AND gender = $2",
-                want_sqlite: "SELECT \"username\", \"nickname\" FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
+                typ: Type::SQLite,
+                want_percent: "SELECT * FROM my_tbl WHERE a = ? AND (ABS(RANDOM() % 1000000) < 5000)",
+                want_rows: "SELECT * FROM my_tbl WHERE a = ? ORDER BY RANDOM() LIMIT 10",
             },
-            // Select all columns
             TC {
-                cols: &cols3,
-                conds: &conds3,
-                want_mysql: "SELECT * FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
-                want_postgresql: "SELECT * FROM my_tbl WHERE name = $1 AND age = 20 AND gender = $2",
-                want_sqlite: "SELECT * FROM my_tbl WHERE name = ? AND age = 20 AND gender = ?",
+                typ: Type::CockroachDB,
+                want_percent: "SELECT * FROM my_tbl WHERE a = $1 AND (RANDOM() < 0.005)",
+                want_rows: "SELECT * FROM my_tbl WHERE a = $1 ORDER BY RANDOM() LIMIT 10",
             },
-            // Empty columns and conditions
             TC {
-                cols: &cols4,
-                conds: &conds4,
-                want_mysql: "SELECT * FROM my_tbl",
-                want_postgresql: "SELECT * FROM my_tbl",
-                want_sqlite: "SELECT * FROM my_tbl",
+                typ: Type::DuckDB,
+                want_percent: "SELECT * FROM my_tbl TABLESAMPLE 0.5 PERCENT WHERE a = ?",
+                want_rows: "SELECT * FROM my_tbl WHERE a = ? ORDER BY RANDOM() LIMIT 10",
             },
         ];
 
+        let conds = [Cond::eq("a", PLACEHOLDER)];
         for tc in test_cases {
-            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
-            assert_eq!(sb_mysql.build_query_stmt(tc.cols, tc.conds), tc.want_mysql);
-
-            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            let sb = StmtBuilder::new(String::from(TABLE), tc.typ);
             assert_eq!(
-                sb_postgresql.build_query_stmt(tc.cols, tc.conds),
-                tc.want_postgresql
+                sb.build_sample_stmt(&[] as &[&str], &conds, Sample::Percent(0.5))
+                    .unwrap(),
+                tc.want_percent
             );
-
-            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
             assert_eq!(
-                sb_sqlite.build_query_stmt(tc.cols, tc.conds),
-                tc.want_sqlite
+                sb.build_sample_stmt(&[] as &[&str], &conds, Sample::Rows(10))
+                    .unwrap(),
+                tc.want_rows
             );
         }
     }
 
     #[test]
-    fn test_build_update_stmt() {
-        struct TC<'a> {
-            cols: &'a [KV<'a>],
-            conds: &'a [KV<'a>],
-            want_mysql: &'a str,
-            want_postgresql: &'a str,
-            want_sqlite: &'a str,
+    fn test_build_sample_stmt_percent_range() {
+        use crate::db::{InvalidSample, Sample};
+
+        struct TC {
+            percent: f64,
+            ok: bool,
         }
 
-        let cols1 = vec![
-            KV {
-                key: "age",
-                val: "20",
-            },
-            KV {
-                key: "username",
-                val: PLACEHOLDER,
+        let test_cases = vec![
+            // Bounds are inclusive
+            TC {
+                percent: 0.0,
+                ok: true,
             },
-            KV {
-                key: "nickname",
-                val: PLACEHOLDER,
+            TC {
+                percent: 100.0,
+                ok: true,
             },
-        ];
-        let conds1 = vec![
-            KV {
-                key: "id",
-                val: PLACEHOLDER,
+            TC {
+                percent: -0.1,
+                ok: false,
             },
-            KV {
-                key: "status",
-                val: "'active'",
+            TC {
+                percent: 100.1,
+                ok: false,
             },
-        ];
-
-        let cols2: Vec<KV<'_>> = Vec::new();
-        let conds2: Vec<KV<'_>> = Vec::new();
-
-        let test_cases = vec![
-            // Multiple columns and conditions
             TC {
-                cols: &cols1,
-                conds: &conds1,
-                want_mysql: "UPDATE my_tbl SET `age` = 20, `username` = ?, `nickname` = ? WHERE id = ? AND status = 'active'",
-                want_postgresql: "UPDATE my_tbl SET \"age\" = 20, \"username\" = $1, \"nickname\" = $2 WHERE id = $3 AND status = 'active'",
-                want_sqlite: "UPDATE my_tbl SET \"age\" = 20, \"username\" = ?, \"nickname\" = ? WHERE id = ? AND status = 'active'",
+                percent: f64::NAN,
+                ok: false,
             },
-            // Empty columns
             TC {
-                cols: &cols2,
-                conds: &conds1,
-                want_mysql: "",
-                want_postgresql: "",
-                want_sqlite: "",
+                percent: f64::INFINITY,
+                ok: false,
             },
-            // Empty conditions
             TC {
-                cols: &cols1,
-                conds: &conds2,
-                want_mysql: "UPDATE my_tbl SET `age` = 20, `username` = ?, `nickname` = ?",
-                want_postgresql: "UPDATE my_tbl SET \"age\" = 20, \"username\" = $1, \"nickname\" = $2",
-                want_sqlite: "UPDATE my_tbl SET \"age\" = 20, \"username\" = ?, \"nickname\" = ?",
+                percent: f64::NEG_INFINITY,
+                ok: false,
             },
         ];
 
+        let sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
         for tc in test_cases {
-            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
-            assert_eq!(sb_mysql.build_update_stmt(tc.cols, tc.conds), tc.want_mysql);
-
-            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
-            assert_eq!(
-                sb_postgresql.build_update_stmt(tc.cols, tc.conds),
-                tc.want_postgresql
-            );
-
-            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
-            assert_eq!(
-                sb_sqlite.build_update_stmt(tc.cols, tc.conds),
-                tc.want_sqlite
-            );
+            let res = sb.build_sample_stmt(&["id"], &[] as &[Cond], Sample::Percent(tc.percent));
+            assert_eq!(res.is_ok(), tc.ok, "percent = {}", tc.percent);
+            if let Err(InvalidSample(percent)) = res {
+                assert!(percent.total_cmp(&tc.percent).is_eq());
+            }
         }
     }
 
     #[test]
-    fn test_build_delete_stmt() {
-        struct TC<'a> {
-            conds: &'a [KV<'a>],
-            want_mysql: &'a str,
-            want_postgresql: &'a str,
-            want_sqlite: &'a str,
-        }
-
-        let conds1 = vec![KV {
-            key: "username",
+    fn test_partitions() {
+        let conds = [Cond::eq("a", PLACEHOLDER)];
+        let kvs = [KV {
+            key: "a",
             val: PLACEHOLDER,
         }];
-        let conds2 = vec![
-            KV {
-                key: "username",
-                val: PLACEHOLDER,
-            },
-            KV {
-                key: "nickname",
-                val: "'foo'",
-            },
-        ];
-        let conds3: Vec<KV<'_>> = Vec::new();
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::MariaDB);
+        sb.set_partitions(vec![String::from("p0"), String::from("p1")]);
+        assert_eq!(
+            sb.build_insert_stmt(&kvs),
+            "INSERT INTO my_tbl PARTITION (p0, p1) (`a`) VALUES (?)"
+        );
+        assert_eq!(
+            sb.build_update_stmt(&kvs, &conds),
+            "UPDATE my_tbl PARTITION (p0, p1) SET `a` = ? WHERE a = ?"
+        );
+        assert_eq!(
+            sb.build_delete_stmt(&conds),
+            "DELETE FROM my_tbl PARTITION (p0, p1) WHERE a = ?"
+        );
+        assert_eq!(
+            sb.build_count_stmt(&conds),
+            "SELECT COUNT(*) FROM my_tbl PARTITION (p0, p1) WHERE a = ?"
+        );
+
+        let mut sb = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+        sb.set_partitions(vec![String::from("p0")]);
+        assert_eq!(
+            sb.build_delete_stmt(&conds),
+            "DELETE FROM my_tbl WHERE a = $1"
+        );
+    }
+
+    #[test]
+    fn test_quote_reserved() {
+        struct TC {
+            typ: Type,
+            want_update: &'static str,
+            want_delete: &'static str,
+        }
 
         let test_cases = vec![
-            // Single column
-            TC {
-                conds: &conds1,
-                want_mysql: "DELETE FROM my_tbl WHERE username = ?",
-                want_postgresql: "DELETE FROM my_tbl WHERE username = $1",
-                want_sqlite: "DELETE FROM my_tbl WHERE username = ?",
-            },
-            // Multiple columns
             TC {
-                conds: &conds2,
-                want_mysql: "DELETE FROM my_tbl WHERE username = ? AND nickname = 'foo'",
-                want_postgresql: "DELETE FROM my_tbl WHERE username = $1 AND nickname = 'foo'",
-                want_sqlite: "DELETE FROM my_tbl WHERE username = ? AND nickname = 'foo'",
+                typ: Type::MySQL,
+                want_update: "UPDATE `order` SET `key` = ? WHERE `key` = ? AND (`group`, name) IN ((1, 2), (3, 4))",
+                want_delete: "DELETE FROM `order` WHERE `key` COLLATE utf8mb4_general_ci = ? AND o.`desc` NOT IN (1)",
             },
-            // Empty conditions
             TC {
-                conds: &conds3,
-                want_mysql: "DELETE FROM my_tbl",
-                want_postgresql: "DELETE FROM my_tbl",
-                want_sqlite: "DELETE FROM my_tbl",
+                typ: Type::SQLite,
+                want_update: "UPDATE \"order\" SET \"key\" = ? WHERE key = ? AND (\"group\", name) IN (VALUES (1, 2), (3, 4))",
+                want_delete: "DELETE FROM \"order\" WHERE LOWER(key) = LOWER(?) AND o.desc NOT IN (1)",
             },
         ];
 
+        let kvs = [KV {
+            key: "key",
+            val: PLACEHOLDER,
+        }];
+        let tuple = Cond::Tuple {
+            cols: vec![String::from("group"), String::from("name")],
+            rows: vec![
+                vec![String::from("1"), String::from("2")],
+                vec![String::from("3"), String::from("4")],
+            ],
+            negated: false,
+        };
+        let conds = [
+            Cond::eq_ci("key", PLACEHOLDER),
+            Cond::not_in_list("o.desc", &["1"]),
+        ];
         for tc in test_cases {
-            let sb_mysql = StmtBuilder::new(String::from(TABLE), Type::MySQL);
-            assert_eq!(sb_mysql.build_delete_stmt(tc.conds), tc.want_mysql);
-
-            let sb_postgresql = StmtBuilder::new(String::from(TABLE), Type::PostgreSQL);
+            let mut sb = StmtBuilder::new(String::from("order"), tc.typ);
+            sb.set_quote_reserved(true);
+            assert!(sb.is_quote_reserved());
             assert_eq!(
-                sb_postgresql.build_delete_stmt(tc.conds),
-                tc.want_postgresql
+                sb.build_update_stmt(&kvs, &[Cond::eq("key", PLACEHOLDER), tuple.clone()]),
+                tc.want_update
             );
-
-            let sb_sqlite = StmtBuilder::new(String::from(TABLE), Type::SQLite);
-            assert_eq!(sb_sqlite.build_delete_stmt(tc.conds), tc.want_sqlite);
+            assert_eq!(sb.build_delete_stmt(&conds), tc.want_delete);
         }
     }
 }
@@ -0,0 +1,501 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::db::{AsCond, AsKV, Cond, StmtBuilder, StmtKind};
+
+/// The structure of a statement, which determines the generated SQL.
+///
+/// The whole builder is part of the key, so that every option of the builder is taken into account.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+struct Shape {
+    kind: StmtKind,
+    sb: StmtBuilder,
+    cols: Vec<String>,
+    conds: Vec<Cond>,
+}
+
+impl Shape {
+    fn new(kind: StmtKind, sb: &StmtBuilder, cols: Vec<String>, conds: Vec<Cond>) -> Shape {
+        Shape {
+            kind,
+            sb: sb.clone(),
+            cols,
+            conds,
+        }
+    }
+}
+
+fn to_conds<P: AsCond>(conds: &[P]) -> Vec<Cond> {
+    conds.iter().map(|p| p.as_cond().to_cond()).collect()
+}
+
+fn flatten_kv<P: AsKV>(kvs: &[P]) -> Vec<String> {
+    kvs.iter()
+        .flat_map(|p| {
+            let kv = p.as_kv();
+            [kv.key.to_string(), kv.val.to_string()]
+        })
+        .collect()
+}
+
+/// A thread-safe LRU cache of SQL statements generated by [`StmtBuilder`].
+///
+/// Statements are keyed by their structure, i.e. the builder with all its options,
+/// the columns and the conditions.
+/// Building a statement whose structure has been seen before returns the cached SQL string
+/// instead of formatting it again.
+/// Post-build hooks registered via [`StmtBuilder::on_build`] still run on every call,
+/// since only the statement before the hooks is cached.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, StmtCache, Type};
+///
+/// let cache = StmtCache::new(128);
+/// let sb = StmtBuilder::new(String::from("my_tbl"), Type::MySQL);
+/// let conds = vec![KV {
+///     key: "id",
+///     val: PLACEHOLDER,
+/// }];
+///
+/// let stmt = cache.build_delete_stmt(&sb, &conds);
+/// let stmt = cache.build_delete_stmt(&sb, &conds);
+///
+/// assert_eq!(&*stmt, "DELETE FROM my_tbl WHERE id = ?");
+/// assert_eq!(cache.get_hits(), 1);
+/// assert_eq!(cache.get_misses(), 1);
+/// ```
+pub struct StmtCache {
+    lru: Mutex<Lru<Shape, Arc<str>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl StmtCache {
+    /// Creates a new [`StmtCache`] that holds at most `capacity` statements.
+    pub fn new(capacity: usize) -> StmtCache {
+        StmtCache {
+            lru: Mutex::new(Lru::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the maximum number of cached statements.
+    pub fn get_capacity(&self) -> usize {
+        self.lock().cap
+    }
+
+    /// Gets the number of cached statements.
+    pub fn len(&self) -> usize {
+        self.lock().map.len()
+    }
+
+    /// Returns `true` if no statement is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the number of cache hits.
+    pub fn get_hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of cache misses.
+    pub fn get_misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Removes all cached statements. The counters are kept.
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        *lru = Lru::new(lru.cap);
+    }
+
+    /// Cached version of [`StmtBuilder::build_insert_stmt`].
+    pub fn build_insert_stmt<P: AsKV>(&self, sb: &StmtBuilder, cols: &[P]) -> Arc<str> {
+        self.get_or_build(
+            Shape::new(StmtKind::Insert, sb, flatten_kv(cols), Vec::new()),
+            sb,
+            |sb| sb.build_insert_stmt(cols),
+            || {},
+        )
+    }
+
+    /// Cached version of [`StmtBuilder::build_query_stmt`].
+    pub fn build_query_stmt<S: AsRef<str>, P: AsCond>(
+        &self,
+        sb: &StmtBuilder,
+        cols: &[S],
+        conds: &[P],
+    ) -> Arc<str> {
+        let cols_key = cols.iter().map(|col| col.as_ref().to_string()).collect();
+        self.get_or_build(
+            Shape::new(StmtKind::Query, sb, cols_key, to_conds(conds)),
+            sb,
+            |sb| sb.build_query_stmt(cols, conds),
+            || sb.record(conds, &[]),
+        )
+    }
+
+    /// Cached version of [`StmtBuilder::build_update_stmt`].
+    pub fn build_update_stmt<C: AsKV, P: AsCond>(
+        &self,
+        sb: &StmtBuilder,
+        cols: &[C],
+        conds: &[P],
+    ) -> Arc<str> {
+        let shape = Shape::new(StmtKind::Update, sb, flatten_kv(cols), to_conds(conds));
+        self.get_or_build(
+            shape,
+            sb,
+            |sb| sb.build_update_stmt(cols, conds),
+            || sb.record(conds, &[]),
+        )
+    }
+
+    /// Cached version of [`StmtBuilder::build_delete_stmt`].
+    pub fn build_delete_stmt<P: AsCond>(&self, sb: &StmtBuilder, conds: &[P]) -> Arc<str> {
+        self.get_or_build(
+            Shape::new(StmtKind::Delete, sb, Vec::new(), to_conds(conds)),
+            sb,
+            |sb| sb.build_delete_stmt(conds),
+            || sb.record(conds, &[]),
+        )
+    }
+
+    /// Gets the cached statement of the shape, or builds and caches it.
+    ///
+    /// `on_hit` is called on cache hits for the side effects of building that are skipped,
+    /// like recording the statement in the index advisor.
+    /// The statement is cached before the post-build hooks of `sb`, which run on every call.
+    fn get_or_build<F, H>(&self, shape: Shape, sb: &StmtBuilder, build: F, on_hit: H) -> Arc<str>
+    where
+        F: FnOnce(&StmtBuilder) -> String,
+        H: FnOnce(),
+    {
+        let kind = shape.kind;
+        let cached = self.lock().get(&shape).cloned();
+        let stmt = match cached {
+            Some(stmt) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                on_hit();
+                stmt
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                // Build without holding the lock.
+                let stmt: Arc<str> = if sb.has_post_hooks() {
+                    Arc::from(build(&sb.without_post_hooks()))
+                } else {
+                    Arc::from(build(sb))
+                };
+                self.lock().put(shape, stmt.clone());
+                stmt
+            }
+        };
+        if !sb.has_post_hooks() {
+            return stmt;
+        }
+        Arc::from(sb.finish(kind, stmt.to_string()))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Lru<Shape, Arc<str>>> {
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+const NIL: usize = usize::MAX;
+
+struct Node<K, V> {
+    key: K,
+    val: V,
+    prev: usize,
+    next: usize,
+}
+
+/// A least recently used cache, where nodes are stored in a slab and linked by indices.
+struct Lru<K, V> {
+    cap: usize,
+    map: HashMap<K, usize>,
+    nodes: Vec<Node<K, V>>,
+    // Most recently used.
+    head: usize,
+    // Least recently used.
+    tail: usize,
+}
+
+impl<K: Eq + Hash + Clone, V> Lru<K, V> {
+    fn new(cap: usize) -> Lru<K, V> {
+        Lru {
+            cap,
+            map: HashMap::new(),
+            nodes: Vec::new(),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let idx = *self.map.get(key)?;
+        self.detach(idx);
+        self.attach_front(idx);
+        Some(&self.nodes[idx].val)
+    }
+
+    fn put(&mut self, key: K, val: V) {
+        if self.cap == 0 {
+            return;
+        }
+        if let Some(&idx) = self.map.get(&key) {
+            self.nodes[idx].val = val;
+            self.detach(idx);
+            self.attach_front(idx);
+            return;
+        }
+        let idx = if self.map.len() < self.cap {
+            self.nodes.push(Node {
+                key: key.clone(),
+                val,
+                prev: NIL,
+                next: NIL,
+            });
+            self.nodes.len() - 1
+        } else {
+            // Reuse the slot of the least recently used node.
+            let idx = self.tail;
+            self.detach(idx);
+            let node = &mut self.nodes[idx];
+            self.map.remove(&node.key);
+            node.key = key.clone();
+            node.val = val;
+            idx
+        };
+        self.map.insert(key, idx);
+        self.attach_front(idx);
+    }
+
+    fn detach(&mut self, idx: usize) {
+        let (prev, next) = (self.nodes[idx].prev, self.nodes[idx].next);
+        match prev {
+            NIL => self.head = next,
+            p => self.nodes[p].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.nodes[n].prev = prev,
+        }
+    }
+
+    fn attach_front(&mut self, idx: usize) {
+        self.nodes[idx].prev = NIL;
+        self.nodes[idx].next = self.head;
+        match self.head {
+            NIL => self.tail = idx,
+            h => self.nodes[h].prev = idx,
+        }
+        self.head = idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::db::{Cond, Hint, IndexAdvisor, KV, PLACEHOLDER, StmtBuilder, Type};
+
+    use super::{Lru, StmtCache};
+
+    #[test]
+    fn test_lru() {
+        let mut lru = Lru::new(2);
+        lru.put("a", 1);
+        lru.put("b", 2);
+        assert_eq!(lru.get(&"a"), Some(&1));
+        // "b" is the least recently used
+        lru.put("c", 3);
+        assert_eq!(lru.get(&"b"), None);
+        assert_eq!(lru.get(&"a"), Some(&1));
+        assert_eq!(lru.get(&"c"), Some(&3));
+        // Update existing key
+        lru.put("a", 4);
+        lru.put("d", 5);
+        assert_eq!(lru.get(&"a"), Some(&4));
+        assert_eq!(lru.get(&"c"), None);
+        assert_eq!(lru.get(&"d"), Some(&5));
+
+        let mut lru = Lru::new(0);
+        lru.put("a", 1);
+        assert_eq!(lru.get(&"a"), None);
+    }
+
+    #[test]
+    fn test_stmt_cache() {
+        let cache = StmtCache::new(2);
+        assert_eq!(cache.get_capacity(), 2);
+        assert!(cache.is_empty());
+
+        let sb = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+        let cols = vec![KV {
+            key: "name",
+            val: PLACEHOLDER,
+        }];
+        let conds = vec![KV {
+            key: "id",
+            val: PLACEHOLDER,
+        }];
+
+        let insert = cache.build_insert_stmt(&sb, &cols);
+        assert_eq!(&*insert, sb.build_insert_stmt(&cols));
+        assert!(Arc::ptr_eq(&insert, &cache.build_insert_stmt(&sb, &cols)));
+        assert_eq!((cache.get_hits(), cache.get_misses()), (1, 1));
+
+        let query = cache.build_query_stmt(&sb, &[String::from("name")], &conds);
+        assert_eq!(
+            &*query,
+            sb.build_query_stmt(&[String::from("name")], &conds)
+        );
+        assert_eq!(cache.len(), 2);
+
+        // Evicts the insert statement
+        let update = cache.build_update_stmt(&sb, &cols, &conds);
+        assert_eq!(&*update, sb.build_update_stmt(&cols, &conds));
+        assert_eq!(cache.len(), 2);
+        cache.build_insert_stmt(&sb, &cols);
+        assert_eq!((cache.get_hits(), cache.get_misses()), (1, 4));
+
+        // Different builder options produce different keys
+        let mut sb_versioned = StmtBuilder::new(String::from("my_tbl"), Type::PostgreSQL);
+        sb_versioned.set_version_col(Some(String::from("version")));
+        assert_eq!(
+            &*cache.build_update_stmt(&sb_versioned, &cols, &conds),
+            sb_versioned.build_update_stmt(&cols, &conds)
+        );
+
+        let delete = cache.build_delete_stmt(&sb, &conds);
+        assert_eq!(&*delete, sb.build_delete_stmt(&conds));
+        assert_eq!((cache.get_hits(), cache.get_misses()), (1, 6));
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get_capacity(), 2);
+    }
+
+    #[test]
+    fn test_stmt_cache_builder_options() {
+        struct TC {
+            name: &'static str,
+            base: StmtBuilder,
+            set: fn(&mut StmtBuilder),
+        }
+
+        let test_cases = vec![
+            TC {
+                name: "hooks",
+                base: StmtBuilder::new(String::from("events"), Type::MySQL),
+                set: |sb| sb.before_build(|_, conds| conds.push(Cond::is_null("deleted_at"))),
+            },
+            TC {
+                name: "as_of",
+                base: StmtBuilder::new(String::from("events"), Type::CockroachDB),
+                set: |sb| sb.set_as_of_system_time(Some(String::from("'-10s'"))),
+            },
+            TC {
+                name: "hints",
+                base: StmtBuilder::new(String::from("events"), Type::MySQL),
+                set: |sb| sb.set_hints(vec![Hint::Index(String::from("idx_id"))]),
+            },
+            TC {
+                name: "partitions",
+                base: StmtBuilder::new(String::from("events"), Type::MySQL),
+                set: |sb| sb.set_partitions(vec![String::from("p0")]),
+            },
+            TC {
+                name: "quote_reserved",
+                base: StmtBuilder::new(String::from("order"), Type::MySQL),
+                set: |sb| sb.set_quote_reserved(true),
+            },
+        ];
+
+        let conds = [Cond::eq("id", PLACEHOLDER)];
+        for tc in test_cases {
+            let mut sb = tc.base.clone();
+            (tc.set)(&mut sb);
+            let cache = StmtCache::new(8);
+            let stmt = cache.build_query_stmt(&tc.base, &["id"], &conds);
+            assert_eq!(
+                &*stmt,
+                tc.base.build_query_stmt(&["id"], &conds),
+                "{}",
+                tc.name
+            );
+            let stmt = cache.build_query_stmt(&sb, &["id"], &conds);
+            assert_eq!(&*stmt, sb.build_query_stmt(&["id"], &conds), "{}", tc.name);
+            assert_ne!(
+                &*stmt,
+                tc.base.build_query_stmt(&["id"], &conds),
+                "{}",
+                tc.name
+            );
+            assert_eq!(cache.get_misses(), 2, "{}", tc.name);
+        }
+    }
+
+    #[test]
+    fn test_stmt_cache_index_advisor() {
+        let advisor = Arc::new(IndexAdvisor::new());
+        let mut sb = StmtBuilder::new(String::from("events"), Type::MySQL);
+        sb.set_index_advisor(Some(advisor.clone()));
+        let conds = [Cond::eq("user_id", PLACEHOLDER)];
+        let cols = [KV {
+            key: "name",
+            val: PLACEHOLDER,
+        }];
+
+        let cache = StmtCache::new(8);
+        for _ in 0..3 {
+            cache.build_query_stmt(&sb, &["id"], &conds);
+            cache.build_update_stmt(&sb, &cols, &conds);
+            cache.build_delete_stmt(&sb, &conds);
+        }
+        assert_eq!((cache.get_hits(), cache.get_misses()), (6, 3));
+        let suggestions = advisor.suggest(Type::MySQL);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].builds, 9);
+    }
+
+    #[test]
+    fn test_stmt_cache_post_hooks() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut sb = StmtBuilder::new(String::from("my_tbl"), Type::MySQL);
+        sb.on_build({
+            let calls = calls.clone();
+            move |stmt| {
+                let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                stmt.sql.push_str(&format!(" /* call {} */", n));
+            }
+        });
+        let conds = [Cond::eq("id", PLACEHOLDER)];
+
+        let cache = StmtCache::new(8);
+        let first = cache.build_query_stmt(&sb, &["id"], &conds);
+        let second = cache.build_query_stmt(&sb, &["id"], &conds);
+        assert_eq!((cache.get_hits(), cache.get_misses()), (1, 1));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(&*first, "SELECT `id` FROM my_tbl WHERE id = ? /* call 1 */");
+        assert_eq!(
+            &*second,
+            "SELECT `id` FROM my_tbl WHERE id = ? /* call 2 */"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::{ops::Deref, sync::Arc};
+
+use crate::db::StmtBuilder;
+
+/// A frozen [`StmtBuilder`] that can be cloned cheaply and shared across threads.
+///
+/// Configure a builder once, for example at startup, then freeze it and hand out clones
+/// to request handlers, which build statements with their own columns and conditions concurrently.
+/// The template dereferences to [`StmtBuilder`], so all the `build_*` methods are available.
+/// Use [`StmtTemplate::to_builder`] to derive a differently configured builder.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// use sainnhe_common::db::{KV, PLACEHOLDER, StmtBuilder, Type};
+///
+/// let mut sb = StmtBuilder::new(String::from("users"), Type::PostgreSQL);
+/// sb.set_version_col(Some(String::from("version")));
+/// let tmpl = sb.freeze();
+///
+/// let handle = {
+///     let tmpl = tmpl.clone();
+///     thread::spawn(move || {
+///         tmpl.build_query_stmt(
+///             &["name"],
+///             &[KV {
+///                 key: "id",
+///                 val: PLACEHOLDER,
+///             }],
+///         )
+///     })
+/// };
+///
+/// assert_eq!(
+///     handle.join().unwrap(),
+///     "SELECT \"name\" FROM users WHERE id = $1"
+/// );
+/// assert_eq!(tmpl.get_version_col().unwrap(), "version");
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct StmtTemplate {
+    sb: Arc<StmtBuilder>,
+}
+
+impl StmtTemplate {
+    /// Gets the frozen builder.
+    pub fn get_builder(&self) -> &StmtBuilder {
+        &self.sb
+    }
+
+    /// Copies the frozen builder, so that it can be reconfigured without affecting the template.
+    pub fn to_builder(&self) -> StmtBuilder {
+        StmtBuilder::clone(&self.sb)
+    }
+}
+
+impl From<StmtBuilder> for StmtTemplate {
+    fn from(sb: StmtBuilder) -> Self {
+        StmtTemplate { sb: Arc::new(sb) }
+    }
+}
+
+impl Deref for StmtTemplate {
+    type Target = StmtBuilder;
+
+    fn deref(&self) -> &Self::Target {
+        &self.sb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Cond, PLACEHOLDER, StmtBuilder, Type};
+
+    use super::StmtTemplate;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<StmtBuilder>();
+        assert_send_sync::<StmtTemplate>();
+    }
+
+    #[test]
+    fn test_stmt_template() {
+        let sb = StmtBuilder::new(String::from("my_tbl"), Type::MySQL);
+        let tmpl = sb.clone().freeze();
+        assert_eq!(tmpl.get_builder(), &sb);
+        assert_eq!(tmpl.clone(), tmpl);
+
+        let mut derived = tmpl.to_builder();
+        derived.set_version_col(Some(String::from("version")));
+        assert!(tmpl.get_version_col().is_none());
+        assert_eq!(
+            tmpl.build_delete_stmt(&[Cond::eq("id", PLACEHOLDER)]),
+            "DELETE FROM my_tbl WHERE id = ?"
+        );
+        assert_ne!(derived, sb);
+    }
+}
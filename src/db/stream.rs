@@ -0,0 +1,269 @@
+//! Fetching large result sets in chunks.
+//!
+//! [`fetch_chunked`] returns a [`Stream`] of row batches, so only one chunk is held in memory at a time.
+
+use futures_util::{Stream, stream};
+use sqlx::{Any, AnyConnection, Column, Connection, Row, Transaction, any::AnyRow};
+
+use crate::db::{Cond, Filter, PLACEHOLDER, StmtBuilder, Value, exec};
+
+/// The name of the cursor declared on PostgreSQL.
+const CURSOR: &str = "sainnhe_common_chunked";
+
+enum State<'a> {
+    Start(&'a mut AnyConnection),
+    /// Keyset pagination, holding the key of the last fetched row.
+    Keyset(&'a mut AnyConnection, Value),
+    /// A server-side cursor declared in the transaction.
+    Cursor(Transaction<'a, Any>),
+    Done,
+}
+
+/// Fetches the rows matching `filter` in chunks of `chunk_size` rows, ordered by `key` in ascending order.
+///
+/// On PostgreSQL, the query is run once with a server-side cursor in a transaction,
+/// which is committed when the stream ends and rolled back if the stream is dropped early.
+/// Other databases use keyset pagination, where each chunk is a query for the rows after the last key,
+/// so `key` must be a unique, non-null column and must be selected.
+///
+/// # Arguments
+///
+/// * `conn` - The connection, which is borrowed until the stream is dropped.
+/// * `sb` - The statement builder.
+/// * `cols` - The selected columns. If it's empty, `["*"]` will be used.
+/// * `filter` - The conditions and their bound values.
+///   With a [`TenantScope::Column`](crate::db::TenantScope::Column) bound to a placeholder,
+///   the tenant value is the last value of `filter`.
+/// * `key` - The column to sort by.
+/// * `chunk_size` - The maximum number of rows per chunk, which must be positive.
+///
+/// # Returns
+///
+/// * The stream of chunks, none of which is empty.
+///
+/// # Examples
+///
+/// ```
+/// use futures_util::TryStreamExt;
+/// use sainnhe_common::db::{Filter, StmtBuilder, Type, exec, stream::fetch_chunked};
+/// use sqlx::{AnyConnection, Connection};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+/// exec::execute(&mut conn, "CREATE TABLE t (id INTEGER PRIMARY KEY)", &[]).await.unwrap();
+/// exec::execute(&mut conn, "INSERT INTO t VALUES (1), (2), (3)", &[]).await.unwrap();
+///
+/// let sb = StmtBuilder::new(String::from("t"), Type::SQLite);
+/// let filter = Filter::new();
+/// let chunks: Vec<usize> = fetch_chunked(&mut conn, &sb, &["id"], &filter, "id", 2)
+///     .map_ok(|rows| rows.len())
+///     .try_collect()
+///     .await
+///     .unwrap();
+///
+/// assert_eq!(chunks, [2, 1]);
+/// # });
+/// ```
+pub fn fetch_chunked<'a, S: AsRef<str>>(
+    conn: &'a mut AnyConnection,
+    sb: &'a StmtBuilder,
+    cols: &'a [S],
+    filter: &'a Filter,
+    key: &'a str,
+    chunk_size: u64,
+) -> impl Stream<Item = Result<Vec<AnyRow>, sqlx::Error>> + 'a {
+    stream::try_unfold(State::Start(conn), move |state| async move {
+        match state {
+            State::Start(conn) if sb.get_typ().is_postgres_family() => {
+                let mut tx = conn.begin().await?;
+                let stmt = format!(
+                    "DECLARE {} NO SCROLL CURSOR FOR {}",
+                    CURSOR,
+                    build_stmt(sb, cols, filter.get_conds().to_vec(), key)
+                );
+                exec::execute(&mut *tx, &stmt, filter.get_args()).await?;
+                next_cursor(tx, chunk_size).await
+            }
+            State::Start(conn) => next_keyset(conn, sb, cols, filter, key, chunk_size, None).await,
+            State::Keyset(conn, last) => {
+                next_keyset(conn, sb, cols, filter, key, chunk_size, Some(last)).await
+            }
+            State::Cursor(tx) => next_cursor(tx, chunk_size).await,
+            State::Done => Ok(None),
+        }
+    })
+}
+
+fn build_stmt<S: AsRef<str>>(sb: &StmtBuilder, cols: &[S], conds: Vec<Cond>, key: &str) -> String {
+    let mut stmt = sb.build_query_stmt(cols, &conds);
+    stmt.push_str(" ORDER BY ");
+    // Writing to a String never fails.
+    let _ = sb.write_col(&mut stmt, key);
+    stmt
+}
+
+async fn next_keyset<'a, S: AsRef<str>>(
+    conn: &'a mut AnyConnection,
+    sb: &StmtBuilder,
+    cols: &[S],
+    filter: &Filter,
+    key: &str,
+    chunk_size: u64,
+    last: Option<Value>,
+) -> Result<Option<(Vec<AnyRow>, State<'a>)>, sqlx::Error> {
+    // The keyset condition goes first, so the values are bound in the order the placeholders are written,
+    // including a tenant value bound after the filter values.
+    let mut conds = Vec::with_capacity(filter.get_conds().len() + 1);
+    let mut args = Vec::with_capacity(filter.get_args().len() + 1);
+    if let Some(last) = last {
+        conds.push(Cond::gt(key, PLACEHOLDER));
+        args.push(last);
+    }
+    conds.extend_from_slice(filter.get_conds());
+    args.extend_from_slice(filter.get_args());
+    let stmt = format!("{} LIMIT {}", build_stmt(sb, cols, conds, key), chunk_size);
+    let rows = exec::fetch_all(&mut *conn, &stmt, &args).await?;
+    let Some(row) = rows.last() else {
+        return Ok(None);
+    };
+    if (rows.len() as u64) < chunk_size {
+        return Ok(Some((rows, State::Done)));
+    }
+    let idx = row
+        .columns()
+        .iter()
+        .position(|c| c.name() == key)
+        .ok_or_else(|| sqlx::Error::ColumnNotFound(key.to_string()))?;
+    let last = exec::decode_value(row, idx)?;
+    Ok(Some((rows, State::Keyset(conn, last))))
+}
+
+async fn next_cursor(
+    mut tx: Transaction<'_, Any>,
+    chunk_size: u64,
+) -> Result<Option<(Vec<AnyRow>, State<'_>)>, sqlx::Error> {
+    let stmt = format!("FETCH {} FROM {}", chunk_size, CURSOR);
+    let rows = exec::fetch_all(&mut *tx, &stmt, &[]).await?;
+    if (rows.len() as u64) < chunk_size {
+        // Committing closes the cursor.
+        tx.commit().await?;
+        return Ok((!rows.is_empty()).then_some((rows, State::Done)));
+    }
+    Ok(Some((rows, State::Cursor(tx))))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::TryStreamExt;
+    use sqlx::{AnyConnection, Connection, Row};
+
+    use crate::db::{Cond, Filter, PLACEHOLDER, StmtBuilder, TenantScope, Type, Value, exec};
+
+    use super::fetch_chunked;
+
+    #[tokio::test]
+    async fn test_fetch_chunked() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        exec::execute(
+            &mut conn,
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, parity INTEGER)",
+            &[],
+        )
+        .await
+        .unwrap();
+        for i in 1..=10 {
+            exec::execute(
+                &mut conn,
+                "INSERT INTO t VALUES (?, ?)",
+                &[Value::from(i), Value::from(i % 2)],
+            )
+            .await
+            .unwrap();
+        }
+        let sb = StmtBuilder::new(String::from("t"), Type::SQLite);
+
+        struct TC {
+            filter: Filter,
+            chunk_size: u64,
+            want: Vec<Vec<i64>>,
+        }
+
+        let test_cases = vec![
+            TC {
+                filter: Filter::new(),
+                chunk_size: 4,
+                want: vec![vec![1, 2, 3, 4], vec![5, 6, 7, 8], vec![9, 10]],
+            },
+            // The last chunk is full
+            TC {
+                filter: Filter::new()
+                    .filter_with(Cond::eq("parity", PLACEHOLDER), [Value::from(0)]),
+                chunk_size: 5,
+                want: vec![vec![2, 4, 6, 8, 10]],
+            },
+            TC {
+                filter: Filter::new().filter(Cond::gt("id", "10")),
+                chunk_size: 3,
+                want: vec![],
+            },
+        ];
+
+        for tc in test_cases {
+            let chunks: Vec<Vec<i64>> =
+                fetch_chunked(&mut conn, &sb, &["id"], &tc.filter, "id", tc.chunk_size)
+                    .map_ok(|rows| rows.iter().map(|r| r.get::<i64, _>(0)).collect())
+                    .try_collect()
+                    .await
+                    .unwrap();
+            assert_eq!(chunks, tc.want);
+        }
+
+        // The key must be selected
+        let filter = Filter::new();
+        let res: Result<Vec<_>, _> = fetch_chunked(&mut conn, &sb, &["parity"], &filter, "id", 2)
+            .try_collect()
+            .await;
+        assert!(matches!(res, Err(sqlx::Error::ColumnNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_chunked_tenant_placeholder() {
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        exec::execute(
+            &mut conn,
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, tenant INTEGER, parity INTEGER)",
+            &[],
+        )
+        .await
+        .unwrap();
+        for i in 1..=10 {
+            exec::execute(
+                &mut conn,
+                "INSERT INTO t VALUES (?, ?, ?)",
+                &[Value::from(i), Value::from(i % 3), Value::from(i % 2)],
+            )
+            .await
+            .unwrap();
+        }
+        let sb = StmtBuilder::new(String::from("t"), Type::SQLite).scoped(TenantScope::Column {
+            col: String::from("tenant"),
+            val: String::from(PLACEHOLDER),
+        });
+
+        // Tenant 1 has 1, 4, 7 and 10, of which 4 and 10 are even.
+        // The tenant value is bound last, after the value of the parity.
+        let filter = Filter::new().filter_with(
+            Cond::eq("parity", PLACEHOLDER),
+            [Value::from(0), Value::from(1)],
+        );
+        let chunks: Vec<Vec<i64>> = fetch_chunked(&mut conn, &sb, &["id"], &filter, "id", 1)
+            .map_ok(|rows| rows.iter().map(|r| r.get::<i64, _>(0)).collect())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks, vec![vec![4], vec![10]]);
+    }
+}
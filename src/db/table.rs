@@ -0,0 +1,329 @@
+use std::fmt;
+
+use crate::{
+    db::{StmtBuilder, Type},
+    hash::fnv1a,
+};
+
+/// Error returned when a [`TableRef`] has more qualifiers than the database type supports.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TableRefError {
+    pub typ: Type,
+    /// The number of segments of the reference.
+    pub depth: usize,
+    /// The maximum number of segments supported by the database type.
+    pub max: usize,
+}
+
+impl fmt::Display for TableRefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} supports table references of at most {} segments, got {}",
+            self.typ, self.max, self.depth
+        )
+    }
+}
+
+impl std::error::Error for TableRefError {}
+
+/// A table name qualified by a schema and a database, like `db.schema.table`.
+///
+/// Each segment is quoted as an identifier of the database type, so unlike the plain table names
+/// of [`StmtBuilder::new`], the segments may contain dots and reserved words.
+///
+/// The supported depth depends on the database type, see [`TableRef::max_depth`].
+/// MySQL, MariaDB and TiDB call schemas databases, and SQLite calls attached databases schemas,
+/// so either qualifier can be used there, but not both.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{TableRef, Type};
+///
+/// let tbl = TableRef::new("orders").schema("sales");
+/// assert_eq!(tbl.try_render(Type::MySQL).unwrap(), "`sales`.`orders`");
+/// assert_eq!(tbl.try_render(Type::PostgreSQL).unwrap(), "\"sales\".\"orders\"");
+///
+/// let tbl = tbl.db("shop");
+/// assert_eq!(
+///     tbl.try_render(Type::CockroachDB).unwrap(),
+///     "\"shop\".\"sales\".\"orders\""
+/// );
+/// assert!(tbl.try_render(Type::MySQL).is_err());
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct TableRef {
+    db: Option<String>,
+    schema: Option<String>,
+    name: String,
+}
+
+impl TableRef {
+    /// Creates an unqualified [`TableRef`].
+    pub fn new(name: &str) -> TableRef {
+        TableRef {
+            db: None,
+            schema: None,
+            name: name.to_string(),
+        }
+    }
+
+    /// Parses a dot-separated reference like `db.schema.table`.
+    ///
+    /// Segments are split at every dot, so use the constructors for names containing dots.
+    ///
+    /// # Returns
+    ///
+    /// * The reference, or [`None`] if there are more than three segments or any segment is empty.
+    pub fn parse(s: &str) -> Option<TableRef> {
+        let segments: Vec<&str> = s.split('.').collect();
+        if segments.len() > 3 || segments.iter().any(|segment| segment.is_empty()) {
+            return None;
+        }
+        let mut segments = segments.into_iter().rev();
+        Some(TableRef {
+            name: segments.next()?.to_string(),
+            schema: segments.next().map(str::to_string),
+            db: segments.next().map(str::to_string),
+        })
+    }
+
+    /// Qualifies the table with a schema.
+    pub fn schema(mut self, schema: &str) -> Self {
+        self.schema = Some(schema.to_string());
+        self
+    }
+
+    /// Qualifies the table with a database.
+    pub fn db(mut self, db: &str) -> Self {
+        self.db = Some(db.to_string());
+        self
+    }
+
+    /// Gets the database.
+    pub fn get_db(&self) -> Option<&String> {
+        self.db.as_ref()
+    }
+
+    /// Gets the schema.
+    pub fn get_schema(&self) -> Option<&String> {
+        self.schema.as_ref()
+    }
+
+    /// Gets the table name.
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    /// Returns the number of segments, from 1 for an unqualified table to 3 for `db.schema.table`.
+    pub fn depth(&self) -> usize {
+        1 + self.db.is_some() as usize + self.schema.is_some() as usize
+    }
+
+    /// Returns the maximum number of segments supported by the database type.
+    ///
+    /// PostgreSQL parses three segments, but rejects databases other than the current one when running the statement.
+    /// CockroachDB and DuckDB resolve a database without a schema to the default schema of the database,
+    /// while PostgreSQL takes it as a schema.
+    pub fn max_depth(typ: Type) -> usize {
+        match typ {
+            Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite => 2,
+            Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => 3,
+        }
+    }
+
+    /// Returns the maximum length of an identifier of the database type, or [`None`] if it's unlimited.
+    ///
+    /// PostgreSQL counts bytes and silently truncates longer identifiers, so different names can collide,
+    /// while MySQL, MariaDB and TiDB count characters and reject longer identifiers.
+    /// CockroachDB, SQLite and DuckDB don't limit identifiers.
+    pub fn max_ident_len(typ: Type) -> Option<usize> {
+        match typ {
+            Type::PostgreSQL => Some(63),
+            Type::MySQL | Type::MariaDB | Type::TiDB => Some(64),
+            Type::CockroachDB | Type::SQLite | Type::DuckDB => None,
+        }
+    }
+
+    /// Renders the reference with each segment quoted for the database type.
+    ///
+    /// # Returns
+    ///
+    /// * The rendered reference, or an error if the database type doesn't support the depth.
+    pub fn try_render(&self, typ: Type) -> Result<String, TableRefError> {
+        let (depth, max) = (self.depth(), TableRef::max_depth(typ));
+        if depth > max {
+            return Err(TableRefError { typ, depth, max });
+        }
+        let mut s = String::new();
+        for segment in [&self.db, &self.schema].into_iter().flatten() {
+            write_quoted(&mut s, typ, segment);
+            s.push('.');
+        }
+        write_quoted(&mut s, typ, &self.name);
+        Ok(s)
+    }
+}
+
+/// Quotes an identifier, doubling the quotes inside it.
+pub(crate) fn write_quoted(s: &mut String, typ: Type, ident: &str) {
+    let quote = if typ.is_mysql_family() { '`' } else { '"' };
+    s.push(quote);
+    for c in ident.chars() {
+        if c == quote {
+            s.push(quote);
+        }
+        s.push(c);
+    }
+    s.push(quote);
+}
+
+/// Returns `true` if the identifier exceeds [`TableRef::max_ident_len`].
+pub(crate) fn is_ident_too_long(typ: Type, ident: &str) -> bool {
+    match TableRef::max_ident_len(typ) {
+        Some(max) if typ.is_mysql_family() => ident.chars().count() > max,
+        Some(max) => ident.len() > max,
+        None => false,
+    }
+}
+
+/// Shortens a generated identifier that exceeds [`TableRef::max_ident_len`]
+/// by truncating it and appending the hash of the whole identifier, like `fk_..._1a2b3c4d`,
+/// so the result is the same for the same identifier and differs between identifiers sharing a prefix.
+pub(crate) fn shorten_ident(typ: Type, ident: &str) -> String {
+    let Some(max) = TableRef::max_ident_len(typ) else {
+        return ident.to_string();
+    };
+    if !is_ident_too_long(typ, ident) {
+        return ident.to_string();
+    }
+    // The suffix is ASCII, so counting bytes is safe for characters too.
+    let suffix = format!("_{:08x}", fnv1a(ident.as_bytes()) as u32);
+    let mut end = max - suffix.len();
+    while !ident.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &ident[..end], suffix)
+}
+
+impl StmtBuilder {
+    /// Creates a new [`StmtBuilder`] on a qualified table.
+    ///
+    /// The table name of the builder is the rendered reference,
+    /// so register that name in the [`Policy`](crate::db::Policy), if any.
+    /// [`TenantScope::Prefix`](crate::db::TenantScope::Prefix) and
+    /// [`TenantScope::Schema`](crate::db::TenantScope::Schema) expect plain table names and shouldn't be combined with it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, TableRef, Type};
+    ///
+    /// let sb = StmtBuilder::try_qualified(&TableRef::parse("analytics.events").unwrap(), Type::MySQL).unwrap();
+    ///
+    /// assert_eq!(
+    ///     sb.build_count_stmt(&[Cond::eq("kind", PLACEHOLDER)]),
+    ///     "SELECT COUNT(*) FROM `analytics`.`events` WHERE kind = ?"
+    /// );
+    /// ```
+    pub fn try_qualified(tbl: &TableRef, typ: Type) -> Result<StmtBuilder, TableRefError> {
+        Ok(StmtBuilder::new(tbl.try_render(typ)?, typ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::{TableRef, TableRefError, is_ident_too_long, shorten_ident};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(TableRef::parse("t"), Some(TableRef::new("t")));
+        assert_eq!(TableRef::parse("s.t"), Some(TableRef::new("t").schema("s")));
+        assert_eq!(
+            TableRef::parse("d.s.t"),
+            Some(TableRef::new("t").schema("s").db("d"))
+        );
+        assert_eq!(TableRef::parse("a.d.s.t"), None);
+        assert_eq!(TableRef::parse("s..t"), None);
+        assert_eq!(TableRef::parse(""), None);
+    }
+
+    #[test]
+    fn test_try_render() {
+        struct TC {
+            tbl: TableRef,
+            typ: Type,
+            want: Result<&'static str, TableRefError>,
+        }
+
+        let test_cases = vec![
+            TC {
+                tbl: TableRef::new("my`tbl"),
+                typ: Type::TiDB,
+                want: Ok("`my``tbl`"),
+            },
+            TC {
+                tbl: TableRef::new("t").db("d"),
+                typ: Type::MariaDB,
+                want: Ok("`d`.`t`"),
+            },
+            TC {
+                tbl: TableRef::new("t").schema("main"),
+                typ: Type::SQLite,
+                want: Ok("\"main\".\"t\""),
+            },
+            TC {
+                tbl: TableRef::new("t").schema("s").db("d"),
+                typ: Type::SQLite,
+                want: Err(TableRefError {
+                    typ: Type::SQLite,
+                    depth: 3,
+                    max: 2,
+                }),
+            },
+            TC {
+                tbl: TableRef::new("t").schema("a.b").db("d"),
+                typ: Type::DuckDB,
+                want: Ok("\"d\".\"a.b\".\"t\""),
+            },
+            TC {
+                tbl: TableRef::new("t").db("d"),
+                typ: Type::DuckDB,
+                want: Ok("\"d\".\"t\""),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(
+                tc.tbl.try_render(tc.typ),
+                tc.want.map(str::to_string),
+                "{:?}",
+                tc.tbl
+            );
+        }
+    }
+
+    #[test]
+    fn test_shorten_ident() {
+        let long = "x".repeat(64);
+        assert!(is_ident_too_long(Type::PostgreSQL, &long));
+        assert!(!is_ident_too_long(Type::MySQL, &long));
+        assert!(!is_ident_too_long(Type::SQLite, &long.repeat(100)));
+        // MySQL counts characters, and PostgreSQL counts bytes.
+        let wide = "é".repeat(40);
+        assert!(!is_ident_too_long(Type::MariaDB, &wide));
+        assert!(is_ident_too_long(Type::PostgreSQL, &wide));
+
+        assert_eq!(shorten_ident(Type::MySQL, &long), long);
+        let short = shorten_ident(Type::PostgreSQL, &long);
+        assert_eq!(short.len(), 63);
+        assert!(short.starts_with(&"x".repeat(54)));
+        assert_eq!(short, shorten_ident(Type::PostgreSQL, &long));
+        assert_ne!(short, shorten_ident(Type::PostgreSQL, &"x".repeat(65)));
+        let short = shorten_ident(Type::PostgreSQL, &wide);
+        assert!(short.len() <= 63 && short.starts_with(&"é".repeat(27)));
+    }
+}
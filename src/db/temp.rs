@@ -0,0 +1,270 @@
+//! Temporary tables, for example to stage rows between the steps of an ETL routine.
+//!
+//! Temporary tables are visible to the connection that creates them only, and are dropped when it's closed.
+//! Since pooled connections outlive their users, [`TempTable`] holds a connection
+//! and drops the table when it's done, so that the next user of the connection starts clean.
+//!
+//! CockroachDB supports temporary tables only after `SET experimental_enable_temp_tables = 'on'`,
+//! and TiDB can't create tables from queries.
+
+#[cfg(feature = "sqlx")]
+use sqlx::{Any, AnyConnection, AnyPool, pool::PoolConnection};
+
+use crate::db::Type;
+#[cfg(feature = "sqlx")]
+use crate::db::{Value, exec};
+
+/// Returns the leading keywords of the statements creating temporary tables.
+fn create_keyword(typ: Type) -> &'static str {
+    if typ.is_mysql_family() {
+        "CREATE TEMPORARY TABLE"
+    } else {
+        "CREATE TEMP TABLE"
+    }
+}
+
+/// Builds the statement creating the temporary table `tbl` with the column definitions `cols`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, temp::build_create_stmt};
+///
+/// assert_eq!(
+///     build_create_stmt(Type::MySQL, "staged", &["id BIGINT", "name TEXT"]),
+///     "CREATE TEMPORARY TABLE staged (id BIGINT, name TEXT)"
+/// );
+/// assert_eq!(
+///     build_create_stmt(Type::PostgreSQL, "staged", &["id BIGINT"]),
+///     "CREATE TEMP TABLE staged (id BIGINT)"
+/// );
+/// ```
+pub fn build_create_stmt<S: AsRef<str>>(typ: Type, tbl: &str, cols: &[S]) -> String {
+    let cols: Vec<&str> = cols.iter().map(AsRef::as_ref).collect();
+    format!("{} {} ({})", create_keyword(typ), tbl, cols.join(", "))
+}
+
+/// Builds the statement creating the temporary table `tbl` from the rows of `query`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, Type, temp::build_create_as_stmt};
+///
+/// let sb = StmtBuilder::new(String::from("orders"), Type::SQLite);
+/// let query = sb.build_query_stmt(&["id", "total"], &[Cond::eq("status", PLACEHOLDER)]);
+///
+/// assert_eq!(
+///     build_create_as_stmt(Type::SQLite, "pending", &query),
+///     "CREATE TEMP TABLE pending AS SELECT \"id\", \"total\" FROM orders WHERE status = ?"
+/// );
+/// ```
+pub fn build_create_as_stmt(typ: Type, tbl: &str, query: &str) -> String {
+    format!("{} {} AS {}", create_keyword(typ), tbl, query)
+}
+
+/// Builds the statement dropping the temporary table `tbl` if it exists.
+///
+/// The table is qualified by the temporary schema, or `TEMPORARY` on MySQL,
+/// so that a permanent table of the same name is never dropped by mistake.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, temp::build_drop_stmt};
+///
+/// assert_eq!(build_drop_stmt(Type::MariaDB, "staged"), "DROP TEMPORARY TABLE IF EXISTS staged");
+/// assert_eq!(build_drop_stmt(Type::PostgreSQL, "staged"), "DROP TABLE IF EXISTS pg_temp.staged");
+/// assert_eq!(build_drop_stmt(Type::SQLite, "staged"), "DROP TABLE IF EXISTS temp.staged");
+/// ```
+pub fn build_drop_stmt(typ: Type, tbl: &str) -> String {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            format!("DROP TEMPORARY TABLE IF EXISTS {}", tbl)
+        }
+        Type::PostgreSQL | Type::CockroachDB => format!("DROP TABLE IF EXISTS pg_temp.{}", tbl),
+        Type::SQLite | Type::DuckDB => format!("DROP TABLE IF EXISTS temp.{}", tbl),
+    }
+}
+
+/// A temporary table along with the connection it lives in, which drops the table when dropped.
+///
+/// Run every statement on the table via [`TempTable::conn`], since other connections can't see it.
+/// Prefer [`TempTable::drop_table`], since dropping is synchronous
+/// and can only close the connection, which drops the table but costs a reconnect.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, exec, temp::TempTable};
+/// use sqlx::AnyPool;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// let pool = AnyPool::connect("sqlite::memory:").await.unwrap();
+///
+/// let mut staged = TempTable::create(&pool, Type::SQLite, "staged", &["id INTEGER"])
+///     .await
+///     .unwrap();
+/// exec::execute(staged.conn(), "INSERT INTO staged VALUES (1), (2)", &[]).await.unwrap();
+/// let rows = exec::fetch_all(staged.conn(), "SELECT id FROM staged", &[]).await.unwrap();
+/// assert_eq!(rows.len(), 2);
+///
+/// staged.drop_table().await.unwrap();
+/// # });
+/// ```
+#[cfg(feature = "sqlx")]
+#[derive(Debug)]
+pub struct TempTable {
+    conn: Option<PoolConnection<Any>>,
+    typ: Type,
+    tbl: String,
+}
+
+#[cfg(feature = "sqlx")]
+impl TempTable {
+    /// Creates the temporary table `tbl` with the column definitions `cols` on a connection of `pool`.
+    pub async fn create<S: AsRef<str>>(
+        pool: &AnyPool,
+        typ: Type,
+        tbl: &str,
+        cols: &[S],
+    ) -> Result<TempTable, sqlx::Error> {
+        TempTable::create_with(pool, typ, tbl, &build_create_stmt(typ, tbl, cols), &[]).await
+    }
+
+    /// Creates the temporary table `tbl` from the rows of `query` on a connection of `pool`,
+    /// where `args` are bound to the placeholders of `query`.
+    pub async fn create_as(
+        pool: &AnyPool,
+        typ: Type,
+        tbl: &str,
+        query: &str,
+        args: &[Value],
+    ) -> Result<TempTable, sqlx::Error> {
+        let stmt = build_create_as_stmt(typ, tbl, query);
+        TempTable::create_with(pool, typ, tbl, &stmt, args).await
+    }
+
+    async fn create_with(
+        pool: &AnyPool,
+        typ: Type,
+        tbl: &str,
+        stmt: &str,
+        args: &[Value],
+    ) -> Result<TempTable, sqlx::Error> {
+        let mut conn = pool.acquire().await?;
+        exec::execute(&mut *conn, stmt, args).await?;
+        Ok(TempTable {
+            conn: Some(conn),
+            typ,
+            tbl: tbl.to_string(),
+        })
+    }
+
+    /// Gets the table name.
+    pub fn get_tbl(&self) -> &str {
+        &self.tbl
+    }
+
+    /// Returns the connection the table lives in.
+    pub fn conn(&mut self) -> &mut AnyConnection {
+        self.conn
+            .as_deref_mut()
+            .expect("the connection is held until the table is dropped")
+    }
+
+    /// Drops the table and returns the connection to the pool.
+    pub async fn drop_table(mut self) -> Result<(), sqlx::Error> {
+        let Some(mut conn) = self.conn.take() else {
+            return Ok(());
+        };
+        let result = exec::execute(&mut *conn, &build_drop_stmt(self.typ, &self.tbl), &[]).await;
+        if result.is_err() {
+            conn.close_on_drop();
+        }
+        result.map(|_| ())
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl Drop for TempTable {
+    fn drop(&mut self) {
+        // Closing the connection drops its temporary tables.
+        if let Some(mut conn) = self.conn.take() {
+            conn.close_on_drop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::build_create_as_stmt;
+
+    #[test]
+    fn test_build_create_as_stmt() {
+        assert_eq!(
+            build_create_as_stmt(Type::TiDB, "t", "SELECT 1"),
+            "CREATE TEMPORARY TABLE t AS SELECT 1"
+        );
+        assert_eq!(
+            build_create_as_stmt(Type::DuckDB, "t", "SELECT 1"),
+            "CREATE TEMP TABLE t AS SELECT 1"
+        );
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_temp_table() {
+        use sqlx::{AnyPool, pool::PoolOptions};
+
+        use crate::db::{Value, exec};
+
+        use super::TempTable;
+
+        sqlx::any::install_default_drivers();
+        let pool: AnyPool = PoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        exec::execute(&pool, "CREATE TABLE t (a INTEGER)", &[])
+            .await
+            .unwrap();
+        exec::execute(&pool, "INSERT INTO t VALUES (1), (2), (3)", &[])
+            .await
+            .unwrap();
+
+        let mut staged = TempTable::create_as(
+            &pool,
+            Type::SQLite,
+            "staged",
+            "SELECT a FROM t WHERE a > ?",
+            &[Value::from(1)],
+        )
+        .await
+        .unwrap();
+        assert_eq!(staged.get_tbl(), "staged");
+        let rows = exec::fetch_all(staged.conn(), "SELECT a FROM staged", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        staged.drop_table().await.unwrap();
+
+        // The connection is back in the pool without the table.
+        assert!(
+            exec::fetch_all(&pool, "SELECT a FROM staged", &[])
+                .await
+                .is_err()
+        );
+        assert_eq!(
+            exec::fetch_all(&pool, "SELECT a FROM t", &[])
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+}
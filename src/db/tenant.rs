@@ -0,0 +1,75 @@
+use std::fmt;
+
+use crate::db::{CondRef, KV};
+
+/// How a [`StmtBuilder`](crate::db::StmtBuilder) isolates the data of a tenant.
+///
+/// Attach it to a builder via [`StmtBuilder::set_tenant`](crate::db::StmtBuilder::set_tenant),
+/// or to a single call via [`StmtBuilder::scoped`](crate::db::StmtBuilder::scoped).
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Cond, PLACEHOLDER, StmtBuilder, TenantScope, Type};
+///
+/// let sb = StmtBuilder::new(String::from("orders"), Type::PostgreSQL);
+/// let conds = vec![Cond::eq("id", PLACEHOLDER)];
+///
+/// let prefixed = sb.scoped(TenantScope::Prefix(String::from("tenant_123")));
+/// assert_eq!(
+///     prefixed.build_delete_stmt(&conds),
+///     "DELETE FROM tenant_123_orders WHERE id = $1"
+/// );
+///
+/// let schema = sb.scoped(TenantScope::Schema(String::from("tenant_123")));
+/// assert_eq!(
+///     schema.build_delete_stmt(&conds),
+///     "DELETE FROM tenant_123.orders WHERE id = $1"
+/// );
+///
+/// let column = sb.scoped(TenantScope::Column {
+///     col: String::from("tenant_id"),
+///     val: String::from(PLACEHOLDER),
+/// });
+/// assert_eq!(
+///     column.build_delete_stmt(&conds),
+///     "DELETE FROM orders WHERE id = $1 AND tenant_id = $2"
+/// );
+/// ```
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub enum TenantScope {
+    /// Prefixes the table name, for example `tenant_123` turns `orders` into `tenant_123_orders`.
+    Prefix(String),
+    /// Qualifies the table name with a schema, for example `tenant_123` turns `orders` into `tenant_123.orders`.
+    Schema(String),
+    /// Restricts every statement to the rows whose column `col` equals `val`.
+    ///
+    /// The condition is appended after the other conditions, and inserts get the column appended.
+    /// `val` can be [`PLACEHOLDER`](crate::db::PLACEHOLDER), whose parameter is bound after the other parameters
+    /// but before the version of [`StmtBuilder::set_version_col`](crate::db::StmtBuilder::set_version_col).
+    Column { col: String, val: String },
+}
+
+impl TenantScope {
+    /// Writes the table name `tbl` as seen by the tenant.
+    pub(crate) fn write_tbl<W: fmt::Write>(&self, w: &mut W, tbl: &str) -> fmt::Result {
+        match self {
+            TenantScope::Prefix(prefix) => write!(w, "{}_{}", prefix, tbl),
+            TenantScope::Schema(schema) => write!(w, "{}.{}", schema, tbl),
+            TenantScope::Column { .. } => w.write_str(tbl),
+        }
+    }
+
+    /// Returns the tenant column and value, if any.
+    pub(crate) fn as_kv(&self) -> Option<KV<'_>> {
+        match self {
+            TenantScope::Column { col, val } => Some(KV { key: col, val }),
+            TenantScope::Prefix(_) | TenantScope::Schema(_) => None,
+        }
+    }
+
+    /// Returns the condition restricting the rows, if any.
+    pub(crate) fn as_cond(&self) -> Option<CondRef<'_>> {
+        self.as_kv().map(CondRef::Eq)
+    }
+}
@@ -0,0 +1,232 @@
+//! Fixtures for integration tests against statements built by [`StmtBuilder`].
+//!
+//! [`TestDb`] creates an isolated database, applies the given migrations,
+//! and removes the database when dropped, so each test starts from a clean state.
+
+use std::{
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use sqlx::{AnyPool, any::AnyPoolOptions};
+
+use crate::db::{StmtBuilder, Type};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Generates a name that is unique across processes and calls.
+fn unique_name() -> String {
+    format!(
+        "test_{}_{}",
+        process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// An isolated test database, which is removed when dropped.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{KV, PLACEHOLDER, Value, exec::execute, testing::TestDb};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let db = TestDb::sqlite(&["CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)"])
+///     .await
+///     .unwrap();
+/// let sb = db.builder("users");
+/// let stmt = sb.build_insert_stmt(&[KV {
+///     key: "name",
+///     val: PLACEHOLDER,
+/// }]);
+///
+/// let rows = execute(db.get_pool(), &stmt, &[Value::from("foo")]).await.unwrap();
+///
+/// assert_eq!(rows, 1);
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct TestDb {
+    pool: AnyPool,
+    typ: Type,
+    schema: Option<String>,
+}
+
+impl TestDb {
+    /// Creates an in-memory SQLite database and applies `migrations` in order.
+    ///
+    /// The database lives as long as the pool, so it's removed once the [`TestDb`] is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `migrations` - The migration scripts. Each script may contain multiple statements.
+    ///
+    /// # Returns
+    ///
+    /// * The test database.
+    pub async fn sqlite(migrations: &[&str]) -> Result<TestDb, sqlx::Error> {
+        sqlx::any::install_default_drivers();
+        let url = format!("sqlite:file:{}?mode=memory&cache=shared", unique_name());
+        // Keep a connection open, otherwise the in-memory database is removed.
+        let pool = AnyPoolOptions::new()
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect(&url)
+            .await?;
+        let db = TestDb {
+            pool,
+            typ: Type::SQLite,
+            schema: None,
+        };
+        db.migrate(migrations).await?;
+        Ok(db)
+    }
+
+    /// Creates a schema in an existing PostgreSQL database and applies `migrations` in order within it.
+    ///
+    /// Every connection of the pool uses the schema as its search path, so unqualified table names refer to it.
+    /// The schema is dropped when the [`TestDb`] is dropped inside a tokio runtime.
+    /// Call [`TestDb::close`] to drop it deterministically.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The connection URL of the PostgreSQL database.
+    /// * `migrations` - The migration scripts. Each script may contain multiple statements.
+    ///
+    /// # Returns
+    ///
+    /// * The test database.
+    pub async fn postgres(url: &str, migrations: &[&str]) -> Result<TestDb, sqlx::Error> {
+        sqlx::any::install_default_drivers();
+        let schema = unique_name();
+        // Sets the search path of every connection via the startup options.
+        let sep = if url.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}options=-c%20search_path%3D{}", url, sep, schema);
+        let pool = AnyPoolOptions::new().connect_lazy(&url)?;
+        sqlx::raw_sql(&format!("CREATE SCHEMA \"{}\"", schema))
+            .execute(&pool)
+            .await?;
+        let db = TestDb {
+            pool,
+            typ: Type::PostgreSQL,
+            schema: Some(schema),
+        };
+        db.migrate(migrations).await?;
+        Ok(db)
+    }
+
+    async fn migrate(&self, migrations: &[&str]) -> Result<(), sqlx::Error> {
+        for migration in migrations {
+            sqlx::raw_sql(migration).execute(&self.pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Gets the connection pool.
+    pub fn get_pool(&self) -> &AnyPool {
+        &self.pool
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        &self.typ
+    }
+
+    /// Gets the PostgreSQL schema, or [`None`] for SQLite.
+    pub fn get_schema(&self) -> Option<&String> {
+        self.schema.as_ref()
+    }
+
+    /// Creates a [`StmtBuilder`] for the given table of this database.
+    pub fn builder(&self, tbl: &str) -> StmtBuilder {
+        StmtBuilder::new(tbl.to_string(), self.typ)
+    }
+
+    /// Removes the database and closes the pool.
+    pub async fn close(mut self) -> Result<(), sqlx::Error> {
+        if let Some(schema) = self.schema.take() {
+            drop_schema(&self.pool, &schema).await?;
+        }
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+async fn drop_schema(pool: &AnyPool, schema: &str) -> Result<(), sqlx::Error> {
+    sqlx::raw_sql(&format!("DROP SCHEMA IF EXISTS \"{}\" CASCADE", schema))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let Some(schema) = self.schema.take() else {
+            return;
+        };
+        // Dropping is synchronous, so the schema can only be dropped in the background.
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let pool = self.pool.clone();
+            handle.spawn(async move {
+                let _ = drop_schema(&pool, &schema).await;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Row;
+
+    use crate::db::{KV, PLACEHOLDER, Type, Value, exec};
+
+    use super::TestDb;
+
+    #[tokio::test]
+    async fn test_sqlite() {
+        let migrations = [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)",
+            "ALTER TABLE users ADD COLUMN age INTEGER; CREATE INDEX idx_age ON users (age)",
+        ];
+        let db = TestDb::sqlite(&migrations).await.unwrap();
+        assert_eq!(db.get_typ(), &Type::SQLite);
+        assert!(db.get_schema().is_none());
+
+        let sb = db.builder("users");
+        let cols = vec![
+            KV {
+                key: "name",
+                val: PLACEHOLDER,
+            },
+            KV {
+                key: "age",
+                val: "20",
+            },
+        ];
+        let stmt = sb.build_insert_stmt(&cols);
+        exec::execute(db.get_pool(), &stmt, &[Value::from("foo")])
+            .await
+            .unwrap();
+
+        // Connections of the same pool share the database.
+        let rows = exec::fetch_all(db.get_pool(), "SELECT name, age FROM users", &[])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get::<i64, _>(1), 20);
+
+        // Databases are isolated from each other.
+        let other = TestDb::sqlite(&[]).await.unwrap();
+        assert!(
+            exec::fetch_all(other.get_pool(), "SELECT * FROM users", &[])
+                .await
+                .is_err()
+        );
+
+        db.close().await.unwrap();
+        other.close().await.unwrap();
+
+        assert!(TestDb::sqlite(&["NOT SQL"]).await.is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Helpers that execute statements built by [`StmtBuilder`](crate::db::StmtBuilder) via tokio-postgres.
+//!
+//! All helpers accept any [`GenericClient`], for example a [`Client`](::tokio_postgres::Client)
+//! or a [`Transaction`](::tokio_postgres::Transaction).
+//! `?` placeholders are converted to `$N`, so statements built for other database types can be executed as well.
+
+use std::{borrow::Cow, error::Error as StdError};
+
+use ::tokio_postgres::{
+    Error, GenericClient, Row,
+    types::{IsNull, ToSql, Type, to_sql_checked},
+};
+use bytes::BytesMut;
+
+use crate::db::{Value, fingerprint::placeholders};
+
+impl ToSql for Value {
+    /// Encodes the value. Integers and floats are narrowed to the column type if needed.
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn StdError + Sync + Send>> {
+        match self {
+            Value::Null => Ok(IsNull::Yes),
+            Value::Bool(v) => v.to_sql_checked(ty, out),
+            Value::Int(v) => match *ty {
+                Type::INT2 => i16::try_from(*v)?.to_sql_checked(ty, out),
+                Type::INT4 => i32::try_from(*v)?.to_sql_checked(ty, out),
+                _ => v.to_sql_checked(ty, out),
+            },
+            Value::Float(v) => match *ty {
+                Type::FLOAT4 => (*v as f32).to_sql_checked(ty, out),
+                _ => v.to_sql_checked(ty, out),
+            },
+            Value::Text(v) => v.to_sql_checked(ty, out),
+            Value::Bytes(v) => v.to_sql_checked(ty, out),
+        }
+    }
+
+    fn accepts(_: &Type) -> bool {
+        // Type mismatches are reported by `to_sql`, which checks the inner value against the column type.
+        true
+    }
+
+    to_sql_checked!();
+}
+
+/// Converts `?` placeholders to `$N` based placeholders.
+///
+/// Placeholders in quoted literals and identifiers are kept as is.
+///
+/// # Arguments
+///
+/// * `stmt` - The SQL statement.
+///
+/// # Returns
+///
+/// * The converted statement, which is borrowed if there are no `?` placeholders.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::tokio_postgres::to_pg_placeholders;
+///
+/// assert_eq!(
+///     to_pg_placeholders("SELECT * FROM t WHERE a = ? AND b = '?' AND c = ?"),
+///     "SELECT * FROM t WHERE a = $1 AND b = '?' AND c = $2"
+/// );
+/// ```
+pub fn to_pg_placeholders(stmt: &str) -> Cow<'_, str> {
+    let found: Vec<_> = placeholders(stmt)
+        .into_iter()
+        .filter(|(range, _)| range.len() == 1 && &stmt[range.clone()] == "?")
+        .collect();
+    if found.is_empty() {
+        return Cow::Borrowed(stmt);
+    }
+    let mut converted = String::with_capacity(stmt.len() + found.len() * 2);
+    let mut begin = 0;
+    for (range, idx) in found {
+        converted.push_str(&stmt[begin..range.start]);
+        converted.push('$');
+        converted.push_str(&(idx + 1).to_string());
+        begin = range.end;
+    }
+    converted.push_str(&stmt[begin..]);
+    Cow::Owned(converted)
+}
+
+fn params(args: &[Value]) -> Vec<&(dyn ToSql + Sync)> {
+    args.iter().map(|arg| arg as &(dyn ToSql + Sync)).collect()
+}
+
+/// Executes a statement.
+///
+/// # Arguments
+///
+/// * `client` - The client or transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+///
+/// # Returns
+///
+/// * The number of rows affected.
+pub async fn execute<C: GenericClient>(
+    client: &C,
+    stmt: &str,
+    args: &[Value],
+) -> Result<u64, Error> {
+    client
+        .execute(to_pg_placeholders(stmt).as_ref(), &params(args))
+        .await
+}
+
+/// Executes a statement and maps all rows via `f`.
+///
+/// # Arguments
+///
+/// * `client` - The client or transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+/// * `f` - The function that maps a row, for example `|row| row.try_get::<_, String>("name")`.
+///
+/// # Returns
+///
+/// * The mapped rows.
+pub async fn fetch_all<C, T, F>(
+    client: &C,
+    stmt: &str,
+    args: &[Value],
+    f: F,
+) -> Result<Vec<T>, Error>
+where
+    C: GenericClient,
+    F: FnMut(&Row) -> Result<T, Error>,
+{
+    client
+        .query(to_pg_placeholders(stmt).as_ref(), &params(args))
+        .await?
+        .iter()
+        .map(f)
+        .collect()
+}
+
+/// Executes a statement and maps the first row via `f`.
+///
+/// # Arguments
+///
+/// * `client` - The client or transaction.
+/// * `stmt` - The SQL statement.
+/// * `args` - The values bound to the placeholders.
+/// * `f` - The function that maps a row.
+///
+/// # Returns
+///
+/// * The mapped row, or [`None`] if no rows are returned.
+pub async fn fetch_optional<C, T, F>(
+    client: &C,
+    stmt: &str,
+    args: &[Value],
+    f: F,
+) -> Result<Option<T>, Error>
+where
+    C: GenericClient,
+    F: FnOnce(&Row) -> Result<T, Error>,
+{
+    let rows = client
+        .query(to_pg_placeholders(stmt).as_ref(), &params(args))
+        .await?;
+    rows.first().map(f).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::tokio_postgres::types::{IsNull, ToSql, Type};
+    use bytes::BytesMut;
+
+    use crate::db::Value;
+
+    use super::to_pg_placeholders;
+
+    #[test]
+    fn test_to_pg_placeholders() {
+        struct TC<'a> {
+            stmt: &'a str,
+            want: &'a str,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "INSERT INTO t (`a`, `b?`) VALUES (?, ?)",
+                want: "INSERT INTO t (`a`, `b?`) VALUES ($1, $2)",
+            },
+            TC {
+                stmt: "SELECT * FROM t WHERE a = $1",
+                want: "SELECT * FROM t WHERE a = $1",
+            },
+            TC {
+                stmt: "SELECT 'it''s ?'",
+                want: "SELECT 'it''s ?'",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(to_pg_placeholders(tc.stmt), tc.want);
+        }
+    }
+
+    #[test]
+    fn test_to_sql() {
+        let encode = |v: Value, ty: Type| {
+            let mut out = BytesMut::new();
+            v.to_sql_checked(&ty, &mut out)
+                .map(|is_null| (is_null, out.to_vec()))
+        };
+
+        assert!(matches!(
+            encode(Value::Null, Type::INT4),
+            Ok((IsNull::Yes, _))
+        ));
+        assert_eq!(encode(Value::from(true), Type::BOOL).unwrap().1, vec![1]);
+        assert_eq!(encode(Value::from(1), Type::INT2).unwrap().1, vec![0, 1]);
+        assert_eq!(
+            encode(Value::from(1), Type::INT4).unwrap().1,
+            vec![0, 0, 0, 1]
+        );
+        assert_eq!(encode(Value::from(1), Type::INT8).unwrap().1.len(), 8);
+        assert!(encode(Value::from(i64::MAX), Type::INT4).is_err());
+        assert_eq!(encode(Value::from(1.5), Type::FLOAT4).unwrap().1.len(), 4);
+        assert_eq!(encode(Value::from(1.5), Type::FLOAT8).unwrap().1.len(), 8);
+        assert_eq!(encode(Value::from("foo"), Type::TEXT).unwrap().1, b"foo");
+        assert_eq!(
+            encode(Value::from(vec![1_u8]), Type::BYTEA).unwrap().1,
+            vec![1]
+        );
+        assert!(encode(Value::from("foo"), Type::INT4).is_err());
+    }
+}
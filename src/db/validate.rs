@@ -0,0 +1,139 @@
+use std::fmt;
+
+use sqlparser::{
+    dialect::{Dialect, DuckDbDialect, MySqlDialect, PostgreSqlDialect, SQLiteDialect},
+    parser::{Parser, ParserError},
+};
+
+use crate::db::Type;
+
+/// Error returned when a statement is not valid.
+#[derive(Debug)]
+pub enum ValidationError {
+    /// The statement is empty.
+    Empty,
+    /// The input contains more than one statement.
+    Multiple(usize),
+    /// The statement can't be parsed.
+    Syntax(ParserError),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Empty => write!(f, "empty statement"),
+            ValidationError::Multiple(n) => write!(f, "expected 1 statement, found {}", n),
+            ValidationError::Syntax(e) => write!(f, "syntax error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ValidationError::Syntax(e) => Some(e),
+            ValidationError::Empty | ValidationError::Multiple(_) => None,
+        }
+    }
+}
+
+/// Validates a SQL statement by parsing it with the SQL dialect of the given database type.
+///
+/// Only the syntax is checked, so unknown tables and columns are not reported.
+/// MariaDB and TiDB are parsed as MySQL, and CockroachDB as PostgreSQL, so their own extensions are rejected.
+/// The input must contain exactly one statement,
+/// which also catches statements injected via raw fragments.
+///
+/// # Arguments
+///
+/// * `stmt` - The SQL statement.
+/// * `typ` - The database type.
+///
+/// # Returns
+///
+/// * [`Ok`] if the statement is valid.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::db::{Type, validate};
+///
+/// assert!(validate("SELECT * FROM users WHERE id = $1", Type::PostgreSQL).is_ok());
+/// assert!(validate("SELECT * FROM users WHERE", Type::PostgreSQL).is_err());
+/// assert!(validate("SELECT 1; DROP TABLE users", Type::MySQL).is_err());
+/// ```
+pub fn validate(stmt: &str, typ: Type) -> Result<(), ValidationError> {
+    let dialect: &dyn Dialect = match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => &MySqlDialect {},
+        Type::PostgreSQL | Type::CockroachDB => &PostgreSqlDialect {},
+        Type::SQLite => &SQLiteDialect {},
+        Type::DuckDB => &DuckDbDialect {},
+    };
+    match Parser::parse_sql(dialect, stmt)
+        .map_err(ValidationError::Syntax)?
+        .len()
+    {
+        0 => Err(ValidationError::Empty),
+        1 => Ok(()),
+        n => Err(ValidationError::Multiple(n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::{ValidationError, validate};
+
+    #[test]
+    fn test_validate() {
+        struct TC<'a> {
+            stmt: &'a str,
+            typ: Type,
+            ok: bool,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "INSERT INTO t (`a`, `b`) VALUES (?, NOW())",
+                typ: Type::MySQL,
+                ok: true,
+            },
+            TC {
+                stmt: "UPDATE t SET \"a\" = $1 WHERE id IN ($2, $3)",
+                typ: Type::PostgreSQL,
+                ok: true,
+            },
+            TC {
+                stmt: "DELETE FROM t WHERE (a = ? OR b = 1) AND NOT (c IS NULL)",
+                typ: Type::SQLite,
+                ok: true,
+            },
+            // Unbalanced parentheses, for example from a raw fragment
+            TC {
+                stmt: "SELECT * FROM t WHERE (a = 1",
+                typ: Type::PostgreSQL,
+                ok: false,
+            },
+            // Dangling operator
+            TC {
+                stmt: "SELECT * FROM t WHERE a =",
+                typ: Type::SQLite,
+                ok: false,
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(validate(tc.stmt, tc.typ).is_ok(), tc.ok, "{}", tc.stmt);
+        }
+
+        assert!(matches!(
+            validate("", Type::MySQL),
+            Err(ValidationError::Empty)
+        ));
+        assert!(matches!(
+            validate("SELECT 1; SELECT 2", Type::MySQL),
+            Err(ValidationError::Multiple(2))
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::Write;
+
+use crate::db::Type;
+
+/// A value that can be bound to a placeholder.
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// Renders the value as a SQL literal of the given database type.
+    ///
+    /// Strings are quoted and escaped, so the literal can be safely embedded in statements.
+    /// Even so, binding values to placeholders is preferred whenever possible.
+    ///
+    /// # Arguments
+    ///
+    /// * `typ` - The database type.
+    ///
+    /// # Returns
+    ///
+    /// * The SQL literal.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::db::{Type, Value};
+    ///
+    /// assert_eq!(Value::from("it's").to_literal(Type::PostgreSQL), "'it''s'");
+    /// assert_eq!(Value::from("C:\\").to_literal(Type::MySQL), "'C:\\\\'");
+    /// assert_eq!(Value::from(vec![0xab_u8]).to_literal(Type::SQLite), "X'ab'");
+    /// assert_eq!(Value::Null.to_literal(Type::SQLite), "NULL");
+    /// ```
+    pub fn to_literal(&self, typ: Type) -> String {
+        match self {
+            Value::Null => String::from("NULL"),
+            Value::Bool(true) => String::from("TRUE"),
+            Value::Bool(false) => String::from("FALSE"),
+            Value::Int(v) => v.to_string(),
+            Value::Float(v) if v.is_finite() => v.to_string(),
+            Value::Float(v) => match typ {
+                Type::PostgreSQL | Type::CockroachDB if v.is_nan() => String::from("'NaN'"),
+                Type::PostgreSQL | Type::CockroachDB if *v > 0.0 => String::from("'Infinity'"),
+                Type::PostgreSQL | Type::CockroachDB => String::from("'-Infinity'"),
+                // DuckDB doesn't cast text to floats implicitly.
+                Type::DuckDB if v.is_nan() => String::from("CAST('NaN' AS DOUBLE)"),
+                Type::DuckDB if *v > 0.0 => String::from("CAST('Infinity' AS DOUBLE)"),
+                Type::DuckDB => String::from("CAST('-Infinity' AS DOUBLE)"),
+                Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite => String::from("NULL"),
+            },
+            Value::Text(v) => {
+                let escaped = v.replace('\'', "''");
+                match typ {
+                    // MySQL treats backslashes as escape characters by default.
+                    Type::MySQL | Type::MariaDB | Type::TiDB => {
+                        format!("'{}'", escaped.replace('\\', "\\\\"))
+                    }
+                    Type::PostgreSQL | Type::CockroachDB | Type::SQLite | Type::DuckDB => {
+                        format!("'{}'", escaped)
+                    }
+                }
+            }
+            Value::Bytes(v) => {
+                let mut hex = String::with_capacity(v.len() * 2);
+                for b in v {
+                    // Writing to a String never fails.
+                    let _ = write!(hex, "{:02x}", b);
+                }
+                match typ {
+                    Type::PostgreSQL | Type::CockroachDB => format!("'\\x{}'", hex),
+                    Type::DuckDB => format!("from_hex('{}')", hex),
+                    Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite => {
+                        format!("X'{}'", hex)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::Int(i64::from(v))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Int(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        Value::Bytes(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<&serde_json::Value> for Value {
+    /// Converts a JSON value. Arrays and objects are converted to their JSON text.
+    fn from(v: &serde_json::Value) -> Self {
+        match v {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(v) => Value::Bool(*v),
+            serde_json::Value::Number(v) => v
+                .as_i64()
+                .map(Value::Int)
+                .unwrap_or_else(|| Value::Float(v.as_f64().unwrap_or(f64::NAN))),
+            serde_json::Value::String(v) => Value::Text(v.clone()),
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+                Value::Text(v.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Type;
+
+    use super::Value;
+
+    #[test]
+    fn test_from() {
+        assert_eq!(Value::from(true), Value::Bool(true));
+        assert_eq!(Value::from(1), Value::Int(1));
+        assert_eq!(Value::from(1_i64), Value::Int(1));
+        assert_eq!(Value::from(1.5), Value::Float(1.5));
+        assert_eq!(Value::from("foo"), Value::Text(String::from("foo")));
+        assert_eq!(
+            Value::from(String::from("foo")),
+            Value::Text(String::from("foo"))
+        );
+        assert_eq!(Value::from(vec![1_u8, 2]), Value::Bytes(vec![1, 2]));
+        assert_eq!(Value::from(Some(1)), Value::Int(1));
+        assert_eq!(Value::from(None::<i64>), Value::Null);
+    }
+
+    #[test]
+    fn test_to_literal() {
+        struct TC {
+            val: Value,
+            want_mysql: &'static str,
+            want_postgresql: &'static str,
+            want_sqlite: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                val: Value::Null,
+                want_mysql: "NULL",
+                want_postgresql: "NULL",
+                want_sqlite: "NULL",
+            },
+            TC {
+                val: Value::Bool(true),
+                want_mysql: "TRUE",
+                want_postgresql: "TRUE",
+                want_sqlite: "TRUE",
+            },
+            TC {
+                val: Value::Bool(false),
+                want_mysql: "FALSE",
+                want_postgresql: "FALSE",
+                want_sqlite: "FALSE",
+            },
+            TC {
+                val: Value::Int(-42),
+                want_mysql: "-42",
+                want_postgresql: "-42",
+                want_sqlite: "-42",
+            },
+            TC {
+                val: Value::Float(1.5),
+                want_mysql: "1.5",
+                want_postgresql: "1.5",
+                want_sqlite: "1.5",
+            },
+            TC {
+                val: Value::Float(f64::NAN),
+                want_mysql: "NULL",
+                want_postgresql: "'NaN'",
+                want_sqlite: "NULL",
+            },
+            TC {
+                val: Value::Float(f64::INFINITY),
+                want_mysql: "NULL",
+                want_postgresql: "'Infinity'",
+                want_sqlite: "NULL",
+            },
+            TC {
+                val: Value::Float(f64::NEG_INFINITY),
+                want_mysql: "NULL",
+                want_postgresql: "'-Infinity'",
+                want_sqlite: "NULL",
+            },
+            TC {
+                val: Value::from("it's a \\ test"),
+                want_mysql: "'it''s a \\\\ test'",
+                want_postgresql: "'it''s a \\ test'",
+                want_sqlite: "'it''s a \\ test'",
+            },
+            TC {
+                val: Value::Bytes(vec![0x00, 0xff, 0x1a]),
+                want_mysql: "X'00ff1a'",
+                want_postgresql: "'\\x00ff1a'",
+                want_sqlite: "X'00ff1a'",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(tc.val.to_literal(Type::MySQL), tc.want_mysql);
+            assert_eq!(tc.val.to_literal(Type::PostgreSQL), tc.want_postgresql);
+            assert_eq!(tc.val.to_literal(Type::SQLite), tc.want_sqlite);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_json() {
+        use serde_json::json;
+
+        assert_eq!(Value::from(&json!(null)), Value::Null);
+        assert_eq!(Value::from(&json!(true)), Value::Bool(true));
+        assert_eq!(Value::from(&json!(1)), Value::Int(1));
+        assert_eq!(Value::from(&json!(1.5)), Value::Float(1.5));
+        assert_eq!(Value::from(&json!(u64::MAX)), Value::Float(u64::MAX as f64));
+        assert_eq!(Value::from(&json!("foo")), Value::from("foo"));
+        assert_eq!(Value::from(&json!([1, "a"])), Value::from("[1,\"a\"]"));
+        assert_eq!(Value::from(&json!({"a": 1})), Value::from("{\"a\":1}"));
+    }
+}
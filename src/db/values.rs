@@ -0,0 +1,190 @@
+//! Inline derived tables built from lists of values.
+//!
+//! [`derived`] returns a table expression that can be used as the table name of a
+//! [`StmtBuilder`](crate::db::StmtBuilder), or joined via raw conditions,
+//! for example to look up many keys at once or to find the keys missing from a table.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::db::{Cond, StmtBuilder, Type, Value, values::derived};
+//!
+//! let rows = [
+//!     [Value::from(1), Value::from("a")],
+//!     [Value::from(2), Value::from("b")],
+//! ];
+//! let keys = derived(Type::PostgreSQL, "v", &["id", "code"], &rows);
+//! assert_eq!(keys, "(VALUES (1, 'a'), (2, 'b')) AS v (\"id\", \"code\")");
+//!
+//! // The keys without users.
+//! let sb = StmtBuilder::new(keys, Type::PostgreSQL);
+//! let missing = Cond::Raw(String::from("NOT EXISTS (SELECT 1 FROM users u WHERE u.id = v.id)"));
+//! assert_eq!(
+//!     sb.build_query_stmt(&["id"], &[missing]),
+//!     "SELECT \"id\" FROM (VALUES (1, 'a'), (2, 'b')) AS v (\"id\", \"code\") \
+//!      WHERE (NOT EXISTS (SELECT 1 FROM users u WHERE u.id = v.id))"
+//! );
+//! ```
+
+use crate::db::{Type, Value, table::write_quoted};
+
+/// Returns a derived table named `alias` with the columns `cols` and the rows `rows`.
+///
+/// PostgreSQL, CockroachDB and DuckDB get a `VALUES` list with column aliases.
+/// MySQL, MariaDB, TiDB and SQLite can't name the columns of a `VALUES` list on every supported version,
+/// so they get the rows joined by `UNION ALL` instead, like `(SELECT 1 AS a UNION ALL SELECT 2) AS v`.
+///
+/// The values are written as literals, so that the placeholders of the rest of the statement keep their numbers.
+/// Without rows, the table is empty and its columns are `NULL`.
+///
+/// # Arguments
+///
+/// * `typ` - The database type.
+/// * `alias` - The name of the derived table.
+/// * `cols` - The column names.
+/// * `rows` - The rows, each of which has a value per column.
+///
+/// # Returns
+///
+/// * The table expression.
+pub fn derived<S: AsRef<str>, R: AsRef<[Value]>>(
+    typ: Type,
+    alias: &str,
+    cols: &[S],
+    rows: &[R],
+) -> String {
+    let mut s = String::from("(");
+    match typ {
+        _ if rows.is_empty() => {
+            s.push_str("SELECT ");
+            for (i, col) in cols.iter().enumerate() {
+                if i > 0 {
+                    s.push_str(", ");
+                }
+                s.push_str("NULL AS ");
+                write_quoted(&mut s, typ, col.as_ref());
+            }
+            // MySQL 5.7 requires FROM before WHERE.
+            if typ.is_mysql_family() {
+                s.push_str(" FROM DUAL");
+            }
+            s.push_str(" WHERE 1 = 0) AS ");
+            s.push_str(alias);
+        }
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => {
+            s.push_str("VALUES ");
+            for (i, row) in rows.iter().enumerate() {
+                if i > 0 {
+                    s.push_str(", ");
+                }
+                s.push('(');
+                push_literals(&mut s, typ, row.as_ref(), &[] as &[S]);
+                s.push(')');
+            }
+            s.push_str(") AS ");
+            s.push_str(alias);
+            s.push_str(" (");
+            for (i, col) in cols.iter().enumerate() {
+                if i > 0 {
+                    s.push_str(", ");
+                }
+                write_quoted(&mut s, typ, col.as_ref());
+            }
+            s.push(')');
+        }
+        Type::MySQL | Type::MariaDB | Type::TiDB | Type::SQLite => {
+            for (i, row) in rows.iter().enumerate() {
+                s.push_str(if i > 0 {
+                    " UNION ALL SELECT "
+                } else {
+                    "SELECT "
+                });
+                // The columns are named by the first row.
+                push_literals(&mut s, typ, row.as_ref(), if i > 0 { &[] } else { cols });
+            }
+            s.push_str(") AS ");
+            s.push_str(alias);
+        }
+    }
+    s
+}
+
+/// Pushes the literals of `row` separated by commas, each followed by an alias from `cols` if any.
+fn push_literals<S: AsRef<str>>(s: &mut String, typ: Type, row: &[Value], cols: &[S]) {
+    for (i, val) in row.iter().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        s.push_str(&val.to_literal(typ));
+        if let Some(col) = cols.get(i) {
+            s.push_str(" AS ");
+            write_quoted(s, typ, col.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Type, Value};
+
+    use super::derived;
+
+    #[test]
+    fn test_derived() {
+        struct TC {
+            typ: Type,
+            want: &'static str,
+            want_empty: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                typ: Type::MySQL,
+                want: "(SELECT 1 AS `a`, 'x' AS `b` UNION ALL SELECT 2, NULL) AS v",
+                want_empty: "(SELECT NULL AS `a`, NULL AS `b` FROM DUAL WHERE 1 = 0) AS v",
+            },
+            TC {
+                typ: Type::SQLite,
+                want: "(SELECT 1 AS \"a\", 'x' AS \"b\" UNION ALL SELECT 2, NULL) AS v",
+                want_empty: "(SELECT NULL AS \"a\", NULL AS \"b\" WHERE 1 = 0) AS v",
+            },
+            TC {
+                typ: Type::DuckDB,
+                want: "(VALUES (1, 'x'), (2, NULL)) AS v (\"a\", \"b\")",
+                want_empty: "(SELECT NULL AS \"a\", NULL AS \"b\" WHERE 1 = 0) AS v",
+            },
+        ];
+
+        let rows = vec![
+            vec![Value::from(1), Value::from("x")],
+            vec![Value::from(2), Value::Null],
+        ];
+        for tc in test_cases {
+            assert_eq!(derived(tc.typ, "v", &["a", "b"], &rows), tc.want);
+            assert_eq!(
+                derived(tc.typ, "v", &["a", "b"], &[] as &[Vec<Value>]),
+                tc.want_empty
+            );
+        }
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_derived_sqlite() {
+        use sqlx::{AnyConnection, Connection, Row};
+
+        use crate::db::{Cond, StmtBuilder, exec};
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        let rows = [
+            [Value::from(1), Value::from("x")],
+            [Value::from(2), Value::from("y")],
+        ];
+        let sb = StmtBuilder::new(derived(Type::SQLite, "v", &["a", "b"], &rows), Type::SQLite);
+        let stmt = sb.build_query_stmt(&["b"], &[Cond::gt("a", "1")]);
+        let rows = exec::fetch_all(&mut conn, &stmt, &[]).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get::<String, _>(0), "y");
+    }
+}
@@ -0,0 +1,232 @@
+//! Compact text encodings for URLs.
+//!
+//! Base62 and base58 shorten numeric IDs like Snowflake IDs in URLs, where base58 leaves out characters
+//! that look alike (`0`, `O`, `I` and `l`) for IDs that are read by humans.
+//! URL-safe base64 without padding encodes bytes like cursors and signatures into tokens for query parameters.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::encoding;
+//!
+//! let id: i64 = 1_789_123_456_789_012_345;
+//! let slug = encoding::encode_base62(id as u64);
+//!
+//! assert_eq!(slug, "28ACflPvrIX");
+//! assert_eq!(encoding::decode_base62(&slug).unwrap() as i64, id);
+//! assert_eq!(encoding::encode_base64url(b"\xfb\xff"), "-_8");
+//! ```
+
+use std::fmt;
+
+const BASE62: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The alphabet of Bitcoin.
+const BASE58: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Errors returned by this module.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The character is not in the alphabet.
+    InvalidChar(char),
+    /// The text is empty, or its length can't result from encoding.
+    InvalidLength(usize),
+    /// The text has unused bits set, so it's not the canonical encoding of any bytes.
+    NonCanonical,
+    /// The number doesn't fit into 64 bits.
+    Overflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidChar(c) => write!(f, "invalid character {:?}", c),
+            Error::InvalidLength(len) => write!(f, "invalid length {}", len),
+            Error::NonCanonical => write!(f, "non-canonical encoding"),
+            Error::Overflow => write!(f, "number overflow"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn encode_num(mut n: u64, alphabet: &[u8]) -> String {
+    let base = alphabet.len() as u64;
+    let mut out = Vec::new();
+    loop {
+        out.push(alphabet[(n % base) as usize]);
+        n /= base;
+        if n == 0 {
+            break;
+        }
+    }
+    out.reverse();
+    // The alphabets are ASCII.
+    String::from_utf8(out).unwrap_or_default()
+}
+
+fn decode_num(s: &str, alphabet: &[u8]) -> Result<u64, Error> {
+    if s.is_empty() {
+        return Err(Error::InvalidLength(0));
+    }
+    let base = alphabet.len() as u64;
+    s.chars().try_fold(0_u64, |n, c| {
+        let digit = u8::try_from(c)
+            .ok()
+            .and_then(|b| alphabet.iter().position(|&a| a == b))
+            .ok_or(Error::InvalidChar(c))?;
+        n.checked_mul(base)
+            .and_then(|n| n.checked_add(digit as u64))
+            .ok_or(Error::Overflow)
+    })
+}
+
+/// Encodes `n` in base62 with the digits, uppercase letters and lowercase letters in ASCII order.
+pub fn encode_base62(n: u64) -> String {
+    encode_num(n, BASE62)
+}
+
+/// Decodes a number encoded by [`encode_base62`].
+pub fn decode_base62(s: &str) -> Result<u64, Error> {
+    decode_num(s, BASE62)
+}
+
+/// Encodes `n` in base58 with the alphabet of Bitcoin.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::encoding;
+///
+/// assert_eq!(encoding::encode_base58(0), "1");
+/// assert_eq!(encoding::encode_base58(57), "z");
+/// assert_eq!(encoding::decode_base58("21").unwrap(), 58);
+/// assert!(encoding::decode_base58("0").is_err());
+/// ```
+pub fn encode_base58(n: u64) -> String {
+    encode_num(n, BASE58)
+}
+
+/// Decodes a number encoded by [`encode_base58`].
+pub fn decode_base58(s: &str) -> Result<u64, Error> {
+    decode_num(s, BASE58)
+}
+
+/// Encodes `bytes` in URL-safe base64 without padding, as defined by RFC 4648.
+pub fn encode_base64url(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0_u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        // A chunk of k bytes takes k + 1 characters.
+        for i in 0..=chunk.len() {
+            out.push(char::from(BASE64URL[(n >> (18 - 6 * i) & 0x3f) as usize]));
+        }
+    }
+    out
+}
+
+/// Decodes text encoded by [`encode_base64url`], rejecting padding and non-canonical text.
+pub fn decode_base64url(s: &str) -> Result<Vec<u8>, Error> {
+    if s.len() % 4 == 1 {
+        return Err(Error::InvalidLength(s.len()));
+    }
+    let mut out = Vec::with_capacity(s.len() / 4 * 3 + 2);
+    let bytes = s.as_bytes();
+    for (ci, chunk) in bytes.chunks(4).enumerate() {
+        let mut n = 0_u32;
+        for (i, &b) in chunk.iter().enumerate() {
+            let digit = match b {
+                b'A'..=b'Z' => b - b'A',
+                b'a'..=b'z' => b - b'a' + 26,
+                b'0'..=b'9' => b - b'0' + 52,
+                b'-' => 62,
+                b'_' => 63,
+                // The preceding bytes are ASCII, so this is the first byte of a character.
+                _ => {
+                    let c = s[ci * 4 + i..].chars().next().unwrap_or_default();
+                    return Err(Error::InvalidChar(c));
+                }
+            };
+            n |= u32::from(digit) << (18 - 6 * i);
+        }
+        // A chunk of k characters holds k - 1 bytes.
+        let len = chunk.len() - 1;
+        if n & (0xffffff >> (8 * len)) != 0 {
+            return Err(Error::NonCanonical);
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..=len]);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Error, decode_base58, decode_base62, decode_base64url, encode_base58, encode_base62,
+        encode_base64url,
+    };
+
+    #[test]
+    fn test_base62() {
+        for n in [0, 61, 62, u64::MAX] {
+            assert_eq!(decode_base62(&encode_base62(n)).unwrap(), n);
+            assert_eq!(decode_base58(&encode_base58(n)).unwrap(), n);
+        }
+        assert_eq!(encode_base62(61), "z");
+        assert_eq!(encode_base62(u64::MAX), "LygHa16AHYF");
+        assert_eq!(decode_base62("LygHa16AHYG"), Err(Error::Overflow));
+        assert_eq!(decode_base62("a-b"), Err(Error::InvalidChar('-')));
+        assert_eq!(decode_base58("l"), Err(Error::InvalidChar('l')));
+        assert_eq!(decode_base62(""), Err(Error::InvalidLength(0)));
+    }
+
+    #[test]
+    fn test_base64url() {
+        struct TC {
+            bytes: &'static [u8],
+            want: &'static str,
+        }
+
+        // See RFC 4648, section 10
+        let test_cases = vec![
+            TC {
+                bytes: b"",
+                want: "",
+            },
+            TC {
+                bytes: b"f",
+                want: "Zg",
+            },
+            TC {
+                bytes: b"fo",
+                want: "Zm8",
+            },
+            TC {
+                bytes: b"foo",
+                want: "Zm9v",
+            },
+            TC {
+                bytes: b"foobar",
+                want: "Zm9vYmFy",
+            },
+            TC {
+                bytes: &[0xfb, 0xff, 0xbf],
+                want: "-_-_",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(encode_base64url(tc.bytes), tc.want);
+            assert_eq!(decode_base64url(tc.want).unwrap(), tc.bytes);
+        }
+        assert_eq!(decode_base64url("Zh"), Err(Error::NonCanonical));
+        assert_eq!(decode_base64url("Zg=="), Err(Error::InvalidChar('=')));
+        assert_eq!(decode_base64url("Zm9vé"), Err(Error::InvalidChar('é')));
+        assert_eq!(decode_base64url("Zm9vY"), Err(Error::InvalidLength(5)));
+    }
+}
@@ -0,0 +1,498 @@
+//! Typed environment variables.
+//!
+//! [`get_parsed`], [`get_or_default`] and [`require`] read a variable and parse it with [`FromEnv`],
+//! which supports the primitive types, durations like `30s`, byte sizes like `512MB` and comma separated lists.
+//!
+//! To report every missing or invalid variable at startup instead of failing on the first one,
+//! read them through a [`Report`].
+
+use std::{
+    ffi::OsString,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
+
+/// Errors returned when reading environment variables.
+///
+/// The value of an invalid variable is not included, since it may be a secret.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The variable is not set.
+    Missing(String),
+    /// The variable could not be parsed.
+    Invalid { name: String, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Missing(name) => write!(f, "environment variable {} is missing", name),
+            Error::Invalid { name, message } => {
+                write!(f, "environment variable {} is invalid: {}", name, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Types that can be parsed from the value of an environment variable.
+pub trait FromEnv: Sized {
+    /// Parses the value, returning the reason on failure.
+    fn from_env(s: &str) -> Result<Self, String>;
+}
+
+macro_rules! impl_from_env {
+    ($($ty:ty),*) => {$(
+        impl FromEnv for $ty {
+            fn from_env(s: &str) -> Result<Self, String> {
+                s.trim().parse().map_err(|e| format!("{}", e))
+            }
+        }
+    )*};
+}
+
+impl_from_env!(
+    i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, char, IpAddr,
+    SocketAddr
+);
+
+impl FromEnv for String {
+    fn from_env(s: &str) -> Result<Self, String> {
+        Ok(s.to_string())
+    }
+}
+
+impl FromEnv for PathBuf {
+    fn from_env(s: &str) -> Result<Self, String> {
+        Ok(PathBuf::from(s))
+    }
+}
+
+/// Accepts `true`, `1`, `yes` and `on`, or `false`, `0`, `no` and `off`, case-insensitively.
+impl FromEnv for bool {
+    fn from_env(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(true),
+            "false" | "0" | "no" | "off" => Ok(false),
+            _ => Err(String::from("expected a boolean")),
+        }
+    }
+}
+
+/// Splits `s` into numbers and the units following them, like `1h30m` into `[("1", "h"), ("30", "m")]`.
+fn split_units(s: &str) -> Result<Vec<(&str, &str)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = s.trim();
+    while !rest.is_empty() {
+        let num_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let unit_end = rest[num_end..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .map_or(rest.len(), |i| num_end + i);
+        if num_end == 0 {
+            return Err(format!("expected a number before {:?}", &rest[..unit_end]));
+        }
+        parts.push((&rest[..num_end], rest[num_end..unit_end].trim()));
+        rest = &rest[unit_end..];
+    }
+    Ok(parts)
+}
+
+/// Accepts a sequence of numbers with units, like `30s`, `1.5h` or `1h30m`.
+/// The units are `ns`, `us`, `ms`, `s`, `m`, `h` and `d`. A bare `0` is accepted as well.
+impl FromEnv for Duration {
+    fn from_env(s: &str) -> Result<Self, String> {
+        if s.trim() == "0" {
+            return Ok(Duration::ZERO);
+        }
+        let parts = split_units(s)?;
+        if parts.is_empty() {
+            return Err(String::from("expected a duration"));
+        }
+        let mut total = Duration::ZERO;
+        for (num, unit) in parts {
+            let unit = match unit {
+                "ns" => Duration::from_nanos(1),
+                "us" | "µs" => Duration::from_micros(1),
+                "ms" => Duration::from_millis(1),
+                "s" => Duration::from_secs(1),
+                "m" => Duration::from_secs(60),
+                "h" => Duration::from_secs(60 * 60),
+                "d" => Duration::from_secs(24 * 60 * 60),
+                "" => return Err(format!("missing unit after {}", num)),
+                _ => return Err(format!("unknown unit {:?}", unit)),
+            };
+            let d = match num.parse::<u32>() {
+                Ok(n) => unit.checked_mul(n),
+                Err(_) => num
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|n| Duration::try_from_secs_f64(unit.as_secs_f64() * n).ok()),
+            };
+            total = d
+                .and_then(|d| total.checked_add(d))
+                .ok_or_else(|| format!("invalid duration {:?}", s.trim()))?;
+        }
+        Ok(total)
+    }
+}
+
+/// A size in bytes.
+///
+/// It's parsed from a number followed by an optional unit, case-insensitively,
+/// where `KB`, `MB`, `GB` and `TB` are powers of 1000 and `KiB`, `MiB`, `GiB` and `TiB` are powers of 1024.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::env::{ByteSize, FromEnv};
+///
+/// assert_eq!(ByteSize::from_env("512MB"), Ok(ByteSize(512_000_000)));
+/// assert_eq!(ByteSize::from_env("1.5 KiB"), Ok(ByteSize(1536)));
+/// assert_eq!(ByteSize::from_env("100"), Ok(ByteSize(100)));
+/// ```
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default, Debug)]
+pub struct ByteSize(pub u64);
+
+impl FromEnv for ByteSize {
+    fn from_env(s: &str) -> Result<Self, String> {
+        let [(num, unit)] = split_units(s)?[..] else {
+            return Err(String::from("expected a byte size"));
+        };
+        let factor: u64 = match unit.to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "mb" => 1000_u64.pow(2),
+            "gb" => 1000_u64.pow(3),
+            "tb" => 1000_u64.pow(4),
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            _ => return Err(format!("unknown unit {:?}", unit)),
+        };
+        let size = match num.parse::<u64>() {
+            Ok(n) => n.checked_mul(factor),
+            Err(_) => num
+                .parse::<f64>()
+                .ok()
+                .map(|n| (n * factor as f64).round())
+                .filter(|n| n.is_finite() && *n < u64::MAX as f64)
+                .map(|n| n as u64),
+        };
+        size.map(ByteSize)
+            .ok_or_else(|| format!("invalid byte size {:?}", s.trim()))
+    }
+}
+
+/// Accepts comma separated items, ignoring the whitespaces around them. An empty value is an empty list.
+impl<T: FromEnv> FromEnv for Vec<T> {
+    fn from_env(s: &str) -> Result<Self, String> {
+        if s.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        s.split(',')
+            .enumerate()
+            .map(|(i, item)| T::from_env(item.trim()).map_err(|e| format!("item {}: {}", i, e)))
+            .collect()
+    }
+}
+
+fn parse<T: FromEnv>(name: &str, val: Option<OsString>) -> Result<Option<T>, Error> {
+    let Some(val) = val else {
+        return Ok(None);
+    };
+    let invalid = |message| Error::Invalid {
+        name: name.to_string(),
+        message,
+    };
+    let val = val
+        .into_string()
+        .map_err(|_| invalid(String::from("not valid unicode")))?;
+    T::from_env(&val).map(Some).map_err(invalid)
+}
+
+/// Reads and parses an environment variable.
+///
+/// # Returns
+///
+/// * The parsed value, or [`None`] if the variable is not set.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::env::get_parsed;
+///
+/// let timeout: Option<std::time::Duration> = get_parsed("NO_SUCH_VAR").unwrap();
+///
+/// assert_eq!(timeout, None);
+/// ```
+pub fn get_parsed<T: FromEnv>(name: &str) -> Result<Option<T>, Error> {
+    parse(name, std::env::var_os(name))
+}
+
+/// Reads and parses an environment variable, returning `default` if it's not set.
+pub fn get_or_default<T: FromEnv>(name: &str, default: T) -> Result<T, Error> {
+    Ok(get_parsed(name)?.unwrap_or(default))
+}
+
+/// Reads and parses an environment variable, which must be set.
+pub fn require<T: FromEnv>(name: &str) -> Result<T, Error> {
+    get_parsed(name)?.ok_or_else(|| Error::Missing(name.to_string()))
+}
+
+/// Collects the errors of reading several environment variables, so that they can be reported at once.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::env::Report;
+///
+/// let mut report = Report::new();
+/// let port: u16 = report.get_or_default("NO_SUCH_PORT", 8080);
+/// let url: Option<String> = report.require("NO_SUCH_DATABASE_URL");
+///
+/// assert_eq!(port, 8080);
+/// assert!(url.is_none());
+/// assert_eq!(
+///     report.finish().unwrap_err().to_string(),
+///     "1 invalid environment variable(s):\n  environment variable NO_SUCH_DATABASE_URL is missing"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Report {
+    lookup: fn(&str) -> Option<OsString>,
+    errors: Vec<Error>,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Report {
+            lookup: |name| std::env::var_os(name),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Report {
+    /// Creates a new [`Report`] without errors.
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    /// Gets errors.
+    pub fn get_errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Like [`get_parsed`], but records the error and returns [`None`] if the variable is invalid.
+    pub fn get_parsed<T: FromEnv>(&mut self, name: &str) -> Option<T> {
+        match parse(name, (self.lookup)(name)) {
+            Ok(v) => v,
+            Err(e) => {
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+
+    /// Like [`get_or_default`], but records the error and returns `default` if the variable is invalid.
+    pub fn get_or_default<T: FromEnv>(&mut self, name: &str, default: T) -> T {
+        self.get_parsed(name).unwrap_or(default)
+    }
+
+    /// Like [`require`], but records the error and returns [`None`] if the variable is missing or invalid.
+    pub fn require<T: FromEnv>(&mut self, name: &str) -> Option<T> {
+        let len = self.errors.len();
+        let v = self.get_parsed(name);
+        if v.is_none() && self.errors.len() == len {
+            self.errors.push(Error::Missing(name.to_string()));
+        }
+        v
+    }
+
+    /// Returns `Ok(())` if no error is recorded, or the report itself otherwise.
+    pub fn finish(self) -> Result<(), Report> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid environment variable(s):", self.errors.len())?;
+        for e in &self.errors {
+            write!(f, "\n  {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Report {}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, fmt::Debug, time::Duration};
+
+    use super::{ByteSize, Error, FromEnv, Report};
+
+    fn check<T: FromEnv + PartialEq + Debug>(s: &str, want: Option<T>) {
+        assert_eq!(T::from_env(s).ok(), want, "{}", s);
+    }
+
+    #[test]
+    fn test_from_env() {
+        check("on", Some(true));
+        check("No", Some(false));
+        check::<bool>("maybe", None);
+        check(" 42 ", Some(42_u16));
+        check::<u8>("256", None);
+        check(
+            "127.0.0.1:80",
+            Some("127.0.0.1:80".parse::<std::net::SocketAddr>().unwrap()),
+        );
+        check(
+            "a, b,c",
+            Some(vec![
+                String::from("a"),
+                String::from("b"),
+                String::from("c"),
+            ]),
+        );
+        check("", Some(Vec::<u32>::new()));
+        check::<Vec<u32>>("1,x", None);
+    }
+
+    #[test]
+    fn test_duration() {
+        struct TC {
+            s: &'static str,
+            want: Option<Duration>,
+        }
+
+        let test_cases = vec![
+            TC {
+                s: "30s",
+                want: Some(Duration::from_secs(30)),
+            },
+            TC {
+                s: "1h30m",
+                want: Some(Duration::from_secs(90 * 60)),
+            },
+            TC {
+                s: "1.5s",
+                want: Some(Duration::from_millis(1500)),
+            },
+            TC {
+                s: "250ms",
+                want: Some(Duration::from_millis(250)),
+            },
+            TC {
+                s: "2d 1ns",
+                want: Some(Duration::from_secs(2 * 24 * 60 * 60) + Duration::from_nanos(1)),
+            },
+            TC {
+                s: "0",
+                want: Some(Duration::ZERO),
+            },
+            TC {
+                s: "30",
+                want: None,
+            },
+            TC {
+                s: "5y",
+                want: None,
+            },
+            TC { s: "s", want: None },
+            TC { s: "", want: None },
+            TC {
+                s: "9999999999999999999999d",
+                want: None,
+            },
+        ];
+
+        for tc in test_cases {
+            check(tc.s, tc.want);
+        }
+    }
+
+    #[test]
+    fn test_byte_size() {
+        struct TC {
+            s: &'static str,
+            want: Option<u64>,
+        }
+
+        let test_cases = vec![
+            TC {
+                s: "512MB",
+                want: Some(512_000_000),
+            },
+            TC {
+                s: "1gib",
+                want: Some(1 << 30),
+            },
+            TC {
+                s: "0.5 KB",
+                want: Some(500),
+            },
+            TC {
+                s: "10B",
+                want: Some(10),
+            },
+            TC {
+                s: "20000000TiB",
+                want: None,
+            },
+            TC {
+                s: "1PB",
+                want: None,
+            },
+            TC {
+                s: "1KB2",
+                want: None,
+            },
+        ];
+
+        for tc in test_cases {
+            check(tc.s, tc.want.map(ByteSize));
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = Report {
+            lookup: |name| match name {
+                "PORT" => Some(OsString::from("80")),
+                "TIMEOUT" => Some(OsString::from("soon")),
+                _ => None,
+            },
+            errors: Vec::new(),
+        };
+        assert_eq!(report.require::<u16>("PORT"), Some(80));
+        assert_eq!(
+            report.get_or_default("TIMEOUT", Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(report.get_parsed::<String>("NAME"), None);
+        assert_eq!(report.require::<u16>("TIMEOUT"), None);
+        assert_eq!(report.require::<String>("URL"), None);
+
+        let report = report.finish().unwrap_err();
+        assert_eq!(report.get_errors().len(), 3);
+        assert_eq!(report.get_errors()[2], Error::Missing(String::from("URL")));
+        assert!(
+            report
+                .to_string()
+                .starts_with("3 invalid environment variable(s):\n")
+        );
+        assert!(Report::new().finish().is_ok());
+    }
+}
@@ -0,0 +1,439 @@
+//! A common application error type.
+//!
+//! [`AppError`] carries a semantic [`Code`], a message, an optional source and whether retrying may help.
+//! It can be converted from the errors of the standard library, sqlx, serde_json and reqwest,
+//! and mapped to an HTTP status via [`Code::http_status`].
+
+use std::{
+    error::Error as StdError,
+    fmt,
+    num::{ParseFloatError, ParseIntError},
+};
+
+/// The semantic category of an [`AppError`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Code {
+    /// The input is malformed or invalid.
+    InvalidArgument,
+    /// The caller is not authenticated.
+    Unauthenticated,
+    /// The caller is not allowed to perform the operation.
+    PermissionDenied,
+    /// The resource doesn't exist.
+    NotFound,
+    /// The resource already exists, for example a unique violation.
+    Conflict,
+    /// The operation violates a constraint other than uniqueness, for example a foreign key.
+    ConstraintViolation,
+    /// The operation was aborted by a concurrent one, for example a serialization failure or a deadlock.
+    Aborted,
+    /// The operation timed out.
+    Timeout,
+    /// A dependency like the database is unavailable.
+    Unavailable,
+    /// An unexpected error.
+    Internal,
+}
+
+impl Code {
+    /// Returns the code in snake case, like `not_found`, which is suitable for API responses.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::InvalidArgument => "invalid_argument",
+            Code::Unauthenticated => "unauthenticated",
+            Code::PermissionDenied => "permission_denied",
+            Code::NotFound => "not_found",
+            Code::Conflict => "conflict",
+            Code::ConstraintViolation => "constraint_violation",
+            Code::Aborted => "aborted",
+            Code::Timeout => "timeout",
+            Code::Unavailable => "unavailable",
+            Code::Internal => "internal",
+        }
+    }
+
+    /// Returns the HTTP status code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::error::Code;
+    ///
+    /// assert_eq!(Code::NotFound.http_status(), 404);
+    /// assert_eq!(Code::Conflict.http_status(), 409);
+    /// ```
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Code::InvalidArgument => 400,
+            Code::Unauthenticated => 401,
+            Code::PermissionDenied => 403,
+            Code::NotFound => 404,
+            Code::Conflict | Code::Aborted => 409,
+            Code::ConstraintViolation => 422,
+            Code::Internal => 500,
+            Code::Unavailable => 503,
+            Code::Timeout => 504,
+        }
+    }
+
+    /// Returns the code of an HTTP error status, which is [`Code::Internal`] for unknown statuses.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::error::Code;
+    ///
+    /// assert_eq!(Code::from_http_status(404), Code::NotFound);
+    /// assert_eq!(Code::from_http_status(429), Code::Unavailable);
+    /// ```
+    pub fn from_http_status(status: u16) -> Code {
+        match status {
+            400 | 405 | 413 | 414 | 415 => Code::InvalidArgument,
+            401 => Code::Unauthenticated,
+            403 => Code::PermissionDenied,
+            404 | 410 => Code::NotFound,
+            409 => Code::Conflict,
+            412 | 422 => Code::ConstraintViolation,
+            408 | 504 => Code::Timeout,
+            429 | 502 | 503 => Code::Unavailable,
+            _ => Code::Internal,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An application error.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::error::{AppError, Code};
+///
+/// let e = AppError::new(Code::NotFound, String::from("user 1 is not found"));
+/// assert_eq!(e.get_code().http_status(), 404);
+/// assert_eq!(e.to_string(), "not_found: user 1 is not found");
+///
+/// let e = AppError::from("x".parse::<u32>().unwrap_err());
+/// assert_eq!(e.get_code(), Code::InvalidArgument);
+/// assert!(!e.is_retryable());
+/// ```
+#[derive(Debug)]
+pub struct AppError {
+    code: Code,
+    message: String,
+    source: Option<Box<dyn StdError + Send + Sync>>,
+    retryable: bool,
+}
+
+impl AppError {
+    /// Creates a new [`AppError`] without source, which is not retryable.
+    pub fn new(code: Code, message: String) -> AppError {
+        AppError {
+            code,
+            message,
+            source: None,
+            retryable: false,
+        }
+    }
+
+    /// Sets the underlying error, which is returned by [`Error::source`](StdError::source).
+    pub fn with_source<E: StdError + Send + Sync + 'static>(mut self, source: E) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Sets whether retrying the operation may succeed.
+    pub fn retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Gets code.
+    pub fn get_code(&self) -> Code {
+        self.code
+    }
+
+    /// Gets message.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns whether retrying the operation may succeed.
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
+    /// Returns the message that is safe to show to clients.
+    ///
+    /// Messages of [`Code::Internal`] errors may contain implementation details,
+    /// so they are replaced by a generic one.
+    pub fn public_message(&self) -> &str {
+        match self.code {
+            Code::Internal => "internal error",
+            _ => &self.message,
+        }
+    }
+
+    fn wrap<E: StdError + Send + Sync + 'static>(code: Code, e: E) -> AppError {
+        AppError::new(code, e.to_string()).with_source(e)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl StdError for AppError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source
+            .as_deref()
+            .map(|e| e as &(dyn StdError + 'static))
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        let (code, retryable) = match e.kind() {
+            ErrorKind::NotFound => (Code::NotFound, false),
+            ErrorKind::PermissionDenied => (Code::PermissionDenied, false),
+            ErrorKind::AlreadyExists => (Code::Conflict, false),
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => (Code::InvalidArgument, false),
+            ErrorKind::TimedOut => (Code::Timeout, true),
+            ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::Interrupted => (Code::Unavailable, true),
+            _ => (Code::Internal, false),
+        };
+        AppError::wrap(code, e).retryable(retryable)
+    }
+}
+
+impl From<ParseIntError> for AppError {
+    fn from(e: ParseIntError) -> Self {
+        AppError::wrap(Code::InvalidArgument, e)
+    }
+}
+
+impl From<ParseFloatError> for AppError {
+    fn from(e: ParseFloatError) -> Self {
+        AppError::wrap(Code::InvalidArgument, e)
+    }
+}
+
+impl From<std::str::Utf8Error> for AppError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        AppError::wrap(Code::InvalidArgument, e)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for AppError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        AppError::wrap(Code::InvalidArgument, e)
+    }
+}
+
+impl From<crate::db::PolicyError> for AppError {
+    fn from(e: crate::db::PolicyError) -> Self {
+        AppError::wrap(Code::InvalidArgument, e)
+    }
+}
+
+impl From<crate::env::Error> for AppError {
+    fn from(e: crate::env::Error) -> Self {
+        AppError::wrap(Code::Internal, e)
+    }
+}
+
+/// Syntax and data errors are invalid input, while I/O errors are internal.
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        let code = match e.classify() {
+            serde_json::error::Category::Io => Code::Internal,
+            _ => Code::InvalidArgument,
+        };
+        AppError::wrap(code, e)
+    }
+}
+
+/// Maps database errors by their kinds, see [`classify`](crate::db::errors::classify),
+/// where serialization failures, timeouts and lost connections are retryable.
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        use crate::db::errors::{DbErrorKind, classify};
+
+        if matches!(e, sqlx::Error::RowNotFound) {
+            return AppError::wrap(Code::NotFound, e);
+        }
+        let (code, retryable) = match classify(&e) {
+            DbErrorKind::UniqueViolation { .. } => (Code::Conflict, false),
+            DbErrorKind::ForeignKeyViolation { .. } | DbErrorKind::NotNullViolation => {
+                (Code::ConstraintViolation, false)
+            }
+            DbErrorKind::SerializationFailure => (Code::Aborted, true),
+            DbErrorKind::Timeout => (Code::Timeout, true),
+            DbErrorKind::ConnectionLost => (Code::Unavailable, true),
+            DbErrorKind::Other => match &e {
+                sqlx::Error::Database(db)
+                    if db.kind() == sqlx::error::ErrorKind::CheckViolation =>
+                {
+                    (Code::ConstraintViolation, false)
+                }
+                _ => (Code::Internal, false),
+            },
+        };
+        AppError::wrap(code, e).retryable(retryable)
+    }
+}
+
+/// Timeouts and connection failures are retryable.
+#[cfg(feature = "http")]
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        let (code, retryable) = if e.is_timeout() {
+            (Code::Timeout, true)
+        } else if e.is_connect() {
+            (Code::Unavailable, true)
+        } else if e.is_builder() {
+            (Code::InvalidArgument, false)
+        } else if let Some(status) = e.status() {
+            let code = Code::from_http_status(status.as_u16());
+            (code, matches!(code, Code::Timeout | Code::Unavailable))
+        } else {
+            (Code::Internal, false)
+        };
+        AppError::wrap(code, e).retryable(retryable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error as _, io};
+
+    use super::{AppError, Code};
+
+    #[test]
+    fn test_from_io() {
+        struct TC {
+            kind: io::ErrorKind,
+            code: Code,
+            retryable: bool,
+        }
+
+        let test_cases = vec![
+            TC {
+                kind: io::ErrorKind::NotFound,
+                code: Code::NotFound,
+                retryable: false,
+            },
+            TC {
+                kind: io::ErrorKind::TimedOut,
+                code: Code::Timeout,
+                retryable: true,
+            },
+            TC {
+                kind: io::ErrorKind::ConnectionReset,
+                code: Code::Unavailable,
+                retryable: true,
+            },
+            TC {
+                kind: io::ErrorKind::Other,
+                code: Code::Internal,
+                retryable: false,
+            },
+        ];
+
+        for tc in test_cases {
+            let e = AppError::from(io::Error::new(tc.kind, "boom"));
+            assert_eq!(e.get_code(), tc.code);
+            assert_eq!(e.is_retryable(), tc.retryable);
+            assert!(e.source().is_some());
+        }
+
+        let e = AppError::from(io::Error::other("secret path"));
+        assert_eq!(e.public_message(), "internal error");
+        assert_eq!(e.get_message(), "secret path");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_from_serde() {
+        let e = AppError::from(serde_json::from_str::<u32>("x").unwrap_err());
+        assert_eq!(e.get_code(), Code::InvalidArgument);
+    }
+
+    #[cfg(feature = "sqlx")]
+    #[tokio::test]
+    async fn test_from_sqlx() {
+        use sqlx::{AnyConnection, Connection};
+
+        use crate::db::exec;
+
+        sqlx::any::install_default_drivers();
+        let mut conn = AnyConnection::connect("sqlite::memory:").await.unwrap();
+        exec::execute(&mut conn, "PRAGMA foreign_keys = ON", &[])
+            .await
+            .unwrap();
+        exec::execute(&mut conn, "CREATE TABLE p (id INTEGER PRIMARY KEY)", &[])
+            .await
+            .unwrap();
+        exec::execute(
+            &mut conn,
+            "CREATE TABLE c (id INTEGER, p INTEGER NOT NULL REFERENCES p (id))",
+            &[],
+        )
+        .await
+        .unwrap();
+        exec::execute(&mut conn, "INSERT INTO p VALUES (1)", &[])
+            .await
+            .unwrap();
+
+        struct TC {
+            stmt: &'static str,
+            code: Code,
+        }
+
+        let test_cases = vec![
+            TC {
+                stmt: "INSERT INTO p VALUES (1)",
+                code: Code::Conflict,
+            },
+            TC {
+                stmt: "INSERT INTO c VALUES (1, 2)",
+                code: Code::ConstraintViolation,
+            },
+            TC {
+                stmt: "INSERT INTO c VALUES (1, NULL)",
+                code: Code::ConstraintViolation,
+            },
+            TC {
+                stmt: "SELECT * FROM no_such_tbl",
+                code: Code::Internal,
+            },
+        ];
+
+        for tc in test_cases {
+            let e = AppError::from(exec::execute(&mut conn, tc.stmt, &[]).await.unwrap_err());
+            assert_eq!(e.get_code(), tc.code, "{}", tc.stmt);
+            assert!(!e.is_retryable());
+        }
+        assert_eq!(
+            AppError::from(sqlx::Error::RowNotFound).get_code(),
+            Code::NotFound
+        );
+        assert!(AppError::from(sqlx::Error::PoolTimedOut).is_retryable());
+    }
+}
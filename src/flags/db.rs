@@ -0,0 +1,141 @@
+//! Feature flags stored in a table.
+
+use sqlx::AnyPool;
+
+use crate::db::{Cond, KV, PLACEHOLDER, StmtBuilder, Type, Value, exec, unsupported_dialect};
+
+use super::{Error, Flag, FlagProvider};
+
+/// Reads flags from a table like `feature_flags`, whose targeted keys are stored comma-separated.
+///
+/// Every call queries the table, so wrap the provider in [`Cached`](super::Cached).
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::{
+///     db::{Type, exec::execute},
+///     flags::{Cached, Flag, FlagProvider, db::DbProvider},
+/// };
+/// use sqlx::any::AnyPoolOptions;
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// sqlx::any::install_default_drivers();
+/// // Every connection to an in-memory database has its own database
+/// let pool = AnyPoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+/// let provider = DbProvider::new(pool.clone(), String::from("feature_flags"), Type::SQLite);
+/// execute(&pool, &provider.ddl().unwrap(), &[]).await.unwrap();
+///
+/// provider.set("new_checkout", &Flag::on().rollout(10)).await.unwrap();
+/// let provider = Cached::new(provider, Duration::from_secs(30));
+///
+/// assert_eq!(provider.get("new_checkout").await.unwrap(), Some(Flag::on().rollout(10)));
+/// assert!(!provider.is_on("no_such_flag", Some("alice")).await.unwrap());
+/// # });
+/// ```
+#[derive(Clone, Debug)]
+pub struct DbProvider {
+    pool: AnyPool,
+    sb: StmtBuilder,
+}
+
+impl DbProvider {
+    /// Creates a new [`DbProvider`], where `tbl` is the table name and `typ` is the database type.
+    pub fn new(pool: AnyPool, tbl: String, typ: Type) -> DbProvider {
+        DbProvider {
+            pool,
+            sb: StmtBuilder::new(tbl, typ),
+        }
+    }
+
+    /// Gets table name.
+    pub fn get_tbl(&self) -> &String {
+        self.sb.get_tbl()
+    }
+
+    /// Gets database type.
+    pub fn get_typ(&self) -> &Type {
+        self.sb.get_typ()
+    }
+
+    /// Generates the statement that creates the table if it doesn't exist.
+    ///
+    /// `enabled` is 0 or 1, because the `Any` driver can't decode SQLite booleans.
+    /// Returns an error on DuckDB, which sqlx has no driver for.
+    pub fn ddl(&self) -> Result<String, sqlx::Error> {
+        let name = match self.get_typ() {
+            Type::MySQL | Type::MariaDB | Type::TiDB => "VARCHAR(255)",
+            Type::PostgreSQL | Type::CockroachDB | Type::SQLite => "TEXT",
+            Type::DuckDB => return Err(unsupported_dialect(Type::DuckDB)),
+        };
+        Ok(format!(
+            "CREATE TABLE IF NOT EXISTS {} (name {} PRIMARY KEY, enabled INTEGER NOT NULL, rollout INTEGER NOT NULL, targets TEXT NOT NULL)",
+            self.get_tbl(),
+            name
+        ))
+    }
+
+    /// Creates or replaces the flag named `name`.
+    pub async fn set(&self, name: &str, flag: &Flag) -> Result<(), Error> {
+        let cols = ["name", "enabled", "rollout", "targets"].map(|key| KV {
+            key,
+            val: PLACEHOLDER,
+        });
+        let stmt = self.sb.build_upsert_stmt(&cols, &["name"]);
+        let targets: Vec<&str> = flag.targets.iter().map(String::as_str).collect();
+        let args = [
+            Value::from(name),
+            Value::from(i64::from(flag.enabled)),
+            Value::from(i64::from(flag.rollout)),
+            Value::from(targets.join(",")),
+        ];
+        exec::execute(&self.pool, &stmt, &args).await?;
+        Ok(())
+    }
+
+    /// Deletes the flag named `name`.
+    pub async fn delete(&self, name: &str) -> Result<(), Error> {
+        let stmt = self.sb.build_delete_stmt(&[Cond::eq("name", PLACEHOLDER)]);
+        exec::execute(&self.pool, &stmt, &[Value::from(name)]).await?;
+        Ok(())
+    }
+}
+
+impl FlagProvider for DbProvider {
+    async fn get(&self, name: &str) -> Result<Option<Flag>, Error> {
+        let stmt = self.sb.build_query_stmt(
+            &["enabled", "rollout", "targets"],
+            &[Cond::eq("name", PLACEHOLDER)],
+        );
+        let Some(row) = exec::fetch_optional(&self.pool, &stmt, &[Value::from(name)]).await? else {
+            return Ok(None);
+        };
+        let invalid = || Error::Invalid(name.to_string());
+        let enabled = match exec::decode_value(&row, 0)? {
+            Value::Int(v) => v != 0,
+            _ => return Err(invalid()),
+        };
+        let rollout = match exec::decode_value(&row, 1)? {
+            Value::Int(v) => u8::try_from(v)
+                .ok()
+                .filter(|v| *v <= 100)
+                .ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        let targets = match exec::decode_value(&row, 2)? {
+            Value::Text(v) => v,
+            _ => return Err(invalid()),
+        };
+        Ok(Some(Flag {
+            enabled,
+            rollout,
+            targets: targets
+                .split(',')
+                .filter(|key| !key.is_empty())
+                .map(String::from)
+                .collect(),
+        }))
+    }
+}
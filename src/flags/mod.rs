@@ -0,0 +1,504 @@
+//! Feature flags.
+//!
+//! A [`FlagProvider`] resolves [`Flag`]s by name, which decide whether a feature is on for a key like a user ID.
+//! A flag is off entirely, on for the keys it targets, and on for a percentage of the other keys.
+//! Keys are assigned to percentages by a stable hash of the flag name and the key,
+//! so a key stays in the rollout as the percentage grows, and different flags roll out to different keys.
+//!
+//! - [`EnvProvider`] reads environment variables.
+//! - [`StaticProvider`] holds fixed flags, like the ones loaded from a config file.
+//! - [`db::DbProvider`] reads a table via this crate's statement builders, with the `sqlx` feature enabled.
+//!
+//! Wrap a provider in [`Cached`] to avoid resolving the same flag on every use.
+//!
+//! Flags are written as comma-separated specs, like `on`, `off`, `25%` or `10%,alice,bob`,
+//! where other items than `on`, `off` and percentages are targeted keys.
+//! With the `serde` feature enabled, flags are (de)serialized as specs, and booleans are deserialized as `on` / `off`.
+
+#[cfg(feature = "sqlx")]
+pub mod db;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{cache::LruCache, hash::xxh64};
+
+/// Errors returned by flag providers.
+#[derive(Debug)]
+pub enum Error {
+    /// The flag is not a valid spec.
+    Invalid(String),
+    /// The query failed.
+    #[cfg(feature = "sqlx")]
+    Sqlx(sqlx::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Invalid(s) => write!(f, "invalid flag {}", s),
+            #[cfg(feature = "sqlx")]
+            Error::Sqlx(e) => write!(f, "sqlx error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "sqlx")]
+            Error::Sqlx(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::Sqlx(e)
+    }
+}
+
+/// A feature flag.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::flags::Flag;
+///
+/// let flag: Flag = "50%,alice".parse().unwrap();
+///
+/// assert!(flag.evaluate("new_checkout", Some("alice")));
+/// assert!(!flag.evaluate("new_checkout", None));
+/// assert_eq!(flag, Flag::on().rollout(50).target(String::from("alice")));
+/// assert!(!Flag::off().target(String::from("alice")).evaluate("new_checkout", Some("alice")));
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Flag {
+    enabled: bool,
+    rollout: u8,
+    targets: BTreeSet<String>,
+}
+
+impl Flag {
+    /// Creates a new [`Flag`] that is on for every key.
+    pub fn on() -> Flag {
+        Flag {
+            enabled: true,
+            rollout: 100,
+            targets: BTreeSet::new(),
+        }
+    }
+
+    /// Creates a new [`Flag`] that is off for every key, including the targeted ones.
+    pub fn off() -> Flag {
+        Flag {
+            enabled: false,
+            rollout: 0,
+            targets: BTreeSet::new(),
+        }
+    }
+
+    /// Sets the percentage of keys that the flag is on for, which is capped at 100.
+    pub fn rollout(mut self, percent: u8) -> Self {
+        self.rollout = percent.min(100);
+        self
+    }
+
+    /// Adds a key that the flag is always on for, unless the flag is off.
+    pub fn target(mut self, key: String) -> Self {
+        self.targets.insert(key);
+        self
+    }
+
+    /// Returns whether the flag is enabled at all.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Gets the percentage of keys that the flag is on for.
+    pub fn get_rollout(&self) -> u8 {
+        self.rollout
+    }
+
+    /// Gets targeted keys.
+    pub fn get_targets(&self) -> &BTreeSet<String> {
+        &self.targets
+    }
+
+    /// Decides whether the flag named `name` is on for `key`.
+    ///
+    /// Without a key, the flag is only on if it's rolled out to every key.
+    pub fn evaluate(&self, name: &str, key: Option<&str>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        match key {
+            _ if self.rollout >= 100 => true,
+            Some(key) => self.targets.contains(key) || bucket(name, key) < self.rollout,
+            None => false,
+        }
+    }
+}
+
+/// Assigns `key` to a bucket between 0 and 99 for the flag named `name`.
+fn bucket(name: &str, key: &str) -> u8 {
+    let hash = xxh64(format!("{}:{}", name, key).as_bytes(), 0);
+    // The remainder is less than 100.
+    (hash % 100) as u8
+}
+
+impl fmt::Display for Flag {
+    /// Formats the flag as a spec.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.enabled, self.rollout) {
+            (false, 0) => f.write_str("off")?,
+            // Keep the rollout of a disabled flag, so it's restored when the flag is enabled again.
+            (false, rollout) => write!(f, "off,{}%", rollout)?,
+            (true, 100) => f.write_str("on")?,
+            (true, rollout) => write!(f, "{}%", rollout)?,
+        }
+        for target in &self.targets {
+            write!(f, ",{}", target)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Flag {
+    type Err = Error;
+
+    /// Parses a spec, where a spec of only targets is on for them and off for the others.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flag = Flag::on().rollout(0);
+        for item in s.split(',').map(str::trim) {
+            match item.to_ascii_lowercase().as_str() {
+                "" => return Err(Error::Invalid(s.to_string())),
+                "on" | "true" => flag.rollout = 100,
+                "off" | "false" => flag.enabled = false,
+                _ => match item.strip_suffix('%').map(str::parse::<u8>) {
+                    Some(Ok(percent)) if percent <= 100 => flag.rollout = percent,
+                    Some(_) => return Err(Error::Invalid(s.to_string())),
+                    None => {
+                        flag.targets.insert(item.to_string());
+                    }
+                },
+            }
+        }
+        Ok(flag)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::fmt;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    use super::Flag;
+
+    impl Serialize for Flag {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.collect_str(self)
+        }
+    }
+
+    struct FlagVisitor;
+
+    impl de::Visitor<'_> for FlagVisitor {
+        type Value = Flag;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a boolean or a flag spec")
+        }
+
+        fn visit_bool<E: de::Error>(self, v: bool) -> Result<Flag, E> {
+            Ok(if v { Flag::on() } else { Flag::off() })
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Flag, E> {
+            v.parse().map_err(de::Error::custom)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Flag {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            d.deserialize_any(FlagVisitor)
+        }
+    }
+}
+
+/// A source of feature flags.
+pub trait FlagProvider {
+    /// Resolves the flag named `name`, or returns [`None`] if it's not defined.
+    fn get(&self, name: &str) -> impl Future<Output = Result<Option<Flag>, Error>> + Send;
+
+    /// Decides whether the flag named `name` is on for `key`, where undefined flags are off.
+    fn is_on(
+        &self,
+        name: &str,
+        key: Option<&str>,
+    ) -> impl Future<Output = Result<bool, Error>> + Send
+    where
+        Self: Sync,
+    {
+        async move { Ok(self.get(name).await?.is_some_and(|f| f.evaluate(name, key))) }
+    }
+}
+
+/// Reads flags from environment variables named `<prefix><NAME>`,
+/// where the name is uppercased and characters other than letters and digits are replaced by `_`.
+#[derive(Clone, Default, Debug)]
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl EnvProvider {
+    /// Creates a new [`EnvProvider`] without prefix.
+    pub fn new() -> EnvProvider {
+        EnvProvider::default()
+    }
+
+    /// Sets the prefix of variable names, like `FLAG_`.
+    pub fn prefix(mut self, prefix: String) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Gets prefix.
+    pub fn get_prefix(&self) -> &str {
+        &self.prefix
+    }
+}
+
+impl FlagProvider for EnvProvider {
+    async fn get(&self, name: &str) -> Result<Option<Flag>, Error> {
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let var = format!("{}{}", self.prefix, name);
+        match std::env::var(&var) {
+            Ok(spec) => spec.parse().map(Some),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(std::env::VarError::NotUnicode(_)) => Err(Error::Invalid(var)),
+        }
+    }
+}
+
+/// Holds fixed flags, like the ones loaded from a config file.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use sainnhe_common::flags::{Flag, FlagProvider, StaticProvider};
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let provider = StaticProvider::new(HashMap::from([(String::from("dark_mode"), Flag::on())]));
+///
+/// assert!(provider.is_on("dark_mode", None).await.unwrap());
+/// assert!(!provider.is_on("no_such_flag", None).await.unwrap());
+/// # });
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct StaticProvider {
+    flags: HashMap<String, Flag>,
+}
+
+impl StaticProvider {
+    /// Creates a new [`StaticProvider`].
+    pub fn new(flags: HashMap<String, Flag>) -> StaticProvider {
+        StaticProvider { flags }
+    }
+
+    /// Gets flags.
+    pub fn get_flags(&self) -> &HashMap<String, Flag> {
+        &self.flags
+    }
+}
+
+impl FlagProvider for StaticProvider {
+    async fn get(&self, name: &str) -> Result<Option<Flag>, Error> {
+        Ok(self.flags.get(name).cloned())
+    }
+}
+
+/// Caches the flags resolved by another provider for a TTL, after which they are resolved again.
+///
+/// Undefined flags are cached as well, while errors are not.
+#[derive(Debug)]
+pub struct Cached<P> {
+    provider: P,
+    cache: LruCache<String, Option<Flag>>,
+}
+
+/// The maximum number of flags cached by [`Cached`].
+const CACHED_CAPACITY: usize = 1024;
+
+impl<P: FlagProvider> Cached<P> {
+    /// Creates a new [`Cached`] provider, whose flags are resolved again after `ttl`.
+    pub fn new(provider: P, ttl: Duration) -> Cached<P> {
+        Cached {
+            provider,
+            cache: LruCache::new(CACHED_CAPACITY).ttl(Some(ttl)),
+        }
+    }
+
+    /// Gets the wrapped provider.
+    pub fn get_provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Drops the cached flag named `name`, for example after it's updated.
+    pub fn invalidate(&self, name: &str) {
+        self.cache.remove(name);
+    }
+}
+
+impl<P: FlagProvider + Sync> FlagProvider for Cached<P> {
+    async fn get(&self, name: &str) -> Result<Option<Flag>, Error> {
+        if let Some(flag) = self.cache.get(name) {
+            return Ok(flag);
+        }
+        let flag = self.provider.get(name).await?;
+        self.cache.insert(name.to_string(), flag.clone());
+        Ok(flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::{Cached, EnvProvider, Error, Flag, FlagProvider, StaticProvider};
+
+    #[test]
+    fn test_spec() {
+        struct TC {
+            spec: &'static str,
+            want: Option<Flag>,
+            want_formatted: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                spec: "ON",
+                want: Some(Flag::on()),
+                want_formatted: "on",
+            },
+            TC {
+                spec: "off, bob",
+                want: Some(Flag::off().target(String::from("bob"))),
+                want_formatted: "off,bob",
+            },
+            TC {
+                spec: "bob,alice",
+                want: Some(
+                    Flag::on()
+                        .rollout(0)
+                        .target(String::from("bob"))
+                        .target(String::from("alice")),
+                ),
+                want_formatted: "0%,alice,bob",
+            },
+            TC {
+                spec: "101%",
+                want: None,
+                want_formatted: "",
+            },
+            TC {
+                spec: "on,,bob",
+                want: None,
+                want_formatted: "",
+            },
+        ];
+
+        for tc in test_cases {
+            let got = tc.spec.parse::<Flag>().ok();
+            assert_eq!(got, tc.want, "{}", tc.spec);
+            if let Some(flag) = got {
+                assert_eq!(flag.to_string(), tc.want_formatted);
+                assert_eq!(flag.to_string().parse::<Flag>().unwrap(), flag);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rollout() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("user-{}", i)).collect();
+        let on = |flag: &Flag, name: &str| {
+            keys.iter()
+                .filter(|key| flag.evaluate(name, Some(key)))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let ten = on(&Flag::on().rollout(10), "a");
+        let fifty = on(&Flag::on().rollout(50), "a");
+        assert!((50..150).contains(&ten.len()), "{}", ten.len());
+        // Keys stay in the rollout as it grows
+        assert!(ten.iter().all(|key| fifty.contains(key)));
+        assert_ne!(ten, on(&Flag::on().rollout(10), "b"));
+        assert!(on(&Flag::on().rollout(0), "a").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_providers() {
+        struct Counting(AtomicUsize);
+
+        impl FlagProvider for Counting {
+            async fn get(&self, _name: &str) -> Result<Option<Flag>, Error> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(None)
+            }
+        }
+
+        let provider = Cached::new(Counting(AtomicUsize::new(0)), Duration::from_secs(60));
+        assert!(!provider.is_on("a", None).await.unwrap());
+        assert!(!provider.is_on("a", None).await.unwrap());
+        provider.invalidate("a");
+        assert!(provider.get("a").await.unwrap().is_none());
+        assert_eq!(provider.get_provider().0.load(Ordering::SeqCst), 2);
+
+        let provider = EnvProvider::new().prefix(String::from("NO_SUCH_PREFIX_"));
+        assert!(provider.get("new-checkout").await.unwrap().is_none());
+
+        let provider = StaticProvider::new(HashMap::from([(
+            String::from("a"),
+            Flag::on().rollout(0).target(String::from("alice")),
+        )]));
+        assert!(provider.is_on("a", Some("alice")).await.unwrap());
+        assert!(!provider.is_on("a", Some("bob")).await.unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let flags: HashMap<String, Flag> =
+            serde_json::from_str(r#"{"a": true, "b": false, "c": "25%,alice"}"#).unwrap();
+        assert_eq!(flags["a"], Flag::on());
+        assert_eq!(flags["b"], Flag::off());
+        assert_eq!(
+            serde_json::to_string(&flags["c"]).unwrap(),
+            r#""25%,alice""#
+        );
+        assert!(serde_json::from_str::<Flag>("1").is_err());
+    }
+}
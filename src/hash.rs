@@ -0,0 +1,268 @@
+//! Checksums and hashes.
+//!
+//! The hashes here are stable across processes, platforms and Rust versions,
+//! unlike [`DefaultHasher`](std::collections::hash_map::DefaultHasher), so they can be persisted or shared between services:
+//!
+//!   - [`crc32`] checksums data against corruption, like payloads stored in blobs.
+//!   - [`xxh64`] is a fast non-cryptographic hash for cache keys and partitioning.
+//!   - [`Fnv1a`] is a [`Hasher`] for small keys, like sharding keys.
+//!   - [`fingerprint_hash`] identifies statements that only differ in their literal values.
+//!   - `sha256` is a cryptographic hash for content addressing and integrity checks, which requires the `sha256` feature.
+//!
+//! None of them should hash passwords, which `auth::password` is for.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::hash;
+//!
+//! assert_eq!(hash::crc32(b"123456789"), 0xcbf43926);
+//! assert_eq!(hash::xxh64(b"abc", 0), 0x44bc2cf5ad770999);
+//! assert_eq!(
+//!     hash::fingerprint_hash("SELECT * FROM t WHERE id = 1"),
+//!     hash::fingerprint_hash("SELECT * FROM t WHERE id = $1"),
+//! );
+//! ```
+
+use std::hash::Hasher;
+
+use crate::db::fingerprint;
+
+const CRC32_TABLE: [u32; 256] = {
+    // The reflected polynomial of CRC-32/ISO-HDLC, as used by zip, gzip and PNG.
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 checksum of `bytes`, as used by zip, gzip and PNG.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |c, &b| {
+        CRC32_TABLE[((c ^ u32::from(b)) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+const XXH_PRIME64_1: u64 = 0x9e3779b185ebca87;
+const XXH_PRIME64_2: u64 = 0xc2b2ae3d27d4eb4f;
+const XXH_PRIME64_3: u64 = 0x165667b19e3779f9;
+const XXH_PRIME64_4: u64 = 0x85ebca77c2b2ae63;
+const XXH_PRIME64_5: u64 = 0x27d4eb2f165667c5;
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(buf)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[..4]);
+    u32::from_le_bytes(buf)
+}
+
+fn xxh64_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge(acc: u64, v: u64) -> u64 {
+    (acc ^ xxh64_round(0, v))
+        .wrapping_mul(XXH_PRIME64_1)
+        .wrapping_add(XXH_PRIME64_4)
+}
+
+/// Computes the XXH64 hash of `bytes` with `seed`.
+pub fn xxh64(bytes: &[u8], seed: u64) -> u64 {
+    let mut rest = bytes;
+    let mut h = if bytes.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(XXH_PRIME64_1).wrapping_add(XXH_PRIME64_2),
+            seed.wrapping_add(XXH_PRIME64_2),
+            seed,
+            seed.wrapping_sub(XXH_PRIME64_1),
+        ];
+        while rest.len() >= 32 {
+            for (i, acc) in v.iter_mut().enumerate() {
+                *acc = xxh64_round(*acc, read_u64(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let h = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        v.iter().fold(h, |h, &acc| xxh64_merge(h, acc))
+    } else {
+        seed.wrapping_add(XXH_PRIME64_5)
+    };
+    h = h.wrapping_add(bytes.len() as u64);
+    while rest.len() >= 8 {
+        h = (h ^ xxh64_round(0, read_u64(rest)))
+            .rotate_left(27)
+            .wrapping_mul(XXH_PRIME64_1)
+            .wrapping_add(XXH_PRIME64_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        h = (h ^ u64::from(read_u32(rest)).wrapping_mul(XXH_PRIME64_1))
+            .rotate_left(23)
+            .wrapping_mul(XXH_PRIME64_2)
+            .wrapping_add(XXH_PRIME64_3);
+        rest = &rest[4..];
+    }
+    for &b in rest {
+        h = (h ^ u64::from(b).wrapping_mul(XXH_PRIME64_5))
+            .rotate_left(11)
+            .wrapping_mul(XXH_PRIME64_1);
+    }
+    h ^= h >> 33;
+    h = h.wrapping_mul(XXH_PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(XXH_PRIME64_3);
+    h ^ (h >> 32)
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// The 64-bit FNV-1a hasher.
+///
+/// # Examples
+///
+/// ```
+/// use std::hash::Hasher;
+///
+/// use sainnhe_common::hash::Fnv1a;
+///
+/// let mut hasher = Fnv1a::default();
+/// hasher.write(b"a");
+///
+/// assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// Computes the 64-bit FNV-1a hash of `bytes`.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher = Fnv1a::default();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Hashes the [`fingerprint`] of a SQL statement via [`xxh64`],
+/// so statements that only differ in their literal values share the same hash.
+///
+/// The hash is a compact key for aggregating statements, like in metrics labels and slow query reports.
+pub fn fingerprint_hash(stmt: &str) -> u64 {
+    xxh64(fingerprint(stmt).as_bytes(), 0)
+}
+
+/// Computes the SHA-256 digest of `bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::hash;
+///
+/// assert_eq!(
+///     hash::sha256_hex(b"abc"),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+#[cfg(feature = "sha256")]
+pub fn sha256(bytes: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(bytes).into()
+}
+
+/// Computes the SHA-256 digest of `bytes` in lowercase hex.
+#[cfg(feature = "sha256")]
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    sha256(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut s, b| {
+            // Writing to a String never fails.
+            let _ = write!(s, "{:02x}", b);
+            s
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, fnv1a, xxh64};
+
+    #[test]
+    fn test_hashes() {
+        struct TC {
+            bytes: &'static [u8],
+            crc32: u32,
+            xxh64: u64,
+            fnv1a: u64,
+        }
+
+        let test_cases = vec![
+            TC {
+                bytes: b"",
+                crc32: 0,
+                xxh64: 0xef46db3751d8e999,
+                fnv1a: 0xcbf29ce484222325,
+            },
+            TC {
+                bytes: b"a",
+                crc32: 0xe8b7be43,
+                xxh64: 0xd24ec4f1a98c6e5b,
+                fnv1a: 0xaf63dc4c8601ec8c,
+            },
+            TC {
+                bytes: b"The quick brown fox jumps over the lazy dog",
+                crc32: 0x414fa339,
+                xxh64: 0x0b242d361fda71bc,
+                fnv1a: 0xf3f9b7f5e7e47110,
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(crc32(tc.bytes), tc.crc32);
+            assert_eq!(xxh64(tc.bytes, 0), tc.xxh64);
+            assert_eq!(fnv1a(tc.bytes), tc.fnv1a);
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! Health checks.
+//!
+//! Components like database pools, caches and message brokers register async checks into a [`Health`],
+//! which runs them concurrently with a timeout and aggregates their results into a [`Report`] for `/healthz` endpoints.
+//! A failing check makes the service down if it's critical, and degraded otherwise.
+//!
+//! `ping_sqlx` and `ping_redis` check pools and connections with the `sqlx` and `redis` features enabled.
+//! With the `serde` feature enabled, reports are serialized like
+//! `{"status": "degraded", "checks": {"cache": {"status": "down", "latency_ms": 1000, "error": "timed out"}}}`.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::poll_fn,
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// The status of a check or a service.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Status {
+    /// Everything works.
+    Up,
+    /// Optional dependencies fail, so some features may not work.
+    Degraded,
+    /// Critical dependencies fail, so the service can't serve requests.
+    Down,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Up => f.write_str("up"),
+            Status::Degraded => f.write_str("degraded"),
+            Status::Down => f.write_str("down"),
+        }
+    }
+}
+
+/// The result of a check.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CheckResult {
+    status: Status,
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "latency_ms", serialize_with = "serialize_millis")
+    )]
+    latency: Duration,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    error: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+fn serialize_millis<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+}
+
+impl CheckResult {
+    /// Gets status, which is [`Status::Up`] or [`Status::Down`].
+    pub fn get_status(&self) -> Status {
+        self.status
+    }
+
+    /// Gets latency.
+    pub fn get_latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Gets the error of the failed check.
+    pub fn get_error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// The aggregated results of checks.
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    status: Status,
+    checks: BTreeMap<String, CheckResult>,
+}
+
+impl Report {
+    /// Gets the overall status.
+    pub fn get_status(&self) -> Status {
+        self.status
+    }
+
+    /// Gets the results of checks by name.
+    pub fn get_checks(&self) -> &BTreeMap<String, CheckResult> {
+        &self.checks
+    }
+
+    /// Returns the HTTP status code of the report, which is 503 when the service is down and 200 otherwise,
+    /// so load balancers keep routing to degraded services.
+    pub fn http_status(&self) -> u16 {
+        match self.status {
+            Status::Up | Status::Degraded => 200,
+            Status::Down => 503,
+        }
+    }
+}
+
+type CheckFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+struct Entry {
+    name: String,
+    critical: bool,
+    check: Box<dyn Fn() -> CheckFuture + Send + Sync>,
+}
+
+/// Registered health checks.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::health::{Health, Status};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let health = Health::new()
+///     .timeout(Duration::from_millis(100))
+///     .check(String::from("db"), || async { Ok(()) })
+///     .optional_check(String::from("cache"), || async {
+///         tokio::time::sleep(Duration::from_secs(1)).await;
+///         Ok(())
+///     });
+///
+/// let report = health.run().await;
+///
+/// assert_eq!(report.get_status(), Status::Degraded);
+/// assert_eq!(report.get_checks()["cache"].get_error(), Some("timed out"));
+/// assert_eq!(report.http_status(), 200);
+/// # });
+/// ```
+pub struct Health {
+    timeout: Duration,
+    entries: Vec<Entry>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health {
+            timeout: Duration::from_secs(5),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Health")
+            .field("timeout", &self.timeout)
+            .field(
+                "checks",
+                &self.entries.iter().map(|e| &e.name).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Health {
+    /// Creates a new [`Health`] without checks, whose checks time out after 5 seconds.
+    pub fn new() -> Health {
+        Health::default()
+    }
+
+    /// Sets how long a check may take before it fails.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Registers a critical check, whose failure makes the service down.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the dependency, like `db`.
+    /// * `check` - The function starting the check, which returns the error message on failure.
+    pub fn check<F, Fut>(self, name: String, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.entry(name, true, check)
+    }
+
+    /// Registers an optional check, whose failure makes the service degraded.
+    pub fn optional_check<F, Fut>(self, name: String, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.entry(name, false, check)
+    }
+
+    fn entry<F, Fut>(mut self, name: String, critical: bool, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.entries.push(Entry {
+            name,
+            critical,
+            check: Box::new(move || Box::pin(check())),
+        });
+        self
+    }
+
+    /// Gets timeout.
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Runs all checks concurrently and aggregates their results.
+    pub async fn run(&self) -> Report {
+        let mut pending: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                Box::pin(async move {
+                    let start = Instant::now();
+                    let res = tokio::time::timeout(self.timeout, (entry.check)()).await;
+                    let error = match res {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(e),
+                        Err(_) => Some(String::from("timed out")),
+                    };
+                    CheckResult {
+                        status: if error.is_some() {
+                            Status::Down
+                        } else {
+                            Status::Up
+                        },
+                        latency: start.elapsed(),
+                        error,
+                    }
+                })
+            })
+            .collect();
+        let mut results: Vec<Option<CheckResult>> = pending.iter().map(|_| None).collect();
+        poll_fn(|cx| {
+            let mut done = true;
+            for (fut, res) in pending.iter_mut().zip(results.iter_mut()) {
+                if res.is_none() {
+                    match fut.as_mut().poll(cx) {
+                        Poll::Ready(v) => *res = Some(v),
+                        Poll::Pending => done = false,
+                    }
+                }
+            }
+            if done { Poll::Ready(()) } else { Poll::Pending }
+        })
+        .await;
+
+        let mut status = Status::Up;
+        let mut checks = BTreeMap::new();
+        for (entry, res) in self.entries.iter().zip(results.into_iter().flatten()) {
+            if res.status == Status::Down {
+                status = status.max(if entry.critical {
+                    Status::Down
+                } else {
+                    Status::Degraded
+                });
+            }
+            checks.insert(entry.name.clone(), res);
+        }
+        Report { status, checks }
+    }
+}
+
+/// Checks a pool by executing `SELECT 1`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::health::{Health, ping_sqlx};
+/// use sqlx::AnyPool;
+///
+/// # fn f(pool: AnyPool) {
+/// let health = Health::new().check(String::from("db"), move || {
+///     let pool = pool.clone();
+///     async move { ping_sqlx(&pool).await }
+/// });
+/// # }
+/// ```
+#[cfg(feature = "sqlx")]
+pub async fn ping_sqlx(pool: &sqlx::AnyPool) -> Result<(), String> {
+    crate::db::exec::execute(pool, "SELECT 1", &[])
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks a Redis connection by sending `PING`.
+#[cfg(feature = "redis")]
+pub async fn ping_redis(conn: &::redis::aio::ConnectionManager) -> Result<(), String> {
+    ::redis::cmd("PING")
+        .query_async::<()>(&mut conn.clone())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Health, Status};
+
+    #[tokio::test]
+    async fn test_run() {
+        struct TC {
+            critical: Result<(), String>,
+            optional: Result<(), String>,
+            want: Status,
+        }
+
+        let test_cases = vec![
+            TC {
+                critical: Ok(()),
+                optional: Ok(()),
+                want: Status::Up,
+            },
+            TC {
+                critical: Ok(()),
+                optional: Err(String::from("refused")),
+                want: Status::Degraded,
+            },
+            TC {
+                critical: Err(String::from("refused")),
+                optional: Err(String::from("refused")),
+                want: Status::Down,
+            },
+        ];
+
+        for tc in test_cases {
+            let (critical, optional) = (tc.critical.clone(), tc.optional.clone());
+            let health = Health::new()
+                .check(String::from("db"), move || {
+                    let res = critical.clone();
+                    async move { res }
+                })
+                .optional_check(String::from("cache"), move || {
+                    let res = optional.clone();
+                    async move { res }
+                });
+            let report = health.run().await;
+            assert_eq!(report.get_status(), tc.want);
+            assert_eq!(
+                report.get_checks()["cache"].get_error(),
+                tc.optional.err().as_deref()
+            );
+        }
+
+        let report = Health::new().run().await;
+        assert_eq!(report.get_status(), Status::Up);
+        assert_eq!(report.http_status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency() {
+        let sleep = || async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok(())
+        };
+        let health = Health::new()
+            .check(String::from("a"), sleep)
+            .check(String::from("b"), sleep);
+        let start = Instant::now();
+        let report = health.run().await;
+        assert_eq!(report.get_status(), Status::Up);
+        // The checks run concurrently
+        assert!(start.elapsed() < Duration::from_millis(400));
+        assert!(report.get_checks()["a"].get_latency() >= Duration::from_millis(200));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let report = super::Report {
+            status: Status::Down,
+            checks: [(
+                String::from("db"),
+                super::CheckResult {
+                    status: Status::Down,
+                    latency: Duration::from_millis(12),
+                    error: Some(String::from("refused")),
+                },
+            )]
+            .into(),
+        };
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "status": "down",
+                "checks": {"db": {"status": "down", "latency_ms": 12, "error": "refused"}},
+            })
+        );
+    }
+}
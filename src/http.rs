@@ -0,0 +1,359 @@
+//! HTTP client.
+//!
+//! [`Client`] wraps [`reqwest::Client`] with a base URL, a default timeout, retries of idempotent requests,
+//! request ID propagation and tracing spans. Failures are reported as [`AppError`]s,
+//! where error statuses are mapped via [`Code::from_http_status`].
+
+use std::time::{Duration, Instant};
+
+use reqwest::{Method, Response, header::CONTENT_TYPE};
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{Instrument, field};
+
+use crate::{
+    error::{AppError, Code},
+    retry::{self, Policy},
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+const DEFAULT_REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// An HTTP client for a service.
+///
+/// Requests with idempotent methods, which are `GET`, `HEAD`, `PUT`, `DELETE` and `OPTIONS`,
+/// are retried per the retry policy on retryable errors, namely timeouts, connection failures,
+/// and the statuses 408, 429, 502, 503 and 504.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::{http::Client, retry::Policy};
+///
+/// let client = Client::new(String::from("https://api.example.com/v1/"))
+///     .timeout(Duration::from_secs(5))
+///     .policy(Policy::new(5));
+///
+/// assert_eq!(client.url("/users/1"), "https://api.example.com/v1/users/1");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Client {
+    client: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+    policy: Policy,
+    request_id_header: String,
+    request_id: Option<fn() -> Option<String>>,
+}
+
+impl Client {
+    /// Creates a new [`Client`] for the service at `base_url`.
+    ///
+    /// Requests time out after 30 s, and idempotent requests are attempted at most 3 times.
+    pub fn new(base_url: String) -> Client {
+        Client {
+            client: reqwest::Client::new(),
+            base_url,
+            timeout: DEFAULT_TIMEOUT,
+            policy: Policy::new(DEFAULT_MAX_ATTEMPTS),
+            request_id_header: String::from(DEFAULT_REQUEST_ID_HEADER),
+            #[cfg(feature = "web")]
+            request_id: Some(crate::web::request_id),
+            #[cfg(not(feature = "web"))]
+            request_id: None,
+        }
+    }
+
+    /// Sets the timeout of each attempt.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the retry policy of idempotent requests.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the underlying client, for example one with custom TLS roots or default headers.
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sets the header carrying the request ID, which defaults to `x-request-id`.
+    pub fn request_id_header(mut self, request_id_header: String) -> Self {
+        self.request_id_header = request_id_header;
+        self
+    }
+
+    /// Sets the function returning the ID of the request being served, which is propagated to the service.
+    ///
+    /// With the `web` feature enabled, it defaults to [`request_id`](crate::web::request_id).
+    pub fn request_id(mut self, request_id: fn() -> Option<String>) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
+    /// Gets base URL.
+    pub fn get_base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Gets timeout.
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Gets retry policy.
+    pub fn get_policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Resolves `path` against the base URL. Absolute URLs are returned as is.
+    pub fn url(&self, path: &str) -> String {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            return path.to_string();
+        }
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// Sends a request, retrying it if the method is idempotent.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The method.
+    /// * `path` - The path relative to the base URL, or an absolute URL.
+    /// * `body` - The JSON body.
+    ///
+    /// # Returns
+    ///
+    /// * The response with a success status.
+    pub async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<Response, AppError> {
+        let url = self.url(path);
+        let request_id = self.request_id.and_then(|f| f());
+        let idempotent = matches!(
+            method,
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+        );
+        let mut attempt = 0;
+        retry::spawn_if(
+            &self.policy,
+            |e: &AppError| idempotent && e.is_retryable(),
+            async || {
+                attempt += 1;
+                let mut req = self
+                    .client
+                    .request(method.clone(), &url)
+                    .timeout(self.timeout);
+                if let Some(id) = &request_id {
+                    req = req.header(&self.request_id_header, id);
+                }
+                if let Some(body) = &body {
+                    req = req
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(body.clone());
+                }
+                let span = tracing::info_span!(
+                    "http.request",
+                    http.method = %method,
+                    http.url = %url,
+                    http.attempt = attempt,
+                    http.status = field::Empty,
+                    http.duration_ms = field::Empty,
+                    http.error = field::Empty,
+                );
+                let start = Instant::now();
+                let res = req.send().instrument(span.clone()).await;
+                span.record("http.duration_ms", start.elapsed().as_secs_f64() * 1000.0);
+                let resp = match res {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        span.record("http.error", field::display(&e));
+                        return Err(AppError::from(e));
+                    }
+                };
+                let status = resp.status();
+                span.record("http.status", status.as_u16());
+                if status.is_success() {
+                    return Ok(resp);
+                }
+                let code = Code::from_http_status(status.as_u16());
+                let retryable = matches!(status.as_u16(), 408 | 429 | 502 | 503 | 504);
+                Err(
+                    AppError::new(code, format!("{} {} returned {}", method, url, status))
+                        .retryable(retryable),
+                )
+            },
+        )
+        .await
+    }
+
+    /// Sends a `GET` request and deserializes the JSON response.
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, AppError> {
+        let resp = self.send(Method::GET, path, None).await?;
+        decode(resp).await
+    }
+
+    /// Sends a `POST` request with a JSON body and deserializes the JSON response. It's never retried.
+    pub async fn post_json<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, AppError> {
+        let resp = self.send(Method::POST, path, Some(encode(body)?)).await?;
+        decode(resp).await
+    }
+
+    /// Sends a `PUT` request with a JSON body and deserializes the JSON response.
+    pub async fn put_json<B: Serialize + ?Sized, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, AppError> {
+        let resp = self.send(Method::PUT, path, Some(encode(body)?)).await?;
+        decode(resp).await
+    }
+
+    /// Sends a `DELETE` request, ignoring the response body.
+    pub async fn delete(&self, path: &str) -> Result<(), AppError> {
+        self.send(Method::DELETE, path, None).await?;
+        Ok(())
+    }
+}
+
+fn encode<B: Serialize + ?Sized>(body: &B) -> Result<Vec<u8>, AppError> {
+    serde_json::to_vec(body)
+        .map_err(|e| AppError::new(Code::InvalidArgument, e.to_string()).with_source(e))
+}
+
+/// Deserializes a JSON response, where malformed responses are internal errors rather than invalid arguments.
+async fn decode<T: DeserializeOwned>(resp: Response) -> Result<T, AppError> {
+    let body = resp.bytes().await?;
+    serde_json::from_slice(&body).map_err(|e| {
+        AppError::new(Code::Internal, format!("malformed response: {}", e)).with_source(e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use serde::Deserialize;
+
+    use crate::{error::Code, retry::Policy};
+
+    use super::Client;
+
+    /// Serves `responses` in order, one per connection, and sends the received requests through the returned channel.
+    fn serve(responses: Vec<&'static str>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for resp in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut req = String::new();
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        len = v.trim().parse().unwrap();
+                    }
+                    req.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                req.push_str(&String::from_utf8(body).unwrap());
+                tx.send(req).unwrap();
+                stream.write_all(resp.as_bytes()).unwrap();
+            }
+        });
+        (format!("http://{}", addr), rx)
+    }
+
+    fn response(status: &str, body: &str) -> &'static str {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .leak()
+    }
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    struct User {
+        id: u32,
+    }
+
+    #[test]
+    fn test_url() {
+        let client = Client::new(String::from("http://a/v1/"));
+        assert_eq!(client.url("users"), "http://a/v1/users");
+        assert_eq!(client.url("/users"), "http://a/v1/users");
+        assert_eq!(client.url("https://b/x"), "https://b/x");
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let (base_url, requests) = serve(vec![
+            response("503 Service Unavailable", ""),
+            response("200 OK", r#"{"id":1}"#),
+            response("503 Service Unavailable", ""),
+            response("404 Not Found", ""),
+        ]);
+        let client = Client::new(base_url)
+            .policy(Policy::constant(2, Duration::ZERO))
+            .request_id(|| Some(String::from("req-1")));
+
+        // GET is retried
+        let user: User = client.get_json("/users/1").await.unwrap();
+        assert_eq!(user, User { id: 1 });
+        let req = requests.recv().unwrap();
+        assert!(req.starts_with("GET /users/1 HTTP/1.1\r\n"));
+        assert!(req.contains("x-request-id: req-1\r\n"));
+        requests.recv().unwrap();
+
+        // POST is not retried
+        let e = client
+            .post_json::<_, User>("/users", &serde_json::json!({"name": "a"}))
+            .await
+            .unwrap_err();
+        assert_eq!(e.get_code(), Code::Unavailable);
+        assert!(e.is_retryable());
+        assert!(requests.recv().unwrap().ends_with(r#"{"name":"a"}"#));
+
+        let e = client.delete("/users/2").await.unwrap_err();
+        assert_eq!(e.get_code(), Code::NotFound);
+        assert_eq!(
+            e.get_message(),
+            format!("DELETE {} returned 404 Not Found", client.url("/users/2"))
+        );
+    }
+}
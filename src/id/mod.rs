@@ -0,0 +1,106 @@
+//! ID generators for primary keys.
+//!
+//! [`Snowflake`] generates `i64` IDs that fit into `BIGINT` columns.
+//! With the `random-id` feature enabled, [`Ulid`] and [`UuidV7`] generate 128-bit IDs without coordination,
+//! see [`sql_type`] for how to store them.
+
+mod snowflake;
+#[cfg(feature = "random-id")]
+mod ulid;
+#[cfg(feature = "random-id")]
+mod uuid;
+
+pub use snowflake::{Error, Parts, Snowflake};
+#[cfg(feature = "random-id")]
+pub use ulid::Ulid;
+#[cfg(feature = "random-id")]
+pub use uuid::UuidV7;
+
+#[cfg(feature = "random-id")]
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "random-id")]
+use crate::db::{Type, Value};
+
+/// Error returned when parsing a [`Ulid`] or a [`UuidV7`].
+#[cfg(feature = "random-id")]
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum ParseError {
+    /// The string has an unexpected length.
+    Length(usize),
+    /// The string contains an unexpected character.
+    Char(char),
+    /// The UUID is not a version 7 UUID.
+    Version,
+}
+
+#[cfg(feature = "random-id")]
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Length(len) => write!(f, "unexpected length {}", len),
+            ParseError::Char(c) => write!(f, "unexpected character {:?}", c),
+            ParseError::Version => write!(f, "not a version 7 UUID"),
+        }
+    }
+}
+
+#[cfg(feature = "random-id")]
+impl std::error::Error for ParseError {}
+
+/// Returns the column type that stores 128-bit IDs in a time-sortable way.
+///
+/// MySQL stores the raw bytes in `BINARY(16)`, PostgreSQL uses the native `UUID` type,
+/// and SQLite stores the canonical string in `TEXT`.
+/// ULIDs are rendered in the UUID format on PostgreSQL.
+///
+/// Note that sqlx binds strings as `TEXT`, so when binding to `UUID` columns on PostgreSQL,
+/// cast the placeholder explicitly, for example `CAST($1 AS UUID)`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{db::Type, id::sql_type};
+///
+/// assert_eq!(sql_type(Type::MySQL), "BINARY(16)");
+/// ```
+#[cfg(feature = "random-id")]
+pub fn sql_type(typ: Type) -> &'static str {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => "BINARY(16)",
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => "UUID",
+        Type::SQLite => "TEXT",
+    }
+}
+
+#[cfg(feature = "random-id")]
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Converts 128-bit IDs into values stored in the columns of [`sql_type`].
+///
+/// `text` is the canonical string of the ID, which is stored as is on SQLite.
+#[cfg(feature = "random-id")]
+fn to_value(bits: u128, text: String, typ: Type) -> Value {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => Value::Bytes(bits.to_be_bytes().to_vec()),
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => {
+            let hex = format!("{:032x}", bits);
+            Value::Text(format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            ))
+        }
+        Type::SQLite => Value::Text(text),
+    }
+}
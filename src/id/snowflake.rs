@@ -0,0 +1,285 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The number of bits of the timestamp, which covers about 69 years since the epoch.
+const TIMESTAMP_BITS: u8 = 41;
+
+/// The number of bits shared by the worker id and the sequence number.
+const NODE_BITS: u8 = 63 - TIMESTAMP_BITS;
+
+/// 2024-01-01T00:00:00Z in milliseconds since the Unix epoch.
+const DEFAULT_EPOCH_MS: u64 = 1_704_067_200_000;
+
+const DEFAULT_MAX_DRIFT: Duration = Duration::from_millis(5);
+
+/// Errors returned by [`Snowflake`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The worker id doesn't fit into the worker bits.
+    WorkerId { worker_id: u64, worker_bits: u8 },
+    /// The worker bits leave no room for the sequence number.
+    WorkerBits(u8),
+    /// The clock moved backwards by more than the tolerated drift.
+    ClockMovedBackwards(Duration),
+    /// The timestamp doesn't fit into the ID, because the clock is before the epoch or too far after it.
+    Timestamp(u64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WorkerId {
+                worker_id,
+                worker_bits,
+            } => write!(
+                f,
+                "worker id {} doesn't fit into {} bits",
+                worker_id, worker_bits
+            ),
+            Error::WorkerBits(bits) => write!(
+                f,
+                "{} worker bits exceed the limit of {}",
+                bits,
+                NODE_BITS - 1
+            ),
+            Error::ClockMovedBackwards(drift) => {
+                write!(f, "clock moved backwards by {:?}", drift)
+            }
+            Error::Timestamp(ms) => write!(f, "timestamp {} ms is out of range", ms),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The parts of a Snowflake ID, see [`Snowflake::decompose`].
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub struct Parts {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    pub worker_id: u64,
+    pub seq: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last_ms: u64,
+    seq: u64,
+}
+
+/// A Snowflake-style ID generator.
+///
+/// An ID is a positive `i64` composed of 41 bits of milliseconds since the epoch,
+/// followed by the worker id and a per-millisecond sequence number,
+/// so IDs are unique across workers and sortable by creation time.
+///
+/// If the clock moves backwards by no more than the tolerated drift, the generator waits for it to catch up.
+/// Otherwise it fails rather than risking duplicate IDs.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::id::Snowflake;
+///
+/// let snowflake = Snowflake::new(1, 10).unwrap();
+///
+/// let a = snowflake.next_id().unwrap();
+/// let b = snowflake.next_id().unwrap();
+///
+/// assert!(a < b);
+/// assert_eq!(snowflake.decompose(b).worker_id, 1);
+/// ```
+#[derive(Debug)]
+pub struct Snowflake {
+    epoch_ms: u64,
+    worker_id: u64,
+    worker_bits: u8,
+    max_drift: Duration,
+    state: Mutex<State>,
+}
+
+impl Snowflake {
+    /// Creates a new [`Snowflake`].
+    ///
+    /// # Arguments
+    ///
+    /// * `worker_id` - The id of this generator, which must be unique among all running generators.
+    /// * `worker_bits` - The number of bits of the worker id. The remaining `22 - worker_bits` bits
+    ///   are used by the sequence number, which limits the IDs generated per millisecond.
+    ///
+    /// # Returns
+    ///
+    /// * The generator, which uses 2024-01-01T00:00:00Z as the epoch and tolerates a clock drift of 5 ms.
+    pub fn new(worker_id: u64, worker_bits: u8) -> Result<Snowflake, Error> {
+        if worker_bits >= NODE_BITS {
+            return Err(Error::WorkerBits(worker_bits));
+        }
+        if worker_id >> worker_bits != 0 {
+            return Err(Error::WorkerId {
+                worker_id,
+                worker_bits,
+            });
+        }
+        Ok(Snowflake {
+            epoch_ms: DEFAULT_EPOCH_MS,
+            worker_id,
+            worker_bits,
+            max_drift: DEFAULT_MAX_DRIFT,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Sets the custom epoch in milliseconds since the Unix epoch.
+    ///
+    /// Changing the epoch of existing generators breaks the uniqueness and ordering of IDs.
+    pub fn epoch(mut self, epoch_ms: u64) -> Self {
+        self.epoch_ms = epoch_ms;
+        self
+    }
+
+    /// Sets how far the clock may move backwards before [`Snowflake::next_id`] fails.
+    pub fn max_drift(mut self, max_drift: Duration) -> Self {
+        self.max_drift = max_drift;
+        self
+    }
+
+    /// Gets worker id.
+    pub fn get_worker_id(&self) -> u64 {
+        self.worker_id
+    }
+
+    fn seq_bits(&self) -> u8 {
+        NODE_BITS - self.worker_bits
+    }
+
+    /// Generates the next ID.
+    pub fn next_id(&self) -> Result<i64, Error> {
+        self.next_id_with(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64)
+        })
+    }
+
+    /// Generates the next ID with the clock `now`, which returns milliseconds since the Unix epoch.
+    fn next_id_with<F: FnMut() -> u64>(&self, mut now: F) -> Result<i64, Error> {
+        let max_seq = (1 << self.seq_bits()) - 1;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut ms = now();
+        if ms < state.last_ms {
+            let drift = Duration::from_millis(state.last_ms - ms);
+            if drift > self.max_drift {
+                return Err(Error::ClockMovedBackwards(drift));
+            }
+            while ms < state.last_ms {
+                thread::sleep(Duration::from_millis(state.last_ms - ms));
+                ms = now();
+            }
+        }
+        if ms == state.last_ms {
+            state.seq = (state.seq + 1) & max_seq;
+            // The sequence is exhausted, so wait for the next millisecond.
+            if state.seq == 0 {
+                while ms <= state.last_ms {
+                    thread::yield_now();
+                    ms = now();
+                }
+            }
+        } else {
+            state.seq = 0;
+        }
+        state.last_ms = ms;
+
+        let ts = ms
+            .checked_sub(self.epoch_ms)
+            .filter(|ts| ts >> TIMESTAMP_BITS == 0)
+            .ok_or(Error::Timestamp(ms))?;
+        let id = (ts << NODE_BITS) | (self.worker_id << self.seq_bits()) | state.seq;
+        Ok(id as i64)
+    }
+
+    /// Splits an ID generated by this generator into its parts.
+    pub fn decompose(&self, id: i64) -> Parts {
+        let id = id as u64;
+        Parts {
+            timestamp_ms: (id >> NODE_BITS) + self.epoch_ms,
+            worker_id: (id >> self.seq_bits()) & ((1 << self.worker_bits) - 1),
+            seq: id & ((1 << self.seq_bits()) - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, time::Duration};
+
+    use super::{DEFAULT_EPOCH_MS, Error, Parts, Snowflake};
+
+    #[test]
+    fn test_new() {
+        assert!(Snowflake::new(1023, 10).is_ok());
+        assert_eq!(
+            Snowflake::new(1024, 10).unwrap_err(),
+            Error::WorkerId {
+                worker_id: 1024,
+                worker_bits: 10
+            }
+        );
+        assert_eq!(Snowflake::new(0, 22).unwrap_err(), Error::WorkerBits(22));
+    }
+
+    #[test]
+    fn test_next_id() {
+        let snowflake = Snowflake::new(3, 20).unwrap().epoch(1000);
+        assert_eq!(snowflake.get_worker_id(), 3);
+        let clock = Cell::new(1010);
+        let now = || clock.get();
+
+        // The sequence has 2 bits, so 4 IDs fit into a millisecond.
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            ids.push(snowflake.next_id_with(now).unwrap());
+        }
+        assert_eq!(
+            snowflake.decompose(ids[3]),
+            Parts {
+                timestamp_ms: 1010,
+                worker_id: 3,
+                seq: 3
+            }
+        );
+        // The 5th ID waits for the next millisecond
+        let mut ticks = 0;
+        let id = snowflake
+            .next_id_with(|| {
+                ticks += 1;
+                1010 + ticks / 3
+            })
+            .unwrap();
+        assert_eq!(snowflake.decompose(id).timestamp_ms, 1011);
+        assert_eq!(snowflake.decompose(id).seq, 0);
+        ids.push(id);
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+        // Small drifts are waited out, large ones are rejected
+        let snowflake = snowflake.max_drift(Duration::from_millis(2));
+        let mut clock = [1009, 1011].into_iter();
+        let id = snowflake.next_id_with(|| clock.next().unwrap()).unwrap();
+        assert_eq!(snowflake.decompose(id).seq, 1);
+        assert_eq!(
+            snowflake.next_id_with(|| 1000),
+            Err(Error::ClockMovedBackwards(Duration::from_millis(11)))
+        );
+
+        // Before the epoch
+        let snowflake = Snowflake::new(0, 10).unwrap();
+        assert_eq!(
+            snowflake.next_id_with(|| DEFAULT_EPOCH_MS - 1),
+            Err(Error::Timestamp(DEFAULT_EPOCH_MS - 1))
+        );
+    }
+}
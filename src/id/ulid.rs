@@ -0,0 +1,154 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    db::{Type, Value},
+    id::{ParseError, now_ms, to_value},
+};
+
+/// Crockford's Base32 alphabet, which excludes I, L, O and U.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+const LEN: usize = 26;
+
+const RANDOM_BITS: u32 = 80;
+
+/// A ULID, which is 48 bits of milliseconds since the Unix epoch followed by 80 random bits.
+///
+/// The canonical form is 26 characters of Crockford's Base32, which sort in creation order.
+/// See [`sql_type`](crate::id::sql_type) for how ULIDs are stored.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{db::Type, id::Ulid};
+///
+/// let ulid = Ulid::from_parts(1_700_000_000_000, 42);
+///
+/// assert_eq!(ulid.to_string(), "01HF7YAT00000000000000001A");
+/// assert_eq!(ulid.to_string().parse::<Ulid>(), Ok(ulid));
+/// assert_eq!(ulid.timestamp_ms(), 1_700_000_000_000);
+/// assert_eq!(
+///     ulid.to_literal(Type::PostgreSQL),
+///     "'018bcfe5-6800-0000-0000-00000000002a'"
+/// );
+/// assert!(Ulid::generate() < Ulid::from_parts(u64::MAX, 0));
+/// ```
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub struct Ulid(u128);
+
+impl Ulid {
+    /// Generates a ULID from the current time and random bits.
+    pub fn generate() -> Ulid {
+        Ulid::from_parts(now_ms(), rand::random())
+    }
+
+    /// Creates a ULID from the timestamp `ms` and the random bits `random`.
+    /// Only the lower 48 bits of `ms` and the lower 80 bits of `random` are used.
+    pub fn from_parts(ms: u64, random: u128) -> Ulid {
+        let ms = u128::from(ms) & ((1 << 48) - 1);
+        Ulid((ms << RANDOM_BITS) | (random & ((1 << RANDOM_BITS) - 1)))
+    }
+
+    /// Returns the timestamp in milliseconds since the Unix epoch.
+    pub fn timestamp_ms(&self) -> u64 {
+        (self.0 >> RANDOM_BITS) as u64
+    }
+
+    /// Returns the 128 bits of the ULID.
+    pub fn to_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// Returns the bytes of the ULID in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Converts the ULID into a value bound to placeholders.
+    pub fn to_value(&self, typ: Type) -> Value {
+        to_value(self.0, self.to_string(), typ)
+    }
+
+    /// Renders the ULID as a SQL literal.
+    pub fn to_literal(&self, typ: Type) -> String {
+        self.to_value(typ).to_literal(typ)
+    }
+}
+
+impl fmt::Display for Ulid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buf = [0_u8; LEN];
+        for (i, b) in buf.iter_mut().enumerate() {
+            let shift = 5 * (LEN - 1 - i);
+            *b = ALPHABET[((self.0 >> shift) & 31) as usize];
+        }
+        // The alphabet is ASCII.
+        f.write_str(std::str::from_utf8(&buf).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for Ulid {
+    type Err = ParseError;
+
+    /// Parses a ULID case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != LEN {
+            return Err(ParseError::Length(s.len()));
+        }
+        let mut v: u128 = 0;
+        for (i, c) in s.chars().enumerate() {
+            let digit = ALPHABET
+                .iter()
+                .position(|&a| char::from(a) == c.to_ascii_uppercase())
+                .ok_or(ParseError::Char(c))?;
+            // The first character only carries 3 bits.
+            if i == 0 && digit > 7 {
+                return Err(ParseError::Char(c));
+            }
+            v = (v << 5) | digit as u128;
+        }
+        Ok(Ulid(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{db::Type, db::Value, id::ParseError};
+
+    use super::Ulid;
+
+    #[test]
+    fn test_ulid() {
+        let ulid = Ulid::from_parts(u64::MAX, u128::MAX);
+        assert_eq!(ulid.to_string(), "7ZZZZZZZZZZZZZZZZZZZZZZZZZ");
+        assert_eq!(ulid.timestamp_ms(), (1 << 48) - 1);
+        assert_eq!(
+            Ulid::from_parts(0, 0).to_string(),
+            "00000000000000000000000000"
+        );
+
+        let ulid = Ulid::generate();
+        assert_eq!(ulid.to_string().to_lowercase().parse::<Ulid>(), Ok(ulid));
+        assert_eq!(ulid.to_bytes(), ulid.to_u128().to_be_bytes());
+        assert_eq!(
+            ulid.to_value(Type::MySQL),
+            Value::Bytes(ulid.to_bytes().to_vec())
+        );
+
+        assert_eq!("0".parse::<Ulid>(), Err(ParseError::Length(1)));
+        assert_eq!(
+            "8ZZZZZZZZZZZZZZZZZZZZZZZZZ".parse::<Ulid>(),
+            Err(ParseError::Char('8'))
+        );
+        assert_eq!(
+            "0000000000000000000000000U".parse::<Ulid>(),
+            Err(ParseError::Char('U'))
+        );
+
+        // Sorted by time
+        let a = Ulid::from_parts(1, u128::MAX);
+        let b = Ulid::from_parts(2, 0);
+        assert!(a < b);
+        assert!(a.to_string() < b.to_string());
+    }
+}
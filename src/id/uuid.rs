@@ -0,0 +1,148 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    db::{Type, Value},
+    id::{ParseError, now_ms, to_value},
+};
+
+/// The positions of the hyphens in the hyphenated format.
+const HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
+const LEN: usize = 36;
+
+/// A version 7 UUID, which is 48 bits of milliseconds since the Unix epoch followed by random bits,
+/// see RFC 9562.
+///
+/// See [`sql_type`](crate::id::sql_type) for how UUIDs are stored.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{db::Type, id::UuidV7};
+///
+/// let uuid = UuidV7::from_parts(1_700_000_000_000, 0);
+///
+/// assert_eq!(uuid.to_string(), "018bcfe5-6800-7000-8000-000000000000");
+/// assert_eq!(uuid.to_string().parse::<UuidV7>(), Ok(uuid));
+/// assert_eq!(uuid.timestamp_ms(), 1_700_000_000_000);
+/// assert_eq!(uuid.to_literal(Type::MySQL), "X'018bcfe5680070008000000000000000'");
+/// ```
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub struct UuidV7(u128);
+
+impl UuidV7 {
+    /// Generates a UUID from the current time and random bits.
+    pub fn generate() -> UuidV7 {
+        UuidV7::from_parts(now_ms(), rand::random())
+    }
+
+    /// Creates a UUID from the timestamp `ms` and the random bits `random`.
+    /// Only the lower 48 bits of `ms` and the lower 74 bits of `random` are used.
+    pub fn from_parts(ms: u64, random: u128) -> UuidV7 {
+        let ms = u128::from(ms) & ((1 << 48) - 1);
+        let rand_a = (random >> 62) & 0xfff;
+        let rand_b = random & ((1 << 62) - 1);
+        UuidV7((ms << 80) | (0x7 << 76) | (rand_a << 64) | (0b10 << 62) | rand_b)
+    }
+
+    /// Returns the timestamp in milliseconds since the Unix epoch.
+    pub fn timestamp_ms(&self) -> u64 {
+        (self.0 >> 80) as u64
+    }
+
+    /// Returns the 128 bits of the UUID.
+    pub fn to_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// Returns the bytes of the UUID in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    /// Converts the UUID into a value bound to placeholders.
+    pub fn to_value(&self, typ: Type) -> Value {
+        to_value(self.0, self.to_string(), typ)
+    }
+
+    /// Renders the UUID as a SQL literal.
+    pub fn to_literal(&self, typ: Type) -> String {
+        self.to_value(typ).to_literal(typ)
+    }
+}
+
+impl fmt::Display for UuidV7 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl FromStr for UuidV7 {
+    type Err = ParseError;
+
+    /// Parses a UUID in the hyphenated format case-insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != LEN {
+            return Err(ParseError::Length(s.len()));
+        }
+        let mut v: u128 = 0;
+        for (i, c) in s.chars().enumerate() {
+            if HYPHENS.contains(&i) {
+                if c != '-' {
+                    return Err(ParseError::Char(c));
+                }
+                continue;
+            }
+            let digit = c.to_digit(16).ok_or(ParseError::Char(c))?;
+            v = (v << 4) | u128::from(digit);
+        }
+        if (v >> 76) & 0xf != 7 || (v >> 62) & 0b11 != 0b10 {
+            return Err(ParseError::Version);
+        }
+        Ok(UuidV7(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{db::Type, id::ParseError};
+
+    use super::UuidV7;
+
+    #[test]
+    fn test_uuid_v7() {
+        let uuid = UuidV7::from_parts(u64::MAX, u128::MAX);
+        assert_eq!(uuid.to_string(), "ffffffff-ffff-7fff-bfff-ffffffffffff");
+        assert_eq!(uuid.timestamp_ms(), (1 << 48) - 1);
+
+        let uuid = UuidV7::generate();
+        assert_eq!(uuid.to_string().to_uppercase().parse::<UuidV7>(), Ok(uuid));
+        assert_eq!(uuid.to_bytes(), uuid.to_u128().to_be_bytes());
+        assert_eq!(uuid.to_literal(Type::SQLite), format!("'{}'", uuid));
+
+        assert_eq!("".parse::<UuidV7>(), Err(ParseError::Length(0)));
+        assert_eq!(
+            "018bcfe5_6800-7000-8000-000000000000".parse::<UuidV7>(),
+            Err(ParseError::Char('_'))
+        );
+        assert_eq!(
+            "018bcfe5-6800-4000-8000-000000000000".parse::<UuidV7>(),
+            Err(ParseError::Version)
+        );
+
+        // Sorted by time
+        let a = UuidV7::from_parts(1, u128::MAX);
+        let b = UuidV7::from_parts(2, 0);
+        assert!(a < b);
+        assert!(a.to_string() < b.to_string());
+    }
+}
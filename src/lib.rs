@@ -4,4 +4,38 @@
 //!
 //! Read the documentation for each module for details.
 
+#[cfg(any(feature = "jwt", feature = "password"))]
+pub mod auth;
+pub mod cache;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "aead")]
+pub mod crypto;
 pub mod db;
+pub mod encoding;
+pub mod env;
+pub mod error;
+pub mod flags;
+pub mod hash;
+#[cfg(feature = "health")]
+pub mod health;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod id;
+#[cfg(feature = "money")]
+pub mod money;
+#[cfg(feature = "mq")]
+pub mod mq;
+pub mod page;
+pub mod rate_limit;
+pub mod redact;
+#[cfg(feature = "retry")]
+pub mod retry;
+pub mod secrets;
+#[cfg(feature = "serde")]
+pub mod serde_util;
+pub mod storage;
+pub mod time;
+pub mod validate;
+#[cfg(feature = "web")]
+pub mod web;
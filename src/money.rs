@@ -0,0 +1,412 @@
+//! Monetary amounts.
+//!
+//! [`Money`] is an exact decimal amount in a currency, so prices never go through floats.
+//! Arithmetic is checked, and amounts of different currencies can't be mixed.
+//!
+//! Amounts are bound as text, which keeps them exact, so wrap their placeholders via [`numeric_placeholder`]
+//! to compare or store them as numbers. Literals are rendered via [`Money::to_literal`].
+//! With the `serde` feature enabled, amounts are (de)serialized as strings,
+//! like `{"amount": "12.30", "currency": "USD"}`.
+
+use std::{fmt, str::FromStr};
+
+pub use rust_decimal::Decimal;
+use rust_decimal::RoundingStrategy;
+
+use crate::db::{Type, Value};
+
+/// Errors returned by this module.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The currency code is not 3 uppercase ASCII letters.
+    InvalidCurrency(String),
+    /// The amount is not a decimal number.
+    InvalidAmount(String),
+    /// The operands are in different currencies.
+    CurrencyMismatch(Currency, Currency),
+    /// The result is out of the range of [`Decimal`].
+    Overflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidCurrency(s) => write!(f, "invalid currency {}", s),
+            Error::InvalidAmount(s) => write!(f, "invalid amount {}", s),
+            Error::CurrencyMismatch(a, b) => write!(f, "currency mismatch: {} and {}", a, b),
+            Error::Overflow => write!(f, "amount overflow"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An ISO 4217 currency code, like `USD`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    /// Creates a new [`Currency`] from a code of 3 uppercase ASCII letters.
+    pub fn new(code: &str) -> Result<Currency, Error> {
+        match code.as_bytes() {
+            &[a, b, c] if [a, b, c].iter().all(u8::is_ascii_uppercase) => Ok(Currency([a, b, c])),
+            _ => Err(Error::InvalidCurrency(code.to_string())),
+        }
+    }
+
+    /// Returns the code.
+    pub fn as_str(&self) -> &str {
+        // The bytes are validated to be ASCII letters.
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    /// Returns the number of digits after the decimal separator of the minor unit, like 2 for cents of `USD`.
+    ///
+    /// Currencies not listed by ISO 4217 with other minor units are assumed to use 2 digits.
+    pub fn minor_units(&self) -> u32 {
+        match self.as_str() {
+            "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF"
+            | "UGX" | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+            "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+            "CLF" | "UYW" => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::new(s)
+    }
+}
+
+/// An exact amount of money in a currency.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{
+///     db::Type,
+///     money::{Currency, Decimal, Money},
+/// };
+///
+/// let usd = Currency::new("USD").unwrap();
+/// let price = Money::new("19.99".parse().unwrap(), usd);
+/// let total = price
+///     .checked_mul(Decimal::from(3))
+///     .unwrap()
+///     .checked_add(&"0.03 USD".parse().unwrap())
+///     .unwrap();
+///
+/// assert_eq!(total.to_string(), "60.00 USD");
+/// assert_eq!(total.to_literal(Type::PostgreSQL), "60.00");
+/// assert!(total.checked_add(&"1 JPY".parse().unwrap()).is_err());
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct Money {
+    amount: Decimal,
+    currency: Currency,
+}
+
+impl Money {
+    /// Creates a new [`Money`].
+    pub fn new(amount: Decimal, currency: Currency) -> Money {
+        Money { amount, currency }
+    }
+
+    /// Creates a new [`Money`] of zero.
+    pub fn zero(currency: Currency) -> Money {
+        Money::new(Decimal::ZERO, currency)
+    }
+
+    /// Creates a new [`Money`] from an amount in the minor unit, like cents.
+    pub fn from_minor(minor: i64, currency: Currency) -> Money {
+        Money::new(Decimal::new(minor, currency.minor_units()), currency)
+    }
+
+    /// Gets amount.
+    pub fn get_amount(&self) -> Decimal {
+        self.amount
+    }
+
+    /// Gets currency.
+    pub fn get_currency(&self) -> Currency {
+        self.currency
+    }
+
+    /// Returns whether the amount is zero.
+    pub fn is_zero(&self) -> bool {
+        self.amount.is_zero()
+    }
+
+    /// Returns whether the amount is negative.
+    pub fn is_negative(&self) -> bool {
+        self.amount.is_sign_negative() && !self.amount.is_zero()
+    }
+
+    fn check_currency(&self, other: &Money) -> Result<(), Error> {
+        if self.currency != other.currency {
+            return Err(Error::CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(())
+    }
+
+    /// Adds `other`, which must be in the same currency.
+    pub fn checked_add(&self, other: &Money) -> Result<Money, Error> {
+        self.check_currency(other)?;
+        let amount = self
+            .amount
+            .checked_add(other.amount)
+            .ok_or(Error::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    /// Subtracts `other`, which must be in the same currency.
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, Error> {
+        self.check_currency(other)?;
+        let amount = self
+            .amount
+            .checked_sub(other.amount)
+            .ok_or(Error::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    /// Multiplies by `factor`, like a quantity or a tax rate. Round the result via [`Money::round`] before storing it.
+    pub fn checked_mul(&self, factor: Decimal) -> Result<Money, Error> {
+        let amount = self.amount.checked_mul(factor).ok_or(Error::Overflow)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    /// Rounds to the minor unit of the currency, rounding half to even, a.k.a. banker's rounding.
+    pub fn round(&self) -> Money {
+        let amount = self.amount.round_dp_with_strategy(
+            self.currency.minor_units(),
+            RoundingStrategy::MidpointNearestEven,
+        );
+        Money::new(amount, self.currency)
+    }
+
+    /// Splits into `n` parts that differ by at most one minor unit and sum up to the amount rounded via [`Money::round`],
+    /// where the earlier parts are the larger ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::money::Money;
+    ///
+    /// let parts = "10.00 USD".parse::<Money>().unwrap().split(3);
+    ///
+    /// assert_eq!(parts.iter().map(ToString::to_string).collect::<Vec<_>>(), ["3.34 USD", "3.33 USD", "3.33 USD"]);
+    /// ```
+    pub fn split(&self, n: u32) -> Vec<Money> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let units = self.currency.minor_units();
+        let unit = Decimal::new(1, units);
+        let total = self.round().amount;
+        let base =
+            (total / Decimal::from(n)).round_dp_with_strategy(units, RoundingStrategy::ToZero);
+        let mut rest = total - base * Decimal::from(n);
+        let step = if rest.is_sign_negative() { -unit } else { unit };
+        (0..n)
+            .map(|_| {
+                let mut amount = base;
+                if !rest.is_zero() {
+                    amount += step;
+                    rest -= step;
+                }
+                Money::new(amount, self.currency)
+            })
+            .collect()
+    }
+
+    /// Renders the amount as a SQL literal of the given database type.
+    ///
+    /// MySQL and PostgreSQL treat decimal literals as exact `DECIMAL` / `NUMERIC` values.
+    /// SQLite has no decimal type, so the amount is rendered as a string,
+    /// which is stored exactly in `TEXT` columns.
+    pub fn to_literal(&self, typ: Type) -> String {
+        match typ {
+            Type::MySQL
+            | Type::MariaDB
+            | Type::TiDB
+            | Type::PostgreSQL
+            | Type::CockroachDB
+            | Type::DuckDB => self.amount.to_string(),
+            Type::SQLite => format!("'{}'", self.amount),
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    /// Formats as the amount followed by the currency, like `12.30 USD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+impl FromStr for Money {
+    type Err = Error;
+
+    /// Parses the amount followed by the currency, like `12.30 USD`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (amount, currency) = s
+            .trim()
+            .rsplit_once(' ')
+            .ok_or_else(|| Error::InvalidAmount(s.to_string()))?;
+        let amount = Decimal::from_str(amount.trim())
+            .map_err(|_| Error::InvalidAmount(amount.to_string()))?;
+        Ok(Money::new(amount, Currency::new(currency)?))
+    }
+}
+
+/// Converts the amount into text, which keeps it exact. The currency is dropped.
+impl From<&Money> for Value {
+    fn from(v: &Money) -> Self {
+        Value::Text(v.amount.to_string())
+    }
+}
+
+/// Converts the amount into text, which keeps it exact. The currency is dropped.
+impl From<Money> for Value {
+    fn from(v: Money) -> Self {
+        Value::from(&v)
+    }
+}
+
+/// Wraps a placeholder bound to an amount, so the text is converted into an exact number.
+///
+/// PostgreSQL rejects text for `NUMERIC` columns, and MySQL compares text with numbers as floats.
+/// SQLite has no decimal type, so the placeholder is returned as is.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{db::Type, money::numeric_placeholder};
+///
+/// assert_eq!(numeric_placeholder(Type::MySQL, "?"), "CAST(? AS DECIMAL(65, 30))");
+/// assert_eq!(numeric_placeholder(Type::PostgreSQL, "$1"), "CAST($1 AS NUMERIC)");
+/// assert_eq!(numeric_placeholder(Type::SQLite, "?"), "?");
+/// ```
+pub fn numeric_placeholder(typ: Type, placeholder: &str) -> String {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => {
+            format!("CAST({} AS DECIMAL(65, 30))", placeholder)
+        }
+        Type::PostgreSQL | Type::CockroachDB => format!("CAST({} AS NUMERIC)", placeholder),
+        // DuckDB decimals have at most 38 digits.
+        Type::DuckDB => format!("CAST({} AS DECIMAL(38, 18))", placeholder),
+        Type::SQLite => placeholder.to_string(),
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    use super::{Currency, Decimal, Money};
+
+    #[derive(Serialize, Deserialize)]
+    struct Repr {
+        amount: String,
+        currency: String,
+    }
+
+    impl Serialize for Money {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            Repr {
+                amount: self.amount.to_string(),
+                currency: self.currency.to_string(),
+            }
+            .serialize(s)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Money {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let repr = Repr::deserialize(d)?;
+            let amount: Decimal = repr
+                .amount
+                .parse()
+                .map_err(|_| de::Error::custom(format!("invalid amount {}", repr.amount)))?;
+            let currency = Currency::new(&repr.currency).map_err(de::Error::custom)?;
+            Ok(Money::new(amount, currency))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Type, Value};
+
+    use super::{Currency, Decimal, Error, Money};
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let usd = Currency::new("USD").unwrap();
+        assert_eq!(Money::from_minor(1999, usd), money("19.99 USD"));
+        assert_eq!(
+            money("0.10 USD").checked_add(&money("0.20 USD")).unwrap(),
+            money("0.30 USD")
+        );
+        assert!(
+            money("1 USD")
+                .checked_sub(&money("2 USD"))
+                .unwrap()
+                .is_negative()
+        );
+        assert_eq!(
+            money("1 USD").checked_add(&money("1 EUR")),
+            Err(Error::CurrencyMismatch(usd, Currency::new("EUR").unwrap()))
+        );
+        assert_eq!(
+            Money::new(Decimal::MAX, usd).checked_add(&money("1 USD")),
+            Err(Error::Overflow)
+        );
+        assert_eq!(money("2.345 USD").round(), money("2.34 USD"));
+        assert_eq!(money("2.5 JPY").round(), money("2 JPY"));
+        assert_eq!(
+            money("-10 USD").split(3),
+            [money("-3.34 USD"), money("-3.33 USD"), money("-3.33 USD")]
+        );
+        assert!(money("1 USD").split(0).is_empty());
+
+        for s in ["1 usd", "1 US", "x USD", "1"] {
+            assert!(s.parse::<Money>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_value() {
+        let m = money("12.30 USD");
+        assert_eq!(Value::from(m), Value::from("12.30"));
+        assert_eq!(m.to_literal(Type::MySQL), "12.30");
+        assert_eq!(m.to_literal(Type::SQLite), "'12.30'");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let m = money("12.30 USD");
+        let json = serde_json::json!({"amount": "12.30", "currency": "USD"});
+        assert_eq!(serde_json::to_value(m).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Money>(json).unwrap(), m);
+        assert!(
+            serde_json::from_value::<Money>(serde_json::json!({"amount": 1.5, "currency": "USD"}))
+                .is_err()
+        );
+    }
+}
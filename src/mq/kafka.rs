@@ -0,0 +1,138 @@
+//! Kafka implementations of [`Publisher`] and [`Consumer`].
+
+use std::time::Duration;
+
+use rdkafka::{
+    Message as _, Offset, TopicPartitionList,
+    consumer::{CommitMode, Consumer as _, StreamConsumer},
+    error::KafkaError,
+    message::{Header, Headers, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+};
+
+use crate::mq::{Ack, Consumer, Delivery, Message, Publisher};
+
+const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publishes messages to Kafka.
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    queue_timeout: Duration,
+}
+
+impl KafkaPublisher {
+    /// Creates a new [`KafkaPublisher`] from a producer, which waits at most 5 seconds
+    /// for space in the queue of the producer.
+    pub fn new(producer: FutureProducer) -> KafkaPublisher {
+        KafkaPublisher {
+            producer,
+            queue_timeout: DEFAULT_QUEUE_TIMEOUT,
+        }
+    }
+
+    /// Sets how long to wait for space in the queue of the producer when it's full.
+    pub fn queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = queue_timeout;
+        self
+    }
+
+    /// Gets producer.
+    pub fn get_producer(&self) -> &FutureProducer {
+        &self.producer
+    }
+}
+
+impl Publisher for KafkaPublisher {
+    type Error = KafkaError;
+
+    async fn publish(&self, msg: &Message) -> Result<(), KafkaError> {
+        let mut headers = OwnedHeaders::new_with_capacity(msg.headers.len());
+        for (key, val) in &msg.headers {
+            headers = headers.insert(Header {
+                key,
+                value: Some(val),
+            });
+        }
+        let mut record = FutureRecord::to(&msg.topic)
+            .payload(&msg.payload)
+            .headers(headers);
+        if let Some(key) = &msg.key {
+            record = record.key(key);
+        }
+        self.producer
+            .send(record, self.queue_timeout)
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+}
+
+/// Consumes messages from Kafka.
+///
+/// Acknowledging a delivery commits the offset after it, so `enable.auto.commit` should be set to `false`.
+/// Since offsets are committed per partition, acknowledging a delivery acknowledges the earlier ones of its partition as well.
+pub struct KafkaConsumer {
+    consumer: StreamConsumer,
+}
+
+impl KafkaConsumer {
+    /// Creates a new [`KafkaConsumer`] from a consumer, and subscribes to `topics`.
+    pub fn new(consumer: StreamConsumer, topics: &[&str]) -> Result<KafkaConsumer, KafkaError> {
+        consumer.subscribe(topics)?;
+        Ok(KafkaConsumer { consumer })
+    }
+
+    /// Gets consumer.
+    pub fn get_consumer(&self) -> &StreamConsumer {
+        &self.consumer
+    }
+}
+
+impl Consumer for KafkaConsumer {
+    type Error = KafkaError;
+
+    /// Waits for the next delivery. Kafka streams never terminate, so it never returns [`None`].
+    async fn recv(&mut self) -> Result<Option<Delivery>, KafkaError> {
+        let m = self.consumer.recv().await?;
+        let headers = m
+            .headers()
+            .map(|headers| {
+                headers
+                    .iter()
+                    .filter_map(|h| {
+                        Some((
+                            h.key.to_string(),
+                            String::from_utf8_lossy(h.value?).into_owned(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let message = Message {
+            topic: m.topic().to_string(),
+            key: m.key().map(|k| String::from_utf8_lossy(k).into_owned()),
+            payload: m.payload().unwrap_or_default().to_vec(),
+            headers,
+        };
+        Ok(Some(Delivery {
+            message,
+            ack: Ack::Kafka {
+                partition: m.partition(),
+                offset: m.offset(),
+            },
+        }))
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> Result<(), KafkaError> {
+        let Ack::Kafka { partition, offset } = delivery.ack else {
+            return Ok(());
+        };
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset(
+            &delivery.message.topic,
+            partition,
+            Offset::Offset(offset + 1),
+        )?;
+        self.consumer.commit(&tpl, CommitMode::Async)
+    }
+}
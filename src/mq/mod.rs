@@ -0,0 +1,226 @@
+//! Messaging.
+//!
+//! The [`Publisher`] and [`Consumer`] traits abstract over message brokers, so services can share one messaging interface
+//! and switch brokers without touching the business logic. [`Memory`] is an in-memory broker for tests.
+//! Brokers are supported by the following features:
+//!
+//! * `kafka` - [`kafka::KafkaPublisher`] and [`kafka::KafkaConsumer`].
+//! * `rabbitmq` - [`rabbitmq::RabbitPublisher`] and [`rabbitmq::RabbitConsumer`].
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "rabbitmq")]
+pub mod rabbitmq;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc;
+
+/// A message.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Message {
+    /// The topic, which is the routing key on RabbitMQ.
+    pub topic: String,
+    /// The key, which decides the partition on Kafka.
+    pub key: Option<String>,
+    /// The payload.
+    pub payload: Vec<u8>,
+    /// The headers.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Message {
+    /// Creates a new [`Message`] without key and headers.
+    pub fn new(topic: String, payload: Vec<u8>) -> Message {
+        Message {
+            topic,
+            key: None,
+            payload,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    /// Sets key.
+    pub fn key(mut self, key: String) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Adds a header.
+    pub fn header(mut self, key: String, val: String) -> Self {
+        self.headers.insert(key, val);
+        self
+    }
+}
+
+/// How a delivery is acknowledged.
+#[derive(Debug)]
+enum Ack {
+    None,
+    #[cfg(feature = "kafka")]
+    Kafka {
+        partition: i32,
+        offset: i64,
+    },
+    #[cfg(feature = "rabbitmq")]
+    Rabbit(lapin::Acker),
+}
+
+/// A message received by a [`Consumer`], which should be acknowledged once processed.
+#[derive(Debug)]
+pub struct Delivery {
+    message: Message,
+    // Only read by the broker implementations.
+    #[cfg_attr(not(any(feature = "kafka", feature = "rabbitmq")), expect(dead_code))]
+    ack: Ack,
+}
+
+impl Delivery {
+    /// Gets message.
+    pub fn get_message(&self) -> &Message {
+        &self.message
+    }
+
+    /// Consumes the delivery and returns the message.
+    pub fn into_message(self) -> Message {
+        self.message
+    }
+}
+
+/// Publishes messages.
+pub trait Publisher {
+    /// The error returned by the broker.
+    type Error;
+
+    /// Publishes a message, and returns once the broker has accepted it.
+    fn publish(&self, msg: &Message) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// Consumes messages.
+///
+/// Deliveries that are not acknowledged are redelivered after the consumer restarts,
+/// so messages are processed at least once.
+pub trait Consumer {
+    /// The error returned by the broker.
+    type Error;
+
+    /// Waits for the next delivery, or returns [`None`] if the subscription is closed.
+    fn recv(&mut self) -> impl Future<Output = Result<Option<Delivery>, Self::Error>> + Send;
+
+    /// Acknowledges a delivery.
+    fn ack(&self, delivery: &Delivery) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// An in-memory broker for tests, which delivers every message to all subscribers of its topic.
+///
+/// Clones share the same subscriptions. Messages published before subscribing are not delivered.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::mq::{Consumer, Memory, Message, Publisher};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let broker = Memory::new();
+/// let mut consumer = broker.subscribe("orders");
+///
+/// let msg = Message::new(String::from("orders"), b"created".to_vec()).key(String::from("1"));
+/// broker.publish(&msg).await.unwrap();
+///
+/// let delivery = consumer.recv().await.unwrap().unwrap();
+/// assert_eq!(delivery.get_message(), &msg);
+/// consumer.ack(&delivery).await.unwrap();
+/// # });
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct Memory {
+    topics: Arc<Mutex<HashMap<String, Vec<mpsc::UnboundedSender<Message>>>>>,
+}
+
+impl Memory {
+    /// Creates a new [`Memory`] without subscriptions.
+    pub fn new() -> Memory {
+        Memory::default()
+    }
+
+    /// Subscribes to `topic`.
+    pub fn subscribe(&self, topic: &str) -> MemoryConsumer {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.topics
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(topic.to_string())
+            .or_default()
+            .push(tx);
+        MemoryConsumer { rx }
+    }
+}
+
+impl Publisher for Memory {
+    type Error = Infallible;
+
+    async fn publish(&self, msg: &Message) -> Result<(), Infallible> {
+        let mut topics = self.topics.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(subscribers) = topics.get_mut(&msg.topic) {
+            // Dropped consumers are unsubscribed.
+            subscribers.retain(|tx| tx.send(msg.clone()).is_ok());
+        }
+        Ok(())
+    }
+}
+
+/// A subscription to a topic of [`Memory`].
+#[derive(Debug)]
+pub struct MemoryConsumer {
+    rx: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Consumer for MemoryConsumer {
+    type Error = Infallible;
+
+    async fn recv(&mut self) -> Result<Option<Delivery>, Infallible> {
+        Ok(self.rx.recv().await.map(|message| Delivery {
+            message,
+            ack: Ack::None,
+        }))
+    }
+
+    async fn ack(&self, _delivery: &Delivery) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Consumer, Memory, Message, Publisher};
+
+    #[tokio::test]
+    async fn test_memory() {
+        let broker = Memory::new();
+        let mut a = broker.subscribe("t");
+        let mut b = broker.clone().subscribe("t");
+        let other = broker.subscribe("other");
+        drop(other);
+
+        for topic in ["t", "other", "none"] {
+            let msg = Message::new(topic.to_string(), b"x".to_vec())
+                .header(String::from("h"), String::from("v"));
+            broker.publish(&msg).await.unwrap();
+        }
+
+        for consumer in [&mut a, &mut b] {
+            let delivery = consumer.recv().await.unwrap().unwrap();
+            assert_eq!(delivery.get_message().topic, "t");
+            assert_eq!(delivery.get_message().headers["h"], "v");
+            assert!(consumer.rx.is_empty());
+        }
+        assert!(broker.topics.lock().unwrap()["other"].is_empty());
+
+        drop(broker);
+        assert!(a.recv().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,124 @@
+//! RabbitMQ implementations of [`Publisher`] and [`Consumer`].
+//!
+//! Topics are mapped to routing keys, and keys to the `x-message-key` header since AMQP has no message keys.
+
+use futures_util::StreamExt;
+use lapin::{
+    BasicProperties, Channel,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions},
+    types::{AMQPValue, FieldTable, LongString},
+};
+
+use crate::mq::{Ack, Consumer, Delivery, Message, Publisher};
+
+const KEY_HEADER: &str = "x-message-key";
+
+/// Publishes messages to an exchange of RabbitMQ.
+pub struct RabbitPublisher {
+    channel: Channel,
+    exchange: String,
+}
+
+impl RabbitPublisher {
+    /// Creates a new [`RabbitPublisher`] that publishes to `exchange` through `channel`.
+    ///
+    /// Enable publisher confirms on the channel via `confirm_select` to wait for the broker to accept each message.
+    pub fn new(channel: Channel, exchange: String) -> RabbitPublisher {
+        RabbitPublisher { channel, exchange }
+    }
+
+    /// Gets channel.
+    pub fn get_channel(&self) -> &Channel {
+        &self.channel
+    }
+
+    /// Gets exchange.
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+}
+
+impl Publisher for RabbitPublisher {
+    type Error = lapin::Error;
+
+    async fn publish(&self, msg: &Message) -> Result<(), lapin::Error> {
+        let mut headers = FieldTable::default();
+        for (key, val) in msg
+            .key
+            .iter()
+            .map(|k| (KEY_HEADER, k))
+            .chain(msg.headers.iter().map(|(key, val)| (key.as_str(), val)))
+        {
+            headers.insert(
+                key.into(),
+                AMQPValue::LongString(LongString::from(val.as_str())),
+            );
+        }
+        self.channel
+            .basic_publish(
+                self.exchange.as_str().into(),
+                msg.topic.as_str().into(),
+                BasicPublishOptions::default(),
+                &msg.payload,
+                BasicProperties::default().with_headers(headers),
+            )
+            .await?
+            .await?;
+        Ok(())
+    }
+}
+
+/// Consumes messages from a queue of RabbitMQ.
+pub struct RabbitConsumer {
+    consumer: lapin::Consumer,
+}
+
+impl RabbitConsumer {
+    /// Creates a new [`RabbitConsumer`] that consumes `queue` through `channel` with manual acknowledgements.
+    pub async fn new(channel: &Channel, queue: &str) -> Result<RabbitConsumer, lapin::Error> {
+        let consumer = channel
+            .basic_consume(
+                queue.into(),
+                "".into(),
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        Ok(RabbitConsumer { consumer })
+    }
+}
+
+impl Consumer for RabbitConsumer {
+    type Error = lapin::Error;
+
+    async fn recv(&mut self) -> Result<Option<Delivery>, lapin::Error> {
+        let Some(d) = self.consumer.next().await.transpose()? else {
+            return Ok(None);
+        };
+        let mut message = Message::new(d.routing_key.to_string(), d.data);
+        for (key, val) in d.properties.headers().iter().flat_map(|h| h.inner()) {
+            let val = match val {
+                AMQPValue::LongString(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+                AMQPValue::ShortString(s) => s.to_string(),
+                _ => continue,
+            };
+            if key.as_str() == KEY_HEADER {
+                message.key = Some(val);
+            } else {
+                message.headers.insert(key.to_string(), val);
+            }
+        }
+        Ok(Some(Delivery {
+            message,
+            ack: Ack::Rabbit(d.acker),
+        }))
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> Result<(), lapin::Error> {
+        let Ack::Rabbit(acker) = &delivery.ack else {
+            return Ok(());
+        };
+        acker.ack(BasicAckOptions::default()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+//! Pagination requests and responses shared by the API and database layers.
+//!
+//! A [`PageConfig`] parses the `page`, `size` and `sort` query parameters into a [`PageRequest`],
+//! checking sort fields against an allow-list so they can be written into `ORDER BY` safely.
+//! The request is passed as is to [`db::page`](crate::db::page), which returns a [`Page`] to respond with.
+//! [`CursorPage`] is the response of keyset pagination, whose cursor is an opaque token.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::page::{PageConfig, SortField};
+//!
+//! let config = PageConfig::new(&["created_at", "name"]).max_size(50);
+//! let req = config.parse(Some("2"), None, Some("-created_at,name")).unwrap();
+//!
+//! assert_eq!((req.page, req.size), (2, 20));
+//! assert_eq!(req.sort, vec![SortField::desc("created_at"), SortField::asc("name")]);
+//! assert!(config.parse(None, Some("100"), None).is_err());
+//! assert!(config.parse(None, None, Some("password")).is_err());
+//! ```
+
+use std::fmt;
+
+use crate::error::{AppError, Code};
+
+/// Errors returned by [`PageConfig::parse`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The page number is not a positive integer.
+    InvalidPage(String),
+    /// The page size is not a positive integer up to the maximum.
+    InvalidSize(String),
+    /// The sort field is not allowed.
+    InvalidSort(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPage(s) => write!(f, "invalid page {}", s),
+            Error::InvalidSize(s) => write!(f, "invalid page size {}", s),
+            Error::InvalidSort(s) => write!(f, "invalid sort field {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for AppError {
+    fn from(e: Error) -> Self {
+        AppError::new(Code::InvalidArgument, e.to_string())
+    }
+}
+
+/// A sort key.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SortField {
+    /// The column name.
+    pub col: String,
+    /// Whether to sort in descending order.
+    pub desc: bool,
+}
+
+impl SortField {
+    /// Creates a sort key in ascending order.
+    pub fn asc(col: &str) -> SortField {
+        SortField {
+            col: col.to_string(),
+            desc: false,
+        }
+    }
+
+    /// Creates a sort key in descending order.
+    pub fn desc(col: &str) -> SortField {
+        SortField {
+            col: col.to_string(),
+            desc: true,
+        }
+    }
+}
+
+/// A page request.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageRequest {
+    /// The page number, starting from 1.
+    pub page: u64,
+    /// The page size.
+    pub size: u64,
+    /// The sort keys. Sort by a unique key last, so the order of rows is stable across pages.
+    pub sort: Vec<SortField>,
+}
+
+impl PageRequest {
+    /// Gets the number of rows skipped before this page.
+    pub fn get_offset(&self) -> u64 {
+        self.page.saturating_sub(1).saturating_mul(self.size)
+    }
+}
+
+/// Parses page requests from query parameters.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PageConfig {
+    allowed: Vec<String>,
+    default_size: u64,
+    max_size: u64,
+    default_sort: Vec<SortField>,
+}
+
+impl PageConfig {
+    /// Creates a new [`PageConfig`] allowing to sort by `allowed` fields,
+    /// whose pages have 20 items by default and 100 items at most.
+    pub fn new<S: AsRef<str>>(allowed: &[S]) -> PageConfig {
+        PageConfig {
+            allowed: allowed.iter().map(|s| s.as_ref().to_string()).collect(),
+            default_size: 20,
+            max_size: 100,
+            default_sort: Vec::new(),
+        }
+    }
+
+    /// Sets the page size used when the size is not requested.
+    pub fn default_size(mut self, size: u64) -> Self {
+        self.default_size = size;
+        self
+    }
+
+    /// Sets the maximum page size.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = size;
+        self
+    }
+
+    /// Sets the sort keys used when the sort is not requested, which may use fields not in the allow-list.
+    pub fn default_sort(mut self, sort: Vec<SortField>) -> Self {
+        self.default_sort = sort;
+        self
+    }
+
+    /// Gets the fields allowed to sort by.
+    pub fn get_allowed(&self) -> &[String] {
+        &self.allowed
+    }
+
+    /// Parses the query parameters of a page request.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The page number, which is 1 if absent.
+    /// * `size` - The page size, which is the default size if absent.
+    /// * `sort` - The comma-separated sort fields, each of which is prefixed by `-` to sort in descending order,
+    ///   or optionally by `+` to sort in ascending order.
+    pub fn parse(
+        &self,
+        page: Option<&str>,
+        size: Option<&str>,
+        sort: Option<&str>,
+    ) -> Result<PageRequest, Error> {
+        let page = match page {
+            Some(s) => s
+                .trim()
+                .parse()
+                .ok()
+                .filter(|page| *page > 0)
+                .ok_or_else(|| Error::InvalidPage(s.to_string()))?,
+            None => 1,
+        };
+        let size = match size {
+            Some(s) => s
+                .trim()
+                .parse()
+                .ok()
+                .filter(|size| *size > 0 && *size <= self.max_size)
+                .ok_or_else(|| Error::InvalidSize(s.to_string()))?,
+            None => self.default_size,
+        };
+        let sort = match sort {
+            Some(s) if !s.trim().is_empty() => self.parse_sort(s)?,
+            _ => self.default_sort.clone(),
+        };
+        Ok(PageRequest { page, size, sort })
+    }
+
+    fn parse_sort(&self, s: &str) -> Result<Vec<SortField>, Error> {
+        parse_sort(
+            s,
+            |item, name| {
+                if self.allowed.iter().any(|allowed| allowed == name) {
+                    Ok(())
+                } else {
+                    Err(Error::InvalidSort(item.to_string()))
+                }
+            },
+            |item| Error::InvalidSort(item.to_string()),
+        )
+    }
+}
+
+/// Parses comma-separated sort fields like `-created_at,+name`.
+///
+/// `check` is called with each item and its field name, and `invalid` with each empty or duplicate item.
+pub(crate) fn parse_sort<E>(
+    s: &str,
+    mut check: impl FnMut(&str, &str) -> Result<(), E>,
+    invalid: impl Fn(&str) -> E,
+) -> Result<Vec<SortField>, E> {
+    let mut sort: Vec<SortField> = Vec::new();
+    for item in s.split(',').map(str::trim) {
+        let (name, desc) = match item.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (item.strip_prefix('+').unwrap_or(item), false),
+        };
+        if name.is_empty() || sort.iter().any(|field| field.col == name) {
+            return Err(invalid(item));
+        }
+        check(item, name)?;
+        sort.push(SortField {
+            col: name.to_string(),
+            desc,
+        });
+    }
+    Ok(sort)
+}
+
+/// A page of items.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Page<T> {
+    /// The items in this page.
+    pub items: Vec<T>,
+    /// The total number of items in all pages.
+    pub total: u64,
+    /// The page number, starting from 1.
+    pub page: u64,
+    /// The page size.
+    pub size: u64,
+    /// Whether there are more pages after this page.
+    pub has_next: bool,
+}
+
+impl<T> Page<T> {
+    /// Creates a new [`Page`] from the items of the requested page and the total number of items.
+    pub fn new(items: Vec<T>, total: u64, req: &PageRequest) -> Page<T> {
+        Page {
+            items,
+            total,
+            page: req.page,
+            size: req.size,
+            has_next: req.get_offset().saturating_add(req.size) < total,
+        }
+    }
+
+    /// Maps the items, for example from database rows to API responses.
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            page: self.page,
+            size: self.size,
+            has_next: self.has_next,
+        }
+    }
+}
+
+/// A page of items fetched by keyset pagination.
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CursorPage<T> {
+    /// The items in this page.
+    pub items: Vec<T>,
+    /// The token to request the next page with, or [`None`] if this is the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> CursorPage<T> {
+    /// Creates a new [`CursorPage`].
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The fetched items. Fetch one more item than the page size to tell whether there is a next page.
+    /// * `size` - The page size, beyond which items are dropped.
+    /// * `cursor` - The function returning the cursor of an item, called on the last item of a page with a next page.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use sainnhe_common::page::CursorPage;
+    ///
+    /// let page = CursorPage::new(vec![1, 2, 3], 2, |id| id.to_string());
+    ///
+    /// assert_eq!(page.items, vec![1, 2]);
+    /// assert_eq!(page.next_cursor.as_deref(), Some("2"));
+    /// assert!(CursorPage::new(vec![1], 2, |id| id.to_string()).next_cursor.is_none());
+    /// ```
+    pub fn new<F: FnOnce(&T) -> String>(mut items: Vec<T>, size: u64, cursor: F) -> CursorPage<T> {
+        let size = usize::try_from(size).unwrap_or(usize::MAX);
+        let next_cursor = if items.len() > size {
+            items.truncate(size);
+            items.last().map(cursor)
+        } else {
+            None
+        };
+        CursorPage { items, next_cursor }
+    }
+
+    /// Maps the items, for example from database rows to API responses.
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> CursorPage<U> {
+        CursorPage {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, PageConfig, SortField};
+
+    #[test]
+    fn test_parse() {
+        struct TC {
+            page: Option<&'static str>,
+            size: Option<&'static str>,
+            sort: Option<&'static str>,
+            want: Result<(u64, u64, Vec<SortField>), Error>,
+        }
+
+        let config = PageConfig::new(&["id", "name"])
+            .default_size(10)
+            .max_size(50)
+            .default_sort(vec![SortField::asc("id")]);
+        let test_cases = vec![
+            TC {
+                page: None,
+                size: None,
+                sort: None,
+                want: Ok((1, 10, vec![SortField::asc("id")])),
+            },
+            TC {
+                page: Some("3"),
+                size: Some("50"),
+                sort: Some(" +name , -id"),
+                want: Ok((3, 50, vec![SortField::asc("name"), SortField::desc("id")])),
+            },
+            TC {
+                page: Some("0"),
+                size: None,
+                sort: None,
+                want: Err(Error::InvalidPage(String::from("0"))),
+            },
+            TC {
+                page: None,
+                size: Some("51"),
+                sort: None,
+                want: Err(Error::InvalidSize(String::from("51"))),
+            },
+            TC {
+                page: None,
+                size: None,
+                sort: Some("id,-id"),
+                want: Err(Error::InvalidSort(String::from("-id"))),
+            },
+            TC {
+                page: None,
+                size: None,
+                sort: Some("name;DROP TABLE users"),
+                want: Err(Error::InvalidSort(String::from("name;DROP TABLE users"))),
+            },
+        ];
+
+        for tc in test_cases {
+            let got = config
+                .parse(tc.page, tc.size, tc.sort)
+                .map(|req| (req.page, req.size, req.sort));
+            assert_eq!(got, tc.want);
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! In-process rate limiters, keyed by for example the client IP or the user id.
+//!
+//! [`TokenBucket`] allows bursts up to its capacity and refills at a constant rate,
+//! while [`SlidingWindow`] limits the number of requests in any window of a fixed length.
+//! Both drop the state of keys that have been idle for longer than a TTL, so memory stays bounded.
+//! Limits are per process, so with multiple instances each instance enforces its own limit.
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The per-key state and the time of the last sweep of idle keys.
+#[derive(Debug)]
+struct Keys<K, S> {
+    states: HashMap<K, S>,
+    last_sweep: Instant,
+}
+
+impl<K: Eq + Hash, S> Keys<K, S> {
+    fn new() -> Keys<K, S> {
+        Keys {
+            states: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+
+    /// Removes the keys last used before `now - ttl`, at most once per `ttl`.
+    fn sweep<F: Fn(&S) -> Instant>(&mut self, now: Instant, ttl: Duration, last_used: F) {
+        if now.saturating_duration_since(self.last_sweep) < ttl {
+            return;
+        }
+        self.states
+            .retain(|_, s| now.saturating_duration_since(last_used(s)) < ttl);
+        self.last_sweep = now;
+    }
+
+    fn get_mut<Q, F>(&mut self, key: &Q, init: F) -> &mut S
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        F: FnOnce() -> S,
+    {
+        if !self.states.contains_key(key) {
+            self.states.insert(key.to_owned(), init());
+        }
+        // The key has just been inserted if it was missing.
+        self.states.get_mut(key).unwrap_or_else(|| unreachable!())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// A token-bucket rate limiter.
+///
+/// Each key has a bucket of `capacity` tokens, which is refilled at `rate` tokens per second.
+/// A request takes one or more tokens, and is rejected if there are not enough tokens.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::rate_limit::TokenBucket;
+///
+/// let limiter = TokenBucket::new(2, 1.0, Duration::from_secs(60));
+///
+/// assert!(limiter.try_acquire("10.0.0.1").is_ok());
+/// assert!(limiter.try_acquire("10.0.0.1").is_ok());
+/// assert!(limiter.try_acquire("10.0.0.1").is_err());
+/// assert!(limiter.try_acquire("10.0.0.2").is_ok());
+/// ```
+#[derive(Debug)]
+pub struct TokenBucket<K> {
+    capacity: u32,
+    rate: f64,
+    ttl: Duration,
+    keys: Mutex<Keys<K, Bucket>>,
+}
+
+impl<K: Eq + Hash> TokenBucket<K> {
+    /// Creates a new [`TokenBucket`].
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of tokens, which is the maximum burst.
+    /// * `rate` - The number of tokens refilled per second, which must be positive.
+    /// * `ttl` - How long the bucket of an idle key is kept. Since a bucket is full again after
+    ///   `capacity / rate` seconds, a TTL longer than that doesn't change the behavior.
+    pub fn new(capacity: u32, rate: f64, ttl: Duration) -> TokenBucket<K> {
+        TokenBucket {
+            capacity,
+            rate,
+            ttl,
+            keys: Mutex::new(Keys::new()),
+        }
+    }
+
+    /// Gets capacity.
+    pub fn get_capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Gets rate.
+    pub fn get_rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Takes a token from the bucket of `key`, see [`TokenBucket::try_acquire_n`].
+    pub fn try_acquire<Q>(&self, key: &Q) -> Result<(), Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.try_acquire_n(key, 1)
+    }
+
+    /// Takes `n` tokens from the bucket of `key`, for example to weigh expensive requests.
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok`] if the tokens are taken, or [`Err`] with how long to wait until enough tokens are available.
+    ///   Nothing is taken if the request is rejected. If `n` exceeds the capacity, it's never accepted
+    ///   and the maximum duration is returned.
+    pub fn try_acquire_n<Q>(&self, key: &Q, n: u32) -> Result<(), Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.try_acquire_n_at(key, n, Instant::now())
+    }
+
+    fn try_acquire_n_at<Q>(&self, key: &Q, n: u32, now: Instant) -> Result<(), Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if n > self.capacity {
+            return Err(Duration::MAX);
+        }
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        keys.sweep(now, self.ttl, |b| b.updated_at);
+        let capacity = f64::from(self.capacity);
+        let bucket = keys.get_mut(key, || Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(capacity);
+        bucket.updated_at = now;
+        let n = f64::from(n);
+        if bucket.tokens >= n {
+            bucket.tokens -= n;
+            return Ok(());
+        }
+        Err(Duration::try_from_secs_f64((n - bucket.tokens) / self.rate).unwrap_or(Duration::MAX))
+    }
+}
+
+#[derive(Debug)]
+struct Window {
+    /// The start of the current window.
+    start: Instant,
+    prev: u32,
+    curr: u32,
+}
+
+/// A sliding-window rate limiter, which accepts at most `limit` requests per `window` for each key.
+///
+/// The number of requests in the sliding window is estimated from the counts of the current and
+/// the previous fixed windows, weighted by how much the previous window overlaps the sliding one.
+/// This takes constant memory per key and smooths out the bursts allowed at the boundaries of fixed windows.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::rate_limit::SlidingWindow;
+///
+/// let limiter = SlidingWindow::new(2, Duration::from_secs(60));
+///
+/// assert!(limiter.try_acquire(&42).is_ok());
+/// assert!(limiter.try_acquire(&42).is_ok());
+/// assert!(limiter.try_acquire(&42).is_err());
+/// ```
+#[derive(Debug)]
+pub struct SlidingWindow<K> {
+    limit: u32,
+    window: Duration,
+    keys: Mutex<Keys<K, Window>>,
+}
+
+impl<K: Eq + Hash> SlidingWindow<K> {
+    /// Creates a new [`SlidingWindow`] that accepts `limit` requests per `window`, which must be positive.
+    ///
+    /// Keys idle for two windows no longer affect the estimation, so their state is dropped then.
+    pub fn new(limit: u32, window: Duration) -> SlidingWindow<K> {
+        SlidingWindow {
+            limit,
+            window,
+            keys: Mutex::new(Keys::new()),
+        }
+    }
+
+    /// Gets limit.
+    pub fn get_limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Gets window.
+    pub fn get_window(&self) -> Duration {
+        self.window
+    }
+
+    /// Records a request of `key` if it's within the limit.
+    ///
+    /// # Returns
+    ///
+    /// * [`Ok`] if the request is accepted, or [`Err`] with how long to wait until it would be accepted.
+    ///   Rejected requests are not counted.
+    pub fn try_acquire<Q>(&self, key: &Q) -> Result<(), Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    fn try_acquire_at<Q>(&self, key: &Q, now: Instant) -> Result<(), Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if self.limit == 0 {
+            return Err(Duration::MAX);
+        }
+        let w = self.window;
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        // A window that started two windows ago no longer overlaps the sliding window.
+        keys.sweep(now, 2 * w, |s| s.start);
+        let state = keys.get_mut(key, || Window {
+            start: now,
+            prev: 0,
+            curr: 0,
+        });
+        let mut elapsed = now.saturating_duration_since(state.start);
+        if elapsed >= w {
+            // Roll over, keeping the start aligned to multiples of the window.
+            let windows = elapsed.as_nanos() / w.as_nanos();
+            state.prev = if windows == 1 { state.curr } else { 0 };
+            state.curr = 0;
+            elapsed = Duration::from_nanos((elapsed.as_nanos() % w.as_nanos()) as u64);
+            state.start = now - elapsed;
+        }
+
+        let (limit, prev, curr) = (
+            f64::from(self.limit),
+            f64::from(state.prev),
+            f64::from(state.curr),
+        );
+        let overlap = 1.0 - elapsed.as_secs_f64() / w.as_secs_f64();
+        if prev * overlap + curr + 1.0 <= limit {
+            state.curr += 1;
+            return Ok(());
+        }
+        // Solve `prev * (1 - t / w) + curr + 1 <= limit` for the elapsed time `t`,
+        // rolling over first if the current window alone is full.
+        let wait = if curr + 1.0 <= limit {
+            w.mul_f64(1.0 - (limit - 1.0 - curr) / prev)
+                .saturating_sub(elapsed)
+        } else {
+            w - elapsed + w.mul_f64(1.0 - (limit - 1.0) / curr)
+        };
+        Err(wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{SlidingWindow, TokenBucket};
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = TokenBucket::<String>::new(3, 2.0, Duration::from_secs(10));
+        assert_eq!(limiter.get_capacity(), 3);
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+
+        struct TC {
+            key: &'static str,
+            n: u32,
+            ms: u64,
+            want: Result<(), Duration>,
+        }
+
+        let test_cases = vec![
+            TC {
+                key: "a",
+                n: 2,
+                ms: 0,
+                want: Ok(()),
+            },
+            TC {
+                key: "a",
+                n: 2,
+                ms: 0,
+                want: Err(Duration::from_millis(500)),
+            },
+            // Other keys are independent
+            TC {
+                key: "b",
+                n: 3,
+                ms: 0,
+                want: Ok(()),
+            },
+            TC {
+                key: "a",
+                n: 2,
+                ms: 500,
+                want: Ok(()),
+            },
+            // Refilled up to the capacity
+            TC {
+                key: "a",
+                n: 3,
+                ms: 60_000,
+                want: Ok(()),
+            },
+            TC {
+                key: "a",
+                n: 4,
+                ms: 120_000,
+                want: Err(Duration::MAX),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(limiter.try_acquire_n_at(tc.key, tc.n, at(tc.ms)), tc.want);
+        }
+
+        // Idle keys are dropped
+        let keys = limiter.keys.lock().unwrap();
+        assert_eq!(keys.states.len(), 1);
+        assert!(keys.states.contains_key("a"));
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let limiter = SlidingWindow::<u64>::new(4, Duration::from_secs(10));
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+
+        for _ in 0..4 {
+            assert_eq!(limiter.try_acquire_at(&1, at(0)), Ok(()));
+        }
+        // The current window is full, so wait for the roll-over and for the previous window to slide out
+        // until `4 * (1 - t / 10s) + 1 <= 4`, that is t = 2.5s.
+        assert_eq!(
+            limiter.try_acquire_at(&1, at(5000)),
+            Err(Duration::from_millis(7500))
+        );
+        assert_eq!(
+            limiter.try_acquire_at(&1, at(12_000)),
+            Err(Duration::from_millis(500))
+        );
+        assert_eq!(limiter.try_acquire_at(&1, at(12_500)), Ok(()));
+        // 4 * 0.5 + 1 + 1 <= 4
+        assert_eq!(limiter.try_acquire_at(&1, at(15_000)), Ok(()));
+        assert!(limiter.try_acquire_at(&1, at(15_000)).is_err());
+
+        // The previous window is forgotten after two windows
+        for _ in 0..4 {
+            assert_eq!(limiter.try_acquire_at(&1, at(35_000)), Ok(()));
+        }
+        assert!(limiter.try_acquire_at(&2, at(35_000)).is_ok());
+
+        // Idle keys are dropped
+        assert!(limiter.try_acquire_at(&3, at(60_000)).is_ok());
+        assert_eq!(limiter.keys.lock().unwrap().states.len(), 1);
+    }
+}
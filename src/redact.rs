@@ -0,0 +1,367 @@
+//! Masking of personally identifiable information.
+//!
+//! The functions mask values like emails, phone numbers and card numbers, keeping just enough to tell them apart,
+//! and [`hash`] replaces identifiers with stable pseudonyms, so logs can still be correlated.
+//! [`auto`] and [`statement`] detect such values in arbitrary text and SQL statements,
+//! and are applied to the statements and bind parameters logged by [`Observer`](crate::db::observe::Observer).
+//!
+//! Types implement [`Redact`] to return copies with their sensitive fields masked before being logged.
+//! With the `macros` feature enabled, it can be derived, where fields are annotated with
+//! `#[redact]` to be masked entirely, `#[redact(email)]`, `#[redact(phone)]`, `#[redact(card)]` or `#[redact(hash)]`
+//! to be masked by the functions of the same names, or `#[redact(nested)]` to be redacted via their own [`Redact`].
+//! Masked fields must be [`Mask`]s, and the other fields must be [`Clone`].
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::redact;
+//!
+//! assert_eq!(redact::email("alice@example.com"), "a***@example.com");
+//! assert_eq!(redact::card("4111 1111 1111 1111"), "**** **** **** 1111");
+//! assert_eq!(redact::statement("SELECT * FROM users WHERE email = 'alice@example.com'"),
+//!     "SELECT * FROM users WHERE email = 'a***@example.com'");
+//! ```
+
+use std::fmt::Write;
+
+use crate::{db::Value, hash::fnv1a};
+
+#[cfg(feature = "macros")]
+pub use sainnhe_common_macros::Redact;
+
+const MASK: &str = "***";
+
+/// A type that can return a copy of itself with sensitive data masked.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::redact::{self, Redact};
+///
+/// #[derive(Clone)]
+/// struct User {
+///     id: i64,
+///     email: String,
+/// }
+///
+/// impl Redact for User {
+///     fn redact(&self) -> Self {
+///         User {
+///             id: self.id,
+///             email: redact::email(&self.email),
+///         }
+///     }
+/// }
+///
+/// let user = User { id: 1, email: String::from("alice@example.com") };
+///
+/// assert_eq!(user.redact().email, "a***@example.com");
+/// ```
+pub trait Redact {
+    /// Returns a copy with sensitive data masked.
+    fn redact(&self) -> Self;
+}
+
+impl<T: Redact> Redact for Option<T> {
+    fn redact(&self) -> Self {
+        self.as_ref().map(Redact::redact)
+    }
+}
+
+impl<T: Redact> Redact for Vec<T> {
+    fn redact(&self) -> Self {
+        self.iter().map(Redact::redact).collect()
+    }
+}
+
+/// A type whose text can be masked by the functions of this module.
+pub trait Mask {
+    /// Returns a copy with the text masked by `f`.
+    fn mask(&self, f: fn(&str) -> String) -> Self;
+}
+
+impl Mask for String {
+    fn mask(&self, f: fn(&str) -> String) -> Self {
+        f(self)
+    }
+}
+
+impl<T: Mask> Mask for Option<T> {
+    fn mask(&self, f: fn(&str) -> String) -> Self {
+        self.as_ref().map(|v| v.mask(f))
+    }
+}
+
+impl<T: Mask> Mask for Vec<T> {
+    fn mask(&self, f: fn(&str) -> String) -> Self {
+        self.iter().map(|v| v.mask(f)).collect()
+    }
+}
+
+/// Masks `v` entirely.
+pub fn full(_v: &str) -> String {
+    String::from(MASK)
+}
+
+/// Masks the local part of an email address except its first character.
+/// Values that are not email addresses are masked entirely.
+pub fn email(v: &str) -> String {
+    match v.rsplit_once('@') {
+        Some((local, domain)) if !local.is_empty() => {
+            let first = local.chars().next().unwrap_or_default();
+            format!("{}{}@{}", first, MASK, domain)
+        }
+        _ => full(v),
+    }
+}
+
+/// Masks every digit except the last 4, keeping separators like `+`, `-` and spaces.
+fn keep_last_digits(v: &str) -> String {
+    let mut digits = v.chars().filter(char::is_ascii_digit).count();
+    v.chars()
+        .map(|c| {
+            if !c.is_ascii_digit() {
+                return c;
+            }
+            digits -= 1;
+            if digits < 4 { c } else { '*' }
+        })
+        .collect()
+}
+
+/// Masks a phone number except its last 4 digits.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::redact;
+///
+/// assert_eq!(redact::phone("+81 90-1234-5678"), "+** **-****-5678");
+/// ```
+pub fn phone(v: &str) -> String {
+    keep_last_digits(v)
+}
+
+/// Masks a card number except its last 4 digits, which PCI DSS allows to be displayed.
+pub fn card(v: &str) -> String {
+    keep_last_digits(v)
+}
+
+/// Replaces an identifier with a stable pseudonym, namely the hex of its 64-bit FNV-1a hash.
+///
+/// The same identifier always has the same pseudonym, so events of the same user can be correlated in logs.
+/// Pseudonyms of identifiers from small spaces like phone numbers can be brute-forced,
+/// so mask such identifiers instead when the logs are shared.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::redact;
+///
+/// assert_eq!(redact::hash("user-1"), redact::hash("user-1"));
+/// assert_ne!(redact::hash("user-1"), redact::hash("user-2"));
+/// assert_eq!(redact::hash("user-1").len(), 16);
+/// ```
+pub fn hash(v: &str) -> String {
+    format!("{:016x}", fnv1a(v.as_bytes()))
+}
+
+fn is_email(v: &str) -> bool {
+    v.rsplit_once('@').is_some_and(|(local, domain)| {
+        !local.is_empty()
+            && domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+            && !v.chars().any(char::is_whitespace)
+    })
+}
+
+/// Returns whether `v` is 13 to 19 digits, optionally separated by spaces or dashes, passing the Luhn check.
+fn is_card(v: &str) -> bool {
+    if !v
+        .chars()
+        .all(|c| c.is_ascii_digit() || c == ' ' || c == '-')
+    {
+        return false;
+    }
+    let digits: Vec<u32> = v.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match i % 2 {
+            0 => d,
+            _ if d * 2 > 9 => d * 2 - 9,
+            _ => d * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Returns whether `v` is `+` followed by 8 to 15 digits, optionally separated by spaces or dashes.
+fn is_phone(v: &str) -> bool {
+    v.strip_prefix('+').is_some_and(|rest| {
+        rest.chars()
+            .all(|c| c.is_ascii_digit() || c == ' ' || c == '-')
+            && (8..=15).contains(&rest.chars().filter(char::is_ascii_digit).count())
+    })
+}
+
+/// Masks `v` if it's an email address, a card number or a phone number, otherwise returns it as is.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::redact;
+///
+/// assert_eq!(redact::auto("alice@example.com"), "a***@example.com");
+/// assert_eq!(redact::auto("4111-1111-1111-1111"), "****-****-****-1111");
+/// assert_eq!(redact::auto("+819012345678"), "+********5678");
+/// assert_eq!(redact::auto("alice"), "alice");
+/// ```
+pub fn auto(v: &str) -> String {
+    let trimmed = v.trim();
+    if is_email(trimmed) {
+        email(v)
+    } else if is_card(trimmed) {
+        card(v)
+    } else if is_phone(trimmed) {
+        phone(v)
+    } else {
+        v.to_string()
+    }
+}
+
+/// Masks the string literals of a SQL statement via [`auto`].
+pub fn statement(stmt: &str) -> String {
+    let mut out = String::with_capacity(stmt.len());
+    let mut rest = stmt;
+    while let Some(start) = rest.find('\'') {
+        out.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+        // Quotes are escaped by doubling them.
+        let mut end = 0;
+        let bytes = rest.as_bytes();
+        while end < bytes.len() {
+            if bytes[end] == b'\'' {
+                if bytes.get(end + 1) == Some(&b'\'') {
+                    end += 2;
+                    continue;
+                }
+                break;
+            }
+            end += 1;
+        }
+        out.push_str(&auto(&rest[..end]));
+        rest = &rest[end..];
+        if let Some(stripped) = rest.strip_prefix('\'') {
+            out.push('\'');
+            rest = stripped;
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Renders a bind parameter for logs, where text is masked via [`auto`] and bytes are replaced by their length.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::{db::Value, redact};
+///
+/// assert_eq!(redact::value(&Value::from("alice@example.com")), "'a***@example.com'");
+/// assert_eq!(redact::value(&Value::from(vec![1_u8, 2])), "<2 bytes>");
+/// assert_eq!(redact::value(&Value::from(1)), "1");
+/// ```
+pub fn value(v: &Value) -> String {
+    match v {
+        Value::Null => String::from("NULL"),
+        Value::Bool(v) => v.to_string(),
+        Value::Int(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Text(v) => format!("'{}'", auto(v).replace('\'', "''")),
+        Value::Bytes(v) => format!("<{} bytes>", v.len()),
+    }
+}
+
+/// Renders bind parameters for logs via [`value`], like `[1, 'a***@example.com']`.
+pub fn values(vs: &[Value]) -> String {
+    let mut out = String::from("[");
+    for (i, v) in vs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        // Writing to a String never fails.
+        let _ = write!(out, "{}", value(v));
+    }
+    out.push(']');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Value;
+
+    use super::{auto, email, statement, values};
+
+    #[test]
+    fn test_auto() {
+        struct TC {
+            v: &'static str,
+            want: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                v: "bob@mail.example.com",
+                want: "b***@mail.example.com",
+            },
+            TC {
+                v: "bob@localhost",
+                want: "bob@localhost",
+            },
+            TC {
+                v: "4111 1111 1111 1111",
+                want: "**** **** **** 1111",
+            },
+            // Fails the Luhn check
+            TC {
+                v: "4111 1111 1111 1112",
+                want: "4111 1111 1111 1112",
+            },
+            TC {
+                v: "+1 415-555-2671",
+                want: "+* ***-***-2671",
+            },
+            TC {
+                v: "2024-01-01",
+                want: "2024-01-01",
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(auto(tc.v), tc.want);
+        }
+        assert_eq!(email("not an email"), "***");
+    }
+
+    #[test]
+    fn test_statement() {
+        assert_eq!(
+            statement("UPDATE t SET a = 'it''s', b = 'x@y.com' WHERE c = 'unterminated"),
+            "UPDATE t SET a = 'it''s', b = 'x***@y.com' WHERE c = 'unterminated"
+        );
+        assert_eq!(
+            values(&[
+                Value::Null,
+                Value::from("o'neil@example.com"),
+                Value::from(1.5)
+            ]),
+            "[NULL, 'o***@example.com', 1.5]"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! Retrying fallible async operations with exponential backoff and jitter.
+//!
+//! A [`Policy`] describes how many attempts are made and how long to wait between them.
+//! [`spawn`] retries every error, while [`spawn_if`] only retries the errors accepted by a predicate,
+//! for example timeouts and deadlocks but not constraint violations.
+
+use std::time::Duration;
+
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+
+const DEFAULT_JITTER: f64 = 0.5;
+
+/// A retry policy.
+///
+/// The backoff before the `n`-th retry is `initial_backoff * multiplier^(n - 1)`, capped at `max_backoff`.
+/// With a jitter of `j`, a random fraction of up to `j` of the backoff is subtracted,
+/// so clients failing at the same time don't retry at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::retry::Policy;
+///
+/// let policy = Policy::new(5)
+///     .initial_backoff(Duration::from_millis(10))
+///     .max_backoff(Duration::from_millis(50))
+///     .jitter(0.0);
+///
+/// assert_eq!(policy.backoff(1), Duration::from_millis(10));
+/// assert_eq!(policy.backoff(2), Duration::from_millis(20));
+/// assert_eq!(policy.backoff(4), Duration::from_millis(50));
+/// ```
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Policy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+}
+
+impl Policy {
+    /// Creates a new [`Policy`] that makes at most `max_attempts` attempts, including the first one.
+    ///
+    /// The backoff starts from 100 ms, doubles after each retry up to 10 s, and has a jitter of 0.5.
+    pub fn new(max_attempts: u32) -> Policy {
+        Policy {
+            max_attempts,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            multiplier: DEFAULT_MULTIPLIER,
+            jitter: DEFAULT_JITTER,
+        }
+    }
+
+    /// Creates a [`Policy`] that waits `interval` between attempts, without growth or jitter.
+    pub fn constant(max_attempts: u32, interval: Duration) -> Policy {
+        Policy::new(max_attempts)
+            .initial_backoff(interval)
+            .max_backoff(interval)
+            .multiplier(1.0)
+            .jitter(0.0)
+    }
+
+    /// Sets the backoff before the first retry.
+    pub fn initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Sets the upper bound of the backoff.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Sets the factor by which the backoff grows after each retry.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Sets the jitter, which is clamped to `[0, 1]`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Gets max attempts.
+    pub fn get_max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Gets jitter.
+    pub fn get_jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Returns the backoff before the `retry`-th retry, starting from 1, without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp = i32::try_from(retry.saturating_sub(1)).unwrap_or(i32::MAX);
+        let factor = self.multiplier.powi(exp);
+        // Overflows and NaNs fall back to the cap.
+        Duration::try_from_secs_f64(self.initial_backoff.as_secs_f64() * factor)
+            .map_or(self.max_backoff, |d| d.min(self.max_backoff))
+    }
+
+    /// Returns the backoff before the `retry`-th retry, with jitter applied.
+    fn jittered_backoff(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        if self.jitter == 0.0 {
+            return backoff;
+        }
+        backoff.mul_f64(1.0 - self.jitter * rand::random::<f64>())
+    }
+}
+
+/// Runs `f` until it succeeds or the attempts of `policy` are exhausted, retrying every error.
+///
+/// # Arguments
+///
+/// * `policy` - The retry policy.
+/// * `f` - The operation, which is called once per attempt.
+///
+/// # Returns
+///
+/// * The first success, or the last error.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::retry::{Policy, spawn};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let policy = Policy::new(3).initial_backoff(Duration::from_millis(1));
+/// let mut calls = 0;
+///
+/// let res: Result<u32, &str> = spawn(&policy, async || {
+///     calls += 1;
+///     if calls < 3 { Err("unavailable") } else { Ok(calls) }
+/// })
+/// .await;
+///
+/// assert_eq!(res, Ok(3));
+/// # });
+/// ```
+pub async fn spawn<F, T, E>(policy: &Policy, f: F) -> Result<T, E>
+where
+    F: AsyncFnMut() -> Result<T, E>,
+{
+    spawn_if(policy, |_| true, f).await
+}
+
+/// Runs `f` until it succeeds, fails with an error rejected by `pred`,
+/// or the attempts of `policy` are exhausted.
+///
+/// # Arguments
+///
+/// * `policy` - The retry policy.
+/// * `pred` - Returns whether an error is worth retrying.
+/// * `f` - The operation, which is called once per attempt.
+///
+/// # Returns
+///
+/// * The first success, or the last error.
+pub async fn spawn_if<F, P, T, E>(policy: &Policy, mut pred: P, mut f: F) -> Result<T, E>
+where
+    F: AsyncFnMut() -> Result<T, E>,
+    P: FnMut(&E) -> bool,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt >= policy.max_attempts || !pred(&e) => return Err(e),
+            Err(_) => {
+                tokio::time::sleep(policy.jittered_backoff(attempt)).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Policy, spawn, spawn_if};
+
+    #[test]
+    fn test_backoff() {
+        struct TC {
+            policy: Policy,
+            retry: u32,
+            want: Duration,
+        }
+
+        let policy = Policy::new(3)
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(60))
+            .multiplier(3.0);
+        let test_cases = vec![
+            TC {
+                policy,
+                retry: 1,
+                want: Duration::from_secs(1),
+            },
+            TC {
+                policy,
+                retry: 3,
+                want: Duration::from_secs(9),
+            },
+            TC {
+                policy,
+                retry: 5,
+                want: Duration::from_secs(60),
+            },
+            TC {
+                policy,
+                retry: u32::MAX,
+                want: Duration::from_secs(60),
+            },
+            TC {
+                policy: Policy::constant(3, Duration::from_millis(5)),
+                retry: 10,
+                want: Duration::from_millis(5),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(tc.policy.backoff(tc.retry), tc.want);
+        }
+
+        let policy = policy.jitter(2.0);
+        assert_eq!(policy.get_jitter(), 1.0);
+        for retry in 1..5 {
+            assert!(policy.jittered_backoff(retry) <= policy.backoff(retry));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spawn() {
+        let policy = Policy::constant(3, Duration::from_millis(1));
+
+        // Attempts are exhausted
+        let mut calls = 0;
+        let res: Result<(), u32> = spawn(&policy, async || {
+            calls += 1;
+            Err(calls)
+        })
+        .await;
+        assert_eq!(res, Err(3));
+
+        // Errors rejected by the predicate are returned immediately
+        let mut calls = 0;
+        let res: Result<(), &str> = spawn_if(
+            &policy,
+            |e| *e == "timeout",
+            async || {
+                calls += 1;
+                Err(if calls == 1 { "timeout" } else { "conflict" })
+            },
+        )
+        .await;
+        assert_eq!(res, Err("conflict"));
+        assert_eq!(calls, 2);
+    }
+}
@@ -0,0 +1,325 @@
+//! Secrets.
+//!
+//! A [`SecretProvider`] resolves secrets like database passwords by name when they are needed,
+//! instead of reading them once at startup, so that rotated secrets are picked up.
+//!
+//! - [`EnvProvider`] reads environment variables.
+//! - [`FileProvider`] reads files in a directory, like the secrets mounted into Kubernetes pods.
+//! - [`vault::VaultProvider`] reads the KV secrets engine of HashiCorp Vault, with the `vault` feature enabled.
+//!
+//! Wrap a provider in [`Cached`] to avoid resolving the same secret on every use.
+
+#[cfg(feature = "vault")]
+pub mod vault;
+
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+use crate::cache::LruCache;
+
+/// A secret value, which is hidden from [`Debug`] and [`Display`](fmt::Display).
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::secrets::Secret;
+///
+/// let secret = Secret::new(String::from("hunter2"));
+///
+/// assert_eq!(secret.expose(), "hunter2");
+/// assert_eq!(format!("{:?}", secret), "Secret(***)");
+/// ```
+#[derive(PartialEq, Eq, Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Creates a new [`Secret`].
+    pub fn new(val: String) -> Secret {
+        Secret(val)
+    }
+
+    /// Returns the secret value.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+/// Errors returned by secret providers.
+#[derive(Debug)]
+pub enum Error {
+    /// The secret doesn't exist.
+    NotFound(String),
+    /// The secret is not valid unicode, or has an unexpected shape.
+    Invalid(String),
+    /// The secret could not be read.
+    Io(io::Error),
+    /// The request to Vault failed.
+    #[cfg(feature = "vault")]
+    Http(reqwest::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(name) => write!(f, "secret {} is not found", name),
+            Error::Invalid(name) => write!(f, "secret {} is invalid", name),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            #[cfg(feature = "vault")]
+            Error::Http(e) => write!(f, "http error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            #[cfg(feature = "vault")]
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(feature = "vault")]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+/// A source of secrets.
+pub trait SecretProvider {
+    /// Resolves the secret named `name`.
+    fn get(&self, name: &str) -> impl Future<Output = Result<Secret, Error>> + Send;
+}
+
+/// Reads secrets from environment variables named `<prefix><name>`.
+#[derive(Clone, Default, Debug)]
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl EnvProvider {
+    /// Creates a new [`EnvProvider`] without prefix.
+    pub fn new() -> EnvProvider {
+        EnvProvider::default()
+    }
+
+    /// Sets the prefix of variable names.
+    pub fn prefix(mut self, prefix: String) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Gets prefix.
+    pub fn get_prefix(&self) -> &str {
+        &self.prefix
+    }
+}
+
+impl SecretProvider for EnvProvider {
+    async fn get(&self, name: &str) -> Result<Secret, Error> {
+        let var = format!("{}{}", self.prefix, name);
+        match std::env::var(&var) {
+            Ok(val) => Ok(Secret(val)),
+            Err(std::env::VarError::NotPresent) => Err(Error::NotFound(var)),
+            Err(std::env::VarError::NotUnicode(_)) => Err(Error::Invalid(var)),
+        }
+    }
+}
+
+/// Reads secrets from the files in a directory, where the file name is the secret name.
+///
+/// Files are read on every call, so files updated in place, like mounted Kubernetes secrets, are picked up.
+/// A single trailing newline is removed.
+#[derive(Clone, Debug)]
+pub struct FileProvider {
+    dir: PathBuf,
+}
+
+impl FileProvider {
+    /// Creates a new [`FileProvider`] reading `dir`, for example `/var/run/secrets/app`.
+    pub fn new(dir: PathBuf) -> FileProvider {
+        FileProvider { dir }
+    }
+
+    /// Gets dir.
+    pub fn get_dir(&self) -> &PathBuf {
+        &self.dir
+    }
+}
+
+impl SecretProvider for FileProvider {
+    async fn get(&self, name: &str) -> Result<Secret, Error> {
+        // Names are file names, so they must not escape the directory.
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        let mut val = match std::fs::read(self.dir.join(name)) {
+            Ok(val) => String::from_utf8(val).map_err(|_| Error::Invalid(name.to_string()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::NotFound(name.to_string()));
+            }
+            Err(e) => return Err(Error::Io(e)),
+        };
+        if val.ends_with('\n') {
+            val.pop();
+            if val.ends_with('\r') {
+                val.pop();
+            }
+        }
+        Ok(Secret(val))
+    }
+}
+
+/// Caches the secrets resolved by another provider for a TTL, after which they are resolved again.
+///
+/// Errors are not cached.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::secrets::{Cached, EnvProvider, Error, SecretProvider};
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let provider = Cached::new(EnvProvider::new(), Duration::from_secs(300));
+///
+/// assert!(matches!(provider.get("NO_SUCH_SECRET").await, Err(Error::NotFound(_))));
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct Cached<P> {
+    provider: P,
+    cache: LruCache<String, Secret>,
+}
+
+/// The maximum number of secrets cached by [`Cached`].
+const CACHED_CAPACITY: usize = 1024;
+
+impl<P: SecretProvider> Cached<P> {
+    /// Creates a new [`Cached`] provider, whose secrets are resolved again after `ttl`.
+    pub fn new(provider: P, ttl: Duration) -> Cached<P> {
+        Cached {
+            provider,
+            cache: LruCache::new(CACHED_CAPACITY).ttl(Some(ttl)),
+        }
+    }
+
+    /// Gets the wrapped provider.
+    pub fn get_provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Drops the cached secret named `name`, for example after the database rejected it.
+    pub fn invalidate(&self, name: &str) {
+        self.cache.remove(name);
+    }
+}
+
+impl<P: SecretProvider + Sync> SecretProvider for Cached<P> {
+    async fn get(&self, name: &str) -> Result<Secret, Error> {
+        if let Some(secret) = self.cache.get(name) {
+            return Ok(secret);
+        }
+        let secret = self.provider.get(name).await?;
+        self.cache.insert(name.to_string(), secret.clone());
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::{Cached, EnvProvider, Error, FileProvider, Secret, SecretProvider};
+
+    #[tokio::test]
+    async fn test_file_provider() {
+        let dir = std::env::temp_dir().join("sainnhe_common_test_file_provider");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("password"), "hunter2\r\n").unwrap();
+        fs::write(dir.join("token"), "abc\n\n").unwrap();
+        let provider = FileProvider::new(dir);
+
+        struct TC {
+            name: &'static str,
+            want: Option<&'static str>,
+        }
+
+        let test_cases = vec![
+            TC {
+                name: "password",
+                want: Some("hunter2"),
+            },
+            TC {
+                name: "token",
+                want: Some("abc\n"),
+            },
+            TC {
+                name: "missing",
+                want: None,
+            },
+            TC {
+                name: "../password",
+                want: None,
+            },
+        ];
+
+        for tc in test_cases {
+            match (provider.get(tc.name).await, tc.want) {
+                (Ok(secret), Some(want)) => assert_eq!(secret.expose(), want),
+                (Err(Error::NotFound(_)), None) => {}
+                (res, _) => panic!("unexpected result for {}: {:?}", tc.name, res),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached() {
+        struct Counting(AtomicUsize);
+
+        impl SecretProvider for Counting {
+            async fn get(&self, name: &str) -> Result<Secret, Error> {
+                let n = self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(Secret::new(format!("{}-{}", name, n)))
+            }
+        }
+
+        let provider = Cached::new(Counting(AtomicUsize::new(0)), Duration::from_secs(60));
+        assert_eq!(provider.get("a").await.unwrap().expose(), "a-0");
+        assert_eq!(provider.get("a").await.unwrap().expose(), "a-0");
+        provider.invalidate("a");
+        assert_eq!(provider.get("a").await.unwrap().expose(), "a-1");
+        assert_eq!(provider.get_provider().0.load(Ordering::SeqCst), 2);
+
+        let provider = EnvProvider::new().prefix(String::from("NO_SUCH_PREFIX_"));
+        assert!(matches!(
+            provider.get("SECRET").await,
+            Err(Error::NotFound(var)) if var == "NO_SUCH_PREFIX_SECRET"
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+//! Secrets stored in the KV version 2 secrets engine of HashiCorp Vault.
+
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+use crate::secrets::{Error, Secret, SecretProvider};
+
+/// The key read when the secret name doesn't specify one.
+const DEFAULT_KEY: &str = "value";
+
+/// Reads secrets from Vault.
+///
+/// Secret names are in the form of `<path>#<key>`, like `db/app#password`,
+/// where `#<key>` may be omitted to read the key `value`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::secrets::{Secret, vault::VaultProvider};
+///
+/// let provider = VaultProvider::new(
+///     String::from("https://vault.example.com:8200"),
+///     Secret::new(String::from("token")),
+/// )
+/// .mount(String::from("kv"));
+///
+/// assert_eq!(provider.get_mount(), "kv");
+/// ```
+#[derive(Clone, Debug)]
+pub struct VaultProvider {
+    client: Client,
+    addr: String,
+    token: Secret,
+    mount: String,
+}
+
+impl VaultProvider {
+    /// Creates a new [`VaultProvider`] for the server at `addr`, reading the engine mounted at `secret`.
+    pub fn new(addr: String, token: Secret) -> VaultProvider {
+        VaultProvider {
+            client: Client::new(),
+            addr,
+            token,
+            mount: String::from("secret"),
+        }
+    }
+
+    /// Sets the mount path of the secrets engine.
+    pub fn mount(mut self, mount: String) -> Self {
+        self.mount = mount;
+        self
+    }
+
+    /// Sets the HTTP client, for example one with custom TLS roots or timeouts.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Gets addr.
+    pub fn get_addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Gets mount.
+    pub fn get_mount(&self) -> &str {
+        &self.mount
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/v1/{}/data/{}",
+            self.addr.trim_end_matches('/'),
+            self.mount.trim_matches('/'),
+            path.trim_matches('/')
+        )
+    }
+}
+
+/// Splits a secret name into the path and the key.
+fn split_name(name: &str) -> (&str, &str) {
+    name.split_once('#').unwrap_or((name, DEFAULT_KEY))
+}
+
+/// Extracts the value of `key` from a response body.
+fn extract(name: &str, body: &Value, key: &str) -> Result<Secret, Error> {
+    match &body["data"]["data"][key] {
+        Value::String(s) => Ok(Secret::new(s.clone())),
+        Value::Null => Err(Error::NotFound(name.to_string())),
+        _ => Err(Error::Invalid(name.to_string())),
+    }
+}
+
+impl SecretProvider for VaultProvider {
+    async fn get(&self, name: &str) -> Result<Secret, Error> {
+        let (path, key) = split_name(name);
+        let resp = self
+            .client
+            .get(self.url(path))
+            .header("X-Vault-Token", self.token.expose())
+            .send()
+            .await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        let body: Value = resp.error_for_status()?.json().await?;
+        extract(name, &body, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::secrets::{Error, Secret};
+
+    use super::{VaultProvider, extract, split_name};
+
+    #[test]
+    fn test_url() {
+        let provider = VaultProvider::new(
+            String::from("http://127.0.0.1:8200/"),
+            Secret::new(String::from("token")),
+        );
+        assert_eq!(
+            provider.url("db/app"),
+            "http://127.0.0.1:8200/v1/secret/data/db/app"
+        );
+        assert_eq!(split_name("db/app#password"), ("db/app", "password"));
+        assert_eq!(split_name("db/app"), ("db/app", "value"));
+        assert!(!format!("{:?}", provider).contains("token\""));
+    }
+
+    #[test]
+    fn test_extract() {
+        let body = json!({"data": {"data": {"password": "hunter2", "port": 5432}}});
+        assert_eq!(extract("n", &body, "password").unwrap().expose(), "hunter2");
+        assert!(matches!(
+            extract("n", &body, "user"),
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            extract("n", &body, "port"),
+            Err(Error::Invalid(_))
+        ));
+    }
+}
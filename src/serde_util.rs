@@ -0,0 +1,366 @@
+//! Serde helpers for fields, used via `#[serde(with = "...")]`.
+//!
+//! - [`string_or_number`] accepts numbers and numeric strings, like `1` and `"1"`.
+//! - [`empty_as_none`] treats empty strings as absent values.
+//! - [`comma_separated`] converts lists from and to comma-separated strings, like `"a,b,c"`.
+//! - [`rfc3339`] converts [`SystemTime`]s from and to RFC 3339 strings, like `"2024-01-01T00:00:00Z"`.
+//! - [`unix_timestamp`] and [`unix_timestamp_millis`] convert [`SystemTime`]s from and to Unix timestamps.
+//! - [`case_insensitive`] deserializes enum variants regardless of their case.
+//!
+//! [`SystemTime`]: std::time::SystemTime
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::{Duration, SystemTime, UNIX_EPOCH};
+//!
+//! use sainnhe_common::serde_util;
+//! use serde::Deserialize;
+//!
+//! #[derive(PartialEq, Deserialize, Debug)]
+//! #[serde(rename_all = "snake_case")]
+//! enum Status {
+//!     Active,
+//!     Suspended,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Query {
+//!     #[serde(with = "serde_util::string_or_number")]
+//!     limit: u32,
+//!     #[serde(default, with = "serde_util::empty_as_none")]
+//!     cursor: Option<String>,
+//!     #[serde(with = "serde_util::comma_separated")]
+//!     fields: Vec<String>,
+//!     #[serde(with = "serde_util::rfc3339")]
+//!     since: SystemTime,
+//!     #[serde(with = "serde_util::case_insensitive")]
+//!     status: Status,
+//! }
+//!
+//! let query: Query = serde_json::from_str(
+//!     r#"{"limit": "20", "cursor": "", "fields": "id,name", "since": "1970-01-01T00:01:00Z", "status": "ACTIVE"}"#,
+//! )
+//! .unwrap();
+//!
+//! assert_eq!(query.limit, 20);
+//! assert_eq!(query.cursor, None);
+//! assert_eq!(query.fields, ["id", "name"]);
+//! assert_eq!(query.since, UNIX_EPOCH + Duration::from_secs(60));
+//! assert_eq!(query.status, Status::Active);
+//! ```
+
+/// Deserializes numbers from either numbers or numeric strings, and serializes them as numbers.
+pub mod string_or_number {
+    use std::{fmt, str::FromStr};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOr<T> {
+        String(String),
+        Value(T),
+    }
+
+    /// Serializes `v` as is.
+    pub fn serialize<T: Serialize, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+        v.serialize(s)
+    }
+
+    /// Deserializes `T` from either itself or a string parsed via [`FromStr`].
+    pub fn deserialize<'de, T, D>(d: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de> + FromStr,
+        T::Err: fmt::Display,
+        D: Deserializer<'de>,
+    {
+        match StringOr::<T>::deserialize(d)? {
+            StringOr::String(s) => s.trim().parse().map_err(de::Error::custom),
+            StringOr::Value(v) => Ok(v),
+        }
+    }
+}
+
+/// Deserializes empty or blank strings as `None`, and other strings via [`FromStr`](std::str::FromStr).
+///
+/// Combine with `#[serde(default)]` to accept missing fields as well.
+pub mod empty_as_none {
+    use std::{fmt, str::FromStr};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    /// Serializes `v` as is, where `None` is serialized as null.
+    pub fn serialize<T: Serialize, S: Serializer>(v: &Option<T>, s: S) -> Result<S::Ok, S::Error> {
+        v.serialize(s)
+    }
+
+    /// Deserializes `None` from null, empty or blank strings, and `Some` from other strings.
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+        D: Deserializer<'de>,
+    {
+        match Option::<String>::deserialize(d)? {
+            Some(s) if !s.trim().is_empty() => s.parse().map(Some).map_err(de::Error::custom),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Converts lists from and to comma-separated strings, where items are trimmed and empty items are skipped.
+pub mod comma_separated {
+    use std::{fmt, str::FromStr};
+
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    /// Serializes `v` as its items joined by commas.
+    pub fn serialize<T: fmt::Display, S: Serializer>(v: &[T], s: S) -> Result<S::Ok, S::Error> {
+        let items: Vec<String> = v.iter().map(ToString::to_string).collect();
+        s.serialize_str(&items.join(","))
+    }
+
+    /// Deserializes a list from a comma-separated string.
+    pub fn deserialize<'de, T, D>(d: D) -> Result<Vec<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(d)?
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| item.parse().map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+/// Converts [`SystemTime`](std::time::SystemTime)s from and to RFC 3339 strings.
+///
+/// Times are serialized in UTC, and deserialized from any offset.
+pub mod rfc3339 {
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    use crate::time::{format_rfc3339, parse_rfc3339};
+
+    /// Serializes `t` as an RFC 3339 string in UTC.
+    pub fn serialize<S: Serializer>(t: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format_rfc3339(*t))
+    }
+
+    /// Deserializes a time from an RFC 3339 string.
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<SystemTime, D::Error> {
+        let s = String::deserialize(d)?;
+        parse_rfc3339(&s)
+            .ok_or_else(|| de::Error::custom(format!("invalid RFC 3339 datetime {}", s)))
+    }
+}
+
+/// Converts [`SystemTime`](std::time::SystemTime)s from and to Unix timestamps in seconds,
+/// truncating sub-second precision.
+pub mod unix_timestamp {
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::time::{from_unix, to_unix};
+
+    /// Serializes `t` as a Unix timestamp in seconds.
+    pub fn serialize<S: Serializer>(t: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_i64(to_unix(*t).0)
+    }
+
+    /// Deserializes a time from a Unix timestamp in seconds.
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<SystemTime, D::Error> {
+        Ok(from_unix(i64::deserialize(d)?, 0))
+    }
+}
+
+/// Converts [`SystemTime`](std::time::SystemTime)s from and to Unix timestamps in milliseconds,
+/// truncating sub-millisecond precision.
+pub mod unix_timestamp_millis {
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::time::{from_unix, to_unix};
+
+    /// Serializes `t` as a Unix timestamp in milliseconds.
+    pub fn serialize<S: Serializer>(t: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+        let (secs, nanos) = to_unix(*t);
+        s.serialize_i64(
+            secs.saturating_mul(1000)
+                .saturating_add(i64::from(nanos / 1_000_000)),
+        )
+    }
+
+    /// Deserializes a time from a Unix timestamp in milliseconds.
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<SystemTime, D::Error> {
+        let millis = i64::deserialize(d)?;
+        let nanos = u32::try_from(millis.rem_euclid(1000)).unwrap_or_default() * 1_000_000;
+        Ok(from_unix(millis.div_euclid(1000), nanos))
+    }
+}
+
+/// Deserializes unit enum variants regardless of their case, like `"ACTIVE"` and `"active"` for `Active`,
+/// and serializes them as is.
+///
+/// Variant names are compared after `#[serde(rename_all = ...)]` and `#[serde(rename = ...)]` are applied.
+pub mod case_insensitive {
+    use std::marker::PhantomData;
+
+    use serde::{
+        Deserialize, Deserializer, Serialize, Serializer,
+        de::{self, IntoDeserializer, Visitor},
+        forward_to_deserialize_any,
+    };
+
+    /// Deserializes a string as any type, except enums whose variants are matched case-insensitively.
+    struct CaseInsensitive<'a, E> {
+        s: &'a str,
+        _marker: PhantomData<E>,
+    }
+
+    impl<'de, E: de::Error> Deserializer<'de> for CaseInsensitive<'_, E> {
+        type Error = E;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, E> {
+            visitor.visit_str(self.s)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, E> {
+            let variant: &str = variants
+                .iter()
+                .find(|v| v.eq_ignore_ascii_case(self.s))
+                .copied()
+                .unwrap_or(self.s);
+            visitor.visit_enum(variant.into_deserializer())
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map struct identifier ignored_any
+        }
+    }
+
+    /// Serializes `v` as is.
+    pub fn serialize<T: Serialize, S: Serializer>(v: &T, s: S) -> Result<S::Ok, S::Error> {
+        v.serialize(s)
+    }
+
+    /// Deserializes `T` from a string, matching enum variants case-insensitively.
+    pub fn deserialize<'de, T, D>(d: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(d)?;
+        T::deserialize(CaseInsensitive {
+            s: &s,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(PartialEq, Serialize, Deserialize, Debug)]
+    enum Color {
+        Red,
+        #[serde(rename = "dark-blue")]
+        DarkBlue,
+    }
+
+    #[derive(PartialEq, Serialize, Deserialize, Debug)]
+    struct Record {
+        #[serde(with = "super::string_or_number")]
+        id: i64,
+        #[serde(default, with = "super::empty_as_none")]
+        note: Option<u32>,
+        #[serde(with = "super::comma_separated")]
+        tags: Vec<u32>,
+        #[serde(with = "super::rfc3339")]
+        created_at: SystemTime,
+        #[serde(with = "super::unix_timestamp")]
+        updated_at: SystemTime,
+        #[serde(with = "super::unix_timestamp_millis")]
+        deleted_at: SystemTime,
+        #[serde(with = "super::case_insensitive")]
+        color: Color,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let record: Record = serde_json::from_value(json!({
+            "id": " 7 ",
+            "note": " ",
+            "tags": "1, 2,,3",
+            "created_at": "1970-01-01T09:00:01.25+09:00",
+            "updated_at": -1,
+            "deleted_at": -1500,
+            "color": "DARK-BLUE",
+        }))
+        .unwrap();
+        assert_eq!(
+            record,
+            Record {
+                id: 7,
+                note: None,
+                tags: vec![1, 2, 3],
+                created_at: UNIX_EPOCH + Duration::from_millis(1250),
+                updated_at: UNIX_EPOCH - Duration::from_secs(1),
+                deleted_at: UNIX_EPOCH - Duration::from_millis(1500),
+                color: Color::DarkBlue,
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&record).unwrap(),
+            json!({
+                "id": 7,
+                "note": null,
+                "tags": "1,2,3",
+                "created_at": "1970-01-01T00:00:01.25Z",
+                "updated_at": -1,
+                "deleted_at": -1500,
+                "color": "dark-blue",
+            })
+        );
+
+        let record: Record = serde_json::from_value(json!({
+            "id": 7,
+            "tags": "",
+            "created_at": "1970-01-01T00:00:00Z",
+            "updated_at": 0,
+            "deleted_at": 0,
+            "color": "red",
+        }))
+        .unwrap();
+        assert_eq!(record.note, None);
+        assert_eq!(record.color, Color::Red);
+
+        for (key, val) in [
+            ("id", json!("x")),
+            ("created_at", json!("yesterday")),
+            ("color", json!("green")),
+        ] {
+            let mut v = serde_json::to_value(&record).unwrap();
+            v[key] = val;
+            assert!(serde_json::from_value::<Record>(v).is_err(), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::storage::{BlobStore, Error, Method};
+
+/// Stores blobs as files in a directory, where the segments of keys are subdirectories.
+///
+/// Presigned URLs are not supported.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::storage::{BlobStore, LocalStore};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// let store = LocalStore::new(std::env::temp_dir().join("sainnhe_common_doc_local_store"));
+/// store.put("avatars/1.png", b"png").await.unwrap();
+///
+/// assert_eq!(store.get("avatars/1.png").await.unwrap(), b"png");
+/// assert_eq!(store.list("avatars/").await.unwrap(), ["avatars/1.png"]);
+///
+/// store.delete("avatars/1.png").await.unwrap();
+/// assert!(store.get("avatars/1.png").await.is_err());
+/// # });
+/// ```
+#[derive(Clone, Debug)]
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    /// Creates a new [`LocalStore`] in `dir`, which is created on the first write.
+    pub fn new(dir: PathBuf) -> LocalStore {
+        LocalStore { dir }
+    }
+
+    /// Gets dir.
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        // Keys must not escape the directory.
+        if key
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == ".." || s.contains('\\'))
+        {
+            return Err(Error::InvalidKey(key.to_string()));
+        }
+        Ok(self.dir.join(key))
+    }
+}
+
+/// Appends the keys of the files under `dir` to `keys`, where `base` is the key of `dir`.
+fn walk(dir: &Path, base: &str, keys: &mut Vec<String>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let key = format!("{}{}", base, name);
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), &format!("{}/", key), keys)?;
+        } else {
+            keys.push(key);
+        }
+    }
+    Ok(())
+}
+
+impl BlobStore for LocalStore {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, Error> {
+        match fs::read(self.path(key)?) {
+            Ok(data) => Ok(data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::NotFound(key.to_string())),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        match fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(Error::Io(e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        walk(&self.dir, "", &mut keys)?;
+        keys.retain(|k| k.starts_with(prefix));
+        keys.sort_unstable();
+        Ok(keys)
+    }
+
+    fn presigned_url(
+        &self,
+        _key: &str,
+        _method: Method,
+        _expires_in: Duration,
+    ) -> Result<String, Error> {
+        Err(Error::Unsupported("presigned URL"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use crate::storage::{BlobStore, Error, Method};
+
+    use super::LocalStore;
+
+    #[tokio::test]
+    async fn test_local_store() {
+        let dir = std::env::temp_dir().join("sainnhe_common_test_local_store");
+        let _ = fs::remove_dir_all(&dir);
+        let store = LocalStore::new(dir);
+        assert!(store.list("").await.unwrap().is_empty());
+
+        for key in ["b/2", "a/1", "a/b/3", "c"] {
+            store.put(key, key.as_bytes()).await.unwrap();
+        }
+        store.put("c", b"replaced").await.unwrap();
+        assert_eq!(store.get("c").await.unwrap(), b"replaced");
+        assert_eq!(store.list("").await.unwrap(), ["a/1", "a/b/3", "b/2", "c"]);
+        assert_eq!(store.list("a/").await.unwrap(), ["a/1", "a/b/3"]);
+
+        store.delete("a/1").await.unwrap();
+        store.delete("a/1").await.unwrap();
+        assert!(matches!(store.get("a/1").await, Err(Error::NotFound(_))));
+
+        for key in ["", "/etc/passwd", "a/../../x", "a//b", "a\\b"] {
+            assert!(
+                matches!(store.get(key).await, Err(Error::InvalidKey(_))),
+                "{}",
+                key
+            );
+        }
+        assert!(matches!(
+            store.presigned_url("c", Method::Get, Duration::from_secs(60)),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
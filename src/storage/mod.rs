@@ -0,0 +1,116 @@
+//! Object storage.
+//!
+//! The [`BlobStore`] trait abstracts over object storages, where blobs are addressed by keys like `avatars/1.png`.
+//! [`LocalStore`] stores blobs in a local directory, which is handy for development and tests.
+//! With the `s3` feature enabled, [`s3::S3Store`] stores blobs in S3 or any S3-compatible storage like MinIO.
+
+mod local;
+#[cfg(feature = "s3")]
+pub mod s3;
+
+use std::{fmt, io, time::Duration};
+
+pub use local::LocalStore;
+
+/// Errors returned by [`BlobStore`].
+#[derive(Debug)]
+pub enum Error {
+    /// The blob doesn't exist.
+    NotFound(String),
+    /// The key is not valid for the store.
+    InvalidKey(String),
+    /// The operation is not supported by the store.
+    Unsupported(&'static str),
+    /// The blob could not be read or written.
+    Io(io::Error),
+    /// The request to the storage failed.
+    #[cfg(feature = "s3")]
+    Http(reqwest::Error),
+    /// The storage responded with an unexpected status.
+    #[cfg(feature = "s3")]
+    Status { status: u16, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(key) => write!(f, "blob {} is not found", key),
+            Error::InvalidKey(key) => write!(f, "invalid blob key: {}", key),
+            Error::Unsupported(op) => write!(f, "{} is not supported", op),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            #[cfg(feature = "s3")]
+            Error::Http(e) => write!(f, "http error: {}", e),
+            #[cfg(feature = "s3")]
+            Error::Status { status, message } => {
+                write!(f, "unexpected status {}: {}", status, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            #[cfg(feature = "s3")]
+            Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(feature = "s3")]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+/// The HTTP method a presigned URL is valid for.
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Method {
+    /// Downloads the blob.
+    Get,
+    /// Uploads the blob.
+    Put,
+}
+
+impl Method {
+    /// Returns the method name, like `GET`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Put => "PUT",
+        }
+    }
+}
+
+/// A store of blobs keyed by strings, where `/` separates the segments of keys.
+pub trait BlobStore {
+    /// Writes a blob, replacing the existing one.
+    fn put(&self, key: &str, data: &[u8]) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Reads a blob, or returns [`Error::NotFound`] if it doesn't exist.
+    fn get(&self, key: &str) -> impl Future<Output = Result<Vec<u8>, Error>> + Send;
+
+    /// Deletes a blob. Deleting a missing blob succeeds.
+    fn delete(&self, key: &str) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Lists the keys starting with `prefix` in ascending order.
+    fn list(&self, prefix: &str) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+
+    /// Creates a URL that allows anyone holding it to perform `method` on the blob until it expires,
+    /// so clients can download or upload blobs without going through the service.
+    fn presigned_url(
+        &self,
+        key: &str,
+        method: Method,
+        expires_in: Duration,
+    ) -> Result<String, Error>;
+}
@@ -0,0 +1,568 @@
+//! Date and time helpers.
+//!
+//! Code depending on the current time takes a [`Clock`], so tests can pass a [`MockClock`] to control it.
+//! [`Clock::now_sql`] renders the current time as SQL, which is the dialect's current-timestamp expression
+//! for [`SystemClock`] and a literal for [`MockClock`], so statements are deterministic in tests too.
+//!
+//! Durations are written like `1h30m` or `1.5s`, as parsed by [`parse_duration`] and formatted by [`humanize`].
+
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::db::Type;
+
+/// Converts days since 1970-01-01 into a `(year, month, day)` date in the proleptic Gregorian calendar.
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a `(year, month, day)` date in the proleptic Gregorian calendar into days since 1970-01-01.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // See http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Splits `t` into seconds since the Unix epoch, which are negative before it, and nanoseconds.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use sainnhe_common::time;
+///
+/// assert_eq!(time::to_unix(UNIX_EPOCH - Duration::from_millis(500)), (-1, 500_000_000));
+/// ```
+pub fn to_unix(t: SystemTime) -> (i64, u32) {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => (
+            i64::try_from(d.as_secs()).unwrap_or(i64::MAX),
+            d.subsec_nanos(),
+        ),
+        Err(e) => {
+            let d = e.duration();
+            let secs = -i64::try_from(d.as_secs()).unwrap_or(i64::MAX);
+            match d.subsec_nanos() {
+                0 => (secs, 0),
+                nanos => (secs - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// Returns the time `secs` seconds and `nanos` nanoseconds after the Unix epoch.
+pub fn from_unix(secs: i64, nanos: u32) -> SystemTime {
+    let t = match u64::try_from(secs) {
+        Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+        Err(_) => UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+    };
+    t + Duration::from_nanos(u64::from(nanos))
+}
+
+/// Formats `t` in RFC 3339 in UTC, like `2024-01-01T00:00:00Z` or `2024-01-01T00:00:00.5Z`.
+pub fn format_rfc3339(t: SystemTime) -> String {
+    let (mut s, nanos) = format_datetime(t, 'T');
+    if nanos > 0 {
+        let frac = format!("{:09}", nanos);
+        s.push('.');
+        s.push_str(frac.trim_end_matches('0'));
+    }
+    s.push('Z');
+    s
+}
+
+/// Formats `t` in UTC like `2024-01-01T00:00:00`, where `sep` separates the date and the time,
+/// returning the nanoseconds separately.
+fn format_datetime(t: SystemTime, sep: char) -> (String, u32) {
+    let (secs, nanos) = to_unix(t);
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    let s = format!(
+        "{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        sep,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+    (s, nanos)
+}
+
+/// Parses a datetime in RFC 3339, like `2024-01-01T09:00:00.5+09:00`.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::time;
+///
+/// let t = time::parse_rfc3339("2024-01-01T09:00:00.5+09:00").unwrap();
+///
+/// assert_eq!(time::format_rfc3339(t), "2024-01-01T00:00:00.5Z");
+/// assert!(time::parse_rfc3339("2024-01-01").is_none());
+/// ```
+pub fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    let b = s.as_bytes();
+    let num = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    if b.len() < 20
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || !matches!(b[10], b'T' | b't' | b' ')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, min, sec) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let mut i = 19;
+    let mut nanos = 0;
+    if b[i] == b'.' {
+        let len = b[i + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+        let frac = &s[i + 1..i + 1 + len.min(9)];
+        nanos = format!("{:0<9}", frac).parse().ok()?;
+        i += 1 + len;
+    }
+    let offset = match b.get(i)? {
+        b'Z' | b'z' if i + 1 == b.len() => 0,
+        sign @ (b'+' | b'-') if i + 6 == b.len() && b[i + 3] == b':' => {
+            let (h, m) = (num(i + 1..i + 3)?, num(i + 4..i + 6)?);
+            if h > 23 || m > 59 {
+                return None;
+            }
+            let offset = h * 3600 + m * 60;
+            if *sign == b'+' { offset } else { -offset }
+        }
+        _ => return None,
+    };
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    let secs = days * 86400 + hour * 3600 + min * 60 + sec - offset;
+    Some(from_unix(secs, nanos))
+}
+
+/// Errors returned by this module.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Error {
+    /// The duration is malformed or out of range.
+    InvalidDuration(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidDuration(s) => write!(f, "invalid duration {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+
+    /// Returns a SQL expression of the current time, which is a literal of [`Clock::now`] by default.
+    fn now_sql(&self, typ: Type) -> String {
+        timestamp_literal(typ, self.now())
+    }
+}
+
+/// The clock of the system, whose SQL expression is the current timestamp of the database.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn now_sql(&self, typ: Type) -> String {
+        current_timestamp(typ).to_string()
+    }
+}
+
+/// A clock that only moves when told to, for tests.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use sainnhe_common::{
+///     db::Type,
+///     time::{Clock, MockClock},
+/// };
+///
+/// let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1704067200));
+/// clock.advance(Duration::from_millis(1500));
+///
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_millis(1704067201500));
+/// assert_eq!(clock.now_sql(Type::PostgreSQL), "TIMESTAMPTZ '2024-01-01 00:00:01.5+00'");
+/// ```
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+    /// Creates a new [`MockClock`] at `now`.
+    pub fn new(now: SystemTime) -> MockClock {
+        MockClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Sets the current time to `now`.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+
+    /// Moves the current time forward by `d`.
+    pub fn advance(&self, d: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += d;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Returns the SQL expression of the current timestamp of the database type,
+/// which can be the value of a column like `KV { key: "updated_at", val: current_timestamp(typ) }`.
+///
+/// The expressions keep fractions of seconds, namely microseconds for MySQL and PostgreSQL and milliseconds for SQLite,
+/// whose timestamps are UTC text like `2024-01-01 00:00:00.000`.
+pub fn current_timestamp(typ: Type) -> &'static str {
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => "CURRENT_TIMESTAMP(6)",
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => "CURRENT_TIMESTAMP",
+        Type::SQLite => "STRFTIME('%Y-%m-%d %H:%M:%f', 'now')",
+    }
+}
+
+/// Renders `t` as a timestamp literal of the database type, in the same precision as [`current_timestamp`].
+///
+/// The literal is in UTC, which matches [`current_timestamp`] of MySQL only when the session time zone is UTC.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use sainnhe_common::{db::Type, time::timestamp_literal};
+///
+/// let t = UNIX_EPOCH + Duration::from_secs(1704067200);
+///
+/// assert_eq!(timestamp_literal(Type::MySQL, t), "TIMESTAMP '2024-01-01 00:00:00'");
+/// assert_eq!(timestamp_literal(Type::SQLite, t), "'2024-01-01 00:00:00.000'");
+/// ```
+pub fn timestamp_literal(typ: Type, t: SystemTime) -> String {
+    let (mut s, nanos) = format_datetime(t, ' ');
+    let micros = nanos / 1000;
+    if typ != Type::SQLite && micros > 0 {
+        let frac = format!("{:06}", micros);
+        s.push('.');
+        s.push_str(frac.trim_end_matches('0'));
+    }
+    match typ {
+        Type::MySQL | Type::MariaDB | Type::TiDB => format!("TIMESTAMP '{}'", s),
+        Type::PostgreSQL | Type::CockroachDB | Type::DuckDB => format!("TIMESTAMPTZ '{}+00'", s),
+        Type::SQLite => format!("'{}.{:03}'", s, nanos / 1_000_000),
+    }
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Parses a duration written as numbers followed by units, like `1h30m`, `1.5s` or `1d 12h`.
+///
+/// The units are `d`, `h`, `m`, `s`, `ms`, `us` (or `µs`) and `ns`, and a bare `0` is accepted.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::time::parse_duration;
+///
+/// assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+/// assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+/// assert!(parse_duration("30").is_err());
+/// ```
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    let err = || Error::InvalidDuration(s.to_string());
+    let mut rest = s.trim();
+    if rest == "0" {
+        return Ok(Duration::ZERO);
+    }
+    if rest.is_empty() {
+        return Err(err());
+    }
+    let is_num = |c: char| c.is_ascii_digit() || c == '.';
+    let mut total: u128 = 0;
+    while !rest.is_empty() {
+        let (num, tail) = rest.split_at(rest.find(|c| !is_num(c)).ok_or_else(err)?);
+        let (unit, tail) = tail.split_at(tail.find(is_num).unwrap_or(tail.len()));
+        let unit: u128 = match unit.trim_end() {
+            "ns" => 1,
+            "us" | "µs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => NANOS_PER_SEC,
+            "m" => 60 * NANOS_PER_SEC,
+            "h" => 3600 * NANOS_PER_SEC,
+            "d" => 86400 * NANOS_PER_SEC,
+            _ => return Err(err()),
+        };
+        let (int, frac) = num.split_once('.').unwrap_or((num, ""));
+        if (int.is_empty() && frac.is_empty()) || !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(err());
+        }
+        let int: u128 = match int {
+            "" => 0,
+            _ => int.parse().map_err(|_| err())?,
+        };
+        // Digits beyond the precision of nanoseconds are dropped.
+        let frac = &frac[..frac.len().min(18)];
+        let frac_nanos = match frac {
+            "" => 0,
+            _ => frac.parse::<u128>().map_err(|_| err())? * unit / 10_u128.pow(frac.len() as u32),
+        };
+        total = int
+            .checked_mul(unit)
+            .and_then(|n| n.checked_add(frac_nanos))
+            .and_then(|n| n.checked_add(total))
+            .ok_or_else(err)?;
+        rest = tail;
+    }
+    let secs = u64::try_from(total / NANOS_PER_SEC).map_err(|_| err())?;
+    Ok(Duration::new(secs, (total % NANOS_PER_SEC) as u32))
+}
+
+/// Formats `d` with the units of [`parse_duration`], like `1h30m` or `1s500ms`, omitting zero units.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use sainnhe_common::time::humanize;
+///
+/// assert_eq!(humanize(Duration::from_secs(93784)), "1d2h3m4s");
+/// assert_eq!(humanize(Duration::from_micros(1500)), "1ms500us");
+/// assert_eq!(humanize(Duration::ZERO), "0s");
+/// ```
+pub fn humanize(d: Duration) -> String {
+    if d.is_zero() {
+        return String::from("0s");
+    }
+    let secs = d.as_secs();
+    let nanos = d.subsec_nanos();
+    let parts = [
+        (secs / 86400, "d"),
+        (secs % 86400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+        (u64::from(nanos / 1_000_000), "ms"),
+        (u64::from(nanos / 1000 % 1000), "us"),
+        (u64::from(nanos % 1000), "ns"),
+    ];
+    parts
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::db::Type;
+
+    use super::{Clock, MockClock, SystemClock, civil_from_days, humanize, parse_duration};
+
+    #[test]
+    fn test_civil_from_days() {
+        struct TC {
+            days: i64,
+            want: (i64, i64, i64),
+        }
+
+        let test_cases = vec![
+            TC {
+                days: 0,
+                want: (1970, 1, 1),
+            },
+            TC {
+                days: -1,
+                want: (1969, 12, 31),
+            },
+            TC {
+                days: 11016,
+                want: (2000, 2, 29),
+            },
+            TC {
+                days: 19723,
+                want: (2024, 1, 1),
+            },
+        ];
+
+        for tc in test_cases {
+            assert_eq!(civil_from_days(tc.days), tc.want);
+        }
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        use super::{format_rfc3339, from_unix, parse_rfc3339};
+
+        struct TC {
+            s: &'static str,
+            want: Option<(i64, u32)>,
+            want_formatted: &'static str,
+        }
+
+        let test_cases = vec![
+            TC {
+                s: "2024-01-01T00:00:00Z",
+                want: Some((1704067200, 0)),
+                want_formatted: "2024-01-01T00:00:00Z",
+            },
+            TC {
+                s: "2024-01-01T09:00:00.5+09:00",
+                want: Some((1704067200, 500_000_000)),
+                want_formatted: "2024-01-01T00:00:00.5Z",
+            },
+            TC {
+                s: "1969-12-31t23:59:59.999999999z",
+                want: Some((-1, 999_999_999)),
+                want_formatted: "1969-12-31T23:59:59.999999999Z",
+            },
+            TC {
+                s: "2023-02-29T00:00:00Z",
+                want: None,
+                want_formatted: "",
+            },
+            TC {
+                s: "2024-01-01T00:00:00",
+                want: None,
+                want_formatted: "",
+            },
+            TC {
+                s: "2024-01-01T00:00:00.Z",
+                want: None,
+                want_formatted: "",
+            },
+        ];
+
+        for tc in test_cases {
+            let got = parse_rfc3339(tc.s);
+            assert_eq!(
+                got,
+                tc.want.map(|(secs, nanos)| from_unix(secs, nanos)),
+                "{}",
+                tc.s
+            );
+            if let Some(t) = got {
+                assert_eq!(format_rfc3339(t), tc.want_formatted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_duration() {
+        struct TC {
+            s: &'static str,
+            want: Option<Duration>,
+        }
+
+        let test_cases = vec![
+            TC {
+                s: "1d 12h",
+                want: Some(Duration::from_secs(129600)),
+            },
+            TC {
+                s: ".5m",
+                want: Some(Duration::from_secs(30)),
+            },
+            TC {
+                s: "1m1.000000001s",
+                want: Some(Duration::new(61, 1)),
+            },
+            TC {
+                s: "10µs",
+                want: Some(Duration::from_micros(10)),
+            },
+            TC {
+                s: "1w",
+                want: None,
+            },
+            TC { s: "h", want: None },
+            TC {
+                s: "1.2.3s",
+                want: None,
+            },
+            TC { s: "", want: None },
+        ];
+
+        for tc in test_cases {
+            let got = parse_duration(tc.s).ok();
+            assert_eq!(got, tc.want, "{}", tc.s);
+            if let Some(d) = got {
+                assert_eq!(parse_duration(&humanize(d)).unwrap(), d);
+            }
+        }
+        assert!(parse_duration(&format!("{}s", u128::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_clock() {
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1704067200));
+        clock.advance(Duration::from_micros(250));
+        assert_eq!(
+            clock.now_sql(Type::MySQL),
+            "TIMESTAMP '2024-01-01 00:00:00.00025'"
+        );
+        assert_eq!(clock.now_sql(Type::SQLite), "'2024-01-01 00:00:00.000'");
+        clock.set(UNIX_EPOCH);
+        assert_eq!(clock.now(), UNIX_EPOCH);
+        assert_eq!(SystemClock.now_sql(Type::PostgreSQL), "CURRENT_TIMESTAMP");
+    }
+}
@@ -0,0 +1,392 @@
+//! Input validation.
+//!
+//! Types like request DTOs implement [`Validate`] by checking their fields against rules,
+//! which are functions returning `Result<(), String>` with the message of the violation,
+//! and collecting the violations into [`Errors`] keyed by field paths like `items[0].name`.
+//! Validate inputs before binding their values into statements, so invalid values are reported per field
+//! instead of surfacing as database errors.
+//!
+//! The [`regex()`] rule requires the `regex` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use sainnhe_common::validate::{self, Errors, Validate};
+//!
+//! struct Address {
+//!     country: String,
+//! }
+//!
+//! impl Validate for Address {
+//!     fn validate(&self) -> Result<(), Errors> {
+//!         let mut errors = Errors::new();
+//!         errors.check("country", validate::one_of(self.country.as_str(), &["JP", "US"]));
+//!         errors.into_result()
+//!     }
+//! }
+//!
+//! struct CreateUser {
+//!     name: String,
+//!     age: i64,
+//!     email: String,
+//!     addresses: Vec<Address>,
+//! }
+//!
+//! impl Validate for CreateUser {
+//!     fn validate(&self) -> Result<(), Errors> {
+//!         let mut errors = Errors::new();
+//!         errors
+//!             .check("name", validate::length(&self.name, 1, 50))
+//!             .check("age", validate::range(self.age, 0, 150))
+//!             .check("email", validate::email(&self.email))
+//!             .nest("addresses", self.addresses.validate());
+//!         errors.into_result()
+//!     }
+//! }
+//!
+//! let user = CreateUser {
+//!     name: String::new(),
+//!     age: 20,
+//!     email: String::from("a@example.com"),
+//!     addresses: vec![Address { country: String::from("XX") }],
+//! };
+//! let errors = user.validate().unwrap_err();
+//!
+//! assert_eq!(errors.get("name"), ["must be between 1 and 50 characters"]);
+//! assert_eq!(errors.get("addresses[0].country"), ["must be one of JP, US"]);
+//! ```
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::error::{AppError, Code};
+
+/// A type whose values can be validated.
+pub trait Validate {
+    /// Validates the value, returning all violations.
+    fn validate(&self) -> Result<(), Errors>;
+}
+
+impl<T: Validate> Validate for [T] {
+    /// Validates each element, where the paths of violations are prefixed by the indexes like `[0].name`.
+    fn validate(&self) -> Result<(), Errors> {
+        let mut errors = Errors::new();
+        for (i, v) in self.iter().enumerate() {
+            errors.nest(&format!("[{}]", i), v.validate());
+        }
+        errors.into_result()
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self) -> Result<(), Errors> {
+        self.as_slice().validate()
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    /// Validates the value if it's present.
+    fn validate(&self) -> Result<(), Errors> {
+        self.as_ref().map_or(Ok(()), Validate::validate)
+    }
+}
+
+/// Violations keyed by field paths, where paths are joined by `.` and indexes are written as `[i]`.
+#[derive(PartialEq, Eq, Clone, Default, Debug)]
+pub struct Errors {
+    fields: BTreeMap<String, Vec<String>>,
+}
+
+impl Errors {
+    /// Creates a new [`Errors`] without violations.
+    pub fn new() -> Errors {
+        Errors::default()
+    }
+
+    /// Adds a violation of the field at `path`.
+    pub fn add(&mut self, path: &str, message: String) -> &mut Self {
+        self.fields
+            .entry(path.to_string())
+            .or_default()
+            .push(message);
+        self
+    }
+
+    /// Adds the violation of a rule, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the field.
+    /// * `res` - The result of the rule.
+    pub fn check(&mut self, path: &str, res: Result<(), String>) -> &mut Self {
+        if let Err(message) = res {
+            self.add(path, message);
+        }
+        self
+    }
+
+    /// Adds the violations of a nested value, prefixing their paths with `path`.
+    pub fn nest(&mut self, path: &str, res: Result<(), Errors>) -> &mut Self {
+        let Err(errors) = res else {
+            return self;
+        };
+        for (child, messages) in errors.fields {
+            let full = if child.is_empty() {
+                path.to_string()
+            } else if child.starts_with('[') || path.is_empty() {
+                format!("{}{}", path, child)
+            } else {
+                format!("{}.{}", path, child)
+            };
+            self.fields.entry(full).or_default().extend(messages);
+        }
+        self
+    }
+
+    /// Returns whether there are no violations.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Gets the violations of the field at `path`.
+    pub fn get(&self, path: &str) -> &[String] {
+        self.fields.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    /// Gets the violations of all fields, ordered by paths.
+    pub fn get_fields(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.fields
+    }
+
+    /// Returns `Ok(())` if there are no violations, otherwise `Err(self)`.
+    pub fn into_result(self) -> Result<(), Errors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (path, messages)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", path, messages.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Errors {}
+
+impl From<Errors> for AppError {
+    fn from(e: Errors) -> Self {
+        AppError::new(Code::InvalidArgument, e.to_string())
+    }
+}
+
+/// Checks that `v` has between `min` and `max` characters, inclusive.
+pub fn length(v: &str, min: usize, max: usize) -> Result<(), String> {
+    let len = v.chars().count();
+    if len < min || len > max {
+        return Err(format!("must be between {} and {} characters", min, max));
+    }
+    Ok(())
+}
+
+/// Checks that `v` is between `min` and `max`, inclusive.
+pub fn range<T: PartialOrd + fmt::Display>(v: T, min: T, max: T) -> Result<(), String> {
+    // NaN is out of every range.
+    if !(v >= min && v <= max) {
+        return Err(format!("must be between {} and {}", min, max));
+    }
+    Ok(())
+}
+
+/// Checks that `v` is present.
+pub fn required<T>(v: &Option<T>) -> Result<(), String> {
+    match v {
+        Some(_) => Ok(()),
+        None => Err(String::from("is required")),
+    }
+}
+
+/// Checks that `v` is one of `allowed`, like the variants of an enum column.
+pub fn one_of<T: PartialEq + fmt::Display>(v: T, allowed: &[T]) -> Result<(), String> {
+    if allowed.contains(&v) {
+        return Ok(());
+    }
+    let allowed: Vec<String> = allowed.iter().map(ToString::to_string).collect();
+    Err(format!("must be one of {}", allowed.join(", ")))
+}
+
+/// Checks that the whole of `v` matches `re`.
+///
+/// The leftmost match must span `v`, so put longer alternatives first, like `ab|a` rather than `a|ab`.
+///
+/// # Examples
+///
+/// ```
+/// use regex::Regex;
+/// use sainnhe_common::validate;
+///
+/// let slug = Regex::new("[a-z0-9-]+").unwrap();
+///
+/// assert!(validate::regex("hello-world", &slug).is_ok());
+/// assert!(validate::regex("Hello world", &slug).is_err());
+/// ```
+#[cfg(feature = "regex")]
+pub fn regex(v: &str, re: &regex::Regex) -> Result<(), String> {
+    match re.find(v) {
+        Some(m) if m.start() == 0 && m.end() == v.len() => Ok(()),
+        _ => Err(format!("must match {}", re.as_str())),
+    }
+}
+
+/// Checks that `v` looks like an email address, namely `local@domain` where the domain has a dot
+/// and neither part has whitespace. Whether the address exists can only be checked by sending emails.
+pub fn email(v: &str) -> Result<(), String> {
+    let valid = v.len() <= 254
+        && !v.chars().any(|c| c.is_whitespace() || c.is_control())
+        && v.rsplit_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty()
+                && local.len() <= 64
+                && !local.contains('@')
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|label| {
+                    !label.is_empty()
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+        });
+    if !valid {
+        return Err(String::from("must be an email address"));
+    }
+    Ok(())
+}
+
+/// Checks that `v` is a phone number in the E.164 format, like `+819012345678`.
+pub fn phone(v: &str) -> Result<(), String> {
+    let valid = v.strip_prefix('+').is_some_and(|digits| {
+        (8..=15).contains(&digits.len())
+            && !digits.starts_with('0')
+            && digits.bytes().all(|b| b.is_ascii_digit())
+    });
+    if !valid {
+        return Err(String::from("must be a phone number in the E.164 format"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Errors, Validate, email, length, phone, range};
+
+    #[test]
+    fn test_rules() {
+        struct TC {
+            res: Result<(), String>,
+            want: bool,
+        }
+
+        let test_cases = vec![
+            TC {
+                res: length("日本語", 1, 3),
+                want: true,
+            },
+            TC {
+                res: length("", 1, 3),
+                want: false,
+            },
+            TC {
+                res: range(1.5, 0.0, 1.0),
+                want: false,
+            },
+            TC {
+                res: range(f64::NAN, 0.0, 1.0),
+                want: false,
+            },
+            TC {
+                res: email("a.b+c@mail.example.com"),
+                want: true,
+            },
+            TC {
+                res: email("a@localhost"),
+                want: false,
+            },
+            TC {
+                res: email("a b@example.com"),
+                want: false,
+            },
+            TC {
+                res: email("a@@example.com"),
+                want: false,
+            },
+            TC {
+                res: email("a@-example.com"),
+                want: false,
+            },
+            TC {
+                res: phone("+819012345678"),
+                want: true,
+            },
+            TC {
+                res: phone("09012345678"),
+                want: false,
+            },
+            TC {
+                res: phone("+81-90-1234"),
+                want: false,
+            },
+        ];
+
+        for (i, tc) in test_cases.into_iter().enumerate() {
+            assert_eq!(tc.res.is_ok(), tc.want, "case {}", i);
+        }
+    }
+
+    struct Item {
+        name: String,
+    }
+
+    impl Validate for Item {
+        fn validate(&self) -> Result<(), Errors> {
+            let mut errors = Errors::new();
+            errors.check("name", length(&self.name, 1, 10));
+            errors.into_result()
+        }
+    }
+
+    #[test]
+    fn test_nest() {
+        let items = vec![
+            Item {
+                name: String::from("a"),
+            },
+            Item {
+                name: String::new(),
+            },
+        ];
+        let mut errors = Errors::new();
+        errors
+            .nest("items", items.validate())
+            .nest(
+                "item",
+                Some(Item {
+                    name: String::new(),
+                })
+                .validate(),
+            )
+            .nest("none", None::<Item>.validate())
+            .add("items", String::from("must not be empty"));
+
+        assert_eq!(
+            errors.get_fields().keys().collect::<Vec<_>>(),
+            ["item.name", "items", "items[1].name"]
+        );
+        assert_eq!(
+            errors.to_string(),
+            "item.name: must be between 1 and 10 characters; items: must not be empty; items[1].name: must be between 1 and 10 characters"
+        );
+        assert!(Errors::new().into_result().is_ok());
+    }
+}
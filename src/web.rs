@@ -0,0 +1,229 @@
+//! Request ID middleware.
+//!
+//! [`RequestIdLayer`] is a tower layer, so it works with axum and any other tower-based framework.
+//! It takes the request ID from the request header, or generates a UUIDv7 if it's missing or malformed,
+//! then serves the request in a `request` tracing span with the `request_id` field,
+//! in which [`request_id`] returns the ID, and finally returns the ID in the response header.
+//!
+//! The ID is picked up by [`http::Client`](crate::http::Client) via [`Client::request_id`](crate::http::Client::request_id)
+//! and by the `db.execute` spans of [`Observer`](crate::db::observe::Observer).
+//! Frameworks that are not based on tower, like actix-web, can serve requests via [`scope`] in their own middleware.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{HeaderName, HeaderValue, Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::Instrument;
+
+use crate::id::UuidV7;
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Returns the ID of the request being served by the current task.
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::web::{request_id, scope};
+///
+/// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+/// assert_eq!(request_id(), None);
+///
+/// scope(String::from("req-1"), async {
+///     assert_eq!(request_id().as_deref(), Some("req-1"));
+/// })
+/// .await;
+/// # });
+/// ```
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Runs `fut` as the request identified by `id`, in a `request` tracing span.
+pub async fn scope<F: Future>(id: String, fut: F) -> F::Output {
+    let span = span(&id);
+    REQUEST_ID.scope(id, fut).instrument(span).await
+}
+
+fn span(id: &str) -> tracing::Span {
+    tracing::info_span!("request", request_id = %id)
+}
+
+/// Returns whether a request ID taken from a header is safe to log and propagate.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// A layer that wraps services with [`RequestId`].
+///
+/// # Examples
+///
+/// ```
+/// use sainnhe_common::web::RequestIdLayer;
+///
+/// let layer = RequestIdLayer::new().header(http::HeaderName::from_static("x-correlation-id"));
+///
+/// assert_eq!(layer.get_header(), "x-correlation-id");
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestIdLayer {
+    header: HeaderName,
+}
+
+impl RequestIdLayer {
+    /// Creates a new [`RequestIdLayer`] using the `x-request-id` header.
+    pub fn new() -> RequestIdLayer {
+        RequestIdLayer {
+            header: HeaderName::from_static("x-request-id"),
+        }
+    }
+
+    /// Sets the header carrying the request ID.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Gets header.
+    pub fn get_header(&self) -> &HeaderName {
+        &self.header
+    }
+}
+
+impl Default for RequestIdLayer {
+    fn default() -> Self {
+        RequestIdLayer::new()
+    }
+}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestId<S>;
+
+    fn layer(&self, inner: S) -> RequestId<S> {
+        RequestId {
+            inner,
+            header: self.header.clone(),
+        }
+    }
+}
+
+/// A service that serves requests via [`scope`] with their IDs, see [`RequestIdLayer`].
+///
+/// The ID is also set in the request header, so handlers can read it from there as well.
+#[derive(Clone, Debug)]
+pub struct RequestId<S> {
+    inner: S,
+    header: HeaderName,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestId<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<ResBody>, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let id = match req.headers().get(&self.header).map(HeaderValue::to_str) {
+            Some(Ok(id)) if is_valid(id) => id.to_string(),
+            _ => UuidV7::generate().to_string(),
+        };
+        let value = HeaderValue::from_str(&id)
+            .unwrap_or_else(|_| unreachable!("request IDs are visible ASCII"));
+        req.headers_mut().insert(self.header.clone(), value.clone());
+        let header = self.header.clone();
+        // Services may do work before returning their futures.
+        let fut = span(&id).in_scope(|| REQUEST_ID.sync_scope(id.clone(), || self.inner.call(req)));
+        Box::pin(scope(id, async move {
+            let mut resp = fut.await?;
+            resp.headers_mut().insert(header, value);
+            Ok(resp)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        future::{Ready, ready},
+        task::{Context, Poll},
+    };
+
+    use http::{Request, Response};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use super::{RequestIdLayer, request_id};
+
+    /// Responds with the request ID seen by the handler.
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request<()>) -> Self::Future {
+            ready(Ok(Response::new(request_id().unwrap_or_default())))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        struct TC {
+            header: Option<&'static str>,
+            want: Option<&'static str>,
+        }
+
+        let test_cases = vec![
+            TC {
+                header: Some("req-1"),
+                want: Some("req-1"),
+            },
+            TC {
+                header: Some("bad id"),
+                want: None,
+            },
+            TC {
+                header: None,
+                want: None,
+            },
+        ];
+
+        let mut svc = RequestIdLayer::new().layer(Echo);
+        for tc in test_cases {
+            let mut req = Request::new(());
+            if let Some(header) = tc.header {
+                req.headers_mut()
+                    .insert("x-request-id", header.parse().unwrap());
+            }
+            let resp = svc.call(req).await.unwrap();
+            let id = resp.headers()["x-request-id"].to_str().unwrap();
+            assert_eq!(resp.body(), id);
+            match tc.want {
+                Some(want) => assert_eq!(id, want),
+                None => assert_eq!(id.len(), 36),
+            }
+        }
+    }
+}
@@ -0,0 +1,2 @@
+-- Gets a user by id.
+SELECT id, name FROM users WHERE id = $1